@@ -0,0 +1,496 @@
+use bytes::Bytes;
+use clap::{ArgAction, Parser};
+use redis_rust_server_2::lib::conn::Connection;
+use redis_rust_server_2::lib::frame::Frame;
+use redis_rust_server_2::lib::Result;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+use tokio::task::JoinSet;
+
+///兼容redis协议的压力测试工具
+///
+/// 打开多个并发的连接反复发送GET、SET、INCR、MSET、LRANGE，按流水线深度成批发送，统计吞吐量与延迟分布。
+///
+/// 比较INCR计数器的整数编码时，让所有请求落在同一个计数器上，运行`-c 50 -n 1000000 -t incr`。
+/// 比较大值的解析时运行`-c 8 -d 102400 -n 50000 -t set,get`，大的bulk直接引用读缓冲区，主要影响SET的吞吐量。
+///
+/// 比较键空间分片数对写入争用的影响时，分别以keyspace-shards为1与默认的64启动服务器，运行
+/// `-c 64 -P 16 -r 100000 -t set,incr,mset --mix`，对比两次的p99与max。
+/// 分片只在多个工作线程真正并行时减少等待，单核的机器上两者的结果没有差别。
+///
+/// 比较慢命令对其他键的影响时同样分别以两种分片数运行
+/// `-c 16 -r 100000 -t set:50,lrange:1 --mix --list-size 100000`，对比按命令分别统计的SET的p99与max。
+/// LRANGE在分片的读锁内构造回复，只挡住同一个分片上的写入
+#[derive(Debug, Parser)]
+#[command(version, about, disable_help_flag = true)]
+struct Args {
+    ///服务器的地址
+    #[arg(short = 'h', long, default_value = "127.0.0.1")]
+    host: String,
+    ///服务器的端口
+    #[arg(short = 'p', long, default_value_t = 6378)]
+    port: u16,
+    ///并发的连接数
+    #[arg(short = 'c', long, default_value_t = 50)]
+    clients: usize,
+    ///每项测试发出的请求总数
+    #[arg(short = 'n', long, default_value_t = 100_000)]
+    requests: usize,
+    ///流水线深度，每个连接一次发出的请求数
+    #[arg(short = 'P', long, default_value_t = 1)]
+    pipeline: usize,
+    ///SET写入的值的字节数
+    #[arg(short = 'd', long, default_value_t = 3)]
+    data_size: usize,
+    ///LRANGE读取的列表的长度，运行前先写入
+    #[arg(long, default_value_t = 600)]
+    list_size: usize,
+    ///随机键的范围，为0时所有请求使用同一个键
+    #[arg(short = 'r', long, default_value_t = 0)]
+    keyspace: u64,
+    ///要运行的测试，以逗号分隔，混合运行时可以写成name:weight指定比例
+    #[arg(
+        short = 't',
+        long,
+        default_value = "set,get,incr",
+        value_delimiter = ','
+    )]
+    tests: Vec<String>,
+    ///所有测试按比例混合在一次运行中，而不是依次运行
+    #[arg(long)]
+    mix: bool,
+    ///吞吐量低于该值（每秒请求数）时以失败退出，用于检查性能改动是否退化
+    #[arg(long)]
+    min_rps: Option<f64>,
+    ///p99延迟高于该值（毫秒）时以失败退出
+    #[arg(long)]
+    max_p99: Option<f64>,
+    ///打印帮助
+    #[arg(long = "help", action = ArgAction::Help)]
+    _help: Option<bool>,
+}
+
+//LRANGE读取的列表，与redis-benchmark一样使用固定的键
+const LIST: &[u8] = b"mylist";
+
+//测试的命令
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Get,
+    Set,
+    Incr,
+    //以相同的哈希标签写入两个键，服务器在一个分片的锁内完成
+    Mset,
+    //读取整个列表，列表很长时服务器会在分片的锁内停留较久
+    Lrange,
+}
+
+//一项测试的结果，延迟以毫秒计
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Summary {
+    requests: usize,
+    secs: f64,
+    throughput: f64,
+    avg: f64,
+    min: f64,
+    p50: f64,
+    p95: f64,
+    p99: f64,
+    max: f64,
+}
+
+//一次运行的负载：按权重挑选命令
+#[derive(Debug)]
+struct Workload {
+    ops: Vec<(Op, u64)>,
+    //SET写入的值
+    value: Bytes,
+    keyspace: u64,
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+    if let Err(e) = run(&args).await {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+//按参数依次或混合运行各项测试
+async fn run(args: &Args) -> Result<()> {
+    let mut ops = vec![];
+    for test in &args.tests {
+        let (name, weight) = match test.split_once(':') {
+            Some((name, weight)) => match weight.parse::<u64>() {
+                Ok(weight) if weight > 0 => (name, weight),
+                _ => return Err(format!("Invalid weight in '{}'", test).into()),
+            },
+            None => (test.as_str(), 1),
+        };
+        let op = match name.to_lowercase().as_str() {
+            "get" => Op::Get,
+            "set" => Op::Set,
+            "incr" => Op::Incr,
+            "mset" => Op::Mset,
+            "lrange" => Op::Lrange,
+            _ => return Err(format!("Unknown test '{}'", name).into()),
+        };
+        ops.push((op, weight));
+    }
+    let value = Bytes::from(vec![b'x'; args.data_size]);
+    let workloads: Vec<(String, Vec<(Op, u64)>)> = if args.mix {
+        vec![(args.tests.join(","), ops)]
+    } else {
+        ops.into_iter()
+            .map(|(op, _)| (format!("{:?}", op).to_uppercase(), vec![(op, 1)]))
+            .collect()
+    };
+    for (title, ops) in workloads {
+        if ops.iter().any(|(op, _)| matches!(op, Op::Lrange)) {
+            fill_list(args, &value).await?;
+        }
+        let workload = Arc::new(Workload {
+            ops,
+            value: value.clone(),
+            keyspace: args.keyspace,
+        });
+        let (summary, by_op) = bench(args, workload).await?;
+        report(args, &title, &summary);
+        //混合运行时再按命令分别统计，慢命令不会掩盖其他命令的延迟
+        if by_op.len() > 1 {
+            for (op, summary) in &by_op {
+                let name = format!("{:?}", op).to_uppercase();
+                report(args, &format!("{} / {}", title, name), summary);
+            }
+        }
+        check(args, &title, &summary)?;
+    }
+    Ok(())
+}
+
+//写入LRANGE读取的列表，元素与SET写入的值相同
+async fn fill_list(args: &Args, value: &Bytes) -> Result<()> {
+    let socket = TcpStream::connect((args.host.as_str(), args.port)).await?;
+    let mut conn = Connection::new(socket);
+    let del = [Bytes::from_static(b"DEL"), Bytes::from_static(LIST)];
+    conn.write_frame(&Frame::Array(del.into_iter().map(Frame::Bulk).collect()))
+        .await?;
+    conn.read_frame().await?;
+    let mut remaining = args.list_size;
+    while remaining > 0 {
+        let n = remaining.min(1000);
+        let mut rpush = vec![Bytes::from_static(b"RPUSH"), Bytes::from_static(LIST)];
+        rpush.resize(n + 2, value.clone());
+        conn.write_frame(&Frame::Array(rpush.into_iter().map(Frame::Bulk).collect()))
+            .await?;
+        if let Some(Frame::Error(e)) = conn.read_frame().await? {
+            return Err(e.into());
+        }
+        remaining -= n;
+    }
+    Ok(())
+}
+
+//运行一项测试，返回全部请求的统计结果与每种命令各自的统计结果
+async fn bench(args: &Args, workload: Arc<Workload>) -> Result<(Summary, Vec<(Op, Summary)>)> {
+    let remaining = Arc::new(AtomicUsize::new(args.requests));
+    let pipeline = args.pipeline.max(1);
+    let mut conns = Vec::with_capacity(args.clients);
+    for _ in 0..args.clients.max(1) {
+        let socket = TcpStream::connect((args.host.as_str(), args.port)).await?;
+        socket.set_nodelay(true)?;
+        conns.push(Connection::new(socket));
+    }
+    let started = Instant::now();
+    let mut tasks = JoinSet::new();
+    for conn in conns {
+        tasks.spawn(client(conn, workload.clone(), remaining.clone(), pipeline));
+    }
+    let mut latencies = Vec::with_capacity(args.requests);
+    while let Some(result) = tasks.join_next().await {
+        latencies.extend(result??);
+    }
+    let elapsed = started.elapsed();
+    latencies.sort_unstable_by_key(|(_, latency)| *latency);
+    let summary = |op: Option<usize>| {
+        let latencies: Vec<Duration> = latencies
+            .iter()
+            .filter(|(i, _)| op.is_none_or(|op| op == *i))
+            .map(|(_, latency)| *latency)
+            .collect();
+        Summary::new(elapsed, &latencies)
+    };
+    let by_op = (0..workload.ops.len())
+        .map(|i| (workload.ops[i].0, summary(Some(i))))
+        .collect();
+    Ok((summary(None), by_op))
+}
+
+//一个连接：每次领取最多pipeline个请求一起发出，再依次读取回复，直到请求全部领完
+//
+// 与redis-benchmark一致，一批中每个请求的延迟都记为整批的往返时间，与命令在ops中的下标一起返回
+async fn client(
+    mut conn: Connection<TcpStream>,
+    workload: Arc<Workload>,
+    remaining: Arc<AtomicUsize>,
+    pipeline: usize,
+) -> Result<Vec<(usize, Duration)>> {
+    let mut rng = Rng::new();
+    let mut latencies = vec![];
+    loop {
+        let batch = match remaining.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| {
+            (n > 0).then_some(n.saturating_sub(pipeline))
+        }) {
+            Ok(n) => n.min(pipeline),
+            Err(_) => return Ok(latencies),
+        };
+        let started = Instant::now();
+        let ops: Vec<usize> = (0..batch).map(|_| workload.pick(&mut rng)).collect();
+        for &op in &ops {
+            conn.buffer_frame(&workload.request(workload.ops[op].0, &mut rng));
+        }
+        for _ in 0..batch {
+            match conn.read_frame().await? {
+                Some(Frame::Error(e)) => return Err(e.into()),
+                Some(_) => {}
+                None => return Err("connection closed by server".into()),
+            }
+        }
+        let elapsed = started.elapsed();
+        latencies.extend(ops.into_iter().map(|op| (op, elapsed)));
+    }
+}
+
+impl Workload {
+    //按权重随机挑选一条命令，返回它在ops中的下标
+    fn pick(&self, rng: &mut Rng) -> usize {
+        let total: u64 = self.ops.iter().map(|(_, weight)| weight).sum();
+        let mut pick = rng.below(total);
+        for (i, (_, weight)) in self.ops.iter().enumerate() {
+            if pick < *weight {
+                return i;
+            }
+            pick -= weight;
+        }
+        0
+    }
+
+    //生成一条命令的请求
+    fn request(&self, op: Op, rng: &mut Rng) -> Frame {
+        let args: Vec<Bytes> = match op {
+            Op::Get => vec![Bytes::from_static(b"GET"), self.key("key", rng)],
+            Op::Set => vec![
+                Bytes::from_static(b"SET"),
+                self.key("key", rng),
+                self.value.clone(),
+            ],
+            Op::Incr => vec![Bytes::from_static(b"INCR"), self.key("counter", rng)],
+            Op::Mset => {
+                let tag = self.key("key", rng);
+                let tag = String::from_utf8_lossy(&tag);
+                vec![
+                    Bytes::from_static(b"MSET"),
+                    Bytes::from(format!("{{{}}}:a", tag)),
+                    self.value.clone(),
+                    Bytes::from(format!("{{{}}}:b", tag)),
+                    self.value.clone(),
+                ]
+            }
+            Op::Lrange => vec![
+                Bytes::from_static(b"LRANGE"),
+                Bytes::from_static(LIST),
+                Bytes::from_static(b"0"),
+                Bytes::from_static(b"-1"),
+            ],
+        };
+        Frame::Array(args.into_iter().map(Frame::Bulk).collect())
+    }
+
+    //与redis-benchmark的键名一致，没有指定随机范围时所有请求使用同一个键
+    fn key(&self, prefix: &str, rng: &mut Rng) -> Bytes {
+        if self.keyspace == 0 {
+            return Bytes::from(format!("{}:__rand_int__", prefix));
+        }
+        Bytes::from(format!("{}:{:012}", prefix, rng.below(self.keyspace)))
+    }
+}
+
+impl Summary {
+    //由总耗时与排好序的延迟计算吞吐量与延迟分布，没有请求时延迟都为0
+    fn new(elapsed: Duration, latencies: &[Duration]) -> Summary {
+        let secs = elapsed.as_secs_f64();
+        let requests = latencies.len();
+        let msec = |d: Duration| d.as_secs_f64() * 1000.0;
+        let percentile = |p: f64| match requests {
+            0 => 0.0,
+            _ => msec(latencies[((requests - 1) as f64 * p).round() as usize]),
+        };
+        let avg = match requests {
+            0 => 0.0,
+            _ => latencies.iter().map(|d| msec(*d)).sum::<f64>() / requests as f64,
+        };
+        Summary {
+            requests,
+            secs,
+            throughput: if secs > 0.0 {
+                requests as f64 / secs
+            } else {
+                0.0
+            },
+            avg,
+            min: percentile(0.0),
+            p50: percentile(0.5),
+            p95: percentile(0.95),
+            p99: percentile(0.99),
+            max: percentile(1.0),
+        }
+    }
+}
+
+//打印吞吐量与延迟分布
+fn report(args: &Args, title: &str, summary: &Summary) {
+    println!("====== {} ======", title);
+    println!(
+        "  {} requests completed in {:.2} seconds",
+        summary.requests, summary.secs
+    );
+    println!("  {} parallel clients", args.clients);
+    println!(
+        "  {} bytes payload, pipeline {}",
+        args.data_size, args.pipeline
+    );
+    println!(
+        "  throughput: {:.2} requests per second",
+        summary.throughput
+    );
+    if summary.requests > 0 {
+        println!(
+            "  latency (msec): avg={:.3} min={:.3} p50={:.3} p95={:.3} p99={:.3} max={:.3}",
+            summary.avg, summary.min, summary.p50, summary.p95, summary.p99, summary.max
+        );
+    }
+    println!();
+}
+
+//按--min-rps与--max-p99检查结果，不满足时返回错误让进程以失败退出
+fn check(args: &Args, title: &str, summary: &Summary) -> Result<()> {
+    if let Some(min) = args.min_rps {
+        if summary.throughput < min {
+            return Err(format!(
+                "{}: throughput {:.2} requests per second is below --min-rps {}",
+                title, summary.throughput, min
+            )
+            .into());
+        }
+    }
+    if let Some(max) = args.max_p99 {
+        if summary.p99 > max {
+            return Err(format!(
+                "{}: p99 latency {:.3} msec is above --max-p99 {}",
+                title, summary.p99, max
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
+
+//xorshift64*，只用来挑选命令与键
+#[derive(Debug)]
+struct Rng(u64);
+
+impl Rng {
+    //以标准库的随机哈希种子初始化，状态不能为0
+    fn new() -> Rng {
+        Rng(RandomState::new().build_hasher().finish() | 1)
+    }
+
+    //生成[0, n)范围内的随机数，n不能为0
+    fn below(&mut self, n: u64) -> u64 {
+        let mut x = self.0;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.0 = x;
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d) % n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(extra: &[&str]) -> Args {
+        Args::parse_from(["redis_rust_bench"].iter().chain(extra))
+    }
+
+    #[test]
+    fn summary() {
+        let latencies: Vec<_> = (1..=100).map(Duration::from_millis).collect();
+        let summary = Summary::new(Duration::from_secs(2), &latencies);
+        assert_eq!(summary.requests, 100);
+        assert_eq!(summary.throughput, 50.0);
+        assert_eq!(summary.min, 1.0);
+        assert_eq!(summary.p50, 51.0);
+        assert_eq!(summary.p99, 99.0);
+        assert_eq!(summary.max, 100.0);
+        let empty = Summary::new(Duration::from_secs(1), &[]);
+        assert_eq!((empty.throughput, empty.p99), (0.0, 0.0));
+    }
+
+    #[test]
+    fn thresholds() {
+        let latencies: Vec<_> = (1..=100).map(Duration::from_millis).collect();
+        let summary = Summary::new(Duration::from_secs(2), &latencies);
+        assert!(check(&args(&[]), "GET", &summary).is_ok());
+        assert!(check(
+            &args(&["--min-rps", "40", "--max-p99", "99"]),
+            "GET",
+            &summary
+        )
+        .is_ok());
+        assert!(check(&args(&["--min-rps", "60"]), "GET", &summary).is_err());
+        assert!(check(&args(&["--max-p99", "50"]), "GET", &summary).is_err());
+    }
+
+    #[test]
+    fn mset_keys_share_a_hash_tag() {
+        let workload = Workload {
+            ops: vec![(Op::Mset, 1)],
+            value: Bytes::from_static(b"xxx"),
+            keyspace: 10,
+        };
+        let args = match workload.request(Op::Mset, &mut Rng::new()) {
+            Frame::Array(args) => args,
+            frame => panic!("unexpected request {:?}", frame),
+        };
+        assert_eq!(args.len(), 5);
+        let tag = |frame: &Frame| match frame {
+            Frame::Bulk(key) => key.split(|b| *b == b'}').next().unwrap().to_vec(),
+            frame => panic!("unexpected argument {:?}", frame),
+        };
+        assert_eq!(tag(&args[1]), tag(&args[3]));
+        assert_ne!(args[1], args[3]);
+    }
+
+    #[test]
+    fn picks_follow_the_weights() {
+        let workload = Workload {
+            ops: vec![(Op::Set, 3), (Op::Lrange, 1)],
+            value: Bytes::from_static(b"xxx"),
+            keyspace: 10,
+        };
+        let mut rng = Rng::new();
+        let mut counts = [0; 2];
+        for _ in 0..4000 {
+            counts[workload.pick(&mut rng)] += 1;
+        }
+        assert!((2700..3300).contains(&counts[0]), "{:?}", counts);
+        assert_eq!(counts[0] + counts[1], 4000);
+    }
+}
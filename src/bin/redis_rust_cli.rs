@@ -0,0 +1,181 @@
+use bytes::{Bytes, BytesMut};
+use clap::{ArgAction, Parser};
+use redis_rust_server_2::lib::codec::{split_args, Decoder};
+use redis_rust_server_2::lib::conn::Connection;
+use redis_rust_server_2::lib::frame::Frame;
+use redis_rust_server_2::lib::Result;
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use std::path::PathBuf;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+//批量模式下攒够这么多字节再写入套接字
+const PIPE_CHUNK: usize = 64 * 1024;
+
+///兼容redis协议的命令行客户端
+///
+/// 给出命令时执行一次后退出，否则进入交互模式，逐行读取命令并打印回复，支持行编辑与历史记录
+#[derive(Debug, Parser)]
+#[command(version, about, disable_help_flag = true)]
+struct Args {
+    ///服务器的地址
+    #[arg(short = 'h', long, default_value = "127.0.0.1")]
+    host: String,
+    ///服务器的端口
+    #[arg(short = 'p', long, default_value_t = 6378)]
+    port: u16,
+    ///从标准输入读取原始的协议数据全部发送给服务器，用于批量导入
+    #[arg(long)]
+    pipe: bool,
+    ///打印帮助
+    #[arg(long = "help", action = ArgAction::Help)]
+    _help: Option<bool>,
+    ///要执行的命令与参数
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    command: Vec<String>,
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+    let socket = match TcpStream::connect((args.host.as_str(), args.port)).await {
+        Ok(socket) => socket,
+        Err(e) => {
+            eprintln!(
+                "Could not connect to Redis at {}:{}: {}",
+                args.host, args.port, e
+            );
+            std::process::exit(1);
+        }
+    };
+    let result = if args.pipe {
+        pipe(socket).await
+    } else if !args.command.is_empty() {
+        let command = args.command.into_iter().map(Bytes::from).collect();
+        call(&mut Connection::new(socket), command).await.map(print)
+    } else {
+        let prompt = format!("{}:{}> ", args.host, args.port);
+        repl(&mut Connection::new(socket), prompt).await
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+//交互模式：逐行读取命令，直到输入结束、按下ctrl-c或输入quit、exit
+//
+// 与redis-cli一样把输入过的命令保存在主目录下的历史文件中
+async fn repl(conn: &mut Connection<TcpStream>, prompt: String) -> Result<()> {
+    let mut editor = DefaultEditor::new().map_err(|e| e.to_string())?;
+    let history = history_file();
+    if let Some(path) = &history {
+        //第一次运行时还没有历史文件
+        let _ = editor.load_history(path);
+    }
+    let result = loop {
+        //读取一行会阻塞线程，告知运行时把其他任务移到别的线程上
+        let line = tokio::task::block_in_place(|| editor.readline(&prompt));
+        let line = match line {
+            Ok(line) => line,
+            Err(ReadlineError::Eof | ReadlineError::Interrupted) => break Ok(()),
+            Err(e) => break Err(e.to_string().into()),
+        };
+        let command = match split_args(line.as_bytes()) {
+            Ok(command) => command,
+            Err(_) => {
+                println!("Invalid argument(s)");
+                continue;
+            }
+        };
+        if command.is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(line.as_str());
+        let quit =
+            command[0].eq_ignore_ascii_case(b"quit") || command[0].eq_ignore_ascii_case(b"exit");
+        if quit {
+            break Ok(());
+        }
+        match call(conn, command).await {
+            Ok(frame) => print(frame),
+            Err(e) => break Err(e),
+        }
+    };
+    if let Some(path) = &history {
+        let _ = editor.save_history(path);
+    }
+    result
+}
+
+//历史文件的路径，没有主目录时不保存历史
+fn history_file() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".redis_rust_cli_history"))
+}
+
+//批量模式：把标准输入中的命令发送给服务器，同时读取并统计回复
+//
+// 一边写一边读，否则导入的数据超出套接字的缓冲区之后，双方都会阻塞在写入上
+async fn pipe(socket: TcpStream) -> Result<()> {
+    let mut input = vec![];
+    tokio::io::stdin().read_to_end(&mut input).await?;
+    let mut decoder = Decoder::new();
+    decoder.extend(&input);
+    let mut frames = vec![];
+    while let Some(frame) = decoder.decode()? {
+        frames.push(frame);
+    }
+    let sent = frames.len();
+    let (mut reader, mut writer) = socket.into_split();
+    //写完之后仍然持有写的一端，以免在读完回复之前关闭连接的写方向
+    let writing = tokio::spawn(async move {
+        let mut buf = BytesMut::with_capacity(PIPE_CHUNK);
+        for frame in &frames {
+            frame.encode(&mut buf);
+            if buf.len() >= PIPE_CHUNK {
+                writer.write_all(&buf).await?;
+                buf.clear();
+            }
+        }
+        writer.write_all(&buf).await?;
+        eprintln!("All data transferred. Waiting for the last reply...");
+        Ok::<_, std::io::Error>(writer)
+    });
+    let mut replies = Decoder::new();
+    let mut chunk = vec![0; PIPE_CHUNK];
+    let (mut received, mut errors) = (0, 0);
+    while received < sent {
+        match replies.decode()? {
+            Some(frame) => {
+                if let Frame::Error(e) = frame {
+                    eprintln!("{}", e);
+                    errors += 1;
+                }
+                received += 1;
+            }
+            None => match reader.read(&mut chunk).await? {
+                0 => return Err("connection closed by server".into()),
+                n => replies.extend(&chunk[..n]),
+            },
+        }
+    }
+    writing.await??;
+    eprintln!("errors: {}, replies: {}", errors, sent);
+    Ok(())
+}
+
+//发送一条命令并读取回复
+async fn call(conn: &mut Connection<TcpStream>, command: Vec<Bytes>) -> Result<Frame> {
+    let frame = Frame::Array(command.into_iter().map(Frame::Bulk).collect());
+    conn.write_frame(&frame).await?;
+    match conn.read_frame().await? {
+        Some(frame) => Ok(frame),
+        None => Err("connection closed by server".into()),
+    }
+}
+
+//以redis-cli的格式打印回复
+fn print(frame: Frame) {
+    println!("{}", frame.pretty());
+}
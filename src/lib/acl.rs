@@ -0,0 +1,368 @@
+use crate::lib::cmd::table::{self, CommandInfo};
+use crate::lib::frame::Frame;
+use crate::lib::glob;
+use crate::lib::sha1;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+//支持的命令类别，由命令表中的属性推导
+const CATEGORIES: &[&str] = &[
+    "all",
+    "read",
+    "write",
+    "fast",
+    "slow",
+    "admin",
+    "dangerous",
+    "pubsub",
+    "blocking",
+];
+
+///ACL中的一个用户
+///
+/// 新建的用户未启用、没有密码，不能执行任何命令，也不能访问任何键与频道
+#[derive(Debug, Clone)]
+pub(crate) struct User {
+    name: String,
+    //未启用的用户不能认证
+    enabled: bool,
+    //是否任意密码都能认证
+    nopass: bool,
+    //密码的SHA1摘要
+    passwords: BTreeSet<String>,
+    //允许执行的命令
+    commands: HashSet<&'static str>,
+    //修改命令权限的规则，描述用户时按顺序列出
+    command_rules: Vec<String>,
+    //允许访问的键的模式
+    keys: Vec<String>,
+    //允许访问的频道的模式
+    channels: Vec<String>,
+}
+
+impl User {
+    fn new(name: &str) -> User {
+        User {
+            name: name.to_string(),
+            enabled: false,
+            nopass: false,
+            passwords: BTreeSet::new(),
+            commands: HashSet::new(),
+            command_rules: vec!["-@all".to_string()],
+            keys: vec![],
+            channels: vec![],
+        }
+    }
+
+    ///用户的属性，与ACL GETUSER回复的flags一致
+    pub(crate) fn flags(&self) -> Vec<&'static str> {
+        let mut flags = vec![if self.enabled { "on" } else { "off" }];
+        if self.nopass {
+            flags.push("nopass");
+        }
+        flags
+    }
+
+    ///密码的SHA1摘要
+    pub(crate) fn passwords(&self) -> impl Iterator<Item = &str> {
+        self.passwords.iter().map(String::as_str)
+    }
+
+    ///命令权限的规则
+    pub(crate) fn command_rules(&self) -> String {
+        self.command_rules.join(" ")
+    }
+
+    ///允许访问的键，与规则中的写法一致
+    pub(crate) fn key_rules(&self) -> String {
+        let keys: Vec<_> = self.keys.iter().map(|key| format!("~{}", key)).collect();
+        keys.join(" ")
+    }
+
+    ///允许访问的频道，与规则中的写法一致
+    pub(crate) fn channel_rules(&self) -> String {
+        let channels: Vec<_> = self
+            .channels
+            .iter()
+            .map(|channel| format!("&{}", channel))
+            .collect();
+        channels.join(" ")
+    }
+
+    ///以ACL SETUSER规则的形式描述用户，ACL LIST的每一行
+    pub(crate) fn describe(&self) -> String {
+        let mut parts = vec![format!("user {}", self.name)];
+        parts.extend(self.flags().into_iter().map(str::to_string));
+        parts.extend(self.passwords().map(|hash| format!("#{}", hash)));
+        if !self.keys.is_empty() {
+            parts.push(self.key_rules());
+        }
+        if self.channels.is_empty() {
+            parts.push("resetchannels".to_string());
+        } else {
+            parts.push(self.channel_rules());
+        }
+        parts.push(self.command_rules());
+        parts.join(" ")
+    }
+
+    //应用一条规则，规则不合法时返回原因
+    fn apply_rule(&mut self, rule: &str) -> Result<(), &'static str> {
+        match rule.to_lowercase().as_str() {
+            "on" => self.enabled = true,
+            "off" => self.enabled = false,
+            "nopass" => {
+                self.nopass = true;
+                self.passwords.clear();
+            }
+            "resetpass" => {
+                self.nopass = false;
+                self.passwords.clear();
+            }
+            "allkeys" => self.keys = vec!["*".to_string()],
+            "resetkeys" => self.keys.clear(),
+            "allchannels" => self.channels = vec!["*".to_string()],
+            "resetchannels" => self.channels.clear(),
+            "allcommands" => self.apply_rule("+@all")?,
+            "nocommands" => self.apply_rule("-@all")?,
+            "reset" => *self = User::new(&self.name),
+            _ => return self.apply_pattern(rule),
+        }
+        Ok(())
+    }
+
+    //应用带前缀的规则
+    fn apply_pattern(&mut self, rule: &str) -> Result<(), &'static str> {
+        let (prefix, rest) = match rule.char_indices().nth(1) {
+            Some((i, _)) => rule.split_at(i),
+            None => return Err("Syntax error"),
+        };
+        match prefix {
+            ">" => {
+                self.passwords.insert(sha1::hex(rest.as_bytes()));
+                self.nopass = false;
+            }
+            "<" => {
+                if !self.passwords.remove(&sha1::hex(rest.as_bytes())) {
+                    return Err("no such password");
+                }
+            }
+            "#" => {
+                let valid = rest.len() == 40
+                    && rest
+                        .bytes()
+                        .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b));
+                if !valid {
+                    return Err("The password hash must be exactly 40 characters and contain only lowercase hexadecimal characters");
+                }
+                self.passwords.insert(rest.to_string());
+                self.nopass = false;
+            }
+            "!" => {
+                if !self.passwords.remove(rest) {
+                    return Err("no such password");
+                }
+            }
+            "~" => self.keys.push(rest.to_string()),
+            "&" => self.channels.push(rest.to_string()),
+            "+" | "-" => {
+                let allow = prefix == "+";
+                let name = rest.to_lowercase();
+                let commands: Vec<&'static CommandInfo> =
+                    match name.strip_prefix('@') {
+                        Some(category) if CATEGORIES.contains(&category) => table::commands()
+                            .into_iter()
+                            .filter(|info| in_category(info, category))
+                            .collect(),
+                        Some(_) => return Err("Unknown command or category name in ACL"),
+                        None => vec![table::lookup(&name)
+                            .ok_or("Unknown command or category name in ACL")?],
+                    };
+                for info in commands {
+                    if allow {
+                        self.commands.insert(info.name);
+                    } else {
+                        self.commands.remove(info.name);
+                    }
+                }
+                //+@all与-@all覆盖之前所有的规则
+                if name == "@all" {
+                    self.command_rules.clear();
+                }
+                self.command_rules.push(format!("{}{}", prefix, name));
+            }
+            _ => return Err("Syntax error"),
+        }
+        Ok(())
+    }
+
+    //能否访问键
+    fn can_access_key(&self, key: &[u8]) -> bool {
+        self.keys
+            .iter()
+            .any(|pattern| glob::matches(pattern.as_bytes(), key))
+    }
+
+    //能否访问频道，literal为true时频道本身是PSUBSCRIBE的模式，只能与用户的模式完全相同
+    fn can_access_channel(&self, channel: &[u8], literal: bool) -> bool {
+        self.channels.iter().any(|pattern| {
+            if literal {
+                pattern == "*" || pattern.as_bytes() == channel
+            } else {
+                glob::matches(pattern.as_bytes(), channel)
+            }
+        })
+    }
+}
+
+///命令所属的类别，不包括all
+pub(crate) fn categories(info: &CommandInfo) -> Vec<&'static str> {
+    CATEGORIES
+        .iter()
+        .copied()
+        .filter(|category| *category != "all" && in_category(info, category))
+        .collect()
+}
+
+//命令是否属于某个类别
+fn in_category(info: &CommandInfo, category: &str) -> bool {
+    match category {
+        "all" => true,
+        "read" => info.has_flag("readonly"),
+        "write" => info.has_flag("write"),
+        "fast" => info.has_flag("fast"),
+        "slow" => !info.has_flag("fast"),
+        "admin" | "dangerous" => info.has_flag("admin"),
+        "pubsub" => info.has_flag("pubsub"),
+        "blocking" => info.has_flag("blocking"),
+        _ => false,
+    }
+}
+
+///访问控制列表
+///
+/// 总是存在default用户，requirepass配置的就是它的密码。连接认证之后，
+/// 每条命令在执行前都要检查当前用户能否执行该命令、访问其中的键与频道
+#[derive(Debug, Clone)]
+pub(crate) struct Acl {
+    users: BTreeMap<String, User>,
+}
+
+impl Acl {
+    ///创建只有default用户的ACL，default用户可以执行所有命令
+    pub(crate) fn new(requirepass: &str) -> Acl {
+        let mut default = User::new("default");
+        for rule in ["on", "allkeys", "allchannels", "+@all"] {
+            let _ = default.apply_rule(rule);
+        }
+        let mut acl = Acl {
+            users: BTreeMap::new(),
+        };
+        acl.users.insert(default.name.clone(), default);
+        acl.set_default_password(requirepass);
+        acl
+    }
+
+    ///修改default用户的密码，为空时default用户无需密码
+    pub(crate) fn set_default_password(&mut self, requirepass: &str) {
+        if let Some(default) = self.users.get_mut("default") {
+            let _ = default.apply_rule("resetpass");
+            let rule = if requirepass.is_empty() {
+                "nopass".to_string()
+            } else {
+                format!(">{}", requirepass)
+            };
+            let _ = default.apply_rule(&rule);
+        }
+    }
+
+    ///创建或修改用户
+    ///
+    /// 规则按顺序应用，任何一条不合法时用户保持不变
+    pub(crate) fn set_user(&mut self, name: &str, rules: &[String]) -> Result<(), String> {
+        let mut user = self
+            .users
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| User::new(name));
+        for rule in rules {
+            user.apply_rule(rule).map_err(|reason| {
+                format!("ERR Error in ACL SETUSER modifier '{}': {}", rule, reason)
+            })?;
+        }
+        self.users.insert(name.to_string(), user);
+        Ok(())
+    }
+
+    ///按名称查找用户
+    pub(crate) fn user(&self, name: &str) -> Option<&User> {
+        self.users.get(name)
+    }
+
+    ///按名称排列的所有用户
+    pub(crate) fn users(&self) -> impl Iterator<Item = &User> {
+        self.users.values()
+    }
+
+    ///用户名与密码是否正确
+    pub(crate) fn authenticate(&self, name: &str, password: &[u8]) -> bool {
+        match self.users.get(name) {
+            Some(user) if user.enabled => {
+                user.nopass || user.passwords.contains(&sha1::hex(password))
+            }
+            _ => false,
+        }
+    }
+
+    ///新连接无需认证即可使用的用户，default用户无需密码时为default
+    pub(crate) fn default_user(&self) -> Option<String> {
+        self.users
+            .get("default")
+            .filter(|user| user.enabled && user.nopass)
+            .map(|user| user.name.clone())
+    }
+
+    ///检查用户能否执行解析之前的命令，不能执行时返回回复给客户端的错误
+    ///
+    /// 不存在的命令与参数个数错误留给解析时回复，认证相关的命令总是可以执行
+    pub(crate) fn check(&self, user: &str, frame: &Frame) -> Result<(), String> {
+        let args = table::args(frame);
+        let name = match args.first() {
+            Some(name) => String::from_utf8_lossy(name).to_lowercase(),
+            None => return Ok(()),
+        };
+        let info = match table::lookup(&name) {
+            Some(info) if info.accepts(args.len()) && !info.has_flag("no-auth") => info,
+            _ => return Ok(()),
+        };
+        let no_permission = || {
+            format!(
+                "NOPERM User {} has no permissions to run the '{}' command",
+                user, name
+            )
+        };
+        let user = self.users.get(user).ok_or_else(no_permission)?;
+        if !user.commands.contains(info.name) {
+            return Err(no_permission());
+        }
+        if info
+            .key_args(&args)
+            .into_iter()
+            .any(|key| !user.can_access_key(key))
+        {
+            return Err("NOPERM No permissions to access a key".to_string());
+        }
+        let channels = match info.name {
+            "publish" | "spublish" => &args[1..2],
+            "subscribe" | "psubscribe" | "ssubscribe" => &args[1..],
+            _ => &[],
+        };
+        let literal = info.name == "psubscribe";
+        if channels
+            .iter()
+            .any(|channel| !user.can_access_channel(channel, literal))
+        {
+            return Err("NOPERM No permissions to access a channel".to_string());
+        }
+        Ok(())
+    }
+}
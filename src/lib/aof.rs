@@ -0,0 +1,557 @@
+use crate::lib;
+use crate::lib::cmd::Command;
+use crate::lib::config::Config;
+use crate::lib::db::{now_millis, Db};
+use crate::lib::frame::{Frame, FrameError};
+use crate::lib::propagate::{encode, select, CommandStream};
+use crate::lib::rdb::{self, Snapshot};
+use crate::lib::value::dump;
+use bytes::Bytes;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Cursor, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+///已经在重写时再次要求重写的错误
+pub(crate) const REWRITE_IN_PROGRESS: &str =
+    "ERR Background append only file rewriting already in progress";
+
+//everysec策略下两次刷盘的间隔，也是写入线程空闲时检查刷盘的间隔
+const SYNC_INTERVAL: Duration = Duration::from_secs(1);
+//自动重写失败后至少间隔这么多秒再重试
+const RETRY_DELAY: u64 = 5;
+
+///AOF：把修改了数据的命令追加到文件，启动时按顺序重放
+///
+/// 开启时写命令在顺序锁内依次执行并追加，文件中命令的顺序与实际修改数据的顺序一致。
+/// 追加的命令交给专门的写入线程，写入线程把积压的命令合并为一次写入，再按appendfsync刷盘。
+/// 文件随写入不断增长，重写时以当前的数据生成最短的命令序列替换原有的文件
+#[derive(Debug)]
+pub(crate) struct Aof {
+    //是否开启
+    enabled: AtomicBool,
+    //是否正在重写
+    rewriting: AtomicBool,
+    //上次重写是否成功
+    rewrite_ok: AtomicBool,
+    //上次开始重写的unix时间戳（秒）
+    rewrite_started: AtomicU64,
+    //写入线程，未开启时为None
+    writer: Mutex<Option<Writer>>,
+    //刷盘策略与写入结果，与写入线程共用
+    status: Arc<Status>,
+}
+
+//向写入线程发送数据的一端
+#[derive(Debug)]
+struct Writer {
+    sender: Sender<Append>,
+    //追加的命令流
+    stream: CommandStream,
+    thread: JoinHandle<()>,
+    //重写期间追加的数据，新文件写完之后补在其末尾，不在重写时为None
+    rewrite: Option<Vec<u8>>,
+}
+
+//一次追加的数据，always策略下附带刷盘之后的确认
+type Append = (Vec<u8>, Option<SyncSender<()>>);
+
+//刷盘策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum Fsync {
+    //每批写入之后刷盘，刷盘之后才回复客户端
+    Always,
+    //每秒刷盘一次
+    EverySec,
+    //交给操作系统决定
+    No,
+}
+
+impl Fsync {
+    fn parse(value: &str) -> Fsync {
+        match value {
+            "always" => Fsync::Always,
+            "no" => Fsync::No,
+            _ => Fsync::EverySec,
+        }
+    }
+}
+
+//刷盘策略与写入结果
+#[derive(Debug)]
+struct Status {
+    //刷盘策略，修改配置时同步
+    fsync: AtomicU8,
+    //上次写入或刷盘是否成功
+    last_write_ok: AtomicBool,
+    //文件当前的大小（字节）
+    size: AtomicU64,
+    //上次重写或开启AOF之后文件的大小（字节），自动重写以此计算增长
+    base_size: AtomicU64,
+}
+
+impl Status {
+    fn fsync(&self) -> Fsync {
+        match self.fsync.load(Ordering::Relaxed) {
+            0 => Fsync::Always,
+            2 => Fsync::No,
+            _ => Fsync::EverySec,
+        }
+    }
+
+    //写入数据并记录结果，失败时数据被丢弃，之后的写入照常进行
+    fn write(&self, file: &mut File, data: &[u8]) {
+        match file.write_all(data) {
+            Ok(()) => {
+                self.size.fetch_add(data.len() as u64, Ordering::Relaxed);
+                self.last_write_ok.store(true, Ordering::Relaxed);
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "写入AOF失败");
+                self.last_write_ok.store(false, Ordering::Relaxed);
+            }
+        }
+    }
+
+    //刷盘并记录结果
+    fn sync(&self, file: &File) {
+        if let Err(e) = file.sync_data() {
+            tracing::warn!(error = %e, "AOF刷盘失败");
+            self.last_write_ok.store(false, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Aof {
+    ///按配置创建，开启要等到载入数据之后调用start
+    pub(crate) fn new(config: &Config) -> Aof {
+        Aof {
+            enabled: AtomicBool::new(false),
+            rewriting: AtomicBool::new(false),
+            rewrite_ok: AtomicBool::new(true),
+            rewrite_started: AtomicU64::new(0),
+            writer: Mutex::new(None),
+            status: Arc::new(Status {
+                fsync: AtomicU8::new(Fsync::parse(config.append_fsync()) as u8),
+                last_write_ok: AtomicBool::new(true),
+                size: AtomicU64::new(0),
+                base_size: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    ///是否开启
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Acquire)
+    }
+
+    ///上次写入或刷盘是否成功
+    pub(crate) fn last_write_ok(&self) -> bool {
+        self.status.last_write_ok.load(Ordering::Relaxed)
+    }
+
+    ///文件当前的大小（字节）
+    pub(crate) fn size(&self) -> u64 {
+        self.status.size.load(Ordering::Relaxed)
+    }
+
+    ///上次重写或开启AOF之后文件的大小（字节）
+    pub(crate) fn base_size(&self) -> u64 {
+        self.status.base_size.load(Ordering::Relaxed)
+    }
+
+    ///是否正在重写
+    pub(crate) fn is_rewriting(&self) -> bool {
+        self.rewriting.load(Ordering::Acquire)
+    }
+
+    ///上次重写是否成功
+    pub(crate) fn rewrite_ok(&self) -> bool {
+        self.rewrite_ok.load(Ordering::Relaxed)
+    }
+
+    ///追加在各自的逻辑数据库中执行的命令，未开启时什么也不做
+    ///
+    /// 必须在顺序锁内调用，transaction为true时包装在MULTI与EXEC之间，重放时同样整体执行。
+    /// always策略下等到写入线程刷盘之后才返回
+    pub(crate) fn append(&self, commands: &[(usize, Vec<Bytes>)], transaction: bool) {
+        if !self.is_enabled() {
+            return;
+        }
+        let synced = {
+            let mut writer = self.writer.lock().unwrap();
+            let writer = match writer.as_mut() {
+                Some(writer) => writer,
+                None => return,
+            };
+            let data = writer.stream.encode(commands, transaction);
+            if let Some(buffer) = &mut writer.rewrite {
+                buffer.extend_from_slice(&data);
+            }
+            let (ack, synced) = match self.status.fsync() {
+                Fsync::Always => {
+                    let (ack, synced) = mpsc::sync_channel(1);
+                    (Some(ack), Some(synced))
+                }
+                _ => (None, None),
+            };
+            if writer.sender.send((data, ack)).is_err() {
+                return;
+            }
+            synced
+        };
+        if let Some(synced) = synced {
+            let _ = synced.recv();
+        }
+    }
+
+    ///关闭AOF，等待写入线程写完积压的数据并刷盘
+    pub(crate) fn stop(&self) {
+        let writer = self.writer.lock().unwrap().take();
+        self.enabled.store(false, Ordering::Release);
+        if let Some(writer) = writer {
+            drop(writer.sender);
+            let _ = writer.thread.join();
+            tracing::info!("已关闭AOF");
+        }
+    }
+}
+
+///开启AOF
+///
+/// base为true时新建文件，先写入当前的全部数据，否则在原有的文件之后追加。
+/// 复制数据时持有顺序锁，之后的写命令都排在这份数据之后
+pub(crate) fn start(db: &Db, base: bool) -> io::Result<()> {
+    let aof = db.aof();
+    let path = db.config().aof_path();
+    db.ordered_exclusively(|| {
+        let mut writer = aof.writer.lock().unwrap();
+        //开启之前配置可能又被改回了no
+        if writer.is_some() || !db.config().append_only() {
+            return Ok(());
+        }
+        let (file, snapshot) = if base {
+            (File::create(&path)?, Some(rdb::snapshot(db).0))
+        } else {
+            let file = OpenOptions::new().create(true).append(true).open(&path)?;
+            (file, None)
+        };
+        let size = file.metadata()?.len();
+        aof.status.size.store(size, Ordering::Relaxed);
+        aof.status.base_size.store(size, Ordering::Relaxed);
+        let (sender, thread) = spawn_writer(file, snapshot, aof.status.clone())?;
+        *writer = Some(Writer {
+            sender,
+            stream: CommandStream::default(),
+            thread,
+            rewrite: None,
+        });
+        aof.enabled.store(true, Ordering::Release);
+        tracing::info!(path = %path.display(), "已开启AOF");
+        Ok(())
+    })
+}
+
+///在后台重写AOF，已经在重写时返回错误
+///
+/// 在顺序锁内复制当前的全部数据，之后追加的命令同时积攒在重写缓冲区中。新文件在阻塞线程池中写入，
+/// 写完之后再在顺序锁内补上缓冲区的内容、替换原有的文件，之后的命令追加到新文件。
+/// 未开启AOF时只以当前的数据生成文件
+pub(crate) fn bgrewrite(db: &Db) -> Result<(), &'static str> {
+    let aof = db.aof();
+    if aof
+        .rewriting
+        .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+        .is_err()
+    {
+        return Err(REWRITE_IN_PROGRESS);
+    }
+    aof.rewrite_started
+        .store(now_millis() / 1000, Ordering::Relaxed);
+    let path = db.config().aof_path();
+    let snapshot = db.ordered_exclusively(|| {
+        if let Some(writer) = aof.writer.lock().unwrap().as_mut() {
+            writer.rewrite = Some(vec![]);
+            //缓冲区中的命令补在新文件的末尾，第一条命令之前需要先SELECT
+            writer.stream.reset();
+        }
+        rdb::snapshot(db).0
+    });
+    let db = db.clone();
+    tokio::task::spawn_blocking(move || {
+        let started = Instant::now();
+        let tmp = path.with_file_name(format!("temp-rewriteaof-{}.aof", std::process::id()));
+        let result = rewrite(&db, &path, &tmp, &snapshot);
+        let aof = db.aof();
+        match &result {
+            Ok(()) => {
+                tracing::info!(path = %path.display(), elapsed = ?started.elapsed(), "AOF重写完成")
+            }
+            Err(e) => {
+                tracing::warn!(path = %path.display(), error = %e, "重写AOF失败");
+                let _ = fs::remove_file(&tmp);
+                if let Some(writer) = aof.writer.lock().unwrap().as_mut() {
+                    writer.rewrite = None;
+                }
+            }
+        }
+        aof.rewrite_ok.store(result.is_ok(), Ordering::Relaxed);
+        aof.rewriting.store(false, Ordering::Release);
+    });
+    Ok(())
+}
+
+///按auto-aof-rewrite-percentage与auto-aof-rewrite-min-size自动重写AOF，每秒检查一次
+///
+/// 文件不小于最小大小、且比上次重写之后增长的百分比达到配置时重写。
+/// 上次重写失败时至少间隔RETRY_DELAY秒再重试
+pub(crate) async fn rewrite_task(db: Db) {
+    let mut interval = tokio::time::interval(SYNC_INTERVAL);
+    loop {
+        interval.tick().await;
+        let aof = db.aof();
+        let since =
+            (now_millis() / 1000).saturating_sub(aof.rewrite_started.load(Ordering::Relaxed));
+        if !aof.is_enabled() || aof.is_rewriting() || !aof.rewrite_ok() && since < RETRY_DELAY {
+            continue;
+        }
+        //重写需要独占键空间复制数据，脚本执行期间等到下一次检查
+        if db.running_script().is_running() {
+            continue;
+        }
+        let (percentage, min_size) = db.config().auto_aof_rewrite();
+        let size = aof.size();
+        if percentage == 0 || size < min_size {
+            continue;
+        }
+        let base = aof.base_size().max(1);
+        let growth = size.saturating_sub(base) * 100 / base;
+        if growth >= percentage {
+            tracing::info!("AOF比上次重写之后增长了{}%，开始重写", growth);
+            //其他连接可能恰好执行了BGREWRITEAOF
+            let _ = bgrewrite(&db);
+        }
+    }
+}
+
+///按配置开启或关闭AOF并同步刷盘策略，修改配置之后调用
+///
+/// 开启需要复制全部数据，交给阻塞线程池进行，不阻塞修改配置的连接
+pub(crate) fn configure(db: &Db) {
+    let (enabled, fsync) = {
+        let config = db.config();
+        (config.append_only(), Fsync::parse(config.append_fsync()))
+    };
+    let aof = db.aof();
+    aof.status.fsync.store(fsync as u8, Ordering::Relaxed);
+    if !enabled {
+        aof.stop();
+    } else if !aof.is_enabled() {
+        let db = db.clone();
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = start(&db, true) {
+                tracing::warn!(error = %e, "开启AOF失败");
+            }
+        });
+    }
+}
+
+///启动时重放AOF，返回重放的命令数，文件不存在时返回None
+///
+/// 命令通过与客户端相同的解析与执行路径重放。文件末尾不完整的命令或事务是写到一半时进程退出留下的，
+/// 截掉之后照常启动；其他无法解析的内容返回错误，与redis一致拒绝在数据不完整的情况下启动
+pub(crate) fn load(db: &Db) -> lib::Result<Option<usize>> {
+    let path = db.config().aof_path();
+    let data = match fs::read(&path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let started = Instant::now();
+    let bad = || format!("Bad file format reading {}", path.display());
+    let mut cursor = Cursor::new(&data[..]);
+    let mut current = db.clone();
+    //MULTI之后排队的命令，EXEC时才执行
+    let mut transaction: Option<Vec<Command>> = None;
+    let mut replayed = 0;
+    //最后一条完整的命令或事务之后的位置
+    let mut complete = 0;
+    while (cursor.position() as usize) < data.len() {
+        let start = cursor.position();
+        match Frame::check(&mut cursor) {
+            Ok(()) => {}
+            Err(FrameError::Incomplete) => break,
+            Err(FrameError::Other(_)) => return Err(bad().into()),
+        }
+        cursor.set_position(start);
+        let frame = Frame::parse(&mut cursor).map_err(|_| bad())?;
+        let cmd = Command::from_frame(frame).map_err(|e| format!("{}: {}", bad(), e))?;
+        match cmd {
+            Command::Multi if transaction.is_none() => transaction = Some(vec![]),
+            Command::Multi => return Err(bad().into()),
+            Command::Exec => {
+                for cmd in transaction.take().ok_or_else(bad)? {
+                    replay(&mut current, cmd);
+                }
+            }
+            cmd => match &mut transaction {
+                Some(queued) => queued.push(cmd),
+                None => replay(&mut current, cmd),
+            },
+        }
+        replayed += 1;
+        if transaction.is_none() {
+            complete = cursor.position();
+        }
+    }
+    if complete < data.len() as u64 {
+        tracing::warn!(
+            path = %path.display(),
+            "AOF末尾的命令不完整，已截掉{}字节",
+            data.len() as u64 - complete
+        );
+        OpenOptions::new()
+            .write(true)
+            .open(&path)?
+            .set_len(complete)?;
+    }
+    db.saves().loaded(db.stats().changes.get());
+    tracing::info!(commands = replayed, elapsed = ?started.elapsed(), "已从AOF载入数据");
+    Ok(Some(replayed))
+}
+
+//重放一条命令，SELECT切换db
+fn replay(db: &mut Db, cmd: Command) {
+    let reply = match cmd {
+        Command::Select(cmd) => cmd.apply(db),
+        cmd => cmd.apply_now(db),
+    };
+    if let Frame::Error(e) = reply {
+        tracing::debug!("重放的命令回复了错误：{}", e);
+    }
+}
+
+//以快照写入临时文件，再在顺序锁内补上重写缓冲区并替换原有的文件与写入线程
+fn rewrite(db: &Db, path: &Path, tmp: &Path, snapshot: &Snapshot) -> io::Result<()> {
+    let mut file = File::create(tmp)?;
+    file.write_all(&encode_snapshot(snapshot))?;
+    file.sync_data()?;
+    let aof = db.aof();
+    db.ordered_exclusively(|| {
+        let mut writer = aof.writer.lock().unwrap();
+        let current = match writer.as_mut() {
+            Some(current) => current,
+            None => return fs::rename(tmp, path),
+        };
+        //重写期间AOF被关闭后又重新开启，新的写入线程没有积攒缓冲区
+        let buffered = current
+            .rewrite
+            .take()
+            .ok_or_else(|| io::Error::other("append only file was reopened during the rewrite"))?;
+        file.write_all(&buffered)?;
+        file.sync_data()?;
+        let size = file.metadata()?.len();
+        //原有的写入线程写完积压的数据之后退出，其中的命令都已在缓冲区中
+        let (sender, thread) = spawn_writer(file, None, aof.status.clone())?;
+        if let Err(e) = fs::rename(tmp, path) {
+            drop(sender);
+            let _ = thread.join();
+            return Err(e);
+        }
+        let previous = std::mem::replace(&mut current.sender, sender);
+        let previous_thread = std::mem::replace(&mut current.thread, thread);
+        drop(previous);
+        let _ = previous_thread.join();
+        aof.status.size.store(size, Ordering::Relaxed);
+        aof.status.base_size.store(size, Ordering::Relaxed);
+        Ok(())
+    })
+}
+
+//启动写入线程，base不为None时先写入其中的全部数据
+fn spawn_writer(
+    file: File,
+    base: Option<Snapshot>,
+    status: Arc<Status>,
+) -> io::Result<(Sender<Append>, JoinHandle<()>)> {
+    let (sender, receiver) = mpsc::channel();
+    let thread = thread::Builder::new()
+        .name("aof-writer".to_string())
+        .spawn(move || write_loop(file, base, receiver, status))?;
+    Ok((sender, thread))
+}
+
+//写入线程：合并积压的数据写入文件，按刷盘策略调用fsync，发送端被丢弃后刷盘退出
+fn write_loop(
+    mut file: File,
+    base: Option<Snapshot>,
+    receiver: Receiver<Append>,
+    status: Arc<Status>,
+) {
+    let mut dirty = false;
+    if let Some(snapshot) = base {
+        status.write(&mut file, &encode_snapshot(&snapshot));
+        let size = status.size.load(Ordering::Relaxed);
+        status.base_size.store(size, Ordering::Relaxed);
+        dirty = true;
+    }
+    let mut synced_at = Instant::now();
+    loop {
+        let first = match receiver.recv_timeout(SYNC_INTERVAL) {
+            Ok(append) => Some(append),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        let mut data = vec![];
+        let mut acks = vec![];
+        for (bytes, ack) in first.into_iter().chain(receiver.try_iter()) {
+            data.extend_from_slice(&bytes);
+            acks.extend(ack);
+        }
+        if !data.is_empty() {
+            status.write(&mut file, &data);
+            dirty = true;
+        }
+        let due = match status.fsync() {
+            Fsync::Always => true,
+            Fsync::EverySec => synced_at.elapsed() >= SYNC_INTERVAL,
+            Fsync::No => false,
+        };
+        if dirty && due {
+            status.sync(&file);
+            dirty = false;
+            synced_at = Instant::now();
+        }
+        for ack in acks {
+            let _ = ack.send(());
+        }
+    }
+    if dirty {
+        status.sync(&file);
+    }
+}
+
+//把快照编码为RESTORE命令，过期时间以unix时间戳给出
+fn encode_snapshot(snapshot: &Snapshot) -> Vec<u8> {
+    let mut data = vec![];
+    for (index, entries) in snapshot {
+        encode(&mut data, &select(*index));
+        for (key, value, expires_at) in entries {
+            let args = [
+                Bytes::from_static(b"RESTORE"),
+                Bytes::from(key.clone()),
+                Bytes::from(expires_at.unwrap_or(0).to_string()),
+                dump::serialize(value),
+                Bytes::from_static(b"REPLACE"),
+                Bytes::from_static(b"ABSTTL"),
+            ];
+            encode(&mut data, &args);
+        }
+    }
+    data
+}
@@ -0,0 +1,374 @@
+use crate::lib::cmd::table::CommandInfo;
+use crate::lib::tracking::Invalidation;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicU16, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Notify, OwnedSemaphorePermit, Semaphore};
+use tokio::task::AbortHandle;
+
+///一个客户端连接的登记信息
+///
+/// 由处理连接的任务持有并更新，CLIENT命令通过登记表读取或关闭其他连接
+#[derive(Debug)]
+pub(crate) struct Client {
+    id: u64,
+    //对端的地址
+    addr: String,
+    //连接建立的时间
+    created: Instant,
+    //客户端设置的名称
+    name: Mutex<Option<String>>,
+    //最近一次执行的命令与执行的时间
+    last_command: Mutex<(&'static str, Instant)>,
+    //当前选择的逻辑数据库
+    db: AtomicUsize,
+    //是否已被CLIENT KILL关闭
+    killed: AtomicBool,
+    //从节点通过REPLCONF告知的监听端口，0代表没有告知
+    listening_port: AtomicU16,
+    //处理连接的任务，关闭连接时中止它
+    task: Mutex<Option<AbortHandle>>,
+    //发往连接的失效消息，由处理连接的任务在等待命令时发出
+    invalidations: mpsc::UnboundedSender<Invalidation>,
+    //开始等待下一个命令的时间，正在执行命令、订阅或阻塞时为None
+    waiting_since: Mutex<Option<Instant>>,
+    //空闲超时，由后台的定时任务发出
+    timed_out: Notify,
+}
+
+impl Client {
+    ///连接的id，在服务器运行期间唯一
+    pub(crate) fn id(&self) -> u64 {
+        self.id
+    }
+
+    ///对端的地址
+    pub(crate) fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    ///客户端设置的名称
+    pub(crate) fn name(&self) -> Option<String> {
+        self.name.lock().unwrap().clone()
+    }
+
+    ///设置名称，为None时清除
+    pub(crate) fn set_name(&self, name: Option<String>) {
+        *self.name.lock().unwrap() = name;
+    }
+
+    ///记录即将执行的命令
+    pub(crate) fn touch(&self, command: &'static str) {
+        *self.last_command.lock().unwrap() = (command, Instant::now());
+    }
+
+    ///记录当前选择的逻辑数据库
+    pub(crate) fn set_db(&self, index: usize) {
+        self.db.store(index, Ordering::Relaxed);
+    }
+
+    ///从节点通过REPLCONF告知的监听端口，0代表没有告知
+    pub(crate) fn listening_port(&self) -> u16 {
+        self.listening_port.load(Ordering::Relaxed)
+    }
+
+    ///记录从节点的监听端口
+    pub(crate) fn set_listening_port(&self, port: u16) {
+        self.listening_port.store(port, Ordering::Relaxed);
+    }
+
+    ///记录处理连接的任务，已被关闭时立即中止它
+    pub(crate) fn set_task(&self, task: AbortHandle) {
+        let mut slot = self.task.lock().unwrap();
+        if self.is_killed() {
+            task.abort();
+        } else {
+            *slot = Some(task);
+        }
+    }
+
+    ///关闭连接，立即中止处理连接的任务
+    pub(crate) fn kill(&self) {
+        self.killed.store(true, Ordering::Release);
+        if let Some(task) = self.task.lock().unwrap().take() {
+            task.abort();
+        }
+    }
+
+    ///标记连接在发出当前的回复之后关闭，连接关闭自己时使用
+    pub(crate) fn close_after_reply(&self) {
+        self.killed.store(true, Ordering::Release);
+    }
+
+    ///连接是否已被关闭
+    pub(crate) fn is_killed(&self) -> bool {
+        self.killed.load(Ordering::Acquire)
+    }
+
+    ///标记连接开始或结束等待下一个命令，连续标记开始时保留最早的时间
+    pub(crate) fn set_waiting(&self, waiting: bool) {
+        let mut since = self.waiting_since.lock().unwrap();
+        *since = match waiting {
+            true => Some(since.unwrap_or_else(Instant::now)),
+            false => None,
+        };
+    }
+
+    ///连接已经等待下一个命令多久，不在等待时为None
+    pub(crate) fn idle_for(&self) -> Option<Duration> {
+        self.waiting_since
+            .lock()
+            .unwrap()
+            .map(|since| since.elapsed())
+    }
+
+    ///等待后台任务发现连接空闲超时
+    ///
+    /// 通知可能在连接收到命令之后才被取走，调用方需要用idle_for再次确认
+    pub(crate) async fn timed_out(&self) {
+        self.timed_out.notified().await;
+    }
+
+    ///向连接发送一条失效消息，连接已经关闭时丢弃
+    pub(crate) fn invalidate(&self, invalidation: Invalidation) {
+        let _ = self.invalidations.send(invalidation);
+    }
+
+    ///CLIENT LIST中的一行
+    pub(crate) fn describe(&self) -> String {
+        let now = Instant::now();
+        let (command, last) = *self.last_command.lock().unwrap();
+        format!(
+            "id={} addr={} name={} age={} idle={} db={} cmd={}\n",
+            self.id,
+            self.addr,
+            self.name().unwrap_or_default(),
+            now.duration_since(self.created).as_secs(),
+            now.duration_since(last).as_secs(),
+            self.db.load(Ordering::Relaxed),
+            command
+        )
+    }
+}
+
+///CLIENT PAUSE暂停的命令
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum PauseMode {
+    ///只暂停写命令以及EVAL、PUBLISH等可能写入的命令
+    Write,
+    ///暂停除CLIENT之外的所有命令，CLIENT留给解除暂停
+    All,
+}
+
+impl PauseMode {
+    //是否暂停该命令
+    fn blocks(self, info: &CommandInfo) -> bool {
+        match self {
+            PauseMode::All => info.name != "client",
+            PauseMode::Write => {
+                info.has_flag("write")
+                    || matches!(
+                        info.name,
+                        "eval" | "evalsha" | "publish" | "spublish" | "pfcount" | "exec"
+                    )
+            }
+        }
+    }
+}
+
+///服务器上所有连接的登记表
+#[derive(Debug)]
+pub(crate) struct Clients {
+    //按id排列的连接
+    clients: Mutex<BTreeMap<u64, Arc<Client>>>,
+    //上一个连接的id
+    last_id: AtomicU64,
+    //暂停的截止时间与暂停的命令
+    pause: Mutex<Option<(Instant, PauseMode)>>,
+    //暂停被提前解除时通知等待的连接
+    unpaused: Notify,
+    //连接数的名额，每个连接持有一个，总数为maxclients
+    slots: Arc<Semaphore>,
+    //当前生效的maxclients
+    limit: Mutex<usize>,
+    //调低maxclients时仍被连接占用、需要在归还时作废的名额
+    owed: AtomicUsize,
+}
+
+impl Clients {
+    ///创建登记表，最多同时存在max_clients个连接
+    pub(crate) fn new(max_clients: usize) -> Clients {
+        Clients {
+            clients: Mutex::new(BTreeMap::new()),
+            last_id: AtomicU64::new(0),
+            pause: Mutex::new(None),
+            unpaused: Notify::new(),
+            slots: Arc::new(Semaphore::new(max_clients)),
+            limit: Mutex::new(max_clients),
+            owed: AtomicUsize::new(0),
+        }
+    }
+
+    ///为新连接申请一个名额，连接数已满时返回None
+    ///
+    /// 连接关闭时需要通过release归还名额
+    pub(crate) fn admit(&self) -> Option<OwnedSemaphorePermit> {
+        self.slots.clone().try_acquire_owned().ok()
+    }
+
+    ///等待名额空出，maxclients-policy为wait时连接数已满的新连接在这里排队
+    pub(crate) async fn wait_admit(&self) -> OwnedSemaphorePermit {
+        //信号量从不关闭
+        self.slots.clone().acquire_owned().await.unwrap()
+    }
+
+    ///归还连接的名额
+    pub(crate) fn release(&self, permit: OwnedSemaphorePermit) {
+        let owed = self
+            .owed
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |owed| {
+                owed.checked_sub(1)
+            });
+        if owed.is_ok() {
+            permit.forget();
+        }
+    }
+
+    ///修改允许同时存在的最大连接数
+    ///
+    /// 调低时已经建立的连接不受影响，多出的名额在这些连接关闭时作废
+    pub(crate) fn set_max(&self, max_clients: usize) {
+        let mut limit = self.limit.lock().unwrap();
+        if max_clients > *limit {
+            let extra = max_clients - *limit;
+            //先抵消尚未作废的名额
+            let mut cancelled = 0;
+            let _ = self
+                .owed
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |owed| {
+                    cancelled = owed.min(extra);
+                    Some(owed - cancelled)
+                });
+            self.slots.add_permits(extra - cancelled);
+        } else {
+            let mut fewer = *limit - max_clients;
+            while fewer > 0 {
+                match self.slots.try_acquire() {
+                    Ok(permit) => permit.forget(),
+                    Err(_) => break,
+                }
+                fewer -= 1;
+            }
+            self.owed.fetch_add(fewer, Ordering::AcqRel);
+        }
+        *limit = max_clients;
+    }
+
+    ///登记新连接，分配一个新的id，同时返回接收发往该连接的失效消息的接收端
+    pub(crate) fn register(
+        &self,
+        addr: String,
+    ) -> (Arc<Client>, mpsc::UnboundedReceiver<Invalidation>) {
+        let id = self.last_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let now = Instant::now();
+        let (invalidations, receiver) = mpsc::unbounded_channel();
+        let client = Arc::new(Client {
+            id,
+            addr,
+            created: now,
+            name: Mutex::new(None),
+            last_command: Mutex::new(("NULL", now)),
+            db: AtomicUsize::new(0),
+            killed: AtomicBool::new(false),
+            listening_port: AtomicU16::new(0),
+            task: Mutex::new(None),
+            invalidations,
+            waiting_since: Mutex::new(None),
+            timed_out: Notify::new(),
+        });
+        self.clients.lock().unwrap().insert(id, client.clone());
+        (client, receiver)
+    }
+
+    ///按id查找连接
+    pub(crate) fn get(&self, id: u64) -> Option<Arc<Client>> {
+        self.clients.lock().unwrap().get(&id).cloned()
+    }
+
+    ///连接关闭后注销
+    pub(crate) fn remove(&self, id: u64) {
+        self.clients.lock().unwrap().remove(&id);
+    }
+
+    ///在线的连接数
+    pub(crate) fn len(&self) -> usize {
+        self.clients.lock().unwrap().len()
+    }
+
+    ///按id排列的所有连接
+    pub(crate) fn all(&self) -> Vec<Arc<Client>> {
+        self.clients.lock().unwrap().values().cloned().collect()
+    }
+
+    ///通知等待下一个命令超过timeout的连接关闭
+    ///
+    /// 由后台的定时任务按hz调用，订阅与阻塞中的连接不在等待命令，不受影响
+    pub(crate) fn close_idle(&self, timeout: Duration) {
+        for client in self.clients.lock().unwrap().values() {
+            if client.idle_for().is_some_and(|idle| idle >= timeout) {
+                client.timed_out.notify_one();
+            }
+        }
+    }
+
+    ///暂停处理命令直到deadline
+    ///
+    /// 已经处于暂停时，截止时间与暂停的范围都取两次中较大的一个
+    pub(crate) fn pause(&self, deadline: Instant, mode: PauseMode) {
+        let mut pause = self.pause.lock().unwrap();
+        *pause = match *pause {
+            Some((until, current)) if until > Instant::now() => {
+                Some((until.max(deadline), current.max(mode)))
+            }
+            _ => Some((deadline, mode)),
+        };
+    }
+
+    ///解除暂停
+    pub(crate) fn unpause(&self) {
+        *self.pause.lock().unwrap() = None;
+        self.unpaused.notify_waiters();
+    }
+
+    ///命令当前是否被暂停
+    pub(crate) fn is_paused(&self, info: &CommandInfo) -> bool {
+        self.paused_until(info).is_some()
+    }
+
+    ///等待暂停结束后返回，命令没有被暂停时立即返回
+    pub(crate) async fn wait_unpaused(&self, info: &CommandInfo) {
+        loop {
+            //先注册通知再检查，以免错过检查之后的解除
+            let unpaused = self.unpaused.notified();
+            let deadline = match self.paused_until(info) {
+                Some(deadline) => deadline,
+                None => return,
+            };
+            tokio::select! {
+                _ = unpaused => {}
+                _ = tokio::time::sleep_until(deadline.into()) => {}
+            }
+        }
+    }
+
+    //命令被暂停时返回暂停的截止时间
+    fn paused_until(&self, info: &CommandInfo) -> Option<Instant> {
+        match *self.pause.lock().unwrap() {
+            Some((deadline, mode)) if deadline > Instant::now() && mode.blocks(info) => {
+                Some(deadline)
+            }
+            _ => None,
+        }
+    }
+}
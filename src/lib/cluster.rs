@@ -0,0 +1,452 @@
+use crate::lib;
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::random;
+use crate::lib::slot::{key_hash_slot, SLOT_COUNT};
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::sync::RwLock;
+
+///集群模式下的节点与哈希槽的分配
+///
+/// 节点与槽的分配在启动时从cluster-config-file读取，格式与redis的nodes.conf相同，
+/// 节点之间不通信，适用于静态部署的多个节点。文件不存在时当前节点单独负责全部的槽
+#[derive(Debug)]
+pub(crate) struct Cluster {
+    //所有节点，包括当前节点
+    nodes: Vec<Node>,
+    //当前节点在nodes中的下标
+    myself: usize,
+    slots: RwLock<Slots>,
+}
+
+///集群中的一个节点
+#[derive(Debug)]
+pub(crate) struct Node {
+    id: String,
+    host: String,
+    port: u16,
+}
+
+//各个槽的归属与迁移状态，节点以在nodes中的下标表示
+#[derive(Debug)]
+struct Slots {
+    //负责每个槽的节点，None代表没有节点负责
+    owners: Vec<Option<usize>>,
+    //正在从当前节点迁出的槽与迁往的节点
+    migrating: HashMap<u16, usize>,
+    //正在迁入当前节点的槽与迁出的节点
+    importing: HashMap<u16, usize>,
+}
+
+///CLUSTER SETSLOT对槽的修改
+#[derive(Debug)]
+pub enum SetSlot {
+    ///开始迁往另一个节点
+    Migrating(String),
+    ///开始从另一个节点迁入
+    Importing(String),
+    ///清除迁移状态
+    Stable,
+    ///把槽分配给节点，同时清除迁移状态
+    Node(String),
+}
+
+impl Node {
+    ///节点的id
+    pub(crate) fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+///开启了集群模式时读取节点与槽的分配，文件格式错误时返回错误
+pub(crate) fn load(db: &Db) -> lib::Result<()> {
+    let config = db.config();
+    if !config.cluster_enabled() {
+        return Ok(());
+    }
+    let path = config.cluster_config_path();
+    let cluster = match fs::read_to_string(&path) {
+        Ok(text) => Cluster::parse(&text).map_err(|line| {
+            format!(
+                "Bad cluster config file {} at line {}",
+                path.display(),
+                line
+            )
+        })?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Cluster::single(config.port()),
+        Err(e) => return Err(e.into()),
+    };
+    drop(config);
+    let myself = cluster.myself();
+    tracing::info!(
+        id = myself.id(),
+        nodes = cluster.nodes.len(),
+        "集群模式已开启"
+    );
+    db.set_cluster(cluster);
+    Ok(())
+}
+
+impl Cluster {
+    //没有配置文件时，当前节点单独负责全部的槽
+    fn single(port: u16) -> Cluster {
+        let id = format!(
+            "{:016x}{:016x}{:08x}",
+            random::next_u64(),
+            random::next_u64(),
+            random::next_u64() as u32
+        );
+        let myself = Node {
+            id,
+            host: "127.0.0.1".to_string(),
+            port,
+        };
+        Cluster {
+            nodes: vec![myself],
+            myself: 0,
+            slots: RwLock::new(Slots {
+                owners: vec![Some(0); SLOT_COUNT as usize],
+                migrating: HashMap::new(),
+                importing: HashMap::new(),
+            }),
+        }
+    }
+
+    //解析nodes.conf，出错时返回出错的行号
+    //
+    // 每行为：id ip:port[@cport] flags master ping-sent pong-recv epoch link-state slot...，
+    // flags中带有myself的是当前节点；槽为单个编号、start-end的范围，
+    // 或者[slot->-id]与[slot-<-id]表示的迁出与迁入
+    fn parse(text: &str) -> Result<Cluster, usize> {
+        let mut nodes = vec![];
+        let mut myself = None;
+        //每个节点负责的槽与迁移状态，节点都读完之后再按id找到下标
+        let mut assigned = vec![];
+        let mut migrations = vec![];
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("vars ") || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 8 {
+                return Err(i + 1);
+            }
+            let addr = fields[1].split(['@', ',']).next().unwrap_or_default();
+            let (host, port) = addr.rsplit_once(':').ok_or(i + 1)?;
+            let port = port.parse().map_err(|_| i + 1)?;
+            let index = nodes.len();
+            if fields[2].split(',').any(|flag| flag == "myself") {
+                myself = Some(index);
+            }
+            for slot in &fields[8..] {
+                match slot.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+                    Some(migration) => {
+                        let (slot, importing, id) = match migration.split_once("->-") {
+                            Some((slot, id)) => (slot, false, id),
+                            None => {
+                                let (slot, id) = migration.split_once("-<-").ok_or(i + 1)?;
+                                (slot, true, id)
+                            }
+                        };
+                        let slot = parse_slot(slot).ok_or(i + 1)?;
+                        migrations.push((i + 1, slot, importing, id.to_string()));
+                    }
+                    None => {
+                        let (start, end) = slot.split_once('-').unwrap_or((slot, slot));
+                        let start = parse_slot(start).ok_or(i + 1)?;
+                        let end = parse_slot(end).filter(|&end| end >= start).ok_or(i + 1)?;
+                        assigned.push((index, start, end));
+                    }
+                }
+            }
+            nodes.push(Node {
+                id: fields[0].to_string(),
+                host: host.to_string(),
+                port,
+            });
+        }
+        let myself = myself.ok_or(text.lines().count())?;
+        let mut slots = Slots {
+            owners: vec![None; SLOT_COUNT as usize],
+            migrating: HashMap::new(),
+            importing: HashMap::new(),
+        };
+        for (index, start, end) in assigned {
+            for slot in start..=end {
+                slots.owners[slot as usize] = Some(index);
+            }
+        }
+        for (line, slot, importing, id) in migrations {
+            let index = nodes.iter().position(|node| node.id == id).ok_or(line)?;
+            if importing {
+                slots.importing.insert(slot, index);
+            } else {
+                slots.migrating.insert(slot, index);
+            }
+        }
+        Ok(Cluster {
+            nodes,
+            myself,
+            slots: RwLock::new(slots),
+        })
+    }
+
+    ///当前节点
+    pub(crate) fn myself(&self) -> &Node {
+        &self.nodes[self.myself]
+    }
+
+    ///检查命令的键是否由当前节点负责，不负责时返回MOVED、ASK等重定向的错误
+    ///
+    /// asking为true时代表客户端在这个命令之前发送了ASKING，正在迁入的槽中的键也在当前节点执行
+    pub(crate) fn route(&self, db: &Db, keys: &[&Bytes], asking: bool) -> Result<(), String> {
+        let slot = match keys.first() {
+            Some(key) => key_hash_slot(key),
+            None => return Ok(()),
+        };
+        if keys.iter().any(|key| key_hash_slot(key) != slot) {
+            return Err("CROSSSLOT Keys in request don't hash to the same slot".to_string());
+        }
+        let slots = self.slots.read().unwrap();
+        if asking && slots.importing.contains_key(&slot) {
+            return Ok(());
+        }
+        match slots.owners[slot as usize] {
+            None => Err("CLUSTERDOWN Hash slot not served".to_string()),
+            Some(owner) if owner != self.myself => Err(self.redirect("MOVED", slot, owner)),
+            Some(_) => {
+                let target = match slots.migrating.get(&slot) {
+                    Some(&target) => target,
+                    None => return Ok(()),
+                };
+                //迁移中的槽只处理还在当前节点的键，不在的键已经迁走或者应当在目标节点创建
+                let present = keys
+                    .iter()
+                    .filter(|key| db.exists(&String::from_utf8_lossy(key)))
+                    .count();
+                if present == keys.len() {
+                    Ok(())
+                } else if present == 0 {
+                    Err(self.redirect("ASK", slot, target))
+                } else {
+                    Err("TRYAGAIN Multiple keys request during rehashing of slot".to_string())
+                }
+            }
+        }
+    }
+
+    //指向另一个节点的重定向错误
+    fn redirect(&self, kind: &str, slot: u16, node: usize) -> String {
+        let node = &self.nodes[node];
+        format!("{} {} {}:{}", kind, slot, node.host, node.port)
+    }
+
+    ///CLUSTER INFO的内容
+    pub(crate) fn info(&self) -> String {
+        let slots = self.slots.read().unwrap();
+        let assigned = slots.owners.iter().filter(|owner| owner.is_some()).count();
+        let state = if assigned == SLOT_COUNT as usize {
+            "ok"
+        } else {
+            "fail"
+        };
+        let size = (0..self.nodes.len())
+            .filter(|&node| slots.owners.contains(&Some(node)))
+            .count();
+        format!(
+            "cluster_enabled:1\r\n\
+             cluster_state:{}\r\n\
+             cluster_slots_assigned:{}\r\n\
+             cluster_slots_ok:{}\r\n\
+             cluster_slots_pfail:0\r\n\
+             cluster_slots_fail:0\r\n\
+             cluster_known_nodes:{}\r\n\
+             cluster_size:{}\r\n\
+             cluster_current_epoch:0\r\n\
+             cluster_my_epoch:0\r\n",
+            state,
+            assigned,
+            assigned,
+            self.nodes.len(),
+            size
+        )
+    }
+
+    ///CLUSTER SLOTS的回复：每段连续的槽与负责的节点
+    pub(crate) fn slots(&self) -> Frame {
+        let ranges = self.ranges().into_iter().map(|(node, start, end)| {
+            Frame::Array(vec![
+                Frame::Integer(start as i64),
+                Frame::Integer(end as i64),
+                self.node_entry(node),
+            ])
+        });
+        Frame::Array(ranges.collect())
+    }
+
+    ///CLUSTER SHARDS的回复：每个负责槽的节点是一个没有从节点的分片
+    pub(crate) fn shards(&self) -> Frame {
+        let ranges = self.ranges();
+        let shards = (0..self.nodes.len())
+            .filter_map(|node| {
+                let slots: Vec<Frame> = ranges
+                    .iter()
+                    .filter(|range| range.0 == node)
+                    .flat_map(|&(_, start, end)| [start, end])
+                    .map(|slot| Frame::Integer(slot as i64))
+                    .collect();
+                if slots.is_empty() {
+                    return None;
+                }
+                let node = &self.nodes[node];
+                let description = Frame::Map(vec![
+                    (bulk("id"), bulk(&node.id)),
+                    (bulk("port"), Frame::Integer(node.port as i64)),
+                    (bulk("ip"), bulk(&node.host)),
+                    (bulk("endpoint"), bulk(&node.host)),
+                    (bulk("role"), bulk("master")),
+                    (bulk("replication-offset"), Frame::Integer(0)),
+                    (bulk("health"), bulk("online")),
+                ]);
+                Some(Frame::Map(vec![
+                    (bulk("slots"), Frame::Array(slots)),
+                    (bulk("nodes"), Frame::Array(vec![description])),
+                ]))
+            })
+            .collect();
+        Frame::Array(shards)
+    }
+
+    //连续的由同一个节点负责的槽，按槽的编号排列
+    fn ranges(&self) -> Vec<(usize, u16, u16)> {
+        let slots = self.slots.read().unwrap();
+        let mut ranges: Vec<(usize, u16, u16)> = vec![];
+        for (slot, owner) in slots.owners.iter().enumerate() {
+            let (owner, slot) = match owner {
+                Some(owner) => (*owner, slot as u16),
+                None => continue,
+            };
+            match ranges.last_mut() {
+                Some(last) if last.0 == owner && last.2 + 1 == slot => last.2 = slot,
+                _ => ranges.push((owner, slot, slot)),
+            }
+        }
+        ranges
+    }
+
+    //CLUSTER SLOTS中的节点：地址、端口与id
+    fn node_entry(&self, node: usize) -> Frame {
+        let node = &self.nodes[node];
+        Frame::Array(vec![
+            bulk(&node.host),
+            Frame::Integer(node.port as i64),
+            bulk(&node.id),
+        ])
+    }
+
+    ///修改槽的归属或迁移状态，只在内存中生效
+    pub(crate) fn set_slot(&self, slot: u16, change: SetSlot) -> Result<(), String> {
+        let mut slots = self.slots.write().unwrap();
+        let owner = slots.owners[slot as usize];
+        match change {
+            SetSlot::Migrating(id) => {
+                if owner != Some(self.myself) {
+                    return Err(format!("ERR I'm not the owner of hash slot {}", slot));
+                }
+                let node = self.other_node(&id)?;
+                slots.migrating.insert(slot, node);
+            }
+            SetSlot::Importing(id) => {
+                if owner == Some(self.myself) {
+                    return Err(format!("ERR I'm already the owner of hash slot {}", slot));
+                }
+                let node = self.other_node(&id)?;
+                slots.importing.insert(slot, node);
+            }
+            SetSlot::Stable => {
+                slots.migrating.remove(&slot);
+                slots.importing.remove(&slot);
+            }
+            SetSlot::Node(id) => {
+                let node = self.find(&id)?;
+                slots.owners[slot as usize] = Some(node);
+                slots.migrating.remove(&slot);
+                slots.importing.remove(&slot);
+            }
+        }
+        Ok(())
+    }
+
+    //按id查找节点
+    fn find(&self, id: &str) -> Result<usize, String> {
+        self.nodes
+            .iter()
+            .position(|node| node.id == id)
+            .ok_or_else(|| format!("ERR I don't know about node {}", id))
+    }
+
+    //按id查找当前节点之外的节点
+    fn other_node(&self, id: &str) -> Result<usize, String> {
+        match self.find(id)? {
+            node if node == self.myself => {
+                Err("ERR I can't migrate or import a slot to or from myself".to_string())
+            }
+            node => Ok(node),
+        }
+    }
+}
+
+//解析槽的编号
+fn parse_slot(slot: &str) -> Option<u16> {
+    slot.parse().ok().filter(|&slot| slot < SLOT_COUNT)
+}
+
+//字符串的Bulk帧
+fn bulk(value: &str) -> Frame {
+    Frame::Bulk(Bytes::copy_from_slice(value.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //CLUSTER INFO中的字段
+    fn fields(info: &str) -> HashMap<&str, &str> {
+        info.lines()
+            .filter_map(|line| line.split_once(':'))
+            .collect()
+    }
+
+    #[test]
+    fn info_of_a_single_node() {
+        let cluster = Cluster::single(6379);
+        let info = cluster.info();
+        assert!(info.ends_with("\r\n"));
+        let fields = fields(&info);
+        assert_eq!(fields["cluster_enabled"], "1");
+        assert_eq!(fields["cluster_state"], "ok");
+        assert_eq!(fields["cluster_slots_assigned"], "16384");
+        assert_eq!(fields["cluster_slots_ok"], "16384");
+        assert_eq!(fields["cluster_known_nodes"], "1");
+        assert_eq!(fields["cluster_size"], "1");
+    }
+
+    #[test]
+    fn info_with_unassigned_slots() {
+        let text = "a 127.0.0.1:7000@17000 myself,master - 0 0 1 connected 0-5460\n\
+                    b 127.0.0.1:7001@17001 master - 0 0 2 connected 5461-10922\n\
+                    c 127.0.0.1:7002@17002 master - 0 0 3 connected\n";
+        let cluster = Cluster::parse(text).unwrap();
+        let info = cluster.info();
+        let fields = fields(&info);
+        assert_eq!(fields["cluster_state"], "fail");
+        assert_eq!(fields["cluster_slots_assigned"], "10923");
+        assert_eq!(fields["cluster_known_nodes"], "3");
+        //没有负责槽的节点不计入cluster_size
+        assert_eq!(fields["cluster_size"], "2");
+    }
+}
@@ -1,11 +1,893 @@
+use crate::lib::cmd::acl::Acl;
+use crate::lib::cmd::append::Append;
+use crate::lib::cmd::auth::Auth;
+use crate::lib::cmd::bgrewriteaof::BgRewriteAof;
+use crate::lib::cmd::bgsave::BgSave;
+use crate::lib::cmd::bitcount::BitCount;
+use crate::lib::cmd::bitop::BitOp;
+use crate::lib::cmd::bitpos::BitPos;
+use crate::lib::cmd::blmove::BLMove;
+use crate::lib::cmd::bpop::BPop;
+use crate::lib::cmd::client::Client;
+use crate::lib::cmd::cluster::Cluster;
+use crate::lib::cmd::command_table::CommandTable;
+use crate::lib::cmd::config::Config;
+use crate::lib::cmd::copy::CopyKey;
+use crate::lib::cmd::custom::Custom;
+use crate::lib::cmd::dbsize::DbSize;
+use crate::lib::cmd::debug::DebugCommand;
+use crate::lib::cmd::del::Del;
+use crate::lib::cmd::dump::Dump;
+use crate::lib::cmd::echo::Echo;
+use crate::lib::cmd::eval::Eval;
+use crate::lib::cmd::exists::Exists;
+use crate::lib::cmd::expire::Expire;
+use crate::lib::cmd::flush::Flush;
+use crate::lib::cmd::geoadd::GeoAdd;
+use crate::lib::cmd::geodist::GeoDist;
+use crate::lib::cmd::geopos::GeoPos;
+use crate::lib::cmd::geosearch::GeoSearch;
 use crate::lib::cmd::get::Get;
+use crate::lib::cmd::getbit::GetBit;
+use crate::lib::cmd::getdel::GetDel;
+use crate::lib::cmd::getex::GetEx;
+use crate::lib::cmd::getrange::GetRange;
+use crate::lib::cmd::hdel::HDel;
+use crate::lib::cmd::hello::Hello;
+use crate::lib::cmd::hget::HGet;
+use crate::lib::cmd::hgetall::HGetAll;
+use crate::lib::cmd::hincrby::HIncrBy;
+use crate::lib::cmd::hlen::HLen;
+use crate::lib::cmd::hrandfield::HRandField;
+use crate::lib::cmd::hset::HSet;
+use crate::lib::cmd::hsetnx::HSetNx;
+use crate::lib::cmd::incr::Incr;
+use crate::lib::cmd::incrbyfloat::IncrByFloat;
+use crate::lib::cmd::info::Info;
+use crate::lib::cmd::key_type::KeyType;
+use crate::lib::cmd::keys::Keys;
+use crate::lib::cmd::lastsave::LastSave;
+use crate::lib::cmd::latency::Latency;
+use crate::lib::cmd::linsert::LInsert;
+use crate::lib::cmd::llen::LLen;
+use crate::lib::cmd::lmove::LMove;
+use crate::lib::cmd::lrange::LRange;
+use crate::lib::cmd::member_scan::{Kind as ScanKind, MemberScan};
+use crate::lib::cmd::memory::Memory;
+use crate::lib::cmd::mget::MGet;
+use crate::lib::cmd::migrate::Migrate;
+use crate::lib::cmd::monitor::Monitor;
+use crate::lib::cmd::move_key::MoveKey;
+use crate::lib::cmd::mset::MSet;
+use crate::lib::cmd::object::Object;
+use crate::lib::cmd::persist::Persist;
+use crate::lib::cmd::pfadd::PfAdd;
+use crate::lib::cmd::pfcount::PfCount;
+use crate::lib::cmd::pfmerge::PfMerge;
+use crate::lib::cmd::ping::Ping;
+use crate::lib::cmd::pop::Pop;
+use crate::lib::cmd::psync::PSync;
+use crate::lib::cmd::publish::Publish;
+use crate::lib::cmd::push::Push;
+use crate::lib::cmd::randomkey::RandomKey;
+use crate::lib::cmd::rename::Rename;
+use crate::lib::cmd::replconf::ReplConf;
+use crate::lib::cmd::replicaof::ReplicaOf;
+use crate::lib::cmd::restore::Restore;
+use crate::lib::cmd::sadd::SAdd;
+use crate::lib::cmd::save::Save;
+use crate::lib::cmd::scan::Scan;
+use crate::lib::cmd::scard::SCard;
+use crate::lib::cmd::script::Script;
+use crate::lib::cmd::select::Select;
+use crate::lib::cmd::set::Set;
+use crate::lib::cmd::setbit::SetBit;
+use crate::lib::cmd::setop::SetOp;
+use crate::lib::cmd::setrange::SetRange;
+use crate::lib::cmd::shutdown::Shutdown;
+use crate::lib::cmd::sismember::SIsMember;
+use crate::lib::cmd::slowlog::SlowLog;
+use crate::lib::cmd::smembers::SMembers;
+use crate::lib::cmd::sort::Sort;
+use crate::lib::cmd::srem::SRem;
+use crate::lib::cmd::strlen::StrLen;
+use crate::lib::cmd::subscribe::{Kind, Subscribe, Unsubscribe};
+use crate::lib::cmd::swapdb::SwapDb;
+use crate::lib::cmd::table::CommandInfo;
+use crate::lib::cmd::ttl::Ttl;
+use crate::lib::cmd::wait::Wait;
+use crate::lib::cmd::watch::Watch;
+use crate::lib::cmd::xack::XAck;
+use crate::lib::cmd::xadd::XAdd;
+use crate::lib::cmd::xclaim::XClaim;
+use crate::lib::cmd::xgroup::XGroup;
+use crate::lib::cmd::xlen::XLen;
+use crate::lib::cmd::xpending::XPending;
+use crate::lib::cmd::xrange::XRange;
+use crate::lib::cmd::xread::XRead;
+use crate::lib::cmd::xreadgroup::XReadGroup;
+use crate::lib::cmd::zadd::ZAdd;
+use crate::lib::cmd::zcard::ZCard;
+use crate::lib::cmd::zincrby::ZIncrBy;
+use crate::lib::cmd::zpop::ZPop;
+use crate::lib::cmd::zrange::ZRange;
+use crate::lib::cmd::zrank::ZRank;
+use crate::lib::cmd::zrem::ZRem;
+use crate::lib::cmd::zscore::ZScore;
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
 
+pub(crate) use crate::lib::cmd::transaction::Transaction;
+use tokio::runtime::{Handle, RuntimeFlavor};
+
+///遍历大量元素的命令每处理这么多个元素让出一次执行权，以免同一个工作线程上的其他连接长时间得不到执行
+pub(crate) const YIELD_EVERY: usize = 1024;
+
+///执行可能耗时很久且无法中途让出的同步操作
+///
+/// 在多线程运行时上先把当前工作线程上的其他任务交给别的线程，以免它们随f一起等待
+pub(crate) fn blocking<R>(f: impl FnOnce() -> R) -> R {
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(f)
+        }
+        _ => f(),
+    }
+}
+
+mod acl;
+mod append;
+mod auth;
+mod bgrewriteaof;
+mod bgsave;
+mod bitcount;
+mod bitop;
+mod bitpos;
+mod blmove;
+mod bpop;
+mod client;
+mod cluster;
+mod command_table;
+mod config;
+mod copy;
+pub(crate) mod custom;
+mod dbsize;
+mod debug;
+mod del;
+mod dump;
+mod echo;
+mod eval;
+mod exists;
+mod expire;
+mod flush;
+mod geoadd;
+mod geodist;
+mod geopos;
+mod geosearch;
 mod get;
+mod getbit;
+mod getdel;
+mod getex;
+mod getrange;
+mod hdel;
+mod hello;
+mod hget;
+mod hgetall;
+mod hincrby;
+mod hlen;
+mod hrandfield;
+mod hset;
+mod hsetnx;
+mod incr;
+mod incrbyfloat;
+mod info;
+mod key_type;
+mod keys;
+mod lastsave;
+mod latency;
+mod linsert;
+mod llen;
+mod lmove;
+mod lrange;
+mod member_scan;
+mod memory;
+mod mget;
+mod migrate;
+pub(crate) mod monitor;
+mod move_key;
+mod mset;
+mod object;
+mod persist;
+mod pfadd;
+mod pfcount;
+mod pfmerge;
+mod ping;
+mod pop;
+mod psync;
+mod publish;
+mod push;
+mod randomkey;
+mod rename;
+mod replconf;
+mod replicaof;
+mod restore;
+mod sadd;
+mod save;
+mod scan;
+mod scard;
+mod script;
+mod select;
+mod set;
+mod setbit;
+mod setop;
+mod setrange;
+mod shutdown;
+mod sismember;
+mod slowlog;
+mod smembers;
+mod sort;
+mod srem;
+mod strlen;
+mod subscribe;
+mod swapdb;
+pub(crate) mod table;
+mod transaction;
+mod ttl;
+mod wait;
+mod watch;
+mod xack;
+mod xadd;
+mod xclaim;
+mod xgroup;
+mod xlen;
+mod xpending;
+mod xrange;
+mod xread;
+mod xreadgroup;
+mod zadd;
+mod zcard;
+mod zincrby;
+mod zpop;
+mod zrange;
+mod zrank;
+mod zrem;
+mod zscore;
 
+///退出订阅模式或监视模式的方式
 #[derive(Debug)]
-pub enum Command{
-    Get(Get)
+pub(crate) enum Exit {
+    ///退订了全部频道，或者连接、服务器关闭
+    Done,
+    ///收到RESET，由连接重置自己的状态并回复
+    Reset,
+    ///收到QUIT，由连接回复后关闭
+    Quit,
+}
+
+///服务端支持的命令
+///
+/// 每个命令由from_frame解析自客户端发来的帧，再由apply在数据库上执行并得到回复
+#[derive(Debug)]
+pub enum Command {
+    Get(Get),
+    Set(Set),
+    Expire(Expire),
+    Ttl(Ttl),
+    Persist(Persist),
+    Del(Del),
+    Exists(Exists),
+    Incr(Incr),
+    IncrByFloat(IncrByFloat),
+    MGet(MGet),
+    MSet(MSet),
+    Append(Append),
+    StrLen(StrLen),
+    GetRange(GetRange),
+    SetRange(SetRange),
+    GetDel(GetDel),
+    GetEx(GetEx),
+    SetBit(SetBit),
+    GetBit(GetBit),
+    BitCount(BitCount),
+    BitPos(BitPos),
+    BitOp(BitOp),
+    PfAdd(PfAdd),
+    PfCount(PfCount),
+    PfMerge(PfMerge),
+    KeyType(KeyType),
+    HSet(HSet),
+    HSetNx(HSetNx),
+    HGet(HGet),
+    HDel(HDel),
+    HGetAll(HGetAll),
+    HIncrBy(HIncrBy),
+    HLen(HLen),
+    HRandField(HRandField),
+    Push(Push),
+    Pop(Pop),
+    LRange(LRange),
+    LLen(LLen),
+    LInsert(LInsert),
+    LMove(LMove),
+    BPop(BPop),
+    BLMove(BLMove),
+    SAdd(SAdd),
+    SRem(SRem),
+    SMembers(SMembers),
+    SIsMember(SIsMember),
+    SCard(SCard),
+    SetOp(SetOp),
+    ZAdd(ZAdd),
+    ZRem(ZRem),
+    ZScore(ZScore),
+    ZCard(ZCard),
+    ZIncrBy(ZIncrBy),
+    ZRank(ZRank),
+    ZRange(ZRange),
+    ZPop(ZPop),
+    GeoAdd(GeoAdd),
+    GeoDist(GeoDist),
+    GeoPos(GeoPos),
+    GeoSearch(GeoSearch),
+    XAdd(XAdd),
+    XLen(XLen),
+    XRange(XRange),
+    XRead(XRead),
+    XGroup(XGroup),
+    XReadGroup(XReadGroup),
+    XAck(XAck),
+    XPending(XPending),
+    XClaim(XClaim),
+    Subscribe(Subscribe),
+    Unsubscribe(Unsubscribe),
+    Publish(Publish),
+    Config(Config),
+    Cluster(Cluster),
+    Multi,
+    Exec,
+    Discard,
+    Watch(Watch),
+    Unwatch,
+    Eval(Eval),
+    Script(Script),
+    Keys(Keys),
+    Scan(Scan),
+    MemberScan(MemberScan),
+    Rename(Rename),
+    RandomKey(RandomKey),
+    DbSize(DbSize),
+    Copy(CopyKey),
+    Flush(Flush),
+    Select(Select),
+    Hello(Hello),
+    Auth(Auth),
+    Quit,
+    Reset,
+    Acl(Acl),
+    Client(Client),
+    SwapDb(SwapDb),
+    MoveKey(MoveKey),
+    Sort(Sort),
+    Dump(Dump),
+    Restore(Restore),
+    Info(Info),
+    CommandTable(CommandTable),
+    Monitor(Monitor),
+    SlowLog(SlowLog),
+    Latency(Latency),
+    Memory(Memory),
+    Object(Object),
+    Debug(DebugCommand),
+    Save(Save),
+    BgSave(BgSave),
+    LastSave(LastSave),
+    BgRewriteAof(BgRewriteAof),
+    Shutdown(Shutdown),
+    ReplConf(ReplConf),
+    PSync(PSync),
+    ReplicaOf(ReplicaOf),
+    Wait(Wait),
+    Asking,
+    Migrate(Migrate),
+    Ping(Ping),
+    Echo(Echo),
+    Custom(Custom),
+}
+
+impl Command {
+    ///命令表中名称为name（小写）的命令，不支持的命令返回None
+    pub(crate) fn info(name: &str) -> Option<&'static CommandInfo> {
+        table::lookup(name)
+    }
+
+    ///帧中的命令在命令表中的信息，不是支持的命令时为None
+    pub(crate) fn info_of(frame: &Frame) -> Option<&'static CommandInfo> {
+        let name = match frame {
+            Frame::Array(parts) => match parts.first() {
+                Some(Frame::Bulk(name)) => String::from_utf8_lossy(name).to_lowercase(),
+                Some(Frame::Simple(name)) => name.to_lowercase(),
+                _ => return None,
+            },
+            _ => return None,
+        };
+        table::lookup(&name)
+    }
+
+    ///从帧中解析出命令
+    ///
+    /// 先按命令表检查命令是否存在以及参数个数，再交给各命令解析参数，
+    /// 参数不足或有多余的参数都回复参数个数错误
+    pub(crate) fn from_frame(frame: Frame) -> Result<Command, ParseError> {
+        let argc = match &frame {
+            Frame::Array(parts) => parts.len(),
+            _ => 0,
+        };
+        let mut parse = Parse::new(frame)?;
+        let name = parse.next_string()?.to_lowercase();
+        let info = match table::lookup(&name) {
+            Some(info) => info,
+            None => {
+                //与redis一致，回显的参数不超过128个字符
+                let mut args = String::new();
+                while let Ok(Some(arg)) = parse.next_optional_bytes() {
+                    if args.len() >= 128 {
+                        break;
+                    }
+                    let arg = String::from_utf8_lossy(&arg);
+                    let arg: String = arg.chars().take(128 - args.len()).collect();
+                    args.push_str(&format!("'{}' ", arg));
+                }
+                let err = format!(
+                    "ERR unknown command '{}', with args beginning with: {}",
+                    name, args
+                );
+                return Err(err.into());
+            }
+        };
+        if !info.accepts(argc) {
+            return Err(wrong_arity(&name));
+        }
+        let cmd = match Command::parse_args(&name, &mut parse) {
+            Err(ParseError::EndOfStream) => return Err(wrong_arity(&name)),
+            result => result?,
+        };
+        if parse.finish().is_err() {
+            return Err(wrong_arity(&name));
+        }
+        Ok(cmd)
+    }
+
+    //按命令名称解析参数
+    fn parse_args(name: &str, parse: &mut Parse) -> Result<Command, ParseError> {
+        let cmd = match name {
+            "get" => Command::Get(Get::parse_frames(parse)?),
+            "set" => Command::Set(Set::parse_frames(parse)?),
+            "expire" => Command::Expire(Expire::parse_frames(parse, "expire")?),
+            "pexpire" => Command::Expire(Expire::parse_frames(parse, "pexpire")?),
+            "expireat" => Command::Expire(Expire::parse_frames(parse, "expireat")?),
+            "pexpireat" => Command::Expire(Expire::parse_frames(parse, "pexpireat")?),
+            "ttl" => Command::Ttl(Ttl::parse_frames(parse, false)?),
+            "pttl" => Command::Ttl(Ttl::parse_frames(parse, true)?),
+            "persist" => Command::Persist(Persist::parse_frames(parse)?),
+            "del" => Command::Del(Del::parse_frames(parse, false)?),
+            "unlink" => Command::Del(Del::parse_frames(parse, true)?),
+            "exists" => Command::Exists(Exists::parse_frames(parse)?),
+            "incr" | "decr" | "incrby" | "decrby" => {
+                Command::Incr(Incr::parse_frames(parse, name)?)
+            }
+            "incrbyfloat" => Command::IncrByFloat(IncrByFloat::parse_frames(parse)?),
+            "mget" => Command::MGet(MGet::parse_frames(parse)?),
+            "mset" => Command::MSet(MSet::parse_frames(parse, false)?),
+            "msetnx" => Command::MSet(MSet::parse_frames(parse, true)?),
+            "append" => Command::Append(Append::parse_frames(parse)?),
+            "strlen" => Command::StrLen(StrLen::parse_frames(parse)?),
+            "getrange" => Command::GetRange(GetRange::parse_frames(parse)?),
+            "setrange" => Command::SetRange(SetRange::parse_frames(parse)?),
+            "getdel" => Command::GetDel(GetDel::parse_frames(parse)?),
+            "getex" => Command::GetEx(GetEx::parse_frames(parse)?),
+            "setbit" => Command::SetBit(SetBit::parse_frames(parse)?),
+            "getbit" => Command::GetBit(GetBit::parse_frames(parse)?),
+            "bitcount" => Command::BitCount(BitCount::parse_frames(parse)?),
+            "bitpos" => Command::BitPos(BitPos::parse_frames(parse)?),
+            "bitop" => Command::BitOp(BitOp::parse_frames(parse)?),
+            "pfadd" => Command::PfAdd(PfAdd::parse_frames(parse)?),
+            "pfcount" => Command::PfCount(PfCount::parse_frames(parse)?),
+            "pfmerge" => Command::PfMerge(PfMerge::parse_frames(parse)?),
+            "type" => Command::KeyType(KeyType::parse_frames(parse)?),
+            "hset" => Command::HSet(HSet::parse_frames(parse, false)?),
+            "hmset" => Command::HSet(HSet::parse_frames(parse, true)?),
+            "hsetnx" => Command::HSetNx(HSetNx::parse_frames(parse)?),
+            "hget" => Command::HGet(HGet::parse_frames(parse)?),
+            "hdel" => Command::HDel(HDel::parse_frames(parse)?),
+            "hgetall" => Command::HGetAll(HGetAll::parse_frames(parse)?),
+            "hincrby" => Command::HIncrBy(HIncrBy::parse_frames(parse)?),
+            "hlen" => Command::HLen(HLen::parse_frames(parse)?),
+            "hrandfield" => Command::HRandField(HRandField::parse_frames(parse)?),
+            "lpush" => Command::Push(Push::parse_frames(parse, true, false)?),
+            "rpush" => Command::Push(Push::parse_frames(parse, false, false)?),
+            "lpushx" => Command::Push(Push::parse_frames(parse, true, true)?),
+            "rpushx" => Command::Push(Push::parse_frames(parse, false, true)?),
+            "lpop" => Command::Pop(Pop::parse_frames(parse, true)?),
+            "rpop" => Command::Pop(Pop::parse_frames(parse, false)?),
+            "lrange" => Command::LRange(LRange::parse_frames(parse)?),
+            "llen" => Command::LLen(LLen::parse_frames(parse)?),
+            "linsert" => Command::LInsert(LInsert::parse_frames(parse)?),
+            "lmove" => Command::LMove(LMove::parse_frames(parse)?),
+            "rpoplpush" => Command::LMove(LMove::parse_rpoplpush(parse)?),
+            "blpop" => Command::BPop(BPop::parse_frames(parse, true)?),
+            "brpop" => Command::BPop(BPop::parse_frames(parse, false)?),
+            "blmove" => Command::BLMove(BLMove::parse_frames(parse)?),
+            "brpoplpush" => Command::BLMove(BLMove::parse_brpoplpush(parse)?),
+            "sadd" => Command::SAdd(SAdd::parse_frames(parse)?),
+            "srem" => Command::SRem(SRem::parse_frames(parse)?),
+            "smembers" => Command::SMembers(SMembers::parse_frames(parse)?),
+            "sismember" => Command::SIsMember(SIsMember::parse_frames(parse)?),
+            "scard" => Command::SCard(SCard::parse_frames(parse)?),
+            "sinter" | "sunion" | "sdiff" | "sinterstore" | "sunionstore" | "sdiffstore" => {
+                Command::SetOp(SetOp::parse_frames(parse, name)?)
+            }
+            "zadd" => Command::ZAdd(ZAdd::parse_frames(parse)?),
+            "zrem" => Command::ZRem(ZRem::parse_frames(parse)?),
+            "zscore" => Command::ZScore(ZScore::parse_frames(parse)?),
+            "zcard" => Command::ZCard(ZCard::parse_frames(parse)?),
+            "zincrby" => Command::ZIncrBy(ZIncrBy::parse_frames(parse)?),
+            "zrank" => Command::ZRank(ZRank::parse_frames(parse, false)?),
+            "zrevrank" => Command::ZRank(ZRank::parse_frames(parse, true)?),
+            "zrange" | "zrevrange" | "zrangebyscore" | "zrevrangebyscore" => {
+                Command::ZRange(ZRange::parse_frames(parse, name)?)
+            }
+            "zpopmin" => Command::ZPop(ZPop::parse_frames(parse, false)?),
+            "zpopmax" => Command::ZPop(ZPop::parse_frames(parse, true)?),
+            "geoadd" => Command::GeoAdd(GeoAdd::parse_frames(parse)?),
+            "geodist" => Command::GeoDist(GeoDist::parse_frames(parse)?),
+            "geopos" => Command::GeoPos(GeoPos::parse_frames(parse)?),
+            "geosearch" => Command::GeoSearch(GeoSearch::parse_frames(parse)?),
+            "xadd" => Command::XAdd(XAdd::parse_frames(parse)?),
+            "xlen" => Command::XLen(XLen::parse_frames(parse)?),
+            "xrange" => Command::XRange(XRange::parse_frames(parse, false)?),
+            "xrevrange" => Command::XRange(XRange::parse_frames(parse, true)?),
+            "xread" => Command::XRead(XRead::parse_frames(parse)?),
+            "xgroup" => Command::XGroup(XGroup::parse_frames(parse)?),
+            "xreadgroup" => Command::XReadGroup(XReadGroup::parse_frames(parse)?),
+            "xack" => Command::XAck(XAck::parse_frames(parse)?),
+            "xpending" => Command::XPending(XPending::parse_frames(parse)?),
+            "xclaim" => Command::XClaim(XClaim::parse_frames(parse)?),
+            "subscribe" => Command::Subscribe(Subscribe::parse_frames(parse, Kind::Channel)?),
+            "psubscribe" => Command::Subscribe(Subscribe::parse_frames(parse, Kind::Pattern)?),
+            "ssubscribe" => Command::Subscribe(Subscribe::parse_frames(parse, Kind::Shard)?),
+            "unsubscribe" => Command::Unsubscribe(Unsubscribe::parse_frames(parse, Kind::Channel)?),
+            "punsubscribe" => {
+                Command::Unsubscribe(Unsubscribe::parse_frames(parse, Kind::Pattern)?)
+            }
+            "sunsubscribe" => Command::Unsubscribe(Unsubscribe::parse_frames(parse, Kind::Shard)?),
+            "publish" => Command::Publish(Publish::parse_frames(parse, false)?),
+            "spublish" => Command::Publish(Publish::parse_frames(parse, true)?),
+            "config" => Command::Config(Config::parse_frames(parse)?),
+            "cluster" => Command::Cluster(Cluster::parse_frames(parse)?),
+            "multi" => Command::Multi,
+            "exec" => Command::Exec,
+            "discard" => Command::Discard,
+            "watch" => Command::Watch(Watch::parse_frames(parse)?),
+            "unwatch" => Command::Unwatch,
+            "eval" => Command::Eval(Eval::parse_frames(parse, false)?),
+            "evalsha" => Command::Eval(Eval::parse_frames(parse, true)?),
+            "script" => Command::Script(Script::parse_frames(parse)?),
+            "keys" => Command::Keys(Keys::parse_frames(parse)?),
+            "scan" => Command::Scan(Scan::parse_frames(parse)?),
+            "hscan" => Command::MemberScan(MemberScan::parse_frames(parse, ScanKind::Hash)?),
+            "sscan" => Command::MemberScan(MemberScan::parse_frames(parse, ScanKind::Set)?),
+            "zscan" => Command::MemberScan(MemberScan::parse_frames(parse, ScanKind::ZSet)?),
+            "rename" => Command::Rename(Rename::parse_frames(parse, false)?),
+            "renamenx" => Command::Rename(Rename::parse_frames(parse, true)?),
+            "randomkey" => Command::RandomKey(RandomKey::parse_frames(parse)?),
+            "dbsize" => Command::DbSize(DbSize::parse_frames(parse)?),
+            "copy" => Command::Copy(CopyKey::parse_frames(parse)?),
+            "flushdb" => Command::Flush(Flush::parse_frames(parse, false)?),
+            "flushall" => Command::Flush(Flush::parse_frames(parse, true)?),
+            "select" => Command::Select(Select::parse_frames(parse)?),
+            "hello" => Command::Hello(Hello::parse_frames(parse)?),
+            "auth" => Command::Auth(Auth::parse_frames(parse)?),
+            "quit" => Command::Quit,
+            "reset" => Command::Reset,
+            "acl" => Command::Acl(Acl::parse_frames(parse)?),
+            "client" => Command::Client(Client::parse_frames(parse)?),
+            "swapdb" => Command::SwapDb(SwapDb::parse_frames(parse)?),
+            "move" => Command::MoveKey(MoveKey::parse_frames(parse)?),
+            "sort" => Command::Sort(Sort::parse_frames(parse)?),
+            "dump" => Command::Dump(Dump::parse_frames(parse)?),
+            "restore" => Command::Restore(Restore::parse_frames(parse)?),
+            "info" => Command::Info(Info::parse_frames(parse)?),
+            "command" => Command::CommandTable(CommandTable::parse_frames(parse)?),
+            "monitor" => Command::Monitor(Monitor::parse_frames(parse)?),
+            "slowlog" => Command::SlowLog(SlowLog::parse_frames(parse)?),
+            "latency" => Command::Latency(Latency::parse_frames(parse)?),
+            "memory" => Command::Memory(Memory::parse_frames(parse)?),
+            "object" => Command::Object(Object::parse_frames(parse)?),
+            "debug" => Command::Debug(DebugCommand::parse_frames(parse)?),
+            "save" => Command::Save(Save::parse_frames(parse)?),
+            "bgsave" => Command::BgSave(BgSave::parse_frames(parse)?),
+            "lastsave" => Command::LastSave(LastSave::parse_frames(parse)?),
+            "bgrewriteaof" => Command::BgRewriteAof(BgRewriteAof::parse_frames(parse)?),
+            "shutdown" => Command::Shutdown(Shutdown::parse_frames(parse)?),
+            "replconf" => Command::ReplConf(ReplConf::parse_frames(parse)?),
+            "psync" => Command::PSync(PSync::parse_frames(parse)?),
+            "replicaof" | "slaveof" => Command::ReplicaOf(ReplicaOf::parse_frames(parse)?),
+            "wait" => Command::Wait(Wait::parse_frames(parse)?),
+            "asking" => Command::Asking,
+            "migrate" => Command::Migrate(Migrate::parse_frames(parse)?),
+            "ping" => Command::Ping(Ping::parse_frames(parse)?),
+            "echo" => Command::Echo(Echo::parse_frames(parse)?),
+            _ => match table::handler(name) {
+                Some(handler) => Command::Custom(Custom::parse_frames(name, handler, parse)?),
+                None => return Err(format!("ERR unknown command '{}'", name).into()),
+            },
+        };
+        Ok(cmd)
+    }
+
+    ///命令执行时是否可能阻塞等待其他连接写入或者其他服务器的回复
+    pub(crate) fn may_block(&self) -> bool {
+        matches!(
+            self,
+            Command::BPop(_)
+                | Command::BLMove(_)
+                | Command::XRead(_)
+                | Command::XReadGroup(_)
+                | Command::Wait(_)
+                | Command::Migrate(_)
+        )
+    }
+
+    ///设置了密码时，连接是否需要先通过认证才能执行这个命令
+    pub(crate) fn requires_auth(&self) -> bool {
+        !matches!(
+            self,
+            Command::Auth(_) | Command::Hello(_) | Command::Quit | Command::Reset
+        )
+    }
+
+    ///在事务中是否立即执行而不是排队
+    pub(crate) fn controls_transaction(&self) -> bool {
+        matches!(
+            self,
+            Command::Multi
+                | Command::Exec
+                | Command::Discard
+                | Command::Watch(_)
+                | Command::Unwatch
+                | Command::Quit
+                | Command::Reset
+        )
+    }
+
+    ///记录执行命令的ACL用户，脚本中调用的命令按这个用户的权限检查
+    pub(crate) fn with_user(self, user: Option<&str>) -> Command {
+        match self {
+            Command::Eval(cmd) => Command::Eval(cmd.with_user(user)),
+            cmd => cmd,
+        }
+    }
+
+    ///执行命令，返回回复给客户端的帧
+    ///
+    /// 阻塞命令会在这里等待其他连接写入或者其他服务器的回复，其余命令都是同步完成的
+    pub(crate) async fn apply(self, db: &Db) -> Frame {
+        match self {
+            Command::BPop(cmd) => cmd.apply(db).await,
+            Command::BLMove(cmd) => cmd.apply(db).await,
+            Command::XRead(cmd) => cmd.apply(db).await,
+            Command::XReadGroup(cmd) => cmd.apply(db).await,
+            Command::Wait(cmd) => cmd.apply(db).await,
+            Command::Migrate(cmd) => cmd.apply(db).await,
+            Command::Debug(cmd) => cmd.apply(db).await,
+            Command::Keys(cmd) => cmd.apply(db).await,
+            cmd => cmd.apply_now(db),
+        }
+    }
+
+    ///不等待地执行命令
+    ///
+    /// 阻塞命令只尝试一次，没有可用的数据时按超时处理，EXEC执行排队的命令时使用
+    pub(crate) fn apply_now(self, db: &Db) -> Frame {
+        match self {
+            Command::Get(cmd) => cmd.apply(db),
+            Command::Set(cmd) => cmd.apply(db),
+            Command::Expire(cmd) => cmd.apply(db),
+            Command::Ttl(cmd) => cmd.apply(db),
+            Command::Persist(cmd) => cmd.apply(db),
+            Command::Del(cmd) => cmd.apply(db),
+            Command::Exists(cmd) => cmd.apply(db),
+            Command::Incr(cmd) => cmd.apply(db),
+            Command::IncrByFloat(cmd) => cmd.apply(db),
+            Command::MGet(cmd) => cmd.apply(db),
+            Command::MSet(cmd) => cmd.apply(db),
+            Command::Append(cmd) => cmd.apply(db),
+            Command::StrLen(cmd) => cmd.apply(db),
+            Command::GetRange(cmd) => cmd.apply(db),
+            Command::SetRange(cmd) => cmd.apply(db),
+            Command::GetDel(cmd) => cmd.apply(db),
+            Command::GetEx(cmd) => cmd.apply(db),
+            Command::SetBit(cmd) => cmd.apply(db),
+            Command::GetBit(cmd) => cmd.apply(db),
+            Command::BitCount(cmd) => cmd.apply(db),
+            Command::BitPos(cmd) => cmd.apply(db),
+            Command::BitOp(cmd) => cmd.apply(db),
+            Command::PfAdd(cmd) => cmd.apply(db),
+            Command::PfCount(cmd) => cmd.apply(db),
+            Command::PfMerge(cmd) => cmd.apply(db),
+            Command::KeyType(cmd) => cmd.apply(db),
+            Command::HSet(cmd) => cmd.apply(db),
+            Command::HSetNx(cmd) => cmd.apply(db),
+            Command::HGet(cmd) => cmd.apply(db),
+            Command::HDel(cmd) => cmd.apply(db),
+            Command::HGetAll(cmd) => cmd.apply(db),
+            Command::HIncrBy(cmd) => cmd.apply(db),
+            Command::HLen(cmd) => cmd.apply(db),
+            Command::HRandField(cmd) => cmd.apply(db),
+            Command::Push(cmd) => cmd.apply(db),
+            Command::Pop(cmd) => cmd.apply(db),
+            Command::LRange(cmd) => cmd.apply(db),
+            Command::LLen(cmd) => cmd.apply(db),
+            Command::LInsert(cmd) => cmd.apply(db),
+            Command::LMove(cmd) => cmd.apply(db),
+            Command::BPop(cmd) => cmd.apply_now(db),
+            Command::BLMove(cmd) => cmd.apply_now(db),
+            Command::SAdd(cmd) => cmd.apply(db),
+            Command::SRem(cmd) => cmd.apply(db),
+            Command::SMembers(cmd) => cmd.apply(db),
+            Command::SIsMember(cmd) => cmd.apply(db),
+            Command::SCard(cmd) => cmd.apply(db),
+            Command::SetOp(cmd) => cmd.apply(db),
+            Command::ZAdd(cmd) => cmd.apply(db),
+            Command::ZRem(cmd) => cmd.apply(db),
+            Command::ZScore(cmd) => cmd.apply(db),
+            Command::ZCard(cmd) => cmd.apply(db),
+            Command::ZIncrBy(cmd) => cmd.apply(db),
+            Command::ZRank(cmd) => cmd.apply(db),
+            Command::ZRange(cmd) => cmd.apply(db),
+            Command::ZPop(cmd) => cmd.apply(db),
+            Command::GeoAdd(cmd) => cmd.apply(db),
+            Command::GeoDist(cmd) => cmd.apply(db),
+            Command::GeoPos(cmd) => cmd.apply(db),
+            Command::GeoSearch(cmd) => cmd.apply(db),
+            Command::XAdd(cmd) => cmd.apply(db),
+            Command::XLen(cmd) => cmd.apply(db),
+            Command::XRange(cmd) => cmd.apply(db),
+            Command::XRead(cmd) => cmd.apply_now(db),
+            Command::XGroup(cmd) => cmd.apply(db),
+            Command::XReadGroup(cmd) => cmd.apply_now(db),
+            Command::XAck(cmd) => cmd.apply(db),
+            Command::XPending(cmd) => cmd.apply(db),
+            Command::XClaim(cmd) => cmd.apply(db),
+            //订阅命令需要接管连接，由连接的处理循环直接执行
+            Command::Subscribe(_) | Command::Unsubscribe(_) => Frame::Error(
+                "ERR SUBSCRIBE and UNSUBSCRIBE are not allowed in this context".to_string(),
+            ),
+            Command::Publish(cmd) => cmd.apply(db),
+            Command::Config(cmd) => cmd.apply(db),
+            Command::Cluster(cmd) => cmd.apply(db),
+            Command::Eval(cmd) => cmd.apply(db),
+            Command::Script(cmd) => cmd.apply(db),
+            Command::Keys(cmd) => cmd.apply_now(db),
+            Command::Scan(cmd) => cmd.apply(db),
+            Command::MemberScan(cmd) => cmd.apply(db),
+            Command::Rename(cmd) => cmd.apply(db),
+            Command::RandomKey(cmd) => cmd.apply(db),
+            Command::DbSize(cmd) => cmd.apply(db),
+            Command::Copy(cmd) => cmd.apply(db),
+            Command::Flush(cmd) => cmd.apply(db),
+            Command::SwapDb(cmd) => cmd.apply(db),
+            Command::MoveKey(cmd) => cmd.apply(db),
+            Command::Sort(cmd) => cmd.apply(db),
+            Command::Dump(cmd) => cmd.apply(db),
+            Command::Restore(cmd) => cmd.apply(db),
+            Command::Info(cmd) => cmd.apply(db),
+            Command::CommandTable(cmd) => cmd.apply(),
+            Command::SlowLog(cmd) => cmd.apply(db),
+            Command::Latency(cmd) => cmd.apply(db),
+            Command::Memory(cmd) => cmd.apply(db),
+            Command::Object(cmd) => cmd.apply(db),
+            Command::Debug(cmd) => cmd.apply_now(db),
+            Command::Save(cmd) => cmd.apply(db),
+            Command::BgSave(cmd) => cmd.apply(db),
+            Command::LastSave(cmd) => cmd.apply(db),
+            Command::BgRewriteAof(cmd) => cmd.apply(db),
+            Command::Shutdown(cmd) => cmd.apply(db),
+            Command::ReplicaOf(cmd) => cmd.apply(db),
+            Command::Wait(cmd) => cmd.apply_now(db),
+            Command::Migrate(cmd) => cmd.apply_now(db),
+            Command::Ping(cmd) => cmd.apply(),
+            Command::Echo(cmd) => cmd.apply(),
+            Command::Custom(cmd) => cmd.apply(db),
+            //事务命令、SELECT、HELLO、AUTH、QUIT、RESET、ACL、CLIENT、MONITOR、REPLCONF、PSYNC与ASKING用到连接上的状态，由连接的处理循环直接执行
+            Command::Select(_) => {
+                Frame::Error("ERR SELECT is not allowed in this context".to_string())
+            }
+            Command::Hello(_) => {
+                Frame::Error("ERR HELLO is not allowed in this context".to_string())
+            }
+            Command::Auth(_) => Frame::Error("ERR AUTH is not allowed in this context".to_string()),
+            Command::Quit => Frame::Error("ERR QUIT is not allowed in this context".to_string()),
+            Command::Reset => Frame::Error("ERR RESET is not allowed in this context".to_string()),
+            Command::Acl(_) => Frame::Error("ERR ACL is not allowed in this context".to_string()),
+            Command::Client(_) => {
+                Frame::Error("ERR CLIENT is not allowed in this context".to_string())
+            }
+            Command::Monitor(_) => {
+                Frame::Error("ERR MONITOR is not allowed in this context".to_string())
+            }
+            Command::ReplConf(_) => {
+                Frame::Error("ERR REPLCONF is not allowed in this context".to_string())
+            }
+            Command::PSync(_) => {
+                Frame::Error("ERR PSYNC is not allowed in this context".to_string())
+            }
+            Command::Asking => {
+                Frame::Error("ERR ASKING is not allowed in this context".to_string())
+            }
+            Command::Multi
+            | Command::Exec
+            | Command::Discard
+            | Command::Watch(_)
+            | Command::Unwatch => {
+                Frame::Error("ERR transaction commands are not allowed in this context".to_string())
+            }
+        }
+    }
+}
+
+//参数个数不符合命令要求时的错误
+fn wrong_arity(name: &str) -> ParseError {
+    format!("wrong number of arguments for '{}' command", name).into()
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::config::Config;
+    use bytes::Bytes;
 
+    fn frame(args: &[&str]) -> Frame {
+        Frame::Array(
+            args.iter()
+                .map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes())))
+                .collect(),
+        )
+    }
 
+    #[test]
+    fn every_command_in_the_table_is_parsed_natively() {
+        for info in table::commands() {
+            let argc = info.arity.unsigned_abs() as usize;
+            let mut args = vec![info.name];
+            args.resize(argc.max(1), "0");
+            //参数不合法的错误可以接受，但不能落到未知命令
+            if let Err(e) = Command::from_frame(frame(&args)) {
+                let e = Frame::from(e);
+                assert!(
+                    !matches!(&e, Frame::Error(e) if e.starts_with("ERR unknown command")),
+                    "{} is in the command table but has no parser",
+                    info.name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_commands_are_rejected_without_a_fallback() {
+        let db = Db::new(Config::default());
+        let reply = match Command::from_frame(frame(&["NOSUCHCOMMAND", "a"])) {
+            Ok(cmd) => cmd.apply_now(&db),
+            Err(e) => e.into(),
+        };
+        assert!(matches!(
+            reply,
+            Frame::Error(e) if e.starts_with("ERR unknown command")
+        ));
+        let reply = Command::from_frame(frame(&["SET", "key", "value"]))
+            .unwrap()
+            .apply_now(&db);
+        assert!(matches!(reply, Frame::Simple(s) if s == "OK"));
+    }
+}
@@ -0,0 +1,92 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use bytes::Bytes;
+
+///管理访问控制列表
+///
+/// ACL SETUSER username [rule ...] | ACL GETUSER username | ACL LIST | ACL WHOAMI
+///
+/// 规则与redis一致：on/off启用或停用用户，>password与<password添加或删除密码，nopass允许任意密码，
+/// +command/-command与+@category/-@category允许或禁止命令，~pattern与&pattern允许访问的键与频道，
+/// reset恢复为新建用户的状态
+#[derive(Debug)]
+pub struct Acl {
+    op: Op,
+}
+
+///ACL的子命令
+#[derive(Debug)]
+enum Op {
+    ///创建或修改用户
+    SetUser(String, Vec<String>),
+    ///查看用户
+    GetUser(String),
+    ///以规则的形式列出所有用户
+    List,
+    ///当前连接的用户
+    WhoAmI,
+}
+
+impl Acl {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Acl, ParseError> {
+        let sub = parse.next_string()?.to_uppercase();
+        let op = match sub.as_str() {
+            "SETUSER" => {
+                let name = parse.next_string()?;
+                let mut rules = vec![];
+                while let Some(rule) = parse.next_optional_string()? {
+                    rules.push(rule);
+                }
+                Op::SetUser(name, rules)
+            }
+            "GETUSER" => Op::GetUser(parse.next_string()?),
+            "LIST" => Op::List,
+            "WHOAMI" => Op::WhoAmI,
+            _ => {
+                let err = format!("unknown subcommand '{}'. Try ACL HELP.", sub);
+                return Err(err.into());
+            }
+        };
+        Ok(Acl { op })
+    }
+
+    ///user是当前连接的用户
+    pub(crate) fn apply(self, db: &Db, user: &str) -> Frame {
+        let bulk = |text: &str| Frame::Bulk(Bytes::copy_from_slice(text.as_bytes()));
+        match self.op {
+            Op::SetUser(name, rules) => match db.update_acl(|acl| acl.set_user(&name, &rules)) {
+                Ok(()) => Frame::Simple("OK".to_string()),
+                Err(e) => Frame::Error(e),
+            },
+            Op::GetUser(name) => {
+                let acl = db.acl();
+                let user = match acl.user(&name) {
+                    Some(user) => user,
+                    None => return Frame::Null,
+                };
+                let field = |name: &'static str| Frame::Bulk(Bytes::from_static(name.as_bytes()));
+                Frame::Map(vec![
+                    (
+                        field("flags"),
+                        Frame::Array(user.flags().into_iter().map(bulk).collect()),
+                    ),
+                    (
+                        field("passwords"),
+                        Frame::Array(user.passwords().map(bulk).collect()),
+                    ),
+                    (field("commands"), bulk(&user.command_rules())),
+                    (field("keys"), bulk(&user.key_rules())),
+                    (field("channels"), bulk(&user.channel_rules())),
+                ])
+            }
+            Op::List => Frame::Array(
+                db.acl()
+                    .users()
+                    .map(|user| bulk(&user.describe()))
+                    .collect(),
+            ),
+            Op::WhoAmI => bulk(user),
+        }
+    }
+}
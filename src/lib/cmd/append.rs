@@ -0,0 +1,50 @@
+use crate::lib::db::{Db, Update};
+use crate::lib::frame::Frame;
+use crate::lib::notify::{Class, Event};
+use crate::lib::parse::{Parse, ParseError};
+use bytes::{Bytes, BytesMut};
+
+///在值的末尾追加内容
+///
+/// APPEND key value
+///
+/// 键不存在时等同于SET，原有的过期时间保持不变
+#[derive(Debug)]
+pub struct Append {
+    key: String,
+    value: Bytes,
+}
+
+impl Append {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Append, ParseError> {
+        let key = parse.next_string()?;
+        let value = parse.next_bytes()?;
+        Ok(Append { key, value })
+    }
+
+    ///回复追加后值的长度
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let value = self.value;
+        db.update_notify(self.key, Event::new(Class::String, "append"), |cur| {
+            let (data, expires_at) = match cur {
+                None => (value, None),
+                Some(entry) => {
+                    let old = match entry.value.as_string() {
+                        Ok(old) => old,
+                        Err(e) => return (Update::Keep, e.into()),
+                    };
+                    let mut data = BytesMut::with_capacity(old.len() + value.len());
+                    data.extend_from_slice(&old);
+                    data.extend_from_slice(&value);
+                    (data.freeze(), entry.expires_at)
+                }
+            };
+            let len = data.len() as i64;
+            let update = Update::Set {
+                value: data.into(),
+                expires_at,
+            };
+            (update, Frame::Integer(len))
+        })
+    }
+}
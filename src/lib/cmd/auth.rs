@@ -0,0 +1,52 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use bytes::Bytes;
+
+///用户名或密码错误时回复的错误
+pub(super) const WRONGPASS: &str = "WRONGPASS invalid username-password pair or user is disabled.";
+
+///验证连接的身份
+///
+/// AUTH [username] password
+///
+/// 用户由ACL管理，default用户的密码由requirepass配置。省略用户名时验证的是default用户
+#[derive(Debug)]
+pub struct Auth {
+    //省略用户名时为None
+    username: Option<String>,
+    password: Bytes,
+}
+
+impl Auth {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Auth, ParseError> {
+        let first = parse.next_bytes()?;
+        let auth = match parse.next_optional_bytes()? {
+            Some(password) => Auth {
+                username: Some(String::from_utf8_lossy(&first).into_owned()),
+                password,
+            },
+            None => Auth {
+                username: None,
+                password: first,
+            },
+        };
+        Ok(auth)
+    }
+
+    ///验证成功时将连接的用户切换为验证的用户并回复OK
+    pub(crate) fn apply(self, db: &Db, user: &mut Option<String>) -> Frame {
+        if self.username.is_none() && db.acl().default_user().is_some() {
+            return Frame::Error(
+                "ERR AUTH <password> called without any password configured for the default user. Are you sure your configuration is correct?"
+                    .to_string(),
+            );
+        }
+        let username = self.username.unwrap_or_else(|| "default".to_string());
+        if !db.acl().authenticate(&username, &self.password) {
+            return Frame::Error(WRONGPASS.to_string());
+        }
+        *user = Some(username);
+        Frame::Simple("OK".to_string())
+    }
+}
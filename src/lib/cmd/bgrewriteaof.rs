@@ -0,0 +1,27 @@
+use crate::lib::aof;
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+
+///在后台重写AOF
+///
+/// BGREWRITEAOF
+///
+/// 以当前的数据生成最短的命令序列替换原有的AOF，重写期间的写入在完成时补到新文件末尾，
+/// 结果可以通过INFO persistence查看
+#[derive(Debug)]
+pub struct BgRewriteAof;
+
+impl BgRewriteAof {
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<BgRewriteAof, ParseError> {
+        Ok(BgRewriteAof)
+    }
+
+    ///已经在重写时回复错误
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match aof::bgrewrite(db) {
+            Ok(()) => Frame::Simple("Background append only file rewriting started".to_string()),
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+}
@@ -0,0 +1,26 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::rdb;
+
+///在后台将数据保存到快照文件
+///
+/// BGSAVE
+///
+/// 复制数据之后立即回复，序列化与写入文件在后台进行，结果可以通过LASTSAVE与INFO persistence查看
+#[derive(Debug)]
+pub struct BgSave;
+
+impl BgSave {
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<BgSave, ParseError> {
+        Ok(BgSave)
+    }
+
+    ///已经在保存时回复错误
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match rdb::bgsave(db) {
+            Ok(()) => Frame::Simple("Background saving started".to_string()),
+            Err(e) => Frame::Error(e.to_string()),
+        }
+    }
+}
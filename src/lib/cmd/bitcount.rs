@@ -0,0 +1,101 @@
+use crate::lib::cmd::getrange::normalize_range;
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+
+///统计值中被置为1的位数
+///
+/// BITCOUNT key [start end [BYTE | BIT]]
+///
+/// 区间包含两端，负数下标从末尾开始计算，默认以字节为单位
+#[derive(Debug)]
+pub struct BitCount {
+    key: String,
+    range: Option<BitRange>,
+}
+
+///BITCOUNT与BITPOS的统计区间
+#[derive(Debug, Clone, Copy)]
+pub(super) struct BitRange {
+    pub(super) start: i64,
+    pub(super) end: i64,
+    ///区间是否以位而不是字节为单位
+    pub(super) bit: bool,
+}
+
+impl BitRange {
+    ///换算为以位为单位的闭区间，区间为空时返回None
+    pub(super) fn to_bits(self, len: usize) -> Option<(usize, usize)> {
+        if self.bit {
+            normalize_range(self.start, self.end, len * 8)
+        } else {
+            let (start, end) = normalize_range(self.start, self.end, len)?;
+            Some((start * 8, end * 8 + 7))
+        }
+    }
+}
+
+impl BitCount {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<BitCount, ParseError> {
+        let key = parse.next_string()?;
+        let start = match parse.next_int() {
+            Ok(start) => start,
+            Err(ParseError::EndOfStream) => return Ok(BitCount { key, range: None }),
+            Err(e) => return Err(e),
+        };
+        //只给出起点是语法错误
+        let end = match parse.next_int() {
+            Ok(end) => end,
+            Err(ParseError::EndOfStream) => return Err("syntax error".into()),
+            Err(e) => return Err(e),
+        };
+        let bit = parse_unit(parse)?;
+        let range = Some(BitRange { start, end, bit });
+        Ok(BitCount { key, range })
+    }
+
+    ///键不存在时回复0
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let data = match db.get(&self.key) {
+            Ok(value) => value.unwrap_or_default(),
+            Err(e) => return e.into(),
+        };
+        let count = match self.range {
+            None => data.iter().map(|byte| byte.count_ones() as i64).sum(),
+            Some(range) => match range.to_bits(data.len()) {
+                Some((start, end)) => count_bits(&data, start, end),
+                None => 0,
+            },
+        };
+        Frame::Integer(count)
+    }
+}
+
+///读取区间末尾可选的BYTE或BIT，返回区间是否以位为单位
+pub(super) fn parse_unit(parse: &mut Parse) -> Result<bool, ParseError> {
+    match parse.next_string() {
+        Ok(unit) => match unit.to_uppercase().as_str() {
+            "BYTE" => Ok(false),
+            "BIT" => Ok(true),
+            _ => Err("syntax error".into()),
+        },
+        Err(ParseError::EndOfStream) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+//统计data中第start位到第end位（包含两端）中1的个数
+fn count_bits(data: &[u8], start: usize, end: usize) -> i64 {
+    let (first, last) = (start / 8, end / 8);
+    //首尾两个字节只统计区间内的位
+    let head = 0xffu8 >> (start % 8);
+    let tail = 0xffu8 << (7 - end % 8);
+    if first == last {
+        return (data[first] & head & tail).count_ones() as i64;
+    }
+    let middle: i64 = data[first + 1..last]
+        .iter()
+        .map(|byte| byte.count_ones() as i64)
+        .sum();
+    (data[first] & head).count_ones() as i64 + middle + (data[last] & tail).count_ones() as i64
+}
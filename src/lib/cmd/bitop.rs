@@ -0,0 +1,95 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::notify::Class;
+use crate::lib::parse::{Parse, ParseError};
+use bytes::BytesMut;
+
+///对多个值按位运算，结果写入目标键
+///
+/// BITOP AND | OR | XOR | NOT destkey key [key ...]
+///
+/// 长度不同的值以0补齐到最长的长度，不存在的键视为空值
+#[derive(Debug)]
+pub struct BitOp {
+    op: Op,
+    dest: String,
+    keys: Vec<String>,
+}
+
+///按位运算的种类
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    And,
+    Or,
+    Xor,
+    Not,
+}
+
+impl BitOp {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<BitOp, ParseError> {
+        let op = match parse.next_string()?.to_uppercase().as_str() {
+            "AND" => Op::And,
+            "OR" => Op::Or,
+            "XOR" => Op::Xor,
+            "NOT" => Op::Not,
+            _ => return Err("syntax error".into()),
+        };
+        let dest = parse.next_string()?;
+        let mut keys = vec![parse.next_string()?];
+        loop {
+            match parse.next_string() {
+                Ok(key) => keys.push(key),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        if op == Op::Not && keys.len() != 1 {
+            return Err("BITOP NOT must be called with a single source key.".into());
+        }
+        Ok(BitOp { op, dest, keys })
+    }
+
+    ///回复结果的长度，结果为空时删除目标键
+    ///
+    /// 读取源键与写入目标键在同一次键空间独占中完成
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let BitOp { op, dest, keys } = self;
+        db.atomically(|| {
+            let mut values = Vec::with_capacity(keys.len());
+            for key in &keys {
+                match db.get(key) {
+                    Ok(value) => values.push(value.unwrap_or_default()),
+                    Err(e) => return e.into(),
+                }
+            }
+            let len = values.iter().map(|value| value.len()).max().unwrap_or(0);
+            if len == 0 {
+                if db.remove(&dest).is_some() {
+                    db.notify(Class::Generic, "del", &dest);
+                }
+                return Frame::Integer(0);
+            }
+            let mut result = BytesMut::with_capacity(len);
+            result.extend_from_slice(&values[0]);
+            result.resize(len, 0);
+            match op {
+                Op::Not => result.iter_mut().for_each(|byte| *byte = !*byte),
+                _ => {
+                    for value in &values[1..] {
+                        for (i, byte) in result.iter_mut().enumerate() {
+                            let other = value.get(i).copied().unwrap_or(0);
+                            match op {
+                                Op::And => *byte &= other,
+                                Op::Or => *byte |= other,
+                                _ => *byte ^= other,
+                            }
+                        }
+                    }
+                }
+            }
+            db.set(dest.clone(), result.freeze().into());
+            db.notify(Class::String, "set", &dest);
+            Frame::Integer(len as i64)
+        })
+    }
+}
@@ -0,0 +1,108 @@
+use crate::lib::cmd::bitcount::{parse_unit, BitRange};
+use crate::lib::cmd::getbit::bit_at;
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+
+///查找值中第一个为0或为1的位
+///
+/// BITPOS key bit [start [end [BYTE | BIT]]]
+#[derive(Debug)]
+pub struct BitPos {
+    key: String,
+    bit: bool,
+    range: Option<BitRange>,
+    //是否显式给出了终点
+    has_end: bool,
+}
+
+impl BitPos {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<BitPos, ParseError> {
+        let key = parse.next_string()?;
+        let bit = match parse.next_int()? {
+            0 => false,
+            1 => true,
+            _ => return Err("The bit argument must be 1 or 0.".into()),
+        };
+        let mut bitpos = BitPos {
+            key,
+            bit,
+            range: None,
+            has_end: false,
+        };
+        let start = match parse.next_int() {
+            Ok(start) => start,
+            Err(ParseError::EndOfStream) => return Ok(bitpos),
+            Err(e) => return Err(e),
+        };
+        let end = match parse.next_int() {
+            Ok(end) => {
+                bitpos.has_end = true;
+                end
+            }
+            Err(ParseError::EndOfStream) => -1,
+            Err(e) => return Err(e),
+        };
+        let unit = if bitpos.has_end {
+            parse_unit(parse)?
+        } else {
+            false
+        };
+        bitpos.range = Some(BitRange {
+            start,
+            end,
+            bit: unit,
+        });
+        Ok(bitpos)
+    }
+
+    ///回复找到的位偏移量，找不到时回复-1
+    ///
+    /// 查找0且没有给出终点时，值末尾之后的位视为0，因此全为1的值回复其总位数。
+    /// 键不存在时视为空值，查找1回复-1，查找0回复0
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let BitPos {
+            key,
+            bit,
+            range,
+            has_end,
+        } = self;
+        let data = match db.get(&key) {
+            Ok(Some(data)) => data,
+            Ok(None) => return Frame::Integer(if bit { -1 } else { 0 }),
+            Err(e) => return e.into(),
+        };
+        let range = range.unwrap_or(BitRange {
+            start: 0,
+            end: -1,
+            bit: false,
+        });
+        let pos = match range.to_bits(data.len()) {
+            None => -1,
+            Some((start, end)) => match find_bit(&data, bit, start, end) {
+                Some(pos) => pos as i64,
+                None if !bit && !has_end => end as i64 + 1,
+                None => -1,
+            },
+        };
+        Frame::Integer(pos)
+    }
+}
+
+//在第start位到第end位（包含两端）中查找第一个值为bit的位
+fn find_bit(data: &[u8], bit: bool, start: usize, end: usize) -> Option<usize> {
+    //整个字节都不可能命中时一次跳过8位
+    let skip = if bit { 0x00 } else { 0xff };
+    let mut pos = start;
+    while pos <= end {
+        if pos.is_multiple_of(8) && pos + 7 <= end && data[pos / 8] == skip {
+            pos += 8;
+            continue;
+        }
+        if bit_at(data, pos) == bit {
+            return Some(pos);
+        }
+        pos += 1;
+    }
+    None
+}
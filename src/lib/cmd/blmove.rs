@@ -0,0 +1,74 @@
+use crate::lib::cmd::bpop::parse_timeout;
+use crate::lib::cmd::lmove::{parse_side, LMove};
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::propagate;
+use std::time::Duration;
+use tokio::time::Instant;
+
+///阻塞地从一个列表弹出元素并插入另一个列表
+///
+/// BLMOVE source destination LEFT | RIGHT LEFT | RIGHT timeout | BRPOPLPUSH source destination timeout
+///
+/// 源列表为空时等待其他连接写入，直到超时
+#[derive(Debug)]
+pub struct BLMove {
+    inner: LMove,
+    //None代表一直等待
+    timeout: Option<Duration>,
+}
+
+impl BLMove {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<BLMove, ParseError> {
+        let source = parse.next_string()?;
+        let destination = parse.next_string()?;
+        let from_left = parse_side(parse)?;
+        let to_left = parse_side(parse)?;
+        let timeout = parse_timeout(&parse.next_string()?)?;
+        let inner = LMove::new(source, destination, from_left, to_left);
+        Ok(BLMove { inner, timeout })
+    }
+
+    ///BRPOPLPUSH等同于BLMOVE source destination RIGHT LEFT timeout
+    pub(crate) fn parse_brpoplpush(parse: &mut Parse) -> Result<BLMove, ParseError> {
+        let source = parse.next_string()?;
+        let destination = parse.next_string()?;
+        let timeout = parse_timeout(&parse.next_string()?)?;
+        let inner = LMove::new(source, destination, false, true);
+        Ok(BLMove { inner, timeout })
+    }
+
+    ///回复被移动的元素，超时时回复Null
+    pub(crate) async fn apply(self, db: &Db) -> Frame {
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let waiter = db.waiter(std::slice::from_ref(self.inner.source()));
+        loop {
+            //阻塞期间不持有顺序锁，每次尝试单独在锁内进行并以LMOVE记录
+            let moved = propagate::record(
+                db,
+                || self.inner.move_one(db),
+                |moved| moved.as_ref().map(|_| self.inner.args()),
+            );
+            if let Some(frame) = moved {
+                return frame;
+            }
+            match deadline {
+                None => waiter.wait().await,
+                Some(deadline) => {
+                    if tokio::time::timeout_at(deadline, waiter.wait())
+                        .await
+                        .is_err()
+                    {
+                        return Frame::Null;
+                    }
+                }
+            }
+        }
+    }
+
+    ///不等待地尝试一次，源列表为空时按超时处理回复Null
+    pub(crate) fn apply_now(self, db: &Db) -> Frame {
+        self.inner.move_one(db).unwrap_or(Frame::Null)
+    }
+}
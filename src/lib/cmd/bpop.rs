@@ -0,0 +1,121 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::notify::{Class, Event};
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::propagate;
+use bytes::Bytes;
+use std::time::Duration;
+use tokio::time::Instant;
+
+///阻塞地从多个列表中弹出元素
+///
+/// BLPOP key [key ...] timeout | BRPOP key [key ...] timeout
+///
+/// 按参数顺序从第一个非空的列表中弹出，所有列表都为空时等待其他连接写入，直到超时
+#[derive(Debug)]
+pub struct BPop {
+    keys: Vec<String>,
+    //None代表一直等待
+    timeout: Option<Duration>,
+    //是否从头部弹出
+    left: bool,
+}
+
+impl BPop {
+    pub(crate) fn parse_frames(parse: &mut Parse, left: bool) -> Result<BPop, ParseError> {
+        //超时时间是最后一个参数，需要先读出全部参数
+        let mut args = vec![parse.next_string()?, parse.next_string()?];
+        loop {
+            match parse.next_string() {
+                Ok(arg) => args.push(arg),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        let timeout = parse_timeout(&args.pop().unwrap())?;
+        Ok(BPop {
+            keys: args,
+            timeout,
+            left,
+        })
+    }
+
+    ///回复弹出元素所在的键与元素，超时时回复Null
+    pub(crate) async fn apply(self, db: &Db) -> Frame {
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let waiter = db.waiter(&self.keys);
+        //阻塞期间不持有顺序锁，每次尝试单独在锁内进行并记录
+        let name = Bytes::from_static(if self.left { b"BLPOP" } else { b"BRPOP" });
+        loop {
+            let popped = propagate::record(
+                db,
+                || self.try_pop(db),
+                |popped| propagate::rewrite(db, vec![name.clone()], popped.as_ref()?),
+            );
+            if let Some(frame) = popped {
+                return frame;
+            }
+            match deadline {
+                None => waiter.wait().await,
+                Some(deadline) => {
+                    if tokio::time::timeout_at(deadline, waiter.wait())
+                        .await
+                        .is_err()
+                    {
+                        return Frame::Null;
+                    }
+                }
+            }
+        }
+    }
+
+    ///不等待地尝试一次，所有列表都为空时按超时处理回复Null
+    pub(crate) fn apply_now(self, db: &Db) -> Frame {
+        self.try_pop(db).unwrap_or(Frame::Null)
+    }
+
+    //依次尝试每个键，全部为空时返回None
+    fn try_pop(&self, db: &Db) -> Option<Frame> {
+        let event = Event::new(Class::List, if self.left { "lpop" } else { "rpop" });
+        for key in &self.keys {
+            let popped = db.mutate_notify(key.clone(), event, |slot| {
+                let list = match slot.as_mut().map(|value| value.as_list_mut()) {
+                    None => return (false, Ok(None)),
+                    Some(Err(e)) => return (false, Err(e)),
+                    Some(Ok(list)) => list,
+                };
+                let value = if self.left {
+                    list.pop_front()
+                } else {
+                    list.pop_back()
+                };
+                (true, Ok(value))
+            });
+            match popped {
+                Ok(Some(value)) => {
+                    return Some(Frame::Array(vec![key.clone().into(), value.into()]));
+                }
+                Ok(None) => {}
+                Err(e) => return Some(e.into()),
+            }
+        }
+        None
+    }
+}
+
+///解析以秒为单位的超时时间，允许小数，0代表一直等待
+pub(super) fn parse_timeout(arg: &str) -> Result<Option<Duration>, ParseError> {
+    let secs: f64 = match arg.parse() {
+        Ok(secs) if f64::is_finite(secs) => secs,
+        _ => return Err("timeout is not a float or out of range".into()),
+    };
+    if secs < 0.0 {
+        return Err("timeout is negative".into());
+    }
+    if secs == 0.0 {
+        return Ok(None);
+    }
+    Duration::try_from_secs_f64(secs)
+        .map(Some)
+        .map_err(|_| "timeout is out of range".into())
+}
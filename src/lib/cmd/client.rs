@@ -0,0 +1,269 @@
+use crate::lib::client::{self, PauseMode};
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::tracking::Options;
+use bytes::Bytes;
+use std::time::{Duration, Instant};
+
+///查看与管理客户端连接
+///
+/// CLIENT ID | CLIENT LIST [ID id [id ...]] | CLIENT SETNAME name | CLIENT GETNAME |
+/// CLIENT KILL addr | CLIENT KILL [ID id] [ADDR addr] [SKIPME yes|no] |
+/// CLIENT PAUSE timeout [WRITE|ALL] | CLIENT UNPAUSE |
+/// CLIENT TRACKING ON|OFF [REDIRECT id] [BCAST] [PREFIX prefix [PREFIX prefix ...]] |
+/// CLIENT GETREDIR
+///
+/// 只带地址的KILL是旧的形式，成功时回复OK；带过滤条件的形式回复关闭的连接数，
+/// 默认不关闭执行命令的连接自己。PAUSE在timeout毫秒内暂停处理所有客户端的命令，
+/// 默认暂停所有命令，WRITE只暂停写命令。
+/// TRACKING开启客户端缓存，读取过的键（BCAST时为匹配前缀的键）被修改后，
+/// 连接或REDIRECT指定的连接会收到失效消息
+#[derive(Debug)]
+pub struct Client {
+    op: Op,
+}
+
+///CLIENT的子命令
+#[derive(Debug)]
+enum Op {
+    ///当前连接的id
+    Id,
+    ///列出连接，可以只列出指定id的连接
+    List(Option<Vec<u64>>),
+    ///设置当前连接的名称，为空时清除
+    SetName(String),
+    ///当前连接的名称
+    GetName,
+    ///关闭匹配的连接
+    Kill(Filter),
+    ///暂停处理命令
+    Pause(Duration, PauseMode),
+    ///解除暂停
+    Unpause,
+    ///开启或关闭追踪，None代表关闭
+    Tracking(Option<Options>),
+    ///失效消息转发给的连接
+    GetRedir,
+}
+
+///CLIENT KILL的过滤条件
+#[derive(Debug, Default)]
+struct Filter {
+    id: Option<u64>,
+    addr: Option<String>,
+    //是否跳过执行命令的连接自己
+    skip_me: bool,
+    //是否为只带地址的旧形式
+    legacy: bool,
+}
+
+impl Client {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Client, ParseError> {
+        let sub = parse.next_string()?.to_uppercase();
+        let op = match sub.as_str() {
+            "ID" => Op::Id,
+            "LIST" => match parse.next_optional_string()? {
+                None => Op::List(None),
+                Some(option) if option.eq_ignore_ascii_case("id") => {
+                    let mut ids = vec![parse_id(parse.next_string()?)?];
+                    while let Some(id) = parse.next_optional_string()? {
+                        ids.push(parse_id(id)?);
+                    }
+                    Op::List(Some(ids))
+                }
+                Some(_) => return Err("syntax error".into()),
+            },
+            "SETNAME" => {
+                let name = parse.next_string()?;
+                check_name(&name)?;
+                Op::SetName(name)
+            }
+            "GETNAME" => Op::GetName,
+            "KILL" => Op::Kill(parse_filter(parse)?),
+            "PAUSE" => {
+                let timeout = parse
+                    .next_int()
+                    .map_err(|_| "timeout is not an integer or out of range")?;
+                let timeout = u64::try_from(timeout).map_err(|_| "timeout is negative")?;
+                let mode = match parse.next_optional_string()? {
+                    None => PauseMode::All,
+                    Some(mode) if mode.eq_ignore_ascii_case("all") => PauseMode::All,
+                    Some(mode) if mode.eq_ignore_ascii_case("write") => PauseMode::Write,
+                    Some(_) => return Err("syntax error".into()),
+                };
+                Op::Pause(Duration::from_millis(timeout), mode)
+            }
+            "UNPAUSE" => Op::Unpause,
+            "TRACKING" => Op::Tracking(parse_tracking(parse)?),
+            "GETREDIR" => Op::GetRedir,
+            _ => {
+                let err = format!("unknown subcommand '{}'. Try CLIENT HELP.", sub);
+                return Err(err.into());
+            }
+        };
+        Ok(Client { op })
+    }
+
+    ///me是执行命令的连接
+    pub(crate) fn apply(self, db: &Db, me: &client::Client) -> Frame {
+        match self.op {
+            Op::Id => Frame::Integer(me.id() as i64),
+            Op::List(ids) => {
+                let list: String = db
+                    .clients()
+                    .all()
+                    .iter()
+                    .filter(|client| ids.as_ref().is_none_or(|ids| ids.contains(&client.id())))
+                    .map(|client| client.describe())
+                    .collect();
+                Frame::Bulk(Bytes::from(list))
+            }
+            Op::SetName(name) => {
+                me.set_name(Some(name).filter(|name| !name.is_empty()));
+                Frame::Simple("OK".to_string())
+            }
+            Op::GetName => match me.name() {
+                Some(name) => Frame::Bulk(Bytes::from(name)),
+                None => Frame::Null,
+            },
+            Op::Kill(filter) => {
+                let mut killed = 0;
+                for client in db.clients().all() {
+                    if !filter.matches(&client, me) {
+                        continue;
+                    }
+                    //关闭自己时先发出回复
+                    if client.id() == me.id() {
+                        client.close_after_reply();
+                    } else {
+                        client.kill();
+                    }
+                    killed += 1;
+                }
+                match (filter.legacy, killed) {
+                    (true, 0) => Frame::Error("ERR No such client".to_string()),
+                    (true, _) => Frame::Simple("OK".to_string()),
+                    (false, killed) => Frame::Integer(killed),
+                }
+            }
+            Op::Pause(timeout, mode) => {
+                db.clients().pause(Instant::now() + timeout, mode);
+                Frame::Simple("OK".to_string())
+            }
+            Op::Unpause => {
+                db.clients().unpause();
+                Frame::Simple("OK".to_string())
+            }
+            Op::Tracking(Some(options)) => {
+                let target = options.redirect;
+                if target.is_some_and(|id| db.clients().get(id).is_none()) {
+                    let err = "ERR The client ID you want redirect to does not exist";
+                    return Frame::Error(err.to_string());
+                }
+                if !db.tracking().enable(me.id(), options) {
+                    let err = "ERR You can't switch BCAST mode on/off before disabling tracking \
+                               for this client, and then re-enabling it with a different mode.";
+                    return Frame::Error(err.to_string());
+                }
+                Frame::Simple("OK".to_string())
+            }
+            Op::Tracking(None) => {
+                db.tracking().disable(me.id());
+                Frame::Simple("OK".to_string())
+            }
+            Op::GetRedir => match db.tracking().redirect(me.id()) {
+                None => Frame::Integer(-1),
+                Some(None) => Frame::Integer(0),
+                Some(Some(id)) => Frame::Integer(id as i64),
+            },
+        }
+    }
+}
+
+impl Filter {
+    //连接是否满足过滤条件
+    fn matches(&self, client: &client::Client, me: &client::Client) -> bool {
+        if self.skip_me && client.id() == me.id() {
+            return false;
+        }
+        self.id.is_none_or(|id| client.id() == id)
+            && self.addr.as_ref().is_none_or(|addr| client.addr() == addr)
+    }
+}
+
+//解析CLIENT KILL的参数
+fn parse_filter(parse: &mut Parse) -> Result<Filter, ParseError> {
+    let first = parse.next_string()?;
+    let value = match parse.next_optional_string()? {
+        Some(value) => value,
+        None => {
+            return Ok(Filter {
+                addr: Some(first),
+                legacy: true,
+                ..Filter::default()
+            })
+        }
+    };
+    let mut filter = Filter {
+        skip_me: true,
+        ..Filter::default()
+    };
+    let mut option = Some(first);
+    let mut value = Some(value);
+    while let (Some(name), Some(arg)) = (option.take(), value.take()) {
+        match name.to_uppercase().as_str() {
+            "ID" => filter.id = Some(parse_id(arg)?),
+            "ADDR" => filter.addr = Some(arg),
+            "SKIPME" => match arg.to_lowercase().as_str() {
+                "yes" => filter.skip_me = true,
+                "no" => filter.skip_me = false,
+                _ => return Err("syntax error".into()),
+            },
+            _ => return Err("syntax error".into()),
+        }
+        option = parse.next_optional_string()?;
+        if option.is_some() {
+            value = Some(parse.next_string().map_err(|_| "syntax error")?);
+        }
+    }
+    Ok(filter)
+}
+
+//解析CLIENT TRACKING的参数，OFF时返回None
+fn parse_tracking(parse: &mut Parse) -> Result<Option<Options>, ParseError> {
+    let on = match parse.next_string()?.to_lowercase().as_str() {
+        "on" => true,
+        "off" => false,
+        _ => return Err("syntax error".into()),
+    };
+    let mut options = Options::default();
+    while let Some(option) = parse.next_optional_string()? {
+        match option.to_uppercase().as_str() {
+            "REDIRECT" => options.redirect = Some(parse_id(parse.next_string()?)?),
+            "BCAST" => options.bcast = true,
+            "PREFIX" => options.prefixes.push(parse.next_string()?),
+            _ => return Err("syntax error".into()),
+        }
+    }
+    if !options.bcast && !options.prefixes.is_empty() {
+        return Err("PREFIX option requires BCAST mode to be enabled".into());
+    }
+    Ok(on.then_some(options))
+}
+
+//解析连接的id
+fn parse_id(id: String) -> Result<u64, ParseError> {
+    id.parse()
+        .ok()
+        .filter(|id| *id > 0)
+        .ok_or_else(|| format!("Invalid client ID '{}'", id).into())
+}
+
+///检查客户端名称，名称中不能有空白与特殊字符
+pub(super) fn check_name(name: &str) -> Result<(), ParseError> {
+    if name.bytes().any(|b| b <= b' ' || b > b'~') {
+        return Err("Client names cannot contain spaces, newlines or special characters.".into());
+    }
+    Ok(())
+}
@@ -0,0 +1,163 @@
+use crate::lib::cluster::SetSlot;
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::slot::{key_hash_slot, SLOT_COUNT};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::OnceLock;
+
+//未开启集群时的集群状态
+const CLUSTER_INFO: &str = "cluster_enabled:0\r\n\
+cluster_state:ok\r\n\
+cluster_slots_assigned:0\r\n\
+cluster_slots_ok:0\r\n\
+cluster_slots_pfail:0\r\n\
+cluster_slots_fail:0\r\n\
+cluster_known_nodes:1\r\n\
+cluster_size:0\r\n\
+cluster_current_epoch:0\r\n\
+cluster_my_epoch:0\r\n";
+
+///集群相关的命令
+///
+/// CLUSTER KEYSLOT key | INFO | MYID | SLOTS | SHARDS |
+/// SETSLOT slot MIGRATING node-id | IMPORTING node-id | STABLE | NODE node-id
+///
+/// 开启了集群模式时按启动时载入的节点配置回复，SETSLOT只在内存中修改槽的归属与迁移状态。
+/// 没有开启集群模式时INFO等子命令只是为了让支持集群的客户端能够正常回退到单节点模式
+#[derive(Debug)]
+pub enum Cluster {
+    ///计算键所属的哈希槽
+    KeySlot(String),
+    ///集群状态
+    Info,
+    ///当前节点的id
+    MyId,
+    ///槽的分配情况
+    Slots,
+    ///分片信息
+    Shards,
+    ///修改槽的归属或迁移状态
+    SetSlot(u16, SetSlot),
+}
+
+impl Cluster {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Cluster, ParseError> {
+        let sub = parse.next_string()?.to_lowercase();
+        let cmd = match sub.as_str() {
+            "keyslot" => Cluster::KeySlot(parse.next_string()?),
+            "info" => Cluster::Info,
+            "myid" => Cluster::MyId,
+            "slots" => Cluster::Slots,
+            "shards" => Cluster::Shards,
+            "setslot" => {
+                let slot = parse.next_int()?;
+                if !(0..SLOT_COUNT as i64).contains(&slot) {
+                    return Err("Invalid or out of range slot".into());
+                }
+                let change = match parse.next_string()?.to_lowercase().as_str() {
+                    "migrating" => SetSlot::Migrating(parse.next_string()?),
+                    "importing" => SetSlot::Importing(parse.next_string()?),
+                    "stable" => SetSlot::Stable,
+                    "node" => SetSlot::Node(parse.next_string()?),
+                    _ => return Err(
+                        "Invalid CLUSTER SETSLOT action or number of arguments. Try CLUSTER HELP"
+                            .into(),
+                    ),
+                };
+                Cluster::SetSlot(slot as u16, change)
+            }
+            _ => {
+                let err = format!("unknown subcommand '{}'. Try CLUSTER HELP.", sub);
+                return Err(err.into());
+            }
+        };
+        Ok(cmd)
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match (self, db.cluster()) {
+            (Cluster::KeySlot(key), _) => Frame::Integer(key_hash_slot(key.as_bytes()) as i64),
+            (cmd, None) => cmd.apply_standalone(),
+            (Cluster::Info, Some(cluster)) => Frame::Bulk(cluster.info().into()),
+            (Cluster::MyId, Some(cluster)) => Frame::Bulk(cluster.myself().id().to_string().into()),
+            (Cluster::Slots, Some(cluster)) => cluster.slots(),
+            (Cluster::Shards, Some(cluster)) => cluster.shards(),
+            (Cluster::SetSlot(slot, change), Some(cluster)) => {
+                match cluster.set_slot(slot, change) {
+                    Ok(()) => Frame::Simple("OK".to_string()),
+                    Err(e) => Frame::Error(e),
+                }
+            }
+        }
+    }
+
+    //没有开启集群模式时的回复
+    fn apply_standalone(self) -> Frame {
+        match self {
+            Cluster::Info => Frame::Bulk(CLUSTER_INFO.into()),
+            Cluster::MyId => Frame::Bulk(node_id().into()),
+            Cluster::Slots | Cluster::Shards => Frame::array(),
+            _ => Frame::Error("ERR This instance has cluster support disabled".to_string()),
+        }
+    }
+}
+
+///获取当前节点的id
+///
+/// 与redis一致为40位十六进制字符串，进程启动后首次访问时随机生成，之后保持不变
+fn node_id() -> &'static str {
+    static NODE_ID: OnceLock<String> = OnceLock::new();
+    NODE_ID.get_or_init(|| {
+        let state = RandomState::new();
+        (0..3)
+            .map(|i| {
+                let mut hasher = state.build_hasher();
+                hasher.write_u32(i);
+                format!("{:016x}", hasher.finish())
+            })
+            .collect::<String>()[..40]
+            .to_string()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lib::cmd::Command;
+    use crate::lib::config::Config;
+    use crate::lib::db::Db;
+    use crate::lib::frame::Frame;
+    use bytes::Bytes;
+
+    fn call(db: &Db, args: &[&str]) -> Frame {
+        let frame = Frame::Array(
+            args.iter()
+                .map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes())))
+                .collect(),
+        );
+        Command::from_frame(frame).unwrap().apply_now(db)
+    }
+
+    #[test]
+    fn info_without_cluster_mode() {
+        let db = Db::new(Config::default());
+        let info = match call(&db, &["CLUSTER", "INFO"]) {
+            Frame::Bulk(info) => String::from_utf8(info.to_vec()).unwrap(),
+            frame => panic!("unexpected reply {:?}", frame),
+        };
+        let fields: Vec<(&str, &str)> = info
+            .split_terminator("\r\n")
+            .map(|line| line.split_once(':').unwrap())
+            .collect();
+        assert_eq!(fields[0], ("cluster_enabled", "0"));
+        assert!(fields.contains(&("cluster_state", "ok")));
+        assert!(fields.contains(&("cluster_slots_assigned", "0")));
+        assert!(fields.contains(&("cluster_known_nodes", "1")));
+        assert!(fields.contains(&("cluster_size", "0")));
+        assert_eq!(
+            call(&db, &["CLUSTER", "KEYSLOT", "foo"]),
+            Frame::Integer(12182)
+        );
+    }
+}
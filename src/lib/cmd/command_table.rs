@@ -0,0 +1,969 @@
+use crate::lib::acl;
+use crate::lib::cmd::table::{self, CommandInfo};
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use bytes::Bytes;
+
+///查看命令表
+///
+/// COMMAND | COMMAND COUNT | COMMAND LIST | COMMAND INFO [command ...] |
+/// COMMAND DOCS [command ...] | COMMAND GETKEYS command [arg ...]
+///
+/// 每个命令的信息依次为名称、参数个数、属性、第一个键、最后一个键、键的间隔、ACL类别、
+/// 提示、键的规格与子命令，与redis 7的格式一致。键的规格由命令表中键的位置生成，没有提示与子命令，
+/// 不存在的命令回复空值。DOCS回复每个存在的命令的简介、加入redis的版本、分组与参数，不存在的命令被省略，
+/// 注册的自定义命令没有文档，分组为module
+#[derive(Debug)]
+pub struct CommandTable {
+    op: Op,
+}
+
+///COMMAND的子命令
+#[derive(Debug)]
+enum Op {
+    ///所有命令的信息
+    All,
+    ///命令的数量
+    Count,
+    ///所有命令的名称
+    List,
+    ///指定命令的信息，为空时为所有命令
+    Info(Vec<String>),
+    ///指定命令的文档，为空时为所有命令
+    Docs(Vec<String>),
+    ///从完整的命令中找出键
+    GetKeys(Vec<Bytes>),
+}
+
+impl CommandTable {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<CommandTable, ParseError> {
+        let sub = match parse.next_optional_string()? {
+            Some(sub) => sub.to_uppercase(),
+            None => return Ok(CommandTable { op: Op::All }),
+        };
+        let op = match sub.as_str() {
+            "COUNT" => Op::Count,
+            "LIST" => Op::List,
+            "INFO" => Op::Info(names(parse)?),
+            "DOCS" => Op::Docs(names(parse)?),
+            "GETKEYS" => {
+                let mut args = vec![parse.next_bytes()?];
+                while let Some(arg) = parse.next_optional_bytes()? {
+                    args.push(arg);
+                }
+                Op::GetKeys(args)
+            }
+            _ => {
+                let err = format!("unknown subcommand '{}'. Try COMMAND HELP.", sub);
+                return Err(err.into());
+            }
+        };
+        Ok(CommandTable { op })
+    }
+
+    pub(crate) fn apply(self) -> Frame {
+        match self.op {
+            Op::All => Frame::Array(table::commands().into_iter().map(describe).collect()),
+            Op::Count => Frame::Integer(table::commands().len() as i64),
+            Op::List => Frame::Array(
+                table::commands()
+                    .into_iter()
+                    .map(|info| Frame::Bulk(Bytes::from_static(info.name.as_bytes())))
+                    .collect(),
+            ),
+            Op::Info(names) if names.is_empty() => {
+                Frame::Array(table::commands().into_iter().map(describe).collect())
+            }
+            Op::Info(names) => Frame::Array(
+                names
+                    .iter()
+                    .map(|name| table::lookup(name).map_or(Frame::Null, describe))
+                    .collect(),
+            ),
+            Op::Docs(names) => {
+                let infos: Vec<&CommandInfo> = if names.is_empty() {
+                    table::commands()
+                } else {
+                    names
+                        .iter()
+                        .filter_map(|name| table::lookup(name))
+                        .collect()
+                };
+                Frame::Map(
+                    infos
+                        .into_iter()
+                        .map(|info| {
+                            let name = Frame::Bulk(Bytes::from_static(info.name.as_bytes()));
+                            (name, document(info))
+                        })
+                        .collect(),
+                )
+            }
+            Op::GetKeys(args) => get_keys(&args),
+        }
+    }
+}
+
+//解析剩余参数中的命令名
+fn names(parse: &mut Parse) -> Result<Vec<String>, ParseError> {
+    let mut names = vec![];
+    while let Some(name) = parse.next_optional_string()? {
+        names.push(name.to_lowercase());
+    }
+    Ok(names)
+}
+
+//命令的信息
+fn describe(info: &CommandInfo) -> Frame {
+    let mut flags: Vec<Frame> = info
+        .flags
+        .iter()
+        .map(|flag| Frame::Simple(flag.to_string()))
+        .collect();
+    if info.movable_keys() {
+        flags.push(Frame::Simple("movablekeys".to_string()));
+    }
+    let categories = acl::categories(info)
+        .into_iter()
+        .map(|category| Frame::Simple(format!("@{}", category)))
+        .collect();
+    Frame::Array(vec![
+        Frame::Bulk(Bytes::from_static(info.name.as_bytes())),
+        Frame::Integer(info.arity),
+        Frame::Set(flags),
+        Frame::Integer(info.first_key),
+        Frame::Integer(info.last_key),
+        Frame::Integer(info.key_step),
+        Frame::Set(categories),
+        Frame::Set(vec![]),
+        Frame::Array(key_specs(info)),
+        Frame::Array(vec![]),
+    ])
+}
+
+//命令的键的规格
+//
+// 位置固定的键生成一个从first_key开始、按key_step取到last_key的规格，last_key为负数时从末尾倒数。
+// 键的位置取决于其他参数的命令生成一个类型为unknown、标记为incomplete的规格，客户端需要通过COMMAND GETKEYS获取
+fn key_specs(info: &CommandInfo) -> Vec<Frame> {
+    let text = |text: &str| Frame::Simple(text.to_string());
+    let pair = |name: &str, value: Frame| (text(name), value);
+    let access = if info.has_flag("write") { "RW" } else { "RO" };
+    let (begin, find, flags) = if info.movable_keys() {
+        let unknown = || {
+            Frame::Map(vec![
+                pair("type", text("unknown")),
+                pair("spec", Frame::Map(vec![])),
+            ])
+        };
+        (unknown(), unknown(), vec![text(access), text("incomplete")])
+    } else if info.first_key > 0 {
+        let begin = Frame::Map(vec![
+            pair("type", text("index")),
+            pair(
+                "spec",
+                Frame::Map(vec![pair("index", Frame::Integer(info.first_key))]),
+            ),
+        ]);
+        //范围的终点相对于起点，负数同样从末尾倒数
+        let last_key = if info.last_key < 0 {
+            info.last_key
+        } else {
+            info.last_key - info.first_key
+        };
+        let find = Frame::Map(vec![
+            pair("type", text("range")),
+            pair(
+                "spec",
+                Frame::Map(vec![
+                    pair("lastkey", Frame::Integer(last_key)),
+                    pair("keystep", Frame::Integer(info.key_step)),
+                    pair("limit", Frame::Integer(0)),
+                ]),
+            ),
+        ]);
+        (begin, find, vec![text(access)])
+    } else {
+        return vec![];
+    };
+    vec![Frame::Map(vec![
+        pair("flags", Frame::Set(flags)),
+        pair("begin_search", begin),
+        pair("find_keys", find),
+    ])]
+}
+
+//命令的文档
+fn document(info: &CommandInfo) -> Frame {
+    let field = |name: &'static str, value: &'static str| {
+        (
+            Frame::Simple(name.to_string()),
+            Frame::Bulk(Bytes::from_static(value.as_bytes())),
+        )
+    };
+    let (group, since, summary) = match DOCS.iter().find(|(name, ..)| *name == info.name) {
+        Some((_, group, since, summary)) => (group, since, summary),
+        None => return Frame::Map(vec![field("group", "module")]),
+    };
+    let mut doc = vec![
+        field("summary", summary),
+        field("since", since),
+        field("group", group),
+    ];
+    let arguments = arguments(info.name);
+    if !arguments.is_empty() {
+        let arguments = arguments.iter().map(Argument::frame).collect();
+        doc.push((
+            Frame::Simple("arguments".to_string()),
+            Frame::Array(arguments),
+        ));
+    }
+    Frame::Map(doc)
+}
+
+//命令的一个参数，与redis的COMMAND DOCS中arguments的结构一致
+#[derive(Debug)]
+struct Argument {
+    name: String,
+    //参数的类型：key、string、integer、double、pattern、unix-time，
+    //只由记号组成的pure-token，多选一的oneof与几个参数组成的block
+    kind: &'static str,
+    //参数之前的记号，比如EX seconds中的EX
+    token: Option<&'static str>,
+    optional: bool,
+    multiple: bool,
+    //oneof与block包含的参数
+    arguments: Vec<Argument>,
+}
+
+impl Argument {
+    fn new(name: String, kind: &'static str, arguments: Vec<Argument>) -> Argument {
+        Argument {
+            name,
+            kind,
+            token: None,
+            optional: false,
+            multiple: false,
+            arguments,
+        }
+    }
+
+    //语法中的一个单词，没有小写字母的是记号，其余的是参数名
+    fn word(word: &'static str) -> Argument {
+        if word.bytes().any(|b| b.is_ascii_lowercase()) {
+            return Argument::new(word.to_string(), argument_type(word), vec![]);
+        }
+        let mut token = Argument::new(word.to_lowercase(), "pure-token", vec![]);
+        token.token = Some(word);
+        token
+    }
+
+    fn frame(&self) -> Frame {
+        let text = |text: &str| Frame::Bulk(Bytes::copy_from_slice(text.as_bytes()));
+        let field = |name: &str, value: Frame| (Frame::Simple(name.to_string()), value);
+        let mut fields = vec![
+            field("name", text(&self.name)),
+            field("type", text(self.kind)),
+        ];
+        if let Some(token) = self.token {
+            fields.push(field("token", text(token)));
+        }
+        let flags: Vec<Frame> = [("optional", self.optional), ("multiple", self.multiple)]
+            .into_iter()
+            .filter(|(_, set)| *set)
+            .map(|(flag, _)| Frame::Simple(flag.to_string()))
+            .collect();
+        if !flags.is_empty() {
+            fields.push(field("flags", Frame::Set(flags)));
+        }
+        if !self.arguments.is_empty() {
+            let arguments = self.arguments.iter().map(Argument::frame).collect();
+            fields.push(field("arguments", Frame::Array(arguments)));
+        }
+        Frame::Map(fields)
+    }
+}
+
+//按参数名推断参数的类型
+fn argument_type(name: &str) -> &'static str {
+    match name {
+        "key" | "newkey" | "destkey" | "sourcekey" | "source" | "destination" => "key",
+        "pattern" => "pattern",
+        "unix-time-seconds" | "unix-time-milliseconds" => "unix-time",
+        "increment" | "score" | "min" | "max" | "longitude" | "latitude" | "radius" | "width"
+        | "height" | "timeout" => "double",
+        "seconds" | "milliseconds" | "ms" | "count" | "numkeys" | "offset" | "start" | "end"
+        | "stop" | "index" | "index1" | "index2" | "db" | "destination-db" | "bit" | "cursor"
+        | "port" | "numreplicas" | "protover" | "ttl" | "slot" | "frequency" | "bytes"
+        | "decrement" | "min-idle-time" | "threshold" => "integer",
+        _ => "string",
+    }
+}
+
+//按ARGUMENTS中的语法生成命令的参数，没有登记的命令没有参数
+fn arguments(name: &str) -> Vec<Argument> {
+    let syntax = match ARGUMENTS.iter().find(|(command, _)| *command == name) {
+        Some((_, syntax)) => syntax,
+        None => return vec![],
+    };
+    let mut parser = Syntax {
+        words: split_syntax(syntax),
+        pos: 0,
+    };
+    parser.sequence()
+}
+
+//把语法拆成单词、括号、|与...
+fn split_syntax(syntax: &'static str) -> Vec<&'static str> {
+    let is_mark = |c: char| "[]<>|.".contains(c);
+    let mut words = vec![];
+    let mut rest = syntax.trim_start();
+    while !rest.is_empty() {
+        let len = if rest.starts_with("...") {
+            3
+        } else if rest.starts_with(is_mark) {
+            1
+        } else {
+            rest.find(|c: char| c.is_whitespace() || is_mark(c))
+                .unwrap_or(rest.len())
+        };
+        words.push(&rest[..len]);
+        rest = rest[len..].trim_start();
+    }
+    words
+}
+
+//命令参数的语法，与redis文档中的写法一致
+//
+// [...]是可选的部分，<...>是必须出现的部分，括号中以|分隔多选一的备选项，...代表前一部分可以重复
+struct Syntax {
+    words: Vec<&'static str>,
+    pos: usize,
+}
+
+impl Syntax {
+    fn peek(&self) -> Option<&'static str> {
+        self.words.get(self.pos).copied()
+    }
+
+    //解析一串参数，直到|、结束的括号或末尾
+    fn sequence(&mut self) -> Vec<Argument> {
+        let mut items = vec![];
+        while let Some(word) = self.peek() {
+            self.pos += 1;
+            let mut item = match word {
+                "|" | "]" | ">" => {
+                    self.pos -= 1;
+                    break;
+                }
+                "[" | "<" => {
+                    let mut group = self.group();
+                    //跳过结束的括号
+                    self.pos += 1;
+                    group.optional = word == "[";
+                    group
+                }
+                word => Argument::word(word),
+            };
+            if self.peek() == Some("...") {
+                self.pos += 1;
+                item.multiple = true;
+            }
+            items.push(item);
+        }
+        items
+    }
+
+    //解析括号中的内容，多个备选项组成oneof
+    fn group(&mut self) -> Argument {
+        let mut alternatives = vec![block(self.sequence())];
+        while self.peek() == Some("|") {
+            self.pos += 1;
+            alternatives.push(block(self.sequence()));
+        }
+        if alternatives.len() == 1 {
+            return alternatives.pop().unwrap();
+        }
+        let names: Vec<&str> = alternatives.iter().map(|a| a.name.as_str()).collect();
+        Argument::new(names.join("-"), "oneof", alternatives)
+    }
+}
+
+//把一串参数合并为一个
+//
+// 以记号开头时记号作为之后参数的token，比如EX seconds是带有token EX的seconds，
+// LIMIT offset count是带有token LIMIT、包含offset与count的block
+fn block(mut items: Vec<Argument>) -> Argument {
+    if items.len() == 1 {
+        return items.pop().unwrap();
+    }
+    let first = &items[0];
+    if first.kind != "pure-token" || first.multiple {
+        return Argument::new(items[0].name.clone(), "block", items);
+    }
+    let token = items.remove(0);
+    if let [rest] = items.as_slice() {
+        if rest.token.is_none() && rest.kind != "pure-token" {
+            let mut rest = items.pop().unwrap();
+            rest.token = token.token;
+            return rest;
+        }
+    }
+    let mut block = Argument::new(token.name, "block", items);
+    block.token = token.token;
+    block
+}
+
+//COMMAND GETKEYS，args为完整的命令
+fn get_keys(args: &[Bytes]) -> Frame {
+    let name = String::from_utf8_lossy(&args[0]).to_lowercase();
+    let info = match table::lookup(&name) {
+        Some(info) => info,
+        None => return Frame::Error("ERR Invalid command specified".to_string()),
+    };
+    if !info.accepts(args.len()) {
+        let err = "ERR Invalid number of arguments specified for command";
+        return Frame::Error(err.to_string());
+    }
+    let keys = info.key_args(args);
+    if keys.is_empty() {
+        return Frame::Error("ERR The command has no key arguments".to_string());
+    }
+    Frame::Array(keys.into_iter().cloned().map(Frame::Bulk).collect())
+}
+
+//命令的文档：名称、分组、加入redis的版本与简介，分组与版本沿用redis的COMMAND DOCS
+//
+//新增命令时需要同时在这里与table.rs中登记
+const DOCS: &[(&str, &str, &str, &str)] = &[
+    ("get", "string", "1.0.0", "Returns the string value of a key."),
+    ("set", "string", "1.0.0", "Sets the string value of a key, ignoring its type. The key is created if it doesn't exist."),
+    ("expire", "generic", "1.0.0", "Sets the expiration time of a key in seconds."),
+    ("pexpire", "generic", "2.6.0", "Sets the expiration time of a key in milliseconds."),
+    ("expireat", "generic", "1.2.0", "Sets the expiration time of a key to a Unix timestamp."),
+    ("pexpireat", "generic", "2.6.0", "Sets the expiration time of a key to a Unix milliseconds timestamp."),
+    ("ttl", "generic", "1.0.0", "Returns the expiration time in seconds of a key."),
+    ("pttl", "generic", "2.6.0", "Returns the expiration time in milliseconds of a key."),
+    ("persist", "generic", "2.2.0", "Removes the expiration time of a key."),
+    ("del", "generic", "1.0.0", "Deletes one or more keys."),
+    ("unlink", "generic", "4.0.0", "Asynchronously deletes one or more keys."),
+    ("exists", "generic", "1.0.0", "Determines whether one or more keys exist."),
+    ("incr", "string", "1.0.0", "Increments the integer value of a key by one. Uses 0 as initial value if the key doesn't exist."),
+    ("decr", "string", "1.0.0", "Decrements the integer value of a key by one. Uses 0 as initial value if the key doesn't exist."),
+    ("incrby", "string", "1.0.0", "Increments the integer value of a key by a number. Uses 0 as initial value if the key doesn't exist."),
+    ("decrby", "string", "1.0.0", "Decrements a number from the integer value of a key. Uses 0 as initial value if the key doesn't exist."),
+    ("incrbyfloat", "string", "2.6.0", "Increment the floating point value of a key by a number. Uses 0 as initial value if the key doesn't exist."),
+    ("mget", "string", "1.0.0", "Atomically returns the string values of one or more keys."),
+    ("mset", "string", "1.0.1", "Atomically creates or modifies the string values of one or more keys."),
+    ("msetnx", "string", "1.0.1", "Atomically modifies the string values of one or more keys only when all keys don't exist."),
+    ("append", "string", "2.0.0", "Appends a string to the value of a key. Creates the key if it doesn't exist."),
+    ("strlen", "string", "2.2.0", "Returns the length of a string value."),
+    ("getrange", "string", "2.4.0", "Returns a substring of the string stored at a key."),
+    ("setrange", "string", "2.2.0", "Overwrites a part of a string value with another by an offset. Creates the key if it doesn't exist."),
+    ("getdel", "string", "6.2.0", "Returns the string value of a key after deleting the key."),
+    ("getex", "string", "6.2.0", "Returns the string value of a key after setting its expiration time."),
+    ("setbit", "bitmap", "2.2.0", "Sets or clears the bit at offset of the string value. Creates the key if it doesn't exist."),
+    ("getbit", "bitmap", "2.2.0", "Returns a bit value by offset."),
+    ("bitcount", "bitmap", "2.6.0", "Counts the number of set bits (population counting) in a string."),
+    ("bitpos", "bitmap", "2.8.7", "Finds the first set (1) or clear (0) bit in a string."),
+    ("bitop", "bitmap", "2.6.0", "Performs bitwise operations on multiple strings, and stores the result."),
+    ("pfadd", "hyperloglog", "2.8.9", "Adds elements to a HyperLogLog key. Creates the key if it doesn't exist."),
+    ("pfcount", "hyperloglog", "2.8.9", "Returns the approximated cardinality of the set(s) observed by the HyperLogLog key(s)."),
+    ("pfmerge", "hyperloglog", "2.8.9", "Merges one or more HyperLogLog values into a single key."),
+    ("type", "generic", "1.0.0", "Determines the type of value stored at a key."),
+    ("hset", "hash", "2.0.0", "Creates or modifies the value of a field in a hash."),
+    ("hmset", "hash", "2.0.0", "Sets the values of multiple fields."),
+    ("hsetnx", "hash", "2.0.0", "Sets the value of a field in a hash only when the field doesn't exist."),
+    ("hget", "hash", "2.0.0", "Returns the value of a field in a hash."),
+    ("hdel", "hash", "2.0.0", "Deletes one or more fields and their values from a hash. Deletes the hash if no fields remain."),
+    ("hgetall", "hash", "2.0.0", "Returns all fields and values in a hash."),
+    ("hincrby", "hash", "2.0.0", "Increments the integer value of a field in a hash by a number. Uses 0 as initial value if the field doesn't exist."),
+    ("hlen", "hash", "2.0.0", "Returns the number of fields in a hash."),
+    ("hrandfield", "hash", "6.2.0", "Returns one or more random fields from a hash."),
+    ("lpush", "list", "1.0.0", "Prepends one or more elements to a list. Creates the key if it doesn't exist."),
+    ("rpush", "list", "1.0.0", "Appends one or more elements to a list. Creates the key if it doesn't exist."),
+    ("lpushx", "list", "2.2.0", "Prepends one or more elements to a list only when the list exists."),
+    ("rpushx", "list", "2.2.0", "Appends an element to a list only when the list exists."),
+    ("lpop", "list", "1.0.0", "Returns the first elements in a list after removing it. Deletes the list if the last element was popped."),
+    ("rpop", "list", "1.0.0", "Returns and removes the last elements of a list. Deletes the list if the last element was popped."),
+    ("lrange", "list", "1.0.0", "Returns a range of elements from a list."),
+    ("llen", "list", "1.0.0", "Returns the length of a list."),
+    ("linsert", "list", "2.2.0", "Inserts an element before or after another element in a list."),
+    ("lmove", "list", "6.2.0", "Returns an element after popping it from one list and pushing it to another. Deletes the list if the last element was moved."),
+    ("rpoplpush", "list", "1.2.0", "Returns the last element of a list after removing and pushing it to another list. Deletes the list if the last element was popped."),
+    ("blpop", "list", "2.0.0", "Removes and returns the first element in a list. Blocks until an element is available otherwise. Deletes the list if the last element was popped."),
+    ("brpop", "list", "2.0.0", "Removes and returns the last element in a list. Blocks until an element is available otherwise. Deletes the list if the last element was popped."),
+    ("blmove", "list", "6.2.0", "Pops an element from a list, pushes it to another list and returns it. Blocks until an element is available otherwise. Deletes the list if the last element was moved."),
+    ("brpoplpush", "list", "2.2.0", "Pops an element from a list, pushes it to another list and returns it. Block until an element is available otherwise. Deletes the list if the last element was popped."),
+    ("sadd", "set", "1.0.0", "Adds one or more members to a set. Creates the key if it doesn't exist."),
+    ("srem", "set", "1.0.0", "Removes one or more members from a set. Deletes the set if the last member was removed."),
+    ("smembers", "set", "1.0.0", "Returns all members of a set."),
+    ("sismember", "set", "1.0.0", "Determines whether a member belongs to a set."),
+    ("scard", "set", "1.0.0", "Returns the number of members in a set."),
+    ("sinter", "set", "1.0.0", "Returns the intersect of multiple sets."),
+    ("sunion", "set", "1.0.0", "Returns the union of multiple sets."),
+    ("sdiff", "set", "1.0.0", "Returns the difference of multiple sets."),
+    ("sinterstore", "set", "1.0.0", "Stores the intersect of multiple sets in a key."),
+    ("sunionstore", "set", "1.0.0", "Stores the union of multiple sets in a key."),
+    ("sdiffstore", "set", "1.0.0", "Stores the difference of multiple sets in a key."),
+    ("zadd", "sorted-set", "1.2.0", "Adds one or more members to a sorted set, or updates their scores. Creates the key if it doesn't exist."),
+    ("zrem", "sorted-set", "1.2.0", "Removes one or more members from a sorted set. Deletes the sorted set if all members were removed."),
+    ("zscore", "sorted-set", "1.2.0", "Returns the score of a member in a sorted set."),
+    ("zcard", "sorted-set", "1.2.0", "Returns the number of members in a sorted set."),
+    ("zincrby", "sorted-set", "1.2.0", "Increments the score of a member in a sorted set."),
+    ("zrank", "sorted-set", "2.0.0", "Returns the index of a member in a sorted set ordered by ascending scores."),
+    ("zrevrank", "sorted-set", "2.0.0", "Returns the index of a member in a sorted set ordered by descending scores."),
+    ("zrange", "sorted-set", "1.2.0", "Returns members in a sorted set within a range of indexes."),
+    ("zrevrange", "sorted-set", "1.2.0", "Returns members in a sorted set within a range of indexes in reverse order."),
+    ("zrangebyscore", "sorted-set", "1.0.5", "Returns members in a sorted set within a range of scores."),
+    ("zrevrangebyscore", "sorted-set", "2.2.0", "Returns members in a sorted set within a range of scores in reverse order."),
+    ("zpopmin", "sorted-set", "5.0.0", "Returns the lowest-scoring members from a sorted set after removing them. Deletes the sorted set if the last member was popped."),
+    ("zpopmax", "sorted-set", "5.0.0", "Returns the highest-scoring members from a sorted set after removing them. Deletes the sorted set if the last member was popped."),
+    ("geoadd", "geo", "3.2.0", "Adds one or more members to a geospatial index. The key is created if it doesn't exist."),
+    ("geodist", "geo", "3.2.0", "Returns the distance between two members of a geospatial index."),
+    ("geopos", "geo", "3.2.0", "Returns the longitude and latitude of members from a geospatial index."),
+    ("geosearch", "geo", "6.2.0", "Queries a geospatial index for members inside an area of a box or a circle."),
+    ("xadd", "stream", "5.0.0", "Appends a new message to a stream. Creates the key if it doesn't exist."),
+    ("xlen", "stream", "5.0.0", "Return the number of messages in a stream."),
+    ("xrange", "stream", "5.0.0", "Returns the messages from a stream within a range of IDs."),
+    ("xrevrange", "stream", "5.0.0", "Returns the messages from a stream within a range of IDs in reverse order."),
+    ("xread", "stream", "5.0.0", "Returns messages from multiple streams with IDs greater than the ones requested. Blocks until a message is available otherwise."),
+    ("xgroup", "stream", "5.0.0", "A container for consumer groups commands."),
+    ("xreadgroup", "stream", "5.0.0", "Returns new or historical messages from a stream for a consumer in a group. Blocks until a message is available otherwise."),
+    ("xack", "stream", "5.0.0", "Returns the number of messages that were successfully acknowledged by the consumer group member of a stream."),
+    ("xpending", "stream", "5.0.0", "Returns the information and entries from a stream consumer group's pending entries list."),
+    ("xclaim", "stream", "5.0.0", "Changes, or acquires, ownership of a message in a consumer group, as if the message was delivered a consumer group member."),
+    ("subscribe", "pubsub", "2.0.0", "Listens for messages published to channels."),
+    ("unsubscribe", "pubsub", "2.0.0", "Stops listening to messages posted to channels."),
+    ("psubscribe", "pubsub", "2.0.0", "Listens for messages published to channels that match one or more patterns."),
+    ("punsubscribe", "pubsub", "2.0.0", "Stops listening to messages published to channels that match one or more patterns."),
+    ("publish", "pubsub", "2.0.0", "Posts a message to a channel."),
+    ("ssubscribe", "pubsub", "7.0.0", "Listens for messages published to shard channels."),
+    ("sunsubscribe", "pubsub", "7.0.0", "Stops listening to messages posted to shard channels."),
+    ("spublish", "pubsub", "7.0.0", "Post a message to a shard channel"),
+    ("config", "server", "2.0.0", "A container for server configuration commands."),
+    ("cluster", "cluster", "3.0.0", "A container for Redis Cluster commands."),
+    ("multi", "transactions", "1.2.0", "Starts a transaction."),
+    ("exec", "transactions", "1.2.0", "Executes all commands in a transaction."),
+    ("discard", "transactions", "2.0.0", "Discards a transaction."),
+    ("watch", "transactions", "2.2.0", "Monitors changes to keys to determine the execution of a transaction."),
+    ("unwatch", "transactions", "2.2.0", "Forgets about watched keys of a transaction."),
+    ("eval", "scripting", "2.6.0", "Executes a server-side Lua script."),
+    ("evalsha", "scripting", "2.6.0", "Executes a server-side Lua script by SHA1 digest."),
+    ("script", "scripting", "2.6.0", "A container for Lua scripts management commands."),
+    ("keys", "generic", "1.0.0", "Returns all key names that match a pattern."),
+    ("scan", "generic", "2.8.0", "Iterates over the key names in the database."),
+    ("hscan", "hash", "2.8.0", "Iterates over fields and values of a hash."),
+    ("sscan", "set", "2.8.0", "Iterates over members of a set."),
+    ("zscan", "sorted-set", "2.8.0", "Iterates over members and scores of a sorted set."),
+    ("rename", "generic", "1.0.0", "Renames a key and overwrites the destination."),
+    ("renamenx", "generic", "1.0.0", "Renames a key only when the target key name doesn't exist."),
+    ("randomkey", "generic", "1.0.0", "Returns a random key name from the database."),
+    ("dbsize", "server", "1.0.0", "Returns the number of keys in the database."),
+    ("copy", "generic", "6.2.0", "Copies the value of a key to a new key."),
+    ("flushdb", "server", "1.0.0", "Remove all keys from the current database."),
+    ("flushall", "server", "1.0.0", "Removes all keys from all databases."),
+    ("select", "connection", "1.0.0", "Changes the selected database."),
+    ("hello", "connection", "6.0.0", "Handshakes with the Redis server."),
+    ("auth", "connection", "1.0.0", "Authenticates the connection."),
+    ("quit", "connection", "1.0.0", "Closes the connection."),
+    ("reset", "connection", "6.2.0", "Resets the connection."),
+    ("acl", "server", "6.0.0", "A container for Access List Control commands."),
+    ("client", "connection", "2.4.0", "A container for client connection commands."),
+    ("swapdb", "server", "4.0.0", "Swaps two Redis databases."),
+    ("move", "generic", "1.0.0", "Moves a key to another database."),
+    ("sort", "generic", "1.0.0", "Sorts the elements in a list, a set, or a sorted set, optionally storing the result."),
+    ("dump", "generic", "2.6.0", "Returns a serialized representation of the value stored at a key."),
+    ("restore", "generic", "2.6.0", "Creates a key from the serialized representation of a value."),
+    ("info", "server", "1.0.0", "Returns information and statistics about the server."),
+    ("command", "server", "2.8.13", "Returns detailed information about all commands."),
+    ("monitor", "server", "1.0.0", "Listens for all requests received by the server in real-time."),
+    ("slowlog", "server", "2.2.12", "A container for slow log commands."),
+    ("latency", "server", "2.8.13", "A container for latency diagnostics commands."),
+    ("memory", "server", "4.0.0", "A container for memory diagnostics commands."),
+    ("object", "generic", "2.2.3", "A container for object introspection commands."),
+    ("debug", "server", "1.0.0", "A container for debugging commands."),
+    ("save", "server", "1.0.0", "Synchronously saves the database(s) to disk."),
+    ("bgsave", "server", "1.0.0", "Asynchronously saves the database(s) to disk."),
+    ("lastsave", "server", "1.0.0", "Returns the Unix timestamp of the last successful save to disk."),
+    ("bgrewriteaof", "server", "1.0.0", "Asynchronously rewrites the append-only file to disk."),
+    ("shutdown", "server", "1.0.0", "Synchronously saves the database(s) to disk and shuts down the Redis server."),
+    ("replconf", "server", "3.0.0", "An internal command for configuring the replication stream."),
+    ("psync", "server", "2.8.0", "An internal command used in replication."),
+    ("replicaof", "server", "5.0.0", "Configures a server as replica of another, or promotes it to a master."),
+    ("slaveof", "server", "1.0.0", "Sets a Redis server as a replica of another, or promotes it to being a master."),
+    ("wait", "generic", "3.0.0", "Blocks until the asynchronous replication of all preceding write commands sent by the connection is completed."),
+    ("asking", "cluster", "3.0.0", "Signals that a cluster client is following an -ASK redirect."),
+    ("migrate", "generic", "2.6.0", "Atomically transfers a key from one Redis instance to another."),
+    ("ping", "connection", "1.0.0", "Returns the server's liveliness response."),
+    ("echo", "connection", "1.0.0", "Returns the given string."),
+];
+
+//命令参数的语法，写法见Syntax，没有参数的命令为空
+//
+//新增命令时需要同时在这里与table.rs中登记
+const ARGUMENTS: &[(&str, &str)] = &[
+    ("get", "key"),
+    ("set", "key value [NX | XX] [GET] [EX seconds | PX milliseconds | EXAT unix-time-seconds | PXAT unix-time-milliseconds | KEEPTTL | PERSIST]"),
+    ("expire", "key seconds"),
+    ("pexpire", "key milliseconds"),
+    ("expireat", "key unix-time-seconds"),
+    ("pexpireat", "key unix-time-milliseconds"),
+    ("ttl", "key"),
+    ("pttl", "key"),
+    ("persist", "key"),
+    ("del", "key..."),
+    ("unlink", "key..."),
+    ("exists", "key..."),
+    ("incr", "key"),
+    ("decr", "key"),
+    ("incrby", "key increment"),
+    ("decrby", "key decrement"),
+    ("incrbyfloat", "key increment"),
+    ("mget", "key..."),
+    ("mset", "<key value>..."),
+    ("msetnx", "<key value>..."),
+    ("append", "key value"),
+    ("strlen", "key"),
+    ("getrange", "key start end"),
+    ("setrange", "key offset value"),
+    ("getdel", "key"),
+    ("getex", "key [EX seconds | PX milliseconds | EXAT unix-time-seconds | PXAT unix-time-milliseconds | PERSIST]"),
+    ("setbit", "key offset value"),
+    ("getbit", "key offset"),
+    ("bitcount", "key [start end [BYTE | BIT]]"),
+    ("bitpos", "key bit [start [end [BYTE | BIT]]]"),
+    ("bitop", "<AND | OR | XOR | NOT> destkey key..."),
+    ("pfadd", "key [element...]"),
+    ("pfcount", "key..."),
+    ("pfmerge", "destkey [sourcekey...]"),
+    ("type", "key"),
+    ("hset", "key <field value>..."),
+    ("hmset", "key <field value>..."),
+    ("hsetnx", "key field value"),
+    ("hget", "key field"),
+    ("hdel", "key field..."),
+    ("hgetall", "key"),
+    ("hincrby", "key field increment"),
+    ("hlen", "key"),
+    ("hrandfield", "key [count [WITHVALUES]]"),
+    ("lpush", "key element..."),
+    ("rpush", "key element..."),
+    ("lpushx", "key element..."),
+    ("rpushx", "key element..."),
+    ("lpop", "key [count]"),
+    ("rpop", "key [count]"),
+    ("lrange", "key start stop"),
+    ("llen", "key"),
+    ("linsert", "key <BEFORE | AFTER> pivot element"),
+    ("lmove", "source destination <LEFT | RIGHT> <LEFT | RIGHT>"),
+    ("rpoplpush", "source destination"),
+    ("blpop", "key... timeout"),
+    ("brpop", "key... timeout"),
+    ("blmove", "source destination <LEFT | RIGHT> <LEFT | RIGHT> timeout"),
+    ("brpoplpush", "source destination timeout"),
+    ("sadd", "key member..."),
+    ("srem", "key member..."),
+    ("smembers", "key"),
+    ("sismember", "key member"),
+    ("scard", "key"),
+    ("sinter", "key..."),
+    ("sunion", "key..."),
+    ("sdiff", "key..."),
+    ("sinterstore", "destination key..."),
+    ("sunionstore", "destination key..."),
+    ("sdiffstore", "destination key..."),
+    ("zadd", "key [NX | XX] [GT | LT] [CH] [INCR] <score member>..."),
+    ("zrem", "key member..."),
+    ("zscore", "key member"),
+    ("zcard", "key"),
+    ("zincrby", "key increment member"),
+    ("zrank", "key member"),
+    ("zrevrank", "key member"),
+    ("zrange", "key start stop [BYSCORE] [REV] [LIMIT offset count] [WITHSCORES]"),
+    ("zrevrange", "key start stop [WITHSCORES]"),
+    ("zrangebyscore", "key min max [WITHSCORES] [LIMIT offset count]"),
+    ("zrevrangebyscore", "key max min [WITHSCORES] [LIMIT offset count]"),
+    ("zpopmin", "key [count]"),
+    ("zpopmax", "key [count]"),
+    ("geoadd", "key [NX | XX] [CH] <longitude latitude member>..."),
+    ("geodist", "key member1 member2 [M | KM | FT | MI]"),
+    ("geopos", "key [member...]"),
+    ("geosearch", "key <FROMMEMBER member | FROMLONLAT longitude latitude> <BYRADIUS radius <M | KM | FT | MI> | BYBOX width height <M | KM | FT | MI>> [ASC | DESC] [COUNT count [ANY]] [WITHCOORD] [WITHDIST] [WITHHASH]"),
+    ("xadd", "key [NOMKSTREAM] [MAXLEN [= | ~] threshold] <* | id> <field value>..."),
+    ("xlen", "key"),
+    ("xrange", "key start end [COUNT count]"),
+    ("xrevrange", "key end start [COUNT count]"),
+    ("xread", "[COUNT count] [BLOCK milliseconds] STREAMS key... id..."),
+    ("xgroup", "<CREATE key group <id | $> [MKSTREAM] | SETID key group <id | $> | DESTROY key group | CREATECONSUMER key group consumer | DELCONSUMER key group consumer>"),
+    ("xreadgroup", "GROUP group consumer [COUNT count] [BLOCK milliseconds] [NOACK] STREAMS key... id..."),
+    ("xack", "key group id..."),
+    ("xpending", "key group [[IDLE min-idle-time] start end count [consumer]]"),
+    ("xclaim", "key group consumer min-idle-time id... [IDLE ms] [TIME unix-time-milliseconds] [RETRYCOUNT count] [FORCE] [JUSTID]"),
+    ("subscribe", "channel..."),
+    ("unsubscribe", "[channel...]"),
+    ("psubscribe", "pattern..."),
+    ("punsubscribe", "[pattern...]"),
+    ("publish", "channel message"),
+    ("ssubscribe", "shardchannel..."),
+    ("sunsubscribe", "[shardchannel...]"),
+    ("spublish", "shardchannel message"),
+    ("config", "<GET parameter... | SET <parameter value>... | REWRITE>"),
+    ("cluster", "<KEYSLOT key | INFO | MYID | SLOTS | SHARDS | SETSLOT slot <MIGRATING node-id | IMPORTING node-id | STABLE | NODE node-id>>"),
+    ("multi", ""),
+    ("exec", ""),
+    ("discard", ""),
+    ("watch", "key..."),
+    ("unwatch", ""),
+    ("eval", "script numkeys [key...] [arg...]"),
+    ("evalsha", "sha1 numkeys [key...] [arg...]"),
+    ("script", "<LOAD script | EXISTS sha1... | FLUSH [ASYNC | SYNC] | KILL>"),
+    ("keys", "pattern"),
+    ("scan", "cursor [MATCH pattern] [COUNT count] [TYPE type]"),
+    ("hscan", "key cursor [MATCH pattern] [COUNT count] [NOVALUES]"),
+    ("sscan", "key cursor [MATCH pattern] [COUNT count]"),
+    ("zscan", "key cursor [MATCH pattern] [COUNT count]"),
+    ("rename", "key newkey"),
+    ("renamenx", "key newkey"),
+    ("randomkey", ""),
+    ("dbsize", ""),
+    ("copy", "source destination [DB destination-db] [REPLACE]"),
+    ("flushdb", "[ASYNC | SYNC]"),
+    ("flushall", "[ASYNC | SYNC]"),
+    ("select", "index"),
+    ("hello", "[protover [AUTH username password] [SETNAME clientname]]"),
+    ("auth", "[username] password"),
+    ("quit", ""),
+    ("reset", ""),
+    ("acl", "<SETUSER username [rule...] | GETUSER username | LIST | WHOAMI>"),
+    ("client", "<ID | LIST [ID id...] | SETNAME name | GETNAME | KILL <addr | [ID id] [ADDR addr] [SKIPME <YES | NO>]> | PAUSE timeout [WRITE | ALL] | UNPAUSE | TRACKING <ON | OFF> [REDIRECT id] [BCAST] [PREFIX prefix...] | GETREDIR>"),
+    ("swapdb", "index1 index2"),
+    ("move", "key db"),
+    ("sort", "key [BY pattern] [LIMIT offset count] [GET pattern...] [ASC | DESC] [ALPHA] [STORE destination]"),
+    ("dump", "key"),
+    ("restore", "key ttl serialized-value [REPLACE] [ABSTTL] [IDLETIME seconds] [FREQ frequency]"),
+    ("info", "[section...]"),
+    ("command", "[COUNT | LIST | INFO [command-name...] | DOCS [command-name...] | GETKEYS command arg...]"),
+    ("monitor", ""),
+    ("slowlog", "<GET [count] | LEN | RESET>"),
+    ("latency", "<HISTORY event | LATEST | RESET [event...]>"),
+    ("memory", "<USAGE key [SAMPLES count] | STATS | DOCTOR>"),
+    ("object", "ENCODING key"),
+    ("debug", "<SLEEP seconds | OBJECT key | SET-ACTIVE-EXPIRE <0 | 1> | QUICKACK <0 | 1> | CHANGE-REPL-ID | JMAP | SET-MEMORY-USAGE bytes | OBJECT-ENCODING key encoding>"),
+    ("save", ""),
+    ("bgsave", ""),
+    ("lastsave", ""),
+    ("bgrewriteaof", ""),
+    ("shutdown", "[NOSAVE | SAVE]"),
+    ("replconf", "<option value>..."),
+    ("psync", "replid offset"),
+    ("replicaof", "<host port | NO ONE>"),
+    ("slaveof", "<host port | NO ONE>"),
+    ("wait", "numreplicas timeout"),
+    ("asking", ""),
+    ("migrate", "host port <key | \"\"> destination-db timeout [COPY] [REPLACE] [AUTH password] [AUTH2 username password] [KEYS key...]"),
+    ("ping", "[message]"),
+    ("echo", "message"),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::cmd::Command;
+    use crate::lib::config::Config;
+    use crate::lib::db::Db;
+
+    fn call(args: &[&str]) -> Frame {
+        let frame = Frame::Array(
+            args.iter()
+                .map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes())))
+                .collect(),
+        );
+        Command::from_frame(frame)
+            .unwrap()
+            .apply_now(&Db::new(Config::default()))
+    }
+
+    #[test]
+    fn docs_of_get_have_a_summary() {
+        let docs = match call(&["COMMAND", "DOCS", "get", "nosuchcommand"]) {
+            Frame::Map(docs) => docs,
+            frame => panic!("unexpected reply {:?}", frame),
+        };
+        //不存在的命令被省略
+        assert_eq!(docs.len(), 1);
+        assert!(docs[0].0 == "get");
+        let fields = match &docs[0].1 {
+            Frame::Map(fields) => fields,
+            frame => panic!("unexpected doc {:?}", frame),
+        };
+        let field = |name: &str| {
+            fields
+                .iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value)
+        };
+        assert!(*field("summary").unwrap() == "Returns the string value of a key.");
+        assert!(*field("since").unwrap() == "1.0.0");
+        assert!(*field("group").unwrap() == "string");
+    }
+
+    //COMMAND INFO中单个命令的键的规格
+    fn key_specs_of(name: &str) -> Vec<Frame> {
+        match call(&["COMMAND", "INFO", name]) {
+            Frame::Array(mut infos) => match infos.pop() {
+                Some(Frame::Array(mut info)) => {
+                    assert_eq!(info.len(), 10);
+                    info.pop();
+                    match info.pop() {
+                        Some(Frame::Array(specs)) => specs,
+                        frame => panic!("unexpected key specs {:?}", frame),
+                    }
+                }
+                frame => panic!("unexpected info {:?}", frame),
+            },
+            frame => panic!("unexpected reply {:?}", frame),
+        }
+    }
+
+    //按键依次取出嵌套映射中的值
+    fn lookup<'a>(mut frame: &'a Frame, path: &[&str]) -> &'a Frame {
+        for name in path {
+            frame = match frame {
+                Frame::Map(pairs) => pairs
+                    .iter()
+                    .find(|(key, _)| key == name)
+                    .map(|(_, value)| value)
+                    .unwrap_or_else(|| panic!("missing {}", name)),
+                frame => panic!("unexpected frame {:?}", frame),
+            };
+        }
+        frame
+    }
+
+    #[test]
+    fn info_reports_key_specs_from_the_table() {
+        let specs = key_specs_of("mset");
+        assert_eq!(specs.len(), 1);
+        let spec = &specs[0];
+        assert_eq!(
+            *lookup(spec, &["flags"]),
+            Frame::Set(vec![Frame::Simple("RW".to_string())])
+        );
+        assert!(*lookup(spec, &["begin_search", "type"]) == "index");
+        assert_eq!(
+            *lookup(spec, &["begin_search", "spec", "index"]),
+            Frame::Integer(1)
+        );
+        assert!(*lookup(spec, &["find_keys", "type"]) == "range");
+        assert_eq!(
+            *lookup(spec, &["find_keys", "spec", "lastkey"]),
+            Frame::Integer(-1)
+        );
+        assert_eq!(
+            *lookup(spec, &["find_keys", "spec", "keystep"]),
+            Frame::Integer(2)
+        );
+        //键的终点为正数时相对于起点
+        let specs = key_specs_of("lmove");
+        assert_eq!(
+            *lookup(&specs[0], &["find_keys", "spec", "lastkey"]),
+            Frame::Integer(1)
+        );
+        //键的位置取决于参数的命令
+        let specs = key_specs_of("eval");
+        assert!(*lookup(&specs[0], &["find_keys", "type"]) == "unknown");
+        //没有键的命令
+        assert!(key_specs_of("ping").is_empty());
+    }
+
+    #[test]
+    fn every_builtin_command_is_documented() {
+        //其他测试可能注册了自定义命令，只检查内置命令
+        let builtin = table::commands()
+            .into_iter()
+            .filter(|info| table::builtin(info.name).is_some());
+        for info in builtin {
+            assert!(
+                DOCS.iter().any(|(name, ..)| *name == info.name),
+                "{} has no docs",
+                info.name
+            );
+        }
+        assert!(DOCS.iter().all(|(name, ..)| table::builtin(name).is_some()));
+    }
+
+    #[test]
+    fn every_builtin_command_has_well_formed_arguments() {
+        let builtin = table::commands()
+            .into_iter()
+            .filter(|info| table::builtin(info.name).is_some());
+        for info in builtin {
+            let syntax = match ARGUMENTS.iter().find(|(name, _)| *name == info.name) {
+                Some((_, syntax)) => syntax,
+                None => panic!("{} has no arguments", info.name),
+            };
+            //括号不配对时解析会提前停下
+            let mut parser = Syntax {
+                words: split_syntax(syntax),
+                pos: 0,
+            };
+            parser.sequence();
+            assert_eq!(parser.pos, parser.words.len(), "{}", info.name);
+        }
+        assert!(ARGUMENTS
+            .iter()
+            .all(|(name, _)| table::builtin(name).is_some()));
+    }
+
+    #[test]
+    fn docs_of_set_have_arguments() {
+        let docs = call(&["COMMAND", "DOCS", "set"]);
+        let arguments = match lookup(&docs, &["set", "arguments"]) {
+            Frame::Array(arguments) => arguments,
+            frame => panic!("unexpected arguments {:?}", frame),
+        };
+        let names: Vec<&Frame> = arguments.iter().map(|a| lookup(a, &["name"])).collect();
+        assert!(names.len() == 5);
+        assert!(*names[0] == "key" && *names[1] == "value");
+        assert!(*lookup(&arguments[0], &["type"]) == "key");
+        //NX | XX组成可选的oneof
+        let condition = &arguments[2];
+        assert!(*lookup(condition, &["name"]) == "nx-xx");
+        assert!(*lookup(condition, &["type"]) == "oneof");
+        assert_eq!(
+            *lookup(condition, &["flags"]),
+            Frame::Set(vec![Frame::Simple("optional".to_string())])
+        );
+        let get = &arguments[3];
+        assert!(*lookup(get, &["type"]) == "pure-token");
+        assert!(*lookup(get, &["token"]) == "GET");
+        //EX seconds是带有记号的integer
+        let ex = match lookup(&arguments[4], &["arguments"]) {
+            Frame::Array(alternatives) => &alternatives[0],
+            frame => panic!("unexpected expiration {:?}", frame),
+        };
+        assert!(*lookup(ex, &["name"]) == "seconds");
+        assert!(*lookup(ex, &["type"]) == "integer");
+        assert!(*lookup(ex, &["token"]) == "EX");
+    }
+
+    #[test]
+    fn repeated_groups_are_blocks() {
+        let docs = call(&["COMMAND", "DOCS", "mset", "ping"]);
+        let data = match lookup(&docs, &["mset", "arguments"]) {
+            Frame::Array(arguments) => &arguments[0],
+            frame => panic!("unexpected arguments {:?}", frame),
+        };
+        assert!(*lookup(data, &["type"]) == "block");
+        assert_eq!(
+            *lookup(data, &["flags"]),
+            Frame::Set(vec![Frame::Simple("multiple".to_string())])
+        );
+        //ping只有一个可选参数
+        match lookup(&docs, &["ping", "arguments"]) {
+            Frame::Array(arguments) => assert_eq!(arguments.len(), 1),
+            frame => panic!("unexpected arguments {:?}", frame),
+        }
+    }
+}
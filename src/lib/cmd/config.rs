@@ -0,0 +1,123 @@
+use crate::lib::config::ConfigError;
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use bytes::Bytes;
+
+///读取或修改运行期间的配置
+///
+/// CONFIG GET parameter [parameter ...] | CONFIG SET parameter value [parameter value ...] |
+/// CONFIG REWRITE
+///
+/// GET的参数名支持glob风格的模式。SET同时修改多项时要么全部成功，要么全部不生效，
+/// 只能在启动时设置的配置不能通过SET修改。REWRITE将当前的配置写回启动时读取的配置文件
+#[derive(Debug)]
+pub struct Config {
+    op: Op,
+}
+
+///CONFIG的子命令
+#[derive(Debug)]
+enum Op {
+    ///读取名称匹配模式的配置
+    Get(Vec<String>),
+    ///修改配置
+    Set(Vec<(String, String)>),
+    ///将配置写回配置文件
+    Rewrite,
+}
+
+impl Config {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Config, ParseError> {
+        let sub = parse.next_string()?.to_uppercase();
+        let op = match sub.as_str() {
+            "GET" => {
+                let mut patterns = vec![parse.next_string()?.to_lowercase()];
+                while let Some(pattern) = parse.next_optional_string()? {
+                    patterns.push(pattern.to_lowercase());
+                }
+                Op::Get(patterns)
+            }
+            "SET" => {
+                let mut pairs = vec![];
+                while let Some(parameter) = parse.next_optional_string()? {
+                    pairs.push((parameter.to_lowercase(), parse.next_string()?));
+                }
+                if pairs.is_empty() {
+                    return Err(ParseError::EndOfStream);
+                }
+                Op::Set(pairs)
+            }
+            "REWRITE" => Op::Rewrite,
+            _ => {
+                let err = format!("unknown subcommand '{}'. Try CONFIG HELP.", sub);
+                return Err(err.into());
+            }
+        };
+        Ok(Config { op })
+    }
+
+    ///GET回复配置名到值的映射，SET与REWRITE成功时回复OK
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match self.op {
+            Op::Get(patterns) => {
+                let config = db.config();
+                let mut pairs: Vec<(&str, &str)> = vec![];
+                for pattern in &patterns {
+                    for (name, value) in config.matching(pattern) {
+                        if !pairs.iter().any(|(seen, _)| *seen == name) {
+                            pairs.push((name, value));
+                        }
+                    }
+                }
+                let bulk = |text: &str| Frame::Bulk(Bytes::copy_from_slice(text.as_bytes()));
+                Frame::Map(
+                    pairs
+                        .into_iter()
+                        .map(|(name, value)| (bulk(name), bulk(value)))
+                        .collect(),
+                )
+            }
+            Op::Set(pairs) => {
+                //出错时记下是哪一项，用于错误信息
+                let mut failed = 0;
+                let result = db.update_config(|config| {
+                    for (i, (parameter, value)) in pairs.iter().enumerate() {
+                        failed = i;
+                        config.set_at_runtime(parameter, value)?;
+                    }
+                    Ok(())
+                });
+                match result {
+                    Ok(()) => Frame::Simple("OK".to_string()),
+                    Err(e) => {
+                        let (parameter, value) = &pairs[failed];
+                        Frame::Error(set_error(e, parameter, value))
+                    }
+                }
+            }
+            Op::Rewrite => match db.config().rewrite() {
+                Ok(()) => Frame::Simple("OK".to_string()),
+                Err(e) => Frame::Error(format!("ERR Rewriting config file: {}", e)),
+            },
+        }
+    }
+}
+
+//修改配置失败时回复的错误
+fn set_error(err: ConfigError, parameter: &str, value: &str) -> String {
+    match err {
+        ConfigError::Unknown => format!(
+            "ERR Unknown option or number of arguments for CONFIG SET - '{}'",
+            parameter
+        ),
+        ConfigError::Immutable => format!(
+            "ERR CONFIG SET failed (possibly related to argument '{}') - can't set immutable config",
+            parameter
+        ),
+        ConfigError::Invalid => format!(
+            "ERR Invalid argument '{}' for CONFIG SET '{}'",
+            value, parameter
+        ),
+    }
+}
@@ -0,0 +1,75 @@
+use crate::lib::cmd::select::select;
+use crate::lib::db::{Db, Update};
+use crate::lib::frame::Frame;
+use crate::lib::notify::{Class, Event};
+use crate::lib::parse::{Parse, ParseError};
+
+///复制键
+///
+/// COPY source destination [DB destination-db] [REPLACE]
+///
+/// 值与过期时间一起复制，DB指定新键所在的逻辑数据库，默认为当前数据库。新键已存在时只有带REPLACE才会被覆盖
+#[derive(Debug)]
+pub struct CopyKey {
+    source: String,
+    destination: String,
+    //新键所在的逻辑数据库
+    target: Option<i64>,
+    replace: bool,
+}
+
+impl CopyKey {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<CopyKey, ParseError> {
+        let source = parse.next_string()?;
+        let destination = parse.next_string()?;
+        let mut target = None;
+        let mut replace = false;
+        loop {
+            match parse.next_string() {
+                Ok(option) => match option.to_uppercase().as_str() {
+                    "REPLACE" => replace = true,
+                    "DB" => target = Some(parse.next_int()?),
+                    _ => return Err("syntax error".into()),
+                },
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(CopyKey {
+            source,
+            destination,
+            target,
+            replace,
+        })
+    }
+
+    ///回复是否复制
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let target = match self.target {
+            Some(index) => match select(db, index) {
+                Ok(target) => target,
+                Err(e) => return e,
+            },
+            None => db.clone(),
+        };
+        if target.index() == db.index() && self.source == self.destination {
+            return Frame::Error("ERR source and destination objects are the same".to_string());
+        }
+        db.atomically(|| {
+            let (value, expires_at) = match db.read(&self.source, |entry| {
+                (entry.value.clone(), entry.expires_at)
+            }) {
+                Some(entry) => entry,
+                None => return Frame::Integer(0),
+            };
+            if !self.replace && target.exists(&self.destination) {
+                return Frame::Integer(0);
+            }
+            let event = Event::new(Class::Generic, "copy_to");
+            target.update_notify(self.destination.clone(), event, |_| {
+                (Update::Set { value, expires_at }, ())
+            });
+            Frame::Integer(1)
+        })
+    }
+}
@@ -0,0 +1,111 @@
+use crate::lib::cmd::Command;
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::notify::Class;
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::value::Value;
+use crate::lib::Error;
+use bytes::Bytes;
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+///自定义命令的处理函数
+///
+/// 通过ServerBuilder::command注册，args是包括命令名（小写）在内的全部参数，参数个数已经按注册时的arity检查过。
+/// 与内置命令一样在连接当前选择的数据库上执行，返回的帧原样回复给客户端，参数不合法时应回复Frame::Error
+pub trait CommandHandler: Send + Sync + 'static {
+    ///执行一条命令，返回回复给客户端的帧
+    fn call(&self, store: &Store<'_>, args: &[Bytes]) -> Frame;
+}
+
+impl<F> CommandHandler for F
+where
+    F: Fn(&Store<'_>, &[Bytes]) -> Frame + Send + Sync + 'static,
+{
+    fn call(&self, store: &Store<'_>, args: &[Bytes]) -> Frame {
+        self(store, args)
+    }
+}
+
+///自定义命令访问数据库的句柄
+///
+/// 对应执行命令的连接当前选择的逻辑数据库。读写与内置命令一样维护过期、版本号、键空间事件与客户端缓存的失效通知，
+/// 其他类型的值可以通过call执行内置命令读写
+#[derive(Debug)]
+pub struct Store<'a> {
+    db: &'a Db,
+}
+
+impl Store<'_> {
+    ///获取键对应的字符串值，键存储的不是字符串时返回Error::WrongType
+    pub fn get(&self, key: &str) -> Result<Option<Bytes>, Error> {
+        let value = self.db.get(key)?;
+        self.db.record_lookup(value.is_some());
+        Ok(value)
+    }
+
+    ///设置键对应的字符串值，清除原有的过期时间
+    pub fn set(&self, key: &str, value: impl Into<Bytes>) {
+        self.db.set(key.to_string(), Value::String(value.into()));
+        self.db.notify(Class::String, "set", key);
+    }
+
+    ///删除键，返回键是否存在
+    pub fn del(&self, key: &str) -> bool {
+        let removed = self.db.remove(key).is_some();
+        if removed {
+            self.db.notify(Class::Generic, "del", key);
+        }
+        removed
+    }
+
+    ///键是否存在
+    pub fn exists(&self, key: &str) -> bool {
+        self.db.exists(key)
+    }
+
+    ///执行一条命令并返回回复，与脚本中的redis.call一样不会阻塞等待
+    ///
+    /// 订阅、事务等需要连接状态的命令回复错误
+    pub fn call<A: AsRef<[u8]>>(&self, args: &[A]) -> Frame {
+        let args = args
+            .iter()
+            .map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_ref())))
+            .collect();
+        match Command::from_frame(Frame::Array(args)) {
+            Ok(cmd) => cmd.apply_now(self.db),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+///通过ServerBuilder::command注册的命令
+pub struct Custom {
+    handler: Arc<dyn CommandHandler>,
+    //包括命令名在内的全部参数
+    args: Vec<Bytes>,
+}
+
+impl Custom {
+    pub(crate) fn parse_frames(
+        name: &str,
+        handler: Arc<dyn CommandHandler>,
+        parse: &mut Parse,
+    ) -> Result<Custom, ParseError> {
+        let mut args = vec![Bytes::copy_from_slice(name.as_bytes())];
+        while let Some(arg) = parse.next_optional_bytes()? {
+            args.push(arg);
+        }
+        Ok(Custom { handler, args })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        self.handler.call(&Store { db }, &self.args)
+    }
+}
+
+impl Debug for Custom {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Custom").field("args", &self.args).finish()
+    }
+}
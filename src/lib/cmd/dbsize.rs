@@ -0,0 +1,45 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+
+///获取键的数量
+///
+/// DBSIZE
+#[derive(Debug)]
+pub struct DbSize;
+
+impl DbSize {
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<DbSize, ParseError> {
+        Ok(DbSize)
+    }
+
+    ///只计入未过期的键，已过期但还没有被删除的键不计入
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        Frame::Integer(db.key_count() as i64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::config::Config;
+    use crate::lib::db::{now_millis, Update};
+    use bytes::Bytes;
+
+    #[test]
+    fn expired_keys_are_not_counted() {
+        let db = Db::new(Config::default());
+        db.set_active_expire(false);
+        db.set("live".to_string(), Bytes::from("value").into());
+        db.update("expired".to_string(), |_| {
+            let update = Update::Set {
+                value: Bytes::from("value").into(),
+                expires_at: Some(now_millis() - 1000),
+            };
+            (update, ())
+        });
+        assert!(matches!(DbSize.apply(&db), Frame::Integer(1)));
+        db.remove("live");
+        assert!(matches!(DbSize.apply(&db), Frame::Integer(0)));
+    }
+}
@@ -0,0 +1,260 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::value::{dump, Value};
+use std::time::Duration;
+
+///供测试与故障注入使用的调试命令
+///
+/// DEBUG SLEEP seconds | DEBUG OBJECT key | DEBUG SET-ACTIVE-EXPIRE 0|1 | DEBUG QUICKACK 0|1 |
+/// DEBUG CHANGE-REPL-ID | DEBUG JMAP | DEBUG SET-MEMORY-USAGE bytes | DEBUG OBJECT-ENCODING key encoding
+///
+/// SLEEP与redis一样在指定的秒数内独占整个键空间，其他连接的键操作都要等它结束，秒数可以是小数；OBJECT回复键的内部信息，键不存在时回复错误，
+/// quicklist编码的列表按redis的节点大小换算出节点数，并按list-compress-depth报告是否压缩；
+/// SET-ACTIVE-EXPIRE开启或关闭主动过期；CHANGE-REPL-ID换用新的复制id，之后从节点只能全量同步。
+/// QUICKACK与JMAP只为兼容依赖它们的测试而接受，不做任何事。
+/// SET-MEMORY-USAGE修改报告的内存占用，用于测试淘汰；OBJECT-ENCODING把小集合换成普通的编码，
+/// 不必写入大量元素就能测试依赖编码的代码。这两个子命令只在测试与开启debug-commands特性时可用
+#[derive(Debug)]
+pub struct DebugCommand {
+    op: Op,
+}
+
+///DEBUG的子命令
+#[derive(Debug)]
+enum Op {
+    Sleep(Duration),
+    Object(String),
+    SetActiveExpire(bool),
+    ChangeReplId,
+    #[cfg(any(test, feature = "debug-commands"))]
+    SetMemoryUsage(usize),
+    #[cfg(any(test, feature = "debug-commands"))]
+    ObjectEncoding(String, String),
+    ///不做任何事，直接回复OK
+    Noop,
+}
+
+impl DebugCommand {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<DebugCommand, ParseError> {
+        let sub = parse.next_string()?.to_uppercase();
+        let op = match sub.as_str() {
+            "SLEEP" => {
+                let seconds = parse
+                    .next_string()?
+                    .parse::<f64>()
+                    .map_err(|_| "value is not a valid float")?;
+                let duration =
+                    Duration::try_from_secs_f64(seconds).map_err(|_| "value is out of range")?;
+                Op::Sleep(duration)
+            }
+            "OBJECT" => Op::Object(parse.next_string()?),
+            "SET-ACTIVE-EXPIRE" => Op::SetActiveExpire(parse_flag(parse)?),
+            "QUICKACK" => {
+                parse_flag(parse)?;
+                Op::Noop
+            }
+            "CHANGE-REPL-ID" => Op::ChangeReplId,
+            "JMAP" => Op::Noop,
+            #[cfg(any(test, feature = "debug-commands"))]
+            "SET-MEMORY-USAGE" => {
+                let bytes = parse.next_int()?;
+                Op::SetMemoryUsage(usize::try_from(bytes).map_err(|_| "value is out of range")?)
+            }
+            #[cfg(any(test, feature = "debug-commands"))]
+            "OBJECT-ENCODING" => {
+                let key = parse.next_string()?;
+                Op::ObjectEncoding(key, parse.next_string()?.to_lowercase())
+            }
+            _ => {
+                let err = format!("unknown subcommand '{}'. Try DEBUG HELP.", sub);
+                return Err(err.into());
+            }
+        };
+        Ok(DebugCommand { op })
+    }
+
+    ///SLEEP在阻塞线程上持有键空间的独占锁，不占用运行时的工作线程
+    pub(crate) async fn apply(self, db: &Db) -> Frame {
+        match self.op {
+            Op::Sleep(duration) => {
+                let db = db.clone();
+                let _ = tokio::task::spawn_blocking(move || sleep(&db, duration)).await;
+                Frame::Simple("OK".to_string())
+            }
+            _ => self.apply_now(db),
+        }
+    }
+
+    ///在事务或脚本中执行时，SLEEP在当前线程上独占键空间，效果与单独执行相同
+    pub(crate) fn apply_now(self, db: &Db) -> Frame {
+        match self.op {
+            Op::Sleep(duration) => sleep(db, duration),
+            Op::Object(key) => {
+                let depth = db.config().list_compress_depth();
+                let info = db.read(&key, |entry| {
+                    let mut info = format!(
+                        "Value at:{:p} refcount:1 encoding:{} serializedlength:{}",
+                        entry,
+                        entry.value.encoding(),
+                        dump::serialize(&entry.value).len()
+                    );
+                    if let Value::List(list) = &entry.value {
+                        if list.encoding() == "quicklist" {
+                            info.push_str(&quicklist(list.iter().map(|value| value.len()), depth));
+                        }
+                    }
+                    info
+                });
+                return match info {
+                    Some(info) => Frame::Simple(info),
+                    None => Frame::Error("ERR no such key".to_string()),
+                };
+            }
+            Op::SetActiveExpire(enabled) => db.set_active_expire(enabled),
+            Op::ChangeReplId => {
+                db.replication().change_replid();
+                tracing::info!("复制id已更换为{}", db.replication().replid());
+            }
+            #[cfg(any(test, feature = "debug-commands"))]
+            Op::SetMemoryUsage(bytes) => db.set_memory_usage(bytes),
+            #[cfg(any(test, feature = "debug-commands"))]
+            Op::ObjectEncoding(key, encoding) => {
+                return db.mutate(key, |value| match value {
+                    Some(value) => {
+                        if value.set_encoding(&encoding) {
+                            (true, Frame::Simple("OK".to_string()))
+                        } else {
+                            let err =
+                                format!("ERR cannot convert the value to encoding '{}'", encoding);
+                            (false, Frame::Error(err))
+                        }
+                    }
+                    None => (false, Frame::Error("ERR no such key".to_string())),
+                });
+            }
+            Op::Noop => {}
+        }
+        Frame::Simple("OK".to_string())
+    }
+}
+
+//独占键空间等待，在EXEC或脚本中已经独占时直接等待
+fn sleep(db: &Db, duration: Duration) {
+    db.atomically(|| std::thread::sleep(duration));
+}
+
+//redis中quicklist每个节点的大小上限，对应list-max-listpack-size的默认值-2
+const QUICKLIST_NODE_SIZE: usize = 8 * 1024;
+
+//DEBUG OBJECT中quicklist的部分，sizes是各个元素的长度
+//
+// 节点数按每个节点装满QUICKLIST_NODE_SIZE换算。与redis一致，ql_compressed表示是否配置了压缩，
+// 两端各depth个节点之外的节点才会被压缩，节点数不超过2*depth时实际上没有节点被压缩
+fn quicklist(sizes: impl Iterator<Item = usize>, depth: usize) -> String {
+    let (count, size) = sizes.fold((0usize, 0usize), |(count, size), len| {
+        (count + 1, size + len)
+    });
+    let nodes = size.div_ceil(QUICKLIST_NODE_SIZE).max(1);
+    format!(
+        " ql_nodes:{} ql_avg_node:{:.2} ql_listpack_max:-2 ql_compressed:{} ql_compress_depth:{} ql_uncompressed_size:{}",
+        nodes,
+        count as f64 / nodes as f64,
+        (depth > 0) as u8,
+        depth,
+        size
+    )
+}
+
+//解析取值为0或1的开关
+fn parse_flag(parse: &mut Parse) -> Result<bool, ParseError> {
+    match parse.next_int()? {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err("value must be 0 or 1".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lib::cmd::Command;
+    use crate::lib::config::Config;
+    use crate::lib::db::Db;
+    use crate::lib::frame::Frame;
+    use bytes::Bytes;
+
+    fn run(db: &Db, args: &[&str]) -> Frame {
+        let frame = Frame::Array(
+            args.iter()
+                .map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes())))
+                .collect(),
+        );
+        Command::from_frame(frame).unwrap().apply_now(db)
+    }
+
+    #[test]
+    fn fake_memory_usage_triggers_eviction() {
+        let mut config = Config::default();
+        config.set("maxmemory", "1mb").unwrap();
+        config.set("maxmemory-policy", "allkeys-random").unwrap();
+        let db = Db::new(config);
+        for i in 0..100 {
+            run(&db, &["SET", &format!("key:{}", i), "value"]);
+        }
+        assert!(db.evict());
+        assert_eq!(db.key_count(), 100);
+
+        let usage = (1024 * 1024 + 1).to_string();
+        assert!(run(&db, &["DEBUG", "SET-MEMORY-USAGE", &usage]) == "OK");
+        assert_eq!(db.used_memory(), 1024 * 1024 + 1);
+        //写命令执行之前先淘汰
+        assert!(db.evict());
+        let evicted = 100 - db.key_count();
+        assert!(evicted > 0 && evicted < 100);
+        assert!(db.used_memory() <= 1024 * 1024);
+        run(&db, &["SET", "key", "value"]);
+
+        run(&db, &["DEBUG", "SET-MEMORY-USAGE", "0"]);
+        assert!(db.used_memory() < 1024 * 1024);
+    }
+
+    #[test]
+    fn force_object_encoding() {
+        let db = Db::new(Config::default());
+        run(&db, &["ZADD", "zset", "1", "a", "2", "b"]);
+        assert!(run(&db, &["OBJECT", "ENCODING", "zset"]) == "listpack");
+        let reply = run(&db, &["DEBUG", "OBJECT-ENCODING", "zset", "skiplist"]);
+        assert!(reply == "OK");
+        assert!(run(&db, &["OBJECT", "ENCODING", "zset"]) == "skiplist");
+        let reply = run(&db, &["ZSCORE", "zset", "b"]);
+        assert!(matches!(reply, Frame::Double(score) if score == 2.0));
+
+        run(&db, &["SET", "string", "value"]);
+        let reply = run(&db, &["DEBUG", "OBJECT-ENCODING", "string", "skiplist"]);
+        assert!(matches!(reply, Frame::Error(_)));
+        let reply = run(&db, &["DEBUG", "OBJECT-ENCODING", "missing", "skiplist"]);
+        assert!(matches!(reply, Frame::Error(_)));
+    }
+
+    #[test]
+    fn object_reports_list_compress_depth() {
+        let mut config = Config::default();
+        config.set("list-compress-depth", "2").unwrap();
+        let db = Db::new(config);
+        let values: Vec<String> = (0..1000).map(|i| format!("value:{}", i)).collect();
+        let mut args = vec!["RPUSH", "list"];
+        args.extend(values.iter().map(String::as_str));
+        run(&db, &args);
+        let info = match run(&db, &["DEBUG", "OBJECT", "list"]) {
+            Frame::Simple(info) => info,
+            frame => panic!("unexpected reply {:?}", frame),
+        };
+        assert!(info.contains("encoding:quicklist"));
+        assert!(info.contains("ql_compressed:1 ql_compress_depth:2"));
+        assert!(info.contains("ql_nodes:2"));
+
+        run(&db, &["RPUSH", "short", "a"]);
+        let reply = run(&db, &["DEBUG", "OBJECT", "short"]);
+        assert!(matches!(reply, Frame::Simple(info) if !info.contains("ql_")));
+    }
+}
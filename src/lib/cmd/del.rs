@@ -0,0 +1,46 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::notify::Class;
+use crate::lib::parse::{Parse, ParseError};
+
+///删除键
+///
+/// DEL key [key ...] | UNLINK key [key ...]
+///
+/// UNLINK立即将键从键空间中移除，值的释放交给后台线程，避免删除大对象时阻塞当前连接
+#[derive(Debug)]
+pub struct Del {
+    keys: Vec<String>,
+    //是否在后台释放值
+    unlink: bool,
+}
+
+impl Del {
+    pub(crate) fn parse_frames(parse: &mut Parse, unlink: bool) -> Result<Del, ParseError> {
+        let mut keys = vec![parse.next_string()?];
+        loop {
+            match parse.next_string() {
+                Ok(key) => keys.push(key),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(Del { keys, unlink })
+    }
+
+    ///回复实际删除的键的数量
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let mut removed = Vec::with_capacity(self.keys.len());
+        for key in &self.keys {
+            if let Some(entry) = db.remove(key) {
+                db.notify(Class::Generic, "del", key);
+                removed.push(entry);
+            }
+        }
+        let count = removed.len() as i64;
+        if self.unlink && !removed.is_empty() {
+            tokio::task::spawn_blocking(move || drop(removed));
+        }
+        Frame::Integer(count)
+    }
+}
@@ -0,0 +1,29 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::value::dump;
+
+///将键的值序列化
+///
+/// DUMP key
+///
+/// 序列化的结果带有版本号与校验和，可以通过RESTORE写回，过期时间不包含在其中
+#[derive(Debug)]
+pub struct Dump {
+    key: String,
+}
+
+impl Dump {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Dump, ParseError> {
+        let key = parse.next_string()?;
+        Ok(Dump { key })
+    }
+
+    ///回复序列化的值，键不存在时回复Null
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.read(&self.key, |entry| dump::serialize(&entry.value)) {
+            Some(data) => Frame::Bulk(data),
+            None => Frame::Null,
+        }
+    }
+}
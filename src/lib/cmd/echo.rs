@@ -0,0 +1,54 @@
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use bytes::Bytes;
+
+///原样回复消息
+///
+/// ECHO message
+#[derive(Debug)]
+pub struct Echo {
+    message: Bytes,
+}
+
+impl Echo {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Echo, ParseError> {
+        let message = parse.next_bytes()?;
+        Ok(Echo { message })
+    }
+
+    pub(crate) fn apply(self) -> Frame {
+        Frame::Bulk(self.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lib::cmd::Command;
+    use crate::lib::config::Config;
+    use crate::lib::db::Db;
+    use crate::lib::frame::Frame;
+    use bytes::Bytes;
+
+    fn run(db: &Db, args: &[&str]) -> Frame {
+        let frame = Frame::Array(
+            args.iter()
+                .map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes())))
+                .collect(),
+        );
+        match Command::from_frame(frame) {
+            Ok(cmd) => cmd.apply_now(db),
+            Err(e) => e.into(),
+        }
+    }
+
+    #[test]
+    fn echo_replies_the_message() {
+        let db = Db::new(Config::default());
+        assert!(matches!(run(&db, &["ECHO", "hello"]), Frame::Bulk(b) if b == "hello"));
+        assert!(matches!(run(&db, &["ECHO", ""]), Frame::Bulk(b) if b.is_empty()));
+        assert!(matches!(
+            run(&db, &["ECHO"]),
+            Frame::Error(e) if e.contains("wrong number of arguments")
+        ));
+    }
+}
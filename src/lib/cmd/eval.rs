@@ -0,0 +1,147 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::lua;
+use crate::lib::parse::{Parse, ParseError};
+use bytes::Bytes;
+
+///执行Lua脚本
+///
+/// EVAL script numkeys [key [key ...]] [arg [arg ...]]
+///
+/// EVALSHA sha1 numkeys [key [key ...]] [arg [arg ...]]
+///
+/// 脚本中通过KEYS与ARGV读取传入的键与参数，通过redis.call与redis.pcall执行命令。
+/// EVAL执行过的脚本会被缓存，之后可以用EVALSHA按SHA1摘要执行
+#[derive(Debug)]
+pub struct Eval {
+    source: Source,
+    keys: Vec<Bytes>,
+    args: Vec<Bytes>,
+    //调用脚本的ACL用户，为None时脚本中的命令不做权限检查，例如复制与AOF重放
+    user: Option<String>,
+}
+
+//脚本的来源
+#[derive(Debug)]
+enum Source {
+    //脚本本身
+    Script(Bytes),
+    //已缓存脚本的SHA1摘要
+    Sha(String),
+}
+
+impl Eval {
+    ///sha为true时解析EVALSHA
+    pub(crate) fn parse_frames(parse: &mut Parse, sha: bool) -> Result<Eval, ParseError> {
+        let source = if sha {
+            Source::Sha(parse.next_string()?)
+        } else {
+            Source::Script(parse.next_bytes()?)
+        };
+        let numkeys = parse.next_int()?;
+        if numkeys < 0 {
+            return Err("Number of keys can't be negative".into());
+        }
+        let mut args = vec![];
+        loop {
+            match parse.next_bytes() {
+                Ok(arg) => args.push(arg),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        if numkeys as usize > args.len() {
+            return Err("Number of keys can't be greater than number of args".into());
+        }
+        let keys = args.drain(..numkeys as usize).collect();
+        Ok(Eval {
+            source,
+            keys,
+            args,
+            user: None,
+        })
+    }
+
+    ///脚本中调用的命令按user的命令、键与频道权限检查
+    pub(crate) fn with_user(mut self, user: Option<&str>) -> Eval {
+        self.user = user.map(str::to_string);
+        self
+    }
+
+    ///回复脚本的返回值，EVALSHA的脚本不在缓存中时回复NOSCRIPT错误
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let script = match self.source {
+            Source::Script(script) => {
+                db.load_script(script.clone());
+                script
+            }
+            Source::Sha(sha) => match db.script(&sha) {
+                Some(script) => script,
+                None => {
+                    return Frame::Error(
+                        "NOSCRIPT No matching script. Please use EVAL.".to_string(),
+                    )
+                }
+            },
+        };
+        lua::eval(db, self.user.as_deref(), &script, self.keys, self.args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lib::cmd::Command;
+    use crate::lib::config::Config;
+    use crate::lib::db::Db;
+    use crate::lib::frame::Frame;
+    use bytes::Bytes;
+
+    fn command(args: &[&str]) -> Frame {
+        Frame::Array(
+            args.iter()
+                .map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes())))
+                .collect(),
+        )
+    }
+
+    fn call(db: &Db, args: &[&str]) -> Frame {
+        Command::from_frame(command(args)).unwrap().apply_now(db)
+    }
+
+    #[test]
+    fn failing_scripts_reply_with_errors() {
+        let db = Db::new(Config::default());
+        let errors = [
+            //语法错误
+            "return (",
+            //运行时错误
+            "return redis.call('NOSUCHCOMMAND')",
+            "error('boom')",
+        ];
+        for script in errors {
+            assert!(
+                matches!(call(&db, &["EVAL", script, "0"]), Frame::Error(_)),
+                "{}",
+                script
+            );
+        }
+        assert!(matches!(
+            call(&db, &["EVAL", "return redis.error_reply('MY failure')", "0"]),
+            Frame::Error(e) if e == "MY failure"
+        ));
+        //出错之后仍然可以执行脚本
+        assert_eq!(call(&db, &["EVAL", "return 1", "0"]), Frame::Integer(1));
+    }
+
+    #[test]
+    fn unknown_sha_and_bad_numkeys() {
+        let db = Db::new(Config::default());
+        let sha = "0123456789012345678901234567890123456789";
+        assert!(matches!(
+            call(&db, &["EVALSHA", sha, "0"]),
+            Frame::Error(e) if e.starts_with("NOSCRIPT")
+        ));
+        assert!(Command::from_frame(command(&["EVAL", "return 1", "-1"])).is_err());
+        assert!(Command::from_frame(command(&["EVAL", "return 1", "2", "key"])).is_err());
+    }
+}
@@ -0,0 +1,31 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+
+///判断键是否存在
+///
+/// EXISTS key [key ...]
+#[derive(Debug)]
+pub struct Exists {
+    keys: Vec<String>,
+}
+
+impl Exists {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Exists, ParseError> {
+        let mut keys = vec![parse.next_string()?];
+        loop {
+            match parse.next_string() {
+                Ok(key) => keys.push(key),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(Exists { keys })
+    }
+
+    ///回复存在的键的数量，重复的键会被重复计数
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let count = self.keys.iter().filter(|key| db.exists(key)).count();
+        Frame::Integer(count as i64)
+    }
+}
@@ -0,0 +1,62 @@
+use crate::lib::db::{now_millis, Db, Update};
+use crate::lib::frame::Frame;
+use crate::lib::notify::{Class, Event};
+use crate::lib::parse::{Parse, ParseError};
+
+///设置键的过期时间
+///
+/// EXPIRE key seconds | PEXPIRE key milliseconds |
+/// EXPIREAT key unix-time-seconds | PEXPIREAT key unix-time-milliseconds
+#[derive(Debug)]
+pub struct Expire {
+    key: String,
+    time: i64,
+    //命令名称，决定时间的单位以及是否为绝对时间
+    name: &'static str,
+}
+
+impl Expire {
+    pub(crate) fn parse_frames(
+        parse: &mut Parse,
+        name: &'static str,
+    ) -> Result<Expire, ParseError> {
+        let key = parse.next_string()?;
+        let time = parse.next_int()?;
+        Ok(Expire { key, time, name })
+    }
+
+    ///设置成功回复1，键不存在回复0。过期时间已经过去时直接删除该键
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let now = now_millis();
+        let at = match self.deadline(now) {
+            Some(at) => at,
+            None => {
+                return Frame::Error(format!(
+                    "ERR invalid expire time in '{}' command",
+                    self.name
+                ))
+            }
+        };
+        db.update_notify(self.key, Event::new(Class::Generic, "expire"), |cur| {
+            if cur.is_none() {
+                (Update::Keep, Frame::Integer(0))
+            } else if at <= now as i64 {
+                (Update::Remove, Frame::Integer(1))
+            } else {
+                (Update::SetExpire(Some(at as u64)), Frame::Integer(1))
+            }
+        })
+    }
+
+    //计算过期的unix时间戳（毫秒），溢出时返回None
+    fn deadline(&self, now: u64) -> Option<i64> {
+        let millis = match self.name {
+            "expire" | "expireat" => self.time.checked_mul(1000)?,
+            _ => self.time,
+        };
+        match self.name {
+            "expireat" | "pexpireat" => Some(millis),
+            _ => millis.checked_add(now as i64),
+        }
+    }
+}
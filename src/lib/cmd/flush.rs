@@ -0,0 +1,41 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+
+///清空键空间
+///
+/// FLUSHDB [ASYNC|SYNC] | FLUSHALL [ASYNC|SYNC]
+///
+/// FLUSHDB只清空当前的逻辑数据库，FLUSHALL清空所有数据库。
+/// ASYNC时键空间会被立即清空，条目的释放交给后台线程
+#[derive(Debug)]
+pub struct Flush {
+    //是否清空所有逻辑数据库
+    all: bool,
+    //是否在后台释放条目
+    lazy: bool,
+}
+
+impl Flush {
+    pub(crate) fn parse_frames(parse: &mut Parse, all: bool) -> Result<Flush, ParseError> {
+        let lazy = match parse.next_string() {
+            Ok(mode) => match mode.to_uppercase().as_str() {
+                "ASYNC" => true,
+                "SYNC" => false,
+                _ => return Err("syntax error".into()),
+            },
+            Err(ParseError::EndOfStream) => false,
+            Err(e) => return Err(e),
+        };
+        Ok(Flush { all, lazy })
+    }
+
+    ///回复OK
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let removed = if self.all { db.flush_all() } else { db.flush() };
+        if self.lazy {
+            tokio::task::spawn_blocking(move || drop(removed));
+        }
+        Frame::Simple("OK".to_string())
+    }
+}
@@ -0,0 +1,116 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::geo;
+use crate::lib::notify::{Class, Event};
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::value::zset::ZSet;
+use crate::lib::value::Value;
+use bytes::Bytes;
+
+///向地理位置索引中添加成员
+///
+/// GEOADD key [NX | XX] [CH] longitude latitude member [longitude latitude member ...]
+///
+/// 索引就是以geohash为分数的有序集合，可以用有序集合的命令读取或删除其中的成员
+#[derive(Debug)]
+pub struct GeoAdd {
+    key: String,
+    //成员与对应的geohash
+    items: Vec<(Bytes, u64)>,
+    //只添加新成员
+    nx: bool,
+    //只更新已有成员
+    xx: bool,
+    //回复中同时计入位置被修改的成员
+    ch: bool,
+}
+
+impl GeoAdd {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<GeoAdd, ParseError> {
+        let key = parse.next_string()?;
+        let mut geoadd = GeoAdd {
+            key,
+            items: vec![],
+            nx: false,
+            xx: false,
+            ch: false,
+        };
+        let mut args = vec![];
+        loop {
+            match parse.next_bytes() {
+                Ok(arg) => args.push(arg),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        //选项都在第一个经度之前
+        let mut rest = args.as_slice();
+        while let Some(arg) = rest.first() {
+            match String::from_utf8_lossy(arg).to_uppercase().as_str() {
+                "NX" => geoadd.nx = true,
+                "XX" => geoadd.xx = true,
+                "CH" => geoadd.ch = true,
+                _ => break,
+            }
+            rest = &rest[1..];
+        }
+        if rest.is_empty() || rest.len() % 3 != 0 {
+            return Err(
+                "syntax error. Try GEOADD key [x1] [y1] [name1] [x2] [y2] [name2] ... ".into(),
+            );
+        }
+        if geoadd.nx && geoadd.xx {
+            return Err("XX and NX options at the same time are not compatible".into());
+        }
+        for triple in rest.chunks_exact(3) {
+            let lon = coordinate(&triple[0])?;
+            let lat = coordinate(&triple[1])?;
+            let hash = geo::encode(lon, lat).ok_or_else(|| geo::invalid_pair(lon, lat))?;
+            geoadd.items.push((triple[2].clone(), hash));
+        }
+        Ok(geoadd)
+    }
+
+    ///回复新增的成员数，带CH时同时计入位置被修改的成员
+    ///
+    /// 与redis一致，发出的键空间事件是zadd
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let GeoAdd {
+            key,
+            items,
+            nx,
+            xx,
+            ch,
+        } = self;
+        db.mutate_notify(key, Event::new(Class::ZSet, "zadd"), |slot| {
+            let zset = slot.get_or_insert_with(|| Value::ZSet(ZSet::default()));
+            let zset = match zset.as_zset_mut() {
+                Ok(zset) => zset,
+                Err(e) => return (false, e.into()),
+            };
+            let (mut added, mut updated) = (0, 0);
+            for (member, hash) in items {
+                let score = hash as f64;
+                match zset.score(&member) {
+                    Some(_) if nx => continue,
+                    None if xx => continue,
+                    Some(old) if old == score => continue,
+                    Some(_) => updated += 1,
+                    None => added += 1,
+                }
+                zset.insert(member, score);
+            }
+            let reply = if ch { added + updated } else { added };
+            (added + updated > 0, Frame::Integer(reply))
+        })
+    }
+}
+
+///解析经度或纬度
+pub(super) fn coordinate(arg: &[u8]) -> Result<f64, ParseError> {
+    std::str::from_utf8(arg)
+        .ok()
+        .and_then(|arg| arg.parse::<f64>().ok())
+        .filter(|value| value.is_finite())
+        .ok_or_else(|| "value is not a valid float".into())
+}
@@ -0,0 +1,55 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::geo;
+use crate::lib::parse::{Parse, ParseError};
+use bytes::Bytes;
+
+///计算地理位置索引中两个成员之间的距离
+///
+/// GEODIST key member1 member2 [M | KM | FT | MI]
+#[derive(Debug)]
+pub struct GeoDist {
+    key: String,
+    members: (Bytes, Bytes),
+    //单位换算为米的倍数
+    unit: f64,
+}
+
+impl GeoDist {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<GeoDist, ParseError> {
+        let key = parse.next_string()?;
+        let members = (parse.next_bytes()?, parse.next_bytes()?);
+        let unit = match parse.next_optional_string()? {
+            Some(unit) => geo::unit(&unit).ok_or(UNSUPPORTED_UNIT)?,
+            None => 1.0,
+        };
+        Ok(GeoDist { key, members, unit })
+    }
+
+    ///回复保留4位小数的距离，任一成员不存在时回复Null
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let (first, second) = &self.members;
+        let scores = db.read(&self.key, |entry| {
+            let zset = entry.value.as_zset();
+            zset.map(|zset| zset.score(first).zip(zset.score(second)))
+        });
+        match scores {
+            Some(Ok(Some((a, b)))) => {
+                let (lon1, lat1) = geo::decode(a as u64);
+                let (lon2, lat2) = geo::decode(b as u64);
+                let dist = geo::distance(lon1, lat1, lon2, lat2) / self.unit;
+                Frame::Bulk(format_distance(dist))
+            }
+            Some(Err(e)) => e.into(),
+            _ => Frame::Null,
+        }
+    }
+}
+
+///单位不合法时回复的错误
+pub(super) const UNSUPPORTED_UNIT: &str = "unsupported unit provided. please use M, KM, FT, MI";
+
+///将距离格式化为保留4位小数的字符串
+pub(super) fn format_distance(dist: f64) -> Bytes {
+    Bytes::from(format!("{:.4}", dist))
+}
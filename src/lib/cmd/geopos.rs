@@ -0,0 +1,56 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::geo;
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::value::zset::format_score;
+use bytes::Bytes;
+
+///读取地理位置索引中成员的经纬度
+///
+/// GEOPOS key [member [member ...]]
+#[derive(Debug)]
+pub struct GeoPos {
+    key: String,
+    members: Vec<Bytes>,
+}
+
+impl GeoPos {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<GeoPos, ParseError> {
+        let key = parse.next_string()?;
+        let mut members = vec![];
+        while let Some(member) = parse.next_optional_bytes()? {
+            members.push(member);
+        }
+        Ok(GeoPos { key, members })
+    }
+
+    ///按顺序回复每个成员的经度与纬度，不存在的成员回复Null
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let members = &self.members;
+        let scores = db.read(&self.key, |entry| {
+            let zset = entry.value.as_zset();
+            zset.map(|zset| members.iter().map(|member| zset.score(member)).collect())
+        });
+        let scores: Vec<Option<f64>> = match scores {
+            Some(Ok(scores)) => scores,
+            Some(Err(e)) => return e.into(),
+            None => vec![None; members.len()],
+        };
+        let positions = scores
+            .into_iter()
+            .map(|score| match score {
+                Some(score) => position(geo::decode(score as u64)),
+                None => Frame::Null,
+            })
+            .collect();
+        Frame::Array(positions)
+    }
+}
+
+///经纬度的回复，经度在前
+pub(super) fn position((lon, lat): (f64, f64)) -> Frame {
+    Frame::Array(vec![
+        Frame::Bulk(format_score(lon)),
+        Frame::Bulk(format_score(lat)),
+    ])
+}
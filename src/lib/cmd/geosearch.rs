@@ -0,0 +1,231 @@
+use crate::lib::cmd::geoadd::coordinate;
+use crate::lib::cmd::geodist::{format_distance, UNSUPPORTED_UNIT};
+use crate::lib::cmd::geopos::position;
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::geo;
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::value::zset::ZSet;
+use bytes::Bytes;
+
+///在地理位置索引中查找圆形或矩形区域内的成员
+///
+/// GEOSEARCH key FROMMEMBER member | FROMLONLAT longitude latitude
+/// BYRADIUS radius M | KM | FT | MI | BYBOX width height M | KM | FT | MI
+/// [ASC | DESC] [COUNT count [ANY]] [WITHCOORD] [WITHDIST] [WITHHASH]
+///
+/// 逐个解码索引中成员的位置判断是否在区域内。带COUNT ANY时找到足够的成员就停止，
+/// 只带COUNT时先按距离升序排列再截取，保证返回的是最近的成员
+#[derive(Debug)]
+pub struct GeoSearch {
+    key: String,
+    origin: Origin,
+    shape: Shape,
+    //单位换算为米的倍数，回复中的距离使用同一单位
+    unit: f64,
+    //None为不排序，Some(true)为按距离降序
+    desc: Option<bool>,
+    count: Option<usize>,
+    any: bool,
+    with_coord: bool,
+    with_dist: bool,
+    with_hash: bool,
+}
+
+//查找区域的中心
+#[derive(Debug)]
+enum Origin {
+    Member(Bytes),
+    LonLat(f64, f64),
+}
+
+//查找区域的形状，长度的单位为米
+#[derive(Debug)]
+enum Shape {
+    Radius(f64),
+    Box { width: f64, height: f64 },
+}
+
+//区域内的成员
+struct Found {
+    member: Bytes,
+    //到中心的距离，单位为米
+    dist: f64,
+    hash: u64,
+}
+
+impl GeoSearch {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<GeoSearch, ParseError> {
+        let key = parse.next_string()?;
+        let (mut origin, mut shape, mut unit) = (None, None, 1.0);
+        let (mut desc, mut count, mut any) = (None, None, false);
+        let (mut with_coord, mut with_dist, mut with_hash) = (false, false, false);
+        loop {
+            let option = match parse.next_string() {
+                Ok(option) => option,
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e),
+            };
+            match option.to_uppercase().as_str() {
+                "FROMMEMBER" if origin.is_none() => {
+                    origin = Some(Origin::Member(parse.next_bytes()?));
+                }
+                "FROMLONLAT" if origin.is_none() => {
+                    let lon = coordinate(&parse.next_bytes()?)?;
+                    let lat = coordinate(&parse.next_bytes()?)?;
+                    if geo::encode(lon, lat).is_none() {
+                        return Err(geo::invalid_pair(lon, lat).into());
+                    }
+                    origin = Some(Origin::LonLat(lon, lat));
+                }
+                "FROMMEMBER" | "FROMLONLAT" => return Err(EXACTLY_ONE_ORIGIN.into()),
+                "BYRADIUS" if shape.is_none() => {
+                    let radius = length(parse)?;
+                    unit = parse_unit(parse)?;
+                    shape = Some(Shape::Radius(radius * unit));
+                }
+                "BYBOX" if shape.is_none() => {
+                    let (width, height) = (length(parse)?, length(parse)?);
+                    unit = parse_unit(parse)?;
+                    shape = Some(Shape::Box {
+                        width: width * unit,
+                        height: height * unit,
+                    });
+                }
+                "BYRADIUS" | "BYBOX" => return Err(EXACTLY_ONE_SHAPE.into()),
+                "ASC" => desc = Some(false),
+                "DESC" => desc = Some(true),
+                "COUNT" => {
+                    let n = parse.next_int()?;
+                    if n <= 0 {
+                        return Err("COUNT must be > 0".into());
+                    }
+                    count = Some(n as usize);
+                }
+                "ANY" => any = true,
+                "WITHCOORD" => with_coord = true,
+                "WITHDIST" => with_dist = true,
+                "WITHHASH" => with_hash = true,
+                _ => return Err("syntax error".into()),
+            }
+        }
+        let origin = origin.ok_or(EXACTLY_ONE_ORIGIN)?;
+        let shape = shape.ok_or(EXACTLY_ONE_SHAPE)?;
+        if any && count.is_none() {
+            return Err("the ANY argument requires COUNT argument".into());
+        }
+        //只带COUNT时需要先排序才能取到最近的成员
+        if count.is_some() && !any && desc.is_none() {
+            desc = Some(false);
+        }
+        Ok(GeoSearch {
+            key,
+            origin,
+            shape,
+            unit,
+            desc,
+            count,
+            any,
+            with_coord,
+            with_dist,
+            with_hash,
+        })
+    }
+
+    ///回复区域内的成员，带WITH选项时每个成员连同距离、geohash与经纬度按此顺序回复
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let found = db.read(&self.key, |entry| match entry.value.as_zset() {
+            Ok(zset) => self.search(zset),
+            Err(e) => Err(e.into()),
+        });
+        let found = match found {
+            Some(Ok(found)) => found,
+            Some(Err(e)) => return e,
+            None => vec![],
+        };
+        let with = self.with_coord || self.with_dist || self.with_hash;
+        let items = found
+            .into_iter()
+            .map(|found| {
+                if !with {
+                    return Frame::Bulk(found.member);
+                }
+                let mut item = vec![Frame::Bulk(found.member)];
+                if self.with_dist {
+                    item.push(Frame::Bulk(format_distance(found.dist / self.unit)));
+                }
+                if self.with_hash {
+                    item.push(Frame::Integer(found.hash as i64));
+                }
+                if self.with_coord {
+                    item.push(position(geo::decode(found.hash)));
+                }
+                Frame::Array(item)
+            })
+            .collect();
+        Frame::Array(items)
+    }
+
+    //找出区域内的成员并按要求排序、截取
+    fn search(&self, zset: &ZSet) -> Result<Vec<Found>, Frame> {
+        let center = match &self.origin {
+            Origin::LonLat(lon, lat) => (*lon, *lat),
+            Origin::Member(member) => match zset.score(member) {
+                Some(score) => geo::decode(score as u64),
+                None => {
+                    let err = "ERR could not decode requested zset member";
+                    return Err(Frame::Error(err.to_string()));
+                }
+            },
+        };
+        let mut found = vec![];
+        for (member, score) in zset.iter() {
+            let hash = score as u64;
+            let point = geo::decode(hash);
+            let dist = match self.shape {
+                Shape::Radius(radius) => {
+                    let dist = geo::distance(center.0, center.1, point.0, point.1);
+                    Some(dist).filter(|dist| *dist <= radius)
+                }
+                Shape::Box { width, height } => geo::distance_in_box(center, point, width, height),
+            };
+            if let Some(dist) = dist {
+                found.push(Found { member, dist, hash });
+                if self.any && Some(found.len()) == self.count {
+                    break;
+                }
+            }
+        }
+        if let Some(desc) = self.desc {
+            found.sort_by(|a, b| a.dist.total_cmp(&b.dist));
+            if desc {
+                found.reverse();
+            }
+        }
+        if let Some(count) = self.count {
+            found.truncate(count);
+        }
+        Ok(found)
+    }
+}
+
+//同时给出或都没有给出FROMMEMBER与FROMLONLAT时回复的错误
+const EXACTLY_ONE_ORIGIN: &str =
+    "exactly one of FROMMEMBER or FROMLONLAT can be specified for GEOSEARCH";
+//同时给出或都没有给出BYRADIUS与BYBOX时回复的错误
+const EXACTLY_ONE_SHAPE: &str = "exactly one of BYRADIUS and BYBOX can be specified for GEOSEARCH";
+
+//解析半径、宽或高，不能为负数
+fn length(parse: &mut Parse) -> Result<f64, ParseError> {
+    let value = coordinate(&parse.next_bytes()?)?;
+    if value < 0.0 {
+        return Err("radius cannot be negative".into());
+    }
+    Ok(value)
+}
+
+//解析距离单位
+fn parse_unit(parse: &mut Parse) -> Result<f64, ParseError> {
+    let unit = parse.next_string()?;
+    geo::unit(&unit).ok_or_else(|| UNSUPPORTED_UNIT.into())
+}
@@ -1,4 +1,87 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+
+///获取键对应的值
+///
+/// GET key
 #[derive(Debug)]
-pub struct Get{
-    key:String
-}
\ No newline at end of file
+pub struct Get {
+    key: String,
+}
+
+impl Get {
+    ///获取键
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Get, ParseError> {
+        let key = parse.next_string()?;
+        Ok(Get { key })
+    }
+
+    ///键存储的不是字符串时回复WRONGTYPE错误
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let value = match db.get(&self.key) {
+            Ok(value) => value,
+            Err(e) => {
+                db.record_lookup(true);
+                return e.into();
+            }
+        };
+        db.record_lookup(value.is_some());
+        match value {
+            None => Frame::Null,
+            Some(value) => Frame::Bulk(value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lib::cmd::Command;
+    use crate::lib::config::Config;
+    use crate::lib::db::Db;
+    use crate::lib::frame::Frame;
+    use bytes::Bytes;
+
+    fn call(db: &Db, args: &[&str]) -> Frame {
+        let frame = Frame::Array(
+            args.iter()
+                .map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes())))
+                .collect(),
+        );
+        Command::from_frame(frame).unwrap().apply_now(db)
+    }
+
+    fn hits_and_misses(db: &Db) -> (u64, u64) {
+        let stats = db.stats();
+        (stats.keyspace_hits.get(), stats.keyspace_misses.get())
+    }
+
+    #[test]
+    fn lookups_count_keyspace_hits_and_misses() {
+        let db = Db::new(Config::default());
+        assert_eq!(call(&db, &["GET", "key"]), Frame::Null);
+        assert_eq!(hits_and_misses(&db), (0, 1));
+        //写命令不计入
+        call(&db, &["SET", "key", "value"]);
+        assert_eq!(hits_and_misses(&db), (0, 1));
+        assert!(call(&db, &["GET", "key"]) == "value");
+        assert_eq!(hits_and_misses(&db), (1, 1));
+        //MGET的每个键分别计数
+        call(&db, &["MGET", "key", "missing", "key"]);
+        assert_eq!(hits_and_misses(&db), (3, 2));
+        //类型不符的键同样存在
+        call(&db, &["HSET", "hash", "field", "value"]);
+        assert!(matches!(call(&db, &["GET", "hash"]), Frame::Error(_)));
+        assert_eq!(hits_and_misses(&db), (4, 2));
+        let info = match call(&db, &["INFO", "stats"]) {
+            Frame::Bulk(info) => String::from_utf8(info.to_vec()).unwrap(),
+            frame => panic!("unexpected reply {:?}", frame),
+        };
+        assert!(info.contains("keyspace_hits:4\r\n"), "{}", info);
+        assert!(info.contains("keyspace_misses:2\r\n"), "{}", info);
+    }
+}
@@ -0,0 +1,53 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+
+//位偏移量的上限，对应512MB的值
+const MAX_BIT_OFFSET: i64 = (1 << 32) - 1;
+
+///获取值中指定偏移量上的位
+///
+/// GETBIT key offset
+///
+/// 偏移量从最高位开始计算，超出值长度的部分视为0
+#[derive(Debug)]
+pub struct GetBit {
+    key: String,
+    offset: usize,
+}
+
+impl GetBit {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<GetBit, ParseError> {
+        let key = parse.next_string()?;
+        let offset = parse_offset(parse)?;
+        Ok(GetBit { key, offset })
+    }
+
+    ///键不存在时回复0
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.get(&self.key) {
+            Ok(value) => {
+                let bit = value.is_some_and(|value| bit_at(&value, self.offset));
+                Frame::Integer(bit as i64)
+            }
+            Err(e) => e.into(),
+        }
+    }
+}
+
+///读取位偏移量参数
+pub(super) fn parse_offset(parse: &mut Parse) -> Result<usize, ParseError> {
+    match parse.next_int() {
+        Ok(offset) if (0..=MAX_BIT_OFFSET).contains(&offset) => Ok(offset as usize),
+        Ok(_) | Err(ParseError::NotInteger | ParseError::Other(_)) => {
+            Err("bit offset is not an integer or out of range".into())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+///获取data中第offset位的值，超出长度时为0
+pub(super) fn bit_at(data: &[u8], offset: usize) -> bool {
+    data.get(offset / 8)
+        .is_some_and(|byte| byte & (0x80 >> (offset % 8)) != 0)
+}
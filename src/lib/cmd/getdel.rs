@@ -0,0 +1,30 @@
+use crate::lib::db::{Db, Update};
+use crate::lib::frame::Frame;
+use crate::lib::notify::{Class, Event};
+use crate::lib::parse::{Parse, ParseError};
+
+///获取键对应的值并删除该键
+///
+/// GETDEL key
+#[derive(Debug)]
+pub struct GetDel {
+    key: String,
+}
+
+impl GetDel {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<GetDel, ParseError> {
+        let key = parse.next_string()?;
+        Ok(GetDel { key })
+    }
+
+    ///回复被删除的值，键不存在时回复Null
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        db.update_notify(self.key, Event::new(Class::Generic, "del"), |cur| match cur
+            .map(|entry| entry.value.as_string())
+        {
+            Some(Ok(data)) => (Update::Remove, Frame::Bulk(data)),
+            Some(Err(e)) => (Update::Keep, e.into()),
+            None => (Update::Keep, Frame::Null),
+        })
+    }
+}
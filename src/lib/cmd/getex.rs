@@ -0,0 +1,75 @@
+use crate::lib::cmd::set::{parse_expire, Expire};
+use crate::lib::db::{now_millis, Db, Update};
+use crate::lib::frame::Frame;
+use crate::lib::notify::{Class, Event};
+use crate::lib::parse::{Parse, ParseError};
+
+///获取键对应的值并修改其过期时间
+///
+/// GETEX key [EX seconds | PX milliseconds | EXAT unix-time-seconds |
+/// PXAT unix-time-milliseconds | PERSIST]
+#[derive(Debug)]
+pub struct GetEx {
+    key: String,
+    //新的过期时间，None代表不修改
+    expire: Option<Expire>,
+    //是否清除过期时间
+    persist: bool,
+}
+
+impl GetEx {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<GetEx, ParseError> {
+        let key = parse.next_string()?;
+        let mut getex = GetEx {
+            key,
+            expire: None,
+            persist: false,
+        };
+        loop {
+            let option = match parse.next_string() {
+                Ok(option) => option.to_uppercase(),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e),
+            };
+            let unset = getex.expire.is_none() && !getex.persist;
+            match option.as_str() {
+                "EX" | "PX" | "EXAT" | "PXAT" if unset => {
+                    let time = parse.next_int()?;
+                    getex.expire = Some(parse_expire(&option, time, "getex")?);
+                }
+                "PERSIST" if unset => getex.persist = true,
+                _ => return Err("syntax error".into()),
+            }
+        }
+        Ok(getex)
+    }
+
+    ///回复键对应的值，键不存在时回复Null且不做任何修改
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let now = now_millis();
+        let GetEx {
+            key,
+            expire,
+            persist,
+        } = self;
+        let event = Event::new(Class::Generic, if persist { "persist" } else { "expire" });
+        db.update_notify(key, event, |cur| {
+            let entry = match cur {
+                Some(entry) => entry,
+                None => return (Update::Keep, Frame::Null),
+            };
+            let reply = match entry.value.as_string() {
+                Ok(data) => Frame::Bulk(data),
+                Err(e) => return (Update::Keep, e.into()),
+            };
+            let update = match expire {
+                Some(Expire::After(ms)) => Update::SetExpire(Some(now.saturating_add(ms))),
+                Some(Expire::At(at)) if at <= now => Update::Remove,
+                Some(Expire::At(at)) => Update::SetExpire(Some(at)),
+                _ if persist => Update::SetExpire(None),
+                _ => Update::Keep,
+            };
+            (update, reply)
+        })
+    }
+}
@@ -0,0 +1,59 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use bytes::Bytes;
+
+///获取值的子串
+///
+/// GETRANGE key start end
+///
+/// 下标从0开始且包含两端，负数下标从末尾开始计算，-1代表最后一个字节
+#[derive(Debug)]
+pub struct GetRange {
+    key: String,
+    start: i64,
+    end: i64,
+}
+
+impl GetRange {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<GetRange, ParseError> {
+        let key = parse.next_string()?;
+        let start = parse.next_int()?;
+        let end = parse.next_int()?;
+        Ok(GetRange { key, start, end })
+    }
+
+    ///键不存在或范围为空时回复空字符串
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let value = match db.get(&self.key) {
+            Ok(value) => value.unwrap_or_default(),
+            Err(e) => return e.into(),
+        };
+        match normalize_range(self.start, self.end, value.len()) {
+            Some((start, end)) => Frame::Bulk(value.slice(start..=end)),
+            None => Frame::Bulk(Bytes::new()),
+        }
+    }
+}
+
+//将可能为负数的闭区间换算为合法的下标，区间为空时返回None
+pub(super) fn normalize_range(start: i64, end: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    if len == 0 {
+        return None;
+    }
+    let start = if start < 0 {
+        (len + start).max(0)
+    } else {
+        start
+    };
+    let end = if end < 0 {
+        (len + end).max(0)
+    } else {
+        end.min(len - 1)
+    };
+    if start > end {
+        return None;
+    }
+    Some((start as usize, end as usize))
+}
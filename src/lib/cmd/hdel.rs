@@ -0,0 +1,48 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::notify::{Class, Event};
+use crate::lib::parse::{Parse, ParseError};
+use bytes::Bytes;
+
+///删除哈希中的字段
+///
+/// HDEL key field [field ...]
+///
+/// 字段全部删除后键也随之删除
+#[derive(Debug)]
+pub struct HDel {
+    key: String,
+    fields: Vec<Bytes>,
+}
+
+impl HDel {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<HDel, ParseError> {
+        let key = parse.next_string()?;
+        let mut fields = vec![parse.next_bytes()?];
+        loop {
+            match parse.next_bytes() {
+                Ok(field) => fields.push(field),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(HDel { key, fields })
+    }
+
+    ///回复实际删除的字段数
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let HDel { key, fields } = self;
+        db.mutate_notify(key, Event::new(Class::Hash, "hdel"), |slot| {
+            let hash = match slot.as_mut().map(|value| value.as_hash_mut()) {
+                None => return (false, Frame::Integer(0)),
+                Some(Err(e)) => return (false, e.into()),
+                Some(Ok(hash)) => hash,
+            };
+            let removed = fields
+                .iter()
+                .filter(|field| hash.remove(field).is_some())
+                .count();
+            (removed > 0, Frame::Integer(removed as i64))
+        })
+    }
+}
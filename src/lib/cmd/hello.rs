@@ -0,0 +1,113 @@
+use crate::lib::client::Client;
+use crate::lib::cmd::auth::WRONGPASS;
+use crate::lib::cmd::client::check_name;
+use crate::lib::codec::Protocol;
+use crate::lib::conn::{Connection, Stream};
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use bytes::Bytes;
+
+///与服务器握手并协商连接使用的协议版本
+///
+/// HELLO [protover [AUTH username password] [SETNAME clientname]]
+///
+/// 不带版本号时只回复服务器的信息而不切换协议。带AUTH时先验证身份，与AUTH命令相同；
+/// SETNAME与CLIENT SETNAME相同
+#[derive(Debug)]
+pub struct Hello {
+    protocol: Option<Protocol>,
+    //用户名与密码
+    auth: Option<(String, Bytes)>,
+    //客户端名称
+    name: Option<String>,
+}
+
+impl Hello {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Hello, ParseError> {
+        let protocol = match parse.next_optional_bytes()? {
+            Some(version) => Some(parse_version(&version)?),
+            None => {
+                return Ok(Hello {
+                    protocol: None,
+                    auth: None,
+                    name: None,
+                })
+            }
+        };
+        let mut auth = None;
+        let mut name = None;
+        while let Some(option) = parse.next_optional_string()? {
+            match option.to_uppercase().as_str() {
+                "AUTH" => {
+                    auth = Some((parse.next_string()?, parse.next_bytes()?));
+                }
+                "SETNAME" => {
+                    let setname = parse.next_string()?;
+                    check_name(&setname)?;
+                    name = Some(setname);
+                }
+                _ => return Err(format!("Syntax error in HELLO option '{}'", option).into()),
+            }
+        }
+        Ok(Hello {
+            protocol,
+            auth,
+            name,
+        })
+    }
+
+    ///切换协议后按新协议回复服务器与连接的信息
+    ///
+    /// 设置了密码时，未认证的连接必须带上AUTH选项
+    pub(crate) fn apply<S: Stream>(
+        self,
+        db: &Db,
+        conn: &mut Connection<S>,
+        client: &Client,
+        user: &mut Option<String>,
+    ) -> Frame {
+        match self.auth {
+            Some((username, password)) if db.acl().authenticate(&username, &password) => {
+                *user = Some(username)
+            }
+            Some(_) => return Frame::Error(WRONGPASS.to_string()),
+            None if user.is_none() => {
+                return Frame::Error(
+                    "NOAUTH HELLO must be called with the client already authenticated, otherwise the HELLO <proto> AUTH <user> <pass> option can be used to authenticate the client and select the RESP protocol version at the same time"
+                        .to_string(),
+                )
+            }
+            None => {}
+        }
+        if let Some(protocol) = self.protocol {
+            conn.set_protocol(protocol);
+        }
+        if let Some(name) = self.name {
+            client.set_name(Some(name).filter(|name| !name.is_empty()));
+        }
+        let field = |name: &'static str| Frame::Bulk(Bytes::from_static(name.as_bytes()));
+        Frame::Map(vec![
+            (field("server"), field("redis")),
+            (field("version"), field(env!("CARGO_PKG_VERSION"))),
+            (field("proto"), Frame::Integer(conn.protocol().version())),
+            (field("id"), Frame::Integer(client.id() as i64)),
+            (field("mode"), field("standalone")),
+            (field("role"), field("master")),
+            (field("modules"), Frame::Array(vec![])),
+        ])
+    }
+}
+
+//解析协议版本号，只支持2与3
+fn parse_version(version: &[u8]) -> Result<Protocol, ParseError> {
+    let version: i64 = std::str::from_utf8(version)
+        .ok()
+        .and_then(|text| text.parse().ok())
+        .ok_or("Protocol version is not an integer or out of range")?;
+    match version {
+        2 => Ok(Protocol::Resp2),
+        3 => Ok(Protocol::Resp3),
+        _ => Err("NOPROTO unsupported protocol version".into()),
+    }
+}
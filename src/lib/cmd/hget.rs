@@ -0,0 +1,32 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use bytes::Bytes;
+
+///获取哈希中字段的值
+///
+/// HGET key field
+#[derive(Debug)]
+pub struct HGet {
+    key: String,
+    field: Bytes,
+}
+
+impl HGet {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<HGet, ParseError> {
+        let key = parse.next_string()?;
+        let field = parse.next_bytes()?;
+        Ok(HGet { key, field })
+    }
+
+    ///键或字段不存在时回复Null
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let field = self.field;
+        let reply = db.read(&self.key, |entry| match entry.value.as_hash() {
+            Ok(hash) => hash.get(&field).map_or(Frame::Null, Frame::Bulk),
+            Err(e) => e.into(),
+        });
+        db.record_lookup(reply.is_some());
+        reply.unwrap_or(Frame::Null)
+    }
+}
@@ -0,0 +1,39 @@
+use crate::lib::db::Db;
+use crate::lib::frame::{Frame, REPLY_TOO_LARGE};
+use crate::lib::parse::{Parse, ParseError};
+
+///获取哈希中所有的字段与值
+///
+/// HGETALL key
+#[derive(Debug)]
+pub struct HGetAll {
+    key: String,
+}
+
+impl HGetAll {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<HGetAll, ParseError> {
+        let key = parse.next_string()?;
+        Ok(HGetAll { key })
+    }
+
+    ///回复字段到值的映射，RESP2中字段与值交替排列在同一个数组中，键不存在时回复空映射，
+    /// 回复超出max-reply-size时回复错误
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let mut limit = db.reply_limit();
+        let reply = db.read(&self.key, |entry| match entry.value.as_hash() {
+            Ok(hash) => {
+                let mut pairs = vec![];
+                for (field, value) in hash.iter() {
+                    if !limit.add(field.len()) || !limit.add(value.len()) {
+                        return Frame::Error(REPLY_TOO_LARGE.to_string());
+                    }
+                    pairs.push((Frame::Bulk(field), Frame::Bulk(value)));
+                }
+                Frame::Map(pairs)
+            }
+            Err(e) => e.into(),
+        });
+        db.record_lookup(reply.is_some());
+        reply.unwrap_or(Frame::Map(vec![]))
+    }
+}
@@ -0,0 +1,64 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::notify::{Class, Event};
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::value::hash::Hash;
+use crate::lib::value::Value;
+use bytes::Bytes;
+
+///对哈希中整数字段做加法
+///
+/// HINCRBY key field increment
+///
+/// 键或字段不存在时视为0
+#[derive(Debug)]
+pub struct HIncrBy {
+    key: String,
+    field: Bytes,
+    delta: i64,
+}
+
+impl HIncrBy {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<HIncrBy, ParseError> {
+        let key = parse.next_string()?;
+        let field = parse.next_bytes()?;
+        let delta = parse.next_int()?;
+        Ok(HIncrBy { key, field, delta })
+    }
+
+    ///回复相加后的值
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let HIncrBy { key, field, delta } = self;
+        db.mutate_notify(key, Event::new(Class::Hash, "hincrby"), |slot| {
+            let hash = slot.get_or_insert_with(|| Value::Hash(Hash::default()));
+            let hash = match hash.as_hash_mut() {
+                Ok(hash) => hash,
+                Err(e) => return (false, e.into()),
+            };
+            let value = match hash.get(&field) {
+                None => 0,
+                Some(data) => match parse_i64(&data) {
+                    Some(value) => value,
+                    None => {
+                        let err = "ERR hash value is not an integer";
+                        return (false, Frame::Error(err.to_string()));
+                    }
+                },
+            };
+            let value = match value.checked_add(delta) {
+                Some(value) => value,
+                None => {
+                    let err = "ERR increment or decrement would overflow";
+                    return (false, Frame::Error(err.to_string()));
+                }
+            };
+            hash.insert(field, value.to_string().into());
+            (true, Frame::Integer(value))
+        })
+    }
+}
+
+//将字节解析为整数，不允许空白与多余的字符
+fn parse_i64(data: &[u8]) -> Option<i64> {
+    std::str::from_utf8(data).ok()?.parse().ok()
+}
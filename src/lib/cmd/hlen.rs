@@ -0,0 +1,27 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+
+///获取哈希中的字段数
+///
+/// HLEN key
+#[derive(Debug)]
+pub struct HLen {
+    key: String,
+}
+
+impl HLen {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<HLen, ParseError> {
+        let key = parse.next_string()?;
+        Ok(HLen { key })
+    }
+
+    ///键不存在时回复0
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        db.read(&self.key, |entry| match entry.value.as_hash() {
+            Ok(hash) => Frame::Integer(hash.len() as i64),
+            Err(e) => e.into(),
+        })
+        .unwrap_or(Frame::Integer(0))
+    }
+}
@@ -0,0 +1,84 @@
+use crate::lib::db::Db;
+use crate::lib::frame::{ArrayBuilder, Frame};
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::random;
+
+///随机获取哈希中的字段
+///
+/// HRANDFIELD key [count [WITHVALUES]]
+///
+/// count为正数时返回不重复的字段，为负数时允许重复并恰好返回其绝对值个字段
+#[derive(Debug)]
+pub struct HRandField {
+    key: String,
+    count: Option<i64>,
+    with_values: bool,
+}
+
+impl HRandField {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<HRandField, ParseError> {
+        let key = parse.next_string()?;
+        let mut cmd = HRandField {
+            key,
+            count: None,
+            with_values: false,
+        };
+        match parse.next_int() {
+            //允许重复时会按绝对值分配回复，过大的数量直接拒绝
+            Ok(count) if count < -(i64::MAX / 2) => return Err("value is out of range".into()),
+            Ok(count) => cmd.count = Some(count),
+            Err(ParseError::EndOfStream) => return Ok(cmd),
+            Err(e) => return Err(e),
+        }
+        match parse.next_string() {
+            Ok(option) if option.eq_ignore_ascii_case("withvalues") => cmd.with_values = true,
+            Ok(_) => return Err("syntax error".into()),
+            Err(ParseError::EndOfStream) => {}
+            Err(e) => return Err(e),
+        }
+        Ok(cmd)
+    }
+
+    ///不带count时回复单个字段，键不存在时回复Null；带count时回复数组，WITHVALUES时字段与值交替排列
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let HRandField {
+            key,
+            count,
+            with_values,
+        } = self;
+        let reply = db.read(&key, |entry| {
+            let hash = match entry.value.as_hash() {
+                Ok(hash) => hash,
+                Err(e) => return e.into(),
+            };
+            let count = match count {
+                None => {
+                    let field = hash.iter().nth(random::below(hash.len()));
+                    return field.map_or(Frame::Null, |(field, _)| Frame::Bulk(field));
+                }
+                Some(count) => count,
+            };
+            let pairs: Vec<_> = hash.iter().collect();
+            let picked = if count >= 0 {
+                random::sample(pairs, count as usize)
+            } else {
+                (0..count.unsigned_abs())
+                    .map(|_| pairs[random::below(pairs.len())].clone())
+                    .collect()
+            };
+            let mut frame = ArrayBuilder::new();
+            for (field, value) in picked {
+                frame.push(field);
+                if with_values {
+                    frame.push(value);
+                }
+            }
+            frame.build()
+        });
+        match (reply, count) {
+            (Some(reply), _) => reply,
+            (None, None) => Frame::Null,
+            (None, Some(_)) => Frame::array(),
+        }
+    }
+}
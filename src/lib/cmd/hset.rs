@@ -0,0 +1,60 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::notify::{Class, Event};
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::value::hash::Hash;
+use crate::lib::value::Value;
+use bytes::Bytes;
+
+///设置哈希中的字段
+///
+/// HSET key field value [field value ...] | HMSET key field value [field value ...]
+#[derive(Debug)]
+pub struct HSet {
+    key: String,
+    pairs: Vec<(Bytes, Bytes)>,
+    //是否为HMSET，两者只有回复不同
+    hmset: bool,
+}
+
+impl HSet {
+    pub(crate) fn parse_frames(parse: &mut Parse, hmset: bool) -> Result<HSet, ParseError> {
+        let key = parse.next_string()?;
+        let mut pairs = vec![(parse.next_bytes()?, parse.next_bytes()?)];
+        loop {
+            let field = match parse.next_bytes() {
+                Ok(field) => field,
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e),
+            };
+            //字段与值必须成对出现
+            let value = parse.next_bytes()?;
+            pairs.push((field, value));
+        }
+        Ok(HSet { key, pairs, hmset })
+    }
+
+    ///HSET回复新增的字段数，HMSET回复OK
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let HSet { key, pairs, hmset } = self;
+        db.mutate_notify(key, Event::new(Class::Hash, "hset"), |slot| {
+            let hash = slot.get_or_insert_with(|| Value::Hash(Hash::default()));
+            let hash = match hash.as_hash_mut() {
+                Ok(hash) => hash,
+                Err(e) => return (false, e.into()),
+            };
+            let mut added = 0;
+            for (field, value) in pairs {
+                if hash.insert(field, value).is_none() {
+                    added += 1;
+                }
+            }
+            let reply = if hmset {
+                Frame::Simple("OK".to_string())
+            } else {
+                Frame::Integer(added)
+            };
+            (true, reply)
+        })
+    }
+}
@@ -0,0 +1,43 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::notify::{Class, Event};
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::value::hash::Hash;
+use crate::lib::value::Value;
+use bytes::Bytes;
+
+///字段不存在时才设置哈希中的字段
+///
+/// HSETNX key field value
+#[derive(Debug)]
+pub struct HSetNx {
+    key: String,
+    field: Bytes,
+    value: Bytes,
+}
+
+impl HSetNx {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<HSetNx, ParseError> {
+        let key = parse.next_string()?;
+        let field = parse.next_bytes()?;
+        let value = parse.next_bytes()?;
+        Ok(HSetNx { key, field, value })
+    }
+
+    ///写入时回复1，字段已存在时回复0
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let HSetNx { key, field, value } = self;
+        db.mutate_notify(key, Event::new(Class::Hash, "hset"), |slot| {
+            let hash = slot.get_or_insert_with(|| Value::Hash(Hash::default()));
+            let hash = match hash.as_hash_mut() {
+                Ok(hash) => hash,
+                Err(e) => return (false, e.into()),
+            };
+            if hash.contains(&field) {
+                return (false, Frame::Integer(0));
+            }
+            hash.insert(field, value);
+            (true, Frame::Integer(1))
+        })
+    }
+}
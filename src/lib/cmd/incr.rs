@@ -0,0 +1,147 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::notify::{Class, Event};
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::value::{Value, WrongType};
+
+///对整数字符串做加减
+///
+/// INCR key | DECR key | INCRBY key increment | DECRBY key decrement
+///
+/// 键不存在时视为0，原有的过期时间保持不变
+#[derive(Debug)]
+pub struct Incr {
+    key: String,
+    delta: i64,
+    //键空间事件的名称
+    event: &'static str,
+}
+
+impl Incr {
+    ///name为命令名称，决定增量的来源与符号
+    pub(crate) fn parse_frames(parse: &mut Parse, name: &str) -> Result<Incr, ParseError> {
+        let key = parse.next_string()?;
+        let delta = match name {
+            "incr" => 1,
+            "decr" => -1,
+            "incrby" => parse.next_int()?,
+            _ => parse
+                .next_int()?
+                .checked_neg()
+                .ok_or("decrement would overflow")?,
+        };
+        let event = if name.starts_with("incr") {
+            "incrby"
+        } else {
+            "decrby"
+        };
+        Ok(Incr { key, delta, event })
+    }
+
+    ///回复加减后的值
+    ///
+    /// 计数器以整数存储，已经是整数的值原地相加，不再经过字符串的解析与格式化
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let delta = self.delta;
+        let event = Event::new(Class::String, self.event);
+        db.mutate_notify(self.key, event, |slot| {
+            let cur = match slot {
+                None => 0,
+                Some(Value::Int(n)) => *n,
+                Some(Value::String(data)) => match parse_i64(data) {
+                    Some(n) => n,
+                    None => {
+                        let err = "ERR value is not an integer or out of range";
+                        return (false, Frame::Error(err.to_string()));
+                    }
+                },
+                Some(_) => return (false, WrongType.into()),
+            };
+            let value = match cur.checked_add(delta) {
+                Some(value) => value,
+                None => {
+                    let err = "ERR increment or decrement would overflow";
+                    return (false, Frame::Error(err.to_string()));
+                }
+            };
+            *slot = Some(Value::Int(value));
+            (true, Frame::Integer(value))
+        })
+    }
+}
+
+//将字节解析为整数，不允许空白与多余的字符
+fn parse_i64(data: &[u8]) -> Option<i64> {
+    std::str::from_utf8(data).ok()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::cmd::Command;
+    use crate::lib::config::Config;
+    use bytes::Bytes;
+
+    fn call(db: &Db, args: &[&str]) -> Frame {
+        let frame = Frame::Array(
+            args.iter()
+                .map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes())))
+                .collect(),
+        );
+        Command::from_frame(frame).unwrap().apply_now(db)
+    }
+
+    #[test]
+    fn counters_are_stored_as_integers() {
+        let db = Db::new(Config::default());
+        for _ in 0..100 {
+            call(&db, &["INCR", "counter"]);
+        }
+        assert_eq!(call(&db, &["DECRBY", "counter", "10"]), Frame::Integer(90));
+        assert_eq!(call(&db, &["GET", "counter"]), Frame::Bulk("90".into()));
+        assert_eq!(
+            call(&db, &["OBJECT", "ENCODING", "counter"]),
+            Frame::Bulk("int".into())
+        );
+        //字符串形式的整数在第一次加减后改为整数存储
+        call(&db, &["SET", "text", "41"]);
+        assert_eq!(call(&db, &["INCR", "text"]), Frame::Integer(42));
+        assert_eq!(call(&db, &["STRLEN", "text"]), Frame::Integer(2));
+    }
+
+    #[test]
+    fn expiry_is_kept() {
+        let db = Db::new(Config::default());
+        call(&db, &["SET", "counter", "1", "EX", "100"]);
+        call(&db, &["INCR", "counter"]);
+        assert!(matches!(
+            call(&db, &["TTL", "counter"]),
+            Frame::Integer(99 | 100)
+        ));
+    }
+
+    #[test]
+    fn invalid_values_are_rejected() {
+        let db = Db::new(Config::default());
+        call(&db, &["SET", "text", "abc"]);
+        assert!(matches!(call(&db, &["INCR", "text"]), Frame::Error(_)));
+        call(&db, &["SET", "max", &i64::MAX.to_string()]);
+        assert!(matches!(call(&db, &["INCR", "max"]), Frame::Error(_)));
+        call(&db, &["SADD", "set", "a"]);
+        assert!(
+            matches!(call(&db, &["INCR", "set"]), Frame::Error(e) if e.starts_with("WRONGTYPE"))
+        );
+    }
+
+    #[test]
+    fn string_commands_work_on_counters() {
+        let db = Db::new(Config::default());
+        call(&db, &["INCRBY", "counter", "12"]);
+        assert_eq!(call(&db, &["APPEND", "counter", "3"]), Frame::Integer(3));
+        assert_eq!(
+            call(&db, &["GETRANGE", "counter", "0", "1"]),
+            Frame::Bulk("12".into())
+        );
+        assert_eq!(call(&db, &["INCR", "counter"]), Frame::Integer(124));
+    }
+}
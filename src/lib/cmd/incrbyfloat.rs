@@ -0,0 +1,62 @@
+use crate::lib::db::{Db, Update};
+use crate::lib::frame::Frame;
+use crate::lib::notify::{Class, Event};
+use crate::lib::parse::{Parse, ParseError};
+use bytes::Bytes;
+
+///对浮点数字符串做加法
+///
+/// INCRBYFLOAT key increment
+///
+/// 键不存在时视为0，原有的过期时间保持不变
+#[derive(Debug)]
+pub struct IncrByFloat {
+    key: String,
+    delta: f64,
+}
+
+impl IncrByFloat {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<IncrByFloat, ParseError> {
+        let key = parse.next_string()?;
+        let delta = parse
+            .next_string()?
+            .parse()
+            .map_err(|_| "value is not a valid float")?;
+        Ok(IncrByFloat { key, delta })
+    }
+
+    ///回复相加后的值
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let delta = self.delta;
+        db.update_notify(self.key, Event::new(Class::String, "incrbyfloat"), |cur| {
+            let value = match cur.map(|entry| entry.value.as_string()) {
+                None => 0.0,
+                Some(Err(e)) => return (Update::Keep, e.into()),
+                Some(Ok(data)) => match parse_f64(&data) {
+                    Some(value) => value,
+                    None => {
+                        let err = "ERR value is not a valid float";
+                        return (Update::Keep, Frame::Error(err.to_string()));
+                    }
+                },
+            };
+            let value = value + delta;
+            if !value.is_finite() {
+                let err = "ERR increment would produce NaN or Infinity";
+                return (Update::Keep, Frame::Error(err.to_string()));
+            }
+            let text = Bytes::from(value.to_string());
+            let update = Update::Set {
+                value: text.clone().into(),
+                expires_at: cur.and_then(|entry| entry.expires_at),
+            };
+            (update, Frame::Bulk(text))
+        })
+    }
+}
+
+//将字节解析为有限的浮点数
+fn parse_f64(data: &[u8]) -> Option<f64> {
+    let value: f64 = std::str::from_utf8(data).ok()?.parse().ok()?;
+    Some(value).filter(|value| value.is_finite())
+}
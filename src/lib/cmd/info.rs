@@ -0,0 +1,363 @@
+use crate::lib::allocator;
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::stats::Stats;
+use crate::lib::value::TYPE_NAMES;
+use bytes::Bytes;
+
+///查看服务器的状态与统计
+///
+/// INFO [section [section ...]]
+///
+/// 不带参数或为default时输出默认的节，all、everything输出所有节，节名不区分大小写，不认识的节被忽略。
+/// commandstats、latencystats、shards与keytypes行数较多，只在all或明确指定时输出。
+/// keytypes列出各类型被删除的键数，以及每个有键的数据库中各类型的键数。
+/// 每一行为“字段:值”，每节以“# 节名”开头
+#[derive(Debug)]
+pub struct Info {
+    sections: Vec<String>,
+}
+
+///支持的节与是否属于默认的节，按输出的顺序排列
+const SECTIONS: &[(&str, bool)] = &[
+    ("server", true),
+    ("clients", true),
+    ("memory", true),
+    ("persistence", true),
+    ("stats", true),
+    ("replication", true),
+    ("commandstats", false),
+    ("latencystats", false),
+    ("shards", false),
+    ("keytypes", false),
+    ("keyspace", true),
+];
+
+//latencystats输出的百分位数
+const PERCENTILES: [f64; 3] = [50.0, 99.0, 99.9];
+
+impl Info {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Info, ParseError> {
+        let mut sections = vec![];
+        while let Some(section) = parse.next_optional_string()? {
+            sections.push(section.to_lowercase());
+        }
+        Ok(Info { sections })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let requested = |name: &str| self.sections.iter().any(|section| section == name);
+        let all = requested("all") || requested("everything");
+        let default = self.sections.is_empty() || requested("default");
+        let mut text = String::new();
+        for (section, in_default) in SECTIONS {
+            let included = all || (default && *in_default) || requested(section);
+            if !included {
+                continue;
+            }
+            if !text.is_empty() {
+                text.push_str("\r\n");
+            }
+            text.push_str(&format!(
+                "# {}{}\r\n",
+                section[..1].to_uppercase(),
+                &section[1..]
+            ));
+            for (field, value) in fields(db, section) {
+                text.push_str(&format!("{}:{}\r\n", field, value));
+            }
+        }
+        Frame::Bulk(Bytes::from(text))
+    }
+}
+
+//一节中的各个字段
+fn fields(db: &Db, section: &str) -> Vec<(String, String)> {
+    let stats = db.stats();
+    let config = db.config();
+    let fields: Vec<(&str, String)> = match section {
+        "server" => {
+            let uptime = stats.uptime().as_secs();
+            vec![
+                ("redis_version", env!("CARGO_PKG_VERSION").to_string()),
+                ("redis_mode", "standalone".to_string()),
+                (
+                    "os",
+                    format!("{} {}", std::env::consts::OS, std::env::consts::ARCH),
+                ),
+                ("arch_bits", usize::BITS.to_string()),
+                ("process_id", std::process::id().to_string()),
+                ("run_id", stats.run_id().to_string()),
+                ("tcp_port", config.port().to_string()),
+                ("uptime_in_seconds", uptime.to_string()),
+                ("uptime_in_days", (uptime / 86400).to_string()),
+                ("hz", config.hz().to_string()),
+            ]
+        }
+        "clients" => vec![
+            ("connected_clients", db.clients().len().to_string()),
+            ("maxclients", config.max_clients().to_string()),
+            ("blocked_clients", db.blocked_clients().to_string()),
+            ("tracking_clients", db.tracking().clients().to_string()),
+        ],
+        "memory" => {
+            let used = allocator::allocated() as u64;
+            let peak = allocator::peak() as u64;
+            let dataset = db.used_memory() as u64;
+            let max = config.max_memory();
+            vec![
+                ("used_memory", used.to_string()),
+                ("used_memory_human", human(used)),
+                ("used_memory_peak", peak.to_string()),
+                ("used_memory_peak_human", human(peak)),
+                ("used_memory_dataset", dataset.to_string()),
+                ("used_memory_dataset_human", human(dataset)),
+                ("maxmemory", max.to_string()),
+                ("maxmemory_human", human(max)),
+                ("maxmemory_policy", config.max_memory_policy().to_string()),
+            ]
+        }
+        "persistence" => {
+            let saves = db.saves();
+            let status = if saves.last_ok() { "ok" } else { "err" };
+            let aof = db.aof();
+            let aof_status = if aof.last_write_ok() { "ok" } else { "err" };
+            let rewrite_status = if aof.rewrite_ok() { "ok" } else { "err" };
+            let mut fields = vec![
+                ("loading", "0".to_string()),
+                (
+                    "rdb_changes_since_last_save",
+                    saves.changes_since(stats.changes.get()).to_string(),
+                ),
+                (
+                    "rdb_bgsave_in_progress",
+                    u8::from(saves.in_progress()).to_string(),
+                ),
+                ("rdb_last_save_time", saves.last_save().to_string()),
+                ("rdb_last_bgsave_status", status.to_string()),
+                ("aof_enabled", u8::from(aof.is_enabled()).to_string()),
+                (
+                    "aof_rewrite_in_progress",
+                    u8::from(aof.is_rewriting()).to_string(),
+                ),
+                ("aof_last_bgrewrite_status", rewrite_status.to_string()),
+                ("aof_last_write_status", aof_status.to_string()),
+            ];
+            if aof.is_enabled() {
+                fields.push(("aof_current_size", aof.size().to_string()));
+                fields.push(("aof_base_size", aof.base_size().to_string()));
+            }
+            fields
+        }
+        "stats" => {
+            let (channels, patterns) = db.pubsub_counts();
+            vec![
+                (
+                    "total_connections_received",
+                    stats.connections_received.get().to_string(),
+                ),
+                (
+                    "total_commands_processed",
+                    stats.commands_processed.get().to_string(),
+                ),
+                (
+                    "rejected_connections",
+                    stats.rejected_connections.get().to_string(),
+                ),
+                ("closed_connections", closed_connections(stats)),
+                ("expired_keys", stats.expired_keys.get().to_string()),
+                ("evicted_keys", stats.evicted_keys.get().to_string()),
+                ("keyspace_hits", stats.keyspace_hits.get().to_string()),
+                ("keyspace_misses", stats.keyspace_misses.get().to_string()),
+                ("pubsub_channels", channels.to_string()),
+                ("pubsub_patterns", patterns.to_string()),
+                ("lock_contentions", db.lock_contentions().to_string()),
+            ]
+        }
+        "replication" => {
+            let replication = db.replication();
+            let replicas = replication.replicas();
+            let link = db.master_link();
+            let mut fields = vec![];
+            let mut offset = replication.offset();
+            match link.master() {
+                Some((host, port)) => {
+                    //从节点的复制偏移量是已经处理到的主节点的偏移量
+                    offset = link.offset();
+                    let (status, last_io) = if link.is_up() {
+                        ("up", link.last_io_secs().to_string())
+                    } else {
+                        ("down", "-1".to_string())
+                    };
+                    fields.extend([
+                        ("role".to_string(), "slave".to_string()),
+                        ("master_host".to_string(), host),
+                        ("master_port".to_string(), port.to_string()),
+                        ("master_link_status".to_string(), status.to_string()),
+                        ("master_last_io_seconds_ago".to_string(), last_io),
+                        (
+                            "master_sync_in_progress".to_string(),
+                            u8::from(link.is_syncing()).to_string(),
+                        ),
+                        ("slave_repl_offset".to_string(), link.offset().to_string()),
+                        (
+                            "slave_read_only".to_string(),
+                            u8::from(config.replica_read_only()).to_string(),
+                        ),
+                    ]);
+                }
+                None => fields.push(("role".to_string(), "master".to_string())),
+            }
+            fields.push(("connected_slaves".to_string(), replicas.len().to_string()));
+            //从节点的编号按连接的先后排列
+            for (i, replica) in replicas.iter().enumerate() {
+                let state = if replica.is_online() {
+                    "online"
+                } else {
+                    "send_bulk"
+                };
+                let value = format!(
+                    "ip={},port={},state={},offset={},lag={}",
+                    replica.ip(),
+                    replica.port(),
+                    state,
+                    replica.ack(),
+                    replica.lag()
+                );
+                fields.push((format!("slave{}", i), value));
+            }
+            let (size, first_byte, histlen) = replication.backlog();
+            let active = u8::from(replication.is_active());
+            fields.extend([
+                ("master_replid".to_string(), replication.replid()),
+                ("master_repl_offset".to_string(), offset.to_string()),
+                ("repl_backlog_active".to_string(), active.to_string()),
+                ("repl_backlog_size".to_string(), size.to_string()),
+                (
+                    "repl_backlog_first_byte_offset".to_string(),
+                    first_byte.to_string(),
+                ),
+                ("repl_backlog_histlen".to_string(), histlen.to_string()),
+            ]);
+            return fields;
+        }
+        "commandstats" => {
+            return stats
+                .commands
+                .all()
+                .into_iter()
+                .map(|stat| {
+                    let value = format!(
+                        "calls={},usec={},usec_per_call={:.2},rejected_calls={},failed_calls={}",
+                        stat.calls,
+                        stat.usec,
+                        stat.usec_per_call(),
+                        stat.rejected_calls,
+                        stat.failed_calls
+                    );
+                    (format!("cmdstat_{}", stat.name), value)
+                })
+                .collect();
+        }
+        "latencystats" => {
+            return stats
+                .commands
+                .percentiles(&PERCENTILES)
+                .into_iter()
+                .map(|(name, values)| {
+                    let value = PERCENTILES
+                        .iter()
+                        .zip(values)
+                        .map(|(percentile, usec)| format!("p{}={:.3}", percentile, usec as f64))
+                        .collect::<Vec<_>>()
+                        .join(",");
+                    (format!("latency_percentiles_usec_{}", name), value)
+                })
+                .collect();
+        }
+        "shards" => {
+            //同一下标的分片在所有逻辑数据库中的统计相加
+            return db
+                .shard_stats()
+                .into_iter()
+                .enumerate()
+                .map(|(index, shard)| {
+                    let value = format!(
+                        "keys={},memory={},reads={},writes={},contentions={}",
+                        shard.keys, shard.memory, shard.reads, shard.writes, shard.contended
+                    );
+                    (format!("shard{}", index), value)
+                })
+                .collect();
+        }
+        "keytypes" => {
+            let deleted = db.type_deletions();
+            let mut fields: Vec<(String, String)> = TYPE_NAMES
+                .iter()
+                .zip(deleted)
+                .map(|(name, deleted)| (format!("deleted_{}", name), deleted.to_string()))
+                .collect();
+            //只列出有键的数据库
+            for db in (0..db.databases()).filter_map(|index| db.select(index)) {
+                let counts = db.type_counts();
+                if counts.iter().all(|count| *count == 0) {
+                    continue;
+                }
+                let value = TYPE_NAMES
+                    .iter()
+                    .zip(counts)
+                    .map(|(name, count)| format!("{}={}", name, count))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                fields.push((format!("db{}", db.index()), value));
+            }
+            return fields;
+        }
+        "keyspace" => {
+            //只列出有键的数据库
+            return (0..db.databases())
+                .filter_map(|index| db.select(index))
+                .filter(|db| db.key_count() > 0)
+                .map(|db| {
+                    let (expires, avg_ttl) = db.expires();
+                    let value = format!(
+                        "keys={},expires={},avg_ttl={}",
+                        db.key_count(),
+                        expires,
+                        avg_ttl
+                    );
+                    (format!("db{}", db.index()), value)
+                })
+                .collect();
+        }
+        _ => vec![],
+    };
+    fields
+        .into_iter()
+        .map(|(field, value)| (field.to_string(), value))
+        .collect()
+}
+
+//按原因分开的关闭的连接数，格式与keyspace一节相同，如eof=3,quit=1
+fn closed_connections(stats: &Stats) -> String {
+    stats
+        .closed_connections()
+        .map(|(reason, count)| format!("{}={}", reason, count))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+//以redis的格式输出便于阅读的字节数，如1.50M
+fn human(bytes: u64) -> String {
+    const UNITS: [(u64, &str); 4] = [
+        (1 << 40, "T"),
+        (1 << 30, "G"),
+        (1 << 20, "M"),
+        (1 << 10, "K"),
+    ];
+    match UNITS.iter().find(|(size, _)| bytes >= *size) {
+        Some((size, unit)) => format!("{:.2}{}", bytes as f64 / *size as f64, unit),
+        None => format!("{}B", bytes),
+    }
+}
@@ -0,0 +1,26 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+
+///获取键存储的值的类型
+///
+/// TYPE key
+#[derive(Debug)]
+pub struct KeyType {
+    key: String,
+}
+
+impl KeyType {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<KeyType, ParseError> {
+        let key = parse.next_string()?;
+        Ok(KeyType { key })
+    }
+
+    ///键不存在时回复none
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let name = db
+            .read(&self.key, |entry| entry.value.type_name())
+            .unwrap_or("none");
+        Frame::Simple(name.to_string())
+    }
+}
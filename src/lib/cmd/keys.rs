@@ -0,0 +1,176 @@
+use crate::lib::cmd::YIELD_EVERY;
+use crate::lib::db::Db;
+use crate::lib::frame::{Frame, ReplyLimit, REPLY_TOO_LARGE};
+use crate::lib::glob;
+use crate::lib::parse::{Parse, ParseError};
+use bytes::Bytes;
+
+///查找所有匹配模式的键
+///
+/// KEYS pattern
+///
+/// 需要遍历整个键空间，键较多时应当使用SCAN
+#[derive(Debug)]
+pub struct Keys {
+    pattern: Bytes,
+}
+
+impl Keys {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Keys, ParseError> {
+        let pattern = parse.next_bytes()?;
+        Ok(Keys { pattern })
+    }
+
+    ///回复匹配的键，回复超出max-reply-size时回复错误
+    ///
+    /// keys-sorted为yes时按字典序回复，否则顺序不固定。
+    /// 分批遍历键空间，每批之间让出执行权，键很多时同一个工作线程上的其他连接也能得到执行，
+    /// 遍历期间一直存在的键都会被回复
+    pub(crate) async fn apply(self, db: &Db) -> Frame {
+        let mut matched = Matched::new(db);
+        let mut cursor = 0;
+        loop {
+            cursor = db.scan(cursor, YIELD_EVERY, |key, _| {
+                matched.push(&self.pattern, key)
+            });
+            if cursor == 0 || matched.exceeded {
+                return matched.reply(db);
+            }
+            tokio::task::yield_now().await;
+        }
+    }
+
+    ///一次遍历完整个键空间，事务与脚本中执行时使用
+    pub(crate) fn apply_now(self, db: &Db) -> Frame {
+        let mut matched = Matched::new(db);
+        db.collect(|key, _| {
+            matched.push(&self.pattern, key);
+            None::<()>
+        });
+        matched.reply(db)
+    }
+}
+
+//已经找到的匹配的键
+struct Matched {
+    keys: Vec<Bytes>,
+    limit: ReplyLimit,
+    //回复是否超出了max-reply-size
+    exceeded: bool,
+}
+
+impl Matched {
+    fn new(db: &Db) -> Matched {
+        Matched {
+            keys: vec![],
+            limit: db.reply_limit(),
+            exceeded: false,
+        }
+    }
+
+    //键匹配模式时加入回复
+    fn push(&mut self, pattern: &[u8], key: &str) {
+        if self.exceeded || !glob::matches(pattern, key.as_bytes()) {
+            return;
+        }
+        self.exceeded = !self.limit.add(key.len());
+        if !self.exceeded {
+            self.keys.push(Bytes::copy_from_slice(key.as_bytes()));
+        }
+    }
+
+    fn reply(mut self, db: &Db) -> Frame {
+        if self.exceeded {
+            return Frame::Error(REPLY_TOO_LARGE.to_string());
+        }
+        if db.config().keys_sorted() {
+            self.keys.sort_unstable();
+        }
+        Frame::Array(self.keys.into_iter().map(Frame::Bulk).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::cmd::Command;
+    use crate::lib::config::Config;
+
+    fn keys(db: &Db, pattern: &str) -> Frame {
+        let frame = Frame::Array(vec![
+            Frame::Bulk(Bytes::from_static(b"KEYS")),
+            Frame::Bulk(Bytes::copy_from_slice(pattern.as_bytes())),
+        ]);
+        Command::from_frame(frame).unwrap().apply_now(db)
+    }
+
+    #[test]
+    fn reply_too_large() {
+        let mut config = Config::default();
+        config.set("max-reply-size", "1024").unwrap();
+        let db = Db::new(config);
+        for i in 0..1000 {
+            db.set(format!("key:{}", i), Bytes::from("value").into());
+        }
+        assert!(matches!(keys(&db, "*"), Frame::Error(e) if e == REPLY_TOO_LARGE));
+        assert!(matches!(keys(&db, "key:1"), Frame::Array(keys) if keys.len() == 1));
+    }
+
+    #[test]
+    fn sorted_keys() {
+        let mut config = Config::default();
+        config.set("keys-sorted", "yes").unwrap();
+        let db = Db::new(config);
+        for key in ["b", "c", "a", "ab", "key:2", "key:10"] {
+            db.set(key.to_string(), Bytes::from("value").into());
+        }
+        let expected = ["a", "ab", "b", "c", "key:10", "key:2"];
+        match keys(&db, "*") {
+            Frame::Array(keys) => {
+                assert_eq!(keys.len(), expected.len());
+                for (key, expected) in keys.iter().zip(expected) {
+                    assert!(*key == expected, "{:?} != {}", key, expected);
+                }
+            }
+            frame => panic!("unexpected reply {:?}", frame),
+        }
+    }
+
+    #[tokio::test]
+    async fn concurrent_get_makes_progress_during_a_large_keys() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        use std::sync::Arc;
+
+        let db = Db::new(Config::default());
+        for i in 0..YIELD_EVERY * 20 {
+            db.set(format!("key:{}", i), Bytes::from("value").into());
+        }
+        let done = Arc::new(AtomicBool::new(false));
+        //单线程的运行时上，KEYS不让出执行权时GET只能等它执行完
+        let keys = tokio::spawn({
+            let (db, done) = (db.clone(), done.clone());
+            async move {
+                let reply = Keys {
+                    pattern: Bytes::from_static(b"*"),
+                }
+                .apply(&db)
+                .await;
+                done.store(true, Ordering::SeqCst);
+                reply
+            }
+        });
+        let get = tokio::spawn({
+            let (db, done) = (db.clone(), done.clone());
+            async move {
+                let value = db.get("key:0").unwrap();
+                (value, done.load(Ordering::SeqCst))
+            }
+        });
+        let (value, keys_done) = get.await.unwrap();
+        assert_eq!(value, Some(Bytes::from("value")));
+        assert!(!keys_done);
+        assert!(
+            matches!(keys.await.unwrap(), Frame::Array(keys) if keys.len() == YIELD_EVERY * 20)
+        );
+    }
+}
@@ -0,0 +1,21 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+
+///获取上次成功保存快照的时间
+///
+/// LASTSAVE
+///
+/// 回复unix时间戳（秒），还没有保存过时是服务器启动的时刻
+#[derive(Debug)]
+pub struct LastSave;
+
+impl LastSave {
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<LastSave, ParseError> {
+        Ok(LastSave)
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        Frame::Integer(db.saves().last_save() as i64)
+    }
+}
@@ -0,0 +1,87 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use bytes::Bytes;
+
+///查看延迟监控记录的尖峰
+///
+/// LATENCY HISTORY event | LATENCY LATEST | LATENCY RESET [event [event ...]]
+///
+/// HISTORY返回事件的每个样本的时间戳与延迟（毫秒）；LATEST返回每个事件的名称、
+/// 最近一次尖峰的时间戳与延迟以及记录以来最大的延迟；RESET回复清除的事件数
+#[derive(Debug)]
+pub struct Latency {
+    op: Op,
+}
+
+///LATENCY的子命令
+#[derive(Debug)]
+enum Op {
+    ///事件的所有样本
+    History(String),
+    ///每个事件最近的尖峰
+    Latest,
+    ///清除记录，为空时清除所有事件
+    Reset(Vec<String>),
+}
+
+impl Latency {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Latency, ParseError> {
+        let sub = parse.next_string()?.to_uppercase();
+        let op = match sub.as_str() {
+            "HISTORY" => Op::History(parse.next_string()?),
+            "LATEST" => Op::Latest,
+            "RESET" => {
+                let mut events = vec![];
+                while let Some(event) = parse.next_optional_string()? {
+                    events.push(event);
+                }
+                Op::Reset(events)
+            }
+            _ => {
+                let err = format!("unknown subcommand '{}'. Try LATENCY HELP.", sub);
+                return Err(err.into());
+            }
+        };
+        Ok(Latency { op })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match self.op {
+            Op::History(event) => {
+                let samples = db
+                    .latency()
+                    .history(&event)
+                    .map(|series| series.samples)
+                    .unwrap_or_default();
+                Frame::Array(
+                    samples
+                        .into_iter()
+                        .map(|sample| {
+                            Frame::Array(vec![
+                                Frame::Integer(sample.time as i64),
+                                Frame::Integer(sample.latency as i64),
+                            ])
+                        })
+                        .collect(),
+                )
+            }
+            Op::Latest => Frame::Array(
+                db.latency()
+                    .all()
+                    .into_iter()
+                    .filter_map(|(event, series)| {
+                        let last = series.samples.back()?;
+                        Some(Frame::Array(vec![
+                            Frame::Bulk(Bytes::from_static(event.as_bytes())),
+                            Frame::Integer(last.time as i64),
+                            Frame::Integer(last.latency as i64),
+                            Frame::Integer(series.max as i64),
+                        ]))
+                    })
+                    .collect(),
+            ),
+            Op::Reset(events) => Frame::Integer(db.latency().reset(&events) as i64),
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::notify::{Class, Event};
+use crate::lib::parse::{Parse, ParseError};
+use bytes::Bytes;
+
+///在列表中第一个等于pivot的元素前或后插入元素
+///
+/// LINSERT key BEFORE | AFTER pivot element
+#[derive(Debug)]
+pub struct LInsert {
+    key: String,
+    //是否插入到pivot之前
+    before: bool,
+    pivot: Bytes,
+    value: Bytes,
+}
+
+impl LInsert {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<LInsert, ParseError> {
+        let key = parse.next_string()?;
+        let before = match parse.next_string()?.to_uppercase().as_str() {
+            "BEFORE" => true,
+            "AFTER" => false,
+            _ => return Err("syntax error".into()),
+        };
+        let pivot = parse.next_bytes()?;
+        let value = parse.next_bytes()?;
+        Ok(LInsert {
+            key,
+            before,
+            pivot,
+            value,
+        })
+    }
+
+    ///回复插入后列表的长度，找不到pivot时回复-1，键不存在时回复0
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let LInsert {
+            key,
+            before,
+            pivot,
+            value,
+        } = self;
+        db.mutate_notify(key, Event::new(Class::List, "linsert"), |slot| {
+            let list = match slot.as_mut().map(|value| value.as_list_mut()) {
+                None => return (false, Frame::Integer(0)),
+                Some(Err(e)) => return (false, e.into()),
+                Some(Ok(list)) => list,
+            };
+            let index = match list.position(&pivot) {
+                Some(index) => index,
+                None => return (false, Frame::Integer(-1)),
+            };
+            let index = if before { index } else { index + 1 };
+            list.insert(index, value);
+            (true, Frame::Integer(list.len() as i64))
+        })
+    }
+}
@@ -0,0 +1,27 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+
+///获取列表的长度
+///
+/// LLEN key
+#[derive(Debug)]
+pub struct LLen {
+    key: String,
+}
+
+impl LLen {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<LLen, ParseError> {
+        let key = parse.next_string()?;
+        Ok(LLen { key })
+    }
+
+    ///键不存在时回复0
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        db.read(&self.key, |entry| match entry.value.as_list() {
+            Ok(list) => Frame::Integer(list.len() as i64),
+            Err(e) => e.into(),
+        })
+        .unwrap_or(Frame::Integer(0))
+    }
+}
@@ -0,0 +1,131 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::notify::{Class, Event};
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::value::list::List;
+use crate::lib::value::Value;
+use bytes::Bytes;
+
+///从一个列表弹出元素并插入另一个列表
+///
+/// LMOVE source destination LEFT | RIGHT LEFT | RIGHT | RPOPLPUSH source destination
+///
+/// 源与目标可以是同一个列表，此时相当于旋转列表
+#[derive(Debug)]
+pub struct LMove {
+    source: String,
+    destination: String,
+    //是否从源列表的头部弹出
+    from_left: bool,
+    //是否插入到目标列表的头部
+    to_left: bool,
+}
+
+impl LMove {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<LMove, ParseError> {
+        let source = parse.next_string()?;
+        let destination = parse.next_string()?;
+        let from_left = parse_side(parse)?;
+        let to_left = parse_side(parse)?;
+        Ok(LMove::new(source, destination, from_left, to_left))
+    }
+
+    ///RPOPLPUSH等同于LMOVE source destination RIGHT LEFT
+    pub(crate) fn parse_rpoplpush(parse: &mut Parse) -> Result<LMove, ParseError> {
+        let source = parse.next_string()?;
+        let destination = parse.next_string()?;
+        Ok(LMove::new(source, destination, false, true))
+    }
+
+    pub(super) fn new(
+        source: String,
+        destination: String,
+        from_left: bool,
+        to_left: bool,
+    ) -> LMove {
+        LMove {
+            source,
+            destination,
+            from_left,
+            to_left,
+        }
+    }
+
+    ///获取源列表的键
+    pub(super) fn source(&self) -> &String {
+        &self.source
+    }
+
+    ///以LMOVE表示的参数，记录到AOF时使用
+    pub(super) fn args(&self) -> Vec<Bytes> {
+        let side = |left| Bytes::from_static(if left { b"LEFT" } else { b"RIGHT" });
+        vec![
+            Bytes::from_static(b"LMOVE"),
+            Bytes::from(self.source.clone()),
+            Bytes::from(self.destination.clone()),
+            side(self.from_left),
+            side(self.to_left),
+        ]
+    }
+
+    ///回复被移动的元素，源列表不存在时回复Null
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        self.move_one(db).unwrap_or(Frame::Null)
+    }
+
+    ///移动一个元素，源列表不存在时返回None
+    ///
+    /// 弹出与插入在同一次键空间独占中完成，目标的类型不符时源列表保持不变
+    pub(super) fn move_one(&self, db: &Db) -> Option<Frame> {
+        let (from_left, to_left) = (self.from_left, self.to_left);
+        db.atomically(|| {
+            let destination = &self.destination;
+            let checked = db.read(destination, |entry| entry.value.as_list().map(|_| ()));
+            if let Some(Err(e)) = checked {
+                return Some(e.into());
+            }
+            let pop = Event::new(Class::List, if from_left { "lpop" } else { "rpop" });
+            let popped = db.mutate_notify(self.source.clone(), pop, |slot| {
+                let list = match slot.as_mut().map(|value| value.as_list_mut()) {
+                    None => return (false, Ok(None)),
+                    Some(Err(e)) => return (false, Err(e)),
+                    Some(Ok(list)) => list,
+                };
+                let value = if from_left {
+                    list.pop_front()
+                } else {
+                    list.pop_back()
+                };
+                (true, Ok(value))
+            });
+            let value = match popped {
+                Ok(Some(value)) => value,
+                Ok(None) => return None,
+                Err(e) => return Some(e.into()),
+            };
+            let push = Event::new(Class::List, if to_left { "lpush" } else { "rpush" });
+            db.mutate_notify(destination.clone(), push, |slot| {
+                let list = slot.get_or_insert_with(|| Value::List(List::default()));
+                //类型已在弹出前检查过
+                if let Ok(list) = list.as_list_mut() {
+                    if to_left {
+                        list.push_front(value.clone());
+                    } else {
+                        list.push_back(value.clone());
+                    }
+                }
+                (true, ())
+            });
+            Some(Frame::Bulk(value))
+        })
+    }
+}
+
+///读取LEFT或RIGHT，返回是否为LEFT
+pub(super) fn parse_side(parse: &mut Parse) -> Result<bool, ParseError> {
+    match parse.next_string()?.to_uppercase().as_str() {
+        "LEFT" => Ok(true),
+        "RIGHT" => Ok(false),
+        _ => Err("syntax error".into()),
+    }
+}
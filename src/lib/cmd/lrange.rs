@@ -0,0 +1,68 @@
+use crate::lib::db::Db;
+use crate::lib::frame::{ArrayBuilder, Frame, REPLY_TOO_LARGE};
+use crate::lib::parse::{Parse, ParseError};
+
+///获取列表中指定范围的元素
+///
+/// LRANGE key start stop
+///
+/// 下标从0开始且包含两端，负数下标从末尾开始计算，-1代表最后一个元素
+#[derive(Debug)]
+pub struct LRange {
+    key: String,
+    start: i64,
+    stop: i64,
+}
+
+impl LRange {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<LRange, ParseError> {
+        let key = parse.next_string()?;
+        let start = parse.next_int()?;
+        let stop = parse.next_int()?;
+        Ok(LRange { key, start, stop })
+    }
+
+    ///键不存在或范围为空时回复空数组，回复超出max-reply-size时回复错误
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let (start, stop) = (self.start, self.stop);
+        let mut limit = db.reply_limit();
+        db.read(&self.key, |entry| {
+            let list = match entry.value.as_list() {
+                Ok(list) => list,
+                Err(e) => return e.into(),
+            };
+            let mut frame = ArrayBuilder::new();
+            if let Some((start, stop)) = index_range(start, stop, list.len()) {
+                for value in list.range(start, stop) {
+                    if !limit.add(value.len()) {
+                        return Frame::Error(REPLY_TOO_LARGE.to_string());
+                    }
+                    frame.push(value);
+                }
+            }
+            frame.build()
+        })
+        .unwrap_or_else(Frame::array)
+    }
+}
+
+///将可能为负数的闭区间换算为合法的下标，区间为空时返回None
+///
+/// 与GETRANGE不同，终点换算后仍为负数时区间为空，而不是截断到第一个元素
+pub(super) fn index_range(start: i64, stop: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 {
+        (len + start).max(0)
+    } else {
+        start
+    };
+    let stop = if stop < 0 {
+        len + stop
+    } else {
+        stop.min(len - 1)
+    };
+    if start > stop || start >= len {
+        return None;
+    }
+    Some((start as usize, stop as usize))
+}
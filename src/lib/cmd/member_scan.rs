@@ -0,0 +1,161 @@
+use crate::lib::cmd::scan::{parse_count, parse_cursor, reply};
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::glob;
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::value::zset::format_score;
+use crate::lib::value::{Value, WrongType};
+use bytes::Bytes;
+
+//未指定COUNT时每批返回的元素数
+const DEFAULT_COUNT: usize = 10;
+
+///按游标分批遍历集合类型的值中的元素
+///
+/// HSCAN key cursor [MATCH pattern] [COUNT count] [NOVALUES]
+///
+/// SSCAN key cursor [MATCH pattern] [COUNT count]
+///
+/// ZSCAN key cursor [MATCH pattern] [COUNT count]
+///
+/// 游标的语义与SCAN相同，MATCH只匹配字段或成员
+#[derive(Debug)]
+pub struct MemberScan {
+    key: String,
+    kind: Kind,
+    cursor: u64,
+    pattern: Option<Bytes>,
+    count: usize,
+    //HSCAN只返回字段
+    no_values: bool,
+}
+
+///被遍历的值的类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Kind {
+    ///HSCAN
+    Hash,
+    ///SSCAN
+    Set,
+    ///ZSCAN
+    ZSet,
+}
+
+impl MemberScan {
+    pub(crate) fn parse_frames(parse: &mut Parse, kind: Kind) -> Result<MemberScan, ParseError> {
+        let key = parse.next_string()?;
+        let cursor = parse_cursor(parse)?;
+        let mut pattern = None;
+        let mut count = DEFAULT_COUNT;
+        let mut no_values = false;
+        loop {
+            match parse.next_string() {
+                Ok(option) => match option.to_uppercase().as_str() {
+                    "MATCH" => pattern = Some(parse.next_bytes()?),
+                    "COUNT" => count = parse_count(parse)?,
+                    "NOVALUES" if kind == Kind::Hash => no_values = true,
+                    _ => return Err("syntax error".into()),
+                },
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(MemberScan {
+            key,
+            kind,
+            cursor,
+            pattern,
+            count,
+            no_values,
+        })
+    }
+
+    ///回复下一个游标与本批的元素，哈希表与有序集合的元素后紧跟对应的值与分数
+    ///
+    /// 每批大约访问COUNT个元素，MATCH在访问之后过滤
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let mut items = vec![];
+        let cursor = match db.read(&self.key, |entry| self.scan(&entry.value, &mut items)) {
+            Some(Ok(cursor)) => cursor,
+            Some(Err(e)) => return e.into(),
+            None => 0,
+        };
+        reply(cursor, items)
+    }
+
+    //遍历一批元素，把匹配的元素与对应的值加入items，返回下一个游标
+    fn scan(&self, value: &Value, items: &mut Vec<Frame>) -> Result<u64, WrongType> {
+        let mut push = |member: Bytes, value: Option<Bytes>| {
+            if let Some(pattern) = &self.pattern {
+                if !glob::matches(pattern, &member) {
+                    return;
+                }
+            }
+            items.push(Frame::Bulk(member));
+            items.extend(value.map(Frame::Bulk));
+        };
+        let cursor = match self.kind {
+            Kind::Hash => value
+                .as_hash()?
+                .scan(self.cursor, self.count, |field, value| {
+                    push(field, Some(value).filter(|_| !self.no_values))
+                }),
+            Kind::Set => value
+                .as_set()?
+                .scan(self.cursor, self.count, |member| push(member, None)),
+            Kind::ZSet => value
+                .as_zset()?
+                .scan(self.cursor, self.count, |member, score| {
+                    push(member, Some(format_score(score)))
+                }),
+        };
+        Ok(cursor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lib::cmd::Command;
+    use crate::lib::config::Config;
+    use crate::lib::db::Db;
+    use crate::lib::frame::Frame;
+    use bytes::Bytes;
+
+    fn call(db: &Db, args: &[&str]) -> Frame {
+        let frame = Frame::Array(
+            args.iter()
+                .map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes())))
+                .collect(),
+        );
+        Command::from_frame(frame).unwrap().apply_now(db)
+    }
+
+    //一次遍历完整个值，返回其中的元素
+    fn scan_all(db: &Db, args: &[&str]) -> Vec<Frame> {
+        match call(db, args) {
+            Frame::Array(mut reply) => match (reply.pop(), reply.pop()) {
+                (Some(Frame::Array(items)), Some(cursor)) => {
+                    assert!(cursor == "0");
+                    items
+                }
+                reply => panic!("unexpected reply {:?}", reply),
+            },
+            frame => panic!("unexpected reply {:?}", frame),
+        }
+    }
+
+    #[test]
+    fn hscan_with_novalues_returns_only_fields() {
+        let db = Db::new(Config::default());
+        call(&db, &["HSET", "hash", "a", "1", "b", "2"]);
+        let mut items = scan_all(&db, &["HSCAN", "hash", "0", "COUNT", "100"]);
+        assert_eq!(items.len(), 4);
+        items = scan_all(&db, &["HSCAN", "hash", "0", "COUNT", "100", "NOVALUES"]);
+        let mut fields: Vec<String> = items.iter().map(ToString::to_string).collect();
+        fields.sort();
+        assert_eq!(fields, ["a", "b"]);
+        //NOVALUES只属于HSCAN
+        let sscan = ["SSCAN", "set", "0", "NOVALUES"].map(|arg| Frame::Bulk(arg.into()));
+        assert!(Command::from_frame(Frame::Array(sscan.to_vec())).is_err());
+    }
+}
@@ -0,0 +1,178 @@
+use crate::lib::allocator;
+use crate::lib::db::{self, Db};
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use bytes::Bytes;
+
+///查看内存占用
+///
+/// MEMORY USAGE key [SAMPLES count] | MEMORY STATS | MEMORY DOCTOR
+///
+/// USAGE回复键估算占用的字节数，集合类型按前count个元素推算，count为0时统计全部元素，键不存在时回复nil；
+/// STATS回复分配器与键空间的各项统计；DOCTOR回复一段诊断内存问题的文字
+#[derive(Debug)]
+pub struct Memory {
+    op: Op,
+}
+
+///MEMORY的子命令
+#[derive(Debug)]
+enum Op {
+    ///键与抽样的元素数
+    Usage(String, usize),
+    ///汇总统计
+    Stats,
+    ///诊断
+    Doctor,
+}
+
+impl Memory {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Memory, ParseError> {
+        let sub = parse.next_string()?.to_uppercase();
+        let op = match sub.as_str() {
+            "USAGE" => {
+                let key = parse.next_string()?;
+                let samples = if parse.next_keyword("SAMPLES") {
+                    match parse.next_int()? {
+                        count if count >= 0 => count as usize,
+                        _ => return Err("value is out of range, must be positive".into()),
+                    }
+                } else {
+                    db::SIZE_SAMPLES
+                };
+                Op::Usage(key, samples)
+            }
+            "STATS" => Op::Stats,
+            "DOCTOR" => Op::Doctor,
+            _ => {
+                let err = format!("unknown subcommand '{}'. Try MEMORY HELP.", sub);
+                return Err(err.into());
+            }
+        };
+        Ok(Memory { op })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match self.op {
+            Op::Usage(key, samples) => db
+                .read(&key, |entry| db::footprint(&key, &entry.value, samples))
+                .map_or(Frame::Null, |size| Frame::Integer(size as i64)),
+            Op::Stats => Frame::Map(stats(db)),
+            Op::Doctor => Frame::Bulk(Bytes::from(doctor(db))),
+        }
+    }
+}
+
+//MEMORY STATS的各项，非空的逻辑数据库各占一项
+fn stats(db: &Db) -> Vec<(Frame, Frame)> {
+    let field = |name: &str| Frame::Bulk(Bytes::from(name.to_string()));
+    let peak = allocator::peak();
+    let total = allocator::allocated();
+    let dataset = db.used_memory();
+    let databases: Vec<Db> = (0..db.databases())
+        .filter_map(|index| db.select(index))
+        .filter(|db| db.key_count() > 0)
+        .collect();
+    let keys: usize = databases.iter().map(Db::key_count).sum();
+    let mut pairs = vec![
+        (field("peak.allocated"), Frame::Integer(peak as i64)),
+        (field("total.allocated"), Frame::Integer(total as i64)),
+        (
+            field("overhead.total"),
+            Frame::Integer(total.saturating_sub(dataset) as i64),
+        ),
+    ];
+    for db in &databases {
+        pairs.push((
+            field(&format!("db.{}", db.index())),
+            Frame::Map(vec![
+                (field("keys"), Frame::Integer(db.key_count() as i64)),
+                (
+                    field("dataset.bytes"),
+                    Frame::Integer(db.dataset_memory() as i64),
+                ),
+            ]),
+        ));
+    }
+    pairs.extend([
+        (field("keys.count"), Frame::Integer(keys as i64)),
+        (
+            field("keys.bytes-per-key"),
+            Frame::Integer(dataset.checked_div(keys).unwrap_or(0) as i64),
+        ),
+        (field("dataset.bytes"), Frame::Integer(dataset as i64)),
+        (
+            field("dataset.percentage"),
+            Frame::Double(percentage(dataset, total)),
+        ),
+        (
+            field("peak.percentage"),
+            Frame::Double(percentage(total, peak)),
+        ),
+    ]);
+    pairs
+}
+
+//part占whole的百分比，whole为0时为0
+fn percentage(part: usize, whole: usize) -> f64 {
+    match whole {
+        0 => 0.0,
+        whole => part as f64 * 100.0 / whole as f64,
+    }
+}
+
+//逐项检查常见的内存问题，生成诊断报告
+fn doctor(db: &Db) -> String {
+    let dataset = db.used_memory();
+    if dataset == 0 {
+        return "This instance is empty, there is nothing to diagnose. \
+            Run MEMORY DOCTOR again after some data has been written.\n"
+            .to_string();
+    }
+    let peak = allocator::peak();
+    let total = allocator::allocated();
+    let (max, policy) = {
+        let config = db.config();
+        (
+            config.max_memory() as usize,
+            config.max_memory_policy().to_string(),
+        )
+    };
+    let mut issues = vec![];
+    if total > 0 && peak > total / 2 * 3 {
+        issues.push(
+            "Peak memory: at some point in the past the allocated memory was more than \
+            150% of the current amount. The process keeps part of the freed memory, \
+            so the resident size may be higher than expected."
+                .to_string(),
+        );
+    }
+    if total > 0 && dataset < total / 2 {
+        issues.push(format!(
+            "High overhead: keys and values account for only {:.2}% of the allocated memory. \
+            Connection buffers, replication and scripts use the rest.",
+            percentage(dataset, total)
+        ));
+    }
+    if max > 0 && dataset > max / 10 * 9 {
+        let outcome = if policy == "noeviction" {
+            "writes will be rejected with OOM errors"
+        } else {
+            "keys will be evicted"
+        };
+        issues.push(format!(
+            "Near maxmemory: the dataset uses {:.2}% of maxmemory, and with the {} policy {}.",
+            percentage(dataset, max),
+            policy,
+            outcome
+        ));
+    }
+    if issues.is_empty() {
+        return "No memory issues were found in this instance.\n".to_string();
+    }
+    let mut report = "The following memory issues were found:\n\n".to_string();
+    for issue in issues {
+        report.push_str(&format!(" * {}\n\n", issue));
+    }
+    report
+}
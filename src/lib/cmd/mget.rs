@@ -0,0 +1,39 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+
+///批量获取键对应的值
+///
+/// MGET key [key ...]
+#[derive(Debug)]
+pub struct MGet {
+    keys: Vec<String>,
+}
+
+impl MGet {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<MGet, ParseError> {
+        let mut keys = vec![parse.next_string()?];
+        loop {
+            match parse.next_string() {
+                Ok(key) => keys.push(key),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(MGet { keys })
+    }
+
+    ///按顺序回复每个键的值，不存在或存储的不是字符串的键回复Null，命中率按键分别统计
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let values = self
+            .keys
+            .iter()
+            .map(|key| {
+                let value = db.read(key, |entry| entry.value.as_string().ok());
+                db.record_lookup(value.is_some());
+                value.flatten().map_or(Frame::Null, Frame::Bulk)
+            })
+            .collect();
+        Frame::Array(values)
+    }
+}
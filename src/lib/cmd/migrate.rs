@@ -0,0 +1,240 @@
+use crate::lib::db::{now_millis, Db, Update};
+use crate::lib::frame::Frame;
+use crate::lib::notify::Class;
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::propagate;
+use crate::lib::upstream::Upstream;
+use crate::lib::value::dump;
+use bytes::Bytes;
+use std::time::Duration;
+
+///把键迁移到另一个服务器
+///
+/// MIGRATE host port key|"" destination-db timeout [COPY] [REPLACE] [AUTH password]
+/// [AUTH2 username password] [KEYS key [key ...]]
+///
+/// 以DUMP的格式序列化键，作为客户端连接目标服务器并以RESTORE写入，写入成功后删除本地的键。
+/// 带COPY时保留本地的键，带REPLACE时覆盖目标服务器上已有的键。timeout是连接与每次读取回复的超时（毫秒）
+#[derive(Debug)]
+pub struct Migrate {
+    host: String,
+    port: u16,
+    keys: Vec<String>,
+    db: i64,
+    timeout: Duration,
+    copy: bool,
+    replace: bool,
+    //用户名与密码，只给出密码时以default用户认证
+    auth: Option<(Option<String>, String)>,
+}
+
+//timeout不是正数时使用的超时（毫秒）
+const DEFAULT_TIMEOUT: u64 = 1000;
+
+impl Migrate {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Migrate, ParseError> {
+        let host = parse.next_string()?;
+        let port = parse
+            .next_string()?
+            .parse()
+            .map_err(|_| ParseError::NotInteger)?;
+        let key = parse.next_string()?;
+        let db = parse.next_int()?;
+        let timeout = match parse.next_int()? {
+            timeout if timeout > 0 => timeout as u64,
+            _ => DEFAULT_TIMEOUT,
+        };
+        let mut migrate = Migrate {
+            host,
+            port,
+            keys: vec![],
+            db,
+            timeout: Duration::from_millis(timeout),
+            copy: false,
+            replace: false,
+            auth: None,
+        };
+        loop {
+            let option = match parse.next_string() {
+                Ok(option) => option.to_uppercase(),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e),
+            };
+            match option.as_str() {
+                "COPY" => migrate.copy = true,
+                "REPLACE" => migrate.replace = true,
+                "AUTH" => migrate.auth = Some((None, parse.next_string()?)),
+                "AUTH2" => {
+                    let username = parse.next_string()?;
+                    migrate.auth = Some((Some(username), parse.next_string()?));
+                }
+                "KEYS" => {
+                    if !key.is_empty() {
+                        let err = "When using MIGRATE KEYS option, \
+                                   the key argument must be set to the empty string";
+                        return Err(err.into());
+                    }
+                    //KEYS之后的参数都是键
+                    while let Some(key) = parse.next_optional_string()? {
+                        migrate.keys.push(key);
+                    }
+                }
+                _ => return Err("syntax error".into()),
+            }
+        }
+        if !key.is_empty() {
+            migrate.keys.push(key);
+        }
+        Ok(migrate)
+    }
+
+    ///全部写入成功时回复OK，要迁移的键都不存在时回复NOKEY
+    ///
+    /// 目标服务器对部分键回复错误时，写入成功的键仍然从本地删除，回复第一个错误
+    pub(crate) async fn apply(self, db: &Db) -> Frame {
+        //读出各个键序列化的值、剩余的过期时间（毫秒，0代表不过期）与版本号，不存在的键跳过
+        let now = now_millis();
+        let entries: Vec<(&String, Bytes, u64, u64)> = self
+            .keys
+            .iter()
+            .filter_map(|key| {
+                db.read(key, |entry| {
+                    let ttl = entry
+                        .expires_at
+                        .map_or(0, |at| at.saturating_sub(now).max(1));
+                    (key, dump::serialize(&entry.value), ttl, entry.version)
+                })
+            })
+            .collect();
+        if entries.is_empty() {
+            return Frame::Simple("NOKEY".to_string());
+        }
+        //目标服务器在集群模式下正在迁入这些键所在的槽，每个RESTORE之前发送ASKING
+        let asking = db.cluster().is_some();
+        let (restored, error) = match self.transfer(&entries, asking).await {
+            Ok(result) => result,
+            Err(e) => return Frame::Error(e),
+        };
+        if !self.copy && !restored.is_empty() {
+            remove(db, &restored);
+        }
+        match error {
+            Some(e) => Frame::Error(format!("ERR Target instance replied with error: {}", e)),
+            None => Frame::Simple("OK".to_string()),
+        }
+    }
+
+    ///不等待地执行，迁移需要与目标服务器通信，事务与脚本中不能执行
+    pub(crate) fn apply_now(self, _db: &Db) -> Frame {
+        Frame::Error("ERR MIGRATE is not allowed in this context".to_string())
+    }
+
+    //把键写入目标服务器，返回写入成功的键与版本号，以及目标服务器对RESTORE回复的第一个错误
+    async fn transfer<'a>(
+        &self,
+        entries: &[(&'a String, Bytes, u64, u64)],
+        asking: bool,
+    ) -> Result<(Vec<(&'a String, u64)>, Option<String>), String> {
+        let mut upstream = Upstream::connect(&self.host, self.port, self.timeout)
+            .await
+            .map_err(|_| "IOERR error or timeout connecting to the client".to_string())?;
+        let target = |e| format!("ERR Target instance replied with error: {}", e);
+        if let Some((username, password)) = &self.auth {
+            let reply = match username {
+                Some(username) => upstream.call(&["AUTH", username, password]).await,
+                None => upstream.call(&["AUTH", password]).await,
+            };
+            if let Frame::Error(e) = reply.map_err(io_error)? {
+                return Err(target(e));
+            }
+        }
+        let db = self.db.to_string();
+        if let Frame::Error(e) = upstream.call(&["SELECT", &db]).await.map_err(io_error)? {
+            return Err(target(e));
+        }
+        //RESTORE一次性全部发出，再按顺序读取回复
+        for (key, payload, ttl, _) in entries {
+            if asking {
+                upstream.send(&["ASKING"]).await.map_err(io_error)?;
+            }
+            let ttl = ttl.to_string();
+            let mut args: Vec<&[u8]> = vec![b"RESTORE", key.as_bytes(), ttl.as_bytes(), payload];
+            if self.replace {
+                args.push(b"REPLACE");
+            }
+            upstream.send(&args).await.map_err(io_error)?;
+        }
+        let mut restored = vec![];
+        let mut error = None;
+        for (key, _, _, version) in entries {
+            if asking {
+                upstream.reply().await.map_err(io_error)?;
+            }
+            match upstream.reply().await.map_err(io_error)? {
+                Frame::Error(e) => {
+                    error.get_or_insert(e);
+                }
+                _ => restored.push((*key, *version)),
+            }
+        }
+        Ok((restored, error))
+    }
+}
+
+//删除已经写入目标服务器的键，迁移期间被修改过的键保留
+//
+// 版本号的比较与删除在同一次分片锁内完成，不会删掉比较之后才被其他连接写入的新值
+fn remove(db: &Db, restored: &[(&String, u64)]) {
+    propagate::record(
+        db,
+        || {
+            let mut removed = vec![];
+            for &(key, version) in restored {
+                let unchanged = db.update(key.clone(), |entry| match entry {
+                    Some(entry) if entry.version == version => (Update::Remove, true),
+                    _ => (Update::Keep, false),
+                });
+                if unchanged {
+                    db.notify(Class::Generic, "del", key);
+                    removed.push(Bytes::from(key.clone()));
+                }
+            }
+            removed
+        },
+        //其他连接同时触发的过期也会改变修改次数，没有删除任何键时不能传播不带键的DEL
+        |removed| {
+            if removed.is_empty() {
+                return None;
+            }
+            let mut args = vec![Bytes::from_static(b"DEL")];
+            args.extend(removed.iter().cloned());
+            Some(args)
+        },
+    );
+}
+
+//与目标服务器收发数据失败或超时
+fn io_error<E>(_: E) -> String {
+    "IOERR error or timeout reading to target instance".to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::config::Config;
+
+    #[test]
+    fn keys_modified_during_the_transfer_are_kept() {
+        let db = Db::new(Config::default());
+        let (a, b) = ("a".to_string(), "b".to_string());
+        db.set(a.clone(), Bytes::from("1").into());
+        db.set(b.clone(), Bytes::from("1").into());
+        let version = |key: &str| db.version(key).unwrap();
+        let restored = [(&a, version("a")), (&b, version("b"))];
+        //b在迁移期间被改写
+        db.set(b.clone(), Bytes::from("2").into());
+        remove(&db, &restored);
+        assert!(!db.exists("a"));
+        assert_eq!(db.get("b").unwrap(), Some(Bytes::from("2")));
+    }
+}
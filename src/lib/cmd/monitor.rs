@@ -0,0 +1,108 @@
+use crate::lib;
+use crate::lib::cmd::Exit;
+use crate::lib::conn::{Connection, Stream};
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::shutdown::Shutdown;
+use bytes::Bytes;
+use std::fmt::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast::error::RecvError;
+
+///监视服务器执行的命令
+///
+/// MONITOR
+///
+/// 回复OK后连接进入监视模式，之后任何连接执行的命令都以一行简单字符串推送过来，
+/// 格式为“时间戳 [数据库 地址] "命令" "参数" ...”。AUTH与HELLO带有密码，不会被推送。
+/// 监视模式下只接受QUIT与RESET
+#[derive(Debug)]
+pub struct Monitor;
+
+impl Monitor {
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<Monitor, ParseError> {
+        Ok(Monitor)
+    }
+
+    ///进入监视模式，直到连接关闭、服务器关闭或收到QUIT、RESET才返回
+    pub(crate) async fn apply<S: Stream>(
+        self,
+        db: &Db,
+        conn: &mut Connection<S>,
+        shutdown: &mut Shutdown,
+    ) -> lib::Result<Exit> {
+        let mut lines = db.monitor();
+        conn.write_frame(&Frame::Simple("OK".to_string())).await?;
+        loop {
+            tokio::select! {
+                _ = shutdown.recv() => return Ok(Exit::Done),
+                line = lines.recv() => match line {
+                    Ok(line) => conn.write_frame(&Frame::Simple(line)).await?,
+                    //落后太多时跳过丢失的命令
+                    Err(RecvError::Lagged(_)) => {}
+                    Err(RecvError::Closed) => return Ok(Exit::Done),
+                },
+                frame = conn.read_frame() => {
+                    let frame = match frame? {
+                        Some(frame) => frame,
+                        None => return Ok(Exit::Done),
+                    };
+                    let mut parse = Parse::new(frame)?;
+                    let name = parse.next_string()?.to_lowercase();
+                    match name.as_str() {
+                        "quit" => return Ok(Exit::Quit),
+                        "reset" => return Ok(Exit::Reset),
+                        _ => {
+                            let err = format!(
+                                "ERR Can't execute '{}': only QUIT and RESET are allowed in MONITOR mode",
+                                name
+                            );
+                            conn.write_frame(&Frame::Error(err)).await?;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+///推送给监视连接的一行，args为包括命令名在内的参数
+pub(crate) fn line(db: usize, addr: &str, args: &[Bytes]) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let mut line = format!(
+        "{}.{:06} [{} {}]",
+        now.as_secs(),
+        now.subsec_micros(),
+        db,
+        addr
+    );
+    for arg in args {
+        line.push(' ');
+        quote(&mut line, arg);
+    }
+    line
+}
+
+//以带引号、转义不可见字符的形式输出参数
+fn quote(out: &mut String, arg: &[u8]) {
+    out.push('"');
+    for &b in arg {
+        match b {
+            b'\\' => out.push_str("\\\\"),
+            b'"' => out.push_str("\\\""),
+            b'\n' => out.push_str("\\n"),
+            b'\r' => out.push_str("\\r"),
+            b'\t' => out.push_str("\\t"),
+            0x07 => out.push_str("\\a"),
+            0x08 => out.push_str("\\b"),
+            b' '..=b'~' => out.push(b as char),
+            _ => {
+                let _ = write!(out, "\\x{:02x}", b);
+            }
+        }
+    }
+    out.push('"');
+}
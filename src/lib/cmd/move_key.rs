@@ -0,0 +1,54 @@
+use crate::lib::cmd::select::select;
+use crate::lib::db::{Db, Update};
+use crate::lib::frame::Frame;
+use crate::lib::notify::{Class, Event};
+use crate::lib::parse::{Parse, ParseError};
+
+///将键移动到另一个逻辑数据库
+///
+/// MOVE key db
+///
+/// 值与过期时间一起移动，目标数据库中已存在同名的键时不移动
+#[derive(Debug)]
+pub struct MoveKey {
+    key: String,
+    target: i64,
+}
+
+impl MoveKey {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<MoveKey, ParseError> {
+        let key = parse.next_string()?;
+        let target = parse.next_int()?;
+        Ok(MoveKey { key, target })
+    }
+
+    ///回复是否移动
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let target = match select(db, self.target) {
+            Ok(target) => target,
+            Err(e) => return e,
+        };
+        if target.index() == db.index() {
+            return Frame::Error("ERR source and destination objects are the same".to_string());
+        }
+        db.atomically(|| {
+            if target.exists(&self.key) {
+                return Frame::Integer(0);
+            }
+            let entry = match db.remove(&self.key) {
+                Some(entry) => entry,
+                None => return Frame::Integer(0),
+            };
+            db.notify(Class::Generic, "move_from", &self.key);
+            let event = Event::new(Class::Generic, "move_to");
+            target.update_notify(self.key.clone(), event, |_| {
+                let update = Update::Set {
+                    value: entry.value,
+                    expires_at: entry.expires_at,
+                };
+                (update, ())
+            });
+            Frame::Integer(1)
+        })
+    }
+}
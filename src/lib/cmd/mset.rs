@@ -0,0 +1,70 @@
+use crate::lib::db::{now_millis, Db, Update};
+use crate::lib::frame::Frame;
+use crate::lib::notify::Class;
+use crate::lib::parse::{Parse, ParseError};
+use bytes::Bytes;
+
+///批量设置键值
+///
+/// MSET key value [key value ...] | MSETNX key value [key value ...]
+///
+/// 所有键在同一次加锁中写入，其他连接不会看到只写入了一部分的状态。
+/// 配置了default-ttl时写入的键都带有该生存时间，否则清除原有的过期时间。
+/// 键都落在存储的同一个分片上时（例如带有相同的哈希标签）只锁住该分片，否则独占整个键空间
+#[derive(Debug)]
+pub struct MSet {
+    pairs: Vec<(String, Bytes)>,
+    //是否只在所有键都不存在时才写入
+    nx: bool,
+}
+
+impl MSet {
+    pub(crate) fn parse_frames(parse: &mut Parse, nx: bool) -> Result<MSet, ParseError> {
+        let mut pairs = vec![(parse.next_string()?, parse.next_bytes()?)];
+        loop {
+            let key = match parse.next_string() {
+                Ok(key) => key,
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e),
+            };
+            //键值必须成对出现
+            let value = parse.next_bytes()?;
+            pairs.push((key, value));
+        }
+        Ok(MSet { pairs, nx })
+    }
+
+    ///MSET总是回复OK，MSETNX全部写入时回复1，任意一个键已存在时不写入并回复0
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let MSet { pairs, nx } = self;
+        let expires_at = db
+            .config()
+            .default_ttl()
+            .map(|ttl| now_millis().saturating_add(ttl));
+        let values = pairs
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone().into()))
+            .collect();
+        let written = db.set_many(values, nx, expires_at).unwrap_or_else(|| {
+            db.atomically(|| {
+                if nx && pairs.iter().any(|(key, _)| db.exists(key)) {
+                    return false;
+                }
+                for (key, value) in &pairs {
+                    let value = value.clone().into();
+                    db.update(key.clone(), |_| (Update::Set { value, expires_at }, ()));
+                }
+                true
+            })
+        });
+        if written {
+            for (key, _) in &pairs {
+                db.notify(Class::String, "set", key);
+            }
+        }
+        match (nx, written) {
+            (true, written) => Frame::Integer(written as i64),
+            (false, _) => Frame::Simple("OK".to_string()),
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use bytes::Bytes;
+
+///查看键的内部信息
+///
+/// OBJECT ENCODING key
+///
+/// 回复值当前使用的内部编码，键不存在时回复nil
+#[derive(Debug)]
+pub struct Object {
+    key: String,
+}
+
+impl Object {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Object, ParseError> {
+        let sub = parse.next_string()?.to_uppercase();
+        if sub != "ENCODING" {
+            let err = format!("unknown subcommand '{}'. Try OBJECT HELP.", sub);
+            return Err(err.into());
+        }
+        let key = parse.next_string()?;
+        Ok(Object { key })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        db.read(&self.key, |entry| entry.value.encoding())
+            .map_or(Frame::Null, |encoding| Frame::Bulk(Bytes::from(encoding)))
+    }
+}
@@ -0,0 +1,33 @@
+use crate::lib::db::{Db, Update};
+use crate::lib::frame::Frame;
+use crate::lib::notify::{Class, Event};
+use crate::lib::parse::{Parse, ParseError};
+
+///清除键的过期时间
+///
+/// PERSIST key
+#[derive(Debug)]
+pub struct Persist {
+    key: String,
+}
+
+impl Persist {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Persist, ParseError> {
+        let key = parse.next_string()?;
+        Ok(Persist { key })
+    }
+
+    ///清除成功回复1，键不存在或没有过期时间回复0
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        db.update_notify(
+            self.key,
+            Event::new(Class::Generic, "persist"),
+            |cur| match cur {
+                Some(entry) if entry.expires_at.is_some() => {
+                    (Update::SetExpire(None), Frame::Integer(1))
+                }
+                _ => (Update::Keep, Frame::Integer(0)),
+            },
+        )
+    }
+}
@@ -0,0 +1,57 @@
+use crate::lib::db::{Db, Update};
+use crate::lib::frame::Frame;
+use crate::lib::notify::{Class, Event};
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::value::hll::{self, Hll};
+use bytes::Bytes;
+
+///向HyperLogLog中添加元素
+///
+/// PFADD key [element [element ...]]
+///
+/// 键不存在时创建一个空的HyperLogLog，原有的过期时间保持不变
+#[derive(Debug)]
+pub struct PfAdd {
+    key: String,
+    elements: Vec<Bytes>,
+}
+
+impl PfAdd {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<PfAdd, ParseError> {
+        let key = parse.next_string()?;
+        let mut elements = vec![];
+        while let Some(element) = parse.next_optional_bytes()? {
+            elements.push(element);
+        }
+        Ok(PfAdd { key, elements })
+    }
+
+    ///有寄存器被更新或新建了键时回复1，否则回复0
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let elements = self.elements;
+        db.update_notify(self.key, Event::new(Class::String, "pfadd"), |cur| {
+            let (mut hll, expires_at) = match cur {
+                None => (Hll::new(), None),
+                Some(entry) => match entry.value.as_string() {
+                    Ok(data) => match Hll::decode(&data) {
+                        Some(hll) => (hll, entry.expires_at),
+                        None => return (Update::Keep, Frame::Error(hll::INVALID.to_string())),
+                    },
+                    Err(e) => return (Update::Keep, e.into()),
+                },
+            };
+            let mut changed = cur.is_none();
+            for element in &elements {
+                changed |= hll.add(element);
+            }
+            if !changed {
+                return (Update::Keep, Frame::Integer(0));
+            }
+            let update = Update::Set {
+                value: hll.encode().into(),
+                expires_at,
+            };
+            (update, Frame::Integer(1))
+        })
+    }
+}
@@ -0,0 +1,100 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::value::hll::{self, Hll};
+
+///估算HyperLogLog的基数
+///
+/// PFCOUNT key [key ...]
+///
+/// 给出多个键时估算它们并集的基数，不存在的键视为空的HyperLogLog
+#[derive(Debug)]
+pub struct PfCount {
+    keys: Vec<String>,
+}
+
+impl PfCount {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<PfCount, ParseError> {
+        let mut keys = vec![parse.next_string()?];
+        while let Some(key) = parse.next_optional_string()? {
+            keys.push(key);
+        }
+        Ok(PfCount { keys })
+    }
+
+    ///回复估算的基数
+    ///
+    /// 单个键时直接使用写入时缓存在头部的基数，多个键时在同一次键空间独占中读取并合并
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        if let [key] = self.keys.as_slice() {
+            return match db.get(key) {
+                Ok(None) => Frame::Integer(0),
+                Ok(Some(data)) => match Hll::cached_count(&data) {
+                    Some(count) => Frame::Integer(count as i64),
+                    None => match Hll::decode(&data) {
+                        Some(hll) => Frame::Integer(hll.count() as i64),
+                        None => Frame::Error(hll::INVALID.to_string()),
+                    },
+                },
+                Err(e) => e.into(),
+            };
+        }
+        db.atomically(|| match union(db, &self.keys) {
+            Ok(hll) => Frame::Integer(hll.count() as i64),
+            Err(e) => e,
+        })
+    }
+}
+
+///合并多个键中的HyperLogLog，不存在的键被跳过
+pub(super) fn union(db: &Db, keys: &[String]) -> Result<Hll, Frame> {
+    let mut union = Hll::new();
+    for key in keys {
+        match db.get(key) {
+            Ok(Some(data)) => match Hll::decode(&data) {
+                Some(hll) => union.merge(&hll),
+                None => return Err(Frame::Error(hll::INVALID.to_string())),
+            },
+            Ok(None) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(union)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::cmd::Command;
+    use crate::lib::config::Config;
+    use crate::lib::value::Value;
+    use bytes::Bytes;
+
+    fn call(db: &Db, args: &[&str]) -> Frame {
+        let frame = Frame::Array(
+            args.iter()
+                .map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes())))
+                .collect(),
+        );
+        Command::from_frame(frame).unwrap().apply_now(db)
+    }
+
+    #[test]
+    fn corrupted_dense_registers() {
+        let db = Db::new(Config::default());
+        call(&db, &["PFADD", "hll", "a", "b", "c"]);
+        //头部的基数缓存标记为失效，第一个寄存器写入63
+        let mut data = b"HYLL".to_vec();
+        data.extend_from_slice(&[0, 0, 0, 0]);
+        data.extend_from_slice(&(1u64 << 63).to_le_bytes());
+        let mut body = vec![0; (16384 * 6) / 8];
+        body[0] = 0x3f;
+        data.extend_from_slice(&body);
+        db.set("corrupted".to_string(), Value::String(Bytes::from(data)));
+        let invalid = |frame: Frame| matches!(frame, Frame::Error(e) if e == hll::INVALID);
+        assert!(invalid(call(&db, &["PFCOUNT", "corrupted"])));
+        assert!(invalid(call(&db, &["PFCOUNT", "corrupted", "hll"])));
+        assert!(invalid(call(&db, &["PFMERGE", "hll", "corrupted"])));
+        assert_eq!(call(&db, &["PFCOUNT", "hll"]), Frame::Integer(3));
+    }
+}
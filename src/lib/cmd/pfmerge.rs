@@ -0,0 +1,48 @@
+use crate::lib::cmd::pfcount::union;
+use crate::lib::db::{Db, Update};
+use crate::lib::frame::Frame;
+use crate::lib::notify::{Class, Event};
+use crate::lib::parse::{Parse, ParseError};
+
+///将多个HyperLogLog合并到目标键
+///
+/// PFMERGE destkey [sourcekey [sourcekey ...]]
+///
+/// 目标键原有的HyperLogLog同样参与合并，原有的过期时间保持不变
+#[derive(Debug)]
+pub struct PfMerge {
+    dest: String,
+    sources: Vec<String>,
+}
+
+impl PfMerge {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<PfMerge, ParseError> {
+        let dest = parse.next_string()?;
+        let mut sources = vec![];
+        while let Some(key) = parse.next_optional_string()? {
+            sources.push(key);
+        }
+        Ok(PfMerge { dest, sources })
+    }
+
+    ///回复OK
+    ///
+    /// 读取源键与写入目标键在同一次键空间独占中完成
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let PfMerge { dest, mut sources } = self;
+        sources.push(dest.clone());
+        db.atomically(|| {
+            let merged = match union(db, &sources) {
+                Ok(merged) => merged,
+                Err(e) => return e,
+            };
+            db.update_notify(dest, Event::new(Class::String, "pfmerge"), |cur| {
+                let update = Update::Set {
+                    value: merged.encode().into(),
+                    expires_at: cur.and_then(|entry| entry.expires_at),
+                };
+                (update, Frame::Simple("OK".to_string()))
+            })
+        })
+    }
+}
@@ -0,0 +1,63 @@
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use bytes::Bytes;
+
+///检查连接是否可用
+///
+/// PING [message]
+///
+/// 没有参数时回复PONG，否则原样回复message。订阅模式下的PING由订阅的处理循环回复
+#[derive(Debug)]
+pub struct Ping {
+    message: Option<Bytes>,
+}
+
+impl Ping {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Ping, ParseError> {
+        let message = match parse.next_bytes() {
+            Ok(message) => Some(message),
+            Err(ParseError::EndOfStream) => None,
+            Err(e) => return Err(e),
+        };
+        Ok(Ping { message })
+    }
+
+    pub(crate) fn apply(self) -> Frame {
+        match self.message {
+            Some(message) => Frame::Bulk(message),
+            None => Frame::Simple("PONG".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lib::cmd::Command;
+    use crate::lib::config::Config;
+    use crate::lib::db::Db;
+    use crate::lib::frame::Frame;
+    use bytes::Bytes;
+
+    fn run(db: &Db, args: &[&str]) -> Frame {
+        let frame = Frame::Array(
+            args.iter()
+                .map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes())))
+                .collect(),
+        );
+        match Command::from_frame(frame) {
+            Ok(cmd) => cmd.apply_now(db),
+            Err(e) => e.into(),
+        }
+    }
+
+    #[test]
+    fn ping_replies_pong_or_the_message() {
+        let db = Db::new(Config::default());
+        assert!(matches!(run(&db, &["PING"]), Frame::Simple(s) if s == "PONG"));
+        assert!(matches!(run(&db, &["ping", "hello"]), Frame::Bulk(b) if b == "hello"));
+        assert!(matches!(
+            run(&db, &["PING", "a", "b"]),
+            Frame::Error(e) if e.contains("wrong number of arguments")
+        ));
+    }
+}
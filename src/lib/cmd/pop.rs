@@ -0,0 +1,59 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::notify::{Class, Event};
+use crate::lib::parse::{Parse, ParseError};
+
+///从列表的头部或尾部弹出元素
+///
+/// LPOP key [count] | RPOP key [count]
+///
+/// 元素全部弹出后键也随之删除
+#[derive(Debug)]
+pub struct Pop {
+    key: String,
+    //None代表只弹出一个元素并以单个值回复
+    count: Option<usize>,
+    //是否从头部弹出
+    left: bool,
+}
+
+impl Pop {
+    pub(crate) fn parse_frames(parse: &mut Parse, left: bool) -> Result<Pop, ParseError> {
+        let key = parse.next_string()?;
+        let count = match parse.next_optional_int()? {
+            Some(count) if count < 0 => {
+                return Err("value is out of range, must be positive".into());
+            }
+            Some(count) => Some(count as usize),
+            None => None,
+        };
+        Ok(Pop { key, count, left })
+    }
+
+    ///不带count时回复单个元素，带count时回复数组，键不存在时都回复Null
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let Pop { key, count, left } = self;
+        let event = Event::new(Class::List, if left { "lpop" } else { "rpop" });
+        db.mutate_notify(key, event, |slot| {
+            let list = match slot.as_mut().map(|value| value.as_list_mut()) {
+                None => return (false, Frame::Null),
+                Some(Err(e)) => return (false, e.into()),
+                Some(Ok(list)) => list,
+            };
+            let mut pop = || {
+                if left {
+                    list.pop_front()
+                } else {
+                    list.pop_back()
+                }
+            };
+            match count {
+                None => (true, pop().map_or(Frame::Null, Frame::Bulk)),
+                Some(count) => {
+                    let values = std::iter::from_fn(pop).take(count).map(Frame::Bulk);
+                    (count > 0, Frame::Array(values.collect()))
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,112 @@
+use crate::lib;
+use crate::lib::client::Client;
+use crate::lib::cmd::Command;
+use crate::lib::conn::{Connection, Stream};
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::rdb;
+use crate::lib::replication::{Replica, Resync};
+use crate::lib::shutdown::Shutdown;
+use std::sync::Arc;
+
+///从节点请求同步
+///
+/// PSYNC replid offset
+///
+/// replid与offset是从节点上次同步到的位置，主节点的积压缓冲区中还有offset之后的命令流时
+/// 回复CONTINUE并补上缺失的部分，否则回复FULLRESYNC、发送全部数据的快照，首次同步以"? -1"请求。
+/// 之后连接成为复制连接，主节点在其上发送命令流，从节点以REPLCONF ACK确认处理到的偏移量
+#[derive(Debug)]
+pub struct PSync {
+    replid: String,
+    offset: i64,
+}
+
+impl PSync {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<PSync, ParseError> {
+        let replid = parse.next_string()?;
+        let offset = parse.next_int()?;
+        Ok(PSync { replid, offset })
+    }
+
+    ///同步并发送命令流，直到从节点断开或服务器关闭才返回
+    pub(crate) async fn apply<S: Stream>(
+        self,
+        db: &Db,
+        conn: &mut Connection<S>,
+        client: &Client,
+        shutdown: &mut Shutdown,
+    ) -> lib::Result<()> {
+        let ip = client
+            .addr()
+            .rsplit_once(':')
+            .map_or(client.addr(), |(ip, _)| ip);
+        let (replica, mut stream) = Replica::new(client.id(), ip, client.listening_port());
+        let replica = Arc::new(replica);
+        let replication = db.replication();
+        let (resync, snapshot) = db.ordered_exclusively(|| {
+            let resync = replication.attach(replica.clone(), &self.replid, self.offset);
+            let snapshot = match resync {
+                Resync::Full(_) => Some(rdb::snapshot(db).0),
+                Resync::Partial(_) => None,
+            };
+            (resync, snapshot)
+        });
+        let _attached = Attached {
+            db,
+            id: client.id(),
+        };
+        match resync {
+            Resync::Partial(backlog) => {
+                tracing::info!(replica = %client.addr(), bytes = backlog.len(), "从节点部分同步");
+                let reply = format!("CONTINUE {}", replication.replid());
+                conn.write_frame(&Frame::Simple(reply)).await?;
+                conn.write_raw(&backlog).await?;
+            }
+            Resync::Full(offset) => {
+                tracing::info!(replica = %client.addr(), "从节点全量同步");
+                let reply = format!("FULLRESYNC {} {}", replication.replid(), offset);
+                conn.write_frame(&Frame::Simple(reply)).await?;
+                let snapshot = snapshot.unwrap_or_default();
+                let payload = tokio::task::spawn_blocking(move || rdb::encode(&snapshot)).await?;
+                conn.write_bulk_stream(payload.len() as u64, &payload[..], false)
+                    .await?;
+            }
+        }
+        replica.set_online();
+        loop {
+            tokio::select! {
+                _ = shutdown.recv() => return Ok(()),
+                data = stream.recv() => match data {
+                    Some(data) => conn.write_raw(&data).await?,
+                    None => return Ok(()),
+                },
+                frame = conn.read_frame() => {
+                    let frame = match frame? {
+                        Some(frame) => frame,
+                        None => return Ok(()),
+                    };
+                    //复制连接上只处理REPLCONF ACK，其他命令不回复
+                    if let Ok(Command::ReplConf(cmd)) = Command::from_frame(frame) {
+                        if let Some(offset) = cmd.ack() {
+                            replication.acknowledge(&replica, offset);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+//复制连接结束时移除从节点
+struct Attached<'a> {
+    db: &'a Db,
+    id: u64,
+}
+
+impl Drop for Attached<'_> {
+    fn drop(&mut self) {
+        self.db.replication().detach(self.id);
+    }
+}
@@ -0,0 +1,39 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use bytes::Bytes;
+
+///向频道发布消息
+///
+/// PUBLISH channel message | SPUBLISH shardchannel message
+///
+/// SPUBLISH发布到分片频道，只有SSUBSCRIBE了同名分片频道的连接会收到
+#[derive(Debug)]
+pub struct Publish {
+    channel: String,
+    message: Bytes,
+    //是否为SPUBLISH
+    shard: bool,
+}
+
+impl Publish {
+    pub(crate) fn parse_frames(parse: &mut Parse, shard: bool) -> Result<Publish, ParseError> {
+        let channel = parse.next_string()?;
+        let message = parse.next_bytes()?;
+        Ok(Publish {
+            channel,
+            message,
+            shard,
+        })
+    }
+
+    ///回复收到消息的订阅者数
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let receivers = if self.shard {
+            db.spublish(&self.channel, self.message)
+        } else {
+            db.publish(&self.channel, self.message)
+        };
+        Frame::Integer(receivers as i64)
+    }
+}
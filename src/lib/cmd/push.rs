@@ -0,0 +1,76 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::notify::{Class, Event};
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::value::list::List;
+use crate::lib::value::Value;
+use bytes::Bytes;
+
+///向列表的头部或尾部插入元素
+///
+/// LPUSH key element [element ...] | RPUSH key element [element ...] |
+/// LPUSHX key element [element ...] | RPUSHX key element [element ...]
+///
+/// 多个元素按参数顺序逐个插入，所以LPUSH之后它们在列表中的顺序是相反的
+#[derive(Debug)]
+pub struct Push {
+    key: String,
+    values: Vec<Bytes>,
+    //是否插入到头部
+    left: bool,
+    //是否只在列表已存在时插入
+    exists_only: bool,
+}
+
+impl Push {
+    pub(crate) fn parse_frames(
+        parse: &mut Parse,
+        left: bool,
+        exists_only: bool,
+    ) -> Result<Push, ParseError> {
+        let key = parse.next_string()?;
+        let mut values = vec![parse.next_bytes()?];
+        loop {
+            match parse.next_bytes() {
+                Ok(value) => values.push(value),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(Push {
+            key,
+            values,
+            left,
+            exists_only,
+        })
+    }
+
+    ///回复插入后列表的长度，LPUSHX与RPUSHX在键不存在时回复0
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let Push {
+            key,
+            values,
+            left,
+            exists_only,
+        } = self;
+        let event = Event::new(Class::List, if left { "lpush" } else { "rpush" });
+        db.mutate_notify(key, event, |slot| {
+            if exists_only && slot.is_none() {
+                return (false, Frame::Integer(0));
+            }
+            let list = slot.get_or_insert_with(|| Value::List(List::default()));
+            let list = match list.as_list_mut() {
+                Ok(list) => list,
+                Err(e) => return (false, e.into()),
+            };
+            for value in values {
+                if left {
+                    list.push_front(value);
+                } else {
+                    list.push_back(value);
+                }
+            }
+            (true, Frame::Integer(list.len() as i64))
+        })
+    }
+}
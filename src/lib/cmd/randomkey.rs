@@ -0,0 +1,22 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use bytes::Bytes;
+
+///随机返回一个键
+///
+/// RANDOMKEY
+#[derive(Debug)]
+pub struct RandomKey;
+
+impl RandomKey {
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<RandomKey, ParseError> {
+        Ok(RandomKey)
+    }
+
+    ///键空间为空时回复Null
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        db.random_key()
+            .map_or(Frame::Null, |key| Frame::Bulk(Bytes::from(key)))
+    }
+}
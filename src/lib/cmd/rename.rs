@@ -0,0 +1,104 @@
+use crate::lib::db::{Db, Update};
+use crate::lib::frame::Frame;
+use crate::lib::notify::{Class, Event};
+use crate::lib::parse::{Parse, ParseError};
+
+///重命名键
+///
+/// RENAME key newkey | RENAMENX key newkey
+///
+/// 值与过期时间一起移动到新键上，新键原有的值会被覆盖。RENAMENX只在新键不存在时重命名
+#[derive(Debug)]
+pub struct Rename {
+    source: String,
+    destination: String,
+    //新键存在时是否放弃
+    nx: bool,
+}
+
+impl Rename {
+    pub(crate) fn parse_frames(parse: &mut Parse, nx: bool) -> Result<Rename, ParseError> {
+        let source = parse.next_string()?;
+        let destination = parse.next_string()?;
+        Ok(Rename {
+            source,
+            destination,
+            nx,
+        })
+    }
+
+    ///RENAME回复OK，RENAMENX回复是否重命名，原键不存在时回复错误
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        //检查新键与移动值必须在同一次独占中完成，否则RENAMENX可能覆盖其他连接刚写入的新键
+        db.atomically(|| {
+            if !db.exists(&self.source) {
+                return Frame::Error("ERR no such key".to_string());
+            }
+            if self.nx && db.exists(&self.destination) {
+                return Frame::Integer(0);
+            }
+            if self.source != self.destination {
+                let entry = match db.remove(&self.source) {
+                    Some(entry) => entry,
+                    None => return Frame::Error("ERR no such key".to_string()),
+                };
+                db.notify(Class::Generic, "rename_from", &self.source);
+                let event = Event::new(Class::Generic, "rename_to");
+                db.update_notify(self.destination.clone(), event, |_| {
+                    let update = Update::Set {
+                        value: entry.value,
+                        expires_at: entry.expires_at,
+                    };
+                    (update, ())
+                });
+            }
+            if self.nx {
+                Frame::Integer(1)
+            } else {
+                Frame::Simple("OK".to_string())
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lib::cmd::Command;
+    use crate::lib::config::Config;
+    use crate::lib::db::Db;
+    use crate::lib::frame::Frame;
+    use bytes::Bytes;
+
+    fn call(db: &Db, args: &[&str]) -> Frame {
+        let frame = Frame::Array(
+            args.iter()
+                .map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes())))
+                .collect(),
+        );
+        Command::from_frame(frame).unwrap().apply_now(db)
+    }
+
+    #[test]
+    fn rename_fires_rename_from_and_rename_to() {
+        let db = Db::new(Config::default());
+        call(&db, &["CONFIG", "SET", "notify-keyspace-events", "Eg"]);
+        call(&db, &["SET", "old", "value"]);
+        call(&db, &["SET", "taken", "value"]);
+        let mut events = db.psubscribe("__keyevent@0__:*".to_string());
+        assert_eq!(call(&db, &["RENAME", "old", "new"]), "OK");
+        let (channel, key) = events.try_recv().unwrap();
+        assert_eq!(
+            (channel.as_str(), key),
+            ("__keyevent@0__:rename_from", "old".into())
+        );
+        let (channel, key) = events.try_recv().unwrap();
+        assert_eq!(
+            (channel.as_str(), key),
+            ("__keyevent@0__:rename_to", "new".into())
+        );
+        //没有重命名时不发布事件
+        assert_eq!(call(&db, &["RENAMENX", "new", "taken"]), Frame::Integer(0));
+        assert!(call(&db, &["RENAME", "missing", "new"]) != "OK");
+        assert!(events.try_recv().is_err());
+    }
+}
@@ -0,0 +1,62 @@
+use crate::lib::client::Client;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+
+///从节点在同步之前与之后告知主节点的信息
+///
+/// REPLCONF option value [option value ...]
+///
+/// LISTENING-PORT告知从节点的监听端口，在INFO replication中显示；IP-ADDRESS与CAPA被接受但不起作用。
+/// ACK offset由复制连接上的从节点发送，确认已经处理到的复制偏移量
+#[derive(Debug)]
+pub struct ReplConf {
+    //选项与值，选项已转换为小写
+    options: Vec<(String, String)>,
+}
+
+impl ReplConf {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<ReplConf, ParseError> {
+        let mut options = vec![];
+        while let Some(option) = parse.next_optional_string()? {
+            let value = parse
+                .next_optional_string()?
+                .ok_or_else(|| ParseError::from("syntax error"))?;
+            options.push((option.to_lowercase(), value));
+        }
+        Ok(ReplConf { options })
+    }
+
+    ///ACK给出的复制偏移量，不是ACK或偏移量无法解析时返回None
+    pub(crate) fn ack(&self) -> Option<u64> {
+        match self.options.first() {
+            Some((option, offset)) if option == "ack" => offset.parse().ok(),
+            _ => None,
+        }
+    }
+
+    ///是否为主节点要求立即确认偏移量的GETACK
+    pub(crate) fn is_getack(&self) -> bool {
+        self.options
+            .first()
+            .is_some_and(|(option, _)| option == "getack")
+    }
+
+    ///记录从节点的监听端口，选项不认识时回复错误
+    pub(crate) fn apply(self, client: &Client) -> Frame {
+        for (option, value) in &self.options {
+            match option.as_str() {
+                "listening-port" => match value.parse() {
+                    Ok(port) => client.set_listening_port(port),
+                    Err(_) => {
+                        return Frame::Error(
+                            "ERR value is not an integer or out of range".to_string(),
+                        )
+                    }
+                },
+                "ip-address" | "capa" | "ack" | "getack" => {}
+                _ => return Frame::Error(format!("ERR Unrecognized REPLCONF option: {}", option)),
+            }
+        }
+        Frame::Simple("OK".to_string())
+    }
+}
@@ -0,0 +1,45 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+
+///复制另一个服务器，或者停止复制
+///
+/// REPLICAOF host port | REPLICAOF NO ONE
+///
+/// 与CONFIG SET replicaof相同，复制在后台进行，连接断开之后自动重连并尽量部分同步。
+/// 改为复制另一个主节点时丢弃现有的数据全量同步，NO ONE停止复制并保留现有的数据
+#[derive(Debug)]
+pub struct ReplicaOf {
+    //主节点的地址与端口，None代表NO ONE
+    master: Option<(String, u16)>,
+}
+
+impl ReplicaOf {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<ReplicaOf, ParseError> {
+        let host = parse.next_string()?;
+        let port = parse.next_string()?;
+        if host.eq_ignore_ascii_case("no") && port.eq_ignore_ascii_case("one") {
+            return Ok(ReplicaOf { master: None });
+        }
+        let port = port.parse().map_err(|_| ParseError::NotInteger)?;
+        Ok(ReplicaOf {
+            master: Some((host, port)),
+        })
+    }
+
+    ///已经在复制同一个主节点时什么也不做
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        if self.master.is_some() && db.master_link().master() == self.master {
+            return Frame::Simple("OK Already connected to specified master".to_string());
+        }
+        let value = match &self.master {
+            Some((host, port)) => format!("{} {}", host, port),
+            None => String::new(),
+        };
+        match db.update_config(|config| config.set_at_runtime("replicaof", &value)) {
+            Ok(()) => Frame::Simple("OK".to_string()),
+            //主机名中带有空白时无法作为配置的值
+            Err(_) => Frame::Error("ERR Invalid master address".to_string()),
+        }
+    }
+}
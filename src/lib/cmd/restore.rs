@@ -0,0 +1,89 @@
+use crate::lib::db::{now_millis, Db, Update};
+use crate::lib::frame::Frame;
+use crate::lib::notify::{Class, Event};
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::value::dump;
+use bytes::Bytes;
+
+///将DUMP序列化的值写入键
+///
+/// RESTORE key ttl serialized-value [REPLACE] [ABSTTL] [IDLETIME seconds] [FREQ frequency]
+///
+/// ttl为0时不过期，带ABSTTL时ttl为过期的unix时间戳（毫秒），否则为剩余的毫秒数。
+/// 服务端不记录键的访问时间与频率，IDLETIME与FREQ只做校验
+#[derive(Debug)]
+pub struct Restore {
+    key: String,
+    ttl: i64,
+    data: Bytes,
+    replace: bool,
+    abs_ttl: bool,
+}
+
+impl Restore {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Restore, ParseError> {
+        let key = parse.next_string()?;
+        let ttl = parse.next_int()?;
+        let data = parse.next_bytes()?;
+        let mut restore = Restore {
+            key,
+            ttl,
+            data,
+            replace: false,
+            abs_ttl: false,
+        };
+        loop {
+            match parse.next_string() {
+                Ok(option) => match option.to_uppercase().as_str() {
+                    "REPLACE" => restore.replace = true,
+                    "ABSTTL" => restore.abs_ttl = true,
+                    "IDLETIME" => {
+                        if parse.next_int()? < 0 {
+                            return Err("Invalid IDLETIME value, must be >= 0".into());
+                        }
+                    }
+                    "FREQ" => {
+                        if !(0..=255).contains(&parse.next_int()?) {
+                            return Err("Invalid FREQ value, must be >= 0 and <= 255".into());
+                        }
+                    }
+                    _ => return Err("syntax error".into()),
+                },
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        if restore.ttl < 0 {
+            return Err("Invalid TTL value, must be >= 0".into());
+        }
+        Ok(restore)
+    }
+
+    ///写入成功回复OK，键已存在且没有REPLACE时回复BUSYKEY错误
+    ///
+    /// 过期时间已经过去时不写入，带REPLACE时删除原有的键
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let value = match dump::deserialize(&self.data) {
+            Ok(value) => value,
+            Err(e) => return Frame::Error(e.to_string()),
+        };
+        let now = now_millis();
+        let expires_at = match (self.ttl, self.abs_ttl) {
+            (0, _) => None,
+            (ttl, true) => Some(ttl as u64),
+            (ttl, false) => Some(now.saturating_add(ttl as u64)),
+        };
+        let replace = self.replace;
+        db.update_notify(self.key, Event::new(Class::Generic, "restore"), |cur| {
+            if cur.is_some() && !replace {
+                let err = "BUSYKEY Target key name already exists.";
+                return (Update::Keep, Frame::Error(err.to_string()));
+            }
+            let ok = Frame::Simple("OK".to_string());
+            if matches!(expires_at, Some(at) if at <= now) {
+                return (Update::Remove, ok);
+            }
+            (Update::Set { value, expires_at }, ok)
+        })
+    }
+}
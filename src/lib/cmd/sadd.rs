@@ -0,0 +1,48 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::notify::{Class, Event};
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::value::set::Set;
+use crate::lib::value::Value;
+use bytes::Bytes;
+
+///向集合中添加成员
+///
+/// SADD key member [member ...]
+#[derive(Debug)]
+pub struct SAdd {
+    key: String,
+    members: Vec<Bytes>,
+}
+
+impl SAdd {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<SAdd, ParseError> {
+        let key = parse.next_string()?;
+        let mut members = vec![parse.next_bytes()?];
+        loop {
+            match parse.next_bytes() {
+                Ok(member) => members.push(member),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(SAdd { key, members })
+    }
+
+    ///回复新增的成员数，已存在的成员不计入
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let SAdd { key, members } = self;
+        db.mutate_notify(key, Event::new(Class::Set, "sadd"), |slot| {
+            let set = slot.get_or_insert_with(|| Value::Set(Set::default()));
+            let set = match set.as_set_mut() {
+                Ok(set) => set,
+                Err(e) => return (false, e.into()),
+            };
+            let added = members
+                .into_iter()
+                .filter(|member| set.insert(member.clone()))
+                .count();
+            (added > 0, Frame::Integer(added as i64))
+        })
+    }
+}
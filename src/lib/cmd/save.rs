@@ -0,0 +1,26 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::rdb;
+
+///将数据保存到快照文件
+///
+/// SAVE
+///
+/// 在当前连接上完成保存之后才回复OK，保存期间复制数据时其他连接的命令都要等待，一般应使用BGSAVE
+#[derive(Debug)]
+pub struct Save;
+
+impl Save {
+    pub(crate) fn parse_frames(_parse: &mut Parse) -> Result<Save, ParseError> {
+        Ok(Save)
+    }
+
+    ///正在后台保存或写入文件失败时回复错误
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match rdb::save(db) {
+            Ok(()) => Frame::Simple("OK".to_string()),
+            Err(e) => Frame::Error(e),
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::glob;
+use crate::lib::parse::{Parse, ParseError};
+use bytes::Bytes;
+
+//未指定COUNT时每批返回的元素数
+const DEFAULT_COUNT: usize = 10;
+
+///按游标分批遍历键空间
+///
+/// SCAN cursor [MATCH pattern] [COUNT count] [TYPE type]
+///
+/// 从游标0开始，直到回复的游标再次为0时遍历结束。遍历期间一直存在的键至少会被返回一次，
+/// 期间被增删的键可能返回也可能不返回
+#[derive(Debug)]
+pub struct Scan {
+    cursor: u64,
+    pattern: Option<Bytes>,
+    count: usize,
+    //只返回存储该类型的值的键
+    kind: Option<String>,
+}
+
+impl Scan {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Scan, ParseError> {
+        let cursor = parse_cursor(parse)?;
+        let mut pattern = None;
+        let mut count = DEFAULT_COUNT;
+        let mut kind = None;
+        loop {
+            match parse.next_string() {
+                Ok(option) => match option.to_uppercase().as_str() {
+                    "MATCH" => pattern = Some(parse.next_bytes()?),
+                    "COUNT" => count = parse_count(parse)?,
+                    "TYPE" => kind = Some(parse.next_string()?.to_lowercase()),
+                    _ => return Err("syntax error".into()),
+                },
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(Scan {
+            cursor,
+            pattern,
+            count,
+            kind,
+        })
+    }
+
+    ///回复下一个游标与本批的键
+    ///
+    /// 每批大约访问COUNT个键，MATCH与TYPE在访问之后过滤，因此一批返回的键可能少于COUNT甚至为空
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let mut keys = vec![];
+        let cursor = db.scan(self.cursor, self.count, |key, entry| {
+            if let Some(kind) = &self.kind {
+                if entry.value.type_name() != kind {
+                    return;
+                }
+            }
+            if let Some(pattern) = &self.pattern {
+                if !glob::matches(pattern, key.as_bytes()) {
+                    return;
+                }
+            }
+            keys.push(Frame::Bulk(Bytes::copy_from_slice(key.as_bytes())));
+        });
+        reply(cursor, keys)
+    }
+}
+
+///读取游标，游标是无符号的64位整数
+pub(super) fn parse_cursor(parse: &mut Parse) -> Result<u64, ParseError> {
+    parse
+        .next_string()?
+        .parse()
+        .map_err(|_| "invalid cursor".into())
+}
+
+///读取COUNT的值，必须为正数
+pub(super) fn parse_count(parse: &mut Parse) -> Result<usize, ParseError> {
+    match parse.next_int()? {
+        count if count < 1 => Err("syntax error".into()),
+        count => Ok(count as usize),
+    }
+}
+
+///SCAN系列命令的回复：下一个游标与本批的元素
+pub(super) fn reply(cursor: u64, items: Vec<Frame>) -> Frame {
+    let cursor = Frame::Bulk(Bytes::from(cursor.to_string()));
+    Frame::Array(vec![cursor, Frame::Array(items)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::cmd::Command;
+    use crate::lib::config::Config;
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    //执行一次SCAN，返回下一个游标与本批的键
+    fn scan(db: &Db, cursor: u64) -> (u64, Vec<Bytes>) {
+        let frame = Frame::Array(
+            ["SCAN", &cursor.to_string(), "COUNT", "20"]
+                .iter()
+                .map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes())))
+                .collect(),
+        );
+        match Command::from_frame(frame).unwrap().apply_now(db) {
+            Frame::Array(mut reply) => match (reply.pop(), reply.pop()) {
+                (Some(Frame::Array(keys)), Some(Frame::Bulk(cursor))) => {
+                    let cursor = std::str::from_utf8(&cursor).unwrap().parse().unwrap();
+                    let keys = keys
+                        .into_iter()
+                        .map(|key| match key {
+                            Frame::Bulk(key) => key,
+                            frame => panic!("unexpected key {:?}", frame),
+                        })
+                        .collect();
+                    (cursor, keys)
+                }
+                reply => panic!("unexpected reply {:?}", reply),
+            },
+            frame => panic!("unexpected reply {:?}", frame),
+        }
+    }
+
+    #[test]
+    fn keys_present_for_the_whole_scan_are_returned_despite_concurrent_writes() {
+        let db = Db::new(Config::default());
+        for i in 0..5000 {
+            db.set(format!("stable:{}", i), Bytes::from("value").into());
+        }
+        let done = Arc::new(AtomicBool::new(false));
+        let writers: Vec<_> = (0..4)
+            .map(|writer| {
+                let db = db.clone();
+                let done = done.clone();
+                std::thread::spawn(move || {
+                    let mut i = 0;
+                    while !done.load(Ordering::Relaxed) {
+                        let key = format!("churn:{}:{}", writer, i);
+                        db.set(key, Bytes::from("value").into());
+                        //删除较早写入的键，键空间在扩容的同时也有删除
+                        if i >= 100 {
+                            db.remove(&format!("churn:{}:{}", writer, i - 100));
+                        }
+                        i += 1;
+                    }
+                    i
+                })
+            })
+            .collect();
+        let mut seen = HashSet::new();
+        let mut cursor = 0;
+        loop {
+            let (next, keys) = scan(&db, cursor);
+            seen.extend(keys);
+            cursor = next;
+            //让写入线程在两批之间插进来
+            std::thread::yield_now();
+            if cursor == 0 {
+                break;
+            }
+        }
+        done.store(true, Ordering::Relaxed);
+        let written: usize = writers.into_iter().map(|w| w.join().unwrap()).sum();
+        assert!(written > 0);
+        for i in 0..5000 {
+            let key = format!("stable:{}", i);
+            assert!(seen.contains(key.as_bytes()), "{} was not returned", key);
+        }
+    }
+}
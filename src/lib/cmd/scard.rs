@@ -0,0 +1,27 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+
+///获取集合的成员数
+///
+/// SCARD key
+#[derive(Debug)]
+pub struct SCard {
+    key: String,
+}
+
+impl SCard {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<SCard, ParseError> {
+        let key = parse.next_string()?;
+        Ok(SCard { key })
+    }
+
+    ///键不存在时回复0
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        db.read(&self.key, |entry| match entry.value.as_set() {
+            Ok(set) => Frame::Integer(set.len() as i64),
+            Err(e) => e.into(),
+        })
+        .unwrap_or(Frame::Integer(0))
+    }
+}
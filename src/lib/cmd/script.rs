@@ -0,0 +1,72 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use bytes::Bytes;
+
+///管理脚本缓存
+///
+/// SCRIPT LOAD script | EXISTS sha1 [sha1 ...] | FLUSH [ASYNC|SYNC] | KILL
+#[derive(Debug)]
+pub enum Script {
+    ///缓存脚本但不执行
+    Load(Bytes),
+    ///检查脚本是否已被缓存
+    Exists(Vec<String>),
+    ///清空脚本缓存
+    Flush,
+    ///终止正在执行的脚本
+    Kill,
+}
+
+impl Script {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Script, ParseError> {
+        let sub = parse.next_string()?.to_lowercase();
+        let cmd = match sub.as_str() {
+            "load" => Script::Load(parse.next_bytes()?),
+            "exists" => {
+                let mut shas = vec![parse.next_string()?];
+                loop {
+                    match parse.next_string() {
+                        Ok(sha) => shas.push(sha),
+                        Err(ParseError::EndOfStream) => break,
+                        Err(e) => return Err(e),
+                    }
+                }
+                Script::Exists(shas)
+            }
+            //缓存本身就是同步清空的，ASYNC与SYNC没有区别
+            "flush" => match parse.next_string() {
+                Ok(mode) if matches!(mode.to_uppercase().as_str(), "ASYNC" | "SYNC") => {
+                    Script::Flush
+                }
+                Ok(_) => return Err("syntax error".into()),
+                Err(ParseError::EndOfStream) => Script::Flush,
+                Err(e) => return Err(e),
+            },
+            "kill" => Script::Kill,
+            _ => {
+                let err = format!("unknown subcommand '{}'. Try SCRIPT HELP.", sub);
+                return Err(err.into());
+            }
+        };
+        Ok(cmd)
+    }
+
+    ///LOAD回复脚本的SHA1摘要，EXISTS按顺序回复每个摘要对应的脚本是否存在，
+    /// KILL在没有可以终止的脚本时回复NOTBUSY或UNKILLABLE错误
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match self {
+            Script::Load(script) => Frame::Bulk(Bytes::from(db.load_script(script))),
+            Script::Exists(shas) => Frame::Array(
+                shas.iter()
+                    .map(|sha| Frame::Integer(db.script(sha).is_some() as i64))
+                    .collect(),
+            ),
+            Script::Flush => {
+                db.flush_scripts();
+                Frame::Simple("OK".to_string())
+            }
+            Script::Kill => db.running_script().kill(),
+        }
+    }
+}
@@ -0,0 +1,37 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+
+///切换连接当前使用的逻辑数据库
+///
+/// SELECT index
+#[derive(Debug)]
+pub struct Select {
+    index: i64,
+}
+
+impl Select {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Select, ParseError> {
+        let index = parse.next_int()?;
+        Ok(Select { index })
+    }
+
+    ///切换成功时将db替换为新选择的数据库并回复OK
+    pub(crate) fn apply(self, db: &mut Db) -> Frame {
+        match select(db, self.index) {
+            Ok(selected) => {
+                *db = selected;
+                Frame::Simple("OK".to_string())
+            }
+            Err(e) => e,
+        }
+    }
+}
+
+///按编号获取逻辑数据库，编号超出范围时返回回复给客户端的错误
+pub(super) fn select(db: &Db, index: i64) -> Result<Db, Frame> {
+    usize::try_from(index)
+        .ok()
+        .and_then(|index| db.select(index))
+        .ok_or_else(|| Frame::Error("ERR DB index is out of range".to_string()))
+}
@@ -0,0 +1,284 @@
+use crate::lib::db::{now_millis, Db, Update};
+use crate::lib::frame::Frame;
+use crate::lib::notify::{Class, Event};
+use crate::lib::parse::{Parse, ParseError};
+use bytes::Bytes;
+
+///设置键对应的值
+///
+/// SET key value [NX | XX] [GET] [EX seconds | PX milliseconds | EXAT unix-time-seconds |
+/// PXAT unix-time-milliseconds | KEEPTTL | PERSIST]
+///
+/// 没有指定过期时间时使用default-ttl，PERSIST写入不过期的键
+#[derive(Debug)]
+pub struct Set {
+    key: String,
+    value: Bytes,
+    //过期时间的设置方式，None代表使用default-ttl，没有配置时清除原有的过期时间
+    expire: Option<Expire>,
+    //写入的前提条件
+    condition: Option<Condition>,
+    //是否返回旧值
+    get: bool,
+}
+
+///过期时间的设置方式
+#[derive(Debug, Clone, Copy)]
+pub(super) enum Expire {
+    ///相对时间，毫秒
+    After(u64),
+    ///unix时间戳，毫秒
+    At(u64),
+    ///保留原有的过期时间
+    KeepTtl,
+    ///不过期，也不使用default-ttl
+    Persist,
+}
+
+///写入的前提条件
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Condition {
+    ///键不存在时才写入
+    Nx,
+    ///键存在时才写入
+    Xx,
+}
+
+impl Set {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Set, ParseError> {
+        let key = parse.next_string()?;
+        let value = parse.next_bytes()?;
+        let mut set = Set {
+            key,
+            value,
+            expire: None,
+            condition: None,
+            get: false,
+        };
+        loop {
+            let option = match parse.next_string() {
+                Ok(option) => option.to_uppercase(),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e),
+            };
+            match option.as_str() {
+                "EX" | "PX" | "EXAT" | "PXAT" if set.expire.is_none() => {
+                    let time = parse.next_int()?;
+                    set.expire = Some(parse_expire(&option, time, "set")?);
+                }
+                "KEEPTTL" if set.expire.is_none() => set.expire = Some(Expire::KeepTtl),
+                "PERSIST" if set.expire.is_none() => set.expire = Some(Expire::Persist),
+                "NX" if set.condition.is_none() => set.condition = Some(Condition::Nx),
+                "XX" if set.condition.is_none() => set.condition = Some(Condition::Xx),
+                "GET" => set.get = true,
+                _ => return Err("syntax error".into()),
+            }
+        }
+        Ok(set)
+    }
+
+    ///写入键值
+    ///
+    /// 条件检查、旧值读取与写入在同一把锁内完成。带GET时无论是否写入都回复旧值，
+    /// 否则写入成功回复OK，条件不满足回复Null。原有的值无论是什么类型都会被覆盖
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let now = now_millis();
+        let default_ttl = db.config().default_ttl();
+        let Set {
+            key,
+            value,
+            expire,
+            condition,
+            get,
+        } = self;
+        db.update_notify(key, Event::new(Class::String, "set"), |cur| {
+            let allowed = match condition {
+                Some(Condition::Nx) => cur.is_none(),
+                Some(Condition::Xx) => cur.is_some(),
+                None => true,
+            };
+            let reply = if get {
+                //带GET时旧值必须是字符串，否则不做任何修改
+                match cur.map(|entry| entry.value.as_string()).transpose() {
+                    Ok(old) => old.map_or(Frame::Null, Frame::Bulk),
+                    Err(e) => return (Update::Keep, e.into()),
+                }
+            } else if allowed {
+                Frame::Simple("OK".to_string())
+            } else {
+                Frame::Null
+            };
+            if !allowed {
+                return (Update::Keep, reply);
+            }
+            let expires_at = match expire {
+                None => default_ttl.map(|ttl| now.saturating_add(ttl)),
+                Some(Expire::Persist) => None,
+                Some(Expire::After(ms)) => Some(now.saturating_add(ms)),
+                Some(Expire::At(at)) => Some(at),
+                Some(Expire::KeepTtl) => cur.and_then(|entry| entry.expires_at),
+            };
+            //过期时间已经过去的写入等同于删除
+            if matches!(expires_at, Some(at) if at <= now) {
+                return (Update::Remove, reply);
+            }
+            let update = Update::Set {
+                value: value.into(),
+                expires_at,
+            };
+            (update, reply)
+        })
+    }
+}
+
+///将EX、PX、EXAT、PXAT参数统一转化为毫秒
+///
+/// name为命令名称，用于错误信息
+pub(super) fn parse_expire(option: &str, time: i64, name: &str) -> Result<Expire, ParseError> {
+    let msg = || format!("invalid expire time in '{}' command", name);
+    if time <= 0 {
+        return Err(msg().into());
+    }
+    let time = time as u64;
+    let expire = match option {
+        "EX" => Expire::After(time.checked_mul(1000).ok_or_else(msg)?),
+        "PX" => Expire::After(time),
+        "EXAT" => Expire::At(time.checked_mul(1000).ok_or_else(msg)?),
+        _ => Expire::At(time),
+    };
+    Ok(expire)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lib::cmd::Command;
+    use crate::lib::config::Config;
+    use crate::lib::db::{now_millis, Db};
+    use crate::lib::frame::Frame;
+    use crate::lib::propagate;
+    use bytes::Bytes;
+
+    fn run(db: &Db, args: &[&str]) -> Frame {
+        let frame = Frame::Array(
+            args.iter()
+                .map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes())))
+                .collect(),
+        );
+        Command::from_frame(frame).unwrap().apply_now(db)
+    }
+
+    //不合法的参数组合在解析时就被拒绝
+    fn rejected(args: &[&str]) -> bool {
+        let frame = Frame::Array(
+            args.iter()
+                .map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes())))
+                .collect(),
+        );
+        Command::from_frame(frame).is_err()
+    }
+
+    fn expires_at(db: &Db, key: &str) -> Option<u64> {
+        db.read(key, |entry| entry.expires_at).unwrap()
+    }
+
+    fn with_default_ttl(seconds: &str) -> Db {
+        let mut config = Config::default();
+        config.set("default-ttl", seconds).unwrap();
+        Db::new(config)
+    }
+
+    #[test]
+    fn default_ttl_applies_to_plain_set() {
+        let db = with_default_ttl("100");
+        let now = now_millis();
+        run(&db, &["SET", "key", "value"]);
+        let at = expires_at(&db, "key").unwrap();
+        assert!(at >= now + 100_000 && at <= now_millis() + 100_000);
+        run(&db, &["MSET", "a", "1", "b", "2"]);
+        assert!(expires_at(&db, "a").is_some());
+        assert!(expires_at(&db, "b").is_some());
+    }
+
+    #[test]
+    fn explicit_expiry_overrides_default_ttl() {
+        let db = with_default_ttl("100");
+        run(&db, &["SET", "key", "value", "PERSIST"]);
+        assert_eq!(expires_at(&db, "key"), None);
+        let now = now_millis();
+        run(&db, &["SET", "key", "value", "PX", "5000"]);
+        let at = expires_at(&db, "key").unwrap();
+        assert!(at >= now + 5000 && at <= now_millis() + 5000);
+        run(&db, &["SET", "key", "other", "KEEPTTL"]);
+        assert_eq!(expires_at(&db, "key"), Some(at));
+    }
+
+    #[test]
+    fn no_default_ttl() {
+        let db = Db::new(Config::default());
+        run(&db, &["SET", "key", "value"]);
+        assert_eq!(expires_at(&db, "key"), None);
+        run(&db, &["MSET", "a", "1"]);
+        assert_eq!(expires_at(&db, "a"), None);
+    }
+
+    #[test]
+    fn default_ttl_is_propagated() {
+        let db = with_default_ttl("100");
+        let reply = run(&db, &["SET", "key", "value"]);
+        let at = expires_at(&db, "key").unwrap();
+        let args = ["SET", "key", "value"].map(Bytes::from).to_vec();
+        let args = propagate::rewrite(&db, args, &reply).unwrap();
+        assert_eq!(
+            args[3..],
+            [Bytes::from("PXAT"), Bytes::from(at.to_string())]
+        );
+    }
+
+    #[test]
+    fn keepttl_keeps_the_expiry_that_plain_set_clears() {
+        let db = Db::new(Config::default());
+        run(&db, &["SET", "key", "value", "EX", "100"]);
+        let at = expires_at(&db, "key").unwrap();
+        assert!(run(&db, &["SET", "key", "other", "KEEPTTL"]) == "OK");
+        assert_eq!(expires_at(&db, "key"), Some(at));
+        assert!(run(&db, &["GET", "key"]) == "other");
+        run(&db, &["SET", "key", "plain"]);
+        assert_eq!(expires_at(&db, "key"), None);
+        //KEEPTTL不能与其他过期时间同时使用
+        assert!(rejected(&["SET", "key", "v", "KEEPTTL", "EX", "10"]));
+        assert!(rejected(&["SET", "key", "v", "PX", "10", "KEEPTTL"]));
+    }
+
+    #[test]
+    fn get_replies_with_the_old_value() {
+        let db = Db::new(Config::default());
+        assert_eq!(run(&db, &["SET", "key", "first", "GET"]), Frame::Null);
+        assert!(run(&db, &["SET", "key", "second", "GET", "EX", "100"]) == "first");
+        assert!(expires_at(&db, "key").is_some());
+        //NX拒绝写入时仍然回复旧值
+        assert!(run(&db, &["SET", "key", "third", "NX", "GET"]) == "second");
+        assert!(run(&db, &["GET", "key"]) == "second");
+        //旧值不是字符串时回复错误并且不做修改
+        run(&db, &["HSET", "hash", "field", "value"]);
+        assert!(matches!(
+            run(&db, &["SET", "hash", "value", "GET"]),
+            Frame::Error(e) if e.starts_with("WRONGTYPE")
+        ));
+        assert_eq!(run(&db, &["HLEN", "hash"]), Frame::Integer(1));
+    }
+
+    #[test]
+    fn nx_and_xx_conditions() {
+        let db = Db::new(Config::default());
+        assert_eq!(run(&db, &["SET", "key", "value", "XX"]), Frame::Null);
+        assert_eq!(run(&db, &["EXISTS", "key"]), Frame::Integer(0));
+        assert!(run(&db, &["SET", "key", "value", "NX"]) == "OK");
+        assert_eq!(run(&db, &["SET", "key", "other", "NX"]), Frame::Null);
+        assert!(run(&db, &["GET", "key"]) == "value");
+        assert!(run(&db, &["SET", "key", "other", "XX", "PX", "100000"]) == "OK");
+        assert!(run(&db, &["GET", "key"]) == "other");
+        assert!(expires_at(&db, "key").is_some());
+        assert!(rejected(&["SET", "key", "value", "NX", "XX"]));
+        assert!(rejected(&["SET", "key", "value", "XX", "NX"]));
+    }
+}
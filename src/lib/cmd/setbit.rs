@@ -0,0 +1,53 @@
+use crate::lib::cmd::getbit::parse_offset;
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::notify::Class;
+use crate::lib::parse::{Parse, ParseError};
+
+///设置值中指定偏移量上的位
+///
+/// SETBIT key offset value
+///
+/// 偏移量超出原有长度时值会被补0扩展，原有的过期时间保持不变
+#[derive(Debug)]
+pub struct SetBit {
+    key: String,
+    offset: usize,
+    bit: bool,
+}
+
+impl SetBit {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<SetBit, ParseError> {
+        let key = parse.next_string()?;
+        let offset = parse_offset(parse)?;
+        let bit = match parse.next_int()? {
+            0 => false,
+            1 => true,
+            _ => return Err("bit is not an integer or out of range".into()),
+        };
+        Ok(SetBit { key, offset, bit })
+    }
+
+    ///回复该位原来的值
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let SetBit { key, offset, bit } = self;
+        let reply = db.modify(key.clone(), |data| {
+            let index = offset / 8;
+            if data.len() <= index {
+                data.resize(index + 1, 0);
+            }
+            let mask = 0x80u8 >> (offset % 8);
+            let old = data[index] & mask != 0;
+            if bit {
+                data[index] |= mask;
+            } else {
+                data[index] &= !mask;
+            }
+            Frame::Integer(old as i64)
+        });
+        if reply.is_ok() {
+            db.notify(Class::String, "setbit", &key);
+        }
+        reply.unwrap_or_else(Frame::from)
+    }
+}
@@ -0,0 +1,175 @@
+use crate::lib::db::Db;
+use crate::lib::frame::{Frame, REPLY_TOO_LARGE};
+use crate::lib::notify::Class;
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::value::{Value, WrongType};
+use bytes::Bytes;
+use std::collections::HashSet;
+
+///多个集合的交集、并集与差集
+///
+/// SINTER key [key ...] | SUNION key [key ...] | SDIFF key [key ...] |
+/// SINTERSTORE destination key [key ...] | SUNIONSTORE destination key [key ...] |
+/// SDIFFSTORE destination key [key ...]
+///
+/// 不存在的键视为空集合，差集为第一个集合减去其余所有集合
+#[derive(Debug)]
+pub struct SetOp {
+    op: Op,
+    //结果写入的键，None代表直接回复结果
+    destination: Option<String>,
+    keys: Vec<String>,
+}
+
+///集合运算的种类
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Inter,
+    Union,
+    Diff,
+}
+
+impl Op {
+    //写入目标键时的键空间事件名称
+    fn store_event(self) -> &'static str {
+        match self {
+            Op::Inter => "sinterstore",
+            Op::Union => "sunionstore",
+            Op::Diff => "sdiffstore",
+        }
+    }
+}
+
+impl SetOp {
+    ///name为命令名称，决定运算的种类以及是否写入目标键
+    pub(crate) fn parse_frames(parse: &mut Parse, name: &str) -> Result<SetOp, ParseError> {
+        let op = match name.trim_end_matches("store") {
+            "sinter" => Op::Inter,
+            "sunion" => Op::Union,
+            _ => Op::Diff,
+        };
+        let destination = if name.ends_with("store") {
+            Some(parse.next_string()?)
+        } else {
+            None
+        };
+        let mut keys = vec![parse.next_string()?];
+        loop {
+            match parse.next_string() {
+                Ok(key) => keys.push(key),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(SetOp {
+            op,
+            destination,
+            keys,
+        })
+    }
+
+    ///直接回复时回复结果中的成员，超出max-reply-size时回复错误，写入目标键时回复结果的成员数
+    ///
+    /// 读取与写入在同一次键空间独占中完成，结果为空时删除目标键
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let SetOp {
+            op,
+            destination,
+            keys,
+        } = self;
+        db.atomically(|| {
+            let result = match compute(db, op, &keys) {
+                Ok(result) => result,
+                Err(e) => return e.into(),
+            };
+            match destination {
+                None => {
+                    let mut limit = db.reply_limit();
+                    let mut members = Vec::with_capacity(result.len());
+                    for member in result {
+                        if !limit.add(member.len()) {
+                            return Frame::Error(REPLY_TOO_LARGE.to_string());
+                        }
+                        members.push(Frame::Bulk(member));
+                    }
+                    Frame::Array(members)
+                }
+                Some(destination) => {
+                    let len = result.len() as i64;
+                    if result.is_empty() {
+                        if db.remove(&destination).is_some() {
+                            db.notify(Class::Generic, "del", &destination);
+                        }
+                    } else {
+                        db.set(
+                            destination.clone(),
+                            Value::Set(result.into_iter().collect()),
+                        );
+                        db.notify(Class::Set, op.store_event(), &destination);
+                    }
+                    Frame::Integer(len)
+                }
+            }
+        })
+    }
+}
+
+//对keys对应的集合依次做运算
+fn compute(db: &Db, op: Op, keys: &[String]) -> Result<HashSet<Bytes>, WrongType> {
+    let mut sets: Vec<HashSet<Bytes>> = Vec::with_capacity(keys.len());
+    for key in keys {
+        let set = db
+            .read(key, |entry| {
+                entry.value.as_set().map(|set| set.iter().collect())
+            })
+            .transpose()?;
+        sets.push(set.unwrap_or_default());
+    }
+    let mut sets = sets.into_iter();
+    let mut result = sets.next().unwrap_or_default();
+    for set in sets {
+        match op {
+            Op::Inter => result.retain(|member| set.contains(member)),
+            Op::Union => result.extend(set),
+            Op::Diff => result.retain(|member| !set.contains(member)),
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::cmd::Command;
+    use crate::lib::config::Config;
+
+    fn call(db: &Db, args: &[&str]) -> Frame {
+        let frame = Frame::Array(
+            args.iter()
+                .map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes())))
+                .collect(),
+        );
+        Command::from_frame(frame).unwrap().apply_now(db)
+    }
+
+    #[test]
+    fn reply_too_large() {
+        let mut config = Config::default();
+        config.set("max-reply-size", "1024").unwrap();
+        let db = Db::new(config);
+        for i in 0..50 {
+            let key = if i % 2 == 0 { "a" } else { "b" };
+            call(&db, &["SADD", key, &format!("member:{}", i)]);
+        }
+        let union = call(&db, &["SUNION", "a", "b"]);
+        assert!(matches!(union, Frame::Error(e) if e == REPLY_TOO_LARGE));
+        assert!(
+            matches!(call(&db, &["SINTER", "a", "b"]), Frame::Array(items) if items.is_empty())
+        );
+        //写入目标键时只回复成员数，不受限制
+        assert_eq!(
+            call(&db, &["SUNIONSTORE", "c", "a", "b"]),
+            Frame::Integer(50)
+        );
+    }
+}
@@ -0,0 +1,65 @@
+use crate::lib::db::{Db, Update};
+use crate::lib::frame::Frame;
+use crate::lib::notify::{Class, Event};
+use crate::lib::parse::{Parse, ParseError};
+use bytes::{Bytes, BytesMut};
+
+//值允许的最大长度，与redis的proto-max-bulk-len默认值一致
+const MAX_STRING_LEN: usize = 512 * 1024 * 1024;
+
+///从指定偏移量开始覆盖值的内容
+///
+/// SETRANGE key offset value
+///
+/// 偏移量超出原有长度时，中间的空缺以0字节填充。原有的过期时间保持不变
+#[derive(Debug)]
+pub struct SetRange {
+    key: String,
+    offset: usize,
+    value: Bytes,
+}
+
+impl SetRange {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<SetRange, ParseError> {
+        let key = parse.next_string()?;
+        let offset = parse.next_int()?;
+        if offset < 0 {
+            return Err("offset is out of range".into());
+        }
+        let value = parse.next_bytes()?;
+        Ok(SetRange {
+            key,
+            offset: offset as usize,
+            value,
+        })
+    }
+
+    ///回复修改后值的长度
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let SetRange { key, offset, value } = self;
+        if offset.saturating_add(value.len()) > MAX_STRING_LEN {
+            let err = "ERR string exceeds maximum allowed size (proto-max-bulk-len)";
+            return Frame::Error(err.to_string());
+        }
+        db.update_notify(key, Event::new(Class::String, "setrange"), |cur| {
+            let old = match cur.map(|entry| entry.value.as_string()).transpose() {
+                Ok(old) => old.unwrap_or_default(),
+                Err(e) => return (Update::Keep, e.into()),
+            };
+            //写入空内容不会修改值，键不存在时也不会被创建
+            if value.is_empty() {
+                return (Update::Keep, Frame::Integer(old.len() as i64));
+            }
+            let len = old.len().max(offset + value.len());
+            let mut data = BytesMut::with_capacity(len);
+            data.extend_from_slice(&old);
+            data.resize(len, 0);
+            data[offset..offset + value.len()].copy_from_slice(&value);
+            let update = Update::Set {
+                value: data.freeze().into(),
+                expires_at: cur.and_then(|entry| entry.expires_at),
+            };
+            (update, Frame::Integer(len as i64))
+        })
+    }
+}
@@ -0,0 +1,36 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::rdb::SaveOnExit;
+
+///关闭服务器
+///
+/// SHUTDOWN [NOSAVE | SAVE]
+///
+/// 与收到SIGTERM一样优雅地关闭：停止接受连接，等待所有连接执行完手头的命令，写完积压的AOF。
+/// 不带参数时配置了save规则才保存快照，SAVE总是保存，NOSAVE不保存。
+/// 执行SHUTDOWN的连接不会收到回复，直接被关闭
+#[derive(Debug)]
+pub struct Shutdown {
+    save: SaveOnExit,
+}
+
+impl Shutdown {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Shutdown, ParseError> {
+        let save = match parse.next_optional_string()? {
+            None => SaveOnExit::Default,
+            Some(option) => match option.to_uppercase().as_str() {
+                "SAVE" => SaveOnExit::Save,
+                "NOSAVE" => SaveOnExit::NoSave,
+                _ => return Err("syntax error".into()),
+            },
+        };
+        Ok(Shutdown { save })
+    }
+
+    ///要求服务器关闭，实际的关闭由服务器在之后完成，回复OK
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        db.request_shutdown(self.save);
+        Frame::Simple("OK".to_string())
+    }
+}
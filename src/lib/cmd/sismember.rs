@@ -0,0 +1,31 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use bytes::Bytes;
+
+///判断成员是否在集合中
+///
+/// SISMEMBER key member
+#[derive(Debug)]
+pub struct SIsMember {
+    key: String,
+    member: Bytes,
+}
+
+impl SIsMember {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<SIsMember, ParseError> {
+        let key = parse.next_string()?;
+        let member = parse.next_bytes()?;
+        Ok(SIsMember { key, member })
+    }
+
+    ///在集合中时回复1，否则回复0
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let member = self.member;
+        db.read(&self.key, |entry| match entry.value.as_set() {
+            Ok(set) => Frame::Integer(set.contains(&member) as i64),
+            Err(e) => e.into(),
+        })
+        .unwrap_or(Frame::Integer(0))
+    }
+}
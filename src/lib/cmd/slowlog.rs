@@ -0,0 +1,78 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use bytes::Bytes;
+
+///查看慢查询日志
+///
+/// SLOWLOG GET [count] | SLOWLOG LEN | SLOWLOG RESET
+///
+/// GET默认返回最新的10条记录，count为-1时返回全部。每条记录依次为编号、开始执行的时间戳、
+/// 耗时（微秒）、参数、连接的地址与名称
+#[derive(Debug)]
+pub struct SlowLog {
+    op: Op,
+}
+
+///SLOWLOG的子命令
+#[derive(Debug)]
+enum Op {
+    ///最新的若干条记录，None代表全部
+    Get(Option<usize>),
+    ///记录的条数
+    Len,
+    ///清空记录
+    Reset,
+}
+
+//GET默认返回的记录数
+const DEFAULT_COUNT: usize = 10;
+
+impl SlowLog {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<SlowLog, ParseError> {
+        let sub = parse.next_string()?.to_uppercase();
+        let op = match sub.as_str() {
+            "GET" => match parse.next_optional_int() {
+                Ok(None) => Op::Get(Some(DEFAULT_COUNT)),
+                Ok(Some(-1)) => Op::Get(None),
+                Ok(Some(count)) if count >= 0 => Op::Get(Some(count as usize)),
+                _ => return Err("count should be greater than or equal to -1".into()),
+            },
+            "LEN" => Op::Len,
+            "RESET" => Op::Reset,
+            _ => {
+                let err = format!("unknown subcommand '{}'. Try SLOWLOG HELP.", sub);
+                return Err(err.into());
+            }
+        };
+        Ok(SlowLog { op })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match self.op {
+            Op::Get(count) => {
+                let entries = db.slowlog().latest(count);
+                Frame::Array(
+                    entries
+                        .into_iter()
+                        .map(|entry| {
+                            Frame::Array(vec![
+                                Frame::Integer(entry.id as i64),
+                                Frame::Integer(entry.time as i64),
+                                Frame::Integer(entry.duration.as_micros() as i64),
+                                Frame::Array(entry.args.into_iter().map(Frame::Bulk).collect()),
+                                Frame::Bulk(Bytes::from(entry.addr)),
+                                Frame::Bulk(Bytes::from(entry.name)),
+                            ])
+                        })
+                        .collect(),
+                )
+            }
+            Op::Len => Frame::Integer(db.slowlog().len() as i64),
+            Op::Reset => {
+                db.slowlog().reset();
+                Frame::Simple("OK".to_string())
+            }
+        }
+    }
+}
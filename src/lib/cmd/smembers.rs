@@ -0,0 +1,37 @@
+use crate::lib::db::Db;
+use crate::lib::frame::{Frame, REPLY_TOO_LARGE};
+use crate::lib::parse::{Parse, ParseError};
+
+///获取集合中的所有成员
+///
+/// SMEMBERS key
+#[derive(Debug)]
+pub struct SMembers {
+    key: String,
+}
+
+impl SMembers {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<SMembers, ParseError> {
+        let key = parse.next_string()?;
+        Ok(SMembers { key })
+    }
+
+    ///成员的顺序不固定，键不存在时回复空集合，回复超出max-reply-size时回复错误
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let mut limit = db.reply_limit();
+        db.read(&self.key, |entry| match entry.value.as_set() {
+            Ok(set) => {
+                let mut members = vec![];
+                for member in set.iter() {
+                    if !limit.add(member.len()) {
+                        return Frame::Error(REPLY_TOO_LARGE.to_string());
+                    }
+                    members.push(Frame::Bulk(member));
+                }
+                Frame::Set(members)
+            }
+            Err(e) => e.into(),
+        })
+        .unwrap_or(Frame::Set(vec![]))
+    }
+}
@@ -0,0 +1,303 @@
+use crate::lib::cmd::{blocking, YIELD_EVERY};
+use crate::lib::db::{Db, Update};
+use crate::lib::frame::{Frame, REPLY_TOO_LARGE};
+use crate::lib::notify::{Class, Event};
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::value::zset::parse_score;
+use crate::lib::value::{Value, WrongType};
+use bytes::Bytes;
+use std::cmp::Ordering;
+
+//权重无法解析为数字时回复的错误
+const NOT_A_DOUBLE: &str = "ERR One or more scores can't be converted into double";
+
+///对列表、集合或有序集合中的元素排序
+///
+/// SORT key [BY pattern] [LIMIT offset count] [GET pattern [GET pattern ...]] [ASC|DESC] [ALPHA] [STORE destination]
+///
+/// 默认把元素当作数字排序，ALPHA时按字节序排序。BY与GET的模式中第一个“*”会被替换为元素，
+/// 得到的键存储的字符串即为权重或要返回的值，模式中带“->field”时读取哈希表中的字段。
+/// BY的模式中没有“*”时不排序，GET的模式为“#”时返回元素本身
+#[derive(Debug)]
+pub struct Sort {
+    key: String,
+    by: Option<Bytes>,
+    //偏移量与数量
+    limit: Option<(i64, i64)>,
+    get: Vec<Bytes>,
+    desc: bool,
+    alpha: bool,
+    store: Option<String>,
+}
+
+impl Sort {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Sort, ParseError> {
+        let key = parse.next_string()?;
+        let mut sort = Sort {
+            key,
+            by: None,
+            limit: None,
+            get: vec![],
+            desc: false,
+            alpha: false,
+            store: None,
+        };
+        loop {
+            match parse.next_string() {
+                Ok(option) => match option.to_uppercase().as_str() {
+                    "BY" => sort.by = Some(parse.next_bytes()?),
+                    "LIMIT" => sort.limit = Some((parse.next_int()?, parse.next_int()?)),
+                    "GET" => sort.get.push(parse.next_bytes()?),
+                    "ASC" => sort.desc = false,
+                    "DESC" => sort.desc = true,
+                    "ALPHA" => sort.alpha = true,
+                    "STORE" => sort.store = Some(parse.next_string()?),
+                    _ => return Err("syntax error".into()),
+                },
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(sort)
+    }
+
+    ///回复排序后的元素或GET取到的值，带STORE时将结果存为列表并回复其长度
+    ///
+    /// 只读取key本身时元素先被复制出来，排序期间不占用任何锁，其他连接照常读写
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        if !self.reads_other_keys() {
+            return self.sort(db);
+        }
+        //排序需要读取多个键，期间不能让其他连接修改它们
+        db.atomically(|| self.sort(db))
+    }
+
+    //BY、GET的模式或STORE是否涉及key之外的键
+    fn reads_other_keys(&self) -> bool {
+        self.store.is_some()
+            || self.by.as_ref().is_some_and(|by| by.contains(&b'*'))
+            || self.get.iter().any(|pattern| &pattern[..] != b"#")
+    }
+
+    fn sort(&self, db: &Db) -> Frame {
+        let elements = match db.read(&self.key, |entry| elements(&entry.value)) {
+            Some(Ok(elements)) => elements,
+            Some(Err(e)) => return e.into(),
+            None => vec![],
+        };
+        let sorted = match &self.by {
+            Some(pattern) if !pattern.contains(&b'*') => elements,
+            //元素很多时排序无法中途让出执行权，先把当前工作线程上的其他任务交给别的线程
+            _ if elements.len() >= YIELD_EVERY => match blocking(|| self.order(db, elements)) {
+                Ok(sorted) => sorted,
+                Err(e) => return e,
+            },
+            _ => match self.order(db, elements) {
+                Ok(sorted) => sorted,
+                Err(e) => return e,
+            },
+        };
+        let (offset, count) = self.limit.unwrap_or((0, -1));
+        let count = usize::try_from(count).unwrap_or(usize::MAX);
+        let page = sorted.into_iter().skip(offset.max(0) as usize).take(count);
+        let results: Vec<Option<Bytes>> = if self.get.is_empty() {
+            page.map(Some).collect()
+        } else {
+            page.flat_map(|element| {
+                self.get
+                    .iter()
+                    .map(|pattern| lookup(db, pattern, &element))
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+        };
+        match &self.store {
+            Some(destination) => store(db, destination, results),
+            None => {
+                let mut limit = db.reply_limit();
+                let mut items = Vec::with_capacity(results.len());
+                for result in results {
+                    if !limit.add(result.as_ref().map_or(0, Bytes::len)) {
+                        return Frame::Error(REPLY_TOO_LARGE.to_string());
+                    }
+                    items.push(result.map_or(Frame::Null, Frame::Bulk));
+                }
+                Frame::Array(items)
+            }
+        }
+    }
+
+    //按元素本身或BY取到的权重排序，权重相同时比较元素本身
+    fn order(&self, db: &Db, elements: Vec<Bytes>) -> Result<Vec<Bytes>, Frame> {
+        let weight = |element: &Bytes| match &self.by {
+            Some(pattern) => lookup(db, pattern, element),
+            None => Some(element.clone()),
+        };
+        let mut sorted: Vec<Bytes> = if self.alpha {
+            let mut keyed: Vec<_> = elements
+                .into_iter()
+                .map(|element| (weight(&element).unwrap_or_default(), element))
+                .collect();
+            keyed.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.cmp(&b.1)));
+            keyed.into_iter().map(|(_, element)| element).collect()
+        } else {
+            let mut keyed = Vec::with_capacity(elements.len());
+            for element in elements {
+                //BY指向的键不存在时权重为0
+                let score = match weight(&element) {
+                    Some(weight) => std::str::from_utf8(&weight)
+                        .ok()
+                        .and_then(|weight| parse_score(weight.trim()))
+                        .ok_or_else(|| Frame::Error(NOT_A_DOUBLE.to_string()))?,
+                    None => 0.0,
+                };
+                keyed.push((score, element));
+            }
+            keyed.sort_by(|a, b| match a.0.partial_cmp(&b.0) {
+                Some(Ordering::Equal) | None => a.1.cmp(&b.1),
+                Some(order) => order,
+            });
+            keyed.into_iter().map(|(_, element)| element).collect()
+        };
+        if self.desc {
+            sorted.reverse();
+        }
+        Ok(sorted)
+    }
+}
+
+//取出可排序的值中的所有元素
+fn elements(value: &Value) -> Result<Vec<Bytes>, WrongType> {
+    match value {
+        Value::List(list) => Ok(list.iter().collect()),
+        Value::Set(set) => Ok(set.iter().collect()),
+        Value::ZSet(zset) => Ok(zset.iter().map(|(member, _)| member).collect()),
+        _ => Err(WrongType),
+    }
+}
+
+//按BY、GET的模式读取元素对应的值，键或字段不存在时返回None
+fn lookup(db: &Db, pattern: &[u8], element: &Bytes) -> Option<Bytes> {
+    if pattern == b"#" {
+        return Some(element.clone());
+    }
+    let star = pattern.iter().position(|b| *b == b'*')?;
+    //“*”之后的“->”将模式分为键与哈希表的字段名
+    let (key_pattern, field) = match pattern[star..].windows(2).position(|w| w == b"->") {
+        Some(arrow) => (&pattern[..star + arrow], Some(&pattern[star + arrow + 2..])),
+        None => (pattern, None),
+    };
+    let mut key = key_pattern[..star].to_vec();
+    key.extend_from_slice(element);
+    key.extend_from_slice(&key_pattern[star + 1..]);
+    let key = String::from_utf8(key).ok()?;
+    db.read(&key, |entry| match (&entry.value, field) {
+        (Value::String(_) | Value::Int(_), None) => entry.value.as_string().ok(),
+        (Value::Hash(hash), Some(field)) => hash.get(field),
+        _ => None,
+    })
+    .flatten()
+}
+
+//将结果存为列表，不存在的值存为空字符串，结果为空时删除目标键
+fn store(db: &Db, destination: &str, results: Vec<Option<Bytes>>) -> Frame {
+    let len = results.len() as i64;
+    if results.is_empty() {
+        if db.remove(destination).is_some() {
+            db.notify(Class::Generic, "del", destination);
+        }
+        return Frame::Integer(0);
+    }
+    let list = results.into_iter().map(Option::unwrap_or_default).collect();
+    let event = Event::new(Class::List, "sortstore");
+    db.update_notify(destination.to_string(), event, |_| {
+        let update = Update::Set {
+            value: Value::List(list),
+            expires_at: None,
+        };
+        (update, ())
+    });
+    Frame::Integer(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::cmd::Command;
+    use crate::lib::config::Config;
+    use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+    use std::sync::Arc;
+
+    fn call(db: &Db, args: &[&str]) -> Frame {
+        let frame = Frame::Array(
+            args.iter()
+                .map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes())))
+                .collect(),
+        );
+        Command::from_frame(frame).unwrap().apply_now(db)
+    }
+
+    #[test]
+    fn sorts_numbers_and_strings() {
+        let db = Db::new(Config::default());
+        let list = ["10", "9", "100", "-1"].map(Bytes::from);
+        db.set("list".to_string(), Value::List(list.into_iter().collect()));
+        let sorted = |args: &[&str]| match call(&db, args) {
+            Frame::Array(items) => items,
+            frame => panic!("unexpected reply {:?}", frame),
+        };
+        let numeric = sorted(&["SORT", "list"]);
+        assert!(["-1", "9", "10", "100"]
+            .iter()
+            .zip(&numeric)
+            .all(|(e, item)| *item == *e));
+        let alpha = sorted(&["SORT", "list", "ALPHA", "DESC"]);
+        assert!(["9", "100", "10", "-1"]
+            .iter()
+            .zip(&alpha)
+            .all(|(e, item)| *item == *e));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn concurrent_get_makes_progress_during_a_large_sort() {
+        let db = Db::new(Config::default());
+        let list = (0..200_000).rev().map(|i: u32| Bytes::from(i.to_string()));
+        db.set("list".to_string(), Value::List(list.collect()));
+        db.set("key".to_string(), Bytes::from("value").into());
+        let done = Arc::new(AtomicBool::new(false));
+        //只有一个工作线程，排序不把其他任务交出去时GET只能等它执行完
+        let sort = tokio::spawn({
+            let (db, done) = (db.clone(), done.clone());
+            async move {
+                let reply = call(&db, &["SORT", "list"]);
+                done.store(true, AtomicOrdering::SeqCst);
+                reply
+            }
+        });
+        let get = tokio::spawn({
+            let (db, done) = (db.clone(), done.clone());
+            async move { (db.get("key").unwrap(), done.load(AtomicOrdering::SeqCst)) }
+        });
+        let (value, sort_done) = get.await.unwrap();
+        assert_eq!(value, Some(Bytes::from("value")));
+        assert!(!sort_done);
+        assert!(matches!(sort.await.unwrap(), Frame::Array(items) if items.len() == 200_000));
+    }
+
+    #[test]
+    fn reply_too_large() {
+        let mut config = Config::default();
+        config.set("max-reply-size", "1024").unwrap();
+        let db = Db::new(config);
+        let list = (0..100).map(|i: u32| Bytes::from(i.to_string()));
+        db.set("list".to_string(), Value::List(list.collect()));
+        assert!(matches!(call(&db, &["SORT", "list"]), Frame::Error(e) if e == REPLY_TOO_LARGE));
+        let page = call(&db, &["SORT", "list", "LIMIT", "0", "10"]);
+        assert!(matches!(page, Frame::Array(items) if items.len() == 10));
+        //STORE只回复长度，不受限制
+        assert_eq!(
+            call(&db, &["SORT", "list", "STORE", "sorted"]),
+            Frame::Integer(100)
+        );
+    }
+}
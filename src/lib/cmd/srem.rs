@@ -0,0 +1,45 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::notify::{Class, Event};
+use crate::lib::parse::{Parse, ParseError};
+use bytes::Bytes;
+
+///从集合中删除成员
+///
+/// SREM key member [member ...]
+///
+/// 成员全部删除后键也随之删除
+#[derive(Debug)]
+pub struct SRem {
+    key: String,
+    members: Vec<Bytes>,
+}
+
+impl SRem {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<SRem, ParseError> {
+        let key = parse.next_string()?;
+        let mut members = vec![parse.next_bytes()?];
+        loop {
+            match parse.next_bytes() {
+                Ok(member) => members.push(member),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(SRem { key, members })
+    }
+
+    ///回复实际删除的成员数
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let SRem { key, members } = self;
+        db.mutate_notify(key, Event::new(Class::Set, "srem"), |slot| {
+            let set = match slot.as_mut().map(|value| value.as_set_mut()) {
+                None => return (false, Frame::Integer(0)),
+                Some(Err(e)) => return (false, e.into()),
+                Some(Ok(set)) => set,
+            };
+            let removed = members.iter().filter(|member| set.remove(member)).count();
+            (removed > 0, Frame::Integer(removed as i64))
+        })
+    }
+}
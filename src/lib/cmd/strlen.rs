@@ -0,0 +1,26 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+
+///获取值的长度
+///
+/// STRLEN key
+#[derive(Debug)]
+pub struct StrLen {
+    key: String,
+}
+
+impl StrLen {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<StrLen, ParseError> {
+        let key = parse.next_string()?;
+        Ok(StrLen { key })
+    }
+
+    ///键不存在时回复0
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        match db.get(&self.key) {
+            Ok(value) => Frame::Integer(value.map_or(0, |value| value.len() as i64)),
+            Err(e) => e.into(),
+        }
+    }
+}
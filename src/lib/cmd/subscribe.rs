@@ -0,0 +1,516 @@
+use crate::lib;
+use crate::lib::cmd::Exit;
+use crate::lib::codec::Protocol;
+use crate::lib::conn::{Connection, Stream};
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::shutdown::Shutdown;
+use crate::lib::tracking::{self, Invalidation};
+use bytes::Bytes;
+use std::collections::HashMap;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, mpsc};
+use tokio::task::JoinHandle;
+
+///订阅频道
+///
+/// SUBSCRIBE channel [channel ...] | PSUBSCRIBE pattern [pattern ...] | SSUBSCRIBE shardchannel [shardchannel ...]
+///
+/// 订阅后连接进入订阅模式，频道上发布的消息以["message", channel, message]的数组推送给客户端，
+/// 按模式订阅收到的消息则为["pmessage", pattern, channel, message]，
+/// 分片频道的消息则为["smessage", shardchannel, message]。
+/// 分片频道只接收SPUBLISH发布的消息，与同名的普通频道互不相通。
+/// 订阅模式下只接受(P|S)SUBSCRIBE、(P|S)UNSUBSCRIBE、PING、RESET与QUIT，退订全部频道、模式与分片频道或RESET后才会退出
+#[derive(Debug)]
+pub struct Subscribe {
+    channels: Vec<String>,
+    kind: Kind,
+}
+
+///退订频道
+///
+/// UNSUBSCRIBE [channel [channel ...]] | PUNSUBSCRIBE [pattern [pattern ...]] | SUNSUBSCRIBE [shardchannel [shardchannel ...]]
+///
+/// 不带参数时退订全部频道、全部模式或全部分片频道
+#[derive(Debug)]
+pub struct Unsubscribe {
+    channels: Vec<String>,
+    kind: Kind,
+}
+
+///订阅的种类
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Kind {
+    ///按频道名订阅，(UN)SUBSCRIBE
+    Channel,
+    ///按glob风格的模式订阅，P(UN)SUBSCRIBE
+    Pattern,
+    ///订阅分片频道，S(UN)SUBSCRIBE
+    Shard,
+}
+
+///转发任务与连接之间的通道容量
+const FORWARD_CAPACITY: usize = 64;
+
+impl Subscribe {
+    pub(crate) fn parse_frames(parse: &mut Parse, kind: Kind) -> Result<Subscribe, ParseError> {
+        let mut channels = vec![parse.next_string()?];
+        loop {
+            match parse.next_string() {
+                Ok(channel) => channels.push(channel),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(Subscribe { channels, kind })
+    }
+
+    ///进入订阅模式，直到退订全部频道、连接关闭或服务器关闭才返回
+    pub(crate) async fn apply<S: Stream>(
+        self,
+        db: &Db,
+        conn: &mut Connection<S>,
+        invalidations: &mut mpsc::UnboundedReceiver<Invalidation>,
+        shutdown: &mut Shutdown,
+    ) -> lib::Result<Exit> {
+        let mut subscriber = Subscriber::new();
+        subscriber
+            .subscribe(db, conn, self.channels, self.kind)
+            .await?;
+        subscriber.run(db, conn, invalidations, shutdown).await
+    }
+}
+
+impl Unsubscribe {
+    pub(crate) fn parse_frames(parse: &mut Parse, kind: Kind) -> Result<Unsubscribe, ParseError> {
+        let mut channels = vec![];
+        loop {
+            match parse.next_string() {
+                Ok(channel) => channels.push(channel),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(Unsubscribe { channels, kind })
+    }
+
+    ///不在订阅模式时执行，对每个频道回复剩余订阅数为0的退订消息
+    pub(crate) async fn apply<S: Stream>(self, conn: &mut Connection<S>) -> lib::Result<()> {
+        Subscriber::new()
+            .unsubscribe(conn, self.channels, self.kind)
+            .await
+    }
+}
+
+///订阅模式下可以执行的命令
+enum Request {
+    Subscribe(Subscribe),
+    Unsubscribe(Unsubscribe),
+    Ping(Option<Bytes>),
+    Reset,
+    Quit,
+}
+
+///转发给连接的一条消息
+struct Message {
+    kind: Kind,
+    //订阅的频道名或模式
+    subscription: String,
+    //消息实际发布的频道
+    channel: String,
+    message: Bytes,
+}
+
+///订阅模式下连接的状态
+///
+/// 每个频道或模式由一个任务把广播的消息转发到同一个通道中，连接只需等待这一个通道
+struct Subscriber {
+    //已订阅的频道与转发该频道消息的任务
+    channels: HashMap<String, JoinHandle<()>>,
+    //已订阅的模式与转发该模式消息的任务
+    patterns: HashMap<String, JoinHandle<()>>,
+    //已订阅的分片频道与转发该频道消息的任务
+    shards: HashMap<String, JoinHandle<()>>,
+    //交给转发任务的发送端
+    sender: mpsc::Sender<Message>,
+    //汇总所有频道的消息
+    receiver: mpsc::Receiver<Message>,
+}
+
+impl Subscriber {
+    fn new() -> Subscriber {
+        let (sender, receiver) = mpsc::channel(FORWARD_CAPACITY);
+        Subscriber {
+            channels: HashMap::new(),
+            patterns: HashMap::new(),
+            shards: HashMap::new(),
+            sender,
+            receiver,
+        }
+    }
+
+    //订阅总数，连接在它降为0之前停留在订阅模式
+    fn total(&self) -> usize {
+        self.channels.len() + self.patterns.len() + self.shards.len()
+    }
+
+    //(退)订回复中的订阅数，与redis一致，分片频道与频道、模式分开计数
+    fn count(&self, kind: Kind) -> usize {
+        match kind {
+            Kind::Channel | Kind::Pattern => self.channels.len() + self.patterns.len(),
+            Kind::Shard => self.shards.len(),
+        }
+    }
+
+    //某一种类的订阅
+    fn subscriptions(&mut self, kind: Kind) -> &mut HashMap<String, JoinHandle<()>> {
+        match kind {
+            Kind::Channel => &mut self.channels,
+            Kind::Pattern => &mut self.patterns,
+            Kind::Shard => &mut self.shards,
+        }
+    }
+
+    //订阅频道、模式或分片频道，每个回复一条订阅消息，已订阅的不会重复订阅
+    async fn subscribe<S: Stream>(
+        &mut self,
+        db: &Db,
+        conn: &mut Connection<S>,
+        channels: Vec<String>,
+        kind: Kind,
+    ) -> lib::Result<()> {
+        let name = match kind {
+            Kind::Channel => "subscribe",
+            Kind::Pattern => "psubscribe",
+            Kind::Shard => "ssubscribe",
+        };
+        for channel in channels {
+            if !self.subscriptions(kind).contains_key(&channel) {
+                let task = self.spawn_forward(db, channel.clone(), kind);
+                self.subscriptions(kind).insert(channel.clone(), task);
+            }
+            let frame = reply(name, Frame::Bulk(channel.into()), self.count(kind));
+            conn.write_frame(&frame).await?;
+        }
+        Ok(())
+    }
+
+    //启动转发频道、模式或分片频道上消息的任务
+    fn spawn_forward(&self, db: &Db, channel: String, kind: Kind) -> JoinHandle<()> {
+        let sender = self.sender.clone();
+        match kind {
+            Kind::Pattern => {
+                let receiver = db.psubscribe(channel.clone());
+                tokio::spawn(forward(receiver, sender, move |(name, message)| Message {
+                    kind,
+                    subscription: channel.clone(),
+                    channel: name,
+                    message,
+                }))
+            }
+            Kind::Channel | Kind::Shard => {
+                let receiver = if kind == Kind::Shard {
+                    db.ssubscribe(channel.clone())
+                } else {
+                    db.subscribe(channel.clone())
+                };
+                tokio::spawn(forward(receiver, sender, move |message| Message {
+                    kind,
+                    subscription: channel.clone(),
+                    channel: channel.clone(),
+                    message,
+                }))
+            }
+        }
+    }
+
+    //退订频道、模式或分片频道，每个回复一条退订消息，channels为空时退订该种类的全部订阅
+    async fn unsubscribe<S: Stream>(
+        &mut self,
+        conn: &mut Connection<S>,
+        channels: Vec<String>,
+        kind: Kind,
+    ) -> lib::Result<()> {
+        let name = match kind {
+            Kind::Channel => "unsubscribe",
+            Kind::Pattern => "punsubscribe",
+            Kind::Shard => "sunsubscribe",
+        };
+        let channels = if channels.is_empty() {
+            self.subscriptions(kind).keys().cloned().collect()
+        } else {
+            channels
+        };
+        //没有订阅任何频道时仍需回复一条退订消息
+        if channels.is_empty() {
+            conn.write_frame(&reply(name, Frame::Null, self.count(kind)))
+                .await?;
+            return Ok(());
+        }
+        for channel in channels {
+            if let Some(task) = self.subscriptions(kind).remove(&channel) {
+                task.abort();
+            }
+            let frame = reply(name, Frame::Bulk(channel.into()), self.count(kind));
+            conn.write_frame(&frame).await?;
+        }
+        Ok(())
+    }
+
+    //转发消息并处理订阅模式下的命令，退订全部频道、收到RESET、连接关闭或服务器关闭时返回
+    async fn run<S: Stream>(
+        &mut self,
+        db: &Db,
+        conn: &mut Connection<S>,
+        invalidations: &mut mpsc::UnboundedReceiver<Invalidation>,
+        shutdown: &mut Shutdown,
+    ) -> lib::Result<Exit> {
+        while self.total() > 0 {
+            tokio::select! {
+                _ = shutdown.recv() => return Ok(Exit::Done),
+                Some(message) = self.receiver.recv() => {
+                    if let Some(frame) = self.message_frame(message) {
+                        conn.write_frame(&frame).await?;
+                    }
+                }
+                //RESP2的连接订阅了__redis__:invalidate时以频道消息的形式收到失效消息
+                Some(invalidation) = invalidations.recv() => {
+                    if conn.protocol() == Protocol::Resp3 {
+                        conn.write_frame(&invalidation.push()).await?;
+                    } else if self.channels.contains_key(tracking::CHANNEL) {
+                        conn.write_frame(&invalidation.message()).await?;
+                    }
+                }
+                frame = conn.read_frame() => {
+                    let frame = match frame? {
+                        Some(frame) => frame,
+                        None => return Ok(Exit::Done),
+                    };
+                    match parse_request(frame) {
+                        Ok(Request::Subscribe(cmd)) => {
+                            self.subscribe(db, conn, cmd.channels, cmd.kind).await?
+                        }
+                        Ok(Request::Unsubscribe(cmd)) => {
+                            self.unsubscribe(conn, cmd.channels, cmd.kind).await?
+                        }
+                        Ok(Request::Ping(message)) => {
+                            let message = message.unwrap_or_default();
+                            let frame = Frame::Array(vec![
+                                Frame::Bulk(Bytes::from_static(b"pong")),
+                                Frame::Bulk(message),
+                            ]);
+                            conn.write_frame(&frame).await?;
+                        }
+                        //订阅随Subscriber一起释放
+                        Ok(Request::Reset) => return Ok(Exit::Reset),
+                        Ok(Request::Quit) => return Ok(Exit::Quit),
+                        Err(e) => conn.write_frame(&e.into()).await?,
+                    }
+                }
+            }
+        }
+        Ok(Exit::Done)
+    }
+
+    //将转发来的消息转化为推送给客户端的帧，退订之前已经转发出来的消息不再推送
+    fn message_frame(&mut self, message: Message) -> Option<Frame> {
+        if !self
+            .subscriptions(message.kind)
+            .contains_key(&message.subscription)
+        {
+            return None;
+        }
+        let frame = match message.kind {
+            Kind::Pattern => Frame::Push(vec![
+                Frame::Bulk(Bytes::from_static(b"pmessage")),
+                Frame::Bulk(message.subscription.into()),
+                Frame::Bulk(message.channel.into()),
+                Frame::Bulk(message.message),
+            ]),
+            Kind::Channel | Kind::Shard => {
+                let name: &'static [u8] = if message.kind == Kind::Shard {
+                    b"smessage"
+                } else {
+                    b"message"
+                };
+                Frame::Push(vec![
+                    Frame::Bulk(Bytes::from_static(name)),
+                    Frame::Bulk(message.channel.into()),
+                    Frame::Bulk(message.message),
+                ])
+            }
+        };
+        Some(frame)
+    }
+}
+
+impl Drop for Subscriber {
+    fn drop(&mut self) {
+        for task in self
+            .channels
+            .values()
+            .chain(self.patterns.values())
+            .chain(self.shards.values())
+        {
+            task.abort();
+        }
+    }
+}
+
+//把频道上广播的消息转发给连接，连接退出后随之结束
+async fn forward<T: Clone>(
+    mut receiver: broadcast::Receiver<T>,
+    sender: mpsc::Sender<Message>,
+    to_message: impl Fn(T) -> Message,
+) {
+    loop {
+        match receiver.recv().await {
+            Ok(message) => {
+                if sender.send(to_message(message)).await.is_err() {
+                    return;
+                }
+            }
+            //落后太多时跳过丢失的消息继续接收
+            Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+//解析订阅模式下收到的命令
+fn parse_request(frame: Frame) -> Result<Request, ParseError> {
+    let mut parse = Parse::new(frame)?;
+    let name = parse.next_string()?.to_lowercase();
+    let request = match name.as_str() {
+        "subscribe" => Request::Subscribe(Subscribe::parse_frames(&mut parse, Kind::Channel)?),
+        "psubscribe" => Request::Subscribe(Subscribe::parse_frames(&mut parse, Kind::Pattern)?),
+        "ssubscribe" => Request::Subscribe(Subscribe::parse_frames(&mut parse, Kind::Shard)?),
+        "unsubscribe" => {
+            Request::Unsubscribe(Unsubscribe::parse_frames(&mut parse, Kind::Channel)?)
+        }
+        "punsubscribe" => {
+            Request::Unsubscribe(Unsubscribe::parse_frames(&mut parse, Kind::Pattern)?)
+        }
+        "sunsubscribe" => Request::Unsubscribe(Unsubscribe::parse_frames(&mut parse, Kind::Shard)?),
+        "ping" => match parse.next_bytes() {
+            Ok(message) => Request::Ping(Some(message)),
+            Err(ParseError::EndOfStream) => Request::Ping(None),
+            Err(e) => return Err(e),
+        },
+        "reset" => {
+            parse.finish()?;
+            Request::Reset
+        }
+        "quit" => Request::Quit,
+        _ => {
+            let err = format!(
+                "Can't execute '{}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / RESET / QUIT are allowed in this context",
+                name
+            );
+            return Err(err.into());
+        }
+    };
+    Ok(request)
+}
+
+//订阅与退订的回复：类型、频道与当前的订阅数，RESP3中以推送的形式发送
+fn reply(kind: &'static str, channel: Frame, count: usize) -> Frame {
+    Frame::Push(vec![
+        Frame::Bulk(Bytes::from_static(kind.as_bytes())),
+        channel,
+        Frame::Integer(count as i64),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::config::Config;
+    use tokio::io::DuplexStream;
+
+    //读取下一个推送，把各个元素转成字符串
+    async fn next(conn: &mut Connection<DuplexStream>) -> Vec<String> {
+        match conn.read_frame().await.unwrap() {
+            Some(Frame::Array(items)) | Some(Frame::Push(items)) => {
+                items.iter().map(|item| item.to_string()).collect()
+            }
+            frame => panic!("unexpected frame {:?}", frame),
+        }
+    }
+
+    fn command(args: &[&str]) -> Frame {
+        Frame::Array(
+            args.iter()
+                .map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes())))
+                .collect(),
+        )
+    }
+
+    //在内存管道上执行订阅命令，返回客户端一侧的连接
+    fn subscribe(db: &Db, channel: &str, kind: Kind) -> Connection<DuplexStream> {
+        let (client, server) = tokio::io::duplex(4096);
+        let subscriber = db.clone();
+        let cmd = Subscribe {
+            channels: vec![channel.to_string()],
+            kind,
+        };
+        tokio::spawn(async move {
+            let mut conn = Connection::new(server);
+            let (_sender, mut invalidations) = mpsc::unbounded_channel();
+            let (_notify, receiver) = broadcast::channel(1);
+            let mut shutdown = Shutdown::new(receiver);
+            cmd.apply(&subscriber, &mut conn, &mut invalidations, &mut shutdown)
+                .await
+        });
+        Connection::new(client)
+    }
+
+    #[tokio::test]
+    async fn shard_channels_are_separate() {
+        let db = Db::new(Config::default());
+        let mut client = subscribe(&db, "news", Kind::Shard);
+        assert_eq!(next(&mut client).await, ["ssubscribe", "news", "1"]);
+        client
+            .write_frame(&command(&["SUBSCRIBE", "news"]))
+            .await
+            .unwrap();
+        //分片频道与普通频道分开计数
+        assert_eq!(next(&mut client).await, ["subscribe", "news", "1"]);
+
+        assert_eq!(db.spublish("news", Bytes::from("shard")), 1);
+        assert_eq!(next(&mut client).await, ["smessage", "news", "shard"]);
+        assert_eq!(db.publish("news", Bytes::from("global")), 1);
+        assert_eq!(next(&mut client).await, ["message", "news", "global"]);
+
+        //退订分片频道后普通频道仍然有效
+        client
+            .write_frame(&command(&["SUNSUBSCRIBE", "news"]))
+            .await
+            .unwrap();
+        assert_eq!(next(&mut client).await, ["sunsubscribe", "news", "0"]);
+        db.spublish("news", Bytes::from("shard"));
+        assert_eq!(db.publish("news", Bytes::from("global")), 1);
+        assert_eq!(next(&mut client).await, ["message", "news", "global"]);
+    }
+
+    #[tokio::test]
+    async fn binary_messages_are_delivered_unchanged() {
+        let db = Db::new(Config::default());
+        let mut client = subscribe(&db, "bin", Kind::Channel);
+        assert_eq!(next(&mut client).await, ["subscribe", "bin", "1"]);
+        let payload = Bytes::from_static(b"\x00\xff\xfe\r\n\x80text\x00");
+        let publish = Frame::Array(vec![
+            Frame::Bulk("PUBLISH".into()),
+            Frame::Bulk("bin".into()),
+            Frame::Bulk(payload.clone()),
+        ]);
+        let publish = crate::lib::cmd::Command::from_frame(publish).unwrap();
+        assert_eq!(publish.apply_now(&db), Frame::Integer(1));
+        let message = match client.read_frame().await.unwrap() {
+            Some(Frame::Array(items)) | Some(Frame::Push(items)) => items,
+            frame => panic!("unexpected frame {:?}", frame),
+        };
+        assert_eq!(message[2], Frame::Bulk(payload));
+    }
+}
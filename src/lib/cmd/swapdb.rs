@@ -0,0 +1,41 @@
+use crate::lib::cmd::select::select;
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+
+///交换两个逻辑数据库的内容
+///
+/// SWAPDB index1 index2
+///
+/// 已经选择了其中一个数据库的连接会立即看到交换过来的内容
+#[derive(Debug)]
+pub struct SwapDb {
+    first: i64,
+    second: i64,
+}
+
+impl SwapDb {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<SwapDb, ParseError> {
+        let first = parse
+            .next_int()
+            .map_err(|_| ParseError::from("invalid first DB index"))?;
+        let second = parse
+            .next_int()
+            .map_err(|_| ParseError::from("invalid second DB index"))?;
+        Ok(SwapDb { first, second })
+    }
+
+    ///回复OK
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let first = match select(db, self.first) {
+            Ok(first) => first.index(),
+            Err(e) => return e,
+        };
+        let second = match select(db, self.second) {
+            Ok(second) => second.index(),
+            Err(e) => return e,
+        };
+        db.swap(first, second);
+        Frame::Simple("OK".to_string())
+    }
+}
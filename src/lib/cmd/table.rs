@@ -0,0 +1,388 @@
+use crate::lib::cmd::custom::CommandHandler;
+use crate::lib::frame::Frame;
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock, RwLock};
+
+///命令表中的一项
+///
+/// arity与redis的约定一致：包括命令名本身在内的参数个数，为负数时代表至少需要其绝对值个参数
+#[derive(Debug)]
+pub(crate) struct CommandInfo {
+    ///命令名称，小写
+    pub(crate) name: &'static str,
+    ///参数个数
+    pub(crate) arity: i64,
+    ///命令的属性，与redis的COMMAND命令中的名称一致
+    pub(crate) flags: &'static [&'static str],
+    ///第一个键的位置，没有键时为0
+    pub(crate) first_key: i64,
+    ///最后一个键的位置，为负数时从末尾倒数
+    pub(crate) last_key: i64,
+    ///相邻两个键的间隔
+    pub(crate) key_step: i64,
+}
+
+impl CommandInfo {
+    ///argc个参数（包括命令名）是否满足命令的要求
+    pub(crate) fn accepts(&self, argc: usize) -> bool {
+        let argc = argc as i64;
+        if self.arity >= 0 {
+            argc == self.arity
+        } else {
+            argc >= -self.arity
+        }
+    }
+
+    ///命令是否带有指定的属性
+    pub(crate) fn has_flag(&self, flag: &str) -> bool {
+        self.flags.contains(&flag)
+    }
+
+    ///键的位置是否取决于其他参数，这样的命令由key_args单独处理
+    pub(crate) fn movable_keys(&self) -> bool {
+        matches!(
+            self.name,
+            "eval" | "evalsha" | "xread" | "xreadgroup" | "sort"
+        )
+    }
+
+    ///参数（包括命令名）中的键
+    ///
+    /// 大多数命令的键位于固定的位置，EVAL、XREAD等键的位置取决于其他参数的命令单独处理
+    pub(crate) fn key_args<'a>(&self, args: &'a [Bytes]) -> Vec<&'a Bytes> {
+        let argc = args.len() as i64;
+        match self.name {
+            "eval" | "evalsha" => {
+                let numkeys = args
+                    .get(2)
+                    .and_then(|n| std::str::from_utf8(n).ok())
+                    .and_then(|n| n.parse::<usize>().ok())
+                    .unwrap_or(0);
+                args.iter().skip(3).take(numkeys).collect()
+            }
+            //STREAMS之后的参数前一半是键，后一半是ID
+            "xread" | "xreadgroup" => {
+                let streams = args
+                    .iter()
+                    .position(|arg| arg.eq_ignore_ascii_case(b"streams"))
+                    .map_or(args.len(), |i| i + 1);
+                let rest = &args[streams..];
+                rest[..rest.len() / 2].iter().collect()
+            }
+            //SORT的STORE选项指定的目标也是键
+            "sort" => {
+                let mut keys: Vec<_> = args.iter().skip(1).take(1).collect();
+                let store = args
+                    .iter()
+                    .skip(2)
+                    .position(|arg| arg.eq_ignore_ascii_case(b"store"));
+                keys.extend(store.and_then(|i| args.get(i + 3)));
+                keys
+            }
+            //键参数为空字符串时，KEYS选项之后的参数都是键
+            "migrate" => match args.get(3) {
+                Some(key) if !key.is_empty() => vec![key],
+                _ => {
+                    let keys = args
+                        .iter()
+                        .skip(6)
+                        .position(|arg| arg.eq_ignore_ascii_case(b"keys"))
+                        .map_or(args.len(), |i| i + 7);
+                    args[keys..].iter().collect()
+                }
+            },
+            _ if self.first_key == 0 => vec![],
+            _ => {
+                let last = if self.last_key < 0 {
+                    argc + self.last_key
+                } else {
+                    self.last_key.min(argc - 1)
+                };
+                (self.first_key..=last)
+                    .step_by(self.key_step as usize)
+                    .filter_map(|i| args.get(i as usize))
+                    .collect()
+            }
+        }
+    }
+}
+
+///命令表中所有的命令，注册的自定义命令按名称排在内置命令之后
+pub(crate) fn commands() -> Vec<&'static CommandInfo> {
+    let mut custom: Vec<&'static CommandInfo> = registry()
+        .read()
+        .unwrap()
+        .values()
+        .map(|(info, _)| *info)
+        .collect();
+    custom.sort_by_key(|info| info.name);
+    COMMANDS.iter().chain(custom).collect()
+}
+
+///命令帧中的各个参数，包括命令名，不是数组的帧没有参数
+pub(crate) fn args(frame: &Frame) -> Vec<Bytes> {
+    match frame {
+        Frame::Array(parts) => parts
+            .iter()
+            .filter_map(|part| match part {
+                Frame::Bulk(arg) => Some(arg.clone()),
+                Frame::Simple(arg) => Some(Bytes::copy_from_slice(arg.as_bytes())),
+                _ => None,
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+///按名称查找命令，名称需为小写，不支持的命令返回None
+pub(crate) fn lookup(name: &str) -> Option<&'static CommandInfo> {
+    builtin(name).or_else(|| registry().read().unwrap().get(name).map(|(info, _)| *info))
+}
+
+///按名称查找内置的命令，名称需为小写
+pub(crate) fn builtin(name: &str) -> Option<&'static CommandInfo> {
+    static INDEX: OnceLock<HashMap<&'static str, &'static CommandInfo>> = OnceLock::new();
+    INDEX
+        .get_or_init(|| COMMANDS.iter().map(|info| (info.name, info)).collect())
+        .get(name)
+        .copied()
+}
+
+///注册一个自定义命令，同名的自定义命令被替换，名称需为小写且不能与内置命令重名
+///
+/// 命令表中的项需要在整个进程中有效，因此注册的信息不会被释放，只应在启动时注册
+pub(crate) fn register(
+    name: &str,
+    arity: i64,
+    flags: &'static [&'static str],
+    handler: Arc<dyn CommandHandler>,
+) {
+    let name: &'static str = Box::leak(name.to_string().into_boxed_str());
+    let entry: &'static CommandInfo = Box::leak(Box::new(info(name, arity, flags)));
+    registry().write().unwrap().insert(name, (entry, handler));
+}
+
+///自定义命令的处理函数，不是自定义命令时返回None
+pub(crate) fn handler(name: &str) -> Option<Arc<dyn CommandHandler>> {
+    let registry = registry().read().unwrap();
+    registry.get(name).map(|(_, handler)| handler.clone())
+}
+
+//自定义命令的信息与处理函数，进程中的所有服务器共用
+type Registry = RwLock<HashMap<&'static str, (&'static CommandInfo, Arc<dyn CommandHandler>)>>;
+
+//注册的自定义命令，第一次使用时创建
+fn registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(Registry::default)
+}
+
+const fn info(name: &'static str, arity: i64, flags: &'static [&'static str]) -> CommandInfo {
+    CommandInfo {
+        name,
+        arity,
+        flags,
+        first_key: 0,
+        last_key: 0,
+        key_step: 0,
+    }
+}
+
+impl CommandInfo {
+    //设置键的位置
+    const fn keys(self, first_key: i64, last_key: i64, key_step: i64) -> CommandInfo {
+        CommandInfo {
+            first_key,
+            last_key,
+            key_step,
+            ..self
+        }
+    }
+}
+
+//常见的属性组合
+//可能增加内存占用的写命令
+const WRITE: &[&str] = &["write", "denyoom"];
+const WRITE_FAST: &[&str] = &["write", "denyoom", "fast"];
+//不会增加内存占用的写命令
+const DEL: &[&str] = &["write"];
+const DEL_FAST: &[&str] = &["write", "fast"];
+const READ: &[&str] = &["readonly"];
+const READ_FAST: &[&str] = &["readonly", "fast"];
+const BLOCK: &[&str] = &["write", "blocking"];
+const PUBSUB: &[&str] = &["pubsub", "noscript", "loading", "stale"];
+const ADMIN: &[&str] = &["admin", "noscript", "loading", "stale"];
+const FAST: &[&str] = &["fast", "loading", "stale"];
+//事务命令与维护连接状态的命令
+const TX: &[&str] = &["noscript", "loading", "stale", "fast"];
+const SCRIPT: &[&str] = &["noscript", "stale"];
+//查看服务器状态的命令
+const STATUS: &[&str] = &["loading", "stale"];
+//设置了密码时未认证的连接也能执行的命令
+const NO_AUTH: &[&str] = &["noscript", "loading", "stale", "fast", "no-auth"];
+
+//新增命令时需要同时在这里与command_table.rs的DOCS与ARGUMENTS中登记
+const COMMANDS: &[CommandInfo] = &[
+    info("get", 2, READ_FAST).keys(1, 1, 1),
+    info("set", -3, WRITE).keys(1, 1, 1),
+    info("expire", 3, DEL_FAST).keys(1, 1, 1),
+    info("pexpire", 3, DEL_FAST).keys(1, 1, 1),
+    info("expireat", 3, DEL_FAST).keys(1, 1, 1),
+    info("pexpireat", 3, DEL_FAST).keys(1, 1, 1),
+    info("ttl", 2, READ_FAST).keys(1, 1, 1),
+    info("pttl", 2, READ_FAST).keys(1, 1, 1),
+    info("persist", 2, DEL_FAST).keys(1, 1, 1),
+    info("del", -2, DEL).keys(1, -1, 1),
+    info("unlink", -2, DEL).keys(1, -1, 1),
+    info("exists", -2, READ_FAST).keys(1, -1, 1),
+    info("incr", 2, WRITE_FAST).keys(1, 1, 1),
+    info("decr", 2, WRITE_FAST).keys(1, 1, 1),
+    info("incrby", 3, WRITE_FAST).keys(1, 1, 1),
+    info("decrby", 3, WRITE_FAST).keys(1, 1, 1),
+    info("incrbyfloat", 3, WRITE).keys(1, 1, 1),
+    info("mget", -2, READ).keys(1, -1, 1),
+    info("mset", -3, WRITE).keys(1, -1, 2),
+    info("msetnx", -3, WRITE).keys(1, -1, 2),
+    info("append", 3, WRITE).keys(1, 1, 1),
+    info("strlen", 2, READ_FAST).keys(1, 1, 1),
+    info("getrange", 4, READ).keys(1, 1, 1),
+    info("setrange", 4, WRITE).keys(1, 1, 1),
+    info("getdel", 2, DEL).keys(1, 1, 1),
+    info("getex", -2, DEL).keys(1, 1, 1),
+    info("setbit", 4, WRITE).keys(1, 1, 1),
+    info("getbit", 3, READ_FAST).keys(1, 1, 1),
+    info("bitcount", -2, READ).keys(1, 1, 1),
+    info("bitpos", -3, READ).keys(1, 1, 1),
+    info("bitop", -4, WRITE).keys(2, -1, 1),
+    info("pfadd", -2, WRITE).keys(1, 1, 1),
+    info("pfcount", -2, READ).keys(1, -1, 1),
+    info("pfmerge", -2, WRITE).keys(1, -1, 1),
+    info("type", 2, READ_FAST).keys(1, 1, 1),
+    info("hset", -4, WRITE).keys(1, 1, 1),
+    info("hmset", -4, WRITE).keys(1, 1, 1),
+    info("hsetnx", 4, WRITE_FAST).keys(1, 1, 1),
+    info("hget", 3, READ_FAST).keys(1, 1, 1),
+    info("hdel", -3, DEL_FAST).keys(1, 1, 1),
+    info("hgetall", 2, READ).keys(1, 1, 1),
+    info("hincrby", 4, WRITE_FAST).keys(1, 1, 1),
+    info("hlen", 2, READ_FAST).keys(1, 1, 1),
+    info("hrandfield", -2, READ).keys(1, 1, 1),
+    info("lpush", -3, WRITE).keys(1, 1, 1),
+    info("rpush", -3, WRITE).keys(1, 1, 1),
+    info("lpushx", -3, WRITE_FAST).keys(1, 1, 1),
+    info("rpushx", -3, WRITE_FAST).keys(1, 1, 1),
+    info("lpop", -2, DEL_FAST).keys(1, 1, 1),
+    info("rpop", -2, DEL_FAST).keys(1, 1, 1),
+    info("lrange", 4, READ).keys(1, 1, 1),
+    info("llen", 2, READ_FAST).keys(1, 1, 1),
+    info("linsert", 5, WRITE).keys(1, 1, 1),
+    info("lmove", 5, WRITE).keys(1, 2, 1),
+    info("rpoplpush", 3, WRITE).keys(1, 2, 1),
+    info("blpop", -3, BLOCK).keys(1, -2, 1),
+    info("brpop", -3, BLOCK).keys(1, -2, 1),
+    info("blmove", 6, BLOCK).keys(1, 2, 1),
+    info("brpoplpush", 4, BLOCK).keys(1, 2, 1),
+    info("sadd", -3, WRITE).keys(1, 1, 1),
+    info("srem", -3, DEL_FAST).keys(1, 1, 1),
+    info("smembers", 2, READ).keys(1, 1, 1),
+    info("sismember", 3, READ_FAST).keys(1, 1, 1),
+    info("scard", 2, READ_FAST).keys(1, 1, 1),
+    info("sinter", -2, READ).keys(1, -1, 1),
+    info("sunion", -2, READ).keys(1, -1, 1),
+    info("sdiff", -2, READ).keys(1, -1, 1),
+    info("sinterstore", -3, WRITE).keys(1, -1, 1),
+    info("sunionstore", -3, WRITE).keys(1, -1, 1),
+    info("sdiffstore", -3, WRITE).keys(1, -1, 1),
+    info("zadd", -4, WRITE).keys(1, 1, 1),
+    info("zrem", -3, DEL_FAST).keys(1, 1, 1),
+    info("zscore", 3, READ_FAST).keys(1, 1, 1),
+    info("zcard", 2, READ_FAST).keys(1, 1, 1),
+    info("zincrby", 4, WRITE_FAST).keys(1, 1, 1),
+    info("zrank", -3, READ_FAST).keys(1, 1, 1),
+    info("zrevrank", -3, READ_FAST).keys(1, 1, 1),
+    info("zrange", -4, READ).keys(1, 1, 1),
+    info("zrevrange", -4, READ).keys(1, 1, 1),
+    info("zrangebyscore", -4, READ).keys(1, 1, 1),
+    info("zrevrangebyscore", -4, READ).keys(1, 1, 1),
+    info("zpopmin", -2, DEL_FAST).keys(1, 1, 1),
+    info("zpopmax", -2, DEL_FAST).keys(1, 1, 1),
+    info("geoadd", -5, WRITE).keys(1, 1, 1),
+    info("geodist", -4, READ).keys(1, 1, 1),
+    info("geopos", -2, READ).keys(1, 1, 1),
+    info("geosearch", -7, READ).keys(1, 1, 1),
+    info("xadd", -5, WRITE).keys(1, 1, 1),
+    info("xlen", 2, READ_FAST).keys(1, 1, 1),
+    info("xrange", -4, READ).keys(1, 1, 1),
+    info("xrevrange", -4, READ).keys(1, 1, 1),
+    info("xread", -4, READ),
+    info("xgroup", -2, WRITE).keys(2, 2, 1),
+    info("xreadgroup", -7, WRITE),
+    info("xack", -4, DEL_FAST).keys(1, 1, 1),
+    info("xpending", -3, READ).keys(1, 1, 1),
+    info("xclaim", -6, DEL_FAST).keys(1, 1, 1),
+    info("subscribe", -2, PUBSUB),
+    info("unsubscribe", -1, PUBSUB),
+    info("psubscribe", -2, PUBSUB),
+    info("punsubscribe", -1, PUBSUB),
+    info("publish", 3, PUBSUB),
+    info("ssubscribe", -2, PUBSUB),
+    info("sunsubscribe", -1, PUBSUB),
+    info("spublish", 3, PUBSUB),
+    info("config", -2, ADMIN),
+    info("cluster", -2, FAST),
+    info("multi", 1, TX),
+    info("exec", 1, TX),
+    info("discard", 1, TX),
+    info("watch", -2, TX).keys(1, -1, 1),
+    info("unwatch", 1, TX),
+    info("eval", -3, SCRIPT),
+    info("evalsha", -3, SCRIPT),
+    info("script", -2, SCRIPT),
+    info("keys", 2, READ),
+    info("scan", -2, READ),
+    info("hscan", -3, READ).keys(1, 1, 1),
+    info("sscan", -3, READ).keys(1, 1, 1),
+    info("zscan", -3, READ).keys(1, 1, 1),
+    info("rename", 3, DEL).keys(1, 2, 1),
+    info("renamenx", 3, DEL).keys(1, 2, 1),
+    info("randomkey", 1, READ),
+    info("dbsize", 1, READ_FAST),
+    info("copy", -3, WRITE).keys(1, 2, 1),
+    info("flushdb", -1, DEL),
+    info("flushall", -1, DEL),
+    info("select", 2, FAST),
+    info("hello", -1, NO_AUTH),
+    info("auth", -2, NO_AUTH),
+    info("quit", -1, NO_AUTH),
+    info("reset", 1, NO_AUTH),
+    info("acl", -2, ADMIN),
+    info("client", -2, ADMIN),
+    info("swapdb", 3, DEL),
+    info("move", 3, DEL).keys(1, 1, 1),
+    info("sort", -2, WRITE).keys(1, 1, 1),
+    info("dump", 2, READ).keys(1, 1, 1),
+    info("restore", -4, WRITE).keys(1, 1, 1),
+    info("info", -1, STATUS),
+    info("command", -1, STATUS),
+    info("monitor", 1, ADMIN),
+    info("slowlog", -2, ADMIN),
+    info("latency", -2, ADMIN),
+    info("memory", -2, READ).keys(2, 2, 1),
+    info("object", 3, READ).keys(2, 2, 1),
+    info("debug", -2, ADMIN),
+    info("save", 1, ADMIN),
+    info("bgsave", 1, ADMIN),
+    info("lastsave", 1, STATUS),
+    info("bgrewriteaof", 1, ADMIN),
+    info("shutdown", -1, ADMIN),
+    info("replconf", -1, ADMIN),
+    info("psync", -3, ADMIN),
+    info("replicaof", 3, ADMIN),
+    info("slaveof", 3, ADMIN),
+    info("wait", 3, SCRIPT),
+    info("asking", 1, FAST),
+    info("migrate", -6, DEL).keys(3, 3, 1),
+    info("ping", -1, FAST),
+    info("echo", 2, FAST),
+];
@@ -0,0 +1,142 @@
+use crate::lib::cmd::Command;
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::propagate;
+use bytes::Bytes;
+
+///连接上的事务状态
+///
+/// MULTI之后的命令只在解析后排队，EXEC时在独占键空间的情况下依次执行，
+/// 其他连接看不到执行到一半的中间状态。排队时出错的事务在EXEC时整体放弃，
+/// WATCH的键在EXEC之前被修改过的事务同样不会执行
+#[derive(Debug, Default)]
+pub(crate) struct Transaction {
+    //排队的命令与命令的参数，None代表连接不在事务中
+    queued: Option<Vec<(Command, Vec<Bytes>)>>,
+    //排队期间是否有命令被拒绝
+    aborted: bool,
+    //被监视的键所在的逻辑数据库、键与WATCH时的版本
+    watched: Vec<(usize, String, Option<u64>)>,
+}
+
+impl Transaction {
+    ///连接是否处于MULTI之后
+    pub(crate) fn is_active(&self) -> bool {
+        self.queued.is_some()
+    }
+
+    ///MULTI，开始事务
+    pub(crate) fn begin(&mut self) -> Frame {
+        if self.is_active() {
+            return Frame::Error("ERR MULTI calls can not be nested".to_string());
+        }
+        self.queued = Some(vec![]);
+        self.aborted = false;
+        Frame::Simple("OK".to_string())
+    }
+
+    ///将命令加入队列，等到EXEC时再执行，参数用于记录到AOF
+    pub(crate) fn queue(&mut self, cmd: Command, args: Vec<Bytes>) -> Frame {
+        match &mut self.queued {
+            Some(queued) => {
+                queued.push((cmd, args));
+                Frame::Simple("QUEUED".to_string())
+            }
+            None => Frame::Error("ERR no transaction in progress".to_string()),
+        }
+    }
+
+    ///拒绝无法排队的命令，回复给客户端的错误原样返回
+    ///
+    /// 之后的EXEC会放弃整个事务
+    pub(crate) fn reject(&mut self, err: Frame) -> Frame {
+        self.aborted = true;
+        err
+    }
+
+    ///WATCH，记录键当前的版本
+    ///
+    /// 键不存在时同样会被记录，之后被创建也会导致事务不执行
+    pub(crate) fn watch(&mut self, db: &Db, keys: Vec<String>) -> Frame {
+        if self.is_active() {
+            return Frame::Error("ERR WATCH inside MULTI is not allowed".to_string());
+        }
+        for key in keys {
+            let version = db.version(&key);
+            self.watched.push((db.index(), key, version));
+        }
+        Frame::Simple("OK".to_string())
+    }
+
+    ///UNWATCH，不再监视任何键
+    pub(crate) fn unwatch(&mut self) -> Frame {
+        self.watched.clear();
+        Frame::Simple("OK".to_string())
+    }
+
+    ///DISCARD，放弃排队的命令
+    pub(crate) fn discard(&mut self) -> Frame {
+        match self.queued.take() {
+            Some(_) => {
+                self.watched.clear();
+                Frame::Simple("OK".to_string())
+            }
+            None => Frame::Error("ERR DISCARD without MULTI".to_string()),
+        }
+    }
+
+    ///EXEC，依次执行排队的命令并以数组回复每个命令的结果
+    ///
+    /// 阻塞命令不会等待，没有可用的数据时按超时处理。监视的键被修改过时回复Null。
+    /// 事务中的SELECT在执行到时才切换数据库，切换的结果在EXEC之后继续生效。
+    /// 修改了数据的命令在执行完之后作为一个整体记录到AOF
+    pub(crate) fn exec(&mut self, db: &mut Db) -> Frame {
+        let queued = match self.queued.take() {
+            Some(queued) => queued,
+            None => return Frame::Error("ERR EXEC without MULTI".to_string()),
+        };
+        //无论事务是否执行，EXEC之后都不再监视
+        let watched = std::mem::take(&mut self.watched);
+        if self.aborted {
+            return Frame::Error(
+                "EXECABORT Transaction discarded because of previous errors.".to_string(),
+            );
+        }
+        //版本的比较与命令的执行必须在同一次独占中完成，否则两者之间的修改会被漏掉
+        let mut current = db.clone();
+        let reply = db.ordered(|| {
+            db.atomically(|| {
+                let dirty = watched.iter().any(|(index, key, version)| {
+                    db.select(*index).and_then(|db| db.version(key)) != *version
+                });
+                if dirty {
+                    return Frame::Null;
+                }
+                //修改了数据的命令所在的逻辑数据库与改写之后的参数
+                let mut written = vec![];
+                let replies = queued
+                    .into_iter()
+                    .map(|(cmd, args)| {
+                        let changes = db.stats().changes.get();
+                        let index = current.index();
+                        let reply = match cmd {
+                            Command::Select(cmd) => cmd.apply(&mut current),
+                            cmd => cmd.apply_now(&current),
+                        };
+                        if db.is_propagating() && db.stats().changes.get() != changes {
+                            written.extend(
+                                propagate::rewrite(&current, args, &reply)
+                                    .map(|args| (index, args)),
+                            );
+                        }
+                        reply
+                    })
+                    .collect();
+                db.propagate(written);
+                Frame::Array(replies)
+            })
+        });
+        *db = current;
+        reply
+    }
+}
@@ -0,0 +1,88 @@
+use crate::lib::db::{now_millis, Db};
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+
+///获取键的剩余存活时间
+///
+/// TTL key | PTTL key
+///
+/// 键不存在回复-2，没有过期时间回复-1
+#[derive(Debug)]
+pub struct Ttl {
+    key: String,
+    //是否以毫秒为单位
+    millis: bool,
+}
+
+impl Ttl {
+    pub(crate) fn parse_frames(parse: &mut Parse, millis: bool) -> Result<Ttl, ParseError> {
+        let key = parse.next_string()?;
+        Ok(Ttl { key, millis })
+    }
+
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let now = now_millis();
+        let ttl = match db.read(&self.key, |entry| entry.expires_at) {
+            None => -2,
+            Some(None) => -1,
+            Some(Some(at)) => {
+                let remaining = at.saturating_sub(now);
+                if self.millis {
+                    remaining as i64
+                } else {
+                    //默认与redis一致，按四舍五入换算为秒
+                    let seconds = match db.config().ttl_rounding() {
+                        "floor" => remaining / 1000,
+                        "ceil" => remaining.div_ceil(1000),
+                        _ => (remaining + 500) / 1000,
+                    };
+                    seconds as i64
+                }
+            }
+        };
+        Frame::Integer(ttl)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::cmd::Command;
+    use crate::lib::config::Config;
+    use bytes::Bytes;
+
+    fn call(db: &Db, args: &[&str]) -> Frame {
+        let frame = Frame::Array(
+            args.iter()
+                .map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes())))
+                .collect(),
+        );
+        Command::from_frame(frame).unwrap().apply_now(db)
+    }
+
+    //写入剩余millis毫秒的键后按指定方式读取TTL
+    fn ttl_with(rounding: &str, millis: &str) -> Frame {
+        let db = Db::new(Config::default());
+        db.update_config(|config| config.set("ttl-rounding", rounding))
+            .unwrap();
+        call(&db, &["SET", "key", "value", "PX", millis]);
+        call(&db, &["TTL", "key"])
+    }
+
+    #[test]
+    fn rounding_modes() {
+        //剩余时间在读取前已略少于写入时的值，离整秒足够远，结果不受执行快慢影响
+        assert_eq!(ttl_with("floor", "1700"), Frame::Integer(1));
+        assert_eq!(ttl_with("ceil", "1300"), Frame::Integer(2));
+        assert_eq!(ttl_with("round", "1300"), Frame::Integer(1));
+        assert_eq!(ttl_with("round", "1700"), Frame::Integer(2));
+    }
+
+    #[test]
+    fn missing_and_persistent_keys() {
+        let db = Db::new(Config::default());
+        assert!(matches!(call(&db, &["TTL", "key"]), Frame::Integer(-2)));
+        call(&db, &["SET", "key", "value"]);
+        assert!(matches!(call(&db, &["PTTL", "key"]), Frame::Integer(-1)));
+    }
+}
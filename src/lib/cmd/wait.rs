@@ -0,0 +1,62 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use std::time::Duration;
+use tokio::time::Instant;
+
+///等待之前的写命令被从节点确认
+///
+/// WAIT numreplicas timeout
+///
+/// 等待至少numreplicas个从节点以REPLCONF ACK确认处理到了执行WAIT时的复制偏移量，
+/// 或者等待timeout毫秒后超时，timeout为0时一直等待。回复确认了的从节点数量
+#[derive(Debug)]
+pub struct Wait {
+    numreplicas: usize,
+    //None代表一直等待
+    timeout: Option<Duration>,
+}
+
+impl Wait {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Wait, ParseError> {
+        let numreplicas = parse.next_int()?.max(0) as usize;
+        let timeout = match parse.next_int()? {
+            timeout if timeout < 0 => return Err("timeout is negative".into()),
+            0 => None,
+            timeout => Some(Duration::from_millis(timeout as u64)),
+        };
+        Ok(Wait {
+            numreplicas,
+            timeout,
+        })
+    }
+
+    ///回复确认了的从节点数量，从节点上执行时回复错误
+    pub(crate) async fn apply(self, db: &Db) -> Frame {
+        if db.master_link().master().is_some() {
+            return replica_error();
+        }
+        let replication = db.replication();
+        let offset = replication.offset();
+        if replication.acknowledged(offset) < self.numreplicas {
+            replication.getack();
+        }
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let count = replication.wait(offset, self.numreplicas, deadline).await;
+        Frame::Integer(count as i64)
+    }
+
+    ///不等待地回复已经确认了的从节点数量
+    pub(crate) fn apply_now(self, db: &Db) -> Frame {
+        if db.master_link().master().is_some() {
+            return replica_error();
+        }
+        let replication = db.replication();
+        Frame::Integer(replication.acknowledged(replication.offset()) as i64)
+    }
+}
+
+//从节点上不能执行WAIT
+fn replica_error() -> Frame {
+    Frame::Error("ERR WAIT cannot be used with replica instances.".to_string())
+}
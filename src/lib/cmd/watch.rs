@@ -0,0 +1,31 @@
+use crate::lib::cmd::Transaction;
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+
+///监视键，键在EXEC之前被其他连接修改时事务不会执行
+///
+/// WATCH key [key ...]
+#[derive(Debug)]
+pub struct Watch {
+    keys: Vec<String>,
+}
+
+impl Watch {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<Watch, ParseError> {
+        let mut keys = vec![parse.next_string()?];
+        loop {
+            match parse.next_string() {
+                Ok(key) => keys.push(key),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(Watch { keys })
+    }
+
+    ///记录键当前的版本，回复OK
+    pub(crate) fn apply(self, db: &Db, transaction: &mut Transaction) -> Frame {
+        transaction.watch(db, self.keys)
+    }
+}
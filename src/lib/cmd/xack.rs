@@ -0,0 +1,55 @@
+use crate::lib::cmd::xadd::INVALID_ID;
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::value::stream::StreamId;
+use bytes::Bytes;
+
+///确认消费者组中的条目，将其移出待确认列表
+///
+/// XACK key group id [id ...]
+#[derive(Debug)]
+pub struct XAck {
+    key: String,
+    group: Bytes,
+    ids: Vec<StreamId>,
+}
+
+impl XAck {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<XAck, ParseError> {
+        let key = parse.next_string()?;
+        let group = parse.next_bytes()?;
+        let mut ids = vec![parse_id(&parse.next_string()?)?];
+        loop {
+            match parse.next_string() {
+                Ok(id) => ids.push(parse_id(&id)?),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(XAck { key, group, ids })
+    }
+
+    ///回复确认成功的条目数，键或组不存在时回复0
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let XAck { key, group, ids } = self;
+        db.mutate(key, |slot| {
+            let stream = match slot.as_mut().map(|value| value.as_stream_mut()) {
+                None => return (false, Frame::Integer(0)),
+                Some(Err(e)) => return (false, e.into()),
+                Some(Ok(stream)) => stream,
+            };
+            let group = match stream.group_mut(&group) {
+                Some(group) => group,
+                None => return (false, Frame::Integer(0)),
+            };
+            let acked = ids.into_iter().filter(|id| group.ack(*id)).count();
+            (acked > 0, Frame::Integer(acked as i64))
+        })
+    }
+}
+
+//解析完整的条目id
+fn parse_id(arg: &str) -> Result<StreamId, ParseError> {
+    StreamId::parse(arg, 0).ok_or_else(|| INVALID_ID.into())
+}
@@ -0,0 +1,144 @@
+use crate::lib::db::{now_millis, Db};
+use crate::lib::frame::Frame;
+use crate::lib::notify::{Class, Event};
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::value::stream::{Fields, Stream, StreamId};
+use crate::lib::value::Value;
+use bytes::Bytes;
+
+///id格式不合法时的错误
+pub(super) const INVALID_ID: &str = "Invalid stream ID specified as stream command argument";
+
+///向流中追加条目
+///
+/// XADD key [NOMKSTREAM] [MAXLEN [= | ~] threshold] * | id field value [field value ...]
+///
+/// id为“*”时自动生成，为“ms-*”时只自动生成序号
+#[derive(Debug)]
+pub struct XAdd {
+    key: String,
+    id: IdSpec,
+    fields: Fields,
+    //键不存在时不创建流
+    no_mkstream: bool,
+    //追加后最多保留的条目数
+    max_len: Option<usize>,
+}
+
+///新条目id的指定方式
+#[derive(Debug, Clone, Copy)]
+enum IdSpec {
+    ///完全自动生成
+    Auto,
+    ///指定时间戳，自动生成序号
+    AutoSeq(u64),
+    ///完全指定
+    Explicit(StreamId),
+}
+
+impl XAdd {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<XAdd, ParseError> {
+        let key = parse.next_string()?;
+        let mut no_mkstream = false;
+        let mut max_len = None;
+        let id = loop {
+            let arg = parse.next_string()?;
+            match arg.to_uppercase().as_str() {
+                "NOMKSTREAM" => no_mkstream = true,
+                "MAXLEN" => {
+                    let mut threshold = parse.next_string()?;
+                    //近似裁剪同样按精确的长度处理
+                    if threshold == "=" || threshold == "~" {
+                        threshold = parse.next_string()?;
+                    }
+                    let threshold: i64 = threshold
+                        .parse()
+                        .map_err(|_| "value is not an integer or out of range")?;
+                    if threshold < 0 {
+                        return Err("The MAXLEN argument must be >= 0.".into());
+                    }
+                    max_len = Some(threshold as usize);
+                }
+                _ => break parse_id_spec(&arg)?,
+            }
+        };
+        let mut fields = vec![(parse.next_bytes()?, parse.next_bytes()?)];
+        loop {
+            let field = match parse.next_bytes() {
+                Ok(field) => field,
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e),
+            };
+            //字段与值必须成对出现
+            let value = parse.next_bytes()?;
+            fields.push((field, value));
+        }
+        Ok(XAdd {
+            key,
+            id,
+            fields,
+            no_mkstream,
+            max_len,
+        })
+    }
+
+    ///回复新条目的id，带NOMKSTREAM且键不存在时回复Null
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let XAdd {
+            key,
+            id,
+            fields,
+            no_mkstream,
+            max_len,
+        } = self;
+        let now = now_millis();
+        db.mutate_notify(key, Event::new(Class::Stream, "xadd"), |slot| {
+            if no_mkstream && slot.is_none() {
+                return (false, Frame::Null);
+            }
+            //须在创建流之前检查，以免留下空的流
+            if matches!(id, IdSpec::Explicit(id) if id == StreamId::MIN) {
+                let err = "ERR The ID specified in XADD must be greater than 0-0";
+                return (false, Frame::Error(err.to_string()));
+            }
+            let stream = slot.get_or_insert_with(|| Value::Stream(Stream::default()));
+            let stream = match stream.as_stream_mut() {
+                Ok(stream) => stream,
+                Err(e) => return (false, e.into()),
+            };
+            let id = match id {
+                IdSpec::Explicit(id) => Some(id).filter(|id| *id > stream.last_id()),
+                IdSpec::AutoSeq(ms) => stream.next_id(Some(ms), now),
+                IdSpec::Auto => stream.next_id(None, now),
+            };
+            let id = match id {
+                Some(id) => id,
+                None => {
+                    let err = "ERR The ID specified in XADD is equal or smaller than the target stream top item";
+                    return (false, Frame::Error(err.to_string()));
+                }
+            };
+            stream.append(id, fields);
+            if let Some(max_len) = max_len {
+                stream.trim(max_len);
+            }
+            (true, Frame::Bulk(Bytes::from(id.to_string())))
+        })
+    }
+}
+
+//解析XADD中新条目的id
+fn parse_id_spec(arg: &str) -> Result<IdSpec, ParseError> {
+    if arg == "*" {
+        return Ok(IdSpec::Auto);
+    }
+    if let Some(ms) = arg.strip_suffix("-*") {
+        return ms
+            .parse()
+            .map(IdSpec::AutoSeq)
+            .map_err(|_| INVALID_ID.into());
+    }
+    StreamId::parse(arg, 0)
+        .map(IdSpec::Explicit)
+        .ok_or_else(|| INVALID_ID.into())
+}
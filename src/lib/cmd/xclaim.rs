@@ -0,0 +1,153 @@
+use crate::lib::cmd::xadd::INVALID_ID;
+use crate::lib::cmd::xgroup::no_group;
+use crate::lib::cmd::xrange::entry_frame;
+use crate::lib::db::{now_millis, Db};
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::value::stream::StreamId;
+use bytes::Bytes;
+
+///将空闲过久的待确认条目转交给另一个消费者
+///
+/// XCLAIM key group consumer min-idle-time id [id ...] [IDLE ms] [TIME unix-time-milliseconds]
+/// [RETRYCOUNT count] [FORCE] [JUSTID]
+///
+/// 用于接管已经失效的消费者手中的条目。已从流中删除的条目会直接移出待确认列表
+#[derive(Debug)]
+pub struct XClaim {
+    key: String,
+    group: Bytes,
+    consumer: Bytes,
+    //空闲时间不少于该值的条目才会被认领，毫秒
+    min_idle: u64,
+    ids: Vec<StreamId>,
+    //认领后条目的投递时间，None代表当前时间
+    delivered_at: Option<Delivered>,
+    //认领后条目的投递次数，None代表原有次数加一
+    retry_count: Option<u64>,
+    //不在待确认列表中的条目也一并认领
+    force: bool,
+    //只回复id，且不增加投递次数
+    just_id: bool,
+}
+
+///认领后条目投递时间的设置方式
+#[derive(Debug, Clone, Copy)]
+enum Delivered {
+    ///空闲时间，毫秒
+    Idle(u64),
+    ///unix时间戳，毫秒
+    At(u64),
+}
+
+impl XClaim {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<XClaim, ParseError> {
+        let key = parse.next_string()?;
+        let group = parse.next_bytes()?;
+        let consumer = parse.next_bytes()?;
+        let min_idle = parse.next_int()?.max(0) as u64;
+        let first = parse.next_string()?;
+        let first = StreamId::parse(&first, 0).ok_or(INVALID_ID)?;
+        let mut claim = XClaim {
+            key,
+            group,
+            consumer,
+            min_idle,
+            ids: vec![first],
+            delivered_at: None,
+            retry_count: None,
+            force: false,
+            just_id: false,
+        };
+        //id之后的第一个无法解析为id的参数开始是选项
+        let mut option = loop {
+            match parse.next_string() {
+                Ok(arg) => match StreamId::parse(&arg, 0) {
+                    Some(id) => claim.ids.push(id),
+                    None => break Some(arg),
+                },
+                Err(ParseError::EndOfStream) => break None,
+                Err(e) => return Err(e),
+            }
+        };
+        while let Some(arg) = option {
+            match arg.to_uppercase().as_str() {
+                "IDLE" => claim.delivered_at = Some(Delivered::Idle(parse_option(parse, "IDLE")?)),
+                "TIME" => claim.delivered_at = Some(Delivered::At(parse_option(parse, "TIME")?)),
+                "RETRYCOUNT" => claim.retry_count = Some(parse_option(parse, "RETRYCOUNT")?),
+                "FORCE" => claim.force = true,
+                "JUSTID" => claim.just_id = true,
+                _ => return Err(format!("Unrecognized XCLAIM option '{}'", arg).into()),
+            }
+            option = match parse.next_string() {
+                Ok(arg) => Some(arg),
+                Err(ParseError::EndOfStream) => None,
+                Err(e) => return Err(e),
+            };
+        }
+        Ok(claim)
+    }
+
+    ///回复被认领的条目，带JUSTID时只回复id
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let now = now_millis();
+        let delivered_at = match self.delivered_at {
+            None => now,
+            Some(Delivered::Idle(idle)) => now.saturating_sub(idle),
+            Some(Delivered::At(at)) => at,
+        };
+        let missing_group = no_group(&self.key, &self.group);
+        db.mutate(self.key.clone(), |slot| {
+            let stream = match slot.as_mut().map(|value| value.as_stream_mut()) {
+                None => return (false, missing_group),
+                Some(Err(e)) => return (false, e.into()),
+                Some(Ok(stream)) => stream,
+            };
+            if stream.group(&self.group).is_none() {
+                return (false, missing_group);
+            }
+            let mut claimed = vec![];
+            let mut changed = false;
+            for id in &self.ids {
+                let fields = stream.get(id).cloned();
+                //组在上面已经检查过
+                let group = match stream.group_mut(&self.group) {
+                    Some(group) => group,
+                    None => break,
+                };
+                let idle = group
+                    .pending()
+                    .get(id)
+                    .map(|pending| now.saturating_sub(pending.delivered_at));
+                let fields = match (idle, fields) {
+                    //条目已从流中删除，不再需要确认
+                    (Some(_), None) => {
+                        changed |= group.ack(*id);
+                        continue;
+                    }
+                    (Some(idle), Some(fields)) if idle >= self.min_idle => fields,
+                    (None, Some(fields)) if self.force => fields,
+                    _ => continue,
+                };
+                group.assign(*id, &self.consumer, now, !self.just_id);
+                group.set_delivery(*id, delivered_at, self.retry_count);
+                changed = true;
+                if self.just_id {
+                    claimed.push(Frame::Bulk(Bytes::from(id.to_string())));
+                } else {
+                    claimed.push(entry_frame((id, &fields)));
+                }
+            }
+            (changed, Frame::Array(claimed))
+        })
+    }
+}
+
+//读取选项的非负整数参数
+fn parse_option(parse: &mut Parse, name: &str) -> Result<u64, ParseError> {
+    let value = parse.next_int()?;
+    if value < 0 {
+        return Err(format!("Invalid {} option argument for XCLAIM", name).into());
+    }
+    Ok(value as u64)
+}
@@ -0,0 +1,165 @@
+use crate::lib::cmd::xadd::INVALID_ID;
+use crate::lib::db::{now_millis, Db};
+use crate::lib::frame::Frame;
+use crate::lib::notify::{Class, Event};
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::value::stream::{Stream, StreamId};
+use crate::lib::value::Value;
+use bytes::Bytes;
+
+///管理流的消费者组
+///
+/// XGROUP CREATE key group id | $ [MKSTREAM] | XGROUP SETID key group id | $ |
+/// XGROUP DESTROY key group | XGROUP CREATECONSUMER key group consumer |
+/// XGROUP DELCONSUMER key group consumer
+#[derive(Debug)]
+pub struct XGroup {
+    key: String,
+    group: Bytes,
+    op: Op,
+}
+
+///XGROUP的子命令
+#[derive(Debug)]
+enum Op {
+    ///创建组，id为None代表“$”，mkstream为true时键不存在就创建空的流
+    Create {
+        id: Option<StreamId>,
+        mkstream: bool,
+    },
+    ///修改组的最近投递id，None代表“$”
+    SetId(Option<StreamId>),
+    ///删除组
+    Destroy,
+    ///创建消费者
+    CreateConsumer(Bytes),
+    ///删除消费者
+    DelConsumer(Bytes),
+}
+
+impl Op {
+    //键空间事件的名称
+    fn event(&self) -> &'static str {
+        match self {
+            Op::Create { .. } => "xgroup-create",
+            Op::SetId(_) => "xgroup-setid",
+            Op::Destroy => "xgroup-destroy",
+            Op::CreateConsumer(_) => "xgroup-createconsumer",
+            Op::DelConsumer(_) => "xgroup-delconsumer",
+        }
+    }
+}
+
+impl XGroup {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<XGroup, ParseError> {
+        let sub = parse.next_string()?.to_uppercase();
+        let key = parse.next_string()?;
+        let group = parse.next_bytes()?;
+        let op = match sub.as_str() {
+            "CREATE" => {
+                let id = parse_group_id(&parse.next_string()?)?;
+                let mkstream = match parse.next_string() {
+                    Ok(option) if option.eq_ignore_ascii_case("mkstream") => true,
+                    Ok(_) => return Err("syntax error".into()),
+                    Err(ParseError::EndOfStream) => false,
+                    Err(e) => return Err(e),
+                };
+                Op::Create { id, mkstream }
+            }
+            "SETID" => Op::SetId(parse_group_id(&parse.next_string()?)?),
+            "DESTROY" => Op::Destroy,
+            "CREATECONSUMER" => Op::CreateConsumer(parse.next_bytes()?),
+            "DELCONSUMER" => Op::DelConsumer(parse.next_bytes()?),
+            _ => {
+                let err = format!("unknown subcommand '{}'. Try XGROUP HELP.", sub);
+                return Err(err.into());
+            }
+        };
+        Ok(XGroup { key, group, op })
+    }
+
+    ///CREATE与SETID回复OK，DESTROY与CREATECONSUMER回复是否生效，DELCONSUMER回复被删除的待确认条目数
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let XGroup { key, group, op } = self;
+        let now = now_millis();
+        let missing_group = no_group(&key, &group);
+        let event = Event::new(Class::Stream, op.event());
+        db.mutate_notify(key, event, |slot| {
+            if slot.is_none() {
+                match op {
+                    Op::Create { mkstream: true, .. } => {
+                        *slot = Some(Value::Stream(Stream::default()));
+                    }
+                    _ => {
+                        let err = "ERR The XGROUP subcommand requires the key to exist. \
+                            Note that for CREATE you may want to use the MKSTREAM option \
+                            to create an empty stream automatically.";
+                        return (false, Frame::Error(err.to_string()));
+                    }
+                }
+            }
+            let stream = match slot.as_mut().map(|value| value.as_stream_mut()) {
+                Some(Ok(stream)) => stream,
+                Some(Err(e)) => return (false, e.into()),
+                None => return (false, Frame::Null),
+            };
+            let ok = || Frame::Simple("OK".to_string());
+            match op {
+                Op::Create { id, .. } => {
+                    let id = id.unwrap_or_else(|| stream.last_id());
+                    if stream.create_group(group, id) {
+                        (true, ok())
+                    } else {
+                        let err = "BUSYGROUP Consumer Group name already exists";
+                        (false, Frame::Error(err.to_string()))
+                    }
+                }
+                Op::Destroy => {
+                    let destroyed = stream.destroy_group(&group);
+                    (destroyed, Frame::Integer(destroyed as i64))
+                }
+                op => {
+                    let last_id = stream.last_id();
+                    let group = match stream.group_mut(&group) {
+                        Some(group) => group,
+                        None => return (false, missing_group),
+                    };
+                    match op {
+                        Op::SetId(id) => {
+                            group.last_delivered = id.unwrap_or(last_id);
+                            (true, ok())
+                        }
+                        Op::CreateConsumer(consumer) => {
+                            let created = group.touch(&consumer, now);
+                            (created, Frame::Integer(created as i64))
+                        }
+                        Op::DelConsumer(consumer) => match group.remove_consumer(&consumer) {
+                            Some(removed) => (true, Frame::Integer(removed as i64)),
+                            None => (false, Frame::Integer(0)),
+                        },
+                        Op::Create { .. } | Op::Destroy => unreachable!(),
+                    }
+                }
+            }
+        })
+    }
+}
+
+///组不存在时回复的错误
+pub(super) fn no_group(key: &str, group: &[u8]) -> Frame {
+    Frame::Error(format!(
+        "NOGROUP No such key '{}' or consumer group '{}'",
+        key,
+        String::from_utf8_lossy(group)
+    ))
+}
+
+//解析组的起始id，“$”代表流当前最大的id，返回None
+fn parse_group_id(arg: &str) -> Result<Option<StreamId>, ParseError> {
+    if arg == "$" {
+        return Ok(None);
+    }
+    StreamId::parse(arg, 0)
+        .map(Some)
+        .ok_or_else(|| INVALID_ID.into())
+}
@@ -0,0 +1,27 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+
+///获取流中的条目数
+///
+/// XLEN key
+#[derive(Debug)]
+pub struct XLen {
+    key: String,
+}
+
+impl XLen {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<XLen, ParseError> {
+        let key = parse.next_string()?;
+        Ok(XLen { key })
+    }
+
+    ///键不存在时回复0
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        db.read(&self.key, |entry| match entry.value.as_stream() {
+            Ok(stream) => Frame::Integer(stream.len() as i64),
+            Err(e) => e.into(),
+        })
+        .unwrap_or(Frame::Integer(0))
+    }
+}
@@ -0,0 +1,151 @@
+use crate::lib::cmd::xgroup::no_group;
+use crate::lib::cmd::xrange::parse_bound;
+use crate::lib::db::{now_millis, Db};
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::value::stream::{Group, StreamId};
+use bytes::Bytes;
+
+///查看消费者组的待确认条目
+///
+/// XPENDING key group [[IDLE min-idle-time] start end count [consumer]]
+///
+/// 不带区间时回复概要：条目数、最小与最大的id、每个消费者持有的条目数
+#[derive(Debug)]
+pub struct XPending {
+    key: String,
+    group: Bytes,
+    //带区间时的详细查询
+    detail: Option<Detail>,
+}
+
+///按区间查询待确认条目的参数
+#[derive(Debug)]
+struct Detail {
+    //换算后的闭区间，区间为空时为None
+    range: Option<(StreamId, StreamId)>,
+    count: usize,
+    //最小的空闲时间，毫秒
+    min_idle: u64,
+    //只查询该消费者持有的条目
+    consumer: Option<Bytes>,
+}
+
+impl XPending {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<XPending, ParseError> {
+        let key = parse.next_string()?;
+        let group = parse.next_bytes()?;
+        let mut start = match parse.next_string() {
+            Ok(start) => start,
+            Err(ParseError::EndOfStream) => {
+                return Ok(XPending {
+                    key,
+                    group,
+                    detail: None,
+                })
+            }
+            Err(e) => return Err(e),
+        };
+        let mut min_idle = 0;
+        if start.eq_ignore_ascii_case("idle") {
+            min_idle = parse.next_int()?.max(0) as u64;
+            start = parse.next_string()?;
+        }
+        let start = parse_bound(&start, true)?;
+        let end = parse_bound(&parse.next_string()?, false)?;
+        let range = match (start, end) {
+            (Some(start), Some(end)) if start <= end => Some((start, end)),
+            _ => None,
+        };
+        let count = parse.next_int()?.max(0) as usize;
+        let consumer = match parse.next_bytes() {
+            Ok(consumer) => Some(consumer),
+            Err(ParseError::EndOfStream) => None,
+            Err(e) => return Err(e),
+        };
+        let detail = Detail {
+            range,
+            count,
+            min_idle,
+            consumer,
+        };
+        Ok(XPending {
+            key,
+            group,
+            detail: Some(detail),
+        })
+    }
+
+    ///键或组不存在时回复NOGROUP错误
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let XPending { key, group, detail } = self;
+        let reply = db.read(&key, |entry| {
+            let stream = match entry.value.as_stream() {
+                Ok(stream) => stream,
+                Err(e) => return Some(e.into()),
+            };
+            let group = stream.group(&group)?;
+            let reply = match &detail {
+                None => summary(group),
+                Some(detail) => details(group, detail),
+            };
+            Some(reply)
+        });
+        reply.flatten().unwrap_or_else(|| no_group(&key, &group))
+    }
+}
+
+//概要：条目数、最小id、最大id、每个消费者的名称与持有的条目数
+fn summary(group: &Group) -> Frame {
+    let pending = group.pending();
+    let (first, last) = match (pending.keys().next(), pending.keys().next_back()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => {
+            let empty = vec![Frame::Integer(0), Frame::Null, Frame::Null, Frame::Null];
+            return Frame::Array(empty);
+        }
+    };
+    let mut consumers: Vec<_> = group.consumers().filter(|(_, count)| *count > 0).collect();
+    consumers.sort();
+    let consumers = consumers
+        .into_iter()
+        .map(|(name, count)| {
+            let count = Frame::Bulk(Bytes::from(count.to_string()));
+            Frame::Array(vec![Frame::Bulk(name.clone()), count])
+        })
+        .collect();
+    Frame::Array(vec![
+        Frame::Integer(pending.len() as i64),
+        Frame::Bulk(Bytes::from(first.to_string())),
+        Frame::Bulk(Bytes::from(last.to_string())),
+        Frame::Array(consumers),
+    ])
+}
+
+//区间内每个条目的id、持有者、空闲时间与投递次数
+fn details(group: &Group, detail: &Detail) -> Frame {
+    let (start, end) = match detail.range {
+        Some(range) => range,
+        None => return Frame::array(),
+    };
+    let now = now_millis();
+    let entries = group
+        .pending()
+        .range(start..=end)
+        .filter(|(_, pending)| match &detail.consumer {
+            Some(consumer) => pending.consumer == *consumer,
+            None => true,
+        })
+        .filter(|(_, pending)| now.saturating_sub(pending.delivered_at) >= detail.min_idle)
+        .take(detail.count)
+        .map(|(id, pending)| {
+            Frame::Array(vec![
+                Frame::Bulk(Bytes::from(id.to_string())),
+                Frame::Bulk(pending.consumer.clone()),
+                Frame::Integer(now.saturating_sub(pending.delivered_at) as i64),
+                Frame::Integer(pending.delivery_count as i64),
+            ])
+        })
+        .collect();
+    Frame::Array(entries)
+}
@@ -0,0 +1,111 @@
+use crate::lib::cmd::xadd::INVALID_ID;
+use crate::lib::db::Db;
+use crate::lib::frame::{ArrayBuilder, Frame};
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::value::stream::{Fields, StreamId};
+
+///获取流中id在指定区间内的条目
+///
+/// XRANGE key start end [COUNT count] | XREVRANGE key end start [COUNT count]
+///
+/// “-”与“+”代表最小与最大的id，以“(”开头的id不包含在区间内，只有时间戳的id覆盖该毫秒内的所有序号
+#[derive(Debug)]
+pub struct XRange {
+    key: String,
+    //换算后的闭区间，区间为空时为None
+    range: Option<(StreamId, StreamId)>,
+    count: Option<usize>,
+    rev: bool,
+}
+
+impl XRange {
+    pub(crate) fn parse_frames(parse: &mut Parse, rev: bool) -> Result<XRange, ParseError> {
+        let key = parse.next_string()?;
+        let first = parse.next_string()?;
+        let second = parse.next_string()?;
+        //XREVRANGE的参数顺序是先终点后起点
+        let (start, end) = if rev {
+            (second, first)
+        } else {
+            (first, second)
+        };
+        let start = parse_bound(&start, true)?;
+        let end = parse_bound(&end, false)?;
+        let range = match (start, end) {
+            (Some(start), Some(end)) if start <= end => Some((start, end)),
+            _ => None,
+        };
+        let count = match parse.next_string() {
+            Ok(option) if option.eq_ignore_ascii_case("count") => {
+                Some(parse.next_int()?.max(0) as usize)
+            }
+            Ok(_) => return Err("syntax error".into()),
+            Err(ParseError::EndOfStream) => None,
+            Err(e) => return Err(e),
+        };
+        Ok(XRange {
+            key,
+            range,
+            count,
+            rev,
+        })
+    }
+
+    ///每个条目回复为id与字段数组组成的二元数组，键不存在时回复空数组
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let XRange {
+            key,
+            range,
+            count,
+            rev,
+        } = self;
+        let (start, end) = match range {
+            Some(range) => range,
+            None => return Frame::array(),
+        };
+        let count = count.unwrap_or(usize::MAX);
+        db.read(&key, |entry| match entry.value.as_stream() {
+            Ok(stream) => {
+                let entries = stream.range(start, end);
+                let entries: Vec<_> = if rev {
+                    entries.rev().take(count).map(entry_frame).collect()
+                } else {
+                    entries.take(count).map(entry_frame).collect()
+                };
+                Frame::Array(entries)
+            }
+            Err(e) => e.into(),
+        })
+        .unwrap_or_else(Frame::array)
+    }
+}
+
+///将条目转化为id与字段数组组成的二元数组
+pub(super) fn entry_frame((id, fields): (&StreamId, &Fields)) -> Frame {
+    let mut values = ArrayBuilder::new();
+    for (field, value) in fields {
+        values.push(field.clone()).push(value.clone());
+    }
+    Frame::Array(vec![id.to_string().into(), values.build()])
+}
+
+///解析区间的一端并换算为闭区间的端点，开区间换算后越界时返回None
+pub(super) fn parse_bound(arg: &str, start: bool) -> Result<Option<StreamId>, ParseError> {
+    match arg {
+        "-" => return Ok(Some(StreamId::MIN)),
+        "+" => return Ok(Some(StreamId::MAX)),
+        _ => {}
+    }
+    let (exclusive, text) = match arg.strip_prefix('(') {
+        Some(text) => (true, text),
+        None => (false, arg),
+    };
+    //只有时间戳时，起点取该毫秒内最小的序号，终点取最大的序号
+    let default_seq = if start { 0 } else { u64::MAX };
+    let id = StreamId::parse(text, default_seq).ok_or(INVALID_ID)?;
+    Ok(match (exclusive, start) {
+        (false, _) => Some(id),
+        (true, true) => id.next(),
+        (true, false) => id.prev(),
+    })
+}
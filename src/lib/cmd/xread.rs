@@ -0,0 +1,185 @@
+use crate::lib::cmd::xadd::INVALID_ID;
+use crate::lib::cmd::xrange::entry_frame;
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::value::stream::StreamId;
+use crate::lib::value::WrongType;
+use bytes::Bytes;
+use std::time::Duration;
+use tokio::time::Instant;
+
+///读取多个流中id大于指定id的条目
+///
+/// XREAD [COUNT count] [BLOCK milliseconds] STREAMS key [key ...] id [id ...]
+///
+/// id为“$”时代表命令执行时流中最大的id，即只读取之后新追加的条目。
+/// 带BLOCK时所有流都没有新条目就等待其他连接追加，BLOCK 0代表一直等待
+#[derive(Debug)]
+pub struct XRead {
+    keys: Vec<String>,
+    //与keys一一对应，None代表“$”
+    ids: Vec<Option<StreamId>>,
+    count: Option<usize>,
+    //None代表不阻塞，Some(None)代表一直等待
+    block: Option<Option<Duration>>,
+}
+
+impl XRead {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<XRead, ParseError> {
+        let mut count = None;
+        let mut block = None;
+        loop {
+            match parse.next_string()?.to_uppercase().as_str() {
+                "COUNT" => count = Some(parse.next_int()?.max(0) as usize),
+                "BLOCK" => block = Some(parse_block(parse)?),
+                "STREAMS" => break,
+                _ => return Err("syntax error".into()),
+            }
+        }
+        let (keys, ids) = parse_streams(parse, "xread")?;
+        let ids = ids
+            .iter()
+            .map(|id| match id.as_str() {
+                "$" => Ok(None),
+                id => StreamId::parse(id, 0).map(Some).ok_or(INVALID_ID),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(XRead {
+            keys,
+            ids,
+            count,
+            block,
+        })
+    }
+
+    ///回复每个有新条目的流的键与条目，都没有新条目或等待超时时回复Null
+    pub(crate) async fn apply(self, db: &Db) -> Frame {
+        //必须在解析“$”之前登记，否则两者之间追加的条目会被错过
+        let waiter = self.block.map(|_| db.waiter(&self.keys));
+        let ids: Vec<StreamId> = match self.resolve_ids(db) {
+            Ok(ids) => ids,
+            Err(e) => return e.into(),
+        };
+        let deadline = match self.block {
+            Some(Some(timeout)) => Some(Instant::now() + timeout),
+            _ => None,
+        };
+        loop {
+            match self.read(db, &ids) {
+                Ok(Some(frame)) => return frame,
+                Ok(None) => {}
+                Err(e) => return e.into(),
+            }
+            let waiter = match &waiter {
+                Some(waiter) => waiter,
+                None => return Frame::Null,
+            };
+            match deadline {
+                None => waiter.wait().await,
+                Some(deadline) => {
+                    if tokio::time::timeout_at(deadline, waiter.wait())
+                        .await
+                        .is_err()
+                    {
+                        return Frame::Null;
+                    }
+                }
+            }
+        }
+    }
+
+    ///不等待地读取一次，都没有新条目时按超时处理回复Null
+    pub(crate) fn apply_now(self, db: &Db) -> Frame {
+        let ids = match self.resolve_ids(db) {
+            Ok(ids) => ids,
+            Err(e) => return e.into(),
+        };
+        match self.read(db, &ids) {
+            Ok(frame) => frame.unwrap_or(Frame::Null),
+            Err(e) => e.into(),
+        }
+    }
+
+    //将“$”换算为流当前最大的id，键不存在时为0-0
+    fn resolve_ids(&self, db: &Db) -> Result<Vec<StreamId>, WrongType> {
+        self.keys
+            .iter()
+            .zip(&self.ids)
+            .map(|(key, id)| match id {
+                Some(id) => Ok(*id),
+                None => {
+                    let last = db.read(key, |entry| entry.value.as_stream().map(|s| s.last_id()));
+                    Ok(last.transpose()?.unwrap_or(StreamId::MIN))
+                }
+            })
+            .collect()
+    }
+
+    //读取每个流中大于对应id的条目，全部没有时返回None
+    fn read(&self, db: &Db, ids: &[StreamId]) -> Result<Option<Frame>, WrongType> {
+        let count = self.count.unwrap_or(usize::MAX);
+        let mut streams = vec![];
+        for (key, id) in self.keys.iter().zip(ids) {
+            let start = match id.next() {
+                Some(start) => start,
+                None => continue,
+            };
+            let entries = db.read(key, |entry| {
+                let stream = entry.value.as_stream()?;
+                let entries: Vec<_> = stream
+                    .range(start, StreamId::MAX)
+                    .take(count)
+                    .map(entry_frame)
+                    .collect();
+                Ok(entries)
+            });
+            match entries.transpose()? {
+                Some(entries) if !entries.is_empty() => {
+                    let key = Frame::Bulk(Bytes::from(key.clone()));
+                    streams.push(Frame::Array(vec![key, Frame::Array(entries)]));
+                }
+                _ => {}
+            }
+        }
+        if streams.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Frame::Array(streams)))
+    }
+}
+
+///读取BLOCK的毫秒数，0代表一直等待，此时返回None
+pub(super) fn parse_block(parse: &mut Parse) -> Result<Option<Duration>, ParseError> {
+    let ms = parse.next_int()?;
+    if ms < 0 {
+        return Err("timeout is negative".into());
+    }
+    Ok(Some(Duration::from_millis(ms as u64)).filter(|d| !d.is_zero()))
+}
+
+///读取STREAMS之后的参数，前一半为键，后一半为对应的id
+///
+/// name为命令名称，用于错误信息
+pub(super) fn parse_streams(
+    parse: &mut Parse,
+    name: &str,
+) -> Result<(Vec<String>, Vec<String>), ParseError> {
+    let mut keys = vec![];
+    loop {
+        match parse.next_string() {
+            Ok(arg) => keys.push(arg),
+            Err(ParseError::EndOfStream) => break,
+            Err(e) => return Err(e),
+        }
+    }
+    if keys.is_empty() || keys.len() % 2 != 0 {
+        let err = format!(
+            "Unbalanced '{}' list of streams: for each stream key an ID or '$' must be specified.",
+            name
+        );
+        return Err(err.into());
+    }
+    let ids = keys.split_off(keys.len() / 2);
+    Ok((keys, ids))
+}
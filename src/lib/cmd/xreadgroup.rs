@@ -0,0 +1,234 @@
+use crate::lib::cmd::xadd::INVALID_ID;
+use crate::lib::cmd::xgroup::no_group;
+use crate::lib::cmd::xrange::entry_frame;
+use crate::lib::cmd::xread::{parse_block, parse_streams};
+use crate::lib::db::{now_millis, Db};
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::propagate;
+use crate::lib::value::stream::StreamId;
+use crate::lib::value::WrongType;
+use bytes::Bytes;
+use std::time::Duration;
+use tokio::time::Instant;
+
+///以消费者组中某个消费者的身份读取流
+///
+/// XREADGROUP GROUP group consumer [COUNT count] [BLOCK milliseconds] [NOACK]
+/// STREAMS key [key ...] id [id ...]
+///
+/// id为“>”时读取组内从未投递过的条目并记入该消费者的待确认列表，
+/// 其他id则重新读取该消费者持有的、id大于指定id的待确认条目。
+/// 只有全部id都是“>”时BLOCK才会生效
+#[derive(Debug)]
+pub struct XReadGroup {
+    group: Bytes,
+    consumer: Bytes,
+    keys: Vec<String>,
+    //与keys一一对应，None代表“>”
+    ids: Vec<Option<StreamId>>,
+    count: Option<usize>,
+    //None代表不阻塞，Some(None)代表一直等待
+    block: Option<Option<Duration>>,
+    //投递的条目不进入待确认列表
+    noack: bool,
+}
+
+impl XReadGroup {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<XReadGroup, ParseError> {
+        if !parse.next_keyword("GROUP") {
+            return Err("syntax error".into());
+        }
+        let group = parse.next_bytes()?;
+        let consumer = parse.next_bytes()?;
+        let mut count = None;
+        let mut block = None;
+        let mut noack = false;
+        loop {
+            match parse.next_string()?.to_uppercase().as_str() {
+                "COUNT" => count = Some(parse.next_int()?.max(0) as usize),
+                "BLOCK" => block = Some(parse_block(parse)?),
+                "NOACK" => noack = true,
+                "STREAMS" => break,
+                _ => return Err("syntax error".into()),
+            }
+        }
+        let (keys, ids) = parse_streams(parse, "xreadgroup")?;
+        let ids = ids
+            .iter()
+            .map(|id| match id.as_str() {
+                ">" => Ok(None),
+                id => StreamId::parse(id, 0).map(Some).ok_or(INVALID_ID),
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(XReadGroup {
+            group,
+            consumer,
+            keys,
+            ids,
+            count,
+            block,
+            noack,
+        })
+    }
+
+    ///回复每个流的键与读到的条目，只读取新条目且都没有新条目或等待超时时回复Null
+    ///
+    /// 已被删除的待确认条目以Null代替字段
+    pub(crate) async fn apply(self, db: &Db) -> Frame {
+        let blocking = self.block.is_some() && self.ids.iter().all(Option::is_none);
+        //必须在第一次读取之前登记，否则两者之间追加的条目会被错过
+        let waiter = if blocking {
+            Some(db.waiter(&self.keys))
+        } else {
+            None
+        };
+        let deadline = match self.block {
+            Some(Some(timeout)) => Some(Instant::now() + timeout),
+            _ => None,
+        };
+        loop {
+            //阻塞期间不持有顺序锁，每次尝试单独在锁内进行并记录
+            let read = propagate::record(
+                db,
+                || self.read(db),
+                |read| read.as_ref().map(|_| self.args()),
+            );
+            if let Some(frame) = read {
+                return frame;
+            }
+            let waiter = match &waiter {
+                Some(waiter) => waiter,
+                None => return Frame::Null,
+            };
+            match deadline {
+                None => waiter.wait().await,
+                Some(deadline) => {
+                    if tokio::time::timeout_at(deadline, waiter.wait())
+                        .await
+                        .is_err()
+                    {
+                        return Frame::Null;
+                    }
+                }
+            }
+        }
+    }
+
+    ///不等待地读取一次，没有任何可回复的条目时按超时处理回复Null
+    pub(crate) fn apply_now(self, db: &Db) -> Frame {
+        self.read(db).unwrap_or(Frame::Null)
+    }
+
+    //记录到AOF时使用的参数，重放时不阻塞
+    fn args(&self) -> Vec<Bytes> {
+        let mut args = vec![
+            Bytes::from_static(b"XREADGROUP"),
+            Bytes::from_static(b"GROUP"),
+            self.group.clone(),
+            self.consumer.clone(),
+        ];
+        if let Some(count) = self.count {
+            args.push(Bytes::from_static(b"COUNT"));
+            args.push(Bytes::from(count.to_string()));
+        }
+        if self.noack {
+            args.push(Bytes::from_static(b"NOACK"));
+        }
+        args.push(Bytes::from_static(b"STREAMS"));
+        args.extend(self.keys.iter().map(|key| Bytes::from(key.clone())));
+        args.extend(self.ids.iter().map(|id| match id {
+            Some(id) => Bytes::from(id.to_string()),
+            None => Bytes::from_static(b">"),
+        }));
+        args
+    }
+
+    //读取全部流，没有任何可回复的条目时返回None
+    //
+    // 先确认所有组都存在再开始投递，以免出错时部分条目已被记入待确认列表
+    fn read(&self, db: &Db) -> Option<Frame> {
+        db.atomically(|| {
+            for key in &self.keys {
+                let found = db.read(key, |entry| {
+                    let stream = entry.value.as_stream()?;
+                    Ok::<_, WrongType>(stream.group(&self.group).is_some())
+                });
+                match found {
+                    Some(Ok(true)) => {}
+                    Some(Err(e)) => return Some(e.into()),
+                    _ => return Some(no_group(key, &self.group)),
+                }
+            }
+            let mut streams = vec![];
+            for (key, id) in self.keys.iter().zip(&self.ids) {
+                let entries = match id {
+                    None => self.deliver(db, key),
+                    Some(id) => Some(self.history(db, key, *id)),
+                };
+                if let Some(entries) = entries {
+                    let key = Frame::Bulk(Bytes::from(key.clone()));
+                    streams.push(Frame::Array(vec![key, Frame::Array(entries)]));
+                }
+            }
+            if streams.is_empty() {
+                return None;
+            }
+            Some(Frame::Array(streams))
+        })
+    }
+
+    //投递新条目，没有新条目时返回None
+    fn deliver(&self, db: &Db, key: &str) -> Option<Vec<Frame>> {
+        let count = self.count.unwrap_or(usize::MAX);
+        let now = now_millis();
+        db.mutate(key.to_string(), |slot| {
+            let stream = match slot.as_mut().map(|value| value.as_stream_mut()) {
+                Some(Ok(stream)) => stream,
+                _ => return (false, None),
+            };
+            let entries = stream
+                .deliver(&self.group, &self.consumer, count, self.noack, now)
+                .unwrap_or_default();
+            if entries.is_empty() {
+                return (false, None);
+            }
+            let entries = entries
+                .iter()
+                .map(|(id, fields)| entry_frame((id, fields)))
+                .collect();
+            (true, Some(entries))
+        })
+    }
+
+    //重新读取消费者持有的待确认条目
+    fn history(&self, db: &Db, key: &str, after: StreamId) -> Vec<Frame> {
+        let count = self.count.unwrap_or(usize::MAX);
+        let now = now_millis();
+        db.mutate(key.to_string(), |slot| {
+            let stream = match slot.as_mut().map(|value| value.as_stream_mut()) {
+                Some(Ok(stream)) => stream,
+                _ => return (false, vec![]),
+            };
+            let group = match stream.group_mut(&self.group) {
+                Some(group) => group,
+                None => return (false, vec![]),
+            };
+            let created = group.touch(&self.consumer, now);
+            let ids: Vec<_> = group
+                .consumer_pending(&self.consumer, after)
+                .take(count)
+                .collect();
+            let entries = ids
+                .iter()
+                .map(|id| match stream.get(id) {
+                    Some(fields) => entry_frame((id, fields)),
+                    None => {
+                        Frame::Array(vec![Frame::Bulk(Bytes::from(id.to_string())), Frame::Null])
+                    }
+                })
+                .collect();
+            (created, entries)
+        })
+    }
+}
@@ -0,0 +1,136 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::notify::{Class, Event};
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::value::zset::{format_score, parse_score, ZSet};
+use crate::lib::value::Value;
+use bytes::Bytes;
+
+///向有序集合中添加成员或更新成员的分数
+///
+/// ZADD key [NX | XX] [GT | LT] [CH] [INCR] score member [score member ...]
+#[derive(Debug)]
+pub struct ZAdd {
+    key: String,
+    pairs: Vec<(f64, Bytes)>,
+    //只添加新成员
+    nx: bool,
+    //只更新已有成员
+    xx: bool,
+    //只在新分数更大时更新
+    gt: bool,
+    //只在新分数更小时更新
+    lt: bool,
+    //回复中同时计入分数被修改的成员
+    ch: bool,
+    //将分数作为增量，行为与ZINCRBY一致
+    incr: bool,
+}
+
+impl ZAdd {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<ZAdd, ParseError> {
+        let key = parse.next_string()?;
+        let mut zadd = ZAdd {
+            key,
+            pairs: vec![],
+            nx: false,
+            xx: false,
+            gt: false,
+            lt: false,
+            ch: false,
+            incr: false,
+        };
+        //选项都在第一个分数之前
+        let mut arg = parse.next_string()?;
+        loop {
+            match arg.to_uppercase().as_str() {
+                "NX" => zadd.nx = true,
+                "XX" => zadd.xx = true,
+                "GT" => zadd.gt = true,
+                "LT" => zadd.lt = true,
+                "CH" => zadd.ch = true,
+                "INCR" => zadd.incr = true,
+                _ => break,
+            }
+            arg = parse.next_string()?;
+        }
+        loop {
+            let score = parse_score(&arg).ok_or("value is not a valid float")?;
+            let member = parse.next_bytes()?;
+            zadd.pairs.push((score, member));
+            arg = match parse.next_string() {
+                Ok(arg) => arg,
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e),
+            };
+        }
+        if zadd.nx && zadd.xx {
+            return Err("XX and NX options at the same time are not compatible".into());
+        }
+        if (zadd.gt && zadd.lt) || (zadd.nx && (zadd.gt || zadd.lt)) {
+            return Err("GT, LT, and/or NX options at the same time are not compatible".into());
+        }
+        if zadd.incr && zadd.pairs.len() > 1 {
+            return Err("INCR option supports a single increment-element pair".into());
+        }
+        Ok(zadd)
+    }
+
+    ///回复新增的成员数，带CH时同时计入被修改的成员；带INCR时回复新的分数，未更新时回复Null
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let ZAdd {
+            key,
+            pairs,
+            nx,
+            xx,
+            gt,
+            lt,
+            ch,
+            incr,
+        } = self;
+        db.mutate_notify(key, Event::new(Class::ZSet, "zadd"), |slot| {
+            let zset = slot.get_or_insert_with(|| Value::ZSet(ZSet::default()));
+            let zset = match zset.as_zset_mut() {
+                Ok(zset) => zset,
+                Err(e) => return (false, e.into()),
+            };
+            let (mut added, mut updated) = (0, 0);
+            let mut last = None;
+            for (score, member) in pairs {
+                let old = zset.score(&member);
+                if (nx && old.is_some()) || (xx && old.is_none()) {
+                    continue;
+                }
+                let score = match old {
+                    Some(old) if incr => old + score,
+                    _ => score,
+                };
+                if score.is_nan() {
+                    let err = "ERR resulting score is not a number (NaN)";
+                    return (added + updated > 0, Frame::Error(err.to_string()));
+                }
+                if let Some(old) = old {
+                    if (gt && score <= old) || (lt && score >= old) {
+                        continue;
+                    }
+                    if score != old {
+                        updated += 1;
+                    }
+                } else {
+                    added += 1;
+                }
+                zset.insert(member, score);
+                last = Some(score);
+            }
+            let changed = added + updated > 0;
+            let reply = if incr {
+                last.map_or(Frame::Null, |score| Frame::Bulk(format_score(score)))
+            } else if ch {
+                Frame::Integer(added + updated)
+            } else {
+                Frame::Integer(added)
+            };
+            (changed, reply)
+        })
+    }
+}
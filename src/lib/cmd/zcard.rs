@@ -0,0 +1,27 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+
+///获取有序集合的成员数
+///
+/// ZCARD key
+#[derive(Debug)]
+pub struct ZCard {
+    key: String,
+}
+
+impl ZCard {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<ZCard, ParseError> {
+        let key = parse.next_string()?;
+        Ok(ZCard { key })
+    }
+
+    ///键不存在时回复0
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        db.read(&self.key, |entry| match entry.value.as_zset() {
+            Ok(zset) => Frame::Integer(zset.len() as i64),
+            Err(e) => e.into(),
+        })
+        .unwrap_or(Frame::Integer(0))
+    }
+}
@@ -0,0 +1,47 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::notify::{Class, Event};
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::value::zset::{format_score, parse_score, ZSet};
+use crate::lib::value::Value;
+use bytes::Bytes;
+
+///对有序集合中成员的分数做加法
+///
+/// ZINCRBY key increment member
+///
+/// 键或成员不存在时视为0
+#[derive(Debug)]
+pub struct ZIncrBy {
+    key: String,
+    delta: f64,
+    member: Bytes,
+}
+
+impl ZIncrBy {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<ZIncrBy, ParseError> {
+        let key = parse.next_string()?;
+        let delta = parse_score(&parse.next_string()?).ok_or("value is not a valid float")?;
+        let member = parse.next_bytes()?;
+        Ok(ZIncrBy { key, delta, member })
+    }
+
+    ///回复相加后的分数
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let ZIncrBy { key, delta, member } = self;
+        db.mutate_notify(key, Event::new(Class::ZSet, "zincr"), |slot| {
+            let zset = slot.get_or_insert_with(|| Value::ZSet(ZSet::default()));
+            let zset = match zset.as_zset_mut() {
+                Ok(zset) => zset,
+                Err(e) => return (false, e.into()),
+            };
+            let score = zset.score(&member).unwrap_or(0.0) + delta;
+            if score.is_nan() {
+                let err = "ERR resulting score is not a number (NaN)";
+                return (false, Frame::Error(err.to_string()));
+            }
+            zset.insert(member, score);
+            (true, Frame::Bulk(format_score(score)))
+        })
+    }
+}
@@ -0,0 +1,58 @@
+use crate::lib::db::Db;
+use crate::lib::frame::{ArrayBuilder, Frame};
+use crate::lib::notify::{Class, Event};
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::value::zset::format_score;
+
+///弹出有序集合中分数最小或最大的成员
+///
+/// ZPOPMIN key [count] | ZPOPMAX key [count]
+///
+/// 成员全部弹出后键也随之删除
+#[derive(Debug)]
+pub struct ZPop {
+    key: String,
+    count: usize,
+    //是否弹出分数最大的成员
+    max: bool,
+}
+
+impl ZPop {
+    pub(crate) fn parse_frames(parse: &mut Parse, max: bool) -> Result<ZPop, ParseError> {
+        let key = parse.next_string()?;
+        let count = match parse.next_int() {
+            Ok(count) if count < 0 => {
+                return Err("value is out of range, must be positive".into());
+            }
+            Ok(count) => count as usize,
+            Err(ParseError::EndOfStream) => 1,
+            Err(e) => return Err(e),
+        };
+        Ok(ZPop { key, count, max })
+    }
+
+    ///成员与分数交替排列在同一个数组中，键不存在时回复空数组
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let ZPop { key, count, max } = self;
+        let event = Event::new(Class::ZSet, if max { "zpopmax" } else { "zpopmin" });
+        db.mutate_notify(key, event, |slot| {
+            let zset = match slot.as_mut().map(|value| value.as_zset_mut()) {
+                None => return (false, Frame::array()),
+                Some(Err(e)) => return (false, e.into()),
+                Some(Ok(zset)) => zset,
+            };
+            let mut frame = ArrayBuilder::new();
+            let mut popped = 0;
+            while popped < count {
+                let next = if max { zset.pop_max() } else { zset.pop_min() };
+                let (member, score) = match next {
+                    Some(next) => next,
+                    None => break,
+                };
+                frame.push(member).push(format_score(score));
+                popped += 1;
+            }
+            (popped > 0, frame.build())
+        })
+    }
+}
@@ -0,0 +1,178 @@
+use crate::lib::cmd::lrange::index_range;
+use crate::lib::db::Db;
+use crate::lib::frame::{ArrayBuilder, Frame, ReplyLimit, REPLY_TOO_LARGE};
+use crate::lib::parse::{Parse, ParseError};
+use crate::lib::value::zset::{format_score, ScoreBound, ZSet};
+use bytes::Bytes;
+
+///获取有序集合中指定范围的成员
+///
+/// ZRANGE key start stop [BYSCORE] [REV] [LIMIT offset count] [WITHSCORES] |
+/// ZREVRANGE key start stop [WITHSCORES] |
+/// ZRANGEBYSCORE key min max [WITHSCORES] [LIMIT offset count] |
+/// ZREVRANGEBYSCORE key max min [WITHSCORES] [LIMIT offset count]
+///
+/// 分数区间以“(”开头时不包含端点，REV时按分数从大到小排列且区间的两端互换
+#[derive(Debug)]
+pub struct ZRange {
+    key: String,
+    range: Range,
+    rev: bool,
+    //跳过的成员数与最多回复的成员数，负数代表不限
+    limit: Option<(i64, i64)>,
+    with_scores: bool,
+}
+
+///范围的种类
+#[derive(Debug)]
+enum Range {
+    ///按排名，包含两端，负数从末尾开始计算
+    Index(i64, i64),
+    ///按分数，依次为下界与上界
+    Score(ScoreBound, ScoreBound),
+}
+
+impl ZRange {
+    ///name为命令名称，决定范围的种类与默认的排列方向
+    pub(crate) fn parse_frames(parse: &mut Parse, name: &str) -> Result<ZRange, ParseError> {
+        let key = parse.next_string()?;
+        let start = parse.next_string()?;
+        let stop = parse.next_string()?;
+        let mut by_score = name.ends_with("byscore");
+        let mut rev = name.starts_with("zrev");
+        let mut limit = None;
+        let mut with_scores = false;
+        loop {
+            let option = match parse.next_string() {
+                Ok(option) => option.to_uppercase(),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e),
+            };
+            match option.as_str() {
+                "WITHSCORES" => with_scores = true,
+                "LIMIT" if name != "zrevrange" => {
+                    limit = Some((parse.next_int()?, parse.next_int()?));
+                }
+                "BYSCORE" if name == "zrange" => by_score = true,
+                "REV" if name == "zrange" => rev = true,
+                _ => return Err("syntax error".into()),
+            }
+        }
+        if limit.is_some() && !by_score {
+            let err =
+                "syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX";
+            return Err(err.into());
+        }
+        let range = if by_score {
+            //REV时参数的顺序是先上界后下界
+            let (min, max) = if rev { (stop, start) } else { (start, stop) };
+            let min = ScoreBound::parse(&min).ok_or("min or max is not a float")?;
+            let max = ScoreBound::parse(&max).ok_or("min or max is not a float")?;
+            Range::Score(min, max)
+        } else {
+            let start = start
+                .parse()
+                .map_err(|_| "value is not an integer or out of range")?;
+            let stop = stop
+                .parse()
+                .map_err(|_| "value is not an integer or out of range")?;
+            Range::Index(start, stop)
+        };
+        Ok(ZRange {
+            key,
+            range,
+            rev,
+            limit,
+            with_scores,
+        })
+    }
+
+    ///带WITHSCORES时成员与分数交替排列，键不存在时回复空数组，回复超出max-reply-size时回复错误
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let mut limit = db.reply_limit();
+        db.read(&self.key, |entry| match entry.value.as_zset() {
+            Ok(zset) => self.collect(zset, &mut limit),
+            Err(e) => e.into(),
+        })
+        .unwrap_or_else(Frame::array)
+    }
+
+    //按范围取出成员，逐个计入回复的大小
+    fn collect(&self, zset: &ZSet, limit: &mut ReplyLimit) -> Frame {
+        let members: Box<dyn Iterator<Item = (Bytes, f64)> + '_> = match self.range {
+            Range::Index(start, stop) => match index_range(start, stop, zset.len()) {
+                None => Box::new(std::iter::empty()),
+                Some((start, stop)) if self.rev => {
+                    Box::new(zset.iter().rev().skip(start).take(stop - start + 1))
+                }
+                Some((start, stop)) => Box::new(zset.iter().skip(start).take(stop - start + 1)),
+            },
+            Range::Score(min, max) => {
+                let (offset, count) = match self.limit {
+                    Some((offset, _)) if offset < 0 => return Frame::array(),
+                    Some((offset, count)) if count >= 0 => (offset as usize, count as usize),
+                    Some((offset, _)) => (offset as usize, usize::MAX),
+                    None => (0, usize::MAX),
+                };
+                let range = zset.range_by_score(min, max);
+                if self.rev {
+                    Box::new(range.rev().skip(offset).take(count))
+                } else {
+                    Box::new(range.skip(offset).take(count))
+                }
+            }
+        };
+        let mut frame = ArrayBuilder::new();
+        for (member, score) in members {
+            if !limit.add(member.len()) {
+                return Frame::Error(REPLY_TOO_LARGE.to_string());
+            }
+            frame.push(member);
+            if self.with_scores {
+                let score = format_score(score);
+                if !limit.add(score.len()) {
+                    return Frame::Error(REPLY_TOO_LARGE.to_string());
+                }
+                frame.push(score);
+            }
+        }
+        frame.build()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::cmd::Command;
+    use crate::lib::config::Config;
+
+    fn call(db: &Db, args: &[&str]) -> Frame {
+        let frame = Frame::Array(
+            args.iter()
+                .map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes())))
+                .collect(),
+        );
+        Command::from_frame(frame).unwrap().apply_now(db)
+    }
+
+    #[test]
+    fn reply_too_large() {
+        let mut config = Config::default();
+        config.set("max-reply-size", "1024").unwrap();
+        let db = Db::new(config);
+        for i in 0..30 {
+            let (score, member) = (i.to_string(), format!("member:{}", i));
+            call(&db, &["ZADD", "zset", &score, &member]);
+        }
+        let all = call(&db, &["ZRANGE", "zset", "0", "-1"]);
+        assert!(matches!(all, Frame::Array(items) if items.len() == 30));
+        //分数同样计入回复的大小
+        let scores = call(&db, &["ZRANGE", "zset", "0", "-1", "WITHSCORES"]);
+        assert!(matches!(scores, Frame::Error(e) if e == REPLY_TOO_LARGE));
+        let page = call(
+            &db,
+            &["ZRANGEBYSCORE", "zset", "-inf", "+inf", "LIMIT", "0", "5"],
+        );
+        assert!(matches!(page, Frame::Array(items) if items.len() == 5));
+    }
+}
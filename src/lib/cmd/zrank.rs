@@ -0,0 +1,38 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use bytes::Bytes;
+
+///获取成员在有序集合中的排名
+///
+/// ZRANK key member | ZREVRANK key member
+///
+/// 排名从0开始，ZRANK按分数从小到大计算，ZREVRANK按分数从大到小计算
+#[derive(Debug)]
+pub struct ZRank {
+    key: String,
+    member: Bytes,
+    rev: bool,
+}
+
+impl ZRank {
+    pub(crate) fn parse_frames(parse: &mut Parse, rev: bool) -> Result<ZRank, ParseError> {
+        let key = parse.next_string()?;
+        let member = parse.next_bytes()?;
+        Ok(ZRank { key, member, rev })
+    }
+
+    ///键或成员不存在时回复Null
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let ZRank { key, member, rev } = self;
+        db.read(&key, |entry| match entry.value.as_zset() {
+            Ok(zset) => match zset.rank(&member) {
+                Some(rank) if rev => Frame::Integer((zset.len() - 1 - rank) as i64),
+                Some(rank) => Frame::Integer(rank as i64),
+                None => Frame::Null,
+            },
+            Err(e) => e.into(),
+        })
+        .unwrap_or(Frame::Null)
+    }
+}
@@ -0,0 +1,48 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::notify::{Class, Event};
+use crate::lib::parse::{Parse, ParseError};
+use bytes::Bytes;
+
+///从有序集合中删除成员
+///
+/// ZREM key member [member ...]
+///
+/// 成员全部删除后键也随之删除
+#[derive(Debug)]
+pub struct ZRem {
+    key: String,
+    members: Vec<Bytes>,
+}
+
+impl ZRem {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<ZRem, ParseError> {
+        let key = parse.next_string()?;
+        let mut members = vec![parse.next_bytes()?];
+        loop {
+            match parse.next_bytes() {
+                Ok(member) => members.push(member),
+                Err(ParseError::EndOfStream) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(ZRem { key, members })
+    }
+
+    ///回复实际删除的成员数
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let ZRem { key, members } = self;
+        db.mutate_notify(key, Event::new(Class::ZSet, "zrem"), |slot| {
+            let zset = match slot.as_mut().map(|value| value.as_zset_mut()) {
+                None => return (false, Frame::Integer(0)),
+                Some(Err(e)) => return (false, e.into()),
+                Some(Ok(zset)) => zset,
+            };
+            let removed = members
+                .iter()
+                .filter(|member| zset.remove(member).is_some())
+                .count();
+            (removed > 0, Frame::Integer(removed as i64))
+        })
+    }
+}
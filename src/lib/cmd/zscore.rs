@@ -0,0 +1,31 @@
+use crate::lib::db::Db;
+use crate::lib::frame::Frame;
+use crate::lib::parse::{Parse, ParseError};
+use bytes::Bytes;
+
+///获取有序集合中成员的分数
+///
+/// ZSCORE key member
+#[derive(Debug)]
+pub struct ZScore {
+    key: String,
+    member: Bytes,
+}
+
+impl ZScore {
+    pub(crate) fn parse_frames(parse: &mut Parse) -> Result<ZScore, ParseError> {
+        let key = parse.next_string()?;
+        let member = parse.next_bytes()?;
+        Ok(ZScore { key, member })
+    }
+
+    ///分数按浮点数回复，键或成员不存在时回复Null
+    pub(crate) fn apply(self, db: &Db) -> Frame {
+        let member = self.member;
+        db.read(&self.key, |entry| match entry.value.as_zset() {
+            Ok(zset) => zset.score(&member).map_or(Frame::Null, Frame::Double),
+            Err(e) => e.into(),
+        })
+        .unwrap_or(Frame::Null)
+    }
+}
@@ -0,0 +1,613 @@
+use crate::lib;
+use crate::lib::frame::{Frame, Limits};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::fmt::Write;
+use std::io::Cursor;
+
+//结束符
+const CRLF: &[u8; 2] = b"\r\n";
+
+//内联命令一行的最大字节数，超出后仍未遇到换行视为协议错误
+const MAX_INLINE_SIZE: usize = 64 * 1024;
+
+//直接引用读缓冲区而不复制的大容量字符串的最短长度，更短的值复制的开销可以忽略
+const MIN_SHARED_BULK: usize = 16 * 1024;
+
+///RESP解码器
+///
+/// 不依赖套接字，调用方通过extend写入任意切分的字节，再通过decode逐个取出完整的帧。
+/// 帧被拆分在多次写入中也没有关系，不完整的部分会留在缓冲区中等待后续字节。
+/// 与redis一样，首字节不是类型标记时按内联命令解析，便于通过telnet等工具直接输入命令
+#[derive(Debug, Default)]
+pub struct Decoder {
+    //尚未解析的字节
+    buffer: BytesMut,
+    //解析时的上限
+    limits: Limits,
+}
+
+impl Decoder {
+    ///创建一个新的解码器
+    pub fn new() -> Decoder {
+        Decoder::default()
+    }
+
+    ///创建一个指定初始缓冲区大小的解码器
+    pub fn with_capacity(capacity: usize) -> Decoder {
+        Decoder {
+            buffer: BytesMut::with_capacity(capacity),
+            limits: Limits::default(),
+        }
+    }
+
+    ///修改解析时的上限，之后解析的帧按新的上限检查
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    ///写入待解析的字节
+    pub fn extend(&mut self, src: &[u8]) {
+        self.buffer.extend_from_slice(src);
+    }
+
+    ///缓冲区中是否还有未解析的字节
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    ///尝试从缓冲区中解析出一个帧
+    ///
+    /// 字节不足以构成完整的帧时返回None，已解析的字节会从缓冲区中消耗掉
+    pub fn decode(&mut self) -> lib::Result<Option<Frame>> {
+        decode_frame(&mut self.buffer, &self.limits)
+    }
+
+    ///获取内部缓冲区，供连接直接从套接字读入数据
+    pub(crate) fn buffer_mut(&mut self) -> &mut BytesMut {
+        &mut self.buffer
+    }
+}
+
+//从缓冲区中解析出一个帧，并消耗掉对应的字节
+fn decode_frame(buffer: &mut BytesMut, limits: &Limits) -> lib::Result<Option<Frame>> {
+    loop {
+        match buffer.first() {
+            None => return Ok(None),
+            Some(byte) if Frame::is_type_marker(*byte) => return decode_resp(buffer, limits),
+            Some(_) => {
+                //换行符与请求一起到达时同样要检查长度，否则一次读入的超长行不受限制
+                let end = match buffer.iter().position(|b| *b == b'\n') {
+                    Some(end) if end > MAX_INLINE_SIZE => return Err(too_big_inline()),
+                    Some(end) => end,
+                    None if buffer.len() > MAX_INLINE_SIZE => return Err(too_big_inline()),
+                    None => return Ok(None),
+                };
+                let line = buffer.split_to(end + 1);
+                check_inline(&line[..end])?;
+                let args = split_args(&line[..end])?;
+                //空行直接跳过
+                if !args.is_empty() {
+                    return Ok(Some(Frame::Array(
+                        args.into_iter().map(Frame::Bulk).collect(),
+                    )));
+                }
+            }
+        }
+    }
+}
+
+//内联命令超过长度上限时的错误
+fn too_big_inline() -> lib::Error {
+    lib::Error::Protocol("Protocol error: too big inline request".to_string())
+}
+
+//检查内联命令的一行中没有控制字符
+//
+// 首字节是类型标记的输入一律按RESP解析，其余的按内联命令解析。内联命令是给人输入的，
+// 除了作为分隔符的空白之外不应出现NUL等控制字符，出现时多半是二进制数据被误当作了内联命令，
+// 直接拒绝，而不是按空白拆分出意料之外的参数。二进制的参数可以在双引号中以\xHH转义
+fn check_inline(line: &[u8]) -> lib::Result<()> {
+    let invalid = |b: &u8| (b.is_ascii_control() && !b.is_ascii_whitespace()) || *b == 0x7f;
+    if line.iter().any(invalid) {
+        return Err(lib::Error::Protocol(
+            "Protocol error: invalid control character in inline request".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+//解析RESP格式的帧
+fn decode_resp(buffer: &mut BytesMut, limits: &Limits) -> lib::Result<Option<Frame>> {
+    use lib::frame::FrameError::Incomplete;
+    let mut buf = Cursor::new(&buffer[..]);
+    match Frame::check_limited(&mut buf, limits) {
+        Ok(_) => {
+            let len = buf.position() as usize;
+            //大容量字符串至少占到读缓冲区的一半时才直接引用，否则一个小值就会让整个读缓冲区无法释放
+            let min = (buffer.capacity() / 2).max(MIN_SHARED_BULK);
+            if len < min {
+                buf.set_position(0);
+                //从字节流中将Frame解析出来
+                let frame = Frame::parse(&mut buf)?;
+                buffer.advance(len);
+                return Ok(Some(frame));
+            }
+            //帧所在的字节从缓冲区中分离出来，其中的大容量字符串引用这段内存而不复制
+            let data = buffer.split_to(len).freeze();
+            Ok(Some(Frame::parse_shared(&data, min)?))
+        }
+        //迟迟没有换行的长行同样不能无限缓冲
+        Err(Incomplete) if buffer.len() > limits.max_frame_size => Err(lib::Error::Protocol(
+            "Protocol error: too big request".to_string(),
+        )),
+        Err(Incomplete) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+///将内联命令的一行拆分为参数，配置文件与命令行客户端输入的一行也按同样的规则拆分
+///
+/// 与redis一致，参数以空白分隔，双引号中支持\n、\t、\xHH等转义，单引号中只支持\'，
+/// 引号未闭合或闭合的引号后面紧跟其他字符时视为协议错误
+pub fn split_args(line: &[u8]) -> lib::Result<Vec<Bytes>> {
+    let mut args = vec![];
+    let mut rest = line;
+    loop {
+        let start = rest
+            .iter()
+            .position(|b| !b.is_ascii_whitespace())
+            .unwrap_or(rest.len());
+        rest = &rest[start..];
+        let (arg, remaining) = match rest {
+            [] => return Ok(args),
+            [b'"', quoted @ ..] => split_double_quoted(quoted)?,
+            [b'\'', quoted @ ..] => split_single_quoted(quoted)?,
+            _ => {
+                let end = rest
+                    .iter()
+                    .position(u8::is_ascii_whitespace)
+                    .unwrap_or(rest.len());
+                (rest[..end].to_vec(), &rest[end..])
+            }
+        };
+        args.push(Bytes::from(arg));
+        rest = remaining;
+    }
+}
+
+//引号未闭合时的错误
+fn unbalanced_quotes() -> lib::Error {
+    lib::Error::Protocol("Protocol error: unbalanced quotes in request".to_string())
+}
+
+//解析双引号中的参数，返回参数与闭合引号之后的部分
+fn split_double_quoted(mut src: &[u8]) -> lib::Result<(Vec<u8>, &[u8])> {
+    let mut arg = vec![];
+    loop {
+        match src {
+            [b'\\', b'x', high, low, rest @ ..]
+                if high.is_ascii_hexdigit() && low.is_ascii_hexdigit() =>
+            {
+                //守卫已保证两个字节都是十六进制数字
+                let digit = |b: u8| (b as char).to_digit(16).unwrap_or(0) as u8;
+                arg.push((digit(*high) << 4) | digit(*low));
+                src = rest;
+            }
+            [b'\\', escaped, rest @ ..] => {
+                arg.push(match escaped {
+                    b'n' => b'\n',
+                    b'r' => b'\r',
+                    b't' => b'\t',
+                    b'b' => 0x08,
+                    b'a' => 0x07,
+                    other => *other,
+                });
+                src = rest;
+            }
+            [b'"', rest @ ..] => return closed(arg, rest),
+            [byte, rest @ ..] => {
+                arg.push(*byte);
+                src = rest;
+            }
+            [] => return Err(unbalanced_quotes()),
+        }
+    }
+}
+
+//解析单引号中的参数，返回参数与闭合引号之后的部分
+fn split_single_quoted(mut src: &[u8]) -> lib::Result<(Vec<u8>, &[u8])> {
+    let mut arg = vec![];
+    loop {
+        match src {
+            [b'\\', b'\'', rest @ ..] => {
+                arg.push(b'\'');
+                src = rest;
+            }
+            [b'\'', rest @ ..] => return closed(arg, rest),
+            [byte, rest @ ..] => {
+                arg.push(*byte);
+                src = rest;
+            }
+            [] => return Err(unbalanced_quotes()),
+        }
+    }
+}
+
+//闭合的引号之后只能是空白或行尾
+fn closed(arg: Vec<u8>, rest: &[u8]) -> lib::Result<(Vec<u8>, &[u8])> {
+    match rest.first() {
+        Some(byte) if !byte.is_ascii_whitespace() => Err(unbalanced_quotes()),
+        _ => Ok((arg, rest)),
+    }
+}
+
+///回复使用的协议版本
+///
+/// 连接默认使用RESP2，通过HELLO 3切换到RESP3
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    #[default]
+    Resp2,
+    Resp3,
+}
+
+impl Protocol {
+    ///协议的版本号
+    pub fn version(self) -> i64 {
+        match self {
+            Protocol::Resp2 => 2,
+            Protocol::Resp3 => 3,
+        }
+    }
+}
+
+///RESP编码器
+///
+/// 与Decoder对应，将帧编码进任意缓冲区而不依赖套接字，嵌套的数组会被递归编码。
+/// 使用RESP2时，RESP3新增的帧会被转换为RESP2中最接近的类型
+#[derive(Debug, Default)]
+pub struct Encoder {
+    protocol: Protocol,
+}
+
+impl Encoder {
+    ///创建一个新的编码器，使用RESP2
+    pub fn new() -> Encoder {
+        Encoder::default()
+    }
+
+    ///当前使用的协议
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    ///切换之后编码使用的协议
+    pub fn set_protocol(&mut self, protocol: Protocol) {
+        self.protocol = protocol;
+    }
+
+    ///将帧编码后追加到dst中
+    pub fn encode(&mut self, frame: &Frame, dst: &mut BytesMut) {
+        self.encode_limited(frame, dst, usize::MAX);
+    }
+
+    ///将帧编码后追加到dst中，dst的长度超出limit时中止编码并返回false
+    ///
+    /// 数组每编码完一个元素就检查一次，超大的回复不必完整地写入缓冲区就能被发现。
+    /// 中止时dst中残留着不完整的帧，调用方需要自行丢弃
+    pub fn encode_limited(&mut self, frame: &Frame, dst: &mut BytesMut, limit: usize) -> bool {
+        let resp3 = self.protocol == Protocol::Resp3;
+        match frame {
+            Frame::Simple(val) => {
+                dst.put_u8(b'+');
+                write_line(val, dst);
+            }
+            Frame::Error(val) => {
+                dst.put_u8(b'-');
+                write_line(val, dst);
+            }
+            Frame::Integer(val) => {
+                dst.put_u8(b':');
+                write_decimal(*val, dst);
+            }
+            Frame::Bulk(val) => write_bulk(val, dst),
+            Frame::Null if resp3 => dst.put_slice(b"_\r\n"),
+            Frame::Null => {
+                dst.put_slice(b"$-1");
+                dst.put_slice(CRLF);
+            }
+            Frame::BigNumber(val) if resp3 => {
+                dst.put_u8(b'(');
+                dst.put_slice(val.as_bytes());
+                dst.put_slice(CRLF);
+            }
+            Frame::BigNumber(val) => write_bulk(val.as_bytes(), dst),
+            Frame::Verbatim { data, .. } if !resp3 => write_bulk(data, dst),
+            Frame::Verbatim { format, data } => {
+                //长度包含格式前缀与冒号
+                dst.put_u8(b'=');
+                write_decimal((data.len() + 4) as i64, dst);
+                dst.put_slice(format);
+                dst.put_u8(b':');
+                dst.put_slice(data);
+                dst.put_slice(CRLF);
+            }
+            Frame::Double(val) if resp3 => {
+                dst.put_u8(b',');
+                dst.put_slice(format_double(*val).as_bytes());
+                dst.put_slice(CRLF);
+            }
+            Frame::Double(val) => write_bulk(format_double(*val).as_bytes(), dst),
+            Frame::Boolean(val) if resp3 => {
+                dst.put_slice(if *val { b"#t" } else { b"#f" });
+                dst.put_slice(CRLF);
+            }
+            Frame::Boolean(val) => {
+                dst.put_u8(b':');
+                write_decimal(*val as i64, dst);
+            }
+            Frame::Map(pairs) => {
+                //RESP2中按键值交替排列的数组发送，长度为元素个数
+                if resp3 {
+                    dst.put_u8(b'%');
+                    write_decimal(pairs.len() as i64, dst);
+                } else {
+                    dst.put_u8(b'*');
+                    write_decimal(pairs.len() as i64 * 2, dst);
+                }
+                for (key, value) in pairs {
+                    if !self.encode_limited(key, dst, limit)
+                        || !self.encode_limited(value, dst, limit)
+                    {
+                        return false;
+                    }
+                }
+            }
+            Frame::Array(vec) | Frame::Set(vec) | Frame::Push(vec) => {
+                let marker = match frame {
+                    Frame::Set(_) if resp3 => b'~',
+                    Frame::Push(_) if resp3 => b'>',
+                    _ => b'*',
+                };
+                dst.put_u8(marker);
+                write_decimal(vec.len() as i64, dst);
+                for cur in vec {
+                    if !self.encode_limited(cur, dst, limit) {
+                        return false;
+                    }
+                }
+            }
+        }
+        dst.len() <= limit
+    }
+}
+
+//写入单行的内容以及结束符
+//
+// 错误信息中可能带有客户端发来的参数，其中的换行会被替换为空格，避免破坏回复的格式
+fn write_line(val: &str, dst: &mut BytesMut) {
+    for byte in val.bytes() {
+        dst.put_u8(if byte == b'\r' || byte == b'\n' {
+            b' '
+        } else {
+            byte
+        });
+    }
+    dst.put_slice(CRLF);
+}
+
+//写入大容量字符串
+fn write_bulk(val: &[u8], dst: &mut BytesMut) {
+    dst.put_u8(b'$');
+    write_decimal(val.len() as i64, dst);
+    dst.put_slice(val);
+    dst.put_slice(CRLF);
+}
+
+//浮点数的文本形式，无穷大写作inf与-inf
+fn format_double(val: f64) -> String {
+    match val {
+        f64::INFINITY => "inf".to_string(),
+        f64::NEG_INFINITY => "-inf".to_string(),
+        val => val.to_string(),
+    }
+}
+
+//写入多位数字以及结束符
+fn write_decimal(val: i64, dst: &mut BytesMut) {
+    //BytesMut会自动扩容，写入不会失败
+    write!(dst, "{}", val).expect("写入BytesMut不会失败");
+    dst.put_slice(CRLF);
+}
+
+///tokio_util的编解码器
+///
+/// 可以将任意AsyncRead + AsyncWrite包装为Framed<_, RespCodec>，得到Frame的Stream与Sink
+#[cfg(feature = "codec")]
+#[derive(Debug, Default)]
+pub struct RespCodec {
+    encoder: Encoder,
+}
+
+#[cfg(feature = "codec")]
+impl RespCodec {
+    ///创建一个新的编解码器
+    pub fn new() -> RespCodec {
+        RespCodec::default()
+    }
+}
+
+#[cfg(feature = "codec")]
+impl tokio_util::codec::Decoder for RespCodec {
+    type Item = Frame;
+    type Error = lib::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> lib::Result<Option<Frame>> {
+        decode_frame(src, &Limits::default())
+    }
+}
+
+#[cfg(feature = "codec")]
+impl tokio_util::codec::Encoder<Frame> for RespCodec {
+    type Error = lib::Error;
+
+    fn encode(&mut self, item: Frame, dst: &mut BytesMut) -> lib::Result<()> {
+        self.encoder.encode(&item, dst);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(input: &[u8]) -> lib::Result<Option<Frame>> {
+        let mut decoder = Decoder::new();
+        decoder.extend(input);
+        decoder.decode()
+    }
+
+    #[test]
+    fn type_marker_is_resp() {
+        let frame = decode(b"*1\r\n$4\r\nPING\r\n").unwrap().unwrap();
+        assert!(matches!(&frame, Frame::Array(items) if items.len() == 1 && items[0] == "PING"));
+        //以*开头的行不会被当作内联命令
+        assert!(matches!(decode(b"*PING\r\n"), Err(lib::Error::Protocol(_))));
+    }
+
+    #[test]
+    fn inline_command() {
+        let frame = decode(b"PING\r\n").unwrap().unwrap();
+        assert!(matches!(&frame, Frame::Array(items) if items.len() == 1 && items[0] == "PING"));
+        let frame = decode(b"SET key \"a\\x00b\"\r\n").unwrap().unwrap();
+        assert!(matches!(&frame, Frame::Array(items) if items.len() == 3 && items[2] == "a\0b"));
+    }
+
+    #[test]
+    fn inline_control_character_is_rejected() {
+        assert!(matches!(
+            decode(b"SET key a\0b\r\n"),
+            Err(lib::Error::Protocol(_))
+        ));
+        assert!(matches!(
+            decode(b"PING\x01\r\n"),
+            Err(lib::Error::Protocol(_))
+        ));
+    }
+
+    #[test]
+    fn oversized_inline_request_is_rejected() {
+        let mut line = vec![b'a'; MAX_INLINE_SIZE + 1];
+        //没有换行时等到超过上限才拒绝
+        assert!(matches!(decode(&line), Err(lib::Error::Protocol(_))));
+        line.extend_from_slice(b"\r\n");
+        assert!(matches!(decode(&line), Err(lib::Error::Protocol(_))));
+        let mut line = vec![b'a'; MAX_INLINE_SIZE - 2];
+        line.extend_from_slice(b"\r\n");
+        assert!(decode(&line).unwrap().is_some());
+    }
+
+    #[test]
+    fn frames_split_at_any_boundary_are_decoded() {
+        let input: &[u8] = b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\nPING\r\n:42\r\n$5\r\nhello\r\n";
+        //按1到input.len()的每种块大小切分输入
+        for size in 1..=input.len() {
+            let mut decoder = Decoder::new();
+            let mut frames = vec![];
+            for chunk in input.chunks(size) {
+                decoder.extend(chunk);
+                while let Some(frame) = decoder.decode().unwrap() {
+                    frames.push(frame);
+                }
+            }
+            assert!(decoder.is_empty());
+            assert_eq!(frames.len(), 4, "chunk size {}", size);
+            assert!(
+                matches!(&frames[0], Frame::Array(items) if items.len() == 2 && items[0] == "GET" && items[1] == "key")
+            );
+            assert!(
+                matches!(&frames[1], Frame::Array(items) if items.len() == 1 && items[0] == "PING")
+            );
+            assert!(matches!(frames[2], Frame::Integer(42)));
+            assert!(frames[3] == "hello");
+        }
+    }
+
+    #[test]
+    fn encoded_nested_array_decodes_to_the_same_frame() {
+        let frame = Frame::Array(vec![
+            Frame::Simple("OK".to_string()),
+            Frame::Integer(-7),
+            Frame::Bulk(Bytes::from_static(b"a\r\nb")),
+            Frame::Null,
+            Frame::Array(vec![
+                Frame::Array(vec![]),
+                Frame::Array(vec![Frame::Error("ERR nested".to_string())]),
+                Frame::Bulk(Bytes::new()),
+            ]),
+        ]);
+        let mut buffer = BytesMut::new();
+        Encoder::new().encode(&frame, &mut buffer);
+        let mut decoder = Decoder::new();
+        decoder.extend(&buffer);
+        assert_eq!(decoder.decode().unwrap(), Some(frame));
+        assert!(decoder.is_empty());
+    }
+
+    #[cfg(feature = "codec")]
+    #[tokio::test]
+    async fn framed_duplex_sends_and_receives_frames() {
+        use futures::{SinkExt, StreamExt};
+        use tokio_util::codec::Framed;
+
+        let (client, server) = tokio::io::duplex(64);
+        let mut client = Framed::new(client, RespCodec::new());
+        let mut server = Framed::new(server, RespCodec::new());
+        let request = Frame::Array(vec![
+            Frame::from("SET"),
+            Frame::from("key"),
+            Frame::from("value"),
+        ]);
+        client.send(request.clone()).await.unwrap();
+        assert_eq!(server.next().await.unwrap().unwrap(), request);
+        server.send(Frame::Simple("OK".to_string())).await.unwrap();
+        assert_eq!(
+            client.next().await.unwrap().unwrap(),
+            Frame::Simple("OK".to_string())
+        );
+        //对端关闭后流结束
+        drop(server);
+        assert!(client.next().await.is_none());
+    }
+
+    #[test]
+    fn big_numbers_and_verbatim_strings_round_trip() {
+        let frames = [
+            Frame::BigNumber("-3492890328409238509324850943850943825024385".to_string()),
+            Frame::Verbatim {
+                format: *b"txt",
+                data: Bytes::from_static(b"Some string\r\nwith a line break"),
+            },
+        ];
+        for frame in frames {
+            let mut dst = BytesMut::new();
+            frame.encode(&mut dst);
+            assert_eq!(decode(&dst).unwrap().unwrap(), frame);
+        }
+        let mut encoder = Encoder::new();
+        encoder.set_protocol(Protocol::Resp3);
+        let mut dst = BytesMut::new();
+        let verbatim = Frame::Verbatim {
+            format: *b"mkd",
+            data: Bytes::from_static(b"# title"),
+        };
+        encoder.encode(&verbatim, &mut dst);
+        assert_eq!(&dst[..], b"=11\r\nmkd:# title\r\n");
+        //RESP2降级为大容量字符串
+        encoder.set_protocol(Protocol::Resp2);
+        dst.clear();
+        encoder.encode(&verbatim, &mut dst);
+        encoder.encode(&Frame::BigNumber("12".to_string()), &mut dst);
+        assert_eq!(&dst[..], b"$7\r\n# title\r\n$2\r\n12\r\n");
+    }
+}
@@ -0,0 +1,897 @@
+use crate::lib;
+use crate::lib::codec::split_args;
+use crate::lib::conn::{Growth, ReadBuffer};
+use crate::lib::frame::Limits;
+use crate::lib::glob;
+use crate::lib::notify::Flags;
+use crate::lib::slot::SLOT_COUNT;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+//一项配置
+struct Parameter {
+    //配置名，小写
+    name: &'static str,
+    //默认值，需为规范化之后的形式
+    default: &'static str,
+    //能否通过CONFIG SET在运行期间修改
+    mutable: bool,
+    //校验并规范化配置的值，不合法时返回None
+    normalize: fn(&str) -> Option<String>,
+}
+
+const fn parameter(
+    name: &'static str,
+    default: &'static str,
+    mutable: bool,
+    normalize: fn(&str) -> Option<String>,
+) -> Parameter {
+    Parameter {
+        name,
+        default,
+        mutable,
+        normalize,
+    }
+}
+
+//支持的配置项，新增配置时需要在这里登记
+const PARAMETERS: &[Parameter] = &[
+    parameter("bind", "127.0.0.1", false, addresses),
+    parameter("port", "6378", false, port),
+    parameter("unixsocket", "", false, any),
+    parameter("unixsocketperm", "0", false, permission),
+    parameter("tls-port", "0", false, port),
+    parameter("metrics-port", "0", false, port),
+    parameter("http-port", "0", false, port),
+    parameter("http-bind", "127.0.0.1", false, addresses),
+    parameter("http-allowed-commands", "", true, command_names),
+    parameter("runtime", "multi-thread", false, runtime),
+    parameter("worker-threads", "0", false, non_negative),
+    parameter("tcp-reuseport", "no", false, yes_no),
+    parameter("tls-cert-file", "", false, any),
+    parameter("tls-key-file", "", false, any),
+    parameter("tls-ca-cert-file", "", false, any),
+    parameter("tls-auth-clients", "yes", false, tls_auth_clients),
+    parameter("databases", "16", false, positive),
+    parameter("keyspace-shards", "64", false, shard_count),
+    parameter("maxclients", "10000", true, positive),
+    parameter("maxclients-policy", "reject", true, maxclients_policy),
+    parameter("timeout", "0", true, non_negative),
+    parameter("tcp-keepalive", "300", true, non_negative),
+    parameter("hz", "10", false, hz),
+    parameter("active-expire-sample-size", "20", false, positive),
+    parameter("notify-keyspace-events", "", true, notify_flags),
+    parameter("maxmemory", "0", true, memory),
+    parameter("maxmemory-policy", "noeviction", true, maxmemory_policy),
+    parameter("maxmemory-samples", "5", true, positive),
+    parameter("proto-max-bulk-len", "512mb", true, memory),
+    parameter("proto-max-multibulk-len", "1048576", true, positive),
+    parameter("proto-max-nesting", "128", true, positive),
+    parameter("client-query-buffer-limit", "1gb", true, memory),
+    parameter("max-reply-size", "512mb", true, memory),
+    parameter("keys-sorted", "no", true, yes_no),
+    parameter("default-ttl", "0", true, non_negative),
+    parameter("ttl-rounding", "round", true, ttl_rounding),
+    parameter("list-compress-depth", "0", true, non_negative),
+    parameter("zset-max-listpack-entries", "128", true, non_negative),
+    parameter("zset-max-listpack-value", "64", true, non_negative),
+    parameter("hash-max-listpack-entries", "128", true, non_negative),
+    parameter("hash-max-listpack-value", "64", true, non_negative),
+    parameter("set-max-intset-entries", "512", true, non_negative),
+    parameter("client-read-buffer-initial", "4kb", true, memory),
+    parameter("client-read-buffer-growth", "double", true, growth),
+    parameter("client-read-buffer-max", "1gb", true, memory),
+    parameter("slowlog-log-slower-than", "10000", true, integer),
+    parameter("slowlog-max-len", "128", true, non_negative),
+    parameter("latency-monitor-threshold", "0", true, non_negative),
+    parameter("lua-time-limit", "5000", true, non_negative),
+    parameter("save", "3600 1 300 100 60 10000", true, save_rules),
+    parameter("dir", ".", false, non_empty),
+    parameter("dbfilename", "dump.rdb", true, non_empty),
+    parameter("appendonly", "no", true, yes_no),
+    parameter("appendfilename", "appendonly.aof", false, non_empty),
+    parameter("appendfsync", "everysec", true, appendfsync),
+    parameter("auto-aof-rewrite-percentage", "100", true, non_negative),
+    parameter("auto-aof-rewrite-min-size", "64mb", true, memory),
+    parameter("repl-backlog-size", "1mb", true, memory),
+    parameter("repl-ping-replica-period", "10", true, positive),
+    parameter("repl-timeout", "60", true, positive),
+    parameter("replicaof", "", true, replica_of),
+    parameter("masterauth", "", true, any),
+    parameter("replica-read-only", "yes", true, yes_no),
+    parameter("cluster-enabled", "no", false, yes_no),
+    parameter("cluster-config-file", "nodes.conf", false, non_empty),
+    parameter("requirepass", "", true, any),
+    parameter("logfile", "", false, any),
+    parameter("loglevel", "notice", true, loglevel),
+    parameter("log-format", "plain", false, log_format),
+    parameter("protocol-trace", "no", true, yes_no),
+];
+
+//配置文件中可以带多个参数的配置，多个参数以空格连接
+const MULTI_VALUED: &[&str] = &["bind", "http-bind", "http-allowed-commands", "save"];
+
+//默认不能经HTTP网关执行的命令
+const HTTP_DENIED: &[&str] = &["eval", "evalsha", "script", "debug", "config", "shutdown"];
+
+///修改配置失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ConfigError {
+    ///没有这项配置
+    Unknown,
+    ///配置只能在启动时设置
+    Immutable,
+    ///值不合法
+    Invalid,
+}
+
+///服务器的配置
+///
+/// 启动时从redis.conf格式的配置文件读取，运行期间通过Db共享给各个模块。
+/// 值都以规范化后的字符串存储，各模块通过带类型的访问方法读取
+#[derive(Debug, Clone)]
+pub(crate) struct Config {
+    //配置文件的路径，CONFIG REWRITE时写回这里
+    path: Option<PathBuf>,
+    values: HashMap<&'static str, String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            path: None,
+            values: PARAMETERS
+                .iter()
+                .map(|parameter| (parameter.name, parameter.default.to_string()))
+                .collect(),
+        }
+    }
+}
+
+impl Config {
+    ///读取配置文件，文件中没有出现的配置使用默认值
+    ///
+    /// 每行是配置名与参数，参数的引号规则与内联命令相同，#开头的行是注释
+    pub(crate) fn load(path: impl AsRef<Path>) -> lib::Result<Config> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        let mut config = Config {
+            path: Some(path.to_path_buf()),
+            ..Config::default()
+        };
+        let mut seen = HashSet::new();
+        for (i, line) in text.lines().enumerate() {
+            config.load_line(line, &mut seen).map_err(|reason| {
+                lib::Error::Config(format!(
+                    "配置文件第{}行错误：{}\n>>> '{}'",
+                    i + 1,
+                    reason,
+                    line.trim()
+                ))
+            })?;
+        }
+        Ok(config)
+    }
+
+    //解析配置文件中的一行，seen记录文件中已经出现过的可以写成多行的配置
+    //
+    // 与redis一致，save可以写成多行，第一行替换默认的规则，之后的行依次追加
+    fn load_line(&mut self, line: &str, seen: &mut HashSet<String>) -> Result<(), String> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(());
+        }
+        let args = split_args(line.as_bytes()).map_err(|e| e.to_string())?;
+        let args = args
+            .iter()
+            .map(|arg| String::from_utf8_lossy(arg).into_owned())
+            .collect::<Vec<_>>();
+        let name = args[0].to_lowercase();
+        //只有MULTI_VALUED中的配置可以带多个参数
+        let mut value = match &args[1..] {
+            [value] => value.clone(),
+            values if MULTI_VALUED.contains(&name.as_str()) && !values.is_empty() => {
+                values.join(" ")
+            }
+            _ => return Err("Bad directive or wrong number of arguments".to_string()),
+        };
+        if name == "save" && !seen.insert(name.clone()) && !value.is_empty() {
+            value = format!("{} {}", self.values["save"], value);
+        }
+        match self.set(&name, &value) {
+            Ok(()) => Ok(()),
+            Err(ConfigError::Invalid) => Err(format!("Invalid argument '{}'", value)),
+            Err(_) => Err("Bad directive or wrong number of arguments".to_string()),
+        }
+    }
+
+    ///名称匹配glob风格模式的配置，按登记的顺序排列
+    pub(crate) fn matching(&self, pattern: &str) -> Vec<(&'static str, &str)> {
+        PARAMETERS
+            .iter()
+            .filter(|parameter| glob::matches(pattern.as_bytes(), parameter.name.as_bytes()))
+            .map(|parameter| (parameter.name, self.values[parameter.name].as_str()))
+            .collect()
+    }
+
+    ///设置一项配置，启动时使用，允许设置只读的配置
+    pub(crate) fn set(&mut self, name: &str, value: &str) -> Result<(), ConfigError> {
+        let parameter = find(name).ok_or(ConfigError::Unknown)?;
+        let value = (parameter.normalize)(value).ok_or(ConfigError::Invalid)?;
+        self.values.insert(parameter.name, value);
+        Ok(())
+    }
+
+    ///运行期间设置一项配置，只读的配置不能修改
+    pub(crate) fn set_at_runtime(&mut self, name: &str, value: &str) -> Result<(), ConfigError> {
+        match find(name) {
+            Some(parameter) if !parameter.mutable => Err(ConfigError::Immutable),
+            _ => self.set(name, value),
+        }
+    }
+
+    ///将当前的配置写回配置文件
+    ///
+    /// 文件中已有的配置行被替换为当前的值，重复的行只保留第一处，注释与其他行保持原样，
+    /// 文件中没有且不是默认值的配置追加到末尾。先写入临时文件再替换，避免写到一半时留下损坏的文件
+    pub(crate) fn rewrite(&self) -> lib::Result<()> {
+        let path = self.path.as_ref().ok_or_else(|| {
+            lib::Error::Config("The server is running without a config file".to_string())
+        })?;
+        let old = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let mut written = HashSet::new();
+        let mut text = String::new();
+        for line in old.lines() {
+            match directive(line) {
+                Some(name) if written.insert(name) => text.push_str(&self.line(name)),
+                Some(_) => continue,
+                None => text.push_str(line),
+            }
+            text.push('\n');
+        }
+        for parameter in PARAMETERS {
+            let value = &self.values[parameter.name];
+            if !written.contains(parameter.name) && value != parameter.default {
+                text.push_str(&self.line(parameter.name));
+                text.push('\n');
+            }
+        }
+        let mut tmp = path.clone().into_os_string();
+        tmp.push(".tmp");
+        fs::write(&tmp, text)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    //配置在配置文件中的一行
+    fn line(&self, name: &'static str) -> String {
+        let value = &self.values[name];
+        //bind的多个地址与save的多条规则等是分开的参数
+        if MULTI_VALUED.contains(&name) && !value.is_empty() {
+            format!("{} {}", name, value)
+        } else {
+            format!("{} {}", name, quote(value))
+        }
+    }
+
+    ///监听的地址，多个地址以空格分隔
+    pub(crate) fn bind(&self) -> &str {
+        &self.values["bind"]
+    }
+
+    ///监听的端口
+    pub(crate) fn port(&self) -> u16 {
+        self.values["port"].parse().unwrap_or_default()
+    }
+
+    ///监听的unix套接字路径，为空时不监听
+    pub(crate) fn unix_socket(&self) -> &str {
+        &self.values["unixsocket"]
+    }
+
+    ///unix套接字文件的权限，为0时保持系统默认的权限
+    #[cfg(unix)]
+    pub(crate) fn unix_socket_perm(&self) -> u32 {
+        u32::from_str_radix(&self.values["unixsocketperm"], 8).unwrap_or_default()
+    }
+
+    ///TLS监听的端口，为0时不监听
+    pub(crate) fn tls_port(&self) -> u16 {
+        self.values["tls-port"].parse().unwrap_or_default()
+    }
+
+    ///Prometheus指标的HTTP端口，为0时不监听
+    pub(crate) fn metrics_port(&self) -> u16 {
+        self.values["metrics-port"].parse().unwrap_or_default()
+    }
+
+    ///HTTP网关的端口，为0时不监听
+    pub(crate) fn http_port(&self) -> u16 {
+        self.values["http-port"].parse().unwrap_or_default()
+    }
+
+    ///HTTP网关监听的地址，默认只监听回环地址，与客户端端口的bind分开配置
+    pub(crate) fn http_bind(&self) -> &str {
+        &self.values["http-bind"]
+    }
+
+    ///命令能否经HTTP网关执行
+    ///
+    /// 脚本、调试、配置与关闭服务器的命令默认拒绝，需要在http-allowed-commands中显式列出
+    pub(crate) fn http_allows(&self, name: &str) -> bool {
+        let name = name.to_ascii_lowercase();
+        !HTTP_DENIED.contains(&name.as_str())
+            || self.values["http-allowed-commands"]
+                .split_ascii_whitespace()
+                .any(|allowed| allowed == name)
+    }
+
+    ///TLS服务端证书链的PEM文件
+    #[cfg(feature = "tls")]
+    pub(crate) fn tls_cert_file(&self) -> &str {
+        &self.values["tls-cert-file"]
+    }
+
+    ///TLS服务端私钥的PEM文件
+    #[cfg(feature = "tls")]
+    pub(crate) fn tls_key_file(&self) -> &str {
+        &self.values["tls-key-file"]
+    }
+
+    ///校验客户端证书使用的CA证书PEM文件
+    #[cfg(feature = "tls")]
+    pub(crate) fn tls_ca_cert_file(&self) -> &str {
+        &self.values["tls-ca-cert-file"]
+    }
+
+    ///是否要求客户端出示证书，取值为yes、no或optional
+    #[cfg(feature = "tls")]
+    pub(crate) fn tls_auth_clients(&self) -> &str {
+        &self.values["tls-auth-clients"]
+    }
+
+    ///default用户的密码，为空时无需密码
+    pub(crate) fn require_pass(&self) -> &str {
+        &self.values["requirepass"]
+    }
+
+    ///日志级别
+    pub(crate) fn log_level(&self) -> &str {
+        &self.values["loglevel"]
+    }
+
+    ///日志文件，为空时输出到标准输出
+    pub(crate) fn log_file(&self) -> &str {
+        &self.values["logfile"]
+    }
+
+    ///日志的输出格式，plain或json
+    pub(crate) fn log_format(&self) -> &str {
+        &self.values["log-format"]
+    }
+
+    ///是否在日志中记录每个连接收发的帧
+    pub(crate) fn protocol_trace(&self) -> bool {
+        self.values["protocol-trace"] == "yes"
+    }
+
+    ///tokio运行时的类型，multi-thread或current-thread
+    pub(crate) fn runtime(&self) -> &str {
+        &self.values["runtime"]
+    }
+
+    ///多线程运行时的工作线程数，配置为0时与CPU核数相同
+    pub(crate) fn worker_threads(&self) -> usize {
+        match self.values["worker-threads"].parse().unwrap_or(0) {
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        }
+    }
+
+    ///每个地址上监听的套接字数
+    ///
+    /// 开启tcp-reuseport时每个工作线程一个以SO_REUSEPORT绑定的套接字，各自运行accept循环，
+    /// 由内核在它们之间分配新连接；单线程运行时、未开启或不是unix系统时只有一个
+    pub(crate) fn acceptors(&self) -> usize {
+        let reuse_port = self.values["tcp-reuseport"] == "yes" && cfg!(unix);
+        if reuse_port && self.runtime() == "multi-thread" {
+            self.worker_threads()
+        } else {
+            1
+        }
+    }
+
+    ///逻辑数据库的数量
+    pub(crate) fn databases(&self) -> usize {
+        self.values["databases"].parse().unwrap_or(1)
+    }
+
+    ///每个逻辑数据库的存储分为多少个分片
+    pub(crate) fn keyspace_shards(&self) -> usize {
+        self.values["keyspace-shards"].parse().unwrap_or(1)
+    }
+
+    ///允许同时存在的最大客户端连接数
+    pub(crate) fn max_clients(&self) -> usize {
+        self.values["maxclients"].parse().unwrap_or(1)
+    }
+
+    ///连接数已满时如何处理新连接：reject为回复错误后关闭，与redis一致；wait为让新连接排队等待名额
+    pub(crate) fn maxclients_policy(&self) -> &str {
+        &self.values["maxclients-policy"]
+    }
+
+    ///连接空闲超过这么多秒后被关闭，为0时不限制
+    pub(crate) fn timeout(&self) -> u64 {
+        self.values["timeout"].parse().unwrap_or(0)
+    }
+
+    ///新连接的TCP keepalive间隔（秒），为0时不开启
+    pub(crate) fn tcp_keepalive(&self) -> u64 {
+        self.values["tcp-keepalive"].parse().unwrap_or(0)
+    }
+
+    ///内存上限的字节数，0代表不限制
+    pub(crate) fn max_memory(&self) -> u64 {
+        self.values["maxmemory"].parse().unwrap_or(0)
+    }
+
+    ///达到内存上限时的淘汰策略
+    pub(crate) fn max_memory_policy(&self) -> &str {
+        &self.values["maxmemory-policy"]
+    }
+
+    ///淘汰时每个逻辑数据库每轮抽样的键数，越大越接近精确的LRU与LFU，开销也越大
+    pub(crate) fn max_memory_samples(&self) -> usize {
+        self.values["maxmemory-samples"].parse().unwrap_or(5)
+    }
+
+    ///慢查询日志的阈值（微秒），为负数时不记录，为0时记录所有命令
+    pub(crate) fn slowlog_log_slower_than(&self) -> i64 {
+        self.values["slowlog-log-slower-than"].parse().unwrap_or(-1)
+    }
+
+    ///慢查询日志最多保留的记录数
+    pub(crate) fn slowlog_max_len(&self) -> usize {
+        self.values["slowlog-max-len"].parse().unwrap_or(0)
+    }
+
+    ///延迟监控的阈值（毫秒），为0时不记录
+    pub(crate) fn latency_monitor_threshold(&self) -> u64 {
+        self.values["latency-monitor-threshold"]
+            .parse()
+            .unwrap_or(0)
+    }
+
+    ///脚本执行超过这么长时间之后，其他连接的命令回复BUSY，脚本可以被SCRIPT KILL终止
+    pub(crate) fn lua_time_limit(&self) -> Duration {
+        Duration::from_millis(self.values["lua-time-limit"].parse().unwrap_or(5000))
+    }
+
+    ///解析客户端数据时的上限，修改后对新建立的连接生效
+    pub(crate) fn frame_limits(&self) -> Limits {
+        let defaults = Limits::default();
+        let number = |name: &str, default| self.values[name].parse().unwrap_or(default);
+        Limits {
+            max_bulk_len: number("proto-max-bulk-len", defaults.max_bulk_len),
+            max_array_len: number("proto-max-multibulk-len", defaults.max_array_len),
+            max_depth: number("proto-max-nesting", defaults.max_depth),
+            max_frame_size: number("client-query-buffer-limit", defaults.max_frame_size),
+        }
+    }
+
+    ///单个回复编码后允许的最大字节数
+    pub(crate) fn max_reply_size(&self) -> usize {
+        self.values["max-reply-size"].parse().unwrap_or(usize::MAX)
+    }
+
+    ///SET、MSET没有指定过期时间时键的生存时间，毫秒，default-ttl为0时不过期
+    pub(crate) fn default_ttl(&self) -> Option<u64> {
+        let seconds: u64 = self.values["default-ttl"].parse().unwrap_or(0);
+        (seconds > 0).then(|| seconds.saturating_mul(1000))
+    }
+
+    ///列表两端各有多少个节点不压缩，0代表不压缩
+    ///
+    /// 列表始终以VecDeque存储，这项配置只体现在DEBUG OBJECT的回复中
+    pub(crate) fn list_compress_depth(&self) -> usize {
+        self.values["list-compress-depth"].parse().unwrap_or(0)
+    }
+
+    ///有序集合以listpack编码存放的最多成员数
+    pub(crate) fn zset_max_listpack_entries(&self) -> usize {
+        self.values["zset-max-listpack-entries"]
+            .parse()
+            .unwrap_or(128)
+    }
+
+    ///有序集合以listpack编码存放时成员的最大长度
+    pub(crate) fn zset_max_listpack_value(&self) -> usize {
+        self.values["zset-max-listpack-value"].parse().unwrap_or(64)
+    }
+
+    ///哈希以listpack编码存放的最多字段数
+    pub(crate) fn hash_max_listpack_entries(&self) -> usize {
+        self.values["hash-max-listpack-entries"]
+            .parse()
+            .unwrap_or(128)
+    }
+
+    ///哈希以listpack编码存放时字段与值的最大长度
+    pub(crate) fn hash_max_listpack_value(&self) -> usize {
+        self.values["hash-max-listpack-value"].parse().unwrap_or(64)
+    }
+
+    ///集合以intset编码存放的最多成员数
+    pub(crate) fn set_max_intset_entries(&self) -> usize {
+        self.values["set-max-intset-entries"].parse().unwrap_or(512)
+    }
+
+    ///KEYS是否按字典序排序后回复
+    ///
+    /// 默认按键空间内部的顺序回复，省去排序的开销；测试需要断言确定的回复时打开
+    pub(crate) fn keys_sorted(&self) -> bool {
+        self.values["keys-sorted"] == "yes"
+    }
+
+    ///TTL把剩余的毫秒数换算为秒的方式：round为四舍五入，floor为向下取整，ceil为向上取整
+    ///
+    /// 默认的round与redis一致，floor与ceil是redis没有的扩展，开启后TTL的回复与redis不同
+    pub(crate) fn ttl_rounding(&self) -> &str {
+        &self.values["ttl-rounding"]
+    }
+
+    ///连接读缓冲区的大小策略，修改后对新建立的连接生效
+    pub(crate) fn read_buffer(&self) -> ReadBuffer {
+        let defaults = ReadBuffer::default();
+        let number = |name: &str, default| self.values[name].parse().unwrap_or(default);
+        let growth = match self.values["client-read-buffer-growth"].parse() {
+            Ok(step) => Growth::Linear(step),
+            Err(_) => Growth::Double,
+        };
+        ReadBuffer {
+            initial: number("client-read-buffer-initial", defaults.initial),
+            growth,
+            max: number("client-read-buffer-max", defaults.max),
+        }
+    }
+
+    ///自动保存快照的规则，每项为秒数与修改次数：距离上次保存超过这么多秒且修改了这么多次时保存。
+    /// 为空时不自动保存
+    pub(crate) fn save_rules(&self) -> Vec<(u64, u64)> {
+        let numbers: Vec<u64> = self.values["save"]
+            .split_ascii_whitespace()
+            .filter_map(|n| n.parse().ok())
+            .collect();
+        numbers
+            .chunks_exact(2)
+            .map(|rule| (rule[0], rule[1]))
+            .collect()
+    }
+
+    ///快照文件的路径，位于dir目录下
+    pub(crate) fn rdb_path(&self) -> PathBuf {
+        Path::new(&self.values["dir"]).join(&self.values["dbfilename"])
+    }
+
+    ///是否开启AOF
+    pub(crate) fn append_only(&self) -> bool {
+        self.values["appendonly"] == "yes"
+    }
+
+    ///AOF的路径，位于dir目录下
+    pub(crate) fn aof_path(&self) -> PathBuf {
+        Path::new(&self.values["dir"]).join(&self.values["appendfilename"])
+    }
+
+    ///AOF的刷盘策略
+    pub(crate) fn append_fsync(&self) -> &str {
+        &self.values["appendfsync"]
+    }
+
+    ///自动重写AOF的条件：文件比上次重写之后增长的百分比与文件的最小大小（字节），百分比为0时不自动重写
+    pub(crate) fn auto_aof_rewrite(&self) -> (u64, u64) {
+        let number = |name| self.values[name].parse().unwrap_or(0);
+        (
+            number("auto-aof-rewrite-percentage"),
+            number("auto-aof-rewrite-min-size"),
+        )
+    }
+
+    ///复制积压缓冲区的大小（字节）
+    pub(crate) fn repl_backlog_size(&self) -> usize {
+        self.values["repl-backlog-size"]
+            .parse()
+            .unwrap_or(1024 * 1024)
+    }
+
+    ///主节点向从节点发送PING的间隔（秒）
+    pub(crate) fn repl_ping_replica_period(&self) -> u64 {
+        self.values["repl-ping-replica-period"]
+            .parse()
+            .unwrap_or(10)
+    }
+
+    ///复制连接上没有收到任何数据多久之后认为连接已经断开（秒）
+    pub(crate) fn repl_timeout(&self) -> u64 {
+        self.values["repl-timeout"].parse().unwrap_or(60)
+    }
+
+    ///要复制的主节点的地址与端口，不是从节点时为None
+    pub(crate) fn replica_of(&self) -> Option<(String, u16)> {
+        let (host, port) = self.values["replicaof"].split_once(' ')?;
+        Some((host.to_string(), port.parse().ok()?))
+    }
+
+    ///连接主节点时使用的密码，为空时不认证
+    pub(crate) fn master_auth(&self) -> &str {
+        &self.values["masterauth"]
+    }
+
+    ///从节点是否拒绝客户端的写命令
+    pub(crate) fn replica_read_only(&self) -> bool {
+        self.values["replica-read-only"] == "yes"
+    }
+
+    ///是否以集群模式运行
+    pub(crate) fn cluster_enabled(&self) -> bool {
+        self.values["cluster-enabled"] == "yes"
+    }
+
+    ///集群节点配置文件的路径，位于dir目录下
+    pub(crate) fn cluster_config_path(&self) -> PathBuf {
+        Path::new(&self.values["dir"]).join(&self.values["cluster-config-file"])
+    }
+
+    ///后台任务每秒运行的次数
+    pub(crate) fn hz(&self) -> u64 {
+        self.values["hz"].parse().unwrap_or(10)
+    }
+
+    ///主动过期单轮最多删除的键数
+    pub(crate) fn active_expire_sample_size(&self) -> usize {
+        self.values["active-expire-sample-size"]
+            .parse()
+            .unwrap_or(20)
+    }
+
+    ///键空间事件的发布配置
+    pub(crate) fn notify_flags(&self) -> Flags {
+        Flags::parse(&self.values["notify-keyspace-events"]).unwrap_or_default()
+    }
+}
+
+//按名称查找配置项
+fn find(name: &str) -> Option<&'static Parameter> {
+    PARAMETERS.iter().find(|parameter| parameter.name == name)
+}
+
+//配置文件中一行配置的配置名，不是已知的配置时返回None
+fn directive(line: &str) -> Option<&'static str> {
+    let line = line.trim();
+    if line.starts_with('#') {
+        return None;
+    }
+    let name = line.split_ascii_whitespace().next()?.to_lowercase();
+    find(&name).map(|parameter| parameter.name)
+}
+
+//值为空或带有空白、引号等字符时加上双引号并转义
+fn quote(value: &str) -> String {
+    let plain = |c: char| c.is_ascii_graphic() && c != '"' && c != '\'' && c != '\\';
+    if !value.is_empty() && value.chars().all(plain) {
+        return value.to_string();
+    }
+    let mut quoted = String::from("\"");
+    for byte in value.bytes() {
+        match byte {
+            b'"' | b'\\' => {
+                quoted.push('\\');
+                quoted.push(byte as char);
+            }
+            b'\n' => quoted.push_str("\\n"),
+            b'\r' => quoted.push_str("\\r"),
+            b'\t' => quoted.push_str("\\t"),
+            b' '..=b'~' => quoted.push(byte as char),
+            _ => quoted.push_str(&format!("\\x{:02x}", byte)),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+//任意值
+fn any(value: &str) -> Option<String> {
+    Some(value.to_string())
+}
+
+//非空的值
+fn non_empty(value: &str) -> Option<String> {
+    Some(value.to_string()).filter(|value| !value.is_empty())
+}
+
+//空格分隔的命令名，统一为小写
+fn command_names(value: &str) -> Option<String> {
+    let names = value.split_ascii_whitespace().collect::<Vec<_>>();
+    Some(names.join(" ").to_ascii_lowercase())
+}
+
+//空格分隔的一个或多个地址
+fn addresses(value: &str) -> Option<String> {
+    let value = value.split_ascii_whitespace().collect::<Vec<_>>().join(" ");
+    Some(value).filter(|value| !value.is_empty())
+}
+
+//自动保存的规则，成对的秒数与修改次数，为空时不自动保存
+fn save_rules(value: &str) -> Option<String> {
+    let numbers = value
+        .split_ascii_whitespace()
+        .map(|n| n.parse::<u64>().ok().map(|n| n.to_string()))
+        .collect::<Option<Vec<_>>>()?;
+    Some(numbers.join(" ")).filter(|_| numbers.len() % 2 == 0)
+}
+
+//端口号
+fn port(value: &str) -> Option<String> {
+    value.parse::<u16>().ok().map(|port| port.to_string())
+}
+
+//主节点的地址与端口，为空或no one时不是从节点
+fn replica_of(value: &str) -> Option<String> {
+    let parts = value.split_ascii_whitespace().collect::<Vec<_>>();
+    match parts.as_slice() {
+        [] => Some(String::new()),
+        [no, one] if no.eq_ignore_ascii_case("no") && one.eq_ignore_ascii_case("one") => {
+            Some(String::new())
+        }
+        [host, port] => Some(format!("{} {}", host, port.parse::<u16>().ok()?)),
+        _ => None,
+    }
+}
+
+//八进制的文件权限
+fn permission(value: &str) -> Option<String> {
+    let mode = u32::from_str_radix(value, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o777)?;
+    Some(format!("{:o}", mode))
+}
+
+//正整数
+fn positive(value: &str) -> Option<String> {
+    value
+        .parse::<u64>()
+        .ok()
+        .filter(|n| *n > 0)
+        .map(|n| n.to_string())
+}
+
+//分片数，必须是不超过哈希槽数量的2的幂，这样同一个槽的键总在同一个分片
+fn shard_count(value: &str) -> Option<String> {
+    value
+        .parse::<u16>()
+        .ok()
+        .filter(|n| n.is_power_of_two() && *n <= SLOT_COUNT)
+        .map(|n| n.to_string())
+}
+
+//整数，可以为负数
+fn integer(value: &str) -> Option<String> {
+    value.parse::<i64>().ok().map(|n| n.to_string())
+}
+
+//非负整数
+fn non_negative(value: &str) -> Option<String> {
+    value.parse::<u64>().ok().map(|n| n.to_string())
+}
+
+//hz，与redis一致限制在1到500之间
+fn hz(value: &str) -> Option<String> {
+    let hz = value.parse::<u64>().ok()?;
+    Some(hz.clamp(1, 500).to_string())
+}
+
+//yes或no
+fn yes_no(value: &str) -> Option<String> {
+    let value = value.to_lowercase();
+    Some(value).filter(|value| value == "yes" || value == "no")
+}
+
+//键空间事件的类别，规范化为与redis相同的字母顺序
+fn notify_flags(value: &str) -> Option<String> {
+    Flags::parse(value).map(|flags| flags.to_string())
+}
+
+//内存大小，支持k、kb、m、mb、g、gb单位，规范化为字节数
+fn memory(value: &str) -> Option<String> {
+    let value = value.to_lowercase();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let unit: u64 = match unit {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1024,
+        "m" => 1000 * 1000,
+        "mb" => 1024 * 1024,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1024 * 1024 * 1024,
+        _ => return None,
+    };
+    let bytes = number.parse::<u64>().ok()?.checked_mul(unit)?;
+    Some(bytes.to_string())
+}
+
+//读缓冲区的扩大方式，double或每次扩大的字节数
+fn growth(value: &str) -> Option<String> {
+    if value.eq_ignore_ascii_case("double") {
+        return Some("double".to_string());
+    }
+    memory(value).filter(|step| step != "0")
+}
+
+//淘汰策略
+fn maxmemory_policy(value: &str) -> Option<String> {
+    one_of(
+        value,
+        &[
+            "noeviction",
+            "allkeys-lru",
+            "allkeys-lfu",
+            "allkeys-random",
+            "volatile-lru",
+            "volatile-lfu",
+            "volatile-random",
+            "volatile-ttl",
+        ],
+    )
+}
+
+//是否校验客户端证书
+fn tls_auth_clients(value: &str) -> Option<String> {
+    one_of(value, &["yes", "no", "optional"])
+}
+
+//AOF的刷盘策略
+fn appendfsync(value: &str) -> Option<String> {
+    one_of(value, &["always", "everysec", "no"])
+}
+
+//连接数已满时处理新连接的方式
+fn maxclients_policy(value: &str) -> Option<String> {
+    one_of(value, &["reject", "wait"])
+}
+
+//TTL换算为秒的方式
+fn ttl_rounding(value: &str) -> Option<String> {
+    one_of(value, &["round", "floor", "ceil"])
+}
+
+//日志级别
+fn loglevel(value: &str) -> Option<String> {
+    one_of(value, &["debug", "verbose", "notice", "warning", "nothing"])
+}
+
+//日志的输出格式
+fn log_format(value: &str) -> Option<String> {
+    one_of(value, &["plain", "json"])
+}
+
+//tokio运行时的类型
+fn runtime(value: &str) -> Option<String> {
+    one_of(value, &["multi-thread", "current-thread"])
+}
+
+//不区分大小写地匹配可选值中的一个
+fn one_of(value: &str, options: &[&str]) -> Option<String> {
+    let value = value.to_lowercase();
+    Some(value).filter(|value| options.contains(&value.as_str()))
+}
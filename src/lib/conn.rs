@@ -1,71 +1,188 @@
 use crate::lib;
-use crate::lib::frame::Frame;
-use bytes::{Buf, BytesMut};
-use std::io::Cursor;
+use crate::lib::codec::{Decoder, Encoder, Protocol};
+use crate::lib::frame::{Frame, Limits, REPLY_TOO_LARGE};
+use bytes::BytesMut;
 use tokio::io;
-use tokio::io::{AsyncReadExt, AsyncWriteExt, BufWriter};
-use tokio::net::TcpStream;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
+///连接使用的传输层，TCP、TLS等任何可以异步读写的字节流都满足
+pub trait Stream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Stream for T {}
+
+///客户端连接
+///
+/// 在字节流之上按帧读写，不关心底层是哪种传输层
 #[derive(Debug)]
-pub(crate) struct Connection {
-    //对于TCP连接的缓冲写入
-    stream: BufWriter<TcpStream>,
-    //作为一个空的缓冲区
-    buffer: BytesMut,
+pub struct Connection<S> {
+    //底层的字节流，回复在write_buf中攒成连续的一段后直接写入，不再经过额外的缓冲
+    stream: S,
+    //读取缓冲区与帧的解析
+    decoder: Decoder,
+    //帧的编码
+    encoder: Encoder,
+    //编码后等待写入的字节
+    write_buf: BytesMut,
+    //是否记录收发的每个帧
+    trace: bool,
+    //读缓冲区的大小策略
+    read_buffer: ReadBuffer,
+    //单个回复编码后允许的最大字节数
+    max_reply: usize,
+}
+
+///读缓冲区的大小策略
+///
+/// 缓冲区从initial开始，装满之后按growth扩大，最多扩大到max；处理完大请求后缓冲区中剩余的数据不多时，
+/// 超出initial太多的缓冲区会被换回initial大小，以免大量连接各自占着曾经用过的大缓冲区
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadBuffer {
+    ///初始的容量
+    pub initial: usize,
+    ///装满之后的扩大方式
+    pub growth: Growth,
+    ///最大的容量，未解析的数据达到该大小后连接被视为违反协议
+    pub max: usize,
+}
+
+///读缓冲区装满之后的扩大方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Growth {
+    ///扩大为当前容量的两倍
+    Double,
+    ///每次扩大固定的字节数
+    Linear(usize),
+}
+
+impl Default for ReadBuffer {
+    fn default() -> ReadBuffer {
+        ReadBuffer {
+            initial: 4 * KB,
+            growth: Growth::Double,
+            max: 1024 * MB,
+        }
+    }
 }
 
+//容量超出initial的这么多倍时在空闲后收缩
+const SHRINK_FACTOR: usize = 4;
+
 const KB: usize = 1024;
-//结束符
-const CRLF: &[u8; 2] = b"\r\n";
+const MB: usize = 1024 * KB;
+
+//单个回复编码后默认允许的最大字节数，与max-reply-size的默认值一致
+const MAX_REPLY_SIZE: usize = 512 * MB;
+
+impl<S: Stream> Connection<S> {
+    ///创建一个新的连接，使用默认的读缓冲区策略
+    pub fn new(socket: S) -> Connection<S> {
+        Connection::with_read_buffer(socket, ReadBuffer::default())
+    }
 
-impl Connection {
-    ///创建一个新的连接
-    pub fn new(socket: TcpStream) -> Connection {
+    ///创建一个新的连接，读缓冲区按read_buffer分配与扩大
+    pub fn with_read_buffer(socket: S, read_buffer: ReadBuffer) -> Connection<S> {
         Connection {
-            stream: BufWriter::new(socket),
-            buffer: BytesMut::with_capacity(4 * KB),
+            stream: socket,
+            decoder: Decoder::with_capacity(read_buffer.initial),
+            encoder: Encoder::new(),
+            write_buf: BytesMut::with_capacity(4 * KB),
+            trace: false,
+            read_buffer,
+            max_reply: MAX_REPLY_SIZE,
         }
     }
 
-    ///从字节流中读取数据，并解析出Frame
-    fn parse_frame(&mut self) -> lib::Result<Option<Frame>> {
-        use lib::frame::FrameError::Incomplete;
-        let mut buf = Cursor::new(&self.buffer[..]);
-        match Frame::check(&mut buf) {
-            Ok(_) => {
-                let len = buf.position() as usize;
-                buf.set_position(0);
-                //从字节流中将Frame解析出来
-                let frame = Frame::parse(&mut buf)?;
-                self.buffer.advance(len);
-                Ok(Some(frame))
-            }
-            Err(Incomplete) => Ok(None),
-            Err(e) => Err(e.into()),
-        }
+    ///连接当前使用的协议
+    pub fn protocol(&self) -> Protocol {
+        self.encoder.protocol()
+    }
+
+    ///切换连接使用的协议，之后的回复都按该协议编码
+    pub fn set_protocol(&mut self, protocol: Protocol) {
+        self.encoder.set_protocol(protocol);
+    }
+
+    ///修改解析对端数据时的上限
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.decoder.set_limits(limits);
+    }
+
+    ///修改单个回复编码后允许的最大字节数，超出的回复改为ERR reply too large
+    ///
+    /// 这里是最后一道检查，KEYS、HGETALL这类回复可能很大的命令在构造回复时就按max-reply-size检查，
+    /// 不会先在内存中构造出整个回复
+    pub fn set_max_reply(&mut self, max: usize) {
+        self.max_reply = max;
+    }
+
+    ///开启或关闭协议追踪
+    ///
+    /// 开启后每个收到与发出的帧都以转义后的原始字节与pretty的格式记录到日志中，
+    /// 用于排查客户端的兼容性问题。追踪需要复制字节，只应在排查问题时开启
+    pub fn set_trace(&mut self, trace: bool) {
+        self.trace = trace;
     }
 
     ///从字节流中尝试读取frame
+    ///
+    /// 缓冲区中的帧都处理完、需要等待套接字上的新数据时，先把积攒的回复发送出去，
+    /// 这样流水线中的一批命令的回复只需要一次写入
     pub async fn read_frame(&mut self) -> lib::Result<Option<Frame>> {
         loop {
             //如果可以解析出一个帧则返回解析出来的frame，直接返回
-            //parse_frame中会自动消耗buffer中的数据
+            //decode中会自动消耗buffer中的数据
             //使用loop的原因是可能目前获取的命令不全，与前一个命令发生了粘包
             // 导致缓存内部命令残缺所以需要多次读取
-            if let Some(frame) = self.parse_frame()? {
+            if self.trace {
+                let raw = self.decoder.buffer_mut().clone();
+                if let Some(frame) = self.decoder.decode()? {
+                    let used = raw.len() - self.decoder.buffer_mut().len();
+                    trace("<<", &raw[..used], &frame);
+                    return Ok(Some(frame));
+                }
+            } else if let Some(frame) = self.decoder.decode()? {
                 return Ok(Some(frame));
             }
+            self.flush().await?;
+            self.reserve()?;
             //从self的stream流中将数据读入buffer中，
-            if self.stream.read_buf(&mut self.buffer).await? == 0 {
-                return if self.buffer.is_empty() {
+            if self.stream.read_buf(self.decoder.buffer_mut()).await? == 0 {
+                return if self.decoder.is_empty() {
                     Ok(None)
                 } else {
-                    Err("链接强制中断".into())
+                    //对端在帧的中途断开，按读写错误处理
+                    Err(io::Error::new(io::ErrorKind::UnexpectedEof, "链接强制中断").into())
                 };
             }
         }
     }
 
+    //读取套接字之前调整读缓冲区：剩余的数据不多时收缩过大的缓冲区，装满时按策略扩大
+    fn reserve(&mut self) -> lib::Result<()> {
+        let policy = self.read_buffer;
+        let buffer = self.decoder.buffer_mut();
+        let len = buffer.len();
+        if len <= policy.initial && buffer.capacity() > policy.initial * SHRINK_FACTOR {
+            let mut shrunk = BytesMut::with_capacity(policy.initial);
+            shrunk.extend_from_slice(buffer);
+            *buffer = shrunk;
+        }
+        if buffer.capacity() > len {
+            return Ok(());
+        }
+        if len >= policy.max {
+            return Err(lib::Error::Protocol(
+                "Protocol error: client read buffer limit reached".to_string(),
+            ));
+        }
+        let additional = match policy.growth {
+            Growth::Double => buffer.capacity().max(policy.initial),
+            Growth::Linear(step) => step,
+        };
+        buffer.reserve(additional.max(1).min(policy.max - len));
+        Ok(())
+    }
+
     ///redis的传输协议
     ///
     ///1、对于简单字符串，回复的第一个字节是“+”，后续直接加字符串内容，一般来说比较短
@@ -78,65 +195,189 @@ impl Connection {
     ///
     /// 5、对于数组，回复的第一个字节是“*”，格式为“${长度} {内容}”，长度为-1时代表为空
     ///
-    /// 进行解析
-    pub async fn write_frame(&mut self, frame: Frame) -> io::Result<()> {
-        match frame {
-            Frame::Array(target_vec) => {
-                self.stream.write_u8(b'*').await?;
-                self.write_decimal(target_vec.len() as u64).await?;
-                for cur in target_vec {
-                    self.write_value(&cur).await?;
-                }
+    /// 使用RESP3时还有映射、集合、浮点数、布尔值与推送等类型，使用RESP2的连接上会被转换为以上类型
+    ///
+    /// 帧先通过编码器写入缓冲区，再连同之前积攒的回复一次性写入stream
+    pub async fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        self.buffer_frame(frame);
+        self.flush().await
+    }
+
+    ///将帧编码进写缓冲区但不发送，回复在flush或下一次需要读取套接字时才会发出
+    pub fn buffer_frame(&mut self, frame: &Frame) {
+        let start = self.write_buf.len();
+        let limit = start.saturating_add(self.max_reply);
+        if !self
+            .encoder
+            .encode_limited(frame, &mut self.write_buf, limit)
+        {
+            self.write_buf.truncate(start);
+            let err = Frame::Error(REPLY_TOO_LARGE.to_string());
+            self.encoder.encode(&err, &mut self.write_buf);
+        }
+        if self.trace {
+            trace(">>", &self.write_buf[start..], frame);
+        }
+    }
+
+    ///发送已经编码好的数据，复制流这类不按帧组织的数据使用
+    pub async fn write_raw(&mut self, data: &[u8]) -> io::Result<()> {
+        self.write_buf.extend_from_slice(data);
+        self.flush().await
+    }
+
+    ///以流的方式发送一个大容量字符串，内容从reader中读出len个字节，值不需要整个放在内存中
+    ///
+    /// 内容直接写入stream而不经过写缓冲区，每次只占用一小块复制用的缓冲区。
+    /// 复制时发送的快照没有结尾的\r\n，terminated为false时省略。
+    /// reader提前结束时返回错误，此时已经发出了部分内容，连接只能关闭
+    pub async fn write_bulk_stream<R: AsyncRead + Unpin>(
+        &mut self,
+        len: u64,
+        reader: R,
+        terminated: bool,
+    ) -> io::Result<()> {
+        self.write_buf
+            .extend_from_slice(format!("${}\r\n", len).as_bytes());
+        self.flush().await?;
+        let copied = io::copy(&mut reader.take(len), &mut self.stream).await?;
+        if copied < len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "bulk value ended before its length",
+            ));
+        }
+        if terminated {
+            self.write_buf.extend_from_slice(b"\r\n");
+        }
+        self.flush().await
+    }
+
+    ///发送写缓冲区中积攒的回复
+    ///
+    /// 缓冲区是连续的一段，通常一次系统调用就能写完。
+    /// 已写出的字节会立即从缓冲区中移除，在select!中被取消后再次调用也不会重复发送
+    pub async fn flush(&mut self) -> io::Result<()> {
+        if self.write_buf.is_empty() {
+            return Ok(());
+        }
+        while !self.write_buf.is_empty() {
+            if self.stream.write_buf(&mut self.write_buf).await? == 0 {
+                return Err(io::ErrorKind::WriteZero.into());
             }
-            _ => self.write_value(&frame).await?,
         }
         self.stream.flush().await
     }
+}
 
-    //将除了数组以外的对象写入stream
-    async fn write_value(&mut self, frame: &Frame) -> io::Result<()> {
-        match frame {
-            Frame::Simple(val) => {
-                self.stream.write_u8(b'+').await?;
-                self.stream.write_all(val.as_bytes()).await?;
-                self.stream.write_all(CRLF).await?;
-            }
-            Frame::Error(val) => {
-                self.stream.write_u8(b'-').await?;
-                self.stream.write_all(val.as_bytes()).await?;
-                self.stream.write_all(CRLF).await?;
-            }
-            Frame::Integer(val) => {
-                self.stream.write_u8(b':').await?;
-                self.write_decimal(*val).await?;
-            }
-            Frame::Bulk(val) => {
-                let len = val.len();
-                self.stream.write_u8(b'$').await?;
-                self.write_decimal(len as u64).await?;
-                self.stream.write_all(val).await?;
-                self.stream.write_all(CRLF).await?;
+//记录一个收发的帧，direction为<<时是收到的帧，>>时是发出的帧
+fn trace(direction: &str, raw: &[u8], frame: &Frame) {
+    tracing::info!(
+        target: "protocol",
+        "{} {}\n{}",
+        direction,
+        raw.escape_ascii(),
+        frame.pretty()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use tokio::io::{duplex, DuplexStream};
+
+    fn pair(capacity: usize) -> (Connection<DuplexStream>, Connection<DuplexStream>) {
+        let (client, server) = duplex(capacity);
+        (Connection::new(client), Connection::new(server))
+    }
+
+    fn command(args: &[&[u8]]) -> Frame {
+        Frame::Array(
+            args.iter()
+                .map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg)))
+                .collect(),
+        )
+    }
+
+    #[tokio::test]
+    async fn round_trip() {
+        //管道容量远小于帧，读写都要分多次完成
+        let (mut client, mut server) = pair(64);
+        let value = vec![b'x'; 1000];
+        let request = command(&[b"SET", b"key", &value]);
+        let (written, read) = tokio::join!(client.write_frame(&request), server.read_frame());
+        written.unwrap();
+        match read.unwrap() {
+            Some(Frame::Array(items)) => {
+                assert_eq!(items.len(), 3);
+                assert!(items[0] == "SET");
+                assert!(matches!(&items[2], Frame::Bulk(data) if data[..] == value[..]));
             }
-            Frame::Null => {
-                self.stream.write_all(b"$-1").await?;
-                self.stream.write_all(CRLF).await?
+            frame => panic!("unexpected frame {:?}", frame),
+        }
+        server
+            .write_frame(&Frame::Simple("OK".to_string()))
+            .await
+            .unwrap();
+        assert!(client.read_frame().await.unwrap().unwrap() == "OK");
+    }
+
+    #[tokio::test]
+    async fn pipeline_and_close() {
+        let (mut client, mut server) = pair(1024);
+        client
+            .write_raw(b"*1\r\n$4\r\nPING\r\n*2\r\n$4\r\nECHO\r\n$2\r\nhi\r\nPING\r\n")
+            .await
+            .unwrap();
+        drop(client);
+        for expected in [1, 2, 1] {
+            match server.read_frame().await.unwrap() {
+                Some(Frame::Array(items)) => assert_eq!(items.len(), expected),
+                frame => panic!("unexpected frame {:?}", frame),
             }
-            Frame::Array(_) => unreachable!(),
         }
-        Ok(())
+        assert!(server.read_frame().await.unwrap().is_none());
     }
 
-    //写入多位数字
-    async fn write_decimal(&mut self, val: u64) -> io::Result<()> {
-        use std::io::Write;
+    #[tokio::test]
+    async fn close_in_the_middle_of_a_frame() {
+        let (mut client, mut server) = pair(1024);
+        client.write_raw(b"*2\r\n$3\r\nGET\r\n").await.unwrap();
+        drop(client);
+        assert!(matches!(server.read_frame().await, Err(lib::Error::Io(_))));
+    }
 
-        let mut buf = [0u8; 20];
-        let mut buf_cur = Cursor::new(&mut buf[..]);
-        //需要通过cursor进行写入
-        write!(&mut buf_cur, "{}", val)?;
-        let len = buf_cur.position() as usize;
-        self.stream.write_all(&buf_cur.get_ref()[0..len]).await?;
-        self.stream.write_all(CRLF).await?;
-        Ok(())
+    #[tokio::test]
+    async fn read_buffer_limit() {
+        let (client, server) = duplex(1024);
+        let mut client = Connection::new(client);
+        let read_buffer = ReadBuffer {
+            initial: 16,
+            growth: Growth::Linear(16),
+            max: 64,
+        };
+        let mut server = Connection::with_read_buffer(server, read_buffer);
+        client.write_raw(b"*1\r\n$100\r\n").await.unwrap();
+        client.write_raw(&[b'x'; 100]).await.unwrap();
+        assert!(matches!(
+            server.read_frame().await,
+            Err(lib::Error::Protocol(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn reply_too_large() {
+        let (mut client, mut server) = pair(1024);
+        server.set_max_reply(16);
+        let reply = Frame::Bulk(Bytes::from(vec![b'x'; 100]));
+        server.write_frame(&reply).await.unwrap();
+        server.write_frame(&Frame::Integer(1)).await.unwrap();
+        let frame = client.read_frame().await.unwrap().unwrap();
+        assert!(matches!(frame, Frame::Error(e) if e == REPLY_TOO_LARGE));
+        assert!(matches!(
+            client.read_frame().await.unwrap(),
+            Some(Frame::Integer(1))
+        ));
     }
 }
@@ -0,0 +1,35 @@
+///计算数据的CRC64校验和
+///
+/// 使用与redis相同的Jones多项式（反射形式），初始值为0且不做最终异或，
+/// 用于校验DUMP等序列化数据在传输或存储中没有损坏
+pub(crate) fn checksum(data: &[u8]) -> u64 {
+    data.iter().fold(0, |crc, byte| {
+        TABLE[((crc ^ *byte as u64) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+//Jones多项式的反射形式
+const POLY: u64 = 0x95ac_9329_ac4b_c9b5;
+
+//按字节查表，编译期生成
+const TABLE: [u64; 256] = table();
+
+const fn table() -> [u64; 256] {
+    let mut table = [0u64; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u64;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ POLY
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
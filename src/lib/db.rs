@@ -0,0 +1,1869 @@
+use crate::lib::acl::Acl;
+use crate::lib::aof::{self, Aof};
+use crate::lib::client::Clients;
+use crate::lib::cluster::Cluster;
+use crate::lib::config::{Config, ConfigError};
+use crate::lib::evict::{Access, Eviction, Policy};
+use crate::lib::frame::ReplyLimit;
+use crate::lib::glob;
+use crate::lib::latency::LatencyMonitor;
+use crate::lib::logging;
+use crate::lib::lua::Running;
+use crate::lib::master_link::{self, MasterLink};
+use crate::lib::notify::{Class, Event, Notifier};
+use crate::lib::propagate::Order;
+use crate::lib::random;
+use crate::lib::rdb::{SaveOnExit, SaveState};
+use crate::lib::replication::Replication;
+use crate::lib::sha1;
+use crate::lib::slowlog::SlowLog;
+use crate::lib::stats::Stats;
+use crate::lib::storage::{ShardStats, ShardedStorage, Storage};
+use crate::lib::tracking::{Invalidation, Tracking};
+use crate::lib::value::{limits, Value, WrongType, TYPE_NAMES};
+use bytes::{Bytes, BytesMut};
+use std::cell::Cell;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError, RwLock, RwLockReadGuard};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::{broadcast, Notify};
+use tokio::time::MissedTickBehavior;
+
+///数据库中存储的条目
+#[derive(Debug, Clone)]
+pub(crate) struct Entry {
+    ///存储的值
+    pub(crate) value: Value,
+    ///最后一次修改该条目时的版本号
+    pub(crate) version: u64,
+    ///过期时间，unix时间戳（毫秒），None代表永不过期
+    pub(crate) expires_at: Option<u64>,
+    ///访问记录，淘汰键时据此挑选
+    pub(crate) access: Access,
+    ///估算占用的内存字节数，包括键与存储结构本身
+    pub(crate) size: usize,
+}
+
+impl Entry {
+    ///条目在now时刻是否已经过期
+    pub(crate) fn is_expired(&self, now: u64) -> bool {
+        matches!(self.expires_at, Some(at) if at <= now)
+    }
+}
+
+///原子更新后键的状态
+#[derive(Debug)]
+pub(crate) enum Update {
+    ///保持原值不变
+    Keep,
+    ///写入新的值与过期时间
+    Set {
+        value: Value,
+        expires_at: Option<u64>,
+    },
+    ///保持原值不变，只修改过期时间
+    SetExpire(Option<u64>),
+    ///删除该键
+    Remove,
+}
+
+///后台定时任务的配置
+#[derive(Debug, Clone)]
+pub(crate) struct CronConfig {
+    ///两次节拍之间的间隔
+    pub(crate) interval: Duration,
+    ///主动过期单轮最多删除的键数，删满时说明过期的键较多，会立即开始下一轮
+    pub(crate) sample_size: usize,
+}
+
+impl CronConfig {
+    ///按配置中每秒hz次节拍计算间隔，每轮最多删除active-expire-sample-size个键，默认与redis一致为20个
+    ///
+    /// hz越高，过期的键、超出maxmemory的内存与空闲的连接被处理得越及时，空闲时占用的CPU也越多。
+    /// 每轮删除的键越多，大量键同时过期时清理得越快，单轮占用工作线程的时间也越长
+    pub(crate) fn new(config: &Config) -> CronConfig {
+        CronConfig {
+            interval: Duration::from_millis(1000 / config.hz().max(1)),
+            sample_size: config.active_expire_sample_size(),
+        }
+    }
+}
+
+///持有数据库并负责在销毁时关闭后台的定时任务
+///
+/// 后台任务持有Shared的引用，单靠Db的引用计数无法让它退出，所以需要这个守卫显式通知
+#[derive(Debug)]
+pub(crate) struct DbDropGuard {
+    db: Db,
+}
+
+impl DbDropGuard {
+    ///按配置创建数据库并启动后台的定时任务
+    pub(crate) fn new(config: Config) -> DbDropGuard {
+        let cron = CronConfig::new(&config);
+        let db = Db::new(config);
+        tokio::spawn(cron_task(db.clone(), cron));
+        DbDropGuard { db }
+    }
+
+    ///获取数据库
+    pub(crate) fn db(&self) -> Db {
+        self.db.clone()
+    }
+}
+
+impl Drop for DbDropGuard {
+    fn drop(&mut self) {
+        self.db.shared.shutdown.store(true, Ordering::Release);
+        self.db.shared.background_task.notify_one();
+    }
+}
+
+///键空间
+///
+/// 对存储后端的包装，所有对键的修改都需要经过这里，以便为每个条目打上单调递增的版本号。
+/// WATCH与客户端缓存的失效通知都依赖版本号来判断一个键是否被修改过。
+/// 每个Db只操作其中一个逻辑数据库，连接通过SELECT切换到其他数据库的Db
+#[derive(Debug, Clone)]
+pub(crate) struct Db {
+    shared: Arc<Shared>,
+    //逻辑数据库的编号
+    index: usize,
+}
+
+///每个频道缓存的消息数，订阅者落后超过该数量时会丢失消息
+const PUB_SUB_CAPACITY: usize = 1024;
+
+///估算集合占用的内存时默认抽样的元素数
+pub(crate) const SIZE_SAMPLES: usize = 5;
+
+//一个逻辑数据库的存储
+#[derive(Debug)]
+struct Keyspace {
+    //键与条目
+    storage: Box<dyn Storage>,
+    //按过期时间排序的键，主动过期任务据此找出已过期的键。
+    // 删除键时可能残留过时的记录，清理时会再次确认条目本身是否过期
+    expirations: Mutex<BTreeSet<(u64, String)>>,
+}
+
+impl Keyspace {
+    fn new(storage: Box<dyn Storage>) -> Keyspace {
+        Keyspace {
+            storage,
+            expirations: Mutex::default(),
+        }
+    }
+
+    //按策略抽取最多samples个候选键，volatile策略只从带有过期时间的键中抽取
+    //
+    // 存储后端不支持随机访问，这里跳过随机个键后取连续的一段。遍历顺序由后端决定，
+    // 默认的后端按键的哈希排列，连续的键之间没有关联，代价是跳过的部分需要逐个遍历
+    fn sample(&self, policy: Policy, samples: usize) -> Vec<String> {
+        if policy.is_volatile() {
+            let expirations = self.expirations.lock().unwrap();
+            //过期索引按时间排序，最前面的就是最快过期的键
+            let skip = match expirations.len() {
+                0 => return vec![],
+                _ if policy == Policy::VolatileTtl => 0,
+                len => random::below(len),
+            };
+            return expirations
+                .iter()
+                .skip(skip)
+                .take(samples)
+                .map(|(_, key)| key.clone())
+                .collect();
+        }
+        let skip = match self.storage.len() {
+            0 => return vec![],
+            len => random::below(len),
+        };
+        let mut seen = 0;
+        let mut keys = vec![];
+        self.storage.scan(&mut |key, _| {
+            seen += 1;
+            if seen > skip {
+                keys.push(key.to_string());
+            }
+            keys.len() < samples
+        });
+        keys
+    }
+}
+
+#[derive(Debug)]
+struct Shared {
+    //所有逻辑数据库的存储
+    keyspaces: Vec<Keyspace>,
+    //逻辑数据库编号到keyspaces下标的映射，SWAPDB只交换映射而不移动数据。
+    // 持有键空间锁时才能访问，只在持有写锁时修改
+    layout: Vec<AtomicUsize>,
+    //全局版本计数器，每次修改自增，保证版本号在整个库中单调递增
+    version: AtomicU64,
+    //运行期间的统计
+    stats: Stats,
+    //通知后台任务
+    background_task: Notify,
+    //数据库是否已关闭
+    shutdown: AtomicBool,
+    //是否主动清理过期的键，DEBUG SET-ACTIVE-EXPIRE可以关闭
+    active_expire: AtomicBool,
+    //键空间锁，单键操作持有读锁，需要原子地操作多个键时持有写锁
+    keyspace_lock: RwLock<()>,
+    //阻塞在各个键上等待写入的客户端，按逻辑数据库编号分开
+    waiters: Mutex<Vec<HashMap<String, Vec<Arc<Notify>>>>>,
+    //正在阻塞等待的客户端数，为0时写入无需检查waiters
+    blocked: AtomicUsize,
+    //发布订阅的频道，没有订阅者的频道会在发布时被清理
+    pub_sub: Mutex<HashMap<String, broadcast::Sender<Bytes>>>,
+    //分片发布订阅的频道，与pub_sub是相互独立的命名空间
+    shard_pub_sub: Mutex<HashMap<String, broadcast::Sender<Bytes>>>,
+    //推送给监视连接的命令
+    monitors: broadcast::Sender<String>,
+    //按模式订阅的频道，消息附带实际发布的频道名
+    patterns: Mutex<HashMap<String, broadcast::Sender<(String, Bytes)>>>,
+    //键空间事件的发布配置
+    notifier: Notifier,
+    //以SHA1摘要为键缓存的Lua脚本
+    scripts: Mutex<HashMap<String, Bytes>>,
+    //正在执行的脚本
+    running_script: Running,
+    //服务器的配置
+    config: RwLock<Config>,
+    //访问控制列表
+    acl: RwLock<Acl>,
+    //在线的连接
+    clients: Clients,
+    //客户端缓存的追踪表
+    tracking: Tracking,
+    //执行较慢的命令
+    slowlog: SlowLog,
+    //各类事件的延迟尖峰
+    latency: LatencyMonitor,
+    //是否追踪连接收发的帧，修改配置时同步到这里
+    protocol_trace: AtomicBool,
+    //连接空闲的超时秒数，为0时不限制，修改配置时同步到这里
+    idle_timeout: AtomicU64,
+    //内存上限与淘汰策略
+    eviction: Eviction,
+    //DEBUG SET-MEMORY-USAGE设置的内存占用与估算值之差，只有测试会修改
+    memory_offset: AtomicI64,
+    //快照的保存状态
+    saves: SaveState,
+    //写命令传播的顺序锁
+    order: Order,
+    //AOF
+    aof: Aof,
+    //复制，主节点一侧
+    replication: Replication,
+    //复制，从节点一侧
+    master_link: MasterLink,
+    //集群模式下的节点与槽的分配，启动时载入，没有开启集群模式时为空
+    cluster: OnceLock<Cluster>,
+    //SHUTDOWN命令要求的保存方式，没有要求关闭时为None
+    shutdown_request: Mutex<Option<SaveOnExit>>,
+    //SHUTDOWN命令要求关闭时通知Server::run
+    shutdown_requested: Notify,
+}
+
+thread_local! {
+    //当前线程是否正持有键空间的写锁，持有时单键操作不再重复加锁
+    static EXCLUSIVE: Cell<bool> = const { Cell::new(false) };
+}
+
+impl Db {
+    ///按配置创建空的逻辑数据库，返回其中0号数据库的Db
+    pub(crate) fn new(config: Config) -> Db {
+        let databases = config.databases();
+        let shards = config.keyspace_shards();
+        let notifier = Notifier::default();
+        notifier.set_flags(config.notify_flags());
+        let slowlog = SlowLog::default();
+        slowlog.configure(config.slowlog_log_slower_than(), config.slowlog_max_len());
+        let latency = LatencyMonitor::default();
+        latency.configure(config.latency_monitor_threshold());
+        let protocol_trace = AtomicBool::new(config.protocol_trace());
+        let clients = Clients::new(config.max_clients());
+        let idle_timeout = AtomicU64::new(config.timeout());
+        let eviction = Eviction::new(&config);
+        let aof = Aof::new(&config);
+        let replication = Replication::new(&config);
+        Db {
+            shared: Arc::new(Shared {
+                keyspaces: (0..databases)
+                    .map(|_| Keyspace::new(Box::new(ShardedStorage::new(shards))))
+                    .collect(),
+                layout: (0..databases).map(AtomicUsize::new).collect(),
+                version: AtomicU64::new(0),
+                stats: Stats::default(),
+                background_task: Notify::new(),
+                shutdown: AtomicBool::new(false),
+                active_expire: AtomicBool::new(true),
+                keyspace_lock: RwLock::new(()),
+                waiters: Mutex::new(vec![HashMap::new(); databases]),
+                blocked: AtomicUsize::new(0),
+                pub_sub: Mutex::new(HashMap::new()),
+                shard_pub_sub: Mutex::new(HashMap::new()),
+                monitors: broadcast::channel(PUB_SUB_CAPACITY).0,
+                patterns: Mutex::new(HashMap::new()),
+                notifier,
+                scripts: Mutex::new(HashMap::new()),
+                running_script: Running::default(),
+                acl: RwLock::new(Acl::new(config.require_pass())),
+                config: RwLock::new(config),
+                clients,
+                tracking: Tracking::default(),
+                slowlog,
+                latency,
+                protocol_trace,
+                idle_timeout,
+                eviction,
+                memory_offset: AtomicI64::new(0),
+                saves: SaveState::default(),
+                order: Order::default(),
+                aof,
+                replication,
+                master_link: MasterLink::default(),
+                cluster: OnceLock::new(),
+                shutdown_request: Mutex::new(None),
+                shutdown_requested: Notify::new(),
+            }),
+            index: 0,
+        }
+    }
+
+    ///当前操作的逻辑数据库的编号
+    pub(crate) fn index(&self) -> usize {
+        self.index
+    }
+
+    ///逻辑数据库的数量
+    pub(crate) fn databases(&self) -> usize {
+        self.shared.keyspaces.len()
+    }
+
+    ///获取操作index号逻辑数据库的Db，编号超出范围时返回None
+    pub(crate) fn select(&self, index: usize) -> Option<Db> {
+        if index >= self.databases() {
+            return None;
+        }
+        Some(Db {
+            shared: self.shared.clone(),
+            index,
+        })
+    }
+
+    ///获取键对应的字符串值，键存储的不是字符串时返回WrongType
+    pub(crate) fn get(&self, key: &str) -> Result<Option<Bytes>, WrongType> {
+        self.read(key, |entry| entry.value.as_string()).transpose()
+    }
+
+    ///读取键对应的条目
+    ///
+    /// 已过期的键视为不存在，并在读取时被惰性删除
+    pub(crate) fn read<R>(&self, key: &str, f: impl FnOnce(&Entry) -> R) -> Option<R> {
+        let _guard = self.lock_shared();
+        let now = now_millis();
+        let found = self.keyspace().storage.read(key, |entry| {
+            if entry.is_expired(now) {
+                return None;
+            }
+            entry.access.touch();
+            Some(f(entry))
+        })?;
+        if found.is_some() {
+            return found;
+        }
+        //读锁释放后才能删除，期间键可能已被重新写入，所以删除前需要再次确认
+        let removed = self
+            .keyspace()
+            .storage
+            .del_if(key, &|entry| entry.is_expired(now));
+        if removed.is_some() {
+            self.expired(key);
+            self.invalidate(key);
+        }
+        None
+    }
+
+    ///设置键对应的值，清除原有的过期时间，并更新版本号
+    ///
+    /// 原有的值无论是什么类型都会被覆盖
+    pub(crate) fn set(&self, key: String, value: Value) {
+        let _guard = self.lock_shared();
+        let entry = self.new_entry(&key, value, None);
+        let key_ref = key.clone();
+        let old = self.keyspace().storage.set(key, entry);
+        if let Some(old) = &old {
+            self.reindex(&key_ref, old.expires_at, None);
+        }
+        self.wake(&key_ref);
+        self.invalidate(&key_ref);
+    }
+
+    ///在一次加锁中写入多个键，过期时间都设为expires_at，返回是否写入
+    ///
+    /// nx为true时只在所有键都不存在时写入，重复的键以最后一次的值为准。
+    /// 存储后端无法在一次加锁中覆盖所有的键时什么也不做并返回None，由调用方改为独占键空间后逐个写入
+    pub(crate) fn set_many(
+        &self,
+        pairs: Vec<(String, Value)>,
+        nx: bool,
+        expires_at: Option<u64>,
+    ) -> Option<bool> {
+        let _guard = self.lock_shared();
+        let now = now_millis();
+        let mut keys: Vec<String> = vec![];
+        let mut values: Vec<Option<Value>> = vec![];
+        let mut positions = HashMap::new();
+        for (key, value) in pairs {
+            match positions.get(&key) {
+                Some(&position) => values[position] = Some(value),
+                None => {
+                    positions.insert(key.clone(), keys.len());
+                    keys.push(key);
+                    values.push(Some(value));
+                }
+            }
+        }
+        let names: Vec<&str> = keys.iter().map(String::as_str).collect();
+        let mut written = false;
+        let locked = self.keyspace().storage.entries(&names, &mut |slots| {
+            if nx && slots.iter().flatten().any(|entry| !entry.is_expired(now)) {
+                return;
+            }
+            for ((key, slot), value) in names.iter().zip(slots.iter_mut()).zip(&mut values) {
+                let old = slot.as_ref().and_then(|old| old.expires_at);
+                self.reindex(key, old, expires_at);
+                if let Some(value) = value.take() {
+                    *slot = Some(self.new_entry(key, value, expires_at));
+                }
+                self.wake(key);
+            }
+            written = true;
+        });
+        if !locked {
+            return None;
+        }
+        if written {
+            for key in &names {
+                self.invalidate(key);
+            }
+        }
+        Some(written)
+    }
+
+    ///删除键，返回被删除的条目，已过期的键视为不存在
+    pub(crate) fn remove(&self, key: &str) -> Option<Entry> {
+        let _guard = self.lock_shared();
+        let now = now_millis();
+        let (key, entry) = self.keyspace().storage.del(key)?;
+        self.reindex(&key, entry.expires_at, None);
+        self.invalidate(&key);
+        if entry.is_expired(now) {
+            self.expired(&key);
+            return None;
+        }
+        Some(entry)
+    }
+
+    ///键是否存在
+    pub(crate) fn exists(&self, key: &str) -> bool {
+        self.read(key, |_| ()).is_some()
+    }
+
+    ///遍历所有未过期的键，收集f返回的结果
+    ///
+    /// 其他连接的写入可能穿插在遍历过程中，结果并不是某一时刻的快照
+    pub(crate) fn collect<R>(&self, mut f: impl FnMut(&str, &Entry) -> Option<R>) -> Vec<R> {
+        let _guard = self.lock_shared();
+        let now = now_millis();
+        let mut collected = vec![];
+        self.keyspace().storage.scan(&mut |key, entry| {
+            if !entry.is_expired(now) {
+                collected.extend(f(key, entry));
+            }
+            true
+        });
+        collected
+    }
+
+    ///从游标开始遍历一批未过期的键，以每个键调用f，返回下一个游标，为0时遍历结束
+    ///
+    /// 每批大约访问count个键，遍历期间一直存在的键至少返回一次
+    pub(crate) fn scan(&self, cursor: u64, count: usize, mut f: impl FnMut(&str, &Entry)) -> u64 {
+        let _guard = self.lock_shared();
+        let now = now_millis();
+        self.keyspace()
+            .storage
+            .scan_from(cursor, count, &mut |key, entry| {
+                if !entry.is_expired(now) {
+                    f(key, entry);
+                }
+            })
+    }
+
+    ///随机挑选一个未过期的键，键空间为空时返回None
+    ///
+    /// 需要遍历整个键空间，每个键被选中的概率相同
+    pub(crate) fn random_key(&self) -> Option<String> {
+        let mut seen = 0;
+        let mut chosen = None;
+        //蓄水池抽样，第n个键以1/n的概率替换已选中的键
+        self.collect(|key, _| {
+            seen += 1;
+            if random::below(seen) == 0 {
+                chosen = Some(key.to_string());
+            }
+            None::<()>
+        });
+        chosen
+    }
+
+    ///清空键空间，返回被移出的条目
+    ///
+    /// 条目只是被移出键空间，由调用方决定在哪里释放，以免释放大量条目时阻塞当前连接
+    pub(crate) fn flush(&self) -> Vec<Entry> {
+        let removed = self.atomically(|| self.clear());
+        self.shared.stats.changes.add(removed.len() as u64);
+        self.invalidate_all();
+        removed
+    }
+
+    ///清空所有逻辑数据库，返回被移出的条目
+    pub(crate) fn flush_all(&self) -> Vec<Entry> {
+        let removed: Vec<Entry> = self.atomically(|| {
+            (0..self.databases())
+                .filter_map(|index| self.select(index))
+                .flat_map(|db| db.clear())
+                .collect()
+        });
+        self.shared.stats.changes.add(removed.len() as u64);
+        self.invalidate_all();
+        removed
+    }
+
+    ///交换两个逻辑数据库的内容，编号超出范围时返回false
+    ///
+    /// 连接上已经选择的数据库编号不变，之后看到的是交换过来的内容。
+    /// 阻塞在这两个数据库上的客户端都会被唤醒重新检查
+    pub(crate) fn swap(&self, a: usize, b: usize) -> bool {
+        if a >= self.databases() || b >= self.databases() {
+            return false;
+        }
+        self.atomically(|| {
+            let layout = &self.shared.layout;
+            let physical = layout[a].load(Ordering::Relaxed);
+            layout[a].store(layout[b].load(Ordering::Relaxed), Ordering::Relaxed);
+            layout[b].store(physical, Ordering::Relaxed);
+            let waiters = self.shared.waiters.lock().unwrap();
+            for notify in [a, b]
+                .iter()
+                .flat_map(|index| waiters[*index].values())
+                .flatten()
+            {
+                notify.notify_one();
+            }
+        });
+        self.shared.stats.changes.incr();
+        self.invalidate_all();
+        true
+    }
+
+    ///未过期的键的数量
+    ///
+    /// 存储中的条目数减去已过期但还没有被删除的键。后者从过期索引中时间最早的一端找出，
+    /// 主动过期让这样的键保持很少，不需要遍历整个键空间
+    pub(crate) fn key_count(&self) -> usize {
+        let _guard = self.lock_shared();
+        let keyspace = self.keyspace();
+        let now = now_millis();
+        let elapsed: Vec<(u64, String)> = keyspace
+            .expirations
+            .lock()
+            .unwrap()
+            .range(..(now.saturating_add(1), String::new()))
+            .cloned()
+            .collect();
+        //索引中可能残留过时的记录，只计入过期时间与条目一致的键，同一个键不会被计入两次
+        let expired = elapsed
+            .iter()
+            .filter(|(at, key)| {
+                let mut expired = false;
+                keyspace.storage.get(key, &mut |entry| {
+                    expired = entry.expires_at == Some(*at);
+                });
+                expired
+            })
+            .count();
+        keyspace.storage.len().saturating_sub(expired)
+    }
+
+    ///在同一个分片锁内完成“读取当前值、校验、生成回复、修改”的过程
+    ///
+    /// GETDEL、GETSET、INCR这类先读后写的命令如果分两次加锁，中间就会出现其他连接插入修改的窗口。
+    /// f接收键当前的条目（不存在或已过期时为None），返回键的新状态与回复给客户端的内容
+    pub(crate) fn update<R>(
+        &self,
+        key: String,
+        f: impl FnOnce(Option<&Entry>) -> (Update, R),
+    ) -> R {
+        self.update_with(key, None, f)
+    }
+
+    ///与update相同，写入新值或修改过期时间时发出event对应的键空间事件，删除键时发出del事件
+    pub(crate) fn update_notify<R>(
+        &self,
+        key: String,
+        event: Event,
+        f: impl FnOnce(Option<&Entry>) -> (Update, R),
+    ) -> R {
+        self.update_with(key, Some(event), f)
+    }
+
+    fn update_with<R>(
+        &self,
+        key: String,
+        event: Option<Event>,
+        f: impl FnOnce(Option<&Entry>) -> (Update, R),
+    ) -> R {
+        let _guard = self.lock_shared();
+        let now = now_millis();
+        let name = key.clone();
+        //键已过期、写入了新值、删除了键
+        let (expired, written, removed, reply) =
+            self.keyspace().storage.update(key, |key, slot| {
+                let cur = match slot.as_mut() {
+                    Some(cur) => cur,
+                    None => {
+                        let (update, reply) = f(None);
+                        let written = matches!(update, Update::Set { .. });
+                        if let Update::Set { value, expires_at } = update {
+                            self.reindex(key, None, expires_at);
+                            self.wake(key);
+                            *slot = Some(self.new_entry(key, value, expires_at));
+                        }
+                        return (false, written, false, reply);
+                    }
+                };
+                let expired = cur.is_expired(now);
+                let old_expires_at = cur.expires_at;
+                let (update, reply) = f(Some(&*cur).filter(|_| !expired));
+                let (written, removed) = match update {
+                    Update::Keep if !expired => (false, false),
+                    Update::SetExpire(expires_at) if !expired => {
+                        self.reindex(key, old_expires_at, expires_at);
+                        cur.expires_at = expires_at;
+                        cur.version = self.next_version();
+                        cur.access.touch();
+                        (true, false)
+                    }
+                    Update::Keep | Update::SetExpire(_) | Update::Remove => {
+                        self.reindex(key, old_expires_at, None);
+                        *slot = None;
+                        (false, !expired)
+                    }
+                    Update::Set { value, expires_at } => {
+                        self.reindex(key, old_expires_at, expires_at);
+                        self.wake(key);
+                        *slot = Some(self.new_entry(key, value, expires_at));
+                        (true, false)
+                    }
+                };
+                (expired, written, removed, reply)
+            });
+        //事件在分片锁释放之后发布
+        if expired {
+            self.expired(&name);
+        }
+        if let Some(event) = event.filter(|_| written) {
+            self.notify(event.class, event.name, &name);
+        }
+        if removed && event.is_some() {
+            self.notify(Class::Generic, "del", &name);
+        }
+        if expired || written || removed {
+            self.invalidate(&name);
+        }
+        reply
+    }
+
+    ///在分片锁内原地修改键对应的字符串值，保留原有的过期时间
+    ///
+    /// 值没有被其他地方引用时，缓冲区直接交给f修改而不发生复制，适合SETBIT这类每次只改动少量字节的命令。
+    /// 键不存在或已过期时f拿到的是空缓冲区，修改后的值总会被写入。键存储的不是字符串时不调用f
+    pub(crate) fn modify<R>(
+        &self,
+        key: String,
+        f: impl FnOnce(&mut BytesMut) -> R,
+    ) -> Result<R, WrongType> {
+        let _guard = self.lock_shared();
+        let now = now_millis();
+        let name = key.clone();
+        let reply = self.keyspace().storage.update(key, |key, slot| {
+            let entry = match slot.as_mut() {
+                Some(entry) => entry,
+                None => {
+                    let mut buf = BytesMut::new();
+                    let reply = f(&mut buf);
+                    *slot = Some(self.new_entry(key, buf.freeze().into(), None));
+                    return Ok(reply);
+                }
+            };
+            if entry.is_expired(now) {
+                self.reindex(key, entry.expires_at, None);
+                entry.value = Value::String(Bytes::new());
+                entry.expires_at = None;
+            }
+            //以整数存储的值先换回字符串再修改
+            if let Value::Int(n) = entry.value {
+                entry.value = Value::String(Bytes::from(n.to_string()));
+            }
+            let data = match &mut entry.value {
+                Value::String(data) => data,
+                _ => return Err(WrongType),
+            };
+            let mut buf = BytesMut::from(std::mem::take(data));
+            let reply = f(&mut buf);
+            *data = buf.freeze();
+            entry.version = self.next_version();
+            entry.access.touch();
+            entry.size = footprint(key, &entry.value, SIZE_SAMPLES);
+            Ok(reply)
+        })?;
+        self.invalidate(&name);
+        Ok(reply)
+    }
+
+    ///在分片锁内原地修改键对应的值，保留原有的过期时间
+    ///
+    /// 列表、哈希这类集合每次只改动少数元素，用update整体替换的话每次都要复制整个集合。
+    /// f拿到键当前的值（不存在或已过期时为None），可以原地修改、写入新值或取走值以删除该键，
+    /// 同时返回是否做了修改与回复的内容，只有做了修改时才会更新版本号。
+    /// 修改后为空的集合会连同键一起删除
+    pub(crate) fn mutate<R>(
+        &self,
+        key: String,
+        f: impl FnOnce(&mut Option<Value>) -> (bool, R),
+    ) -> R {
+        self.mutate_with(key, None, f)
+    }
+
+    ///与mutate相同，做了修改时发出event对应的键空间事件，键因此被删除时再发出del事件
+    pub(crate) fn mutate_notify<R>(
+        &self,
+        key: String,
+        event: Event,
+        f: impl FnOnce(&mut Option<Value>) -> (bool, R),
+    ) -> R {
+        self.mutate_with(key, Some(event), f)
+    }
+
+    fn mutate_with<R>(
+        &self,
+        key: String,
+        event: Option<Event>,
+        f: impl FnOnce(&mut Option<Value>) -> (bool, R),
+    ) -> R {
+        let _guard = self.lock_shared();
+        let now = now_millis();
+        let name = key.clone();
+        //键已过期、做了修改、删除了键
+        let (expired, changed, removed, reply) =
+            self.keyspace().storage.update(key, |key, slot| {
+                let entry = match slot.as_mut() {
+                    Some(entry) => entry,
+                    None => {
+                        let mut value = None;
+                        let (changed, reply) = f(&mut value);
+                        if let Some(value) = value.filter(|value| !value.is_empty()) {
+                            self.wake(key);
+                            *slot = Some(self.new_entry(key, value, None));
+                        }
+                        return (false, changed, false, reply);
+                    }
+                };
+                let expired = entry.is_expired(now);
+                let mut value = if expired {
+                    None
+                } else {
+                    let placeholder = Value::String(Bytes::new());
+                    Some(std::mem::replace(&mut entry.value, placeholder))
+                };
+                let (changed, reply) = f(&mut value);
+                let removed = match value.filter(|value| !value.is_empty()) {
+                    None => {
+                        self.reindex(key, entry.expires_at, None);
+                        *slot = None;
+                        !expired
+                    }
+                    Some(value) => {
+                        //过期的键被重新写入时视为新键，不再带有过期时间
+                        if expired {
+                            self.reindex(key, entry.expires_at, None);
+                            entry.expires_at = None;
+                        }
+                        if changed || expired {
+                            self.wake(key);
+                            entry.version = self.next_version();
+                        }
+                        entry.size = footprint(key, &value, SIZE_SAMPLES);
+                        entry.value = value;
+                        entry.access.touch();
+                        false
+                    }
+                };
+                (expired, changed, removed, reply)
+            });
+        //事件在分片锁释放之后发布
+        if expired {
+            self.expired(&name);
+        }
+        if let Some(event) = event.filter(|_| changed) {
+            self.notify(event.class, event.name, &name);
+        }
+        if removed && event.is_some() {
+            self.notify(Class::Generic, "del", &name);
+        }
+        if expired || changed || removed {
+            self.invalidate(&name);
+        }
+        reply
+    }
+
+    ///登记一个等待keys中任意一个被写入的客户端
+    ///
+    /// 必须在第一次检查键之前登记，否则检查与登记之间的写入就会被错过
+    pub(crate) fn waiter(&self, keys: &[String]) -> Waiter {
+        let notify = Arc::new(Notify::new());
+        let mut waiters = self.shared.waiters.lock().unwrap();
+        for key in keys {
+            waiters[self.index]
+                .entry(key.clone())
+                .or_default()
+                .push(notify.clone());
+        }
+        self.shared.blocked.fetch_add(1, Ordering::SeqCst);
+        Waiter {
+            shared: self.shared.clone(),
+            index: self.index,
+            keys: keys.to_vec(),
+            notify,
+        }
+    }
+
+    ///订阅频道，返回接收该频道消息的接收端
+    ///
+    /// 接收端落后太多时会丢失最旧的消息，而不是让发布者等待
+    pub(crate) fn subscribe(&self, channel: String) -> broadcast::Receiver<Bytes> {
+        subscribe_channel(&self.shared.pub_sub, channel)
+    }
+
+    ///订阅分片频道，分片频道与普通频道同名时也互不相通
+    pub(crate) fn ssubscribe(&self, channel: String) -> broadcast::Receiver<Bytes> {
+        subscribe_channel(&self.shared.shard_pub_sub, channel)
+    }
+
+    ///按glob风格的模式订阅频道，接收端收到的是频道名与消息
+    pub(crate) fn psubscribe(&self, pattern: String) -> broadcast::Receiver<(String, Bytes)> {
+        let mut patterns = self.shared.patterns.lock().unwrap();
+        match patterns.get(&pattern) {
+            Some(sender) => sender.subscribe(),
+            None => {
+                let (sender, receiver) = broadcast::channel(PUB_SUB_CAPACITY);
+                patterns.insert(pattern, sender);
+                receiver
+            }
+        }
+    }
+
+    ///向频道发布消息，返回收到消息的订阅者数，按模式订阅的每个匹配的订阅者各计一次
+    pub(crate) fn publish(&self, channel: &str, message: Bytes) -> usize {
+        self.shared.publish(channel, message)
+    }
+
+    ///向分片频道发布消息，返回收到消息的订阅者数，模式订阅不会收到分片频道的消息
+    pub(crate) fn spublish(&self, channel: &str, message: Bytes) -> usize {
+        publish_channel(&self.shared.shard_pub_sub, channel, message)
+    }
+
+    ///进入监视模式，接收之后执行的每条命令
+    pub(crate) fn monitor(&self) -> broadcast::Receiver<String> {
+        self.shared.monitors.subscribe()
+    }
+
+    ///是否有连接处于监视模式
+    pub(crate) fn has_monitors(&self) -> bool {
+        self.shared.monitors.receiver_count() > 0
+    }
+
+    ///把执行的命令推送给处于监视模式的连接
+    pub(crate) fn feed_monitors(&self, line: String) {
+        let _ = self.shared.monitors.send(line);
+    }
+
+    ///发布键空间事件，notify-keyspace-events没有开启该类别时什么也不做
+    ///
+    /// 命令在写入成功之后调用，不能在update、mutate的闭包中调用
+    pub(crate) fn notify(&self, class: Class, event: &str, key: &str) {
+        self.shared.notify(self.index, class, event, key);
+    }
+
+    ///当前的配置
+    pub(crate) fn config(&self) -> RwLockReadGuard<'_, Config> {
+        self.shared.config.read().unwrap()
+    }
+
+    ///按max-reply-size累计回复大小的计数器，回复可能很大的命令在构造回复时使用
+    pub(crate) fn reply_limit(&self) -> ReplyLimit {
+        ReplyLimit::new(self.config().max_reply_size())
+    }
+
+    ///修改配置，f返回错误时配置保持不变
+    ///
+    /// 修改在配置的副本上进行，全部成功后才替换，需要立即生效的配置随后应用到对应的模块
+    pub(crate) fn update_config(
+        &self,
+        f: impl FnOnce(&mut Config) -> Result<(), ConfigError>,
+    ) -> Result<(), ConfigError> {
+        let mut config = self.shared.config.write().unwrap();
+        let mut updated = config.clone();
+        f(&mut updated)?;
+        self.shared.notifier.set_flags(updated.notify_flags());
+        self.shared
+            .slowlog
+            .configure(updated.slowlog_log_slower_than(), updated.slowlog_max_len());
+        self.shared
+            .latency
+            .configure(updated.latency_monitor_threshold());
+        self.shared
+            .protocol_trace
+            .store(updated.protocol_trace(), Ordering::Relaxed);
+        self.shared.clients.set_max(updated.max_clients());
+        self.shared
+            .idle_timeout
+            .store(updated.timeout(), Ordering::Relaxed);
+        self.shared.eviction.configure(&updated);
+        self.shared
+            .replication
+            .configure(updated.repl_backlog_size());
+        if updated.log_level() != config.log_level() {
+            logging::set_level(updated.log_level());
+        }
+        //编码的上限在进程内共享，与loglevel一样只在修改时同步
+        if encoding_limits(&updated) != encoding_limits(&config) {
+            limits::configure(&updated);
+        }
+        //requirepass就是default用户的密码，只在修改时同步，以免覆盖ACL SETUSER的修改
+        if updated.require_pass() != config.require_pass() {
+            let mut acl = self.shared.acl.write().unwrap();
+            acl.set_default_password(updated.require_pass());
+        }
+        *config = updated;
+        drop(config);
+        //开启AOF时要复制全部数据，在释放配置的锁之后进行
+        aof::configure(self);
+        master_link::configure(self);
+        Ok(())
+    }
+
+    ///当前的访问控制列表
+    pub(crate) fn acl(&self) -> RwLockReadGuard<'_, Acl> {
+        self.shared.acl.read().unwrap()
+    }
+
+    ///在线连接的登记表
+    pub(crate) fn clients(&self) -> &Clients {
+        &self.shared.clients
+    }
+
+    ///客户端缓存的追踪表
+    pub(crate) fn tracking(&self) -> &Tracking {
+        &self.shared.tracking
+    }
+
+    ///慢查询日志
+    pub(crate) fn slowlog(&self) -> &SlowLog {
+        &self.shared.slowlog
+    }
+
+    ///延迟监控
+    pub(crate) fn latency(&self) -> &LatencyMonitor {
+        &self.shared.latency
+    }
+
+    ///是否追踪连接收发的帧
+    pub(crate) fn protocol_trace(&self) -> bool {
+        self.shared.protocol_trace.load(Ordering::Relaxed)
+    }
+
+    ///键空间估算的内存占用（字节），包括所有逻辑数据库
+    pub(crate) fn used_memory(&self) -> usize {
+        let offset = self.shared.memory_offset.load(Ordering::Relaxed);
+        (self.estimated_memory() as i64)
+            .saturating_add(offset)
+            .max(0) as usize
+    }
+
+    ///把报告的内存占用设为bytes，之后随键的写入与删除在此基础上变化，bytes为0时恢复为估算值
+    ///
+    /// 只供测试使用，不需要真的分配大量内存就能触发淘汰与OOM
+    #[cfg(any(test, feature = "debug-commands"))]
+    pub(crate) fn set_memory_usage(&self, bytes: usize) {
+        let offset = match bytes {
+            0 => 0,
+            bytes => (bytes as i64).saturating_sub(self.estimated_memory() as i64),
+        };
+        self.shared.memory_offset.store(offset, Ordering::Relaxed);
+    }
+
+    //所有逻辑数据库的存储后端估算的内存占用之和
+    fn estimated_memory(&self) -> usize {
+        self.shared
+            .keyspaces
+            .iter()
+            .map(|keyspace| keyspace.storage.memory_usage())
+            .sum()
+    }
+
+    ///因存储后端的锁被占用而不得不等待的操作次数，包括所有逻辑数据库，持续偏高说明存在热点键
+    pub(crate) fn lock_contentions(&self) -> u64 {
+        self.shared
+            .keyspaces
+            .iter()
+            .map(|keyspace| keyspace.storage.contended())
+            .sum()
+    }
+
+    ///存储后端各个分片的统计，同一下标的分片在所有逻辑数据库中的统计相加
+    pub(crate) fn shard_stats(&self) -> Vec<ShardStats> {
+        let mut total: Vec<ShardStats> = vec![];
+        for keyspace in &self.shared.keyspaces {
+            for (index, shard) in keyspace.storage.shards().into_iter().enumerate() {
+                if index == total.len() {
+                    total.push(ShardStats::default());
+                }
+                let sum = &mut total[index];
+                sum.keys += shard.keys;
+                sum.memory += shard.memory;
+                sum.reads += shard.reads;
+                sum.writes += shard.writes;
+                sum.contended += shard.contended;
+            }
+        }
+        total
+    }
+
+    ///当前逻辑数据库中各类型的键数，下标与Value::type_index一致，包括已过期但尚未被删除的键
+    pub(crate) fn type_counts(&self) -> [usize; TYPE_NAMES.len()] {
+        let _guard = self.lock_shared();
+        self.keyspace().storage.type_counts()
+    }
+
+    ///所有逻辑数据库中各类型被删除的键数之和
+    pub(crate) fn type_deletions(&self) -> [u64; TYPE_NAMES.len()] {
+        let mut total = [0; TYPE_NAMES.len()];
+        for keyspace in &self.shared.keyspaces {
+            for (sum, deleted) in total.iter_mut().zip(keyspace.storage.deletions()) {
+                *sum += deleted;
+            }
+        }
+        total
+    }
+
+    ///当前逻辑数据库估算的内存占用（字节）
+    pub(crate) fn dataset_memory(&self) -> usize {
+        let _guard = self.lock_shared();
+        self.keyspace().storage.memory_usage()
+    }
+
+    ///内存占用超过maxmemory时按淘汰策略删除键，直到回到上限以内
+    ///
+    /// 在写命令执行之前调用，返回内存占用是否在上限以内。
+    /// noeviction策略或找不到可淘汰的键时返回false，调用方应拒绝会增加内存占用的命令
+    pub(crate) fn evict(&self) -> bool {
+        let eviction = &self.shared.eviction;
+        let limit = eviction.max_memory() as usize;
+        if limit == 0 || self.used_memory() <= limit {
+            return true;
+        }
+        let policy = eviction.policy();
+        if policy == Policy::NoEviction {
+            return false;
+        }
+        let started = Instant::now();
+        //淘汰以DEL传播，与写命令一样需要在顺序锁内进行
+        let fits = self.ordered(|| {
+            while self.used_memory() > limit {
+                if !self.shared.evict_one(policy, eviction.samples()) {
+                    return false;
+                }
+            }
+            true
+        });
+        self.shared
+            .latency
+            .record("eviction-cycle", started.elapsed());
+        fits
+    }
+
+    ///快照的保存状态
+    pub(crate) fn saves(&self) -> &SaveState {
+        &self.shared.saves
+    }
+
+    ///AOF
+    pub(crate) fn aof(&self) -> &Aof {
+        &self.shared.aof
+    }
+
+    ///复制的主节点一侧
+    pub(crate) fn replication(&self) -> &Replication {
+        &self.shared.replication
+    }
+
+    ///与主节点的复制连接
+    pub(crate) fn master_link(&self) -> &MasterLink {
+        &self.shared.master_link
+    }
+
+    ///集群模式下的节点与槽的分配，没有开启集群模式时为None
+    pub(crate) fn cluster(&self) -> Option<&Cluster> {
+        self.shared.cluster.get()
+    }
+
+    ///设置启动时载入的集群配置，只在启动时调用一次
+    pub(crate) fn set_cluster(&self, cluster: Cluster) {
+        let _ = self.shared.cluster.set(cluster);
+    }
+
+    ///是否需要传播写命令，开启了AOF或有从节点请求过同步时为true
+    pub(crate) fn is_propagating(&self) -> bool {
+        self.shared.is_propagating()
+    }
+
+    ///在顺序锁内执行f，需要传播写命令时同一时刻只有一个f在执行
+    pub(crate) fn ordered<R>(&self, f: impl FnOnce() -> R) -> R {
+        self.shared.order.run(|| self.is_propagating(), f)
+    }
+
+    ///独占顺序锁执行f，开始传播之前复制全部数据时使用
+    pub(crate) fn ordered_exclusively<R>(&self, f: impl FnOnce() -> R) -> R {
+        self.shared.order.exclusive(f)
+    }
+
+    ///传播在各自的逻辑数据库中执行的命令，必须在顺序锁内调用
+    ///
+    /// 多于一条时包装在MULTI与EXEC之间，AOF重放与从节点执行时同样整体执行
+    pub(crate) fn propagate(&self, commands: Vec<(usize, Vec<Bytes>)>) {
+        self.shared.propagate(commands);
+    }
+
+    ///要求关闭服务器，由Server::run完成关闭，关闭时按save保存快照
+    pub(crate) fn request_shutdown(&self, save: SaveOnExit) {
+        *self.shared.shutdown_request.lock().unwrap() = Some(save);
+        self.shared.shutdown_requested.notify_one();
+    }
+
+    ///等待SHUTDOWN命令要求关闭服务器，返回要求的保存方式
+    pub(crate) async fn shutdown_requested(&self) -> SaveOnExit {
+        loop {
+            if let Some(save) = *self.shared.shutdown_request.lock().unwrap() {
+                return save;
+            }
+            self.shared.shutdown_requested.notified().await;
+        }
+    }
+
+    ///连接空闲多久之后被关闭，None代表不限制
+    pub(crate) fn idle_timeout(&self) -> Option<Duration> {
+        match self.shared.idle_timeout.load(Ordering::Relaxed) {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        }
+    }
+
+    ///开启或关闭主动过期，关闭后过期的键只在被访问时删除
+    pub(crate) fn set_active_expire(&self, enabled: bool) {
+        self.shared.active_expire.store(enabled, Ordering::Relaxed);
+    }
+
+    ///修改访问控制列表
+    pub(crate) fn update_acl<T>(&self, f: impl FnOnce(&mut Acl) -> T) -> T {
+        f(&mut self.shared.acl.write().unwrap())
+    }
+
+    ///缓存脚本，返回脚本的SHA1摘要
+    pub(crate) fn load_script(&self, script: Bytes) -> String {
+        let sha = sha1::hex(&script);
+        let mut scripts = self.shared.scripts.lock().unwrap();
+        scripts.insert(sha.clone(), script);
+        sha
+    }
+
+    ///按SHA1摘要查找缓存的脚本，摘要不区分大小写
+    pub(crate) fn script(&self, sha: &str) -> Option<Bytes> {
+        let scripts = self.shared.scripts.lock().unwrap();
+        scripts.get(&sha.to_lowercase()).cloned()
+    }
+
+    ///清空脚本缓存
+    pub(crate) fn flush_scripts(&self) {
+        self.shared.scripts.lock().unwrap().clear();
+    }
+
+    ///正在执行的脚本，BUSY回复与SCRIPT KILL使用
+    pub(crate) fn running_script(&self) -> &Running {
+        &self.shared.running_script
+    }
+
+    ///独占整个键空间执行f
+    ///
+    /// MSETNX这类需要同时检查、修改多个键的操作，逐个加分片锁无法保证其他连接看不到中间状态。
+    /// f执行期间其他连接的所有键操作都会等待，当前线程上的键操作则照常进行，因此f必须是同步且短小的
+    pub(crate) fn atomically<R>(&self, f: impl FnOnce() -> R) -> R {
+        if EXCLUSIVE.with(Cell::get) {
+            return f();
+        }
+        //锁只保护()，持锁的线程panic时没有需要恢复的数据，忽略中毒继续使用，否则之后所有的键操作都会panic
+        let _guard = self
+            .shared
+            .keyspace_lock
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        //f发生panic时同样需要清除标记，否则该线程之后的操作都会跳过加锁
+        struct Reset;
+        impl Drop for Reset {
+            fn drop(&mut self) {
+                EXCLUSIVE.with(|exclusive| exclusive.set(false));
+            }
+        }
+        EXCLUSIVE.with(|exclusive| exclusive.set(true));
+        let _reset = Reset;
+        f()
+    }
+
+    ///获取键当前的版本号
+    ///
+    /// 键不存在时返回None，因此删除同样可以被调用方通过比较检测出来
+    pub(crate) fn version(&self, key: &str) -> Option<u64> {
+        self.read(key, |entry| entry.version)
+    }
+
+    ///记录一次读命令的查找结果
+    ///
+    /// 只有面向客户端的读命令才需要记录，内部的查找不计入命中率
+    pub(crate) fn record_lookup(&self, hit: bool) {
+        let stats = &self.shared.stats;
+        if hit {
+            stats.keyspace_hits.incr();
+        } else {
+            stats.keyspace_misses.incr();
+        }
+    }
+
+    ///运行期间的统计
+    pub(crate) fn stats(&self) -> &Stats {
+        &self.shared.stats
+    }
+
+    ///正在阻塞等待的客户端数
+    pub(crate) fn blocked_clients(&self) -> usize {
+        self.shared.blocked.load(Ordering::SeqCst)
+    }
+
+    ///有订阅者的频道数与模式数
+    pub(crate) fn pubsub_counts(&self) -> (usize, usize) {
+        let channels = self.shared.pub_sub.lock().unwrap();
+        let patterns = self.shared.patterns.lock().unwrap();
+        (
+            channels
+                .values()
+                .filter(|sender| sender.receiver_count() > 0)
+                .count(),
+            patterns
+                .values()
+                .filter(|sender| sender.receiver_count() > 0)
+                .count(),
+        )
+    }
+
+    ///带有过期时间的键数，以及它们平均的剩余生存时间（毫秒），没有这样的键时平均值为0
+    pub(crate) fn expires(&self) -> (usize, u64) {
+        let now = now_millis();
+        let ttls = self.collect(|_, entry| entry.expires_at.map(|at| at.saturating_sub(now)));
+        let avg = match ttls.len() {
+            0 => 0,
+            len => ttls.iter().sum::<u64>() / len as u64,
+        };
+        (ttls.len(), avg)
+    }
+
+    //唤醒阻塞在key上的客户端，由写入方在持有分片锁时调用
+    fn wake(&self, key: &str) {
+        if self.shared.blocked.load(Ordering::SeqCst) == 0 {
+            return;
+        }
+        let waiters = self.shared.waiters.lock().unwrap();
+        for notify in waiters[self.index].get(key).into_iter().flatten() {
+            notify.notify_one();
+        }
+    }
+
+    //移出键空间中的所有条目，必须在持有键空间的写锁时调用
+    fn clear(&self) -> Vec<Entry> {
+        let mut keys = vec![];
+        self.keyspace().storage.scan(&mut |key, _| {
+            keys.push(key.to_string());
+            true
+        });
+        let removed = keys
+            .iter()
+            .filter_map(|key| self.keyspace().storage.del(key))
+            .map(|(_, entry)| entry)
+            .collect();
+        self.keyspace().expirations.lock().unwrap().clear();
+        removed
+    }
+
+    //键过期被删除，计数并发布expired事件
+    fn expired(&self, key: &str) {
+        self.shared.expired(self.index, key);
+    }
+
+    //向追踪了key的连接发送失效消息，由写入方在修改之后调用
+    fn invalidate(&self, key: &str) {
+        self.shared.invalidate(key);
+    }
+
+    //数据库被清空或交换，向所有开启了追踪的连接发送失效消息
+    fn invalidate_all(&self) {
+        if !self.shared.tracking.is_active() {
+            return;
+        }
+        for id in self.shared.tracking.flush() {
+            if let Some(client) = self.shared.clients.get(id) {
+                client.invalidate(Invalidation::Flush);
+            }
+        }
+    }
+
+    //当前逻辑数据库的存储，必须在持有键空间锁时调用
+    fn keyspace(&self) -> &Keyspace {
+        let physical = self.shared.layout[self.index].load(Ordering::Relaxed);
+        &self.shared.keyspaces[physical]
+    }
+
+    //获取键空间的读锁，当前线程已持有写锁时无需再加锁
+    fn lock_shared(&self) -> Option<RwLockReadGuard<'_, ()>> {
+        if EXCLUSIVE.with(Cell::get) {
+            None
+        } else {
+            Some(
+                self.shared
+                    .keyspace_lock
+                    .read()
+                    .unwrap_or_else(PoisonError::into_inner),
+            )
+        }
+    }
+
+    //过期时间变化后同步更新过期索引
+    //
+    // 调用时持有键所在分片的锁，锁的获取顺序始终是先分片后索引，清理任务不会同时持有两者
+    fn reindex(&self, key: &str, old: Option<u64>, new: Option<u64>) {
+        if old == new {
+            return;
+        }
+        let mut expirations = self.keyspace().expirations.lock().unwrap();
+        if let Some(at) = old {
+            expirations.remove(&(at, key.to_string()));
+        }
+        if let Some(at) = new {
+            expirations.insert((at, key.to_string()));
+        }
+    }
+
+    //为key创建一个带有新版本号的条目
+    fn new_entry(&self, key: &str, value: Value, expires_at: Option<u64>) -> Entry {
+        Entry {
+            size: footprint(key, &value, SIZE_SAMPLES),
+            value,
+            version: self.next_version(),
+            expires_at,
+            access: Access::default(),
+        }
+    }
+
+    //生成下一个版本号
+    fn next_version(&self) -> u64 {
+        self.shared.version.fetch_add(1, Ordering::Relaxed) + 1
+    }
+}
+
+///阻塞命令在一组键上的等待登记
+///
+/// 登记期间这些键上的任何写入都会唤醒等待者，销毁时自动注销
+#[derive(Debug)]
+pub(crate) struct Waiter {
+    shared: Arc<Shared>,
+    //等待的键所在的逻辑数据库
+    index: usize,
+    keys: Vec<String>,
+    notify: Arc<Notify>,
+}
+
+impl Waiter {
+    ///等待任意一个键被写入
+    ///
+    /// 写入的不一定是等待者需要的内容，多个等待者也可能争抢同一次写入，被唤醒后需要重新检查
+    pub(crate) async fn wait(&self) {
+        self.notify.notified().await;
+    }
+}
+
+impl Drop for Waiter {
+    fn drop(&mut self) {
+        let mut guard = self.shared.waiters.lock().unwrap();
+        let waiters = &mut guard[self.index];
+        for key in &self.keys {
+            if let Some(list) = waiters.get_mut(key) {
+                list.retain(|notify| !Arc::ptr_eq(notify, &self.notify));
+                if list.is_empty() {
+                    waiters.remove(key);
+                }
+            }
+        }
+        self.shared.blocked.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+//订阅channels中的频道，频道还没有订阅者时创建
+fn subscribe_channel(
+    channels: &Mutex<HashMap<String, broadcast::Sender<Bytes>>>,
+    channel: String,
+) -> broadcast::Receiver<Bytes> {
+    let mut channels = channels.lock().unwrap();
+    match channels.get(&channel) {
+        Some(sender) => sender.subscribe(),
+        None => {
+            let (sender, receiver) = broadcast::channel(PUB_SUB_CAPACITY);
+            channels.insert(channel, sender);
+            receiver
+        }
+    }
+}
+
+//向channels中的频道发布消息，返回收到消息的订阅者数
+fn publish_channel(
+    channels: &Mutex<HashMap<String, broadcast::Sender<Bytes>>>,
+    channel: &str,
+    message: Bytes,
+) -> usize {
+    let mut channels = channels.lock().unwrap();
+    match channels.get(channel).map(|sender| sender.send(message)) {
+        Some(Ok(count)) => count,
+        //所有订阅者都已退订
+        Some(Err(_)) => {
+            channels.remove(channel);
+            0
+        }
+        None => 0,
+    }
+}
+
+impl Shared {
+    //向频道发布消息，返回收到消息的订阅者数，按模式订阅的每个匹配的订阅者各计一次
+    fn publish(&self, channel: &str, message: Bytes) -> usize {
+        let mut receivers = publish_channel(&self.pub_sub, channel, message.clone());
+        let mut patterns = self.patterns.lock().unwrap();
+        patterns.retain(|pattern, sender| {
+            if sender.receiver_count() == 0 {
+                return false;
+            }
+            if glob::matches(pattern.as_bytes(), channel.as_bytes()) {
+                if let Ok(count) = sender.send((channel.to_string(), message.clone())) {
+                    receivers += count;
+                }
+            }
+            true
+        });
+        receivers
+    }
+
+    //发布index号逻辑数据库中的键空间事件
+    fn notify(&self, index: usize, class: Class, event: &str, key: &str) {
+        let flags = self.notifier.flags();
+        if !flags.enabled(class) {
+            return;
+        }
+        for (channel, message) in flags.messages(index, event, key) {
+            self.publish(&channel, message);
+        }
+    }
+
+    //index号逻辑数据库中的键过期被删除，计数并发布expired事件
+    fn expired(&self, index: usize, key: &str) {
+        self.stats.expired_keys.incr();
+        self.notify(index, Class::Expired, "expired", key);
+    }
+
+    //key被修改之后调用，计入修改次数，并向追踪了key的连接发送失效消息
+    fn invalidate(&self, key: &str) {
+        self.stats.changes.incr();
+        if !self.tracking.is_active() {
+            return;
+        }
+        for id in self.tracking.invalidate(key) {
+            if let Some(client) = self.clients.get(id) {
+                client.invalidate(Invalidation::Key(Bytes::copy_from_slice(key.as_bytes())));
+            }
+        }
+    }
+
+    //在每个逻辑数据库中删除最多limit个已过期的键，返回本轮处理的过期记录数
+    fn purge_expired(&self, limit: usize) -> usize {
+        (0..self.keyspaces.len())
+            .map(|physical| self.purge_keyspace(physical, limit))
+            .sum()
+    }
+
+    //删除keyspaces中第physical个存储里最多limit个已过期的键
+    fn purge_keyspace(&self, physical: usize, limit: usize) -> usize {
+        let now = now_millis();
+        let keyspace = &self.keyspaces[physical];
+        let keys: Vec<String> = {
+            let mut expirations = keyspace.expirations.lock().unwrap();
+            let mut keys = Vec::new();
+            while keys.len() < limit {
+                match expirations.first() {
+                    Some((at, _)) if *at <= now => {}
+                    _ => break,
+                }
+                let (_, key) = expirations.pop_first().unwrap();
+                keys.push(key);
+            }
+            keys
+        };
+        //索引的锁已经释放，这里再逐个确认条目确实过期后删除
+        let _guard = self
+            .keyspace_lock
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        let index = self.logical(physical);
+        for key in &keys {
+            let removed = keyspace.storage.del_if(key, &|entry| entry.is_expired(now));
+            if removed.is_some() {
+                self.expired(index, key);
+                self.invalidate(key);
+            }
+        }
+        keys.len()
+    }
+
+    //从所有逻辑数据库中按策略抽样，淘汰其中最优先的一个键，找不到可淘汰的键时返回false
+    fn evict_one(&self, policy: Policy, samples: usize) -> bool {
+        let _guard = self
+            .keyspace_lock
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        //优先级、所在的存储与键
+        let mut best: Option<(u64, usize, String)> = None;
+        for (physical, keyspace) in self.keyspaces.iter().enumerate() {
+            for key in keyspace.sample(policy, samples) {
+                let rank = match keyspace
+                    .storage
+                    .read(&key, |entry| policy.rank(&entry.access, entry.expires_at))
+                {
+                    Some(rank) => rank,
+                    None => continue,
+                };
+                if !matches!(&best, Some((best, _, _)) if *best >= rank) {
+                    best = Some((rank, physical, key));
+                }
+            }
+        }
+        let (physical, key) = match best {
+            Some((_, physical, key)) => (physical, key),
+            None => return false,
+        };
+        let keyspace = &self.keyspaces[physical];
+        //抽样之后键可能已被其他连接删除，同样算作释放了内存
+        if let Some((key, entry)) = keyspace.storage.del(&key) {
+            if let Some(at) = entry.expires_at {
+                keyspace
+                    .expirations
+                    .lock()
+                    .unwrap()
+                    .remove(&(at, key.clone()));
+            }
+            self.stats.evicted_keys.incr();
+            let index = self.logical(physical);
+            self.notify(index, Class::Evicted, "evicted", &key);
+            self.invalidate(&key);
+            let del = vec![Bytes::from_static(b"DEL"), Bytes::from(key)];
+            self.propagate(vec![(index, del)]);
+        }
+        true
+    }
+
+    //开启了AOF或有从节点请求过同步
+    fn is_propagating(&self) -> bool {
+        self.aof.is_enabled() || self.replication.is_active()
+    }
+
+    //发送给从节点并追加到AOF
+    fn propagate(&self, commands: Vec<(usize, Vec<Bytes>)>) {
+        if commands.is_empty() {
+            return;
+        }
+        let transaction = commands.len() > 1;
+        self.replication.feed(&commands, transaction);
+        self.aof.append(&commands, transaction);
+    }
+
+    //keyspaces中第physical个存储对应的逻辑数据库编号，必须在持有键空间锁时调用
+    fn logical(&self, physical: usize) -> usize {
+        self.layout
+            .iter()
+            .position(|slot| slot.load(Ordering::Relaxed) == physical)
+            .unwrap_or(physical)
+    }
+
+    fn is_shutdown(&self) -> bool {
+        self.shutdown.load(Ordering::Acquire)
+    }
+}
+
+///后台定时任务
+///
+/// 与redis的serverCron一样，每秒hz次节拍，每次依次进行淘汰、空闲连接的检查与主动过期，直到数据库关闭。
+/// 只依靠写命令之前的淘汰与惰性删除的话，没有写入时超出的内存与再也不会被读取的键会一直占用内存
+async fn cron_task(db: Db, config: CronConfig) {
+    let shared = db.shared.clone();
+    let mut tick = tokio::time::interval(config.interval);
+    tick.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    loop {
+        tokio::select! {
+            _ = tick.tick() => {}
+            _ = shared.background_task.notified() => {}
+        }
+        if shared.is_shutdown() {
+            return;
+        }
+        db.evict();
+        if let Some(timeout) = db.idle_timeout() {
+            shared.clients.close_idle(timeout);
+        }
+        //一轮删满说明积压较多，让出执行权后立即继续。
+        //脚本执行期间键空间被独占，与redis一致暂停主动过期，以免阻塞工作线程
+        loop {
+            if !shared.active_expire.load(Ordering::Relaxed) || shared.running_script.is_running() {
+                break;
+            }
+            let started = Instant::now();
+            let purged = shared.purge_expired(config.sample_size);
+            shared.latency.record("expire-cycle", started.elapsed());
+            if purged < config.sample_size || shared.is_shutdown() {
+                break;
+            }
+            tokio::task::yield_now().await;
+        }
+    }
+}
+
+//各类型紧凑编码的上限，用于判断配置是否修改了其中之一
+fn encoding_limits(config: &Config) -> [usize; 5] {
+    [
+        config.zset_max_listpack_entries(),
+        config.zset_max_listpack_value(),
+        config.hash_max_listpack_entries(),
+        config.hash_max_listpack_value(),
+        config.set_max_intset_entries(),
+    ]
+}
+
+///key对应的条目估算占用的内存字节数，包括键、值与存储结构本身
+///
+/// 集合类型按前samples个元素推算，samples为0时统计全部元素
+pub(crate) fn footprint(key: &str, value: &Value, samples: usize) -> usize {
+    std::mem::size_of::<(String, Entry)>() + key.len() + value.memory(samples)
+}
+
+///当前的unix时间戳（毫秒）
+pub(crate) fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mutations_increment_the_version_and_reads_do_not() {
+        let db = Db::new(Config::default());
+        assert_eq!(db.version("key"), None);
+        db.set("key".to_string(), Bytes::from("a").into());
+        let first = db.version("key").unwrap();
+        for _ in 0..3 {
+            assert!(db.get("key").unwrap().is_some());
+            assert!(db.exists("key"));
+        }
+        assert_eq!(db.version("key"), Some(first));
+        db.set("key".to_string(), Bytes::from("b").into());
+        let second = db.version("key").unwrap();
+        assert!(second > first);
+        db.update("key".to_string(), |_| (Update::SetExpire(None), ()));
+        assert!(db.version("key").unwrap() > second);
+        //删除后版本号消失，WATCH同样能发现
+        db.remove("key");
+        assert_eq!(db.version("key"), None);
+    }
+
+    #[test]
+    fn hammering_one_key_is_counted_as_lock_contention() {
+        use crate::lib::cmd::Command;
+        use crate::lib::frame::Frame;
+        use std::sync::mpsc;
+        use std::thread;
+        use std::time::Duration;
+
+        let db = Db::new(Config::default());
+        assert_eq!(db.lock_contentions(), 0);
+        let incr = || Frame::Array(vec![Frame::Bulk("incr".into()), Frame::Bulk("hot".into())]);
+        let (entered, wait) = mpsc::channel();
+        //先占住热点键所在分片的锁一段时间，保证后面的命令一定要等待
+        let holder = {
+            let db = db.clone();
+            thread::spawn(move || {
+                db.update("hot".to_string(), |_| {
+                    entered.send(()).unwrap();
+                    thread::sleep(Duration::from_millis(50));
+                    (Update::Keep, ())
+                })
+            })
+        };
+        wait.recv().unwrap();
+        let workers: Vec<_> = (0..8)
+            .map(|_| {
+                let db = db.clone();
+                thread::spawn(move || {
+                    for _ in 0..500 {
+                        Command::from_frame(incr()).unwrap().apply_now(&db);
+                    }
+                })
+            })
+            .collect();
+        holder.join().unwrap();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        assert_eq!(db.get("hot").unwrap(), Some(Bytes::from("4000")));
+        assert!(db.lock_contentions() > 0);
+    }
+
+    #[test]
+    fn concurrent_updates_are_not_lost() {
+        use std::thread;
+
+        let db = Db::new(Config::default());
+        //每个线程在同一个键上反复“读取、加一、写回”，中间不能插入其他线程的修改
+        let workers: Vec<_> = (0..8)
+            .map(|_| {
+                let db = db.clone();
+                thread::spawn(move || {
+                    for _ in 0..1000 {
+                        db.update("counter".to_string(), |entry| {
+                            let n = match entry.map(|entry| &entry.value) {
+                                Some(Value::Int(n)) => *n,
+                                _ => 0,
+                            };
+                            let update = Update::Set {
+                                value: Value::Int(n + 1),
+                                expires_at: None,
+                            };
+                            (update, ())
+                        });
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        assert_eq!(db.get("counter").unwrap(), Some(Bytes::from("8000")));
+    }
+
+    #[test]
+    fn keyspace_is_usable_after_a_panic_while_held_exclusively() {
+        use std::panic::{self, AssertUnwindSafe};
+
+        let db = Db::new(Config::default());
+        let result = panic::catch_unwind(AssertUnwindSafe(|| db.atomically(|| panic!("boom"))));
+        assert!(result.is_err());
+        //键空间锁已中毒，之后的操作仍然照常加锁
+        db.set("key".to_string(), Bytes::from("value").into());
+        assert!(db.atomically(|| db.exists("key")));
+        assert_eq!(db.shared.purge_expired(20), 0);
+    }
+
+    //以给定的hz启动后台的定时任务
+    fn with_hz(hz: &str) -> DbDropGuard {
+        let mut config = Config::default();
+        config.set("hz", hz).unwrap();
+        DbDropGuard::new(config)
+    }
+
+    #[tokio::test]
+    async fn short_ttl_keys_are_expired_by_the_cron() {
+        let guard = with_hz("100");
+        let db = guard.db();
+        for i in 0..50 {
+            let key = format!("key:{i}");
+            db.set(key.clone(), Bytes::from("value").into());
+            db.update(key, |_| (Update::SetExpire(Some(now_millis() + 10)), ()));
+        }
+        assert_eq!(db.key_count(), 50);
+        //没有任何读取，键只能由定时任务删除
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(db.key_count(), 0);
+    }
+
+    #[test]
+    fn cron_config_follows_the_config() {
+        let mut config = Config::default();
+        let cron = CronConfig::new(&config);
+        assert_eq!(cron.interval, Duration::from_millis(100));
+        assert_eq!(cron.sample_size, 20);
+        config.set("hz", "50").unwrap();
+        config.set("active-expire-sample-size", "200").unwrap();
+        assert!(config.set("active-expire-sample-size", "0").is_err());
+        let cron = CronConfig::new(&config);
+        assert_eq!(cron.interval, Duration::from_millis(20));
+        assert_eq!(cron.sample_size, 200);
+    }
+
+    #[tokio::test]
+    async fn memory_over_the_limit_is_evicted_by_the_cron() {
+        let guard = with_hz("100");
+        let db = guard.db();
+        for i in 0..100 {
+            db.set(format!("key:{i}"), Bytes::from("value").into());
+        }
+        let limit = db.used_memory() / 2;
+        db.update_config(|config| {
+            config.set("maxmemory-policy", "allkeys-random")?;
+            config.set("maxmemory", &limit.to_string())
+        })
+        .unwrap();
+        //没有写命令触发淘汰，内存只能由定时任务降到上限以内
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(db.used_memory() <= limit);
+        assert!(db.key_count() < 100);
+    }
+}
@@ -0,0 +1,65 @@
+use crate::lib::conn::Connection;
+use crate::lib::frame::Frame;
+use crate::lib::Result;
+use bytes::Bytes;
+use std::io;
+use tokio::io::DuplexStream;
+use tokio::sync::mpsc;
+
+//内存管道每个方向上缓冲的字节数
+const PIPE_CAPACITY: usize = 64 * 1024;
+
+///嵌入式的客户端，不经过网络直接向同一进程中的服务器发送命令
+///
+/// 通过Server::client创建，每个客户端是服务器上一个独立的连接，有自己的认证状态、选择的数据库与事务，
+/// 与网络连接一样计入连接数并出现在CLIENT LIST中。服务器开始run之前创建的客户端也可以使用，
+/// 命令会一直等到服务器开始接受连接时才被执行。服务器关闭后再发送命令会返回错误
+#[derive(Debug)]
+pub struct EmbeddedClient {
+    conn: Connection<DuplexStream>,
+    //把管道的另一端交给服务器，用于打开新的连接
+    connector: mpsc::UnboundedSender<DuplexStream>,
+}
+
+impl EmbeddedClient {
+    ///打开一个新的连接，connector是服务器接受嵌入式连接的通道
+    pub(crate) fn open(connector: mpsc::UnboundedSender<DuplexStream>) -> EmbeddedClient {
+        let (client, server) = tokio::io::duplex(PIPE_CAPACITY);
+        //服务器已经关闭时管道的另一端被直接丢弃，之后的命令读到连接关闭
+        let _ = connector.send(server);
+        EmbeddedClient {
+            conn: Connection::new(client),
+            connector,
+        }
+    }
+
+    ///在同一个服务器上打开另一个独立的连接
+    pub fn connect(&self) -> EmbeddedClient {
+        EmbeddedClient::open(self.connector.clone())
+    }
+
+    ///发送一条命令并等待回复，命令执行失败时回复的是Frame::Error
+    ///
+    /// 连接已被关闭（QUIT、CLIENT KILL或服务器关闭）时返回Error::Io。
+    /// 订阅与客户端缓存的推送消息同样从这里读出，订阅之后应使用read_frame逐条读取
+    pub async fn call<A: AsRef<[u8]>>(&mut self, args: &[A]) -> Result<Frame> {
+        let args = args
+            .iter()
+            .map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_ref())))
+            .collect();
+        self.conn.write_frame(&Frame::Array(args)).await?;
+        self.read_frame().await
+    }
+
+    ///读取服务器发来的下一个帧，订阅之后用它接收消息
+    pub async fn read_frame(&mut self) -> Result<Frame> {
+        match self.conn.read_frame().await? {
+            Some(frame) => Ok(frame),
+            None => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "connection closed by server",
+            )
+            .into()),
+        }
+    }
+}
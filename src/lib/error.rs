@@ -0,0 +1,102 @@
+use crate::lib::frame::{Frame, FrameError};
+use crate::lib::parse::{ParseError, ERROR_CODES};
+use crate::lib::value::WrongType;
+use std::io;
+use tokio::task::JoinError;
+
+///大多数函数返回的错误
+///
+/// 按原因区分，既可以由库的使用者按变体分别处理，也可以通过Frame::from转换为回复给客户端的错误帧。
+/// 解析帧与命令的热路径上仍然使用FrameError与ParseError，它们在需要时再转换为这个类型
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    ///客户端发来的数据不符合协议，之后的字节已经无法分帧
+    #[error("{0}")]
+    Protocol(String),
+    ///读写套接字或文件失败
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    ///键上的值不是命令操作的类型
+    #[error("{}", WrongType)]
+    WrongType,
+    ///命令的参数不合法
+    #[error("{0}")]
+    Parse(String),
+    ///认证被拒绝
+    #[error("{0}")]
+    Auth(String),
+    ///配置文件或配置项不合法
+    #[error("{0}")]
+    Config(String),
+    ///其他错误
+    #[error("{0}")]
+    Other(String),
+}
+
+impl From<&str> for Error {
+    fn from(msg: &str) -> Error {
+        Error::Other(msg.to_string())
+    }
+}
+
+impl From<String> for Error {
+    fn from(msg: String) -> Error {
+        Error::Other(msg)
+    }
+}
+
+impl From<WrongType> for Error {
+    fn from(_: WrongType) -> Error {
+        Error::WrongType
+    }
+}
+
+///帧不完整或不合法都视为协议错误
+impl From<FrameError> for Error {
+    fn from(err: FrameError) -> Error {
+        match err {
+            FrameError::Other(err) => err,
+            err => Error::Protocol(err.to_string()),
+        }
+    }
+}
+
+impl From<ParseError> for Error {
+    fn from(err: ParseError) -> Error {
+        match err {
+            ParseError::Other(err) => err,
+            err => Error::Parse(err.to_string()),
+        }
+    }
+}
+
+impl From<JoinError> for Error {
+    fn from(err: JoinError) -> Error {
+        Error::Other(err.to_string())
+    }
+}
+
+///将错误转化为回复给客户端的错误帧
+///
+/// 协议错误统一带上Protocol error前缀，类型错误回复WRONGTYPE，
+/// 其余错误回复其携带的信息，没有错误码的信息补上ERR前缀
+impl From<Error> for Frame {
+    fn from(err: Error) -> Frame {
+        match err {
+            Error::WrongType => Frame::from(WrongType),
+            Error::Protocol(msg) if msg.starts_with("Protocol error") => {
+                Frame::Error(format!("ERR {}", msg))
+            }
+            Error::Protocol(msg) => Frame::Error(format!("ERR Protocol error: {}", msg)),
+            err => {
+                let msg = err.to_string();
+                let code = msg.split(' ').next().unwrap_or_default();
+                if ERROR_CODES.contains(&code) {
+                    Frame::Error(msg)
+                } else {
+                    Frame::Error(format!("ERR {}", msg))
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use crate::lib::config::Config;
+use crate::lib::random;
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+//新条目的LFU计数，避免刚写入的键立即被淘汰，与redis一致
+const LFU_INIT: u8 = 5;
+//LFU计数增长的对数因子，越大计数增长越慢
+const LFU_LOG_FACTOR: f64 = 10.0;
+//条目多久没有被访问时LFU计数减一（秒）
+const LFU_DECAY: u32 = 60;
+
+///内存达到上限时的淘汰策略，对应maxmemory-policy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Policy {
+    ///不淘汰，拒绝会增加内存占用的命令
+    NoEviction,
+    ///在所有键中淘汰最久没有被访问的
+    AllKeysLru,
+    ///在所有键中淘汰访问频率最低的
+    AllKeysLfu,
+    ///在所有键中随机淘汰
+    AllKeysRandom,
+    ///在带有过期时间的键中淘汰最久没有被访问的
+    VolatileLru,
+    ///在带有过期时间的键中淘汰访问频率最低的
+    VolatileLfu,
+    ///在带有过期时间的键中随机淘汰
+    VolatileRandom,
+    ///在带有过期时间的键中淘汰最快过期的
+    VolatileTtl,
+}
+
+//策略与配置中的名称
+const POLICIES: [(Policy, &str); 8] = [
+    (Policy::NoEviction, "noeviction"),
+    (Policy::AllKeysLru, "allkeys-lru"),
+    (Policy::AllKeysLfu, "allkeys-lfu"),
+    (Policy::AllKeysRandom, "allkeys-random"),
+    (Policy::VolatileLru, "volatile-lru"),
+    (Policy::VolatileLfu, "volatile-lfu"),
+    (Policy::VolatileRandom, "volatile-random"),
+    (Policy::VolatileTtl, "volatile-ttl"),
+];
+
+impl Policy {
+    ///按配置中的名称查找策略，未知的名称视为noeviction
+    pub(crate) fn parse(name: &str) -> Policy {
+        POLICIES
+            .iter()
+            .find(|(_, policy)| *policy == name)
+            .map_or(Policy::NoEviction, |(policy, _)| *policy)
+    }
+
+    ///是否只淘汰带有过期时间的键
+    pub(crate) fn is_volatile(self) -> bool {
+        matches!(
+            self,
+            Policy::VolatileLru
+                | Policy::VolatileLfu
+                | Policy::VolatileRandom
+                | Policy::VolatileTtl
+        )
+    }
+
+    ///候选键的淘汰优先级，越大越先被淘汰
+    pub(crate) fn rank(self, access: &Access, expires_at: Option<u64>) -> u64 {
+        match self {
+            Policy::AllKeysLru | Policy::VolatileLru => access.idle(),
+            Policy::AllKeysLfu | Policy::VolatileLfu => u64::from(u8::MAX - access.frequency()),
+            Policy::VolatileTtl => u64::MAX - expires_at.unwrap_or(u64::MAX),
+            Policy::NoEviction | Policy::AllKeysRandom | Policy::VolatileRandom => {
+                random::next_u64()
+            }
+        }
+    }
+
+    //在POLICIES中的下标
+    fn index(self) -> u8 {
+        POLICIES
+            .iter()
+            .position(|(policy, _)| *policy == self)
+            .unwrap_or(0) as u8
+    }
+}
+
+///内存上限与淘汰策略，修改配置时同步到这里
+#[derive(Debug)]
+pub(crate) struct Eviction {
+    //内存上限的字节数，为0时不限制
+    max_memory: AtomicU64,
+    //策略在POLICIES中的下标
+    policy: AtomicU8,
+    //每个逻辑数据库每轮抽样的键数
+    samples: AtomicUsize,
+}
+
+impl Eviction {
+    ///按配置创建
+    pub(crate) fn new(config: &Config) -> Eviction {
+        let eviction = Eviction {
+            max_memory: AtomicU64::new(0),
+            policy: AtomicU8::new(0),
+            samples: AtomicUsize::new(0),
+        };
+        eviction.configure(config);
+        eviction
+    }
+
+    ///同步修改后的配置
+    pub(crate) fn configure(&self, config: &Config) {
+        self.max_memory
+            .store(config.max_memory(), Ordering::Relaxed);
+        let policy = Policy::parse(config.max_memory_policy());
+        self.policy.store(policy.index(), Ordering::Relaxed);
+        self.samples
+            .store(config.max_memory_samples(), Ordering::Relaxed);
+    }
+
+    ///内存上限的字节数，为0时不限制
+    pub(crate) fn max_memory(&self) -> u64 {
+        self.max_memory.load(Ordering::Relaxed)
+    }
+
+    ///淘汰策略
+    pub(crate) fn policy(&self) -> Policy {
+        POLICIES[self.policy.load(Ordering::Relaxed) as usize].0
+    }
+
+    ///每个逻辑数据库每轮抽样的键数
+    pub(crate) fn samples(&self) -> usize {
+        self.samples.load(Ordering::Relaxed)
+    }
+}
+
+///条目的访问记录
+///
+/// LRU策略使用最近一次访问的时间，LFU策略使用对数增长的访问计数：
+/// 计数越大，再次访问时加一的概率越小，长时间没有访问时计数逐渐衰减。
+/// 读取时只持有分片的读锁，所以用原子变量记录
+#[derive(Debug)]
+pub(crate) struct Access {
+    //最近一次访问的unix时间戳（秒）
+    time: AtomicU32,
+    //LFU计数
+    counter: AtomicU8,
+}
+
+impl Default for Access {
+    fn default() -> Access {
+        Access {
+            time: AtomicU32::new(now_secs()),
+            counter: AtomicU8::new(LFU_INIT),
+        }
+    }
+}
+
+impl Clone for Access {
+    fn clone(&self) -> Access {
+        Access {
+            time: AtomicU32::new(self.time.load(Ordering::Relaxed)),
+            counter: AtomicU8::new(self.counter.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+impl Access {
+    ///记录一次访问
+    pub(crate) fn touch(&self) {
+        let counter = self.frequency();
+        self.counter.store(increment(counter), Ordering::Relaxed);
+        self.time.store(now_secs(), Ordering::Relaxed);
+    }
+
+    ///距离最近一次访问的秒数
+    pub(crate) fn idle(&self) -> u64 {
+        u64::from(now_secs().saturating_sub(self.time.load(Ordering::Relaxed)))
+    }
+
+    ///扣除衰减之后的LFU计数
+    pub(crate) fn frequency(&self) -> u8 {
+        let periods = self.idle() / u64::from(LFU_DECAY);
+        let counter = self.counter.load(Ordering::Relaxed);
+        counter.saturating_sub(periods.min(u64::from(u8::MAX)) as u8)
+    }
+}
+
+//以1/((counter-LFU_INIT)*LFU_LOG_FACTOR+1)的概率把计数加一
+fn increment(counter: u8) -> u8 {
+    if counter == u8::MAX {
+        return counter;
+    }
+    let base = f64::from(counter.saturating_sub(LFU_INIT));
+    let r = (random::next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+    if r < 1.0 / (base * LFU_LOG_FACTOR + 1.0) {
+        counter + 1
+    } else {
+        counter
+    }
+}
+
+//当前的unix时间戳（秒）
+fn now_secs() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as u32)
+        .unwrap_or(0)
+}
@@ -1,5 +1,7 @@
 use crate::lib;
-use bytes::{Buf, Bytes};
+use crate::lib::codec::{Encoder, Protocol};
+use atoi::FromRadix10SignedChecked;
+use bytes::{Buf, Bytes, BytesMut};
 use core::fmt;
 use std::fmt::{Debug, Display, Formatter};
 use std::io::Cursor;
@@ -8,7 +10,7 @@ use std::string::FromUtf8Error;
 
 /// 帧
 /// 用于和读取的字节进行中继
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Frame {
     ///简单字符串
     ///
@@ -20,18 +22,82 @@ pub enum Frame {
     Error(String),
     ///整型
     ///
-    /// 对于整数，回复的第一个字节是“：”，后续直接加数字
-    Integer(u64),
+    /// 对于整数，回复的第一个字节是“：”，后续直接加数字，可以为负数
+    Integer(i64),
     ///大容量字节
     ///
     /// 对于大容量字符串，回复的第一个字节是“$”，
     Bulk(Bytes),
     /// 空
     Null,
+    ///大数字
+    ///
+    /// RESP3新增，回复的第一个字节是“(”，后续为任意长度的十进制整数
+    BigNumber(String),
+    ///带格式的原样字符串
+    ///
+    /// RESP3新增，回复的第一个字节是“=”，格式与大容量字符串相同，内容以“txt:”或“mkd:”这样的格式前缀开头
+    Verbatim { format: [u8; 3], data: Bytes },
     ///数组
     ///
-    /// 对于数组，回复的第一个字节是“*”，格式为“${长度} {内容}”，长度为-1时代表为空
+    /// 对于数组，回复的第一个字节是“*”，格式为“${长度} {内容}”，元素可以是包括数组在内的任意帧。
+    /// 长度为-1的空数组解析为Null
     Array(Vec<Frame>),
+    ///键值对
+    ///
+    /// RESP3新增，回复的第一个字节是“%”，后续为键值对的个数，之后键与值交替排列。
+    /// 使用RESP2的连接上按键值交替排列的数组发送
+    Map(Vec<(Frame, Frame)>),
+    ///无序集合
+    ///
+    /// RESP3新增，回复的第一个字节是“~”，格式与数组相同，使用RESP2的连接上按数组发送
+    Set(Vec<Frame>),
+    ///浮点数
+    ///
+    /// RESP3新增，回复的第一个字节是“,”，使用RESP2的连接上按大容量字符串发送
+    Double(f64),
+    ///布尔值
+    ///
+    /// RESP3新增，回复为“#t”或“#f”，使用RESP2的连接上按整数1或0发送
+    Boolean(bool),
+    ///推送
+    ///
+    /// RESP3新增，回复的第一个字节是“>”，格式与数组相同，用于发布订阅这类不是对某个命令的回复的消息，
+    /// 使用RESP2的连接上按数组发送
+    Push(Vec<Frame>),
+}
+
+///单个数组帧默认允许包含的最大元素个数，与redis的默认值保持一致
+///
+/// 即使每个元素都很小，元素数量过多的命令同样可能耗尽内存，因此在分配之前进行拦截。
+/// 直接调用parse时预先分配的空间同样不超过这个数
+pub const MAX_ARRAY_LEN: usize = 1024 * 1024;
+
+///解析对端发来的帧时的上限
+///
+/// 长度前缀由对端控制，不加限制时一个声明了巨大长度的字符串或层层嵌套的数组就能让服务器一直缓冲下去，
+/// 超出任何一项上限都视为协议错误
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    ///单个大容量字符串的最大字节数
+    pub max_bulk_len: usize,
+    ///单个数组、集合或映射的最大元素个数
+    pub max_array_len: usize,
+    ///聚合类型的最大嵌套层数，不嵌套的数组为1层
+    pub max_depth: usize,
+    ///单个帧的最大字节数，帧尚未完整时缓冲的字节同样受此限制
+    pub max_frame_size: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_bulk_len: 512 * 1024 * 1024,
+            max_array_len: MAX_ARRAY_LEN,
+            max_depth: 128,
+            max_frame_size: 1024 * 1024 * 1024,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -48,100 +114,252 @@ impl Frame {
         Frame::Array(vec![])
     }
 
-    pub(crate) fn push_bulk(&mut self, bytes: Bytes) {
-        match self {
-            Frame::Array(vec) => vec.push(Frame::Bulk(bytes)),
-            _ => panic!("not an bulk array"),
-        }
+    ///把整个帧（包括嵌套的数组）按RESP3编码后追加到dst中
+    ///
+    /// 需要按连接的协议降级为RESP2时使用Encoder
+    pub fn encode(&self, dst: &mut BytesMut) {
+        let mut encoder = Encoder::new();
+        encoder.set_protocol(Protocol::Resp3);
+        encoder.encode(self, dst);
+    }
+
+    ///以redis-cli的风格输出便于阅读的多行文本
+    ///
+    /// 字符串加上引号并转义不可打印的字节，嵌套的数组逐层缩进并编号，用于调试与协议追踪
+    pub fn pretty(&self) -> String {
+        let mut out = String::new();
+        self.write_pretty(&mut out, 0);
+        out
     }
 
-    pub(crate) fn push_int(&mut self, value: u64) {
+    //indent为嵌套的元素换行后需要缩进的宽度
+    fn write_pretty(&self, out: &mut String, indent: usize) {
         match self {
-            Frame::Array(vec) => vec.push(Frame::Integer(value)),
-            _ => panic!(),
+            Frame::Simple(text) => out.push_str(text),
+            Frame::Error(msg) => out.push_str(&format!("(error) {}", msg)),
+            Frame::Integer(value) => out.push_str(&format!("(integer) {}", value)),
+            Frame::Bulk(value) => out.push_str(&format!("\"{}\"", value.escape_ascii())),
+            Frame::Null => out.push_str("(nil)"),
+            Frame::BigNumber(value) => out.push_str(&format!("(big number) {}", value)),
+            Frame::Verbatim { format, data } => out.push_str(&format!(
+                "(verbatim {}) \"{}\"",
+                format.escape_ascii(),
+                data.escape_ascii()
+            )),
+            Frame::Double(value) => out.push_str(&format!("(double) {}", value)),
+            Frame::Boolean(value) => out.push_str(&format!("({})", value)),
+            Frame::Array(items) | Frame::Push(items) => write_items(out, indent, items, ')'),
+            Frame::Set(items) => write_items(out, indent, items, '~'),
+            Frame::Map(pairs) if pairs.is_empty() => out.push_str("(empty hash)"),
+            Frame::Map(pairs) => {
+                let width = pairs.len().to_string().len();
+                for (i, (key, value)) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        out.push('\n');
+                        out.push_str(&" ".repeat(indent));
+                    }
+                    let prefix = format!("{:>width$}# ", i + 1, width = width);
+                    out.push_str(&prefix);
+                    //键通常只有一行，值换行后与键之后的位置对齐
+                    let key = format!("{} => ", key.pretty());
+                    out.push_str(&key);
+                    value.write_pretty(out, indent + prefix.len() + key.chars().count());
+                }
+            }
         }
     }
 
-    ///查看是否可以将流中的数据转化为帧
+    ///字节是否是RESP中某种帧的类型标记，不是时数据按内联命令解析
+    pub(crate) fn is_type_marker(byte: u8) -> bool {
+        b"+-:$*_,#%~>(=".contains(&byte)
+    }
+
+    ///查看是否可以将流中的数据转化为帧，使用默认的上限
     pub fn check(src: &mut Cursor<&[u8]>) -> Result<(), FrameError> {
-        match get_u8(src)? {
-            b'+' => {
-                get_line(src)?;
-                Ok(())
-            }
-            b'-' => {
-                get_line(src)?;
-                Ok(())
+        Frame::check_limited(src, &Limits::default())
+    }
+
+    ///查看是否可以将流中的数据转化为帧，超出limits时返回协议错误
+    ///
+    /// 长度前缀一经读出就与上限比较，不必等到字节到齐
+    pub fn check_limited(src: &mut Cursor<&[u8]>, limits: &Limits) -> Result<(), FrameError> {
+        check_frame(src, limits, 0)
+    }
+
+    pub fn parse(src: &mut Cursor<&[u8]>) -> Result<Frame, FrameError> {
+        parse_frame(src, None)
+    }
+
+    ///解析src开头的一个帧，不短于min字节的大容量字符串直接引用src中的内容而不复制
+    pub(crate) fn parse_shared(src: &Bytes, min: usize) -> Result<Frame, FrameError> {
+        parse_frame(&mut Cursor::new(&src[..]), Some((src, min)))
+    }
+}
+
+//解析一个帧，shared为src的内容所在的Bytes与可以直接引用的最短长度，为None时总是复制
+fn parse_frame(
+    src: &mut Cursor<&[u8]>,
+    shared: Option<(&Bytes, usize)>,
+) -> Result<Frame, FrameError> {
+    match get_u8(src)? {
+        b'+' => {
+            let text = get_line(src)?.to_vec();
+            let text = String::from_utf8(text)?;
+            Ok(Frame::Simple(text))
+        }
+        b'-' => {
+            let line = get_line(src)?.to_vec();
+            let text = String::from_utf8(line)?;
+            Ok(Frame::Error(text))
+        }
+        b':' => {
+            let num = get_signed_decimal(src)?;
+            Ok(Frame::Integer(num))
+        }
+        b'(' => {
+            let line = get_line(src)?.to_vec();
+            let text = String::from_utf8(line)?;
+            Ok(Frame::BigNumber(text))
+        }
+        b'=' => {
+            let size: usize = get_decimal(src)?.try_into()?;
+            let total = with_crlf(size)?;
+            if src.remaining() < total {
+                return Err(FrameError::Incomplete);
             }
-            b':' => {
-                get_decimal(src)?;
-                Ok(())
+            //内容至少包含三个字节的格式与一个冒号
+            let chunk = &src.chunk()[..size];
+            if size < 4 || chunk[3] != b':' {
+                return Err("Protocol error: invalid verbatim string".into());
             }
-            b'$' => {
-                if peek_u8(src)? == b'-' {
-                    skip(src, 4_usize)
-                } else {
-                    let len: usize = get_decimal(src)?.try_into()?;
-                    skip(src, len + 2)
+            let format = [chunk[0], chunk[1], chunk[2]];
+            let data = Bytes::copy_from_slice(&chunk[4..]);
+            skip(src, total)?;
+            Ok(Frame::Verbatim { format, data })
+        }
+        b'$' => {
+            let flag = peek_u8(src)?;
+            if flag == b'-' {
+                get_null(src, "Protocol error: invalid bulk length")?;
+                Ok(Frame::Null)
+            } else {
+                let size: usize = get_decimal(src)?.try_into()?;
+                let total = with_crlf(size)?;
+                if src.remaining() < total {
+                    return Err(FrameError::Incomplete);
                 }
+                let start = src.position() as usize;
+                let data = match shared {
+                    Some((bytes, min)) if size >= min => bytes.slice(start..start + size),
+                    _ => Bytes::copy_from_slice(&src.chunk()[..size]),
+                };
+                skip(src, total)?;
+                Ok(Frame::Bulk(data))
             }
-            b'*' => {
-                let len: usize = get_decimal(src)?.try_into()?;
-                for _ in 0..len {
-                    Frame::check(src)?;
-                }
-                Ok(())
+        }
+        b'*' => {
+            //空数组与空的大容量字符串一样解析为Null
+            if peek_u8(src)? == b'-' {
+                get_null(src, "Protocol error: invalid multibulk length")?;
+                return Ok(Frame::Null);
+            }
+            let size: usize = get_decimal(src)?.try_into()?;
+            let mut vec = Vec::with_capacity(size.min(MAX_ARRAY_LEN));
+            for _ in 0..size {
+                let frame = parse_frame(src, shared)?;
+                vec.push(frame);
+            }
+            Ok(Frame::Array(vec))
+        }
+        marker @ (b'~' | b'>') => {
+            let size: usize = get_decimal(src)?.try_into()?;
+            let mut vec = Vec::with_capacity(size.min(MAX_ARRAY_LEN));
+            for _ in 0..size {
+                vec.push(parse_frame(src, shared)?);
+            }
+            if marker == b'~' {
+                Ok(Frame::Set(vec))
+            } else {
+                Ok(Frame::Push(vec))
+            }
+        }
+        b'%' => {
+            let size: usize = get_decimal(src)?.try_into()?;
+            let mut pairs = Vec::with_capacity(size.min(MAX_ARRAY_LEN));
+            for _ in 0..size {
+                pairs.push((parse_frame(src, shared)?, parse_frame(src, shared)?));
             }
-            actual => Err(format!("校验发生错误，错误内容：{}", actual).into()),
+            Ok(Frame::Map(pairs))
+        }
+        b'_' => {
+            get_empty_line(src)?;
+            Ok(Frame::Null)
         }
+        b',' => Ok(Frame::Double(get_double(src)?)),
+        b'#' => Ok(Frame::Boolean(get_boolean(src)?)),
+        actual => Err(unexpected(actual)),
     }
+}
 
-    pub fn parse(src: &mut Cursor<&[u8]>) -> Result<Frame, FrameError> {
-        match get_u8(src)? {
-            b'+' => {
-                let text = get_line(src)?.to_vec();
-                let text = String::from_utf8(text)?;
-                Ok(Frame::Simple(text))
-            }
-            b'-' => {
-                let line = get_line(src)?.to_vec();
-                let text = String::from_utf8(line)?;
-                Ok(Frame::Simple(text))
+//按limits校验一个帧，depth为所在的嵌套层数
+fn check_frame(src: &mut Cursor<&[u8]>, limits: &Limits, depth: usize) -> Result<(), FrameError> {
+    match get_u8(src)? {
+        b'+' | b'-' | b'(' => {
+            get_line(src)?;
+            Ok(())
+        }
+        b':' => {
+            get_signed_decimal(src)?;
+            Ok(())
+        }
+        b'$' if peek_u8(src)? == b'-' => get_null(src, "Protocol error: invalid bulk length"),
+        b'$' | b'=' => {
+            let len: usize = get_decimal(src)?.try_into()?;
+            if len > limits.max_bulk_len {
+                return Err("Protocol error: invalid bulk length".into());
             }
-            b':' => {
-                let num = get_decimal(src)?;
-                Ok(Frame::Integer(num))
+            let total = with_crlf(len)?;
+            check_frame_size(src, total, limits)?;
+            skip(src, total)
+        }
+        b'*' if peek_u8(src)? == b'-' => get_null(src, "Protocol error: invalid multibulk length"),
+        marker @ (b'*' | b'~' | b'>' | b'%') => {
+            if depth >= limits.max_depth {
+                return Err("Protocol error: too deeply nested aggregate".into());
             }
-            b'$' => {
-                let flag = peek_u8(src)?;
-                if flag == b'-' {
-                    let line = get_line(src)?;
-                    if line == b"-1" {
-                        return Err("非法协议，大容量字符串长度为-1以外负数".into());
-                    }
-                    Ok(Frame::Null)
-                } else {
-                    let size: usize = get_decimal(src)?.try_into()?;
-                    if src.remaining() < size + 2 {
-                        return Err(FrameError::Incomplete);
-                    }
-                    let data = Bytes::copy_from_slice(&src.chunk()[..size]);
-                    skip(src, size + 2)?;
-                    Ok(Frame::Bulk(data))
-                }
+            let len: usize = get_decimal(src)?.try_into()?;
+            let len = if marker == b'%' {
+                len.saturating_mul(2)
+            } else {
+                len
+            };
+            if len > limits.max_array_len {
+                return Err("Protocol error: invalid multibulk length".into());
             }
-            b'*' => {
-                let size = get_decimal(src)?;
-                let mut vec = Vec::with_capacity(size as usize);
-                for _ in 0..size {
-                    let frame = Frame::parse(src)?;
-                    vec.push(frame);
-                }
-                todo!()
+            for _ in 0..len {
+                check_frame(src, limits, depth + 1)?;
             }
-            _ => Err("解析发生错误".into()),
+            Ok(())
         }
+        b'_' => get_empty_line(src),
+        b',' => {
+            get_double(src)?;
+            Ok(())
+        }
+        b'#' => {
+            get_boolean(src)?;
+            Ok(())
+        }
+        actual => Err(unexpected(actual)),
+    }
+}
+
+//帧读到当前位置再加上声明的len个字节后是否超出上限
+fn check_frame_size(src: &Cursor<&[u8]>, len: usize, limits: &Limits) -> Result<(), FrameError> {
+    if (src.position() as usize).saturating_add(len) > limits.max_frame_size {
+        return Err("Protocol error: too big request".into());
     }
+    Ok(())
 }
 
 ///查看下一个u8的数值
@@ -163,7 +381,7 @@ fn get_u8(src: &mut Cursor<&[u8]>) -> Result<u8, FrameError> {
 
 ///跳过range个字节
 fn skip(src: &mut Cursor<&[u8]>, range: usize) -> Result<(), FrameError> {
-    if !src.remaining() < range {
+    if src.remaining() < range {
         return Err(FrameError::Incomplete);
     }
     src.advance(range);
@@ -173,8 +391,9 @@ fn skip(src: &mut Cursor<&[u8]>, range: usize) -> Result<(), FrameError> {
 ///获取一整行
 fn get_line<'a>(src: &mut Cursor<&'a [u8]>) -> Result<&'a [u8], FrameError> {
     let start = src.position() as usize;
-    let end = src.get_ref().len() as usize;
-    for i in start..end {
+    let end = src.get_ref().len();
+    //最后一个字节之后没有'\n'可以比较，不必检查
+    for i in start..end.saturating_sub(1) {
         if src.get_ref()[i] == b'\r' && src.get_ref()[i + 1] == b'\n' {
             src.set_position((i + 2) as u64);
             return Ok(&src.get_ref()[start..i]);
@@ -183,13 +402,96 @@ fn get_line<'a>(src: &mut Cursor<&'a [u8]>) -> Result<&'a [u8], FrameError> {
     Err(FrameError::Incomplete)
 }
 
-/// 解析并获取下一个u64
+/// 解析并获取下一个长度值
+///
+/// 大容量字符串与数组的长度不允许为负数，-1只能通过get_null识别
 fn get_decimal(src: &mut Cursor<&[u8]>) -> Result<u64, FrameError> {
-    use atoi::atoi;
+    let line = get_line(src)?;
+    if line.first() == Some(&b'-') {
+        return Err("Protocol error: invalid length, negative value".into());
+    }
+    parse_decimal(line)
+}
 
+/// 解析并获取下一个有符号整数，用于整型帧
+fn get_signed_decimal(src: &mut Cursor<&[u8]>) -> Result<i64, FrameError> {
     let line = get_line(src)?;
+    parse_decimal(line)
+}
+
+/// 大容量字符串的内容加上结尾的\r\n的长度，长度前缀大到溢出时返回协议错误
+fn with_crlf(size: usize) -> Result<usize, FrameError> {
+    size.checked_add(2)
+        .ok_or_else(|| "Protocol error: invalid bulk length".into())
+}
 
-    atoi::<u64>(line).ok_or_else(|| "从流中获取u64失败".into())
+/// 校验并跳过空的大容量字符串或空数组，长度只能为-1，其他负数以err报错
+fn get_null(src: &mut Cursor<&[u8]>, err: &str) -> Result<(), FrameError> {
+    if get_line(src)? != b"-1" {
+        return Err(err.into());
+    }
+    Ok(())
+}
+
+//不认识的类型标记，与redis一致按期望大容量字符串报错，不可打印的字节转义后输出
+fn unexpected(actual: u8) -> FrameError {
+    let actual = std::ascii::escape_default(actual);
+    format!("Protocol error: expected '$', got '{}'", actual).into()
+}
+
+/// 校验并跳过RESP3的Null，之后只能是空行
+fn get_empty_line(src: &mut Cursor<&[u8]>) -> Result<(), FrameError> {
+    if !get_line(src)?.is_empty() {
+        return Err("Protocol error: invalid null".into());
+    }
+    Ok(())
+}
+
+/// 解析并获取下一个浮点数，允许inf、-inf与nan
+fn get_double(src: &mut Cursor<&[u8]>) -> Result<f64, FrameError> {
+    let line = get_line(src)?;
+    std::str::from_utf8(line)
+        .ok()
+        .and_then(|text| text.parse().ok())
+        .ok_or_else(|| "Protocol error: invalid double".into())
+}
+
+/// 解析并获取下一个布尔值，只能为t或f
+fn get_boolean(src: &mut Cursor<&[u8]>) -> Result<bool, FrameError> {
+    match get_line(src)? {
+        b"t" => Ok(true),
+        b"f" => Ok(false),
+        _ => Err("Protocol error: invalid boolean".into()),
+    }
+}
+
+//按pretty的格式输出数组与集合的元素，marker为编号之后的标记
+fn write_items(out: &mut String, indent: usize, items: &[Frame], marker: char) {
+    if items.is_empty() {
+        out.push_str("(empty array)");
+        return;
+    }
+    let width = items.len().to_string().len();
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+            out.push_str(&" ".repeat(indent));
+        }
+        let prefix = format!("{:>width$}{} ", i + 1, marker, width = width);
+        out.push_str(&prefix);
+        item.write_pretty(out, indent + prefix.len());
+    }
+}
+
+/// 将整行解析为整数
+///
+/// 整行必须全部为数字（允许一个前导符号），前后的空白字符与多余的字节都视为协议错误
+fn parse_decimal<T: FromRadix10SignedChecked>(line: &[u8]) -> Result<T, FrameError> {
+    let (value, used) = T::from_radix_10_signed_checked(line);
+    match value {
+        Some(value) if used == line.len() && !line.is_empty() => Ok(value),
+        _ => Err("Protocol error: invalid integer".into()),
+    }
 }
 
 impl Display for Frame {
@@ -210,13 +512,34 @@ impl Display for Frame {
 
             Frame::Null => Display::fmt("(nil)", f),
 
-            Frame::Array(vec) => {
+            Frame::BigNumber(value) => Display::fmt(value, f),
+
+            Frame::Verbatim { data, .. } => match str::from_utf8(data) {
+                Ok(text) => Display::fmt(text, f),
+                Err(_) => write!(f, "{:?}", data),
+            },
+
+            Frame::Array(vec) | Frame::Set(vec) | Frame::Push(vec) => {
                 for cur in vec.iter().skip(1) {
                     write!(f, " ")?;
                     Display::fmt(cur, f)?;
                 }
                 Ok(())
             }
+
+            Frame::Map(pairs) => {
+                for (i, (key, value)) in pairs.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(f, "{} {}", key, value)?;
+                }
+                Ok(())
+            }
+
+            Frame::Double(value) => Display::fmt(value, f),
+
+            Frame::Boolean(value) => Display::fmt(value, f),
         }
     }
 }
@@ -231,37 +554,315 @@ impl PartialEq<&str> for Frame {
     }
 }
 
+///回复超出max-reply-size时的错误
+pub(crate) const REPLY_TOO_LARGE: &str = "ERR reply too large";
+
+///构造回复时累计的大小
+///
+/// 元素可能很多的命令每加入一个元素就计入它编码后的大小，超出上限时放弃构造，改为回复REPLY_TOO_LARGE，
+/// 而不是先在内存中构造出整个回复
+#[derive(Debug)]
+pub(crate) struct ReplyLimit {
+    used: usize,
+    max: usize,
+}
+
+impl ReplyLimit {
+    ///允许max字节的回复
+    pub(crate) fn new(max: usize) -> ReplyLimit {
+        ReplyLimit { used: 0, max }
+    }
+
+    ///计入一个长度为len的大容量字符串，累计超出上限时返回false
+    pub(crate) fn add(&mut self, len: usize) -> bool {
+        //$、最多20位的长度与两个\r\n
+        self.used = self.used.saturating_add(len + 24);
+        self.used <= self.max
+    }
+}
+
+///数组回复的构造器
+///
+/// 元素可以是任何能转换为Frame的值，逐个追加之后得到Frame::Array
+#[derive(Debug, Default)]
+pub struct ArrayBuilder {
+    items: Vec<Frame>,
+}
+
+impl ArrayBuilder {
+    ///创建一个空的构造器
+    pub fn new() -> ArrayBuilder {
+        ArrayBuilder::default()
+    }
+
+    ///追加一个元素
+    pub fn push(&mut self, item: impl Into<Frame>) -> &mut ArrayBuilder {
+        self.items.push(item.into());
+        self
+    }
+
+    ///得到由已追加的元素组成的数组
+    pub fn build(self) -> Frame {
+        Frame::Array(self.items)
+    }
+}
+
+///字符串作为大容量字符串回复
+///
+/// 转换得到的总是Frame::Bulk，因为字符串通常是键上的数据，可能包含换行，不能作为简单字符串发送。
+/// "OK"这样的状态回复不能用into得到，仍然要直接写Frame::Simple
+impl From<&str> for Frame {
+    fn from(src: &str) -> Frame {
+        Frame::Bulk(Bytes::copy_from_slice(src.as_bytes()))
+    }
+}
+
+impl From<String> for Frame {
+    fn from(src: String) -> Frame {
+        Frame::Bulk(Bytes::from(src))
+    }
+}
+
+impl From<Bytes> for Frame {
+    fn from(src: Bytes) -> Frame {
+        Frame::Bulk(src)
+    }
+}
+
+impl From<i64> for Frame {
+    fn from(src: i64) -> Frame {
+        Frame::Integer(src)
+    }
+}
+
+impl From<Vec<Frame>> for Frame {
+    fn from(src: Vec<Frame>) -> Frame {
+        Frame::Array(src)
+    }
+}
+
+///None转换为空回复
+impl From<Option<Bytes>> for Frame {
+    fn from(src: Option<Bytes>) -> Frame {
+        src.map_or(Frame::Null, Frame::Bulk)
+    }
+}
+
+///简单字符串、大容量字符串与原样字符串的内容，不是合法的UTF-8时返回错误
+impl TryFrom<Frame> for String {
+    type Error = FrameError;
+
+    fn try_from(frame: Frame) -> Result<String, FrameError> {
+        match frame {
+            Frame::Simple(text) => Ok(text),
+            Frame::Bulk(data) | Frame::Verbatim { data, .. } => String::from_utf8(data.to_vec())
+                .map_err(|_| mismatch("expected a UTF-8 string reply")),
+            _ => Err(mismatch("expected a string reply")),
+        }
+    }
+}
+
+///整数回复的值
+impl TryFrom<Frame> for i64 {
+    type Error = FrameError;
+
+    fn try_from(frame: Frame) -> Result<i64, FrameError> {
+        match frame {
+            Frame::Integer(value) => Ok(value),
+            _ => Err(mismatch("expected an integer reply")),
+        }
+    }
+}
+
+///简单字符串、大容量字符串与原样字符串的字节
+impl TryFrom<Frame> for Bytes {
+    type Error = FrameError;
+
+    fn try_from(frame: Frame) -> Result<Bytes, FrameError> {
+        match frame {
+            Frame::Simple(text) => Ok(Bytes::from(text)),
+            Frame::Bulk(data) | Frame::Verbatim { data, .. } => Ok(data),
+            _ => Err(mismatch("expected a string reply")),
+        }
+    }
+}
+
+//回复的类型与期望的不同，帧本身是合法的，所以不是协议错误
+fn mismatch(msg: &str) -> FrameError {
+    FrameError::Other(lib::Error::Other(msg.to_string()))
+}
+
 impl From<String> for FrameError {
     fn from(src: String) -> FrameError {
-        FrameError::Other(src.into())
+        FrameError::Other(lib::Error::Protocol(src))
     }
 }
 
 impl From<&str> for FrameError {
     fn from(src: &str) -> FrameError {
-        FrameError::Other(src.into())
+        FrameError::Other(lib::Error::Protocol(src.to_string()))
     }
 }
 
 impl From<TryFromIntError> for FrameError {
     fn from(err: TryFromIntError) -> Self {
-        FrameError::Other(err.into())
+        FrameError::Other(lib::Error::Protocol(err.to_string()))
     }
 }
 
 impl From<FromUtf8Error> for FrameError {
     fn from(err: FromUtf8Error) -> Self {
-        FrameError::Other(err.into())
+        FrameError::Other(lib::Error::Protocol(err.to_string()))
     }
 }
 
 impl Display for FrameError {
     fn fmt(&self, fmt: &mut Formatter<'_>) -> fmt::Result {
         match self {
-            FrameError::Incomplete => Display::fmt("流过早关闭", fmt),
-            FrameError::Other(err) => write!(fmt, "错误error:{}", err),
+            FrameError::Incomplete => Display::fmt("Protocol error: unexpected end of stream", fmt),
+            FrameError::Other(err) => Display::fmt(err, fmt),
         }
     }
 }
 
 impl std::error::Error for FrameError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NESTED: &[u8] = b"*3\r\n:1\r\n*2\r\n$3\r\nfoo\r\n*1\r\n+OK\r\n*-1\r\n";
+
+    #[test]
+    fn nested_arrays_are_checked_and_parsed() {
+        let mut src = Cursor::new(NESTED);
+        Frame::check(&mut src).unwrap();
+        assert_eq!(src.position() as usize, NESTED.len());
+        let frame = Frame::parse(&mut Cursor::new(NESTED)).unwrap();
+        let expected = Frame::Array(vec![
+            Frame::Integer(1),
+            Frame::Array(vec![
+                Frame::Bulk(Bytes::from_static(b"foo")),
+                Frame::Array(vec![Frame::Simple("OK".to_string())]),
+            ]),
+            Frame::Null,
+        ]);
+        assert_eq!(frame, expected);
+    }
+
+    #[test]
+    fn null_and_empty_arrays() {
+        let mut src = Cursor::new(&b"*-1\r\n"[..]);
+        Frame::check(&mut src).unwrap();
+        assert_eq!(src.position(), 5);
+        assert_eq!(
+            Frame::parse(&mut Cursor::new(&b"*-1\r\n"[..])).unwrap(),
+            Frame::Null
+        );
+        Frame::check(&mut Cursor::new(&b"*0\r\n"[..])).unwrap();
+        assert_eq!(
+            Frame::parse(&mut Cursor::new(&b"*0\r\n"[..])).unwrap(),
+            Frame::Array(vec![])
+        );
+    }
+
+    fn parse(input: &[u8]) -> Result<Frame, FrameError> {
+        Frame::parse(&mut Cursor::new(input))
+    }
+
+    #[test]
+    fn integers_are_parsed_strictly() {
+        assert_eq!(parse(b":-5\r\n").unwrap(), Frame::Integer(-5));
+        assert!(matches!(parse(b": 5\r\n"), Err(FrameError::Other(_))));
+        assert!(matches!(parse(b":5 \r\n"), Err(FrameError::Other(_))));
+        //大容量字符串的长度只能是-1或非负数
+        assert!(matches!(parse(b"$-5\r\n"), Err(FrameError::Other(_))));
+        assert!(matches!(
+            Frame::check(&mut Cursor::new(&b"$-5\r\n"[..])),
+            Err(FrameError::Other(_))
+        ));
+    }
+
+    #[test]
+    fn overflowing_bulk_length_is_rejected() {
+        //不限制长度时加上\r\n后才会溢出
+        let limits = Limits {
+            max_bulk_len: usize::MAX,
+            max_frame_size: usize::MAX,
+            ..Limits::default()
+        };
+        for input in [
+            &b"$18446744073709551615\r\n"[..],
+            b"=18446744073709551614\r\n",
+        ] {
+            assert!(matches!(parse(input), Err(FrameError::Other(_))));
+            assert!(matches!(
+                Frame::check_limited(&mut Cursor::new(input), &limits),
+                Err(FrameError::Other(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn arrays_longer_than_the_limit_are_rejected() {
+        let limits = Limits {
+            max_array_len: 3,
+            ..Limits::default()
+        };
+        let check = |input: &[u8]| Frame::check_limited(&mut Cursor::new(input), &limits);
+        check(b"*3\r\n:1\r\n:2\r\n:3\r\n").unwrap();
+        //只看声明的长度，不等元素到齐
+        assert!(matches!(check(b"*4\r\n"), Err(FrameError::Other(_))));
+        //映射的每一对占两个元素
+        assert!(matches!(check(b"%2\r\n"), Err(FrameError::Other(_))));
+        let declared = format!("*{}\r\n", MAX_ARRAY_LEN + 1);
+        assert!(matches!(
+            Frame::check(&mut Cursor::new(declared.as_bytes())),
+            Err(FrameError::Other(_))
+        ));
+    }
+
+    #[test]
+    fn truncated_nested_array_is_incomplete() {
+        for len in 0..NESTED.len() {
+            let mut src = Cursor::new(&NESTED[..len]);
+            assert!(matches!(
+                Frame::check(&mut src),
+                Err(FrameError::Incomplete)
+            ));
+        }
+    }
+
+    #[test]
+    fn nesting_deeper_than_the_limit_is_rejected() {
+        let limits = Limits::default();
+        let mut src = b"*1\r\n".repeat(limits.max_depth + 1);
+        src.extend_from_slice(b":1\r\n");
+        let result = Frame::check_limited(&mut Cursor::new(&src[..]), &limits);
+        assert!(matches!(result, Err(FrameError::Other(_))));
+    }
+
+    #[test]
+    fn malformed_frames_report_redis_protocol_errors() {
+        let cases: [(&[u8], &str); 4] = [
+            (b"*1\r\n@x\r\n", "Protocol error: expected '$', got '@'"),
+            (
+                b"*1\r\n\x01\r\n",
+                "Protocol error: expected '$', got '\\x01'",
+            ),
+            (b"$-2\r\n", "Protocol error: invalid bulk length"),
+            (b"*-2\r\n", "Protocol error: invalid multibulk length"),
+        ];
+        for (src, expected) in cases {
+            let checked = Frame::check(&mut Cursor::new(src)).unwrap_err();
+            assert_eq!(checked.to_string(), expected);
+            let parsed = Frame::parse(&mut Cursor::new(src)).unwrap_err();
+            assert_eq!(parsed.to_string(), expected);
+        }
+        let err = Frame::check(&mut Cursor::new(&b"*1\r\n@x\r\n"[..])).unwrap_err();
+        assert_eq!(
+            Frame::from(lib::Error::from(err)),
+            Frame::Error("ERR Protocol error: expected '$', got '@'".to_string())
+        );
+    }
+}
@@ -0,0 +1,90 @@
+//纬度的范围
+const LAT_MIN: f64 = -85.051_128_78;
+const LAT_MAX: f64 = 85.051_128_78;
+//经度的范围
+const LON_MIN: f64 = -180.0;
+const LON_MAX: f64 = 180.0;
+//经度与纬度各自占用的位数
+const STEP: u32 = 26;
+//计算距离时使用的地球半径，单位为米，与redis保持一致
+const EARTH_RADIUS: f64 = 6_372_797.560_856;
+
+///经纬度不在可以编码的范围内时回复的错误
+pub(crate) fn invalid_pair(lon: f64, lat: f64) -> String {
+    format!("ERR invalid longitude,latitude pair {:.6},{:.6}", lon, lat)
+}
+
+///将经纬度编码为52位的geohash，超出范围时返回None
+///
+/// 与redis一致，经纬度各取26位交错组成geohash，作为有序集合中成员的分数存储。
+/// 纬度的范围限制在Web墨卡托投影能表示的区间内
+pub(crate) fn encode(lon: f64, lat: f64) -> Option<u64> {
+    if !(LON_MIN..=LON_MAX).contains(&lon) || !(LAT_MIN..=LAT_MAX).contains(&lat) {
+        return None;
+    }
+    let scale = (1u64 << STEP) as f64;
+    //恰好落在上界时归入最后一格
+    let lat_bits = (((lat - LAT_MIN) / (LAT_MAX - LAT_MIN) * scale) as u64).min((1 << STEP) - 1);
+    let lon_bits = (((lon - LON_MIN) / (LON_MAX - LON_MIN) * scale) as u64).min((1 << STEP) - 1);
+    //纬度占偶数位，经度占奇数位
+    Some(spread(lat_bits) | (spread(lon_bits) << 1))
+}
+
+///将geohash解码为所在格子中心的经纬度
+pub(crate) fn decode(hash: u64) -> (f64, f64) {
+    let lat_bits = squash(hash);
+    let lon_bits = squash(hash >> 1);
+    let scale = (1u64 << STEP) as f64;
+    let lat = LAT_MIN + (lat_bits as f64 + 0.5) / scale * (LAT_MAX - LAT_MIN);
+    let lon = LON_MIN + (lon_bits as f64 + 0.5) / scale * (LON_MAX - LON_MIN);
+    (lon.clamp(LON_MIN, LON_MAX), lat.clamp(LAT_MIN, LAT_MAX))
+}
+
+///两点之间的大圆距离，单位为米
+pub(crate) fn distance(lon1: f64, lat1: f64, lon2: f64, lat2: f64) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let u = ((lat2 - lat1) / 2.0).sin();
+    let v = ((lon2.to_radians() - lon1.to_radians()) / 2.0).sin();
+    2.0 * EARTH_RADIUS * (u * u + lat1.cos() * lat2.cos() * v * v).sqrt().asin()
+}
+
+///点到中心的距离不超过半宽与半高时返回距离，单位为米
+///
+/// 东西方向的距离在点所在的纬度上计算，与redis的BYBOX一致
+pub(crate) fn distance_in_box(
+    center: (f64, f64),
+    point: (f64, f64),
+    width: f64,
+    height: f64,
+) -> Option<f64> {
+    let (lon1, lat1) = center;
+    let (lon2, lat2) = point;
+    if distance(lon2, lat1, lon2, lat2) > height / 2.0 {
+        return None;
+    }
+    if distance(lon1, lat2, lon2, lat2) > width / 2.0 {
+        return None;
+    }
+    Some(distance(lon1, lat1, lon2, lat2))
+}
+
+///距离单位换算为米的倍数，单位不合法时返回None
+pub(crate) fn unit(name: &str) -> Option<f64> {
+    match name.to_lowercase().as_str() {
+        "m" => Some(1.0),
+        "km" => Some(1000.0),
+        "ft" => Some(0.3048),
+        "mi" => Some(1609.34),
+        _ => None,
+    }
+}
+
+//将低26位分散到偶数位上
+fn spread(bits: u64) -> u64 {
+    (0..STEP).fold(0, |hash, i| hash | (((bits >> i) & 1) << (2 * i)))
+}
+
+//取出偶数位上的26位
+fn squash(hash: u64) -> u64 {
+    (0..STEP).fold(0, |bits, i| bits | (((hash >> (2 * i)) & 1) << i))
+}
@@ -0,0 +1,104 @@
+///判断text是否匹配glob风格的pattern
+///
+/// 与redis的规则一致：“*”匹配任意长度的字节，“?”匹配单个字节，“[...]”匹配其中任意一个字节，
+/// 支持“a-z”形式的范围与开头的“^”取反，“\”转义下一个字节。KEYS、SCAN MATCH与PSUBSCRIBE共用这套规则
+pub(crate) fn matches(pattern: &[u8], text: &[u8]) -> bool {
+    let (mut p, mut t) = (0, 0);
+    //最近一个“*”之后的位置以及它当时对应的text位置，匹配失败时从这里回溯
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() {
+            match pattern[p] {
+                b'*' => {
+                    //连续的“*”等同于一个
+                    while p < pattern.len() && pattern[p] == b'*' {
+                        p += 1;
+                    }
+                    if p == pattern.len() {
+                        return true;
+                    }
+                    backtrack = Some((p, t));
+                    continue;
+                }
+                b'?' => {
+                    p += 1;
+                    t += 1;
+                    continue;
+                }
+                b'[' => {
+                    let (matched, next) = match_class(pattern, p, text[t]);
+                    if matched {
+                        p = next;
+                        t += 1;
+                        continue;
+                    }
+                }
+                b'\\' if p + 1 < pattern.len() => {
+                    if pattern[p + 1] == text[t] {
+                        p += 2;
+                        t += 1;
+                        continue;
+                    }
+                }
+                c => {
+                    if c == text[t] {
+                        p += 1;
+                        t += 1;
+                        continue;
+                    }
+                }
+            }
+        }
+        //当前位置匹配失败，让最近的“*”多吞掉一个字节后重试
+        match backtrack {
+            Some((star_p, star_t)) => {
+                backtrack = Some((star_p, star_t + 1));
+                p = star_p;
+                t = star_t + 1;
+            }
+            None => return false,
+        }
+    }
+    //text已经耗尽，pattern剩余的部分只能全是“*”
+    pattern[p..].iter().all(|c| *c == b'*')
+}
+
+//匹配从pattern[start]的“[”开始的字节类，返回是否匹配以及类之后的位置
+//
+// 没有闭合的“]”时类延伸到pattern末尾，与redis的行为一致
+fn match_class(pattern: &[u8], start: usize, c: u8) -> (bool, usize) {
+    let mut p = start + 1;
+    let negate = pattern.get(p) == Some(&b'^');
+    if negate {
+        p += 1;
+    }
+    let mut matched = false;
+    loop {
+        match pattern.get(p) {
+            None => break,
+            Some(b']') => {
+                p += 1;
+                break;
+            }
+            Some(b'\\') if p + 1 < pattern.len() => {
+                matched |= pattern[p + 1] == c;
+                p += 2;
+            }
+            Some(&low) if pattern.get(p + 1) == Some(&b'-') && p + 2 < pattern.len() => {
+                let high = pattern[p + 2];
+                let (low, high) = if low <= high {
+                    (low, high)
+                } else {
+                    (high, low)
+                };
+                matched |= low <= c && c <= high;
+                p += 3;
+            }
+            Some(&other) => {
+                matched |= other == c;
+                p += 1;
+            }
+        }
+    }
+    (matched != negate, p)
+}
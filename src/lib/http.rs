@@ -0,0 +1,618 @@
+use crate::lib;
+use crate::lib::cmd::Command;
+use crate::lib::db::Db;
+use crate::lib::embedded::EmbeddedClient;
+use crate::lib::frame::Frame;
+use bytes::Bytes;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, OwnedSemaphorePermit};
+
+//请求头的最大长度
+const MAX_HEADER: usize = 8 * 1024;
+//请求体只用于单个值或一条命令，更大的数据应通过客户端协议写入
+const MAX_BODY: usize = 1024 * 1024;
+//读完整个请求的最长时间，超时的连接回复408后关闭
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+const JSON: &str = "application/json";
+
+///处理一个HTTP网关的连接
+///
+/// 支持GET、PUT、DELETE /keys/{key}与POST /command，命令经嵌入式连接执行，与客户端连接一样经过认证、ACL与命令分发。
+/// 认证使用Authorization头，Basic携带用户名与密码，Bearer携带default用户的密码。
+/// 读取请求期间占用permit这个连接名额，执行时改由嵌入式连接占用。
+/// 请求体不超过1mb，请求需要在10秒内读完，会阻塞等待的命令回复400，
+/// 没有在http-allowed-commands中放开的脚本、调试、配置与关闭命令回复403。
+/// 回复之后关闭连接，不支持keep-alive
+pub(crate) async fn respond(
+    mut stream: TcpStream,
+    db: &Db,
+    connector: mpsc::UnboundedSender<DuplexStream>,
+    permit: OwnedSemaphorePermit,
+) {
+    let request = tokio::time::timeout(READ_TIMEOUT, read_request(&mut stream)).await;
+    drop(permit);
+    let request = match request {
+        Ok(Ok(request)) => request,
+        Ok(Err(reply)) => return send(&mut stream, reply).await,
+        Err(_) => return send(&mut stream, Reply::error("408 Request Timeout", "Timeout")).await,
+    };
+    let reply = match request.command() {
+        Ok((args, _)) if !db.config().http_allows(&String::from_utf8_lossy(&args[0])) => {
+            Reply::error("403 Forbidden", "Command is not allowed over HTTP")
+        }
+        Ok((args, format)) => match request.credentials() {
+            Some(credentials) => match call(connector, credentials, args).await {
+                Ok(frame) => format.reply(frame),
+                Err(e) => Reply::error("500 Internal Server Error", &e.to_string()),
+            },
+            None => Reply::error("401 Unauthorized", "Invalid Authorization header"),
+        },
+        Err(reply) => reply,
+    };
+    send(&mut stream, reply).await;
+}
+
+///拒绝超出连接数上限的请求
+pub(crate) async fn reject(mut stream: TcpStream) {
+    let reply = Reply::error("503 Service Unavailable", "max number of clients reached");
+    send(&mut stream, reply).await;
+}
+
+//发送响应后关闭连接，对端已经关闭时忽略错误
+async fn send(stream: &mut TcpStream, reply: Reply) {
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        reply.status,
+        reply.content_type,
+        reply.body.len()
+    );
+    if stream.write_all(head.as_bytes()).await.is_ok() {
+        let _ = stream.write_all(&reply.body).await;
+    }
+    let _ = stream.shutdown().await;
+}
+
+//打开一个嵌入式连接，以RESP3握手并认证后执行命令
+async fn call(
+    connector: mpsc::UnboundedSender<DuplexStream>,
+    credentials: Option<(String, String)>,
+    args: Vec<Bytes>,
+) -> lib::Result<Frame> {
+    let mut client = EmbeddedClient::open(connector);
+    let mut hello = vec![Bytes::from_static(b"HELLO"), Bytes::from_static(b"3")];
+    if let Some((user, password)) = credentials {
+        hello.extend([Bytes::from_static(b"AUTH"), user.into(), password.into()]);
+    }
+    match client.call(&hello).await? {
+        Frame::Error(msg) => Ok(Frame::Error(msg)),
+        _ => client.call(&args).await,
+    }
+}
+
+//解析过的请求
+#[derive(Debug)]
+struct Request {
+    method: String,
+    //去掉查询参数之后的路径
+    path: String,
+    authorization: Option<String>,
+    body: Vec<u8>,
+}
+
+//读取请求头与请求体，请求不合法或超出长度限制时返回要回复的错误
+async fn read_request(stream: &mut TcpStream) -> Result<Request, Reply> {
+    let bad_request = || Reply::error("400 Bad Request", "Bad Request");
+    let mut buf = Vec::new();
+    let mut chunk = [0; 4096];
+    let end = loop {
+        if let Some(i) = buf.windows(4).position(|window| window == b"\r\n\r\n") {
+            break i + 4;
+        }
+        if buf.len() > MAX_HEADER {
+            return Err(Reply::error(
+                "431 Request Header Fields Too Large",
+                "Request header is too large",
+            ));
+        }
+        match stream.read(&mut chunk).await {
+            Ok(0) | Err(_) => return Err(bad_request()),
+            Ok(n) => buf.extend_from_slice(&chunk[..n]),
+        }
+    };
+    let head = String::from_utf8_lossy(&buf[..end]).into_owned();
+    let mut lines = head.split("\r\n");
+    let mut parts = lines.next().unwrap_or_default().split_ascii_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target),
+        _ => return Err(bad_request()),
+    };
+    let path = target.split('?').next().unwrap_or_default().to_string();
+    let mut length = 0;
+    let mut authorization = None;
+    let mut expect_continue = false;
+    for line in lines {
+        let (name, value) = match line.split_once(':') {
+            Some((name, value)) => (name.trim().to_lowercase(), value.trim()),
+            None => continue,
+        };
+        match name.as_str() {
+            "content-length" => length = value.parse().map_err(|_| bad_request())?,
+            "authorization" => authorization = Some(value.to_string()),
+            "expect" => expect_continue = value.eq_ignore_ascii_case("100-continue"),
+            _ => {}
+        }
+    }
+    if length > MAX_BODY {
+        return Err(Reply::error(
+            "413 Payload Too Large",
+            "Request body is larger than 1mb",
+        ));
+    }
+    //curl发送较大的请求体之前会等待100 Continue
+    if expect_continue && buf.len() < end + length {
+        stream
+            .write_all(b"HTTP/1.1 100 Continue\r\n\r\n")
+            .await
+            .map_err(|_| bad_request())?;
+    }
+    let mut body = buf.split_off(end);
+    while body.len() < length {
+        match stream.read(&mut chunk).await {
+            Ok(0) | Err(_) => return Err(bad_request()),
+            Ok(n) => body.extend_from_slice(&chunk[..n]),
+        }
+    }
+    body.truncate(length);
+    Ok(Request {
+        method,
+        path,
+        authorization,
+        body,
+    })
+}
+
+impl Request {
+    //请求对应的命令与回复的格式，路径或请求体不合法时返回要回复的错误
+    fn command(&self) -> Result<(Vec<Bytes>, Format), Reply> {
+        if self.path == "/command" {
+            if self.method != "POST" {
+                return Err(Reply::error("405 Method Not Allowed", "Method Not Allowed"));
+            }
+            return match parse_args(&self.body) {
+                Some(args) if blocks(&args) => Err(Reply::error(
+                    "400 Bad Request",
+                    "Blocking commands are not supported over HTTP",
+                )),
+                Some(args) if !args.is_empty() => Ok((args, Format::Json)),
+                _ => Err(Reply::error(
+                    "400 Bad Request",
+                    "Request body must be a non-empty JSON array of strings",
+                )),
+            };
+        }
+        let key = match self.path.strip_prefix("/keys/").map(percent_decode) {
+            Some(Some(key)) if !key.is_empty() => Bytes::from(key),
+            Some(_) => return Err(Reply::error("400 Bad Request", "Invalid key")),
+            None => return Err(Reply::error("404 Not Found", "Not Found")),
+        };
+        match self.method.as_str() {
+            "GET" => Ok((vec![Bytes::from_static(b"GET"), key], Format::Value)),
+            "PUT" => {
+                let value = Bytes::copy_from_slice(&self.body);
+                Ok((vec![Bytes::from_static(b"SET"), key, value], Format::Json))
+            }
+            "DELETE" => Ok((vec![Bytes::from_static(b"DEL"), key], Format::Deleted)),
+            _ => Err(Reply::error("405 Method Not Allowed", "Method Not Allowed")),
+        }
+    }
+
+    //从Authorization头取出HELLO AUTH的用户名与密码，没有这个头时为Some(None)，头不合法时为None
+    fn credentials(&self) -> Option<Option<(String, String)>> {
+        let authorization = match &self.authorization {
+            Some(authorization) => authorization,
+            None => return Some(None),
+        };
+        let (scheme, value) = authorization.split_once(' ')?;
+        if scheme.eq_ignore_ascii_case("bearer") {
+            return Some(Some(("default".to_string(), value.trim().to_string())));
+        }
+        if !scheme.eq_ignore_ascii_case("basic") {
+            return None;
+        }
+        let decoded = String::from_utf8(base64_decode(value.trim())?).ok()?;
+        let (user, password) = decoded.split_once(':')?;
+        Some(Some((user.to_string(), password.to_string())))
+    }
+}
+
+//命令是否可能阻塞等待，HTTP请求不能无限期地挂起
+fn blocks(args: &[Bytes]) -> bool {
+    let frame = Frame::Array(args.iter().cloned().map(Frame::Bulk).collect());
+    Command::from_frame(frame).is_ok_and(|cmd| cmd.may_block())
+}
+
+//回复的格式
+#[derive(Debug, Clone, Copy)]
+enum Format {
+    //回复转换为JSON
+    Json,
+    //GET：字符串原样作为响应体，键不存在时回复404
+    Value,
+    //DELETE：键不存在时回复404
+    Deleted,
+}
+
+impl Format {
+    //按命令的回复生成HTTP响应
+    fn reply(self, frame: Frame) -> Reply {
+        match (self, frame) {
+            (_, Frame::Error(msg)) => Reply::error(error_status(&msg), &msg),
+            (Format::Value, Frame::Bulk(value)) => Reply {
+                status: "200 OK",
+                content_type: "application/octet-stream",
+                body: value.to_vec(),
+            },
+            (Format::Value, Frame::Null) | (Format::Deleted, Frame::Integer(0)) => {
+                Reply::error("404 Not Found", "Key not found")
+            }
+            (_, frame) => {
+                let mut body = String::new();
+                write_json(&mut body, &frame);
+                Reply::json("200 OK", body)
+            }
+        }
+    }
+}
+
+//按错误码选择HTTP状态
+fn error_status(msg: &str) -> &'static str {
+    match msg.split(' ').next().unwrap_or_default() {
+        "NOAUTH" | "WRONGPASS" => "401 Unauthorized",
+        "NOPERM" => "403 Forbidden",
+        _ => "400 Bad Request",
+    }
+}
+
+//HTTP响应
+#[derive(Debug)]
+struct Reply {
+    status: &'static str,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Reply {
+    fn json(status: &'static str, body: String) -> Reply {
+        Reply {
+            status,
+            content_type: JSON,
+            body: body.into_bytes(),
+        }
+    }
+
+    //错误以{"error": msg}回复
+    fn error(status: &'static str, msg: &str) -> Reply {
+        let mut body = String::new();
+        write_json(&mut body, &Frame::Error(msg.to_string()));
+        Reply::json(status, body)
+    }
+}
+
+//把回复编码为JSON
+//
+// 字符串按UTF-8解码，不合法的字节替换为U+FFFD；无穷大与NaN编码为字符串；
+// 映射的键不是字符串时以其JSON编码作为键
+fn write_json(out: &mut String, frame: &Frame) {
+    match frame {
+        Frame::Simple(text) => write_string(out, text.as_bytes()),
+        Frame::Error(msg) => {
+            out.push_str("{\"error\":");
+            write_string(out, msg.as_bytes());
+            out.push('}');
+        }
+        Frame::Integer(value) => out.push_str(&value.to_string()),
+        Frame::Bulk(value) | Frame::Verbatim { data: value, .. } => write_string(out, value),
+        Frame::Null => out.push_str("null"),
+        Frame::BigNumber(value) => out.push_str(value),
+        Frame::Double(value) if value.is_finite() => out.push_str(&value.to_string()),
+        Frame::Double(value) => write_string(out, value.to_string().as_bytes()),
+        Frame::Boolean(value) => out.push_str(if *value { "true" } else { "false" }),
+        Frame::Array(items) | Frame::Set(items) | Frame::Push(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_json(out, item);
+            }
+            out.push(']');
+        }
+        Frame::Map(pairs) => {
+            out.push('{');
+            for (i, (key, value)) in pairs.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                match key {
+                    Frame::Simple(text) => write_string(out, text.as_bytes()),
+                    Frame::Bulk(key) => write_string(out, key),
+                    key => {
+                        let mut encoded = String::new();
+                        write_json(&mut encoded, key);
+                        write_string(out, encoded.as_bytes());
+                    }
+                }
+                out.push(':');
+                write_json(out, value);
+            }
+            out.push('}');
+        }
+    }
+}
+
+//编码JSON字符串，转义引号、反斜杠与控制字符
+fn write_string(out: &mut String, value: &[u8]) {
+    out.push('"');
+    for c in String::from_utf8_lossy(value).chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+//解析作为命令参数的JSON数组，元素可以是字符串、数字或布尔值，不合法时返回None
+fn parse_args(input: &[u8]) -> Option<Vec<Bytes>> {
+    let mut json = Json { input, pos: 0 };
+    json.skip_whitespace();
+    json.expect(b'[')?;
+    let mut args = vec![];
+    json.skip_whitespace();
+    if !json.eat(b']') {
+        loop {
+            json.skip_whitespace();
+            args.push(json.scalar()?);
+            json.skip_whitespace();
+            if json.eat(b']') {
+                break;
+            }
+            json.expect(b',')?;
+        }
+    }
+    json.skip_whitespace();
+    (json.pos == input.len()).then_some(args)
+}
+
+//JSON解析的游标
+#[derive(Debug)]
+struct Json<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl Json<'_> {
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).copied()
+    }
+
+    //下一个字节是expected时跳过它
+    fn eat(&mut self, expected: u8) -> bool {
+        let matched = self.peek() == Some(expected);
+        if matched {
+            self.pos += 1;
+        }
+        matched
+    }
+
+    fn expect(&mut self, expected: u8) -> Option<()> {
+        self.eat(expected).then_some(())
+    }
+
+    //字符串、数字或布尔值，数字与布尔值保留原本的文本
+    fn scalar(&mut self) -> Option<Bytes> {
+        match self.peek()? {
+            b'"' => {
+                self.pos += 1;
+                self.string().map(Bytes::from)
+            }
+            b't' | b'f' => {
+                let literal: &[u8] = if self.peek()? == b't' {
+                    b"true"
+                } else {
+                    b"false"
+                };
+                self.input[self.pos..].starts_with(literal).then(|| {
+                    self.pos += literal.len();
+                    Bytes::from_static(literal)
+                })
+            }
+            b'-' | b'0'..=b'9' => {
+                let start = self.pos;
+                while matches!(
+                    self.peek(),
+                    Some(b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9')
+                ) {
+                    self.pos += 1;
+                }
+                let text = std::str::from_utf8(&self.input[start..self.pos]).ok()?;
+                text.parse::<f64>().ok()?;
+                Some(Bytes::copy_from_slice(text.as_bytes()))
+            }
+            _ => None,
+        }
+    }
+
+    //开头的引号之后的字符串内容
+    fn string(&mut self) -> Option<Vec<u8>> {
+        let mut out = vec![];
+        loop {
+            let b = self.peek()?;
+            self.pos += 1;
+            match b {
+                b'"' => return Some(out),
+                b'\\' => {
+                    let escaped = self.peek()?;
+                    self.pos += 1;
+                    match escaped {
+                        b'"' | b'\\' | b'/' => out.push(escaped),
+                        b'b' => out.push(0x08),
+                        b'f' => out.push(0x0c),
+                        b'n' => out.push(b'\n'),
+                        b'r' => out.push(b'\r'),
+                        b't' => out.push(b'\t'),
+                        b'u' => {
+                            let mut units = vec![self.hex4()?];
+                            //基本平面以外的字符以代理对表示
+                            if (0xd800..0xdc00).contains(&units[0]) {
+                                self.expect(b'\\')?;
+                                self.expect(b'u')?;
+                                units.push(self.hex4()?);
+                            }
+                            let c = char::decode_utf16(units).next()?.ok()?;
+                            out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                        }
+                        _ => return None,
+                    }
+                }
+                b if b < 0x20 => return None,
+                b => out.push(b),
+            }
+        }
+    }
+
+    //\u之后的4位十六进制数
+    fn hex4(&mut self) -> Option<u16> {
+        let digits = self.input.get(self.pos..self.pos + 4)?;
+        if !digits.iter().all(u8::is_ascii_hexdigit) {
+            return None;
+        }
+        self.pos += 4;
+        u16::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()
+    }
+}
+
+//解码路径中以%XX转义的字节
+fn percent_decode(input: &str) -> Option<Vec<u8>> {
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes.get(i + 1..i + 3)?;
+            if !hex.iter().all(u8::is_ascii_hexdigit) {
+                return None;
+            }
+            out.push(u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    Some(out)
+}
+
+//解码标准的base64，填充的等号可以省略
+fn base64_decode(input: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(input.len() / 4 * 3);
+    let mut bits = 0u32;
+    let mut count = 0;
+    for c in input.trim_end_matches('=').bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        bits = ((bits << 6) | value as u32) & 0xffff;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            out.push((bits >> count) as u8);
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::config::Config;
+    use std::sync::Arc;
+    use tokio::net::TcpListener;
+    use tokio::sync::Semaphore;
+
+    //向respond发送一个请求并读取完整的响应
+    async fn request(db: &Db, raw: &str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        let (connector, _embedded) = mpsc::unbounded_channel();
+        let permit = Arc::new(Semaphore::new(1)).acquire_owned().await.unwrap();
+        client.write_all(raw.as_bytes()).await.unwrap();
+        respond(stream, db, connector, permit).await;
+        let mut response = String::new();
+        client.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    fn command(args: &str) -> String {
+        format!(
+            "POST /command HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}",
+            args.len(),
+            args
+        )
+    }
+
+    #[tokio::test]
+    async fn dangerous_commands_are_denied_by_default() {
+        let db = Db::new(Config::default());
+        for args in [
+            r#"["EVAL", "return 1", "0"]"#,
+            r#"["evalsha", "abc", "0"]"#,
+            r#"["SCRIPT", "FLUSH"]"#,
+            r#"["CONFIG", "SET", "maxmemory", "1"]"#,
+            r#"["DEBUG", "SLEEP", "0"]"#,
+            r#"["SHUTDOWN", "NOSAVE"]"#,
+        ] {
+            let response = request(&db, &command(args)).await;
+            assert!(
+                response.starts_with("HTTP/1.1 403 Forbidden"),
+                "{}",
+                response
+            );
+        }
+    }
+
+    #[test]
+    fn allowed_commands_are_configurable() {
+        let mut config = Config::default();
+        assert!(config.http_allows("get"));
+        assert!(!config.http_allows("EVAL"));
+        config
+            .set("http-allowed-commands", "EVAL  evalsha")
+            .unwrap();
+        assert!(config.http_allows("eval"));
+        assert!(config.http_allows("EVALSHA"));
+        assert!(!config.http_allows("config"));
+        assert_eq!(config.http_bind(), "127.0.0.1");
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+//每个事件保留的样本数，与redis一致
+const SAMPLES: usize = 160;
+
+///一个事件的一次延迟尖峰
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Sample {
+    ///发生的unix时间戳（秒）
+    pub(crate) time: u64,
+    ///延迟（毫秒）
+    pub(crate) latency: u64,
+}
+
+///一个事件记录下的尖峰
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Series {
+    ///最近的样本，从旧到新排列，同一秒内的尖峰只保留最大的一个
+    pub(crate) samples: VecDeque<Sample>,
+    ///记录以来最大的延迟（毫秒）
+    pub(crate) max: u64,
+}
+
+///按事件记录超过阈值的延迟尖峰
+///
+/// 事件包括普通命令command、快速命令fast-command与主动过期的一轮清理expire-cycle，
+/// 持久化等后台操作同样以各自的事件名记录。阈值为latency-monitor-threshold毫秒，为0时不记录
+#[derive(Debug, Default)]
+pub(crate) struct LatencyMonitor {
+    events: Mutex<HashMap<&'static str, Series>>,
+    //阈值（毫秒），修改配置时同步到这里
+    threshold: AtomicU64,
+}
+
+impl LatencyMonitor {
+    ///修改阈值
+    pub(crate) fn configure(&self, threshold: u64) {
+        self.threshold.store(threshold, Ordering::Relaxed);
+    }
+
+    ///事件耗时duration，超过阈值时记录下来
+    pub(crate) fn record(&self, event: &'static str, duration: Duration) {
+        let threshold = self.threshold.load(Ordering::Relaxed);
+        let latency = duration.as_millis() as u64;
+        if threshold == 0 || latency < threshold {
+            return;
+        }
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        let mut events = self.events.lock().unwrap();
+        let series = events.entry(event).or_default();
+        series.max = series.max.max(latency);
+        match series.samples.back_mut() {
+            Some(last) if last.time == time => last.latency = last.latency.max(latency),
+            _ => {
+                if series.samples.len() == SAMPLES {
+                    series.samples.pop_front();
+                }
+                series.samples.push_back(Sample { time, latency });
+            }
+        }
+    }
+
+    ///事件记录下的尖峰，没有记录过时返回None
+    pub(crate) fn history(&self, event: &str) -> Option<Series> {
+        self.events.lock().unwrap().get(event).cloned()
+    }
+
+    ///所有记录过尖峰的事件，按事件名排列
+    pub(crate) fn all(&self) -> Vec<(&'static str, Series)> {
+        let events = self.events.lock().unwrap();
+        let mut all: Vec<(&'static str, Series)> = events
+            .iter()
+            .map(|(event, series)| (*event, series.clone()))
+            .collect();
+        all.sort_by_key(|(event, _)| *event);
+        all
+    }
+
+    ///清除指定事件的记录，为空时清除所有事件，返回清除的事件数
+    pub(crate) fn reset(&self, events: &[String]) -> usize {
+        let mut all = self.events.lock().unwrap();
+        if events.is_empty() {
+            let count = all.len();
+            all.clear();
+            return count;
+        }
+        events
+            .iter()
+            .filter(|event| all.remove(event.as_str()).is_some())
+            .count()
+    }
+}
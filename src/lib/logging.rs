@@ -0,0 +1,88 @@
+use crate::lib::config::Config;
+use crate::lib::Result;
+use std::fs::{File, OpenOptions};
+use std::sync::{Arc, OnceLock};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, Registry};
+
+//运行期间修改loglevel时通过它替换过滤级别
+static LEVEL: OnceLock<reload::Handle<LevelFilter, Registry>> = OnceLock::new();
+
+///按配置安装全局的日志订阅者
+///
+/// logfile为空时输出到标准输出，否则追加到该文件；log-format为json时每条日志输出为一行JSON。
+/// 进程中已经安装过订阅者时保留原有的订阅者，此时loglevel的修改也不会生效
+pub(crate) fn init(config: &Config) -> Result<()> {
+    let (filter, handle) = reload::Layer::new(level(config.log_level()));
+    let file = match config.log_file() {
+        "" => None,
+        path => Some(Arc::new(
+            OpenOptions::new().create(true).append(true).open(path)?,
+        )),
+    };
+    let json = config.log_format() == "json";
+    let plain = (!json).then(|| {
+        fmt::layer()
+            .with_ansi(file.is_none())
+            .with_writer(writer(&file))
+    });
+    let json = json.then(|| fmt::layer().json().with_writer(writer(&file)));
+    if tracing_subscriber::registry()
+        .with(filter)
+        .with(plain)
+        .with(json)
+        .try_init()
+        .is_ok()
+    {
+        let _ = LEVEL.set(handle);
+    }
+    Ok(())
+}
+
+///修改日志的过滤级别，没有由init安装订阅者时什么也不做
+pub(crate) fn set_level(loglevel: &str) {
+    if let Some(handle) = LEVEL.get() {
+        let _ = handle.modify(|filter| *filter = level(loglevel));
+    }
+}
+
+//日志的输出目标，None时为标准输出
+fn writer(file: &Option<Arc<File>>) -> BoxMakeWriter {
+    match file {
+        Some(file) => BoxMakeWriter::new(file.clone()),
+        None => BoxMakeWriter::new(std::io::stdout),
+    }
+}
+
+//redis的日志级别对应的过滤级别
+fn level(loglevel: &str) -> LevelFilter {
+    match loglevel {
+        "debug" => LevelFilter::TRACE,
+        "verbose" => LevelFilter::DEBUG,
+        "warning" => LevelFilter::WARN,
+        "nothing" => LevelFilter::OFF,
+        _ => LevelFilter::INFO,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing::Level;
+
+    #[test]
+    fn loglevels_filter_the_less_important_events() {
+        //notice只输出info及以上的事件
+        assert!(level("notice") >= Level::INFO);
+        assert!(level("notice") < Level::DEBUG);
+        assert!(level("warning") >= Level::WARN);
+        assert!(level("warning") < Level::INFO);
+        assert!(level("verbose") >= Level::DEBUG);
+        assert!(level("verbose") < Level::TRACE);
+        assert!(level("debug") >= Level::TRACE);
+        assert!(level("nothing") < Level::ERROR);
+    }
+}
@@ -0,0 +1,530 @@
+use crate::lib::cmd::{blocking, Command};
+use crate::lib::db::Db;
+use crate::lib::frame::{Frame, ReplyLimit, REPLY_TOO_LARGE};
+use bytes::Bytes;
+use mlua::{ChunkMode, HookTriggers, Lua, LuaOptions, StdLib, Table, Value, Variadic};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+//脚本每执行这么多条指令检查一次是否被SCRIPT KILL终止
+const HOOK_INSTRUCTIONS: u32 = 1000;
+
+///回复给脚本执行超过lua-time-limit期间到来的命令
+pub(crate) const BUSY: &str =
+    "BUSY Redis is busy running a script. You can only call SCRIPT KILL or SHUTDOWN NOSAVE.";
+
+//被SCRIPT KILL终止的脚本返回的错误
+const KILLED: &str = "ERR Script killed by user with SCRIPT KILL...";
+
+///正在执行的脚本
+///
+/// 与redis一致，脚本执行超过lua-time-limit之后其他连接的命令回复BUSY而不再等待，
+/// 此时可以用SCRIPT KILL终止还没有执行过写命令的脚本
+#[derive(Debug, Default)]
+pub(crate) struct Running {
+    //正在执行的脚本的开始时间，没有脚本在执行时为None
+    started: Mutex<Option<Instant>>,
+    //脚本是否执行过写命令，执行过的脚本终止后数据会处于中间状态，不允许SCRIPT KILL
+    wrote: AtomicBool,
+    //SCRIPT KILL要求终止脚本
+    kill: AtomicBool,
+    //脚本结束时通知等待的连接
+    finished: Notify,
+}
+
+impl Running {
+    //开始执行脚本，返回的guard在脚本结束时清除状态
+    fn begin(&self) -> RunningGuard<'_> {
+        self.wrote.store(false, Ordering::Relaxed);
+        self.kill.store(false, Ordering::Relaxed);
+        *self.started.lock().unwrap() = Some(Instant::now());
+        RunningGuard(self)
+    }
+
+    ///是否有脚本正在执行
+    pub(crate) fn is_running(&self) -> bool {
+        self.started.lock().unwrap().is_some()
+    }
+
+    ///等待正在执行的脚本结束，脚本执行超过limit时返回false，此时命令应回复BUSY
+    ///
+    /// 异步地等待而不是阻塞在键空间锁上，工作线程因此可以继续处理SCRIPT KILL与BUSY的回复
+    pub(crate) async fn wait(&self, limit: Duration) -> bool {
+        loop {
+            //先注册通知再检查，以免错过检查之后脚本的结束
+            let finished = self.finished.notified();
+            let started = match *self.started.lock().unwrap() {
+                Some(started) => started,
+                None => return true,
+            };
+            let deadline = started + limit;
+            if deadline <= Instant::now() {
+                return false;
+            }
+            tokio::select! {
+                _ = finished => {}
+                _ = tokio::time::sleep_until(deadline.into()) => {}
+            }
+        }
+    }
+
+    ///SCRIPT KILL，终止正在执行且没有执行过写命令的脚本
+    pub(crate) fn kill(&self) -> Frame {
+        if !self.is_running() {
+            return Frame::Error("NOTBUSY No scripts in execution right now.".to_string());
+        }
+        if self.wrote.load(Ordering::Relaxed) {
+            return Frame::Error(
+                "UNKILLABLE Sorry the script already executed write commands against the dataset. \
+                 You can either wait the script termination or kill the server in a hard way using the SHUTDOWN NOSAVE command."
+                    .to_string(),
+            );
+        }
+        self.kill.store(true, Ordering::Relaxed);
+        Frame::Simple("OK".to_string())
+    }
+
+    //由指令计数的钩子调用，被要求终止时返回错误中断脚本
+    //
+    // 脚本中的pcall会捕获这个错误，所以之后每次检查都会再次返回错误，直到脚本结束
+    fn check(&self) -> mlua::Result<()> {
+        if self.kill.load(Ordering::Relaxed) {
+            return Err(mlua::Error::runtime(KILLED));
+        }
+        Ok(())
+    }
+}
+
+///脚本执行超过lua-time-limit期间仍然可以执行的命令：SCRIPT KILL与SHUTDOWN NOSAVE
+pub(crate) fn allowed_when_busy(args: &[Bytes]) -> bool {
+    let is = |index: usize, name: &str| {
+        args.get(index)
+            .is_some_and(|arg| arg.eq_ignore_ascii_case(name.as_bytes()))
+    };
+    (is(0, "script") && is(1, "kill")) || (is(0, "shutdown") && is(1, "nosave"))
+}
+
+//脚本结束时清除执行状态并唤醒等待的连接
+struct RunningGuard<'a>(&'a Running);
+
+impl Drop for RunningGuard<'_> {
+    fn drop(&mut self) {
+        *self.0.started.lock().unwrap() = None;
+        self.0.finished.notify_waiters();
+    }
+}
+
+///执行Lua脚本，返回由脚本的返回值转换而来的帧
+///
+/// 脚本执行期间独占整个键空间，脚本中调用的命令之间不会插入其他连接的操作。
+/// 每次执行都使用全新的Lua状态，脚本无法通过全局变量在两次执行之间传递数据。
+/// 脚本运行在沙箱中，只能使用基础库、string、table与math，不能访问文件、进程与环境变量。
+/// user不为None时脚本中调用的命令与客户端直接执行一样经过ACL检查
+pub(crate) fn eval(
+    db: &Db,
+    user: Option<&str>,
+    script: &[u8],
+    keys: Vec<Bytes>,
+    args: Vec<Bytes>,
+) -> Frame {
+    //脚本可能执行很久，等待中的连接因此可以回复BUSY、执行SCRIPT KILL
+    blocking(|| {
+        db.atomically(|| {
+            let _running = db.running_script().begin();
+            match run(db, user, script, keys, args) {
+                Ok(frame) => frame,
+                Err(e) => Frame::Error(format!("ERR Error running script: {}", e)),
+            }
+        })
+    })
+}
+
+//创建Lua状态，注入KEYS、ARGV与redis库后执行脚本
+fn run(
+    db: &Db,
+    user: Option<&str>,
+    script: &[u8],
+    keys: Vec<Bytes>,
+    args: Vec<Bytes>,
+) -> mlua::Result<Frame> {
+    let lua = sandbox()?;
+    let hook_db = db.clone();
+    lua.set_hook(
+        HookTriggers::new().every_nth_instruction(HOOK_INSTRUCTIONS),
+        move |_, _| hook_db.running_script().check(),
+    );
+    let check_db = db.clone();
+    let check = lua.create_function(move |_, ()| check_db.running_script().check())?;
+    lua.load(GUARD_PCALL).call::<_, ()>(check)?;
+    let globals = lua.globals();
+    globals.set("KEYS", sequence(&lua, keys)?)?;
+    globals.set("ARGV", sequence(&lua, args)?)?;
+    let redis = lua.create_table()?;
+    //命令返回错误时redis.call中断脚本，redis.pcall则将错误作为返回值交给脚本处理
+    let call_db = db.clone();
+    let call_user = user.map(str::to_string);
+    let call = lua.create_function(move |lua, args: Variadic<Value>| {
+        match dispatch(&call_db, call_user.as_deref(), args)? {
+            Frame::Error(msg) => Err(mlua::Error::runtime(msg)),
+            frame => to_lua(lua, frame),
+        }
+    })?;
+    redis.set("call", call)?;
+    let pcall_db = db.clone();
+    let pcall_user = user.map(str::to_string);
+    let pcall = lua.create_function(move |lua, args: Variadic<Value>| {
+        to_lua(lua, dispatch(&pcall_db, pcall_user.as_deref(), args)?)
+    })?;
+    redis.set("pcall", pcall)?;
+    let status_reply = lua.create_function(|lua, msg: mlua::String| reply_table(lua, "ok", msg))?;
+    redis.set("status_reply", status_reply)?;
+    let error_reply = lua.create_function(|lua, msg: mlua::String| reply_table(lua, "err", msg))?;
+    redis.set("error_reply", error_reply)?;
+    globals.set("redis", redis)?;
+    let value: Value = lua
+        .load(script)
+        .set_name("@user_script")
+        .set_mode(ChunkMode::Text)
+        .eval()?;
+    let mut limit = db.reply_limit();
+    Ok(from_lua(value, &mut limit).unwrap_or_else(|| Frame::Error(REPLY_TOO_LARGE.to_string())))
+}
+
+//替换pcall与xpcall，返回之前检查脚本是否已被终止
+//
+// 否则循环中的pcall会捕获钩子返回的错误，被SCRIPT KILL终止的脚本永远无法结束
+const GUARD_PCALL: &str = r#"
+local pcall, xpcall, check = pcall, xpcall, ...
+local function rethrow(...)
+    check()
+    return ...
+end
+_G.pcall = function(...) return rethrow(pcall(...)) end
+_G.xpcall = function(...) return rethrow(xpcall(...)) end
+"#;
+
+//基础库中可以读取文件或加载字节码的函数，沙箱中移除
+const UNSAFE_BASE: [&str; 3] = ["dofile", "loadfile", "load"];
+
+//只加载string、table与math的Lua状态，io、os、package与debug都不可用
+fn sandbox() -> mlua::Result<Lua> {
+    let lua = Lua::new_with(
+        StdLib::STRING | StdLib::TABLE | StdLib::MATH,
+        LuaOptions::default(),
+    )?;
+    for name in UNSAFE_BASE {
+        lua.globals().set(name, Value::Nil)?;
+    }
+    Ok(lua)
+}
+
+//执行脚本通过redis.call、redis.pcall调用的命令，user不为None时先按其ACL权限检查
+fn dispatch(db: &Db, user: Option<&str>, args: Variadic<Value>) -> mlua::Result<Frame> {
+    let mut parts = vec![];
+    for arg in args.iter() {
+        let part = match arg {
+            Value::String(s) => Bytes::copy_from_slice(s.as_bytes()),
+            Value::Integer(i) => Bytes::from(i.to_string()),
+            Value::Number(n) => Bytes::from(n.to_string()),
+            _ => {
+                return Err(mlua::Error::runtime(
+                    "Lua redis lib command arguments must be strings or integers",
+                ))
+            }
+        };
+        parts.push(part);
+    }
+    let name = match parts.first() {
+        Some(name) => String::from_utf8_lossy(name).to_lowercase(),
+        None => {
+            return Err(mlua::Error::runtime(
+                "Please specify at least one argument for this redis lib call",
+            ))
+        }
+    };
+    match Command::info(&name) {
+        None => {
+            return Ok(Frame::Error(
+                "ERR Unknown Redis command called from script".to_string(),
+            ))
+        }
+        //脚本中不能执行脚本、事务、订阅与管理类的命令
+        Some(info) if info.has_flag("noscript") => {
+            return Ok(Frame::Error(
+                "ERR This Redis command is not allowed from script".to_string(),
+            ))
+        }
+        Some(info) if info.has_flag("write") => {
+            db.running_script().wrote.store(true, Ordering::Relaxed);
+        }
+        Some(_) => {}
+    }
+    let frame = Frame::Array(parts.into_iter().map(Frame::Bulk).collect());
+    if let Some(Err(e)) = user.map(|user| db.acl().check(user, &frame)) {
+        return Ok(Frame::Error(e));
+    }
+    let reply = match Command::from_frame(frame) {
+        Ok(cmd) => cmd.apply_now(db),
+        Err(e) => e.into(),
+    };
+    Ok(reply)
+}
+
+//将参数转换为从1开始编号的Lua数组
+fn sequence(lua: &Lua, items: Vec<Bytes>) -> mlua::Result<Table<'_>> {
+    let items = items
+        .iter()
+        .map(|item| lua.create_string(item))
+        .collect::<mlua::Result<Vec<_>>>()?;
+    lua.create_sequence_from(items)
+}
+
+//redis.status_reply与redis.error_reply返回的单字段表
+fn reply_table<'lua>(
+    lua: &'lua Lua,
+    field: &str,
+    msg: mlua::String<'lua>,
+) -> mlua::Result<Table<'lua>> {
+    let table = lua.create_table()?;
+    table.set(field, msg)?;
+    Ok(table)
+}
+
+//将命令的回复转换为Lua值
+//
+// 与redis一致，Null转换为false，简单字符串与错误分别转换为带ok、err字段的表
+fn to_lua(lua: &Lua, frame: Frame) -> mlua::Result<Value<'_>> {
+    let value = match frame {
+        Frame::Simple(text) => Value::Table(reply_table(lua, "ok", lua.create_string(text)?)?),
+        Frame::Error(msg) => Value::Table(reply_table(lua, "err", lua.create_string(msg)?)?),
+        Frame::Integer(value) => Value::Integer(value),
+        Frame::Bulk(data) | Frame::Verbatim { data, .. } => {
+            Value::String(lua.create_string(&data)?)
+        }
+        Frame::Null => Value::Boolean(false),
+        Frame::BigNumber(value) => Value::String(lua.create_string(value)?),
+        Frame::Array(items) | Frame::Set(items) | Frame::Push(items) => {
+            let items = items
+                .into_iter()
+                .map(|item| to_lua(lua, item))
+                .collect::<mlua::Result<Vec<_>>>()?;
+            Value::Table(lua.create_sequence_from(items)?)
+        }
+        //脚本中的命令按RESP2的规则转换，映射展开为键值交替排列的数组
+        Frame::Map(pairs) => {
+            let items = pairs
+                .into_iter()
+                .flat_map(|(key, value)| [key, value])
+                .map(|item| to_lua(lua, item))
+                .collect::<mlua::Result<Vec<_>>>()?;
+            Value::Table(lua.create_sequence_from(items)?)
+        }
+        Frame::Double(value) => Value::String(lua.create_string(value.to_string())?),
+        Frame::Boolean(value) => Value::Integer(value as i64),
+    };
+    Ok(value)
+}
+
+//将脚本的返回值转换为回复给客户端的帧，回复超出limit时返回None
+//
+// 小数会被截断为整数，表按数组处理并在第一个nil处截止，与redis的转换规则一致
+fn from_lua(value: Value, limit: &mut ReplyLimit) -> Option<Frame> {
+    let len = match &value {
+        Value::String(s) => s.as_bytes().len(),
+        _ => 0,
+    };
+    if !limit.add(len) {
+        return None;
+    }
+    let frame = match value {
+        Value::Boolean(true) => Frame::Integer(1),
+        Value::Integer(value) => Frame::Integer(value),
+        Value::Number(value) => Frame::Integer(value as i64),
+        Value::String(s) => Frame::Bulk(Bytes::copy_from_slice(s.as_bytes())),
+        Value::Table(table) => {
+            if let Ok(Some(msg)) = table.get::<_, Option<mlua::String>>("err") {
+                return Some(Frame::Error(msg.to_string_lossy().into_owned()));
+            }
+            if let Ok(Some(text)) = table.get::<_, Option<mlua::String>>("ok") {
+                return Some(Frame::Simple(text.to_string_lossy().into_owned()));
+            }
+            let items = table
+                .sequence_values::<Value>()
+                .map_while(Result::ok)
+                .map(|value| from_lua(value, limit))
+                .collect::<Option<_>>()?;
+            Frame::Array(items)
+        }
+        _ => Frame::Null,
+    };
+    Some(frame)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::config::Config;
+
+    fn eval_str(db: &Db, script: &str) -> Frame {
+        eval(db, None, script.as_bytes(), vec![], vec![])
+    }
+
+    #[test]
+    fn unsafe_libraries_are_not_loaded() {
+        let db = Db::new(Config::default());
+        for name in ["io", "os", "package", "debug", "dofile", "loadfile", "load"] {
+            let script = format!("return {} == nil", name);
+            assert!(
+                matches!(eval_str(&db, &script), Frame::Integer(1)),
+                "{} is available to scripts",
+                name
+            );
+        }
+        assert!(matches!(
+            eval_str(&db, "return io.popen('id'):read('*a')"),
+            Frame::Error(_)
+        ));
+    }
+
+    #[test]
+    fn safe_libraries_are_loaded() {
+        let db = Db::new(Config::default());
+        let script = "return {string.upper('a'), table.concat({'b', 'c'}), math.floor(1.5)}";
+        let frame = eval_str(&db, script);
+        assert!(
+            matches!(&frame, Frame::Array(items) if items.len() == 3 && items[0] == "A" && items[1] == "bc")
+        );
+        let script = "local ok = pcall(error, 'x') return ok == false";
+        assert!(matches!(eval_str(&db, script), Frame::Integer(1)));
+    }
+
+    //在另一个线程中执行脚本，等到脚本开始执行后返回
+    fn spawn(db: &Db, script: &'static str) -> std::thread::JoinHandle<Frame> {
+        let runner = db.clone();
+        let handle = std::thread::spawn(move || eval_str(&runner, script));
+        while !db.running_script().is_running() {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        handle
+    }
+
+    #[test]
+    fn script_kill_stops_a_running_script() {
+        let db = Db::new(Config::default());
+        assert!(matches!(db.running_script().kill(), Frame::Error(e) if e.starts_with("NOTBUSY")));
+        let handle = spawn(&db, "while true do end");
+        assert!(matches!(db.running_script().kill(), Frame::Simple(_)));
+        let reply = handle.join().unwrap();
+        assert!(matches!(reply, Frame::Error(e) if e.contains("SCRIPT KILL")));
+        assert!(!db.running_script().is_running());
+    }
+
+    #[test]
+    fn pcall_cannot_swallow_script_kill() {
+        let db = Db::new(Config::default());
+        let handle = spawn(
+            &db,
+            "while true do pcall(function() while true do end end) end",
+        );
+        db.running_script().kill();
+        assert!(matches!(handle.join().unwrap(), Frame::Error(_)));
+    }
+
+    #[test]
+    fn scripts_that_wrote_are_unkillable() {
+        let db = Db::new(Config::default());
+        let handle = spawn(&db, "redis.call('SET', 'key', 'value') while true do end");
+        while !db.running_script().wrote.load(Ordering::Relaxed) {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        let reply = db.running_script().kill();
+        assert!(matches!(reply, Frame::Error(e) if e.starts_with("UNKILLABLE")));
+        //绕过SCRIPT KILL的检查直接终止，结束测试
+        db.running_script().kill.store(true, Ordering::Relaxed);
+        handle.join().unwrap();
+    }
+
+    #[tokio::test]
+    async fn waiting_commands_turn_busy_after_the_limit() {
+        let db = Db::new(Config::default());
+        assert!(db.running_script().wait(Duration::ZERO).await);
+        let handle = spawn(&db, "while true do end");
+        assert!(!db.running_script().wait(Duration::from_millis(20)).await);
+        db.running_script().kill();
+        assert!(db.running_script().wait(Duration::from_secs(60)).await);
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn busy_exemptions() {
+        let args = |args: &[&'static str]| -> Vec<Bytes> {
+            args.iter()
+                .map(|arg| Bytes::from_static(arg.as_bytes()))
+                .collect()
+        };
+        assert!(allowed_when_busy(&args(&["script", "KILL"])));
+        assert!(allowed_when_busy(&args(&["SHUTDOWN", "nosave"])));
+        assert!(!allowed_when_busy(&args(&["SHUTDOWN"])));
+        assert!(!allowed_when_busy(&args(&["GET", "key"])));
+    }
+
+    #[test]
+    fn binary_chunks_are_rejected() {
+        let db = Db::new(Config::default());
+        assert!(matches!(
+            eval(&db, None, b"\x1bLua", vec![], vec![]),
+            Frame::Error(_)
+        ));
+    }
+
+    #[test]
+    fn script_commands_are_checked_against_the_caller_acl() {
+        let db = Db::new(Config::default());
+        let rules = ["on", "nopass", "~app:*", "+eval", "+get", "+set"];
+        let rules = rules.map(str::to_string);
+        db.update_acl(|acl| acl.set_user("limited", &rules))
+            .unwrap();
+        let run = |script: &str| eval(&db, Some("limited"), script.as_bytes(), vec![], vec![]);
+        assert!(matches!(
+            run("redis.call('SET', 'app:1', 'v') return redis.call('GET', 'app:1')"),
+            Frame::Bulk(value) if value == "v"
+        ));
+        assert!(matches!(
+            run("return redis.pcall('GET', 'other')"),
+            Frame::Error(e) if e == "NOPERM No permissions to access a key"
+        ));
+        assert!(matches!(
+            run("return redis.pcall('DEL', 'app:1')"),
+            Frame::Error(e) if e.starts_with("NOPERM User limited has no permissions")
+        ));
+        assert!(matches!(
+            run("return redis.call('GET', 'other')"),
+            Frame::Error(_)
+        ));
+        //没有用户时不检查，例如复制与AOF重放
+        assert!(matches!(
+            eval(
+                &db,
+                None,
+                b"return redis.call('GET', 'other')",
+                vec![],
+                vec![]
+            ),
+            Frame::Null
+        ));
+    }
+
+    #[test]
+    fn reply_too_large() {
+        let mut config = Config::default();
+        config.set("max-reply-size", "1024").unwrap();
+        let db = Db::new(config);
+        //嵌套的表同样计入回复的大小
+        let nested = "local t = {} for i = 1, 100 do t[i] = {i, 'x'} end return t";
+        assert!(matches!(eval_str(&db, nested), Frame::Error(e) if e == REPLY_TOO_LARGE));
+        let small = eval_str(&db, "return {1, {2, 'x'}}");
+        assert!(matches!(small, Frame::Array(items) if items.len() == 2));
+    }
+}
@@ -0,0 +1,415 @@
+use crate::lib;
+use crate::lib::aof;
+use crate::lib::cmd::{table, Command};
+use crate::lib::db::{now_millis, Db};
+use crate::lib::frame::Frame;
+use crate::lib::propagate;
+use crate::lib::rdb::{self, Snapshot};
+use crate::lib::upstream::Upstream;
+use bytes::Bytes;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::task::AbortHandle;
+
+//连接断开之后重连的间隔
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+//向主节点确认偏移量的间隔，同时检查连接是否超时
+const ACK_INTERVAL: Duration = Duration::from_secs(1);
+
+///复制：从节点一侧的状态
+///
+/// 设置了replicaof时在后台任务中连接主节点，全量或部分同步之后执行主节点发来的命令流。
+/// 连接断开之后每秒重连一次，以上次处理到的位置请求部分同步
+#[derive(Debug, Default)]
+pub(crate) struct MasterLink {
+    //正在复制的主节点与复制的任务，不是从节点时为None
+    master: Mutex<Option<((String, u16), AbortHandle)>>,
+    //与主节点的连接是否正常
+    up: AtomicBool,
+    //是否正在全量同步
+    syncing: AtomicBool,
+    //上次从主节点收到数据的unix时间戳（毫秒）
+    last_io: AtomicU64,
+    //在主节点命令流中处理到的位置，还没有同步过时为None
+    position: Mutex<Option<Position>>,
+}
+
+//在主节点命令流中的位置
+#[derive(Debug, Clone)]
+struct Position {
+    //主节点的复制id
+    replid: String,
+    //已经处理的复制偏移量
+    offset: u64,
+    //命令流当前所在的逻辑数据库，部分同步补上的命令流不一定以SELECT开头
+    index: usize,
+}
+
+impl MasterLink {
+    ///正在复制的主节点的地址与端口，不是从节点时为None
+    pub(crate) fn master(&self) -> Option<(String, u16)> {
+        let master = self.master.lock().unwrap();
+        master.as_ref().map(|(master, _)| master.clone())
+    }
+
+    ///与主节点的连接是否正常
+    pub(crate) fn is_up(&self) -> bool {
+        self.up.load(Ordering::Relaxed)
+    }
+
+    ///是否正在全量同步
+    pub(crate) fn is_syncing(&self) -> bool {
+        self.syncing.load(Ordering::Relaxed)
+    }
+
+    ///距上次从主节点收到数据的秒数
+    pub(crate) fn last_io_secs(&self) -> u64 {
+        now_millis().saturating_sub(self.last_io.load(Ordering::Relaxed)) / 1000
+    }
+
+    ///已经处理的主节点的复制偏移量
+    pub(crate) fn offset(&self) -> u64 {
+        let position = self.position.lock().unwrap();
+        position.as_ref().map_or(0, |position| position.offset)
+    }
+
+    ///停止复制，关闭服务器时调用，配置保持不变
+    pub(crate) fn stop(&self) {
+        if let Some((_, task)) = self.master.lock().unwrap().take() {
+            task.abort();
+        }
+        self.up.store(false, Ordering::Relaxed);
+        self.syncing.store(false, Ordering::Relaxed);
+    }
+}
+
+///按replicaof开始或停止复制，启动时与修改配置之后调用
+///
+/// 主节点改变时放弃之前同步到的位置，从新的主节点全量同步
+pub(crate) fn configure(db: &Db) {
+    let target = db.config().replica_of();
+    let link = db.master_link();
+    let current = link.master();
+    if current == target {
+        return;
+    }
+    link.stop();
+    *link.position.lock().unwrap() = None;
+    match target {
+        Some((host, port)) => {
+            tracing::info!("开始复制主节点{}:{}", host, port);
+            let task = tokio::spawn(run(db.clone(), host.clone(), port)).abort_handle();
+            *link.master.lock().unwrap() = Some(((host, port), task));
+        }
+        //不再沿用主节点的复制id，之后的命令流是自己的
+        None => {
+            db.replication().change_replid();
+            tracing::info!("不再复制主节点，成为主节点");
+        }
+    }
+}
+
+//复制的任务：连接主节点并执行命令流，断开之后重连
+async fn run(db: Db, host: String, port: u16) {
+    loop {
+        let result = replicate(&db, &host, port).await;
+        let link = db.master_link();
+        link.up.store(false, Ordering::Relaxed);
+        link.syncing.store(false, Ordering::Relaxed);
+        match result {
+            Ok(()) => tracing::warn!("主节点关闭了复制连接"),
+            Err(e) => tracing::warn!(error = %e, "与主节点的复制连接断开"),
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+//连接主节点，握手并同步，之后执行命令流直到连接断开
+async fn replicate(db: &Db, host: &str, port: u16) -> lib::Result<()> {
+    let (timeout, auth, listening_port) = {
+        let config = db.config();
+        let timeout = Duration::from_secs(config.repl_timeout());
+        (timeout, config.master_auth().to_string(), config.port())
+    };
+    let mut upstream = Upstream::connect(host, port, timeout).await?;
+    //PING只确认连接可用，要求认证的主节点回复的NOAUTH同样可以接受
+    upstream.call(&["PING"]).await?;
+    if !auth.is_empty() {
+        if let Frame::Error(e) = upstream.call(&["AUTH", &auth]).await? {
+            return Err(lib::Error::Auth(e));
+        }
+    }
+    let listening_port = listening_port.to_string();
+    expect_ok(
+        upstream
+            .call(&["REPLCONF", "listening-port", &listening_port])
+            .await?,
+    )?;
+    expect_ok(upstream.call(&["REPLCONF", "capa", "psync2"]).await?)?;
+    let link = db.master_link();
+    let previous = link.position.lock().unwrap().clone();
+    let (replid, offset) = match &previous {
+        Some(position) => (position.replid.clone(), (position.offset + 1).to_string()),
+        None => ("?".to_string(), "-1".to_string()),
+    };
+    let mut position = match upstream.call(&["PSYNC", &replid, &offset]).await? {
+        Frame::Simple(line) if line.starts_with("FULLRESYNC") => {
+            let mut parts = line.split_ascii_whitespace().skip(1);
+            let replid = parts.next().unwrap_or_default().to_string();
+            let offset = parts
+                .next()
+                .and_then(|offset| offset.parse().ok())
+                .ok_or("invalid FULLRESYNC reply from master")?;
+            link.syncing.store(true, Ordering::Relaxed);
+            tracing::info!("开始从主节点全量同步");
+            let payload = upstream.read_payload().await?;
+            let databases = db.databases();
+            let snapshot = tokio::task::spawn_blocking(move || rdb::decode(&payload, databases))
+                .await?
+                .ok_or("invalid snapshot from master")?;
+            let loaded = load(db, snapshot);
+            link.syncing.store(false, Ordering::Relaxed);
+            tracing::info!(keys = loaded, "已从主节点载入数据");
+            db.replication().set_replid(&replid);
+            Position {
+                replid,
+                offset,
+                index: 0,
+            }
+        }
+        Frame::Simple(line) if line.starts_with("CONTINUE") => {
+            let mut position = previous.ok_or("unexpected CONTINUE reply from master")?;
+            //主节点的复制id可能已经改变
+            if let Some(replid) = line.split_ascii_whitespace().nth(1) {
+                position.replid = replid.to_string();
+                db.replication().set_replid(replid);
+            }
+            tracing::info!("与主节点部分同步");
+            position
+        }
+        Frame::Error(e) => return Err(e.into()),
+        reply => return Err(format!("unexpected reply to PSYNC: {}", reply).into()),
+    };
+    *link.position.lock().unwrap() = Some(position.clone());
+    link.last_io.store(now_millis(), Ordering::Relaxed);
+    link.up.store(true, Ordering::Relaxed);
+    let mut current = db.select(position.index).unwrap_or_else(|| db.clone());
+    //MULTI之后排队的命令与其占用的字节数，EXEC时整体执行，偏移量也在那时才前进
+    let mut transaction: Option<Vec<(Command, Vec<Bytes>)>> = None;
+    let mut pending = 0;
+    let mut ack_tick = tokio::time::interval(ACK_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = ack_tick.tick() => {
+                if link.last_io_secs() >= timeout.as_secs() {
+                    return Err("timeout, no data received from master".into());
+                }
+                ack(&mut upstream, position.offset).await?;
+            }
+            frame = upstream.read_frame() => {
+                let (frame, len) = match frame? {
+                    Some(frame) => frame,
+                    None => return Ok(()),
+                };
+                link.last_io.store(now_millis(), Ordering::Relaxed);
+                pending += len as u64;
+                let getack = apply(&mut current, &mut transaction, frame);
+                if transaction.is_none() {
+                    position.offset += pending;
+                    position.index = current.index();
+                    pending = 0;
+                    *link.position.lock().unwrap() = Some(position.clone());
+                }
+                if getack {
+                    ack(&mut upstream, position.offset).await?;
+                }
+            }
+        }
+    }
+}
+
+//全量同步：清空所有数据之后载入主节点的快照，返回载入的键数
+//
+// 载入期间独占顺序锁，不会有写命令夹在中间。开启了AOF时重写，让文件与新的数据一致
+fn load(db: &Db, snapshot: Snapshot) -> usize {
+    let (removed, loaded) = db.ordered_exclusively(|| {
+        let removed = db.flush_all();
+        (removed, rdb::restore(db, snapshot))
+    });
+    tokio::task::spawn_blocking(move || drop(removed));
+    if db.aof().is_enabled() {
+        //已经在重写时，缓冲区中没有这次载入，重写完成之后文件与数据仍不一致，这里只能尽力而为
+        let _ = aof::bgrewrite(db);
+    }
+    loaded
+}
+
+//执行主节点发来的一条命令，返回主节点是否要求立即确认偏移量
+//
+// 命令与客户端发来的一样通过顺序锁执行，开启了AOF或有下级从节点时同样传播出去
+fn apply(db: &mut Db, transaction: &mut Option<Vec<(Command, Vec<Bytes>)>>, frame: Frame) -> bool {
+    let args = table::args(&frame);
+    let cmd = match Command::from_frame(frame) {
+        Ok(cmd) => cmd,
+        Err(e) => {
+            tracing::warn!("无法执行主节点发来的命令：{}", e);
+            return false;
+        }
+    };
+    match cmd {
+        Command::Multi => *transaction = Some(vec![]),
+        Command::Exec => {
+            if let Some(queued) = transaction.take() {
+                exec(db, queued);
+            }
+        }
+        Command::ReplConf(cmd) => return cmd.is_getack(),
+        cmd => match transaction {
+            Some(queued) => queued.push((cmd, args)),
+            None => replay(db, cmd, args),
+        },
+    }
+    false
+}
+
+//执行事务之外的一条命令，SELECT切换db
+fn replay(db: &mut Db, cmd: Command, args: Vec<Bytes>) {
+    if let Command::Select(cmd) = cmd {
+        cmd.apply(db);
+        return;
+    }
+    propagate::record(
+        db,
+        || cmd.apply_now(db),
+        |reply| propagate::rewrite(db, args, reply),
+    );
+}
+
+//整体执行主节点发来的事务
+fn exec(db: &mut Db, queued: Vec<(Command, Vec<Bytes>)>) {
+    let mut current = db.clone();
+    db.ordered(|| {
+        db.atomically(|| {
+            let mut written = vec![];
+            for (cmd, args) in queued {
+                let changes = db.stats().changes.get();
+                let index = current.index();
+                let reply = match cmd {
+                    Command::Select(cmd) => cmd.apply(&mut current),
+                    cmd => cmd.apply_now(&current),
+                };
+                if db.is_propagating() && db.stats().changes.get() != changes {
+                    written.extend(
+                        propagate::rewrite(&current, args, &reply).map(|args| (index, args)),
+                    );
+                }
+            }
+            db.propagate(written);
+        })
+    });
+    *db = current;
+}
+
+//确认已经处理的偏移量
+async fn ack(upstream: &mut Upstream, offset: u64) -> io::Result<()> {
+    upstream
+        .send(&["REPLCONF", "ACK", &offset.to_string()])
+        .await
+}
+
+//握手时主节点回复错误则放弃这次连接
+fn expect_ok(reply: Frame) -> lib::Result<()> {
+    match reply {
+        Frame::Error(e) => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::config::Config;
+    use crate::lib::conn::Connection;
+    use tokio::net::TcpListener;
+
+    const MASTER_REPLID: &str = "8d0c2b1e4a5f6e7d8c9b0a1f2e3d4c5b6a7f8e9d";
+
+    //只会握手并回复FULLRESYNC与空快照的主节点，offset为全量同步的偏移量
+    async fn fake_master(listener: TcpListener, offset: u64) {
+        let (socket, _) = listener.accept().await.unwrap();
+        let mut conn = Connection::new(socket);
+        while let Some(frame) = conn.read_frame().await.unwrap() {
+            let args = table::args(&frame);
+            let name = args[0].to_ascii_uppercase();
+            match &name[..] {
+                b"PING" => conn.write_frame(&Frame::Simple("PONG".into())).await,
+                //与真实的主节点一样不回复ACK，否则回复会被当作复制流计入偏移量
+                b"REPLCONF" if args[1].eq_ignore_ascii_case(b"ACK") => Ok(()),
+                b"REPLCONF" => conn.write_frame(&Frame::Simple("OK".into())).await,
+                b"PSYNC" => {
+                    let reply = format!("FULLRESYNC {} {}", MASTER_REPLID, offset);
+                    conn.write_frame(&Frame::Simple(reply)).await.unwrap();
+                    let payload = rdb::encode(&Snapshot::default());
+                    conn.write_bulk_stream(payload.len() as u64, &payload[..], false)
+                        .await
+                }
+                _ => Ok(()),
+            }
+            .unwrap();
+        }
+    }
+
+    //复制以offset全量同步的主节点，等到与主节点的连接正常
+    async fn replicate_fake_master(db: &Db, offset: u64) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(fake_master(listener, offset));
+        db.update_config(|config| config.set("replicaof", &format!("127.0.0.1 {}", port)))
+            .unwrap();
+        configure(db);
+        let synced = async {
+            while !db.master_link().is_up() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), synced)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn replica_adopts_the_master_replid_after_a_full_sync() {
+        let db = Db::new(Config::default());
+        let own = db.replication().replid();
+        replicate_fake_master(&db, 0).await;
+        assert_eq!(db.replication().replid(), MASTER_REPLID);
+        //成为主节点之后换用自己的复制id
+        db.update_config(|config| config.set("replicaof", "no one"))
+            .unwrap();
+        configure(&db);
+        let replid = db.replication().replid();
+        assert_ne!(replid, MASTER_REPLID);
+        assert_ne!(replid, own);
+    }
+
+    #[tokio::test]
+    async fn info_reports_the_processed_offset_on_a_replica() {
+        let db = Db::new(Config::default());
+        replicate_fake_master(&db, 1000).await;
+        let info = Frame::Array(
+            ["INFO", "replication"]
+                .iter()
+                .map(|arg| Frame::Bulk(Bytes::from_static(arg.as_bytes())))
+                .collect(),
+        );
+        let info = match Command::from_frame(info).unwrap().apply_now(&db) {
+            Frame::Bulk(info) => String::from_utf8(info.to_vec()).unwrap(),
+            frame => panic!("unexpected reply {:?}", frame),
+        };
+        assert!(info.contains("role:slave\r\n"), "{}", info);
+        assert!(info.contains("slave_repl_offset:1000\r\n"), "{}", info);
+        assert!(info.contains("master_repl_offset:1000\r\n"), "{}", info);
+    }
+}
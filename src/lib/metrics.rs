@@ -0,0 +1,325 @@
+use crate::lib::allocator;
+use crate::lib::db::Db;
+use crate::lib::stats::CommandStat;
+use crate::lib::value::TYPE_NAMES;
+use std::fmt::Write;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+//请求头的最大长度，超出时直接关闭连接
+const MAX_REQUEST: usize = 8 * 1024;
+//命令耗时直方图的上界（微秒），从100微秒到10秒
+const BOUNDS: [u64; 10] = [
+    100, 500, 1_000, 5_000, 10_000, 50_000, 100_000, 500_000, 1_000_000, 10_000_000,
+];
+
+///处理一个抓取指标的HTTP连接
+///
+/// 只支持GET /metrics，回复之后关闭连接，不支持keep-alive
+pub(crate) async fn respond(mut stream: TcpStream, db: Db) {
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        match stream.read(&mut buf).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => request.extend_from_slice(&buf[..n]),
+        }
+        if request.len() > MAX_REQUEST {
+            return;
+        }
+    }
+    let line = String::from_utf8_lossy(&request);
+    let mut parts = line.split_ascii_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", render(&db)),
+        (Some("GET"), _) => ("404 Not Found", "Not Found\n".to_string()),
+        _ => ("405 Method Not Allowed", "Method Not Allowed\n".to_string()),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+///以Prometheus的文本格式输出当前的指标
+fn render(db: &Db) -> String {
+    let stats = db.stats();
+    let mut out = String::new();
+    let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, String)]| {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} {}", name, kind);
+        for (labels, value) in samples {
+            let _ = writeln!(out, "{}{} {}", name, labels, value);
+        }
+    };
+    let single = |value: u64| vec![(String::new(), value.to_string())];
+    metric(
+        "redis_uptime_in_seconds",
+        "gauge",
+        "Number of seconds since the server started",
+        &single(stats.uptime().as_secs()),
+    );
+    metric(
+        "redis_connected_clients",
+        "gauge",
+        "Number of client connections",
+        &single(db.clients().len() as u64),
+    );
+    metric(
+        "redis_blocked_clients",
+        "gauge",
+        "Number of clients waiting on a blocking command",
+        &single(db.blocked_clients() as u64),
+    );
+    metric(
+        "redis_connections_received_total",
+        "counter",
+        "Total number of connections accepted",
+        &single(stats.connections_received.get()),
+    );
+    metric(
+        "redis_rejected_connections_total",
+        "counter",
+        "Connections rejected because of the maxclients limit",
+        &single(stats.rejected_connections.get()),
+    );
+    let closed: Vec<_> = stats
+        .closed_connections()
+        .map(|(reason, count)| (format!("{{reason=\"{}\"}}", reason), count.to_string()))
+        .collect();
+    metric(
+        "redis_closed_connections_total",
+        "counter",
+        "Client connections closed, by reason",
+        &closed,
+    );
+    metric(
+        "redis_commands_processed_total",
+        "counter",
+        "Total number of commands processed",
+        &single(stats.commands_processed.get()),
+    );
+    metric(
+        "redis_keyspace_hits_total",
+        "counter",
+        "Successful key lookups by read commands",
+        &single(stats.keyspace_hits.get()),
+    );
+    metric(
+        "redis_keyspace_misses_total",
+        "counter",
+        "Failed key lookups by read commands",
+        &single(stats.keyspace_misses.get()),
+    );
+    metric(
+        "redis_expired_keys_total",
+        "counter",
+        "Total number of keys removed because they expired",
+        &single(stats.expired_keys.get()),
+    );
+    metric(
+        "redis_evicted_keys_total",
+        "counter",
+        "Total number of keys evicted because of the maxmemory limit",
+        &single(stats.evicted_keys.get()),
+    );
+    metric(
+        "redis_memory_used_bytes",
+        "gauge",
+        "Bytes currently allocated by the server",
+        &single(allocator::allocated() as u64),
+    );
+    metric(
+        "redis_memory_used_peak_bytes",
+        "gauge",
+        "Peak bytes allocated by the server",
+        &single(allocator::peak() as u64),
+    );
+    metric(
+        "redis_memory_used_dataset_bytes",
+        "gauge",
+        "Estimated bytes used by keys and values",
+        &single(db.used_memory() as u64),
+    );
+    metric(
+        "redis_memory_max_bytes",
+        "gauge",
+        "The maxmemory setting, 0 when unlimited",
+        &single(db.config().max_memory()),
+    );
+    metric(
+        "redis_rdb_changes_since_last_save",
+        "gauge",
+        "Number of changes since the last successful snapshot",
+        &single(db.saves().changes_since(stats.changes.get())),
+    );
+    metric(
+        "redis_rdb_last_save_timestamp_seconds",
+        "gauge",
+        "Unix time of the last successful snapshot",
+        &single(db.saves().last_save()),
+    );
+    let databases: Vec<Db> = (0..db.databases())
+        .filter_map(|index| db.select(index))
+        .filter(|db| db.key_count() > 0)
+        .collect();
+    let keys: Vec<(String, String)> = databases
+        .iter()
+        .map(|db| (db_label(db), db.key_count().to_string()))
+        .collect();
+    metric(
+        "redis_db_keys",
+        "gauge",
+        "Number of keys per database",
+        &keys,
+    );
+    let expiring: Vec<(String, String)> = databases
+        .iter()
+        .map(|db| (db_label(db), db.expires().0.to_string()))
+        .collect();
+    metric(
+        "redis_db_keys_expiring",
+        "gauge",
+        "Number of keys with an expiration per database",
+        &expiring,
+    );
+    let by_type: Vec<(String, String)> = databases
+        .iter()
+        .flat_map(|db| {
+            TYPE_NAMES
+                .iter()
+                .zip(db.type_counts())
+                .map(move |(name, count)| {
+                    let labels = format!("{{db=\"db{}\",type=\"{}\"}}", db.index(), name);
+                    (labels, count.to_string())
+                })
+        })
+        .collect();
+    metric(
+        "redis_db_keys_by_type",
+        "gauge",
+        "Number of keys per database and value type",
+        &by_type,
+    );
+    let deleted: Vec<(String, String)> = TYPE_NAMES
+        .iter()
+        .zip(db.type_deletions())
+        .map(|(name, count)| (format!("{{type=\"{}\"}}", name), count.to_string()))
+        .collect();
+    metric(
+        "redis_deleted_keys_total",
+        "counter",
+        "Total number of keys removed per value type, including expired and evicted keys",
+        &deleted,
+    );
+    let commands = stats.commands.cumulative(&BOUNDS);
+    let per_command = |value: fn(&CommandStat) -> u64| {
+        commands
+            .iter()
+            .map(|(stat, _)| {
+                (
+                    format!("{{cmd=\"{}\"}}", stat.name),
+                    value(stat).to_string(),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    metric(
+        "redis_commands_total",
+        "counter",
+        "Calls per command",
+        &per_command(|stat| stat.calls),
+    );
+    metric(
+        "redis_commands_failed_calls_total",
+        "counter",
+        "Calls per command that replied with an error",
+        &per_command(|stat| stat.failed_calls),
+    );
+    metric(
+        "redis_commands_rejected_calls_total",
+        "counter",
+        "Calls per command rejected before execution",
+        &per_command(|stat| stat.rejected_calls),
+    );
+    let mut histogram = vec![];
+    for (stat, counts) in &commands {
+        for (bound, count) in BOUNDS.iter().zip(counts) {
+            let le = *bound as f64 / 1_000_000.0;
+            histogram.push((
+                format!("_bucket{{cmd=\"{}\",le=\"{}\"}}", stat.name, le),
+                count.to_string(),
+            ));
+        }
+        histogram.push((
+            format!("_bucket{{cmd=\"{}\",le=\"+Inf\"}}", stat.name),
+            stat.calls.to_string(),
+        ));
+        histogram.push((
+            format!("_sum{{cmd=\"{}\"}}", stat.name),
+            (stat.usec as f64 / 1_000_000.0).to_string(),
+        ));
+        histogram.push((
+            format!("_count{{cmd=\"{}\"}}", stat.name),
+            stat.calls.to_string(),
+        ));
+    }
+    metric(
+        "redis_command_duration_seconds",
+        "histogram",
+        "Execution time per command",
+        &histogram,
+    );
+    out
+}
+
+//数据库的标签
+fn db_label(db: &Db) -> String {
+    format!("{{db=\"db{}\"}}", db.index())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::cmd::Command;
+    use crate::lib::config::Config;
+    use crate::lib::frame::Frame;
+    use bytes::Bytes;
+
+    fn run(db: &Db, args: &[&str]) -> Frame {
+        let frame = Frame::Array(
+            args.iter()
+                .map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes())))
+                .collect(),
+        );
+        Command::from_frame(frame).unwrap().apply_now(db)
+    }
+
+    #[test]
+    fn keys_by_type() {
+        let db = Db::new(Config::default());
+        run(&db, &["RPUSH", "list:1", "a"]);
+        run(&db, &["RPUSH", "list:2", "a", "b"]);
+        run(&db, &["SET", "string", "value"]);
+        let text = render(&db);
+        assert!(text.contains("redis_db_keys_by_type{db=\"db0\",type=\"list\"} 2\n"));
+        assert!(text.contains("redis_db_keys_by_type{db=\"db0\",type=\"string\"} 1\n"));
+        assert!(text.contains("redis_db_keys_by_type{db=\"db0\",type=\"hash\"} 0\n"));
+
+        //弹出最后一个元素与DEL同样算作删除，覆盖为其他类型不算
+        run(&db, &["DEL", "list:1"]);
+        run(&db, &["RPOP", "list:2", "2"]);
+        run(&db, &["SADD", "set", "member"]);
+        run(&db, &["SET", "set", "value"]);
+        let text = render(&db);
+        assert!(text.contains("redis_db_keys_by_type{db=\"db0\",type=\"list\"} 0\n"));
+        assert!(text.contains("redis_db_keys_by_type{db=\"db0\",type=\"string\"} 2\n"));
+        assert!(text.contains("redis_db_keys_by_type{db=\"db0\",type=\"set\"} 0\n"));
+        assert!(text.contains("redis_deleted_keys_total{type=\"list\"} 2\n"));
+        assert!(text.contains("redis_deleted_keys_total{type=\"set\"} 0\n"));
+    }
+}
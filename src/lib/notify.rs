@@ -0,0 +1,155 @@
+use bytes::Bytes;
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+///键空间事件的类别，对应notify-keyspace-events中的字符
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Class {
+    ///与类型无关的命令，如DEL、EXPIRE、RENAME，对应“g”
+    Generic,
+    ///字符串命令，对应“$”
+    String,
+    ///列表命令，对应“l”
+    List,
+    ///集合命令，对应“s”
+    Set,
+    ///哈希命令，对应“h”
+    Hash,
+    ///有序集合命令，对应“z”
+    ZSet,
+    ///流命令，对应“t”
+    Stream,
+    ///键过期，对应“x”
+    Expired,
+    ///键因内存不足被淘汰，对应“e”
+    Evicted,
+}
+
+///写入对应的键空间事件
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Event {
+    ///事件的类别
+    pub(crate) class: Class,
+    ///事件名称，通常是命令名的小写
+    pub(crate) name: &'static str,
+}
+
+impl Event {
+    pub(crate) const fn new(class: Class, name: &'static str) -> Event {
+        Event { class, name }
+    }
+}
+
+///notify-keyspace-events的配置
+///
+/// 类别决定哪些事件会被发布，K与E决定发布到__keyspace@0__还是__keyevent@0__频道，
+/// 两者都没有时不发布任何事件
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Flags(u32);
+
+//发布到__keyspace@0__:<key>，消息为事件名
+const KEYSPACE: u32 = 1 << 0;
+//发布到__keyevent@0__:<event>，消息为键
+const KEYEVENT: u32 = 1 << 1;
+
+//类别与配置中的字符，顺序与redis输出配置时一致
+const CLASSES: [(Class, char); 9] = [
+    (Class::Generic, 'g'),
+    (Class::String, '$'),
+    (Class::List, 'l'),
+    (Class::Set, 's'),
+    (Class::Hash, 'h'),
+    (Class::ZSet, 'z'),
+    (Class::Expired, 'x'),
+    (Class::Evicted, 'e'),
+    (Class::Stream, 't'),
+];
+
+impl Class {
+    //类别在Flags中占用的位，最低的两位留给K与E
+    fn bit(self) -> u32 {
+        1 << (2 + self as u32)
+    }
+}
+
+impl Flags {
+    //“A”代表的全部类别
+    fn all_classes() -> u32 {
+        CLASSES
+            .iter()
+            .fold(0, |bits, (class, _)| bits | class.bit())
+    }
+
+    ///解析notify-keyspace-events的值，包含未知字符时返回None
+    pub(crate) fn parse(text: &str) -> Option<Flags> {
+        let mut bits = 0;
+        for c in text.chars() {
+            bits |= match c {
+                'K' => KEYSPACE,
+                'E' => KEYEVENT,
+                'A' => Flags::all_classes(),
+                c => CLASSES.iter().find(|(_, letter)| *letter == c)?.0.bit(),
+            };
+        }
+        Some(Flags(bits))
+    }
+
+    ///是否需要发布该类别的事件
+    pub(crate) fn enabled(self, class: Class) -> bool {
+        self.0 & (KEYSPACE | KEYEVENT) != 0 && self.0 & class.bit() != 0
+    }
+
+    ///一次事件需要发布的频道与消息，db为键所在的逻辑数据库
+    pub(crate) fn messages(self, db: usize, event: &str, key: &str) -> Vec<(String, Bytes)> {
+        let mut messages = vec![];
+        if self.0 & KEYSPACE != 0 {
+            let channel = format!("__keyspace@{}__:{}", db, key);
+            messages.push((channel, Bytes::from(event.to_string())));
+        }
+        if self.0 & KEYEVENT != 0 {
+            let channel = format!("__keyevent@{}__:{}", db, event);
+            messages.push((channel, Bytes::from(key.to_string())));
+        }
+        messages
+    }
+}
+
+impl Display for Flags {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let all = Flags::all_classes();
+        if self.0 & all == all {
+            write!(f, "A")?;
+        } else {
+            for (class, letter) in CLASSES {
+                if self.0 & class.bit() != 0 {
+                    write!(f, "{}", letter)?;
+                }
+            }
+        }
+        if self.0 & KEYSPACE != 0 {
+            write!(f, "K")?;
+        }
+        if self.0 & KEYEVENT != 0 {
+            write!(f, "E")?;
+        }
+        Ok(())
+    }
+}
+
+///当前生效的notify-keyspace-events配置，可以在运行期间修改
+#[derive(Debug, Default)]
+pub(crate) struct Notifier {
+    flags: AtomicU32,
+}
+
+impl Notifier {
+    ///当前的配置
+    pub(crate) fn flags(&self) -> Flags {
+        Flags(self.flags.load(Ordering::Relaxed))
+    }
+
+    ///修改配置
+    pub(crate) fn set_flags(&self, flags: Flags) {
+        self.flags.store(flags.0, Ordering::Relaxed);
+    }
+}
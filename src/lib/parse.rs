@@ -1,5 +1,7 @@
 use crate::lib;
 use crate::lib::frame::Frame;
+use bytes::Bytes;
+use std::fmt::{Display, Formatter};
 use std::vec::IntoIter;
 
 ///用于解析命令的实用程序
@@ -17,7 +19,13 @@ pub struct Parse {
 
 #[derive(Debug)]
 pub(crate) enum ParseError {
+    ///命令的参数不足
     EndOfStream,
+    ///解析完成后还有多余的参数
+    Trailing,
+    ///参数不是整数或超出范围
+    NotInteger,
+    ///其他错误
     Other(lib::Error),
 }
 
@@ -40,6 +48,34 @@ impl Parse {
         self.part.next().ok_or(ParseError::EndOfStream)
     }
 
+    ///获取命令中的下一个大容量字节
+    pub(crate) fn next_bytes(&mut self) -> Result<Bytes, ParseError> {
+        match self.next()? {
+            Frame::Simple(text) => Ok(Bytes::from(text.into_bytes())),
+            Frame::Bulk(data) => Ok(data),
+            frame => Err(format!(
+                "解析错误，预计获取的帧为简单字符串或大容量比特，实际获取的为:{}",
+                frame
+            )
+            .into()),
+        }
+    }
+
+    ///获取命令中的下一个整数
+    ///
+    /// 客户端通常以字符串的形式发送数字，因此字符串也会尝试解析为整数
+    pub(crate) fn next_int(&mut self) -> Result<i64, ParseError> {
+        match self.next()? {
+            Frame::Integer(value) => Ok(value),
+            Frame::Simple(text) => text.parse().map_err(|_| ParseError::NotInteger),
+            Frame::Bulk(data) => std::str::from_utf8(&data)
+                .ok()
+                .and_then(|text| text.parse().ok())
+                .ok_or(ParseError::NotInteger),
+            frame => Err(format!("解析错误，预计获取的帧为整数，实际获取的为:{}", frame).into()),
+        }
+    }
+
     ///获取命令中的下一个字符串
     pub(crate) fn next_string(&mut self) -> Result<String, ParseError> {
         match self.next()? {
@@ -55,16 +91,99 @@ impl Parse {
             .into()),
         }
     }
+
+    ///获取命令中的下一个字符串，参数已经读完时返回None
+    pub(crate) fn next_optional_string(&mut self) -> Result<Option<String>, ParseError> {
+        optional(self.next_string())
+    }
+
+    ///获取命令中的下一个大容量字节，参数已经读完时返回None
+    pub(crate) fn next_optional_bytes(&mut self) -> Result<Option<Bytes>, ParseError> {
+        optional(self.next_bytes())
+    }
+
+    ///获取命令中的下一个整数，参数已经读完时返回None
+    pub(crate) fn next_optional_int(&mut self) -> Result<Option<i64>, ParseError> {
+        optional(self.next_int())
+    }
+
+    ///下一个参数是keyword（不区分大小写）时读取它并返回true，否则不读取并返回false
+    ///
+    /// 用于解析可选的关键字参数，例如XGROUP CREATE末尾的MKSTREAM
+    pub(crate) fn next_keyword(&mut self, keyword: &str) -> bool {
+        let matched = match self.part.as_slice().first() {
+            Some(Frame::Simple(text)) => text.eq_ignore_ascii_case(keyword),
+            Some(Frame::Bulk(data)) => data.eq_ignore_ascii_case(keyword.as_bytes()),
+            _ => false,
+        };
+        if matched {
+            self.part.next();
+        }
+        matched
+    }
+
+    ///确认参数已经全部读取，还有多余的参数时返回错误
+    pub(crate) fn finish(&mut self) -> Result<(), ParseError> {
+        match self.part.next() {
+            None => Ok(()),
+            Some(_) => Err(ParseError::Trailing),
+        }
+    }
+}
+
+//参数已经读完时返回None，其余错误照常返回
+fn optional<T>(result: Result<T, ParseError>) -> Result<Option<T>, ParseError> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(ParseError::EndOfStream) => Ok(None),
+        Err(e) => Err(e),
+    }
 }
 
 impl From<&str> for ParseError {
     fn from(text: &str) -> Self {
-        ParseError::Other(text.to_string().into())
+        ParseError::Other(lib::Error::Parse(text.to_string()))
     }
 }
 
 impl From<String> for ParseError {
     fn from(text: String) -> Self {
-        ParseError::Other(text.into())
+        ParseError::Other(lib::Error::Parse(text))
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::EndOfStream => Display::fmt("命令参数不足", f),
+            ParseError::Trailing => Display::fmt("syntax error", f),
+            ParseError::NotInteger => Display::fmt("value is not an integer or out of range", f),
+            ParseError::Other(err) => Display::fmt(err, f),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+///解析时可能出现的错误码，带这些前缀的信息原样回复
+pub(crate) const ERROR_CODES: &[&str] = &["ERR", "NOPROTO", "WRONGPASS", "NOPERM"];
+
+///将解析错误转化为回复给客户端的错误帧
+///
+/// 参数不足统一回复协议错误，其余错误回复其携带的信息，没有错误码的信息补上ERR前缀
+impl From<ParseError> for Frame {
+    fn from(err: ParseError) -> Frame {
+        match err {
+            ParseError::EndOfStream => Frame::Error("ERR Protocol error".to_string()),
+            err => {
+                let msg = err.to_string();
+                let code = msg.split(' ').next().unwrap_or_default();
+                if ERROR_CODES.contains(&code) {
+                    Frame::Error(msg)
+                } else {
+                    Frame::Error(format!("ERR {}", msg))
+                }
+            }
+        }
     }
 }
@@ -0,0 +1,256 @@
+use crate::lib::db::{now_millis, Db};
+use crate::lib::frame::Frame;
+use bytes::Bytes;
+use std::cell::Cell;
+use std::sync::RwLock;
+
+///顺序锁：需要传播写命令时让写命令依次执行，传播出去的命令与实际修改数据的顺序一致
+///
+/// 开启AOF或有从节点时写命令持有写锁依次执行，否则持有读锁并发执行，
+/// 只与开启AOF、全量同步这类需要复制全部数据的操作互斥
+#[derive(Debug, Default)]
+pub(crate) struct Order {
+    lock: RwLock<()>,
+}
+
+thread_local! {
+    //当前线程是否已在顺序锁内，嵌套执行时不再重复加锁
+    static ORDERED: Cell<bool> = const { Cell::new(false) };
+}
+
+impl Order {
+    ///在顺序锁内执行f，exclusive在持有读锁之后判断是否需要独占
+    ///
+    /// 开始与停止传播都在独占时进行，持有读锁期间exclusive的结果不会改变。
+    /// f中嵌套的调用直接执行
+    pub(crate) fn run<R>(&self, exclusive: impl FnOnce() -> bool, f: impl FnOnce() -> R) -> R {
+        if ORDERED.with(Cell::get) {
+            return f();
+        }
+        let _shared;
+        let _exclusive;
+        let shared = self.lock.read().unwrap();
+        if exclusive() {
+            drop(shared);
+            _exclusive = self.lock.write().unwrap();
+        } else {
+            _shared = shared;
+        }
+        Order::enter(f)
+    }
+
+    ///独占顺序锁执行f，期间没有写命令在执行
+    ///
+    /// 嵌套在读锁内时直接执行：持有读锁说明此时没有传播，也就没有需要排在后面的命令
+    pub(crate) fn exclusive<R>(&self, f: impl FnOnce() -> R) -> R {
+        if ORDERED.with(Cell::get) {
+            return f();
+        }
+        let _exclusive = self.lock.write().unwrap();
+        Order::enter(f)
+    }
+
+    //标记当前线程已在顺序锁内执行f
+    fn enter<R>(f: impl FnOnce() -> R) -> R {
+        //f发生panic时同样需要清除标记
+        struct Reset;
+        impl Drop for Reset {
+            fn drop(&mut self) {
+                ORDERED.with(|ordered| ordered.set(false));
+            }
+        }
+        ORDERED.with(|ordered| ordered.set(true));
+        let _reset = Reset;
+        f()
+    }
+}
+
+///编码传播出去的命令流，切换逻辑数据库时先插入SELECT
+#[derive(Debug, Default)]
+pub(crate) struct CommandStream {
+    //上一条命令所在的逻辑数据库
+    selected: Option<usize>,
+}
+
+impl CommandStream {
+    ///编码在各自的逻辑数据库中执行的命令，transaction为true时包装在MULTI与EXEC之间
+    pub(crate) fn encode(
+        &mut self,
+        commands: &[(usize, Vec<Bytes>)],
+        transaction: bool,
+    ) -> Vec<u8> {
+        let mut data = vec![];
+        if transaction {
+            encode(&mut data, &[Bytes::from_static(b"MULTI")]);
+        }
+        for (index, args) in commands {
+            if self.selected != Some(*index) {
+                encode(&mut data, &select(*index));
+                self.selected = Some(*index);
+            }
+            encode(&mut data, args);
+        }
+        if transaction {
+            encode(&mut data, &[Bytes::from_static(b"EXEC")]);
+        }
+        data
+    }
+
+    ///之后的第一条命令之前总是插入SELECT，命令流从头开始时调用
+    pub(crate) fn reset(&mut self) {
+        self.selected = None;
+    }
+}
+
+///在顺序锁内执行f，f修改了数据时传播effect给出的命令
+///
+/// 是否修改以执行前后全局的修改次数判断。其他连接同时触发的过期与淘汰可能让没有修改数据的命令也被传播，
+/// 这样的命令重放时同样不会修改数据
+pub(crate) fn record<R>(
+    db: &Db,
+    f: impl FnOnce() -> R,
+    effect: impl FnOnce(&R) -> Option<Vec<Bytes>>,
+) -> R {
+    db.ordered(|| {
+        let changes = db.stats().changes.get();
+        let result = f();
+        if db.is_propagating() && db.stats().changes.get() != changes {
+            if let Some(args) = effect(&result) {
+                db.propagate(vec![(db.index(), args)]);
+            }
+        }
+        result
+    })
+}
+
+///把执行过的命令改写为重放时结果相同的形式，不需要传播时返回None
+///
+/// 相对的过期时间换算为unix时间戳，SET使用的default-ttl补上PXAT，XADD自动生成的id换成实际的id，EVALSHA换成脚本本身，
+/// 阻塞命令换成对应的非阻塞命令。回复错误的命令没有修改数据，只有脚本可能在出错之前已经执行过写命令
+pub(crate) fn rewrite(db: &Db, mut args: Vec<Bytes>, reply: &Frame) -> Option<Vec<Bytes>> {
+    let name = String::from_utf8_lossy(args.first()?).to_lowercase();
+    if matches!(reply, Frame::Error(_)) && !matches!(name.as_str(), "eval" | "evalsha") {
+        return None;
+    }
+    let now = now_millis() as i64;
+    match name.as_str() {
+        "set" | "getex" => {
+            let mut i = if name == "set" { 3 } else { 2 };
+            while i + 1 < args.len() {
+                let factor = match args[i].to_ascii_uppercase().as_slice() {
+                    b"EX" => 1000,
+                    b"PX" => 1,
+                    _ => {
+                        i += 1;
+                        continue;
+                    }
+                };
+                if let Some(at) = absolute(&args[i + 1], factor, now) {
+                    args[i] = Bytes::from_static(b"PXAT");
+                    args[i + 1] = at;
+                }
+                i += 2;
+            }
+            //没有指定过期时间的SET使用了default-ttl，重放时不能依赖对方的配置
+            if name == "set" && args.len() > 2 && db.config().default_ttl().is_some() {
+                let explicit = args[3..].iter().any(|arg| {
+                    [&b"EX"[..], b"PX", b"EXAT", b"PXAT", b"KEEPTTL", b"PERSIST"]
+                        .iter()
+                        .any(|option| arg.eq_ignore_ascii_case(option))
+                });
+                let key = String::from_utf8_lossy(&args[1]).into_owned();
+                if !explicit {
+                    if let Some(Some(at)) = db.read(&key, |entry| entry.expires_at) {
+                        args.push(Bytes::from_static(b"PXAT"));
+                        args.push(Bytes::from(at.to_string()));
+                    }
+                }
+            }
+        }
+        "expire" | "pexpire" if args.len() > 2 => {
+            let factor = if name == "expire" { 1000 } else { 1 };
+            if let Some(at) = absolute(&args[2], factor, now) {
+                args[0] = Bytes::from_static(b"PEXPIREAT");
+                args[2] = at;
+            }
+        }
+        "restore" if args.len() > 3 => {
+            let abs_ttl = args[4..]
+                .iter()
+                .any(|arg| arg.eq_ignore_ascii_case(b"ABSTTL"));
+            let relative = int(&args[2]).is_some_and(|ttl| ttl > 0);
+            if !abs_ttl && relative {
+                if let Some(at) = absolute(&args[2], 1, now) {
+                    args[2] = at;
+                    args.push(Bytes::from_static(b"ABSTTL"));
+                }
+            }
+        }
+        "xadd" => {
+            if let Frame::Bulk(id) = reply {
+                let auto = args
+                    .iter()
+                    .skip(2)
+                    .position(|arg| arg.as_ref() == b"*" || arg.ends_with(b"-*"));
+                if let Some(i) = auto {
+                    args[i + 2] = id.clone();
+                }
+            }
+        }
+        "blpop" | "brpop" => {
+            let key = match reply {
+                Frame::Array(items) => match items.first() {
+                    Some(Frame::Bulk(key)) => key.clone(),
+                    _ => return None,
+                },
+                _ => return None,
+            };
+            let pop: &'static [u8] = if name == "blpop" { b"LPOP" } else { b"RPOP" };
+            args = vec![Bytes::from_static(pop), key];
+        }
+        "blmove" => {
+            args[0] = Bytes::from_static(b"LMOVE");
+            args.truncate(5);
+        }
+        "brpoplpush" => {
+            args[0] = Bytes::from_static(b"RPOPLPUSH");
+            args.truncate(3);
+        }
+        "evalsha" if args.len() > 1 => {
+            let script = db.script(&String::from_utf8_lossy(&args[1]))?;
+            args[0] = Bytes::from_static(b"EVAL");
+            args[1] = script;
+        }
+        _ => {}
+    }
+    Some(args)
+}
+
+///把命令编码为RESP数组追加到data
+pub(crate) fn encode(data: &mut Vec<u8>, args: &[Bytes]) {
+    data.extend_from_slice(format!("*{}\r\n", args.len()).as_bytes());
+    for arg in args {
+        data.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+        data.extend_from_slice(arg);
+        data.extend_from_slice(b"\r\n");
+    }
+}
+
+///切换到index号逻辑数据库的SELECT命令
+pub(crate) fn select(index: usize) -> [Bytes; 2] {
+    [
+        Bytes::from_static(b"SELECT"),
+        Bytes::from(index.to_string()),
+    ]
+}
+
+//参数为整数时解析
+fn int(arg: &[u8]) -> Option<i64> {
+    std::str::from_utf8(arg).ok()?.parse().ok()
+}
+
+//把相对的时间乘以factor换算为毫秒，再加上now得到unix时间戳（毫秒）
+fn absolute(arg: &[u8], factor: i64, now: i64) -> Option<Bytes> {
+    let at = int(arg)?.checked_mul(factor)?.checked_add(now)?;
+    Some(Bytes::from(at.to_string()))
+}
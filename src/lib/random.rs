@@ -0,0 +1,40 @@
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+thread_local! {
+    //每个线程独立的随机数状态，以标准库的随机哈希种子初始化
+    //状态不能为0，否则之后生成的永远是0
+    static STATE: Cell<u64> = Cell::new(RandomState::new().build_hasher().finish() | 1);
+}
+
+///生成一个伪随机数
+///
+/// 使用xorshift64*，只用于SPOP、HRANDFIELD这类随机挑选元素的命令，不能用于任何安全相关的场景
+pub(crate) fn next_u64() -> u64 {
+    STATE.with(|state| {
+        let mut x = state.get();
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        state.set(x);
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    })
+}
+
+///生成[0, n)范围内的随机数，n不能为0
+pub(crate) fn below(n: usize) -> usize {
+    (next_u64() % n as u64) as usize
+}
+
+///从items中随机挑选count个不重复的元素，count不小于元素个数时返回全部元素
+pub(crate) fn sample<T>(mut items: Vec<T>, count: usize) -> Vec<T> {
+    let count = count.min(items.len());
+    //只需要打乱前count个位置
+    for i in 0..count {
+        let j = i + below(items.len() - i);
+        items.swap(i, j);
+    }
+    items.truncate(count);
+    items
+}
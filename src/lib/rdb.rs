@@ -0,0 +1,352 @@
+use crate::lib;
+use crate::lib::crc64;
+use crate::lib::db::{now_millis, Db, Update};
+use crate::lib::value::dump::{self, Decoder, Encoder};
+use crate::lib::value::Value;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+///已经在保存时再次要求保存的错误
+pub(crate) const IN_PROGRESS: &str = "ERR Background save already in progress";
+
+//文件开头的魔数
+const MAGIC: &[u8] = b"RRDB";
+//文件格式的版本号，格式变化时递增
+const VERSION: usize = 1;
+//切换到另一个逻辑数据库，之后是8字节的数据库编号
+const OP_SELECT_DB: u8 = 0xfe;
+//下一个键的过期时间，之后是8字节的unix时间戳（毫秒）
+const OP_EXPIRE_TIME: u8 = 0xfc;
+//一个键，之后是键名与DUMP格式的值（不带版本号与校验和）
+const OP_KEY: u8 = 0xfb;
+//文件结束，之后是对之前所有字节计算的8字节CRC64
+const OP_EOF: u8 = 0xff;
+//自动保存失败后至少间隔这么多秒再重试
+const RETRY_DELAY: u64 = 5;
+
+///关闭服务器时是否保存快照
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SaveOnExit {
+    ///配置了save规则时保存
+    Default,
+    ///总是保存
+    Save,
+    ///不保存
+    NoSave,
+}
+
+///某一时刻所有非空逻辑数据库的内容：数据库编号，以及其中每个键的名称、值与过期时间
+pub(crate) type Snapshot = Vec<(usize, Vec<(String, Value, Option<u64>)>)>;
+
+///快照的保存状态
+#[derive(Debug)]
+pub(crate) struct SaveState {
+    //上次成功保存时的修改次数
+    saved_changes: AtomicU64,
+    //上次成功保存的unix时间戳（秒）
+    last_save: AtomicU64,
+    //上次开始保存的unix时间戳（秒）
+    last_attempt: AtomicU64,
+    //是否正在保存
+    in_progress: AtomicBool,
+    //上次保存是否成功
+    last_ok: AtomicBool,
+}
+
+impl Default for SaveState {
+    fn default() -> SaveState {
+        SaveState {
+            saved_changes: AtomicU64::new(0),
+            last_save: AtomicU64::new(now_secs()),
+            last_attempt: AtomicU64::new(0),
+            in_progress: AtomicBool::new(false),
+            last_ok: AtomicBool::new(true),
+        }
+    }
+}
+
+impl SaveState {
+    ///上次成功保存的unix时间戳（秒），还没有保存过时是启动的时刻
+    pub(crate) fn last_save(&self) -> u64 {
+        self.last_save.load(Ordering::Relaxed)
+    }
+
+    ///是否正在保存
+    pub(crate) fn in_progress(&self) -> bool {
+        self.in_progress.load(Ordering::Acquire)
+    }
+
+    ///上次保存是否成功
+    pub(crate) fn last_ok(&self) -> bool {
+        self.last_ok.load(Ordering::Relaxed)
+    }
+
+    ///当前的修改次数为changes时，上次成功保存之后的修改次数
+    pub(crate) fn changes_since(&self, changes: u64) -> u64 {
+        changes.saturating_sub(self.saved_changes.load(Ordering::Relaxed))
+    }
+
+    ///从文件载入数据之后调用，changes是载入完成时的修改次数，载入的数据不算作需要保存的修改
+    pub(crate) fn loaded(&self, changes: u64) {
+        self.saved_changes.store(changes, Ordering::Relaxed);
+    }
+
+    //开始保存，已经在保存时返回false
+    fn begin(&self) -> bool {
+        let started = self
+            .in_progress
+            .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+            .is_ok();
+        if started {
+            self.last_attempt.store(now_secs(), Ordering::Relaxed);
+        }
+        started
+    }
+
+    //保存结束，成功时changes是复制快照时的修改次数，失败时为None
+    fn finish(&self, changes: Option<u64>) {
+        if let Some(changes) = changes {
+            self.saved_changes.store(changes, Ordering::Relaxed);
+            self.last_save.store(now_secs(), Ordering::Relaxed);
+        }
+        self.last_ok.store(changes.is_some(), Ordering::Relaxed);
+        self.in_progress.store(false, Ordering::Release);
+    }
+}
+
+///在当前线程上保存快照，写入文件之后才返回
+pub(crate) fn save(db: &Db) -> Result<(), String> {
+    if !db.saves().begin() {
+        return Err(IN_PROGRESS.to_string());
+    }
+    let (snapshot, changes) = snapshot(db);
+    let path = db.config().rdb_path();
+    persist(db, &path, &snapshot, changes).map_err(|e| format!("ERR {}", e))
+}
+
+///在后台保存快照
+///
+/// 数据在当前线程上复制，序列化与写入文件交给阻塞线程池，之后的写入不会出现在这次的快照中
+pub(crate) fn bgsave(db: &Db) -> Result<(), &'static str> {
+    if !db.saves().begin() {
+        return Err(IN_PROGRESS);
+    }
+    let (snapshot, changes) = snapshot(db);
+    let path = db.config().rdb_path();
+    let db = db.clone();
+    tokio::task::spawn_blocking(move || {
+        //失败已经记录在日志与保存状态中
+        let _ = persist(&db, &path, &snapshot, changes);
+    });
+    Ok(())
+}
+
+///启动时载入快照文件，返回载入的键数，文件不存在时什么也不做
+///
+/// 文件损坏或校验和不符时返回错误，与redis一致拒绝在数据不完整的情况下启动。已经过期的键被跳过
+pub(crate) fn load(db: &Db) -> lib::Result<usize> {
+    let path = db.config().rdb_path();
+    let data = match fs::read(&path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    let started = Instant::now();
+    let snapshot = decode(&data, db.databases())
+        .ok_or_else(|| format!("Bad file format reading {}", path.display()))?;
+    let loaded = restore(db, snapshot);
+    db.saves().loaded(db.stats().changes.get());
+    tracing::info!(keys = loaded, elapsed = ?started.elapsed(), "已从快照文件载入数据");
+    Ok(loaded)
+}
+
+///把快照中的键写入对应的逻辑数据库，返回写入的键数，已经过期的键被跳过
+pub(crate) fn restore(db: &Db, snapshot: Snapshot) -> usize {
+    let now = now_millis();
+    let mut loaded = 0;
+    //decode保证了数据库编号在范围内
+    for (db, entries) in snapshot
+        .into_iter()
+        .filter_map(|(index, entries)| Some((db.select(index)?, entries)))
+    {
+        for (key, value, expires_at) in entries {
+            if expires_at.is_some_and(|at| at <= now) {
+                continue;
+            }
+            db.update(key, |_| (Update::Set { value, expires_at }, ()));
+            loaded += 1;
+        }
+    }
+    loaded
+}
+
+///关闭服务器时按mode保存快照，正在后台保存时先等待其结束
+pub(crate) async fn save_on_exit(db: &Db, mode: SaveOnExit) {
+    let should_save = match mode {
+        SaveOnExit::Default => !db.config().save_rules().is_empty(),
+        SaveOnExit::Save => true,
+        SaveOnExit::NoSave => false,
+    };
+    if !should_save {
+        return;
+    }
+    while db.saves().in_progress() {
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    //失败已经记录在日志中，无论如何都继续关闭
+    let _ = save(db);
+}
+
+///按save规则自动在后台保存快照，每秒检查一次
+///
+/// 任意一条规则满足时保存：距离上次成功保存超过规则中的秒数，且期间的修改次数达到规则中的次数。
+/// 上次保存失败时至少间隔RETRY_DELAY秒再重试，以免磁盘故障时不停地复制数据
+pub(crate) async fn save_task(db: Db) {
+    let mut interval = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        interval.tick().await;
+        let state = db.saves();
+        let now = now_secs();
+        //复制快照需要独占键空间，脚本执行期间等到下一次检查
+        if state.in_progress()
+            || db.running_script().is_running()
+            || !state.last_ok()
+                && now.saturating_sub(state.last_attempt.load(Ordering::Relaxed)) < RETRY_DELAY
+        {
+            continue;
+        }
+        let changes = state.changes_since(db.stats().changes.get());
+        let elapsed = now.saturating_sub(state.last_save());
+        let rules = db.config().save_rules();
+        let due = rules
+            .iter()
+            .find(|(secs, min)| changes > 0 && changes >= *min && elapsed >= *secs);
+        if let Some((secs, min)) = due {
+            tracing::info!("{}秒内至少有{}次修改，开始保存快照", secs, min);
+            //其他连接可能恰好执行了BGSAVE
+            let _ = bgsave(&db);
+        }
+    }
+}
+
+///复制出所有数据，同时返回复制开始时的修改次数
+///
+/// 复制期间独占顺序锁，写命令与事务都要等待，快照中不会出现执行了一半的事务；
+/// 读命令照常执行，每次只锁住正在复制的一个分片。复制期间被过期或淘汰的键可能出现在快照中，也可能不出现。
+/// 序列化与写入文件在复制之后进行，不再占用任何锁
+pub(crate) fn snapshot(db: &Db) -> (Snapshot, u64) {
+    db.ordered_exclusively(|| {
+        let changes = db.stats().changes.get();
+        let snapshot = (0..db.databases())
+            .filter_map(|index| db.select(index))
+            .map(|db| {
+                let entries = db.collect(|key, entry| {
+                    Some((key.to_string(), entry.value.clone(), entry.expires_at))
+                });
+                (db.index(), entries)
+            })
+            .filter(|(_, entries)| !entries.is_empty())
+            .collect();
+        (snapshot, changes)
+    })
+}
+
+//将快照写入path并记录结果
+fn persist(db: &Db, path: &Path, snapshot: &Snapshot, changes: u64) -> io::Result<()> {
+    let started = Instant::now();
+    let result = write(path, &encode(snapshot));
+    match &result {
+        Ok(()) => {
+            tracing::info!(path = %path.display(), elapsed = ?started.elapsed(), "快照已保存");
+            db.saves().finish(Some(changes));
+        }
+        Err(e) => {
+            tracing::warn!(path = %path.display(), error = %e, "保存快照失败");
+            db.saves().finish(None);
+        }
+    }
+    result
+}
+
+///将快照编码为文件的内容，全量同步时同样以这种格式发送给从节点
+///
+/// 魔数与版本号之后，每个逻辑数据库以数据库编号开头，随后是其中的键，最后是结束标记与校验和
+pub(crate) fn encode(snapshot: &Snapshot) -> Vec<u8> {
+    let mut encoder = Encoder::default();
+    encoder.put_len(VERSION);
+    for (index, entries) in snapshot {
+        encoder.put_u8(OP_SELECT_DB);
+        encoder.put_u64(*index as u64);
+        for (key, value, expires_at) in entries {
+            if let Some(at) = expires_at {
+                encoder.put_u8(OP_EXPIRE_TIME);
+                encoder.put_u64(*at);
+            }
+            encoder.put_u8(OP_KEY);
+            encoder.put_bytes(key.as_bytes());
+            dump::encode_value(&mut encoder, value);
+        }
+    }
+    encoder.put_u8(OP_EOF);
+    let mut data = MAGIC.to_vec();
+    data.extend_from_slice(&encoder.into_vec());
+    let crc = crc64::checksum(&data);
+    data.extend_from_slice(&crc.to_le_bytes());
+    data
+}
+
+///解析文件的内容，格式或校验和不符、数据库编号不小于databases时返回None
+pub(crate) fn decode(data: &[u8], databases: usize) -> Option<Snapshot> {
+    let (body, crc) = data.split_at(data.len().checked_sub(8)?);
+    if crc64::checksum(body) != u64::from_le_bytes(crc.try_into().ok()?) {
+        return None;
+    }
+    let mut decoder = Decoder::new(body.strip_prefix(MAGIC)?);
+    if decoder.get_len()? != VERSION {
+        return None;
+    }
+    let mut snapshot: Snapshot = vec![];
+    let mut expires_at = None;
+    loop {
+        match decoder.get_u8()? {
+            OP_SELECT_DB => {
+                let index = usize::try_from(decoder.get_u64()?).ok()?;
+                if index >= databases {
+                    return None;
+                }
+                snapshot.push((index, vec![]));
+            }
+            OP_EXPIRE_TIME => expires_at = Some(decoder.get_u64()?),
+            OP_KEY => {
+                let key = String::from_utf8(decoder.get_bytes()?.to_vec()).ok()?;
+                let value = dump::decode_value(&mut decoder)?;
+                snapshot.last_mut()?.1.push((key, value, expires_at.take()));
+            }
+            OP_EOF if decoder.is_empty() => return Some(snapshot),
+            _ => return None,
+        }
+    }
+}
+
+//先写入同一目录下的临时文件并刷到磁盘，再替换原有的文件，写到一半失败时原有的文件保持完整
+fn write(path: &Path, data: &[u8]) -> io::Result<()> {
+    let tmp = path.with_file_name(format!("temp-{}.rdb", std::process::id()));
+    let result = fs::File::create(&tmp)
+        .and_then(|mut file| {
+            file.write_all(data)?;
+            file.sync_all()
+        })
+        .and_then(|()| fs::rename(&tmp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+//当前的unix时间戳（秒）
+fn now_secs() -> u64 {
+    now_millis() / 1000
+}
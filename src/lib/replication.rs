@@ -0,0 +1,396 @@
+use crate::lib::config::Config;
+use crate::lib::db::{now_millis, Db};
+use crate::lib::propagate::{self, CommandStream};
+use crate::lib::random;
+use bytes::Bytes;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, Notify};
+use tokio::time::Instant;
+
+///复制：主节点一侧的状态
+///
+/// 第一个从节点请求同步之后开始传播写命令，命令流按字节累加复制偏移量，
+/// 在发送给在线从节点的同时留在复制积压缓冲区中，断线重连的从节点从中补上缺失的部分
+#[derive(Debug)]
+pub(crate) struct Replication {
+    //复制id，与偏移量一起标识命令流中的位置
+    replid: Mutex<String>,
+    //是否已有从节点请求过同步，之后一直传播写命令
+    active: AtomicBool,
+    //命令流与积压缓冲区
+    stream: Mutex<Stream>,
+    //连接上来的从节点
+    replicas: Mutex<Vec<Arc<Replica>>>,
+    //从节点确认了新的偏移量时通知等待中的WAIT
+    acks: Notify,
+}
+
+//命令流与积压缓冲区
+#[derive(Debug)]
+struct Stream {
+    encoder: CommandStream,
+    //复制偏移量，命令流累计的字节数
+    offset: u64,
+    //命令流最后的一段
+    backlog: VecDeque<u8>,
+    //积压缓冲区的大小（字节）
+    capacity: usize,
+}
+
+impl Stream {
+    //积压缓冲区中第一个字节的偏移量
+    fn first_byte(&self) -> u64 {
+        self.offset - self.backlog.len() as u64 + 1
+    }
+
+    //追加到命令流，发送给所有从节点
+    fn push(&mut self, data: Vec<u8>, replicas: &[Arc<Replica>]) {
+        self.offset += data.len() as u64;
+        self.backlog.extend(&data);
+        let excess = self.backlog.len().saturating_sub(self.capacity);
+        self.backlog.drain(..excess);
+        let data = Bytes::from(data);
+        for replica in replicas {
+            //连接已经关闭的从节点稍后自行移除
+            let _ = replica.sender.send(data.clone());
+        }
+    }
+}
+
+///从节点的同步方式
+#[derive(Debug)]
+pub(crate) enum Resync {
+    ///从积压缓冲区补上缺失的命令流
+    Partial(Vec<u8>),
+    ///发送全部数据，之后的命令流从这个偏移量开始
+    Full(u64),
+}
+
+///连接上来的从节点
+#[derive(Debug)]
+pub(crate) struct Replica {
+    //连接的id
+    id: u64,
+    ip: String,
+    //从节点通过REPLCONF告知的监听端口
+    port: u16,
+    //从节点确认的复制偏移量
+    ack: AtomicU64,
+    //上次确认的unix时间戳（毫秒）
+    ack_at: AtomicU64,
+    //全量同步的数据是否已发送完毕
+    online: AtomicBool,
+    //发往从节点的命令流
+    sender: mpsc::UnboundedSender<Bytes>,
+}
+
+impl Replica {
+    ///创建从节点，同时返回接收命令流的一端
+    pub(crate) fn new(id: u64, ip: &str, port: u16) -> (Replica, mpsc::UnboundedReceiver<Bytes>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let replica = Replica {
+            id,
+            ip: ip.to_string(),
+            port,
+            ack: AtomicU64::new(0),
+            ack_at: AtomicU64::new(now_millis()),
+            online: AtomicBool::new(false),
+            sender,
+        };
+        (replica, receiver)
+    }
+
+    ///从节点的地址
+    pub(crate) fn ip(&self) -> &str {
+        &self.ip
+    }
+
+    ///从节点的监听端口
+    pub(crate) fn port(&self) -> u16 {
+        self.port
+    }
+
+    ///从节点确认的复制偏移量
+    pub(crate) fn ack(&self) -> u64 {
+        self.ack.load(Ordering::Relaxed)
+    }
+
+    ///距上次确认的秒数
+    pub(crate) fn lag(&self) -> u64 {
+        now_millis().saturating_sub(self.ack_at.load(Ordering::Relaxed)) / 1000
+    }
+
+    ///全量同步的数据是否已发送完毕
+    pub(crate) fn is_online(&self) -> bool {
+        self.online.load(Ordering::Relaxed)
+    }
+
+    ///标记为在线，之后只发送命令流
+    pub(crate) fn set_online(&self) {
+        self.online.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Replication {
+    ///按配置创建，复制id随机生成
+    pub(crate) fn new(config: &Config) -> Replication {
+        Replication {
+            replid: Mutex::new(new_replid()),
+            active: AtomicBool::new(false),
+            stream: Mutex::new(Stream {
+                encoder: CommandStream::default(),
+                offset: 0,
+                backlog: VecDeque::new(),
+                capacity: config.repl_backlog_size(),
+            }),
+            replicas: Mutex::new(vec![]),
+            acks: Notify::new(),
+        }
+    }
+
+    ///是否在传播写命令
+    pub(crate) fn is_active(&self) -> bool {
+        self.active.load(Ordering::Acquire)
+    }
+
+    ///复制id
+    pub(crate) fn replid(&self) -> String {
+        self.replid.lock().unwrap().clone()
+    }
+
+    ///换用新的随机复制id，之前的复制id与偏移量不能再用于部分同步
+    pub(crate) fn change_replid(&self) {
+        *self.replid.lock().unwrap() = new_replid();
+    }
+
+    ///沿用主节点的复制id，从节点全量同步之后调用
+    ///
+    /// 与redis一致，从节点以主节点的复制id作为自己的复制id，主节点的复制id改变时同样跟随，
+    /// 下级从节点之前以旧复制id记录的位置不再能用于部分同步
+    pub(crate) fn set_replid(&self, replid: &str) {
+        *self.replid.lock().unwrap() = replid.to_string();
+    }
+
+    ///当前的复制偏移量
+    pub(crate) fn offset(&self) -> u64 {
+        self.stream.lock().unwrap().offset
+    }
+
+    ///积压缓冲区的大小、第一个字节的偏移量与其中数据的长度
+    pub(crate) fn backlog(&self) -> (usize, u64, usize) {
+        let stream = self.stream.lock().unwrap();
+        (stream.capacity, stream.first_byte(), stream.backlog.len())
+    }
+
+    ///连接上来的从节点
+    pub(crate) fn replicas(&self) -> Vec<Arc<Replica>> {
+        self.replicas.lock().unwrap().clone()
+    }
+
+    ///修改积压缓冲区的大小，修改配置之后调用
+    pub(crate) fn configure(&self, capacity: usize) {
+        let mut stream = self.stream.lock().unwrap();
+        stream.capacity = capacity;
+        let excess = stream.backlog.len().saturating_sub(capacity);
+        stream.backlog.drain(..excess);
+    }
+
+    ///传播在各自的逻辑数据库中执行的命令，没有从节点请求过同步时什么也不做
+    ///
+    /// 必须在顺序锁内调用，transaction为true时包装在MULTI与EXEC之间
+    pub(crate) fn feed(&self, commands: &[(usize, Vec<Bytes>)], transaction: bool) {
+        if !self.is_active() {
+            return;
+        }
+        let mut stream = self.stream.lock().unwrap();
+        let data = stream.encoder.encode(commands, transaction);
+        stream.push(data, &self.replicas.lock().unwrap());
+    }
+
+    ///向从节点发送PING，从节点以此判断与主节点的连接是否正常
+    pub(crate) fn ping(&self) {
+        let mut data = vec![];
+        propagate::encode(&mut data, &[Bytes::from_static(b"PING")]);
+        let mut stream = self.stream.lock().unwrap();
+        stream.push(data, &self.replicas.lock().unwrap());
+    }
+
+    ///要求从节点立即以REPLCONF ACK确认处理到的偏移量
+    pub(crate) fn getack(&self) {
+        let mut data = vec![];
+        let args = [
+            Bytes::from_static(b"REPLCONF"),
+            Bytes::from_static(b"GETACK"),
+            Bytes::from_static(b"*"),
+        ];
+        propagate::encode(&mut data, &args);
+        let mut stream = self.stream.lock().unwrap();
+        stream.push(data, &self.replicas.lock().unwrap());
+    }
+
+    ///记录从节点通过REPLCONF ACK确认的偏移量，唤醒等待确认的WAIT
+    pub(crate) fn acknowledge(&self, replica: &Replica, offset: u64) {
+        replica.ack.fetch_max(offset, Ordering::Relaxed);
+        replica.ack_at.store(now_millis(), Ordering::Relaxed);
+        self.acks.notify_waiters();
+    }
+
+    ///已经确认处理到offset的从节点数量
+    pub(crate) fn acknowledged(&self, offset: u64) -> usize {
+        self.replicas
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|replica| replica.ack() >= offset)
+            .count()
+    }
+
+    ///等待至少numreplicas个从节点确认处理到offset，返回确认了的从节点数量
+    ///
+    /// deadline为None时一直等待
+    pub(crate) async fn wait(
+        &self,
+        offset: u64,
+        numreplicas: usize,
+        deadline: Option<Instant>,
+    ) -> usize {
+        loop {
+            //先登记等待再检查，检查之后到达的确认不会被错过
+            let notified = self.acks.notified();
+            let count = self.acknowledged(offset);
+            if count >= numreplicas {
+                return count;
+            }
+            match deadline {
+                None => notified.await,
+                Some(deadline) => {
+                    if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                        return self.acknowledged(offset);
+                    }
+                }
+            }
+        }
+    }
+
+    ///登记请求同步的从节点，按PSYNC给出的复制id与偏移量决定同步方式
+    ///
+    /// 必须在独占顺序锁时调用，全量同步的数据也要在同一次独占中复制，
+    /// 之后传播的命令都排在这份数据或补上的命令流之后
+    pub(crate) fn attach(&self, replica: Arc<Replica>, replid: &str, offset: i64) -> Resync {
+        self.active.store(true, Ordering::Release);
+        let mut stream = self.stream.lock().unwrap();
+        let available = stream.first_byte() as i64..=stream.offset as i64 + 1;
+        let sync = if replid == *self.replid.lock().unwrap() && available.contains(&offset) {
+            let skip = (offset - available.start()) as usize;
+            Resync::Partial(stream.backlog.iter().skip(skip).copied().collect())
+        } else {
+            //从节点从全部数据之后开始接收，第一条命令之前需要先SELECT
+            stream.encoder.reset();
+            Resync::Full(stream.offset)
+        };
+        self.replicas.lock().unwrap().push(replica);
+        sync
+    }
+
+    ///移除连接已经关闭的从节点
+    pub(crate) fn detach(&self, id: u64) {
+        self.replicas
+            .lock()
+            .unwrap()
+            .retain(|replica| replica.id != id);
+    }
+}
+
+//随机生成的40位十六进制复制id
+fn new_replid() -> String {
+    format!(
+        "{:016x}{:016x}{:08x}",
+        random::next_u64(),
+        random::next_u64(),
+        random::next_u64() as u32
+    )
+}
+
+///每隔repl-ping-replica-period秒向从节点发送PING
+pub(crate) async fn ping_task(db: Db) {
+    loop {
+        let period = db.config().repl_ping_replica_period();
+        tokio::time::sleep(Duration::from_secs(period)).await;
+        let replication = db.replication();
+        if !replication.replicas().is_empty() {
+            replication.ping();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(key: &str) -> Vec<(usize, Vec<Bytes>)> {
+        let args = ["SET", key, "value"].map(|arg| Bytes::copy_from_slice(arg.as_bytes()));
+        vec![(0, args.to_vec())]
+    }
+
+    fn attach(replication: &Replication, id: u64, replid: &str, offset: i64) -> Resync {
+        let (replica, _stream) = Replica::new(id, "127.0.0.1", 0);
+        let resync = replication.attach(Arc::new(replica), replid, offset);
+        replication.detach(id);
+        resync
+    }
+
+    #[test]
+    fn reconnecting_replicas_continue_from_the_backlog() {
+        let replication = Replication::new(&Config::default());
+        assert!(matches!(attach(&replication, 1, "?", -1), Resync::Full(0)));
+        replication.feed(&set("a"), false);
+        let synced = replication.offset();
+        replication.feed(&set("b"), false);
+        let replid = replication.replid();
+        //从上次处理到的位置之后继续，只补上缺失的部分
+        let missing = match attach(&replication, 2, &replid, synced as i64 + 1) {
+            Resync::Partial(missing) => missing,
+            resync => panic!("unexpected resync {:?}", resync),
+        };
+        assert_eq!(missing.len() as u64, replication.offset() - synced);
+        assert!(missing.ends_with(b"$3\r\nSET\r\n$1\r\nb\r\n$5\r\nvalue\r\n"));
+        //已经同步到最新时没有需要补上的数据
+        let next = replication.offset() as i64 + 1;
+        assert!(matches!(
+            attach(&replication, 3, &replid, next),
+            Resync::Partial(missing) if missing.is_empty()
+        ));
+        //复制id不同或偏移量超出命令流时全量同步
+        assert!(matches!(
+            attach(&replication, 4, "other", synced as i64 + 1),
+            Resync::Full(_)
+        ));
+        assert!(matches!(
+            attach(&replication, 5, &replid, next + 1),
+            Resync::Full(_)
+        ));
+    }
+
+    #[test]
+    fn offsets_trimmed_from_the_backlog_need_a_full_resync() {
+        let replication = Replication::new(&Config::default());
+        attach(&replication, 1, "?", -1);
+        replication.configure(16);
+        replication.feed(&set("a"), false);
+        replication.feed(&set("b"), false);
+        let (capacity, first_byte, histlen) = replication.backlog();
+        assert_eq!((capacity, histlen), (16, 16));
+        let replid = replication.replid();
+        assert!(matches!(
+            attach(&replication, 2, &replid, first_byte as i64 - 1),
+            Resync::Full(offset) if offset == replication.offset()
+        ));
+        assert!(matches!(
+            attach(&replication, 3, &replid, first_byte as i64),
+            Resync::Partial(missing) if missing.len() == 16
+        ));
+    }
+}
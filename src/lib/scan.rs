@@ -0,0 +1,287 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::sync::OnceLock;
+
+//平均每个桶的元素超过该值时桶数翻倍
+const LOAD: usize = 4;
+//一批中最多访问的空桶数是count的多少倍，避免稀疏的表一次遍历太多桶
+const EMPTY_VISITS: usize = 10;
+
+///按哈希值分桶的表，支持SCAN系列命令的增量遍历
+///
+/// 元素按哈希值的高位分到2的幂个桶中，平均每个桶超过4个元素时桶数翻倍，每个桶一分为二，不缩容。
+/// 游标是哈希值：从游标所在的桶开始按哈希值从小到大逐个桶遍历，下一个游标是下一个桶覆盖的最小哈希值，为0时遍历结束。
+/// 扩容只会把桶拆得更细，游标指向的位置不变，因此遍历期间其他元素的增删与扩容都不会让从头到尾一直存在的元素被跳过，
+/// 每批只访问大约count个元素
+#[derive(Debug, Clone)]
+pub(crate) struct Buckets<K, V> {
+    //每个元素与其哈希值一起存放，扩容时不必重新计算
+    buckets: Vec<Vec<(u64, K, V)>>,
+    //桶数以2为底的对数
+    bits: u32,
+    len: usize,
+}
+
+impl<K, V> Default for Buckets<K, V> {
+    fn default() -> Buckets<K, V> {
+        Buckets {
+            buckets: vec![Vec::new()],
+            bits: 0,
+            len: 0,
+        }
+    }
+}
+
+impl<K: Hash + Eq, V> FromIterator<(K, V)> for Buckets<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Buckets<K, V> {
+        let mut buckets = Buckets::default();
+        for (key, value) in iter {
+            buckets.insert(key, value);
+        }
+        buckets
+    }
+}
+
+impl<K: Hash + Eq, V> Buckets<K, V> {
+    ///元素个数
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    ///获取键对应的值
+    pub(crate) fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_key_value(key).map(|(_, value)| value)
+    }
+
+    ///获取键与对应的值
+    pub(crate) fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = hash(key);
+        self.buckets[self.index(hash)]
+            .iter()
+            .find(|(cur, k, _)| *cur == hash && k.borrow() == key)
+            .map(|(_, key, value)| (key, value))
+    }
+
+    ///键是否存在
+    pub(crate) fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get_key_value(key).is_some()
+    }
+
+    ///写入键对应的值，返回原来的值
+    pub(crate) fn insert(&mut self, key: K, value: V) -> Option<V> {
+        self.update(key, |_, slot| slot.replace(value))
+    }
+
+    ///删除键，返回原来的值
+    pub(crate) fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.remove_entry(key).map(|(_, value)| value)
+    }
+
+    ///删除键，返回被删除的键与值
+    pub(crate) fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let hash = hash(key);
+        let index = self.index(hash);
+        let bucket = &mut self.buckets[index];
+        let at = bucket
+            .iter()
+            .position(|(cur, k, _)| *cur == hash && k.borrow() == key)?;
+        let (_, key, value) = bucket.swap_remove(at);
+        self.len -= 1;
+        Some((key, value))
+    }
+
+    ///读取并修改键对应的值
+    ///
+    /// f拿到键与当前的值（不存在时为None），可以原地修改、替换或取走，返回时为None的键被删除
+    pub(crate) fn update<R>(&mut self, key: K, f: impl FnOnce(&K, &mut Option<V>) -> R) -> R {
+        let hash = hash(&key);
+        let index = self.index(hash);
+        let bucket = &mut self.buckets[index];
+        let (key, mut slot) = match bucket
+            .iter()
+            .position(|(cur, k, _)| *cur == hash && *k == key)
+        {
+            Some(at) => {
+                let (_, key, value) = bucket.swap_remove(at);
+                self.len -= 1;
+                (key, Some(value))
+            }
+            None => (key, None),
+        };
+        let result = f(&key, &mut slot);
+        if let Some(value) = slot {
+            self.buckets[index].push((hash, key, value));
+            self.len += 1;
+            self.grow();
+        }
+        result
+    }
+
+    ///按桶的顺序遍历键与值
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&K, &V)> + '_ {
+        self.buckets
+            .iter()
+            .flatten()
+            .map(|(_, key, value)| (key, value))
+    }
+
+    ///按桶的顺序遍历键
+    pub(crate) fn keys(&self) -> impl Iterator<Item = &K> + '_ {
+        self.iter().map(|(key, _)| key)
+    }
+
+    ///从游标开始遍历一批元素，以每个元素调用f，返回下一个游标，为0时遍历结束
+    ///
+    /// 总是遍历完整的桶，因此一批可能略多于count个元素
+    pub(crate) fn scan(&self, cursor: u64, count: usize, mut f: impl FnMut(&K, &V)) -> u64 {
+        let mut index = self.index(cursor);
+        let mut visited = 0;
+        let mut empty = 0;
+        while visited < count && empty < count.saturating_mul(EMPTY_VISITS) {
+            let bucket = &self.buckets[index];
+            if bucket.is_empty() {
+                empty += 1;
+            }
+            for (_, key, value) in bucket {
+                f(key, value);
+            }
+            visited += bucket.len();
+            index += 1;
+            if index == self.buckets.len() {
+                return 0;
+            }
+        }
+        self.start(index)
+    }
+
+    //哈希值所在的桶，取哈希值的高bits位
+    fn index(&self, hash: u64) -> usize {
+        match self.bits {
+            0 => 0,
+            bits => (hash >> (64 - bits)) as usize,
+        }
+    }
+
+    //桶覆盖的最小哈希值
+    fn start(&self, index: usize) -> u64 {
+        match self.bits {
+            0 => 0,
+            bits => (index as u64) << (64 - bits),
+        }
+    }
+
+    //元素过多时桶数翻倍，桶i拆分为2i与2i+1，元素的相对顺序不变
+    fn grow(&mut self) {
+        if self.len <= self.buckets.len() * LOAD {
+            return;
+        }
+        self.bits += 1;
+        let mut buckets = Vec::with_capacity(self.buckets.len() * 2);
+        for bucket in std::mem::take(&mut self.buckets) {
+            let (low, high): (Vec<_>, Vec<_>) = bucket
+                .into_iter()
+                .partition(|(hash, _, _)| (hash >> (64 - self.bits)) & 1 == 0);
+            buckets.push(low);
+            buckets.push(high);
+        }
+        self.buckets = buckets;
+    }
+}
+
+//计算键的哈希值，种子在进程启动后随机选定，同一个键在进程内的哈希值不变
+fn hash<Q: Hash + ?Sized>(key: &Q) -> u64 {
+    static SEED: OnceLock<RandomState> = OnceLock::new();
+    SEED.get_or_init(RandomState::new).hash_one(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    //从游标0开始遍历到结束，返回所有元素与每批访问的元素数
+    fn scan_all(buckets: &Buckets<u32, ()>, count: usize) -> (Vec<u32>, Vec<usize>) {
+        let mut cursor = 0;
+        let mut seen = vec![];
+        let mut batches = vec![];
+        loop {
+            let before = seen.len();
+            cursor = buckets.scan(cursor, count, |key, _| seen.push(*key));
+            batches.push(seen.len() - before);
+            if cursor == 0 {
+                return (seen, batches);
+            }
+        }
+    }
+
+    #[test]
+    fn map_operations() {
+        let mut buckets: Buckets<String, u32> = Buckets::default();
+        for i in 0..1000 {
+            assert_eq!(buckets.insert(i.to_string(), i), None);
+        }
+        assert_eq!(buckets.insert("7".to_string(), 70), Some(7));
+        assert_eq!(buckets.len(), 1000);
+        assert_eq!(buckets.get("7"), Some(&70));
+        assert!(buckets.contains_key("999"));
+        assert_eq!(buckets.remove("999"), Some(999));
+        assert!(!buckets.contains_key("999"));
+        buckets.update("1".to_string(), |_, slot| *slot = None);
+        assert_eq!(buckets.len(), 998);
+        assert_eq!(buckets.iter().count(), 998);
+    }
+
+    #[test]
+    fn scan_returns_every_element_once_in_small_batches() {
+        let buckets: Buckets<u32, ()> = (0..10_000).map(|i| (i, ())).collect();
+        let (mut seen, batches) = scan_all(&buckets, 10);
+        seen.sort_unstable();
+        assert_eq!(seen, (0..10_000).collect::<Vec<_>>());
+        //每批访问完整的桶，平均每个桶不超过LOAD个元素
+        assert!(batches.iter().all(|&batch| batch < 10 + 10 * LOAD));
+    }
+
+    #[test]
+    fn growth_during_scan_does_not_skip_elements() {
+        let mut buckets: Buckets<u32, ()> = (0..1000).map(|i| (i, ())).collect();
+        let mut seen = HashSet::new();
+        let mut cursor = 0;
+        let mut next = 1000;
+        loop {
+            cursor = buckets.scan(cursor, 10, |key, _| {
+                seen.insert(*key);
+            });
+            //每批之间插入多于一批的元素，遍历期间扩容多次
+            for _ in 0..50 {
+                buckets.insert(next, ());
+                next += 1;
+            }
+            if cursor == 0 {
+                break;
+            }
+        }
+        assert!(buckets.len() > 8 * 1000);
+        assert!((0..1000).all(|i| seen.contains(&i)));
+    }
+}
@@ -0,0 +1,1545 @@
+use crate::lib::aof;
+use crate::lib::client::Client;
+use crate::lib::cluster;
+use crate::lib::cmd;
+use crate::lib::cmd::custom::CommandHandler;
+use crate::lib::cmd::table;
+use crate::lib::codec;
+use crate::lib::config::{Config, ConfigError};
+use crate::lib::conn::{Connection, Stream};
+use crate::lib::db::{Db, DbDropGuard};
+use crate::lib::embedded::EmbeddedClient;
+use crate::lib::frame::Frame;
+use crate::lib::http;
+use crate::lib::logging;
+use crate::lib::lua;
+use crate::lib::master_link;
+use crate::lib::metrics;
+use crate::lib::propagate;
+use crate::lib::rdb::{self, SaveOnExit};
+use crate::lib::replication;
+use crate::lib::shutdown::Shutdown;
+use crate::lib::stats::{CloseReason, CommandStat, Outcome};
+use crate::lib::tracking::Invalidation;
+use crate::lib::value::limits;
+use crate::lib::{Error, Result};
+use socket2::{Domain, Protocol, SockRef, Socket, TcpKeepalive, Type};
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::DuplexStream;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+use tokio::net::{lookup_host, TcpListener, TcpStream};
+use tokio::runtime::{self, Runtime};
+use tokio::sync::{broadcast, mpsc, OwnedSemaphorePermit};
+use tokio::task::JoinSet;
+use tracing::Instrument;
+
+///服务器的配置
+///
+/// 通过Server::builder创建，未设置的项使用默认值：监听127.0.0.1:6378，
+/// 最多10000个客户端，16个逻辑数据库，关闭时最多等待连接10秒。
+/// 后设置的值覆盖先设置的值，因此命令行参数应在读取配置文件之后设置
+#[derive(Debug, Clone)]
+pub struct ServerBuilder {
+    config: Config,
+    shutdown_timeout: Duration,
+}
+
+impl Default for ServerBuilder {
+    fn default() -> Self {
+        ServerBuilder {
+            config: Config::default(),
+            shutdown_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl ServerBuilder {
+    ///读取redis.conf格式的配置文件，文件中的配置覆盖之前设置的所有值
+    ///
+    /// CONFIG REWRITE会将运行期间修改的配置写回这个文件
+    pub fn config_file(mut self, path: impl AsRef<Path>) -> Result<Self> {
+        self.config = Config::load(path)?;
+        Ok(self)
+    }
+
+    ///按redis.conf中的名称设置一项配置
+    pub fn set(mut self, name: &str, value: &str) -> Result<Self> {
+        match self.config.set(&name.to_lowercase(), value) {
+            Ok(()) => Ok(self),
+            Err(ConfigError::Invalid) => Err(Error::Config(format!(
+                "Invalid argument '{}' for '{}'",
+                value, name
+            ))),
+            Err(_) => Err(Error::Config(format!("Unknown option '{}'", name))),
+        }
+    }
+
+    ///注册自定义命令，客户端、脚本、事务与AOF重放中都可以像内置命令一样调用
+    ///
+    /// arity与flags的含义与COMMAND命令中的一致：arity包括命令名本身，为负数时代表至少需要其绝对值个参数；
+    /// 带write的命令在执行后写入AOF并传播给从节点，带denyoom的命令在内存超出上限时被拒绝。
+    /// 命令表是进程中所有服务器共用的，注册立即生效，同名的自定义命令被替换。与内置命令重名时返回错误
+    pub fn command(
+        self,
+        name: &str,
+        arity: i64,
+        flags: &'static [&'static str],
+        handler: impl CommandHandler,
+    ) -> Result<Self> {
+        let name = name.to_lowercase();
+        if name.is_empty() || name.contains(|c: char| c.is_ascii_whitespace()) {
+            return Err(Error::Config(format!("Invalid command name '{}'", name)));
+        }
+        if arity == 0 {
+            return Err(Error::Config(format!("Invalid arity for '{}'", name)));
+        }
+        if table::builtin(&name).is_some() {
+            return Err(Error::Config(format!(
+                "Command '{}' is already defined",
+                name
+            )));
+        }
+        table::register(&name, arity, flags, Arc::new(handler));
+        Ok(self)
+    }
+
+    ///监听的地址，多个地址以空格分隔，为空时保持原来的地址
+    pub fn bind(mut self, bind: &str) -> Self {
+        //值只有为空时不合法
+        let _ = self.config.set("bind", bind);
+        self
+    }
+
+    ///监听的端口，为0时由系统分配，可以通过Server::local_addr得知实际的端口。
+    /// 配置了unix套接字时为0代表不监听TCP
+    pub fn port(mut self, port: u16) -> Self {
+        let _ = self.config.set("port", &port.to_string());
+        self
+    }
+
+    ///Prometheus指标的HTTP端口，为0时不监听
+    pub fn metrics_port(mut self, port: u16) -> Self {
+        let _ = self.config.set("metrics-port", &port.to_string());
+        self
+    }
+
+    ///HTTP网关的端口，为0时不监听
+    ///
+    /// 网关提供GET、PUT、DELETE /keys/{key}与接受JSON数组的POST /command，与客户端连接执行同样的命令
+    pub fn http_port(mut self, port: u16) -> Self {
+        let _ = self.config.set("http-port", &port.to_string());
+        self
+    }
+
+    ///允许同时存在的最大客户端连接数，超出后新连接会收到错误并被关闭，至少为1
+    pub fn max_clients(mut self, max_clients: usize) -> Self {
+        let _ = self
+            .config
+            .set("maxclients", &max_clients.max(1).to_string());
+        self
+    }
+
+    ///逻辑数据库的数量，至少为1
+    pub fn databases(mut self, databases: usize) -> Self {
+        let _ = self.config.set("databases", &databases.max(1).to_string());
+        self
+    }
+
+    ///关闭时等待连接处理完手头命令的最长时间
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    ///按loglevel、logfile与log-format安装全局的日志订阅者
+    ///
+    /// 嵌入到其他程序时可以不调用，由程序自己安装订阅者
+    pub fn init_logging(&self) -> Result<()> {
+        logging::init(&self.config)
+    }
+
+    ///按runtime与worker-threads配置创建运行服务器的tokio运行时
+    ///
+    /// 命令行程序用它代替tokio::main，嵌入到其他程序时由程序自己的运行时执行build与run
+    pub fn runtime(&self) -> io::Result<Runtime> {
+        let mut builder = if self.config.runtime() == "current-thread" {
+            runtime::Builder::new_current_thread()
+        } else {
+            let mut builder = runtime::Builder::new_multi_thread();
+            builder.worker_threads(self.config.worker_threads());
+            builder
+        };
+        builder.enable_all().build()
+    }
+
+    ///绑定监听的地址与端口并创建服务器，配置的每个地址都会被监听
+    ///
+    /// tls-port不为0时同时在各个地址的该端口上监听TLS连接，需要编译时启用tls特性。
+    /// 配置了unixsocket时同时监听该路径，路径上已有的文件会被删除。
+    /// metrics-port不为0时在各个地址的该端口上以HTTP提供Prometheus指标。
+    /// http-port不为0时在http-bind的各个地址的该端口上提供HTTP网关。
+    /// 开启tcp-reuseport时客户端端口在每个地址上为每个工作线程绑定一个套接字。
+    /// 快照文件存在时载入其中的数据，文件损坏时返回错误
+    pub async fn build(self) -> io::Result<Server> {
+        let bind = self.config.bind();
+        let unix_socket = self.config.unix_socket();
+        let acceptors = self.config.acceptors();
+        let mut listeners = vec![];
+        if self.config.port() != 0 || unix_socket.is_empty() {
+            for listener in bind_all(bind, self.config.port(), acceptors).await? {
+                listeners.push(Listener::Tcp(listener));
+            }
+        }
+        if self.config.tls_port() != 0 {
+            listeners.extend(self.tls_listeners(bind).await?);
+        }
+        if !unix_socket.is_empty() {
+            listeners.push(self.unix_listener(unix_socket)?);
+        }
+        if self.config.metrics_port() != 0 {
+            for listener in bind_all(bind, self.config.metrics_port(), 1).await? {
+                listeners.push(Listener::Metrics(listener));
+            }
+        }
+        if self.config.http_port() != 0 {
+            for listener in bind_all(self.config.http_bind(), self.config.http_port(), 1).await? {
+                listeners.push(Listener::Http(listener));
+            }
+        }
+        self.into_server(listeners)
+    }
+
+    //创建数据库、载入数据并组装服务器
+    //
+    // 开启了AOF时以AOF为准；AOF还不存在时从快照载入，并把载入的数据写在新建的AOF开头
+    fn into_server(self, mut listeners: Vec<Listener>) -> io::Result<Server> {
+        let append_only = self.config.append_only();
+        limits::configure(&self.config);
+        let db = DbDropGuard::new(self.config);
+        let invalid = |e| io::Error::new(io::ErrorKind::InvalidData, e);
+        cluster::load(&db.db()).map_err(invalid)?;
+        let replayed = if append_only {
+            aof::load(&db.db()).map_err(invalid)?
+        } else {
+            None
+        };
+        if replayed.is_none() {
+            rdb::load(&db.db()).map_err(invalid)?;
+        }
+        if append_only {
+            aof::start(&db.db(), replayed.is_none())?;
+        }
+        let (connector, embedded) = mpsc::unbounded_channel();
+        listeners.push(Listener::Embedded(embedded));
+        Ok(Server {
+            listeners,
+            db,
+            connector,
+            shutdown_timeout: self.shutdown_timeout,
+        })
+    }
+
+    //在各个地址上绑定TLS监听的端口
+    #[cfg(feature = "tls")]
+    async fn tls_listeners(&self, bind: &str) -> io::Result<Vec<Listener>> {
+        let tls = crate::lib::tls::server_config(&self.config)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let listeners = bind_all(bind, self.config.tls_port(), self.config.acceptors()).await?;
+        Ok(listeners
+            .into_iter()
+            .map(|listener| Listener::Tls(listener, tls.clone()))
+            .collect())
+    }
+
+    //没有编译TLS支持时无法监听TLS端口
+    #[cfg(not(feature = "tls"))]
+    async fn tls_listeners(&self, _bind: &str) -> io::Result<Vec<Listener>> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "TLS support is not compiled in, rebuild with the tls feature",
+        ))
+    }
+
+    //绑定unix套接字
+    #[cfg(unix)]
+    fn unix_listener(&self, path: &str) -> io::Result<Listener> {
+        use std::os::unix::fs::PermissionsExt;
+        //上次运行遗留的套接字文件会导致绑定失败
+        if let Err(e) = std::fs::remove_file(path) {
+            if e.kind() != io::ErrorKind::NotFound {
+                return Err(e);
+            }
+        }
+        let listener = UnixListener::bind(path)?;
+        let perm = self.config.unix_socket_perm();
+        if perm != 0 {
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(perm))?;
+        }
+        Ok(Listener::Unix(listener))
+    }
+
+    //只有unix系统支持unix套接字
+    #[cfg(not(unix))]
+    fn unix_listener(&self, _path: &str) -> io::Result<Listener> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Unix sockets are not supported on this platform",
+        ))
+    }
+
+    ///使用已经绑定好的监听器创建服务器，忽略配置中的地址与端口，快照文件损坏时返回错误
+    pub fn with_listener(self, listener: TcpListener) -> io::Result<Server> {
+        self.into_server(vec![Listener::Tcp(listener)])
+    }
+}
+
+///在空格分隔的每个地址上绑定同一个端口
+///
+/// 地址可以是IPv4、IPv6或主机名，主机名使用解析出的第一个地址。
+/// 端口为0时第一个地址由系统分配端口，其余地址使用同一个端口。
+/// acceptors大于1时每个地址以SO_REUSEPORT绑定这么多个套接字，每个套接字有自己的accept循环
+async fn bind_all(bind: &str, port: u16, acceptors: usize) -> io::Result<Vec<TcpListener>> {
+    let mut listeners: Vec<TcpListener> = vec![];
+    for host in bind.split_ascii_whitespace() {
+        let port = match listeners.first() {
+            Some(first) if port == 0 => first.local_addr()?.port(),
+            _ => port,
+        };
+        let mut addr = lookup_host((host, port))
+            .await?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, host.to_string()))?;
+        for _ in 0..acceptors {
+            let listener = bind_addr(addr, acceptors > 1)
+                .map_err(|e| io::Error::new(e.kind(), format!("Could not bind {}: {}", addr, e)))?;
+            //端口为0时同一地址上其余的套接字绑定系统分配的端口
+            addr.set_port(listener.local_addr()?.port());
+            listeners.push(listener);
+        }
+    }
+    Ok(listeners)
+}
+
+///绑定一个地址
+///
+/// IPv6的套接字只接受IPv6连接，这样"0.0.0.0 ::"这样的配置可以同时绑定同一个端口。
+/// reuse_port为true时设置SO_REUSEPORT，允许多个套接字绑定同一个地址
+fn bind_addr(addr: SocketAddr, reuse_port: bool) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    if reuse_port {
+        socket.set_reuse_port(true)?;
+    }
+    #[cfg(not(unix))]
+    let _ = reuse_port;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(511)?;
+    TcpListener::from_std(socket.into())
+}
+
+///服务器接受连接的监听器
+#[derive(Debug)]
+enum Listener {
+    ///明文的TCP连接
+    Tcp(TcpListener),
+    ///TCP之上的TLS连接，握手在连接自己的任务中进行
+    #[cfg(feature = "tls")]
+    Tls(TcpListener, Arc<rustls::ServerConfig>),
+    ///本机的unix套接字连接
+    #[cfg(unix)]
+    Unix(UnixListener),
+    ///以HTTP提供Prometheus指标
+    Metrics(TcpListener),
+    ///HTTP网关，每个请求经一个嵌入式连接执行
+    Http(TcpListener),
+    ///同一进程中的嵌入式客户端，接收内存管道的服务器一端
+    Embedded(mpsc::UnboundedReceiver<DuplexStream>),
+}
+
+impl Listener {
+    ///不断接受新连接并为每个连接启动任务
+    async fn accept(mut self, ctx: Context) {
+        loop {
+            //文件描述符耗尽等错误只影响这一次accept，记录后继续接受连接
+            let result = match &mut self {
+                Listener::Tcp(listener) => listener.accept().await.map(|(stream, addr)| {
+                    ctx.keepalive(&stream);
+                    ctx.spawn(addr.to_string(), async move { Ok(stream) })
+                }),
+                #[cfg(feature = "tls")]
+                Listener::Tls(listener, tls) => listener.accept().await.map(|(stream, addr)| {
+                    ctx.keepalive(&stream);
+                    let acceptor = tokio_rustls::TlsAcceptor::from(tls.clone());
+                    ctx.spawn(addr.to_string(), acceptor.accept(stream))
+                }),
+                #[cfg(unix)]
+                //与redis一致，unix套接字连接的地址记为套接字路径加上端口0
+                Listener::Unix(listener) => listener.accept().await.map(|(stream, _)| {
+                    let path = listener.local_addr().ok();
+                    let path = path.as_ref().and_then(|addr| addr.as_pathname());
+                    let addr = format!("{}:0", path.unwrap_or(Path::new("")).display());
+                    ctx.spawn(addr, async move { Ok(stream) })
+                }),
+                //抓取指标的连接不是客户端，不计入连接数
+                Listener::Metrics(listener) => listener.accept().await.map(|(stream, _)| {
+                    tokio::spawn(metrics::respond(stream, ctx.db.clone()));
+                }),
+                Listener::Http(listener) => listener.accept().await.map(|(stream, _)| {
+                    ctx.keepalive(&stream);
+                    ctx.spawn_http(stream);
+                }),
+                //客户端都被丢弃后不会再有新连接，但监听器结束会被当作需要关闭服务器，所以一直等待
+                Listener::Embedded(connections) => match connections.recv().await {
+                    Some(stream) => {
+                        ctx.spawn("embedded:0".to_string(), async move { Ok(stream) });
+                        Ok(())
+                    }
+                    None => std::future::pending().await,
+                },
+            };
+            if let Err(e) = result {
+                tracing::warn!(error = %e, "接受连接失败");
+            }
+        }
+    }
+
+    //明文的TCP监听器
+    fn tcp(&self) -> Option<&TcpListener> {
+        match self {
+            Listener::Tcp(listener) => Some(listener),
+            #[cfg(feature = "tls")]
+            Listener::Tls(..) => None,
+            #[cfg(unix)]
+            Listener::Unix(_) => None,
+            Listener::Metrics(_) | Listener::Http(_) | Listener::Embedded(_) => None,
+        }
+    }
+}
+
+impl Drop for Listener {
+    //停止监听时删除unix套接字文件
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Listener::Unix(listener) = self {
+            if let Some(path) = listener
+                .local_addr()
+                .ok()
+                .and_then(|addr| addr.as_pathname().map(|path| path.to_path_buf()))
+            {
+                let _ = std::fs::remove_file(path);
+            }
+        }
+    }
+}
+
+///redis服务器
+///
+/// 持有监听器，调用run后开始接受连接，直到关闭信号完成
+#[derive(Debug)]
+pub struct Server {
+    listeners: Vec<Listener>,
+    //创建服务器时即创建数据库，运行之前也可以读取统计
+    db: DbDropGuard,
+    //嵌入式客户端把内存管道的一端经这里交给监听器
+    connector: mpsc::UnboundedSender<DuplexStream>,
+    shutdown_timeout: Duration,
+}
+
+impl Server {
+    ///创建服务器的配置
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder::default()
+    }
+
+    ///实际监听的明文TCP地址，只监听unix套接字时返回错误
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listeners
+            .iter()
+            .find_map(Listener::tcp)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Not listening on TCP"))?
+            .local_addr()
+    }
+
+    ///Prometheus指标实际监听的地址，没有配置metrics-port时返回错误
+    pub fn metrics_addr(&self) -> io::Result<SocketAddr> {
+        self.listeners
+            .iter()
+            .find_map(|listener| match listener {
+                Listener::Metrics(listener) => Some(listener),
+                _ => None,
+            })
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Metrics are disabled"))?
+            .local_addr()
+    }
+
+    ///创建一个不经过网络、直接在进程内连接服务器的客户端
+    ///
+    /// 需要在run之前创建，run开始后客户端上的命令才会被执行，更多的连接可以通过EmbeddedClient::connect打开
+    pub fn client(&self) -> EmbeddedClient {
+        EmbeddedClient::open(self.connector.clone())
+    }
+
+    ///按命令名排列的调用统计，与INFO commandstats一致，只包括被调用过的命令
+    pub fn command_stats(&self) -> Vec<CommandStat> {
+        self.db.db().stats().commands.all()
+    }
+
+    ///处理连接，直到shutdown完成或客户端执行了SHUTDOWN
+    ///
+    /// 关闭时先停止接受新连接，再通知所有连接：正在执行的命令会执行完并发出回复，
+    /// 之后连接被关闭。最多等待配置的时长，超时仍未结束的连接随运行时一起被丢弃。
+    /// 连接都结束之后写完积压的AOF，并按SHUTDOWN的参数或save规则保存快照
+    pub async fn run(self, shutdown: impl Future) {
+        let Server {
+            listeners,
+            db: db_holder,
+            connector,
+            shutdown_timeout,
+        } = self;
+        let db = db_holder.db();
+        //丢弃发送端即通知所有连接关闭
+        let (notify_shutdown, _) = broadcast::channel(1);
+        //每个连接任务持有一个发送端，全部丢弃后接收端返回，据此得知连接都已结束
+        let (shutdown_complete, mut all_closed) = mpsc::channel::<()>(1);
+        let ctx = Context {
+            db,
+            notify_shutdown,
+            shutdown_complete,
+            connector,
+        };
+        let saving = tokio::spawn(rdb::save_task(ctx.db.clone()));
+        let rewriting = tokio::spawn(aof::rewrite_task(ctx.db.clone()));
+        let pinging = tokio::spawn(replication::ping_task(ctx.db.clone()));
+        master_link::configure(&ctx.db);
+        //每个监听器在自己的任务中接受连接
+        let mut accepting = JoinSet::new();
+        for listener in listeners {
+            accepting.spawn(listener.accept(ctx.clone()));
+        }
+        let save = tokio::select! {
+            _ = accepting.join_next() => SaveOnExit::Default,
+            _ = shutdown => {
+                tracing::info!("开始关闭服务器");
+                SaveOnExit::Default
+            }
+            save = ctx.db.shutdown_requested() => {
+                tracing::info!("客户端要求关闭服务器");
+                save
+            }
+        };
+        //停止接受连接，随后丢弃上下文中的发送端
+        accepting.shutdown().await;
+        saving.abort();
+        rewriting.abort();
+        pinging.abort();
+        ctx.db.master_link().stop();
+        drop(ctx);
+        if tokio::time::timeout(shutdown_timeout, all_closed.recv())
+            .await
+            .is_err()
+        {
+            tracing::warn!("部分连接未能在{:?}内结束", shutdown_timeout);
+        }
+        //连接都已结束，不会再有新的写入
+        let db = db_holder.db();
+        db.aof().stop();
+        rdb::save_on_exit(&db, save).await;
+    }
+}
+
+///接受连接的任务共享的状态
+#[derive(Clone)]
+struct Context {
+    db: Db,
+    notify_shutdown: broadcast::Sender<()>,
+    shutdown_complete: mpsc::Sender<()>,
+    //HTTP网关经这里打开嵌入式连接
+    connector: mpsc::UnboundedSender<DuplexStream>,
+}
+
+impl Context {
+    ///按tcp-keepalive开启TCP keepalive，让系统发现并关闭对端已经消失的连接
+    fn keepalive(&self, stream: &TcpStream) {
+        let secs = self.db.config().tcp_keepalive();
+        if secs == 0 {
+            return;
+        }
+        let keepalive = TcpKeepalive::new().with_time(Duration::from_secs(secs));
+        if let Err(e) = SockRef::from(stream).set_tcp_keepalive(&keepalive) {
+            tracing::debug!(error = %e, "开启TCP keepalive失败");
+        }
+    }
+
+    ///为新连接启动任务，addr是对端的地址，connect完成传输层的握手
+    ///
+    /// 每个连接占用一个名额，上限可以在运行期间修改。名额用完时按maxclients-policy处理：
+    /// reject直接回复错误并关闭，wait让新连接排队等待名额，服务器关闭时放弃等待
+    fn spawn<S: Stream + 'static>(
+        &self,
+        addr: String,
+        connect: impl Future<Output = io::Result<S>> + Send + 'static,
+    ) {
+        let permit = match self.db.clients().admit() {
+            Some(permit) => permit,
+            None if self.db.config().maxclients_policy() == "wait" => {
+                let ctx = self.clone();
+                let mut shutdown = Shutdown::new(self.notify_shutdown.subscribe());
+                tokio::spawn(async move {
+                    tokio::select! {
+                        permit = ctx.db.clients().wait_admit() => ctx.start(addr, connect, permit),
+                        _ = shutdown.recv() => {}
+                    }
+                });
+                return;
+            }
+            None => {
+                self.db.stats().rejected_connections.incr();
+                tokio::spawn(async move {
+                    if let Ok(stream) = connect.await {
+                        reject(stream).await;
+                    }
+                });
+                return;
+            }
+        };
+        self.start(addr, connect, permit);
+    }
+
+    //以取得的名额启动处理连接的任务
+    fn start<S: Stream + 'static>(
+        &self,
+        addr: String,
+        connect: impl Future<Output = io::Result<S>> + Send + 'static,
+        permit: OwnedSemaphorePermit,
+    ) {
+        self.db.stats().connections_received.incr();
+        let (mut guard, mut invalidations) = ClientGuard::new(&self.db, addr, permit);
+        let client = guard.client.clone();
+        let db = self.db.clone();
+        let mut shutdown = Shutdown::new(self.notify_shutdown.subscribe());
+        let complete = self.shutdown_complete.clone();
+        //连接上的所有日志都带有连接的id与地址
+        let span = tracing::info_span!("conn", id = client.id(), addr = client.addr());
+        let task = tokio::spawn(
+            async move {
+                let reason = match connect.await {
+                    Ok(stream) => {
+                        serve(stream, db, &guard.client, &mut invalidations, &mut shutdown).await
+                    }
+                    Err(e) => {
+                        tracing::warn!(error = %e, "握手失败");
+                        CloseReason::Io
+                    }
+                };
+                guard.reason = Some(reason);
+                drop(guard);
+                drop(complete);
+            }
+            .instrument(span),
+        );
+        //CLIENT KILL通过中止任务关闭连接
+        client.set_task(task.abort_handle());
+    }
+
+    ///为HTTP网关的连接启动任务
+    ///
+    /// 读取请求期间占用一个连接名额，名额用完时回复503。服务器关闭时中断还没有完成的请求
+    fn spawn_http(&self, stream: TcpStream) {
+        let permit = match self.db.clients().admit() {
+            Some(permit) => permit,
+            None => {
+                self.db.stats().rejected_connections.incr();
+                tokio::spawn(http::reject(stream));
+                return;
+            }
+        };
+        let mut shutdown = Shutdown::new(self.notify_shutdown.subscribe());
+        let complete = self.shutdown_complete.clone();
+        let connector = self.connector.clone();
+        let db = self.db.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = http::respond(stream, &db, connector, permit) => {}
+                _ = shutdown.recv() => {}
+            }
+            drop(complete);
+        });
+    }
+}
+
+///拒绝超出上限的连接
+async fn reject<S: Stream>(socket: S) {
+    let mut conn = Connection::new(socket);
+    let err = Frame::Error("ERR max number of clients reached".to_string());
+    //连接随后就会关闭，写入失败也无需处理
+    let _ = conn.write_frame(&err).await;
+}
+
+///连接登记的守卫
+///
+/// 创建时在登记表中登记连接，销毁时注销，即便处理连接的任务发生panic或被中止也能正确注销
+struct ClientGuard {
+    db: Db,
+    client: Arc<Client>,
+    //连接占用的名额，销毁时归还
+    permit: Option<OwnedSemaphorePermit>,
+    //连接关闭的原因，任务被CLIENT KILL中止时来不及记录，为None
+    reason: Option<CloseReason>,
+}
+
+impl ClientGuard {
+    //同时返回接收发往该连接的失效消息的接收端
+    fn new(
+        db: &Db,
+        addr: String,
+        permit: OwnedSemaphorePermit,
+    ) -> (ClientGuard, mpsc::UnboundedReceiver<Invalidation>) {
+        let (client, invalidations) = db.clients().register(addr);
+        let guard = ClientGuard {
+            db: db.clone(),
+            client,
+            permit: Some(permit),
+            reason: None,
+        };
+        (guard, invalidations)
+    }
+}
+
+impl Drop for ClientGuard {
+    fn drop(&mut self) {
+        self.db.tracking().disable(self.client.id());
+        self.db.clients().remove(self.client.id());
+        if let Some(permit) = self.permit.take() {
+            self.db.clients().release(permit);
+        }
+        let reason = self.reason.unwrap_or(CloseReason::Killed);
+        self.db.stats().connection_closed(reason);
+        tracing::debug!(%reason, "连接关闭");
+    }
+}
+
+///处理连接上的命令，返回连接关闭的原因
+async fn serve<S: Stream>(
+    socket: S,
+    mut db: Db,
+    client: &Client,
+    invalidations: &mut mpsc::UnboundedReceiver<Invalidation>,
+    shutdown: &mut Shutdown,
+) -> CloseReason {
+    let mut conn = Connection::with_read_buffer(socket, db.config().read_buffer());
+    conn.set_limits(db.config().frame_limits());
+    let mut transaction = cmd::Transaction::default();
+    //是否收到了ASKING，只对紧接着的一个命令有效
+    let mut asking = false;
+    //连接当前的用户，default用户需要密码时为None，需要先通过AUTH或HELLO认证
+    let mut user = db.acl().default_user();
+    loop {
+        conn.set_trace(db.protocol_trace());
+        conn.set_max_reply(db.config().max_reply_size());
+        client.set_waiting(true);
+        let frame = tokio::select! {
+            frame = conn.read_frame() => frame,
+            //空闲超时由后台的定时任务检查，订阅与阻塞中的连接不在这里等待，不受影响
+            _ = client.timed_out() => {
+                match (db.idle_timeout(), client.idle_for()) {
+                    (Some(timeout), Some(idle)) if idle >= timeout => {
+                        let _ = conn.flush().await;
+                        return CloseReason::Timeout;
+                    }
+                    _ => continue,
+                }
+            }
+            //追踪的键被修改，RESP2的连接只能在订阅模式下收到失效消息
+            Some(invalidation) = invalidations.recv() => {
+                if conn.protocol() == codec::Protocol::Resp3 {
+                    conn.buffer_frame(&invalidation.push());
+                }
+                continue;
+            }
+            //只在等待新命令时响应关闭，正在执行的命令会先完成
+            _ = shutdown.recv() => {
+                let _ = conn.flush().await;
+                return CloseReason::Shutdown;
+            }
+        };
+        client.set_waiting(false);
+        let frame = match frame {
+            //与redis一致，空的请求（*0与*-1）直接跳过，不回复
+            Ok(Some(Frame::Null)) => continue,
+            Ok(Some(Frame::Array(parts))) if parts.is_empty() => continue,
+            Ok(Some(frame)) => frame,
+            Ok(None) => return CloseReason::Eof,
+            Err(e) => {
+                let reason = close_reason(&e);
+                //无法解析的数据之后的字节已经无从分帧，回复错误后关闭连接
+                if reason == CloseReason::Protocol {
+                    let _ = conn.write_frame(&Frame::from(e)).await;
+                }
+                return reason;
+            }
+        };
+        let info = cmd::Command::info_of(&frame);
+        client.touch(info.map_or("NULL", |info| info.name));
+        //CLIENT PAUSE期间先发出之前的回复再等待暂停结束，事务中只排队的命令不受影响
+        if let Some(info) = info.filter(|info| !transaction.is_active() || info.name == "exec") {
+            if db.clients().is_paused(info) {
+                if conn.flush().await.is_err() {
+                    return CloseReason::Io;
+                }
+                tokio::select! {
+                    _ = db.clients().wait_unpaused(info) => {}
+                    _ = shutdown.recv() => return CloseReason::Shutdown,
+                }
+            }
+        }
+        //命令的参数，监视、追踪与慢查询日志共用
+        let args = match info {
+            Some(_) => cmd::table::args(&frame),
+            None => vec![],
+        };
+        //有脚本在执行时异步地等待其结束，而不是阻塞在键空间锁上；
+        //脚本执行超过lua-time-limit之后除SCRIPT KILL与SHUTDOWN NOSAVE以外的命令都回复BUSY
+        let busy = match info {
+            Some(_) if db.running_script().is_running() && !lua::allowed_when_busy(&args) => {
+                if conn.flush().await.is_err() {
+                    return CloseReason::Io;
+                }
+                let limit = db.config().lua_time_limit();
+                tokio::select! {
+                    finished = db.running_script().wait(limit) => !finished,
+                    _ = shutdown.recv() => return CloseReason::Shutdown,
+                }
+            }
+            _ => false,
+        };
+        //有连接在监视时，命令通过检查之后推送给它们，AUTH与HELLO带有密码不推送
+        let monitored = match info {
+            Some(info) if db.has_monitors() && !matches!(info.name, "auth" | "hello") => {
+                Some(cmd::monitor::line(db.index(), client.addr(), &args))
+            }
+            _ => None,
+        };
+        //开启了追踪的连接读取的键，执行读命令时登记到追踪表
+        let reads: Vec<String> = match info {
+            Some(info) if info.has_flag("readonly") && db.tracking().is_active() => info
+                .key_args(&args)
+                .into_iter()
+                .map(|key| String::from_utf8_lossy(key).into_owned())
+                .collect(),
+            _ => vec![],
+        };
+        //ACL按解析之前的参数检查，解析会消耗帧
+        let denied = match &user {
+            Some(user) => db.acl().check(user, &frame).err(),
+            None => None,
+        };
+        //集群模式下键不由当前节点负责时回复重定向，未认证的连接不透露槽的分配
+        let asked = std::mem::take(&mut asking);
+        let denied = denied.or_else(|| match (db.cluster(), info, &user) {
+            (Some(cluster), Some(info), Some(_)) if info.accepts(args.len()) => {
+                cluster.route(&db, &info.key_args(&args), asked).err()
+            }
+            _ => None,
+        });
+        let parsed = match denied {
+            Some(denied) => Err(denied.into()),
+            None => cmd::Command::from_frame(frame).map(|cmd| cmd.with_user(user.as_deref())),
+        };
+        if let (Some(line), Ok(_)) = (monitored, &parsed) {
+            db.feed_monitors(line);
+        }
+        //写命令执行之前先淘汰键，内存仍然超出上限时拒绝可能增加内存占用的命令
+        let oom = match info {
+            Some(info) if parsed.is_ok() && info.has_flag("write") => {
+                !db.evict() && info.has_flag("denyoom")
+            }
+            _ => false,
+        };
+        //只读的从节点拒绝客户端的写命令，主节点传来的命令由复制连接执行，不经过这里
+        let readonly = match info {
+            Some(info) if parsed.is_ok() && info.has_flag("write") => {
+                db.master_link().master().is_some() && db.config().replica_read_only()
+            }
+            _ => false,
+        };
+        let span = tracing::debug_span!("cmd", name = info.map_or("unknown", |info| info.name));
+        //阻塞命令的耗时主要是等待，不计入慢查询日志
+        let blocking = matches!(&parsed, Ok(cmd) if cmd.may_block());
+        //事务中只排队的命令在EXEC时才执行，不计入统计
+        let queued =
+            transaction.is_active() && matches!(&parsed, Ok(cmd) if !cmd.controls_transaction());
+        let rejected = match &parsed {
+            Ok(cmd) => busy || oom || readonly || user.is_none() && cmd.requires_auth(),
+            Err(_) => true,
+        };
+        let started = Instant::now();
+        let resp = match parsed {
+            _ if busy => Frame::Error(lua::BUSY.to_string()),
+            Ok(cmd) if user.is_none() && cmd.requires_auth() => {
+                Frame::Error("NOAUTH Authentication required.".to_string())
+            }
+            //事务中被拒绝的命令同样会让之后的EXEC放弃整个事务
+            Ok(_) if oom => {
+                let err = Frame::Error(
+                    "OOM command not allowed when used memory > 'maxmemory'.".to_string(),
+                );
+                if transaction.is_active() {
+                    transaction.reject(err)
+                } else {
+                    err
+                }
+            }
+            Ok(_) if readonly => {
+                let err = Frame::Error(
+                    "READONLY You can't write against a read only replica.".to_string(),
+                );
+                if transaction.is_active() {
+                    transaction.reject(err)
+                } else {
+                    err
+                }
+            }
+            //回复OK后关闭连接，在事务中也立即生效
+            Ok(cmd::Command::Quit) => return quit(&mut conn).await,
+            Ok(cmd::Command::Reset) => {
+                reset(&mut conn, &mut db, client, &mut transaction, &mut user)
+            }
+            Ok(cmd::Command::Asking) => match db.cluster() {
+                Some(_) => {
+                    asking = true;
+                    Frame::Simple("OK".to_string())
+                }
+                None => Frame::Error("ERR This instance has cluster support disabled".to_string()),
+            },
+            Ok(cmd::Command::Multi) => transaction.begin(),
+            Ok(cmd::Command::Exec) => transaction.exec(&mut db),
+            Ok(cmd::Command::Discard) => transaction.discard(),
+            Ok(cmd::Command::Watch(cmd)) => cmd.apply(&db, &mut transaction),
+            Ok(cmd::Command::Unwatch) => transaction.unwatch(),
+            //事务中的命令只排队，无法解析的命令会让之后的EXEC放弃整个事务
+            Ok(cmd) if transaction.is_active() => transaction.queue(cmd, args.clone()),
+            Err(e) if transaction.is_active() => transaction.reject(e.into()),
+            //订阅命令接管连接，直到退订全部频道
+            Ok(cmd::Command::Subscribe(cmd)) => {
+                match cmd.apply(&db, &mut conn, invalidations, shutdown).await {
+                    Ok(cmd::Exit::Done) => continue,
+                    Ok(cmd::Exit::Reset) => {
+                        reset(&mut conn, &mut db, client, &mut transaction, &mut user)
+                    }
+                    Ok(cmd::Exit::Quit) => return quit(&mut conn).await,
+                    Err(e) => return close_reason(&e),
+                }
+            }
+            Ok(cmd::Command::Unsubscribe(cmd)) => match cmd.apply(&mut conn).await {
+                Ok(()) => continue,
+                Err(e) => return close_reason(&e),
+            },
+            //监视模式同样接管连接
+            Ok(cmd::Command::Monitor(cmd)) => match cmd.apply(&db, &mut conn, shutdown).await {
+                Ok(cmd::Exit::Done) => continue,
+                Ok(cmd::Exit::Reset) => {
+                    reset(&mut conn, &mut db, client, &mut transaction, &mut user)
+                }
+                Ok(cmd::Exit::Quit) => return quit(&mut conn).await,
+                Err(e) => return close_reason(&e),
+            },
+            //PSYNC之后连接成为复制连接，直到从节点断开或服务器关闭
+            Ok(cmd::Command::PSync(cmd)) => {
+                match cmd.apply(&db, &mut conn, client, shutdown).await {
+                    Ok(()) => continue,
+                    Err(e) => return close_reason(&e),
+                }
+            }
+            //SHUTDOWN不回复，发出流水线中之前命令的回复后关闭连接，其他连接随服务器一起关闭
+            Ok(cmd::Command::Shutdown(cmd)) => {
+                cmd.apply(&db);
+                let _ = conn.flush().await;
+                return CloseReason::Shutdown;
+            }
+            Ok(cmd::Command::Select(cmd)) => cmd.apply(&mut db),
+            Ok(cmd::Command::Hello(cmd)) => cmd.apply(&db, &mut conn, client, &mut user),
+            Ok(cmd::Command::Auth(cmd)) => cmd.apply(&db, &mut user),
+            //未认证的连接在前面已被拒绝，这里一定有用户
+            Ok(cmd::Command::Acl(cmd)) => cmd.apply(&db, user.as_deref().unwrap_or_default()),
+            Ok(cmd::Command::Client(cmd)) => cmd.apply(&db, client),
+            Ok(cmd::Command::ReplConf(cmd)) => cmd.apply(client),
+            Ok(cmd) => {
+                //先登记再读取，读取之后的修改一定会发出失效消息
+                if !reads.is_empty() {
+                    db.tracking().track(client.id(), reads);
+                }
+                //阻塞命令可能等待很久，先把流水线中之前命令的回复发送出去
+                if cmd.may_block() && conn.flush().await.is_err() {
+                    return CloseReason::Io;
+                }
+                //写命令在顺序锁内执行并传播，阻塞命令在等到数据之后自行传播
+                let propagated = info.is_some_and(|info| {
+                    info.has_flag("write") || matches!(info.name, "eval" | "evalsha")
+                });
+                if cmd.may_block() {
+                    //阻塞等待期间服务器开始关闭时放弃等待，与空闲的连接一样关闭
+                    tokio::select! {
+                        resp = cmd.apply(&db).instrument(span.clone()) => resp,
+                        _ = shutdown.recv() => {
+                            let _ = conn.flush().await;
+                            return CloseReason::Shutdown;
+                        }
+                    }
+                } else if propagated {
+                    propagate::record(
+                        &db,
+                        || span.in_scope(|| cmd.apply_now(&db)),
+                        |resp| propagate::rewrite(&db, args.clone(), resp),
+                    )
+                } else {
+                    cmd.apply(&db).instrument(span.clone()).await
+                }
+            }
+            Err(e) => e.into(),
+        };
+        if let Some(info) = info.filter(|_| !queued) {
+            let elapsed = started.elapsed();
+            db.stats().commands_processed.incr();
+            let outcome = match &resp {
+                _ if rejected => Outcome::Rejected,
+                Frame::Error(_) => Outcome::Failed,
+                _ => Outcome::Ok,
+            };
+            db.stats().commands.record(info.name, elapsed, outcome);
+            span.in_scope(|| {
+                tracing::debug!(?outcome, usec = elapsed.as_micros() as u64, "命令执行完毕")
+            });
+            if !blocking {
+                let name = client.name().unwrap_or_default();
+                db.slowlog().record(elapsed, &args, client.addr(), &name);
+                let event = if info.has_flag("fast") {
+                    "fast-command"
+                } else {
+                    "command"
+                };
+                db.latency().record(event, elapsed);
+            }
+        }
+        //回复先积攒在缓冲区中，读取下一批命令之前统一发送
+        conn.buffer_frame(&resp);
+        client.set_db(db.index());
+        //连接被CLIENT KILL关闭了自己，发出回复后关闭
+        if client.is_killed() {
+            let _ = conn.flush().await;
+            return CloseReason::Killed;
+        }
+    }
+}
+
+///QUIT：回复OK后关闭连接
+async fn quit<S: Stream>(conn: &mut Connection<S>) -> CloseReason {
+    conn.buffer_frame(&Frame::Simple("OK".to_string()));
+    let _ = conn.flush().await;
+    CloseReason::Quit
+}
+
+///RESET：丢弃事务、选回0号数据库、清除名称、关闭追踪、恢复RESP2并回到default用户，
+/// 与刚建立的连接相同
+fn reset<S: Stream>(
+    conn: &mut Connection<S>,
+    db: &mut Db,
+    client: &Client,
+    transaction: &mut cmd::Transaction,
+    user: &mut Option<String>,
+) -> Frame {
+    *transaction = cmd::Transaction::default();
+    if let Some(first) = db.select(0) {
+        *db = first;
+    }
+    client.set_name(None);
+    db.tracking().disable(client.id());
+    conn.set_protocol(codec::Protocol::Resp2);
+    *user = db.acl().default_user();
+    Frame::Simple("RESET".to_string())
+}
+
+///根据读写连接时的错误得出连接关闭的原因
+fn close_reason(err: &Error) -> CloseReason {
+    match err {
+        Error::Io(_) => CloseReason::Io,
+        _ => CloseReason::Protocol,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use tokio::io::duplex;
+    use tokio::task::JoinHandle;
+
+    //在内存管道上启动一个连接，返回客户端一侧的连接、服务端任务与通知关闭的发送端
+    fn connect(
+        db: &Db,
+    ) -> (
+        Connection<DuplexStream>,
+        JoinHandle<CloseReason>,
+        broadcast::Sender<()>,
+    ) {
+        let (client, server) = duplex(1024);
+        let (notify, _) = broadcast::channel(1);
+        let mut shutdown = Shutdown::new(notify.subscribe());
+        let db = db.clone();
+        let task = tokio::spawn(async move {
+            let (client, mut invalidations) = db.clients().register("test".to_string());
+            serve(server, db, &client, &mut invalidations, &mut shutdown).await
+        });
+        (Connection::new(client), task, notify)
+    }
+
+    fn command(args: &[&str]) -> Frame {
+        Frame::Array(
+            args.iter()
+                .map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes())))
+                .collect(),
+        )
+    }
+
+    async fn call(conn: &mut Connection<DuplexStream>, args: &[&str]) -> Frame {
+        conn.write_frame(&command(args)).await.unwrap();
+        conn.read_frame().await.unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn commands_are_served_over_a_duplex_stream() {
+        let db = Db::new(Config::default());
+        let (mut conn, task, _notify) = connect(&db);
+        assert!(call(&mut conn, &["PING"]).await == "PONG");
+        assert!(call(&mut conn, &["SET", "key", "value"]).await == "OK");
+        assert!(call(&mut conn, &["GET", "key"]).await == "value");
+        //流水线中的多个命令依次回复
+        conn.write_raw(b"*2\r\n$4\r\nINCR\r\n$1\r\nn\r\nINCR n\r\n")
+            .await
+            .unwrap();
+        assert_eq!(conn.read_frame().await.unwrap(), Some(Frame::Integer(1)));
+        assert_eq!(conn.read_frame().await.unwrap(), Some(Frame::Integer(2)));
+        drop(conn);
+        assert_eq!(task.await.unwrap(), CloseReason::Eof);
+    }
+
+    #[tokio::test]
+    async fn empty_requests_are_skipped_and_malformed_ones_close_the_connection() {
+        let db = Db::new(Config::default());
+        let (mut conn, task, _notify) = connect(&db);
+        conn.write_raw(b"*0\r\n*-1\r\n*1\r\n$4\r\nPING\r\n")
+            .await
+            .unwrap();
+        assert!(conn.read_frame().await.unwrap().unwrap() == "PONG");
+        conn.write_raw(b"*1\r\n@x\r\n").await.unwrap();
+        assert_eq!(
+            conn.read_frame().await.unwrap(),
+            Some(Frame::Error(
+                "ERR Protocol error: expected '$', got '@'".to_string()
+            ))
+        );
+        assert_eq!(task.await.unwrap(), CloseReason::Protocol);
+    }
+
+    #[tokio::test]
+    async fn shutdown_closes_idle_connections() {
+        let db = Db::new(Config::default());
+        let (mut conn, task, notify) = connect(&db);
+        assert!(call(&mut conn, &["PING"]).await == "PONG");
+        notify.send(()).unwrap();
+        assert_eq!(task.await.unwrap(), CloseReason::Shutdown);
+    }
+
+    #[tokio::test]
+    async fn shutdown_interrupts_blocking_commands() {
+        let db = Db::new(Config::default());
+        let (mut conn, task, notify) = connect(&db);
+        conn.write_frame(&command(&["BLPOP", "list", "0"]))
+            .await
+            .unwrap();
+        while db.blocked_clients() == 0 {
+            tokio::task::yield_now().await;
+        }
+        notify.send(()).unwrap();
+        let closed = tokio::time::timeout(Duration::from_secs(1), task);
+        assert_eq!(closed.await.unwrap().unwrap(), CloseReason::Shutdown);
+        assert_eq!(conn.read_frame().await.unwrap(), None);
+        assert_eq!(db.blocked_clients(), 0);
+    }
+
+    #[tokio::test]
+    async fn idle_connections_are_closed_by_the_cron() {
+        let mut config = Config::default();
+        config.set("hz", "100").unwrap();
+        config.set("timeout", "1").unwrap();
+        let guard = DbDropGuard::new(config);
+        let (mut conn, task, _notify) = connect(&guard.db());
+        let started = std::time::Instant::now();
+        assert!(call(&mut conn, &["PING"]).await == "PONG");
+        assert_eq!(task.await.unwrap(), CloseReason::Timeout);
+        assert!(started.elapsed() >= Duration::from_secs(1));
+    }
+
+    //按配置创建接受连接的上下文
+    fn context(config: Config) -> (Context, broadcast::Sender<()>) {
+        let (notify_shutdown, _) = broadcast::channel(1);
+        let ctx = Context {
+            db: Db::new(config),
+            notify_shutdown: notify_shutdown.clone(),
+            shutdown_complete: mpsc::channel(1).0,
+            connector: mpsc::unbounded_channel().0,
+        };
+        (ctx, notify_shutdown)
+    }
+
+    //经上下文接受一个内存管道上的连接，返回客户端一侧的连接
+    fn accept(ctx: &Context) -> Connection<DuplexStream> {
+        let (client, server) = duplex(1024);
+        ctx.spawn("test".to_string(), async move { Ok(server) });
+        Connection::new(client)
+    }
+
+    //maxclients为1，连接数已满时按policy处理
+    fn single_client(policy: &str) -> Config {
+        let mut config = Config::default();
+        config.set("maxclients", "1").unwrap();
+        config.set("maxclients-policy", policy).unwrap();
+        config
+    }
+
+    #[tokio::test]
+    async fn connections_over_maxclients_are_rejected() {
+        let (ctx, _notify) = context(single_client("reject"));
+        let mut first = accept(&ctx);
+        assert!(call(&mut first, &["PING"]).await == "PONG");
+        let mut second = accept(&ctx);
+        let reply = second.read_frame().await.unwrap();
+        assert!(matches!(reply, Some(Frame::Error(e)) if e == "ERR max number of clients reached"));
+        assert_eq!(second.read_frame().await.unwrap(), None);
+        assert_eq!(ctx.db.stats().rejected_connections.get(), 1);
+    }
+
+    #[tokio::test]
+    async fn connections_over_maxclients_wait_for_a_slot() {
+        let (ctx, _notify) = context(single_client("wait"));
+        let mut first = accept(&ctx);
+        assert!(call(&mut first, &["PING"]).await == "PONG");
+        let mut second = accept(&ctx);
+        second.write_frame(&command(&["PING"])).await.unwrap();
+        let waiting = tokio::time::timeout(Duration::from_millis(100), second.read_frame());
+        assert!(waiting.await.is_err());
+        //第一个连接关闭后名额空出，排队的连接开始处理命令
+        drop(first);
+        assert!(second.read_frame().await.unwrap().unwrap() == "PONG");
+        assert_eq!(ctx.db.stats().rejected_connections.get(), 0);
+    }
+
+    //等待以reason关闭的连接数达到count，连接的任务在后台结束
+    async fn closed(ctx: &Context, reason: CloseReason, count: u64) {
+        let recorded = || {
+            ctx.db
+                .stats()
+                .closed_connections()
+                .any(|(cur, n)| cur == reason && n == count)
+        };
+        let waiting = async {
+            while !recorded() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(1), waiting)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn close_reasons_are_counted() {
+        let (ctx, _notify) = context(Config::default());
+        let mut conn = accept(&ctx);
+        assert!(call(&mut conn, &["QUIT"]).await == "OK");
+        closed(&ctx, CloseReason::Quit, 1).await;
+        //被其他连接用CLIENT KILL关闭
+        let mut victim = accept(&ctx);
+        let id = match call(&mut victim, &["CLIENT", "ID"]).await {
+            Frame::Integer(id) => id.to_string(),
+            frame => panic!("unexpected reply {:?}", frame),
+        };
+        let mut killer = accept(&ctx);
+        assert_eq!(
+            call(&mut killer, &["CLIENT", "KILL", "ID", &id]).await,
+            Frame::Integer(1)
+        );
+        closed(&ctx, CloseReason::Killed, 1).await;
+        assert_eq!(victim.read_frame().await.unwrap(), None);
+        drop(killer);
+        closed(&ctx, CloseReason::Eof, 1).await;
+        let info = match call(&mut accept(&ctx), &["INFO", "stats"]).await {
+            Frame::Bulk(info) => String::from_utf8(info.to_vec()).unwrap(),
+            frame => panic!("unexpected reply {:?}", frame),
+        };
+        assert!(info.contains("closed_connections:eof=1,quit=1,killed=1,"));
+    }
+
+    #[tokio::test]
+    async fn tracking_mode_cannot_be_switched_while_enabled() {
+        let db = Db::new(Config::default());
+        let (mut conn, _task, _notify) = connect(&db);
+        let id = match call(&mut conn, &["CLIENT", "ID"]).await {
+            Frame::Integer(id) => id as u64,
+            frame => panic!("unexpected reply {:?}", frame),
+        };
+        assert!(call(&mut conn, &["CLIENT", "TRACKING", "on"]).await == "OK");
+        let switch = ["CLIENT", "TRACKING", "on", "BCAST", "PREFIX", "a"];
+        assert!(
+            matches!(call(&mut conn, &switch).await, Frame::Error(err) if err.contains("BCAST"))
+        );
+        //关闭之后可以换成广播模式，再次开启时前缀合并
+        assert!(call(&mut conn, &["CLIENT", "TRACKING", "off"]).await == "OK");
+        assert!(call(&mut conn, &switch).await == "OK");
+        let more = ["CLIENT", "TRACKING", "on", "BCAST", "PREFIX", "b"];
+        assert!(call(&mut conn, &more).await == "OK");
+        assert_eq!(db.tracking().invalidate("a1"), vec![id]);
+        assert_eq!(db.tracking().invalidate("b1"), vec![id]);
+        assert!(db.tracking().invalidate("c1").is_empty());
+        let back = ["CLIENT", "TRACKING", "on"];
+        assert!(matches!(call(&mut conn, &back).await, Frame::Error(_)));
+    }
+
+    #[tokio::test]
+    async fn writes_from_another_connection_push_invalidations() {
+        let db = Db::new(Config::default());
+        let (mut reader, _task, _notify) = connect(&db);
+        assert!(matches!(
+            call(&mut reader, &["HELLO", "3"]).await,
+            Frame::Map(_)
+        ));
+        assert!(call(&mut reader, &["CLIENT", "TRACKING", "on"]).await == "OK");
+        assert_eq!(call(&mut reader, &["GET", "key"]).await, Frame::Null);
+        let (mut writer, _task, _notify) = connect(&db);
+        assert!(call(&mut writer, &["SET", "key", "value"]).await == "OK");
+        let push = tokio::time::timeout(Duration::from_secs(1), reader.read_frame());
+        assert_eq!(
+            push.await.unwrap().unwrap(),
+            Some(Frame::Push(vec![
+                Frame::Bulk(Bytes::from_static(b"invalidate")),
+                Frame::Array(vec![Frame::Bulk(Bytes::from_static(b"key"))]),
+            ]))
+        );
+        //失效消息只发送一次，再次读取之后才重新追踪
+        assert!(call(&mut writer, &["SET", "key", "other"]).await == "OK");
+        assert!(call(&mut reader, &["GET", "key"]).await == "other");
+    }
+
+    #[tokio::test]
+    async fn latency_spikes_are_recorded_once_the_threshold_is_lowered() {
+        let db = Db::new(Config::default());
+        let (mut conn, _task, _notify) = connect(&db);
+        //阈值默认为0，不记录
+        assert!(call(&mut conn, &["DEBUG", "SLEEP", "0.02"]).await == "OK");
+        assert_eq!(
+            call(&mut conn, &["LATENCY", "LATEST"]).await,
+            Frame::Array(vec![])
+        );
+        let lower = ["CONFIG", "SET", "latency-monitor-threshold", "10"];
+        assert!(call(&mut conn, &lower).await == "OK");
+        assert!(call(&mut conn, &["DEBUG", "SLEEP", "0.02"]).await == "OK");
+        let latest = match call(&mut conn, &["LATENCY", "LATEST"]).await {
+            Frame::Array(latest) => latest,
+            frame => panic!("unexpected reply {:?}", frame),
+        };
+        assert_eq!(latest.len(), 1);
+        match &latest[0] {
+            Frame::Array(event) => {
+                assert!(event[0] == "command");
+                assert!(matches!(event[2], Frame::Integer(latency) if latency >= 20));
+                assert!(matches!(event[3], Frame::Integer(max) if max >= 20));
+            }
+            frame => panic!("unexpected event {:?}", frame),
+        }
+    }
+
+    #[tokio::test]
+    async fn only_writes_that_changed_data_are_appended_to_the_aof() {
+        let dir = std::env::temp_dir().join(format!("aof-propagation-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut config = Config::default();
+        config.set("dir", dir.to_str().unwrap()).unwrap();
+        config.set("appendonly", "yes").unwrap();
+        //每批写入都刷盘之后才回复，回复之后文件中一定已经有这条命令
+        config.set("appendfsync", "always").unwrap();
+        let path = config.aof_path();
+        let db = Db::new(config);
+        aof::start(&db, false).unwrap();
+        let (mut conn, _task, _notify) = connect(&db);
+        assert_eq!(call(&mut conn, &["DEL", "key"]).await, Frame::Integer(0));
+        assert!(call(&mut conn, &["SET", "key", "value"]).await == "OK");
+        assert_eq!(call(&mut conn, &["DEL", "key"]).await, Frame::Integer(1));
+        assert_eq!(call(&mut conn, &["DEL", "key"]).await, Frame::Integer(0));
+        db.aof().stop();
+        let data = std::fs::read(&path).unwrap();
+        let _ = std::fs::remove_dir_all(&dir);
+        let count = |command: &[u8]| {
+            data.windows(command.len())
+                .filter(|w| *w == command)
+                .count()
+        };
+        assert_eq!(count(b"$3\r\nSET\r\n"), 1);
+        assert_eq!(count(b"$3\r\nDEL\r\n"), 1);
+    }
+
+    #[tokio::test]
+    async fn hgetall_replies_with_a_map_only_on_resp3() {
+        let db = Db::new(Config::default());
+        let (mut conn, _task, _notify) = connect(&db);
+        assert_eq!(
+            call(&mut conn, &["HSET", "hash", "field", "value"]).await,
+            Frame::Integer(1)
+        );
+        let bulk = |text: &'static str| Frame::Bulk(Bytes::from_static(text.as_bytes()));
+        assert_eq!(
+            call(&mut conn, &["HGETALL", "hash"]).await,
+            Frame::Array(vec![bulk("field"), bulk("value")])
+        );
+        assert!(matches!(
+            call(&mut conn, &["HELLO", "3"]).await,
+            Frame::Map(_)
+        ));
+        assert_eq!(
+            call(&mut conn, &["HGETALL", "hash"]).await,
+            Frame::Map(vec![(bulk("field"), bulk("value"))])
+        );
+        assert!(matches!(
+            call(&mut conn, &["HELLO", "2"]).await,
+            Frame::Array(_)
+        ));
+        assert_eq!(
+            call(&mut conn, &["HGETALL", "hash"]).await,
+            Frame::Array(vec![bulk("field"), bulk("value")])
+        );
+    }
+
+    //INFO clients中的blocked_clients
+    async fn blocked_clients(conn: &mut Connection<DuplexStream>) -> String {
+        let info = match call(conn, &["INFO", "clients"]).await {
+            Frame::Bulk(info) => String::from_utf8(info.to_vec()).unwrap(),
+            frame => panic!("unexpected reply {:?}", frame),
+        };
+        info.lines()
+            .find_map(|line| line.strip_prefix("blocked_clients:"))
+            .unwrap()
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn blocked_clients_are_counted_until_they_are_served() {
+        let db = Db::new(Config::default());
+        let (mut blocked, _task, _notify) = connect(&db);
+        let (mut other, _task, _notify) = connect(&db);
+        assert_eq!(blocked_clients(&mut other).await, "0");
+        blocked
+            .write_frame(&command(&["BLPOP", "list", "0"]))
+            .await
+            .unwrap();
+        while db.blocked_clients() == 0 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(blocked_clients(&mut other).await, "1");
+        assert_eq!(
+            call(&mut other, &["RPUSH", "list", "a"]).await,
+            Frame::Integer(1)
+        );
+        assert!(matches!(
+            blocked.read_frame().await.unwrap(),
+            Some(Frame::Array(_))
+        ));
+        assert_eq!(blocked_clients(&mut other).await, "0");
+        //超时返回的连接同样不再计入
+        assert_eq!(
+            call(&mut blocked, &["BLPOP", "list", "0.01"]).await,
+            Frame::Null
+        );
+        assert_eq!(blocked_clients(&mut other).await, "0");
+    }
+
+    //回复是否为指定错误码开头的错误
+    fn is_error(frame: &Frame, code: &str) -> bool {
+        matches!(frame, Frame::Error(e) if e.starts_with(code))
+    }
+
+    #[tokio::test]
+    async fn read_only_users_cannot_write_or_run_scripts() {
+        let db = Db::new(Config::default());
+        let (mut conn, _task, _notify) = connect(&db);
+        let setuser = ["ACL", "SETUSER", "reader", "on", ">secret", "~*", "+@read"];
+        assert!(call(&mut conn, &setuser).await == "OK");
+        assert!(call(&mut conn, &["SET", "key", "value"]).await == "OK");
+        assert!(call(&mut conn, &["AUTH", "reader", "secret"]).await == "OK");
+        assert!(call(&mut conn, &["GET", "key"]).await == "value");
+        let denied = call(&mut conn, &["SET", "key", "other"]).await;
+        assert!(is_error(&denied, "NOPERM"), "{:?}", denied);
+        let script = ["EVAL", "return redis.call('SET', 'key', 'other')", "0"];
+        let denied = call(&mut conn, &script).await;
+        assert!(is_error(&denied, "NOPERM"), "{:?}", denied);
+        assert!(call(&mut conn, &["GET", "key"]).await == "value");
+    }
+
+    #[tokio::test]
+    async fn acl_whoami_follows_auth() {
+        let db = Db::new(Config::default());
+        let (mut conn, _task, _notify) = connect(&db);
+        assert!(call(&mut conn, &["ACL", "WHOAMI"]).await == "default");
+        let setuser = ["ACL", "SETUSER", "alice", "on", ">pw", "+@all", "~*"];
+        assert!(call(&mut conn, &setuser).await == "OK");
+        let wrong = call(&mut conn, &["AUTH", "alice", "nope"]).await;
+        assert!(is_error(&wrong, "WRONGPASS"), "{:?}", wrong);
+        assert!(call(&mut conn, &["ACL", "WHOAMI"]).await == "default");
+        assert!(call(&mut conn, &["AUTH", "alice", "pw"]).await == "OK");
+        assert!(call(&mut conn, &["ACL", "WHOAMI"]).await == "alice");
+        //RESET回到default用户
+        assert!(call(&mut conn, &["RESET"]).await == "RESET");
+        assert!(call(&mut conn, &["ACL", "WHOAMI"]).await == "default");
+    }
+
+    #[tokio::test]
+    async fn unsupported_protocol_versions_leave_the_connection_usable() {
+        let db = Db::new(Config::default());
+        let (mut conn, _task, _notify) = connect(&db);
+        let reply = call(&mut conn, &["HELLO", "4"]).await;
+        assert!(is_error(&reply, "NOPROTO"), "{:?}", reply);
+        //仍然使用RESP2
+        assert_eq!(
+            call(&mut conn, &["HGETALL", "missing"]).await,
+            Frame::Array(vec![])
+        );
+        assert!(matches!(
+            call(&mut conn, &["HELLO", "3"]).await,
+            Frame::Map(_)
+        ));
+        assert_eq!(call(&mut conn, &["GET", "missing"]).await, Frame::Null);
+    }
+
+    #[tokio::test]
+    async fn shutdown_closes_subscribed_connections() {
+        let db = Db::new(Config::default());
+        let (mut conn, task, notify) = connect(&db);
+        conn.write_frame(&command(&["SUBSCRIBE", "channel"]))
+            .await
+            .unwrap();
+        let reply = conn.read_frame().await.unwrap().unwrap();
+        assert!(matches!(reply, Frame::Array(ref parts) if parts[0] == "subscribe"));
+        notify.send(()).unwrap();
+        let closed = tokio::time::timeout(Duration::from_secs(1), task);
+        assert_eq!(closed.await.unwrap().unwrap(), CloseReason::Shutdown);
+        assert_eq!(conn.read_frame().await.unwrap(), None);
+    }
+}
@@ -0,0 +1,62 @@
+use tokio::sync::broadcast;
+
+///监听服务器关闭的信号
+///
+/// 每个连接任务持有一个，服务器开始关闭时广播通道的发送端被丢弃，所有接收端随之收到通知。
+/// 收到一次后再调用recv会立即返回
+#[derive(Debug)]
+pub(crate) struct Shutdown {
+    //是否已经收到关闭的信号
+    is_shutdown: bool,
+    //接收关闭信号的通道
+    notify: broadcast::Receiver<()>,
+}
+
+impl Shutdown {
+    ///基于广播通道的接收端创建
+    pub(crate) fn new(notify: broadcast::Receiver<()>) -> Shutdown {
+        Shutdown {
+            is_shutdown: false,
+            notify,
+        }
+    }
+
+    ///等待关闭的信号
+    ///
+    /// 只是等待通道上的消息，在select!中被取消也不会丢失信号
+    pub(crate) async fn recv(&mut self) {
+        if self.is_shutdown {
+            return;
+        }
+        //发送端被丢弃或发来消息都视为关闭
+        let _ = self.notify.recv().await;
+        self.is_shutdown = true;
+    }
+}
+
+///等待进程收到ctrl-c或SIGTERM
+///
+/// 注册信号处理失败时永远不会返回，服务器只能被直接杀死
+pub async fn signal() {
+    let ctrl_c = async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut stream) => {
+                stream.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+    }
+}
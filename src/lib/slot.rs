@@ -0,0 +1,56 @@
+///集群中哈希槽的数量
+pub const SLOT_COUNT: u16 = 16384;
+
+///计算键所属的哈希槽
+///
+/// 与redis一致，使用CRC16对16384取模。键中包含非空的“{...}”时只对第一个花括号内的内容计算，
+/// 这样相关的键可以通过相同的哈希标签被分配到同一个槽中
+pub fn key_hash_slot(key: &[u8]) -> u16 {
+    crc16(hash_tag(key)) % SLOT_COUNT
+}
+
+//提取参与计算的部分，没有合法的哈希标签时使用整个键
+fn hash_tag(key: &[u8]) -> &[u8] {
+    let start = match key.iter().position(|&b| b == b'{') {
+        Some(start) => start + 1,
+        None => return key,
+    };
+    match key[start..].iter().position(|&b| b == b'}') {
+        Some(len) if len > 0 => &key[start..start + len],
+        _ => key,
+    }
+}
+
+//CRC16-CCITT（XMODEM），多项式为0x1021，初始值为0
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slots_match_redis() {
+        assert_eq!(key_hash_slot(b"foo"), 12182);
+        assert_eq!(key_hash_slot(b"{user1000}.following"), 3443);
+        assert_eq!(key_hash_slot(b"{user1000}.followers"), 3443);
+        //空的哈希标签不生效，整个键参与计算
+        assert_eq!(
+            key_hash_slot(b"foo{}{bar}"),
+            crc16(b"foo{}{bar}") % SLOT_COUNT
+        );
+        assert_eq!(key_hash_slot(b"foo{{bar}}zap"), key_hash_slot(b"{bar"));
+    }
+}
@@ -0,0 +1,120 @@
+use bytes::Bytes;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicI64, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+//每条记录最多保留的参数个数，超出的参数合并为一条说明
+const MAX_ARGS: usize = 32;
+//每个参数最多保留的字节数
+const MAX_ARG_LEN: usize = 128;
+
+///慢查询日志中的一条记录
+#[derive(Debug, Clone)]
+pub(crate) struct Entry {
+    ///记录的编号，服务器运行期间递增
+    pub(crate) id: u64,
+    ///命令开始执行时的unix时间戳（秒）
+    pub(crate) time: u64,
+    ///执行耗时
+    pub(crate) duration: Duration,
+    ///截断后的参数，包括命令名
+    pub(crate) args: Vec<Bytes>,
+    ///执行命令的连接的地址
+    pub(crate) addr: String,
+    ///执行命令的连接的名称
+    pub(crate) name: String,
+}
+
+///慢查询日志
+///
+/// 执行时间超过slowlog-log-slower-than微秒的命令被记录下来，最多保留slowlog-max-len条，
+/// 超出时丢弃最旧的记录。两项配置在修改时同步到这里，记录时无需读取配置
+#[derive(Debug, Default)]
+pub(crate) struct SlowLog {
+    //最新的记录在前
+    entries: Mutex<VecDeque<Entry>>,
+    //下一条记录的编号
+    next_id: AtomicU64,
+    //记录的阈值（微秒），为负数时不记录
+    threshold: AtomicI64,
+    //最多保留的记录数
+    max_len: AtomicUsize,
+}
+
+impl SlowLog {
+    ///修改阈值与最多保留的记录数
+    pub(crate) fn configure(&self, threshold: i64, max_len: usize) {
+        self.threshold.store(threshold, Ordering::Relaxed);
+        self.max_len.store(max_len, Ordering::Relaxed);
+        let mut entries = self.entries.lock().unwrap();
+        entries.truncate(max_len);
+    }
+
+    ///命令执行完毕，耗时超过阈值时记录下来
+    pub(crate) fn record(&self, duration: Duration, args: &[Bytes], addr: &str, name: &str) {
+        let threshold = self.threshold.load(Ordering::Relaxed);
+        if threshold < 0 || duration.as_micros() < threshold as u128 {
+            return;
+        }
+        let time = (SystemTime::now() - duration)
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        let entry = Entry {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            time,
+            duration,
+            args: truncate(args),
+            addr: addr.to_string(),
+            name: name.to_string(),
+        };
+        let max_len = self.max_len.load(Ordering::Relaxed);
+        let mut entries = self.entries.lock().unwrap();
+        entries.push_front(entry);
+        entries.truncate(max_len);
+    }
+
+    ///最新的count条记录，None时返回全部记录
+    pub(crate) fn latest(&self, count: Option<usize>) -> Vec<Entry> {
+        let entries = self.entries.lock().unwrap();
+        let count = count.unwrap_or(entries.len());
+        entries.iter().take(count).cloned().collect()
+    }
+
+    ///记录的条数
+    pub(crate) fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    ///清空所有记录
+    pub(crate) fn reset(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+//按redis的规则截断参数，避免巨大的命令占用日志的内存
+fn truncate(args: &[Bytes]) -> Vec<Bytes> {
+    let keep = if args.len() > MAX_ARGS {
+        MAX_ARGS - 1
+    } else {
+        args.len()
+    };
+    let mut truncated: Vec<Bytes> = args[..keep]
+        .iter()
+        .map(|arg| {
+            if arg.len() > MAX_ARG_LEN {
+                let more = format!("... ({} more bytes)", arg.len() - MAX_ARG_LEN);
+                let mut short = arg[..MAX_ARG_LEN].to_vec();
+                short.extend_from_slice(more.as_bytes());
+                Bytes::from(short)
+            } else {
+                arg.clone()
+            }
+        })
+        .collect();
+    if keep < args.len() {
+        let more = format!("... ({} more arguments)", args.len() - keep);
+        truncated.push(Bytes::from(more));
+    }
+    truncated
+}
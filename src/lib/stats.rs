@@ -0,0 +1,333 @@
+use crate::lib::random;
+use dashmap::DashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+///单调递增的计数器
+#[derive(Debug, Default)]
+pub(crate) struct Counter(AtomicU64);
+
+impl Counter {
+    ///计数加一
+    pub(crate) fn incr(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    ///计数加n
+    pub(crate) fn add(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    ///当前的计数
+    pub(crate) fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+///连接关闭的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CloseReason {
+    ///客户端关闭了连接
+    Eof,
+    ///客户端发送了QUIT
+    Quit,
+    ///被CLIENT KILL关闭
+    Killed,
+    ///客户端发送了无法解析的数据
+    Protocol,
+    ///读写套接字失败
+    Io,
+    ///服务器正在关闭
+    Shutdown,
+    ///空闲超过timeout
+    Timeout,
+}
+
+impl CloseReason {
+    ///所有的原因，按在统计中的顺序排列
+    pub(crate) const ALL: [CloseReason; 7] = [
+        CloseReason::Eof,
+        CloseReason::Quit,
+        CloseReason::Killed,
+        CloseReason::Protocol,
+        CloseReason::Io,
+        CloseReason::Shutdown,
+        CloseReason::Timeout,
+    ];
+
+    ///在日志、INFO与指标中使用的名称
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            CloseReason::Eof => "eof",
+            CloseReason::Quit => "quit",
+            CloseReason::Killed => "killed",
+            CloseReason::Protocol => "protocol_error",
+            CloseReason::Io => "io_error",
+            CloseReason::Shutdown => "shutdown",
+            CloseReason::Timeout => "timeout",
+        }
+    }
+}
+
+impl std::fmt::Display for CloseReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(self.name(), f)
+    }
+}
+
+///服务器运行期间的统计，INFO据此生成回复
+///
+/// 计数器由连接的处理循环与存储层在各自的路径上累加，读取时不加锁，各项之间不保证是同一时刻的值
+#[derive(Debug)]
+pub(crate) struct Stats {
+    //服务器启动的时刻
+    started: Instant,
+    //本次运行的随机标识
+    run_id: String,
+    ///接受的连接数
+    pub(crate) connections_received: Counter,
+    ///因连接数达到上限被拒绝的连接数
+    pub(crate) rejected_connections: Counter,
+    //按原因分开的关闭的连接数，顺序与CloseReason::ALL一致
+    closed_connections: [Counter; CloseReason::ALL.len()],
+    ///执行的命令数
+    pub(crate) commands_processed: Counter,
+    ///过期被删除的键数
+    pub(crate) expired_keys: Counter,
+    ///因内存达到上限被淘汰的键数
+    pub(crate) evicted_keys: Counter,
+    ///键被修改的次数，包括过期与淘汰，持久化据此判断自上次保存之后的修改
+    pub(crate) changes: Counter,
+    ///读命令命中键的次数
+    pub(crate) keyspace_hits: Counter,
+    ///读命令未命中键的次数
+    pub(crate) keyspace_misses: Counter,
+    ///按命令名分开的调用统计
+    pub(crate) commands: CommandStats,
+}
+
+impl Default for Stats {
+    fn default() -> Stats {
+        let run_id = (0..5)
+            .map(|_| format!("{:08x}", random::next_u64() as u32))
+            .collect();
+        Stats {
+            started: Instant::now(),
+            run_id,
+            connections_received: Counter::default(),
+            rejected_connections: Counter::default(),
+            closed_connections: Default::default(),
+            commands_processed: Counter::default(),
+            expired_keys: Counter::default(),
+            evicted_keys: Counter::default(),
+            changes: Counter::default(),
+            keyspace_hits: Counter::default(),
+            keyspace_misses: Counter::default(),
+            commands: CommandStats::default(),
+        }
+    }
+}
+
+impl Stats {
+    ///服务器已经运行的时间
+    pub(crate) fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    ///记录一个连接因reason关闭
+    pub(crate) fn connection_closed(&self, reason: CloseReason) {
+        self.closed_connections[reason as usize].incr();
+    }
+
+    ///各个原因关闭的连接数
+    pub(crate) fn closed_connections(&self) -> impl Iterator<Item = (CloseReason, u64)> + '_ {
+        CloseReason::ALL
+            .into_iter()
+            .zip(&self.closed_connections)
+            .map(|(reason, counter)| (reason, counter.get()))
+    }
+
+    ///本次运行的随机标识，40个十六进制字符
+    pub(crate) fn run_id(&self) -> &str {
+        &self.run_id
+    }
+}
+
+///一条命令的调用统计
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandStat {
+    ///命令名，小写
+    pub name: &'static str,
+    ///执行的次数，包括失败的调用
+    pub calls: u64,
+    ///执行的总耗时（微秒）
+    pub usec: u64,
+    ///执行后回复了错误的次数
+    pub failed_calls: u64,
+    ///执行之前就被拒绝的次数，如参数错误、权限不足或未认证
+    pub rejected_calls: u64,
+}
+
+impl CommandStat {
+    ///平均每次执行的耗时（微秒）
+    pub fn usec_per_call(&self) -> f64 {
+        if self.calls == 0 {
+            0.0
+        } else {
+            self.usec as f64 / self.calls as f64
+        }
+    }
+}
+
+//直方图中每个2的幂区间再细分的桶数，相对误差不超过1/16
+const SUB_BUCKETS: u64 = 16;
+//直方图记录的最大耗时为2^40微秒，约12天，更长的耗时计入最后一个桶
+const MAX_EXPONENT: u64 = 40;
+//直方图的桶数
+const BUCKETS: usize = ((MAX_EXPONENT - 3) * SUB_BUCKETS) as usize;
+
+///命令执行的结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Outcome {
+    ///执行后回复了正常的结果
+    Ok,
+    ///执行后回复了错误
+    Failed,
+    ///执行之前就被拒绝
+    Rejected,
+}
+
+///按命令名分开的调用次数、耗时与耗时分布
+///
+/// 每条命令第一次被调用时才分配统计，耗时分布记录在对数分桶的直方图中，用于计算百分位数
+#[derive(Debug, Default)]
+pub(crate) struct CommandStats {
+    commands: DashMap<&'static str, Record>,
+}
+
+#[derive(Debug)]
+struct Record {
+    stat: CommandStat,
+    //耗时的分布，执行之前就被拒绝的调用不计入
+    histogram: Vec<u64>,
+}
+
+impl CommandStats {
+    ///记录一次调用
+    pub(crate) fn record(&self, name: &'static str, elapsed: Duration, outcome: Outcome) {
+        let mut record = self.commands.entry(name).or_insert_with(|| Record {
+            stat: CommandStat {
+                name,
+                calls: 0,
+                usec: 0,
+                failed_calls: 0,
+                rejected_calls: 0,
+            },
+            histogram: vec![0; BUCKETS],
+        });
+        if outcome == Outcome::Rejected {
+            record.stat.rejected_calls += 1;
+            return;
+        }
+        let usec = elapsed.as_micros() as u64;
+        record.stat.calls += 1;
+        record.stat.usec += usec;
+        if outcome == Outcome::Failed {
+            record.stat.failed_calls += 1;
+        }
+        record.histogram[bucket(usec)] += 1;
+    }
+
+    ///所有被调用过的命令的统计，按命令名排列
+    pub(crate) fn all(&self) -> Vec<CommandStat> {
+        let mut stats: Vec<CommandStat> = self
+            .commands
+            .iter()
+            .map(|record| record.stat.clone())
+            .collect();
+        stats.sort_by_key(|stat| stat.name);
+        stats
+    }
+
+    ///各条命令耗时（微秒）的百分位数，按命令名排列，没有成功执行过的命令不列出
+    ///
+    /// percentiles为0到100之间的百分数，结果是所在桶的上界，精度与直方图的分桶一致
+    pub(crate) fn percentiles(&self, percentiles: &[f64]) -> Vec<(&'static str, Vec<u64>)> {
+        let mut all: Vec<(&'static str, Vec<u64>)> = self
+            .commands
+            .iter()
+            .filter(|record| record.stat.calls > 0)
+            .map(|record| {
+                let values = percentiles
+                    .iter()
+                    .map(|percentile| quantile(&record.histogram, record.stat.calls, *percentile))
+                    .collect();
+                (record.stat.name, values)
+            })
+            .collect();
+        all.sort_by_key(|(name, _)| *name);
+        all
+    }
+
+    ///各条命令成功执行的耗时不超过每个上界（微秒）的调用次数，按命令名排列
+    ///
+    /// 用于导出累积的直方图，耗时按所在桶的上界比较，精度与直方图的分桶一致
+    pub(crate) fn cumulative(&self, bounds: &[u64]) -> Vec<(CommandStat, Vec<u64>)> {
+        let mut all: Vec<(CommandStat, Vec<u64>)> = self
+            .commands
+            .iter()
+            .filter(|record| record.stat.calls > 0)
+            .map(|record| {
+                let counts = bounds
+                    .iter()
+                    .map(|bound| {
+                        record
+                            .histogram
+                            .iter()
+                            .enumerate()
+                            .take_while(|(bucket, _)| upper_bound(*bucket) <= *bound)
+                            .map(|(_, count)| count)
+                            .sum()
+                    })
+                    .collect();
+                (record.stat.clone(), counts)
+            })
+            .collect();
+        all.sort_by_key(|(stat, _)| stat.name);
+        all
+    }
+}
+
+//耗时所在的桶，小于SUB_BUCKETS的耗时每个值一个桶，之后每个2的幂区间分为SUB_BUCKETS个桶
+fn bucket(usec: u64) -> usize {
+    if usec < SUB_BUCKETS {
+        return usec as usize;
+    }
+    let exponent = (63 - usec.leading_zeros() as u64).min(MAX_EXPONENT - 1);
+    let mantissa = (usec >> (exponent - 4)).min(2 * SUB_BUCKETS - 1);
+    ((exponent - 3) * SUB_BUCKETS + mantissa - SUB_BUCKETS) as usize
+}
+
+//桶中最大的耗时
+fn upper_bound(bucket: usize) -> u64 {
+    let bucket = bucket as u64;
+    if bucket < SUB_BUCKETS {
+        return bucket;
+    }
+    let exponent = bucket / SUB_BUCKETS + 3;
+    let mantissa = bucket % SUB_BUCKETS + SUB_BUCKETS;
+    ((mantissa + 1) << (exponent - 4)) - 1
+}
+
+//直方图中第percentile百分位的耗时
+fn quantile(histogram: &[u64], total: u64, percentile: f64) -> u64 {
+    let rank = ((percentile / 100.0 * total as f64).ceil() as u64).clamp(1, total);
+    let mut seen = 0;
+    for (bucket, count) in histogram.iter().enumerate() {
+        seen += count;
+        if seen >= rank {
+            return upper_bound(bucket);
+        }
+    }
+    upper_bound(histogram.len() - 1)
+}
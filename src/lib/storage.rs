@@ -0,0 +1,462 @@
+use crate::lib::db::Entry;
+use crate::lib::scan::Buckets;
+use crate::lib::slot::{key_hash_slot, SLOT_COUNT};
+use crate::lib::value::TYPE_NAMES;
+use std::cell::Cell;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+///键空间的存储后端
+///
+/// 只负责按键存取条目并统计内存占用，版本号、过期索引、键空间事件与失效通知都由Db在此之上维护，
+/// 因此换用其他的后端不需要改动命令。方法会被多个线程同时调用，
+/// 同一个键上的entry必须与其他修改互斥，读取、修改与写回之间不能插入其他连接的修改。
+///
+/// 传入的回调都在持有后端内部的锁时调用，回调中不能再调用同一个后端的任何方法，
+/// 否则可能在同一把锁上死锁，需要读写其他键时先在回调中记下，返回之后再操作。
+/// 默认的后端检查到这样的重入时直接panic
+pub(crate) trait Storage: Debug + Send + Sync {
+    ///读取键对应的条目，键存在时以条目调用f并返回true
+    fn get(&self, key: &str, f: &mut dyn FnMut(&Entry)) -> bool;
+
+    ///写入键对应的条目，返回原有的条目
+    fn set(&self, key: String, entry: Entry) -> Option<Entry>;
+
+    ///删除键，返回被删除的键与条目
+    fn del(&self, key: &str) -> Option<(String, Entry)>;
+
+    ///条目满足f时删除，判断与删除之间不会插入其他修改
+    fn del_if(&self, key: &str, f: &dyn Fn(&Entry) -> bool) -> Option<(String, Entry)>;
+
+    ///在同一次加锁中读取并修改键对应的条目
+    ///
+    /// f拿到键与当前的条目（不存在时为None），可以原地修改、替换或取走条目，返回时为None的键被删除
+    fn entry(&self, key: String, f: &mut dyn FnMut(&str, &mut Option<Entry>));
+
+    ///在同一次加锁中读取并修改多个键对应的条目，keys不能重复
+    ///
+    /// f拿到与keys一一对应的条目，用法与entry相同。后端无法在一次加锁中覆盖所有的键时不调用f并返回false，
+    /// 由调用方改为独占整个键空间后逐个修改
+    fn entries(&self, keys: &[&str], f: &mut dyn FnMut(&mut [Option<Entry>])) -> bool;
+
+    ///按后端内部的顺序遍历所有条目，f返回false时停止
+    ///
+    /// 遍历期间其他连接的修改可能穿插进来，结果不要求是某一时刻的快照
+    fn scan(&self, f: &mut dyn FnMut(&str, &Entry) -> bool);
+
+    ///从游标开始遍历一批条目，返回下一个游标，游标0代表开始与结束
+    ///
+    /// 每批大约访问count个条目，遍历期间一直存在的条目至少返回一次，期间增删的条目可能返回也可能不返回
+    fn scan_from(&self, cursor: u64, count: usize, f: &mut dyn FnMut(&str, &Entry)) -> u64;
+
+    ///键的数量，包括已过期但尚未被删除的键
+    fn len(&self) -> usize;
+
+    ///所有条目估算的内存占用（字节），按条目的size累计
+    fn memory_usage(&self) -> usize;
+
+    ///各类型的键数，下标与Value::type_index一致，包括已过期但尚未被删除的键
+    fn type_counts(&self) -> [usize; TYPE_NAMES.len()];
+
+    ///各类型被删除的键数，包括删除、过期、淘汰与清空，键被覆盖为其他类型的值不算删除
+    fn deletions(&self) -> [u64; TYPE_NAMES.len()];
+
+    ///因锁被其他线程占用而不得不等待的次数
+    fn contended(&self) -> u64;
+
+    ///各个分片的统计，没有分片的后端视为只有一个分片
+    fn shards(&self) -> Vec<ShardStats>;
+}
+
+impl dyn Storage {
+    ///读取键对应的条目，返回f的结果，键不存在时返回None
+    pub(crate) fn read<R>(&self, key: &str, f: impl FnOnce(&Entry) -> R) -> Option<R> {
+        let mut f = Some(f);
+        let mut result = None;
+        self.get(key, &mut |entry| {
+            if let Some(f) = f.take() {
+                result = Some(f(entry));
+            }
+        });
+        result
+    }
+
+    ///与entry相同，返回f的结果
+    pub(crate) fn update<R>(
+        &self,
+        key: String,
+        f: impl FnOnce(&str, &mut Option<Entry>) -> R,
+    ) -> R {
+        let mut f = Some(f);
+        let mut result = None;
+        self.entry(key, &mut |key, slot| {
+            if let Some(f) = f.take() {
+                result = Some(f(key, slot));
+            }
+        });
+        result.expect("存储后端没有调用entry的回调")
+    }
+}
+
+thread_local! {
+    //当前线程是否正在持有分片锁时执行回调
+    static IN_CALLBACK: Cell<bool> = const { Cell::new(false) };
+}
+
+//持有分片锁时执行回调，期间当前线程对存储的访问都会在reentry中panic
+fn callback<R>(f: impl FnOnce() -> R) -> R {
+    //f发生panic时同样需要清除标记
+    struct Reset;
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            IN_CALLBACK.with(|inside| inside.set(false));
+        }
+    }
+    IN_CALLBACK.with(|inside| inside.set(true));
+    let _reset = Reset;
+    f()
+}
+
+//在回调中再次访问存储时panic，而不是在已经持有的锁上死锁
+fn reentry() {
+    assert!(
+        !IN_CALLBACK.with(Cell::get),
+        "存储后端的回调中不能再访问存储"
+    );
+}
+
+///默认的存储后端，按键分片的内存存储
+///
+/// 键按哈希槽路由到固定数量的分片上，每个分片是一个由读写锁保护的Buckets，不同分片上的读写互不影响。
+/// 分片数是2的幂，因此哈希标签相同的键总是落在同一个分片上，可以在一次加锁中原子地修改
+#[derive(Debug)]
+pub(crate) struct ShardedStorage {
+    shards: Box<[Shard]>,
+    //所有条目估算的内存占用之和
+    used: AtomicUsize,
+    //各类型的键数
+    types: [AtomicUsize; TYPE_NAMES.len()],
+    //各类型被删除的键数
+    deleted: [AtomicU64; TYPE_NAMES.len()],
+}
+
+//一个分片，按缓存行对齐，以免相邻分片的锁与计数器互相干扰
+#[derive(Debug, Default)]
+#[repr(align(128))]
+struct Shard {
+    entries: RwLock<Buckets<String, Entry>>,
+    //该分片上条目估算的内存占用之和
+    used: AtomicUsize,
+    //读取与修改的次数
+    reads: AtomicU64,
+    writes: AtomicU64,
+    //锁被占用的次数
+    contended: AtomicU64,
+}
+
+///一个分片的统计
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct ShardStats {
+    ///键的数量
+    pub(crate) keys: usize,
+    ///估算的内存占用（字节）
+    pub(crate) memory: usize,
+    ///读取的次数
+    pub(crate) reads: u64,
+    ///修改的次数
+    pub(crate) writes: u64,
+    ///锁被占用而不得不等待的次数
+    pub(crate) contended: u64,
+}
+
+impl ShardedStorage {
+    ///创建有shards个分片的存储，分片数会被调整为不超过哈希槽数量的2的幂
+    pub(crate) fn new(shards: usize) -> ShardedStorage {
+        let shards = shards
+            .clamp(1, SLOT_COUNT as usize)
+            .next_power_of_two()
+            .min(SLOT_COUNT as usize);
+        ShardedStorage {
+            shards: (0..shards).map(|_| Shard::default()).collect(),
+            used: AtomicUsize::new(0),
+            types: Default::default(),
+            deleted: Default::default(),
+        }
+    }
+
+    //键所在的分片下标，分片数整除哈希槽数量，同一个槽的键总在同一个分片
+    fn index(&self, key: &str) -> usize {
+        key_hash_slot(key.as_bytes()) as usize % self.shards.len()
+    }
+
+    //键所在的分片
+    fn shard(&self, key: &str) -> &Shard {
+        &self.shards[self.index(key)]
+    }
+
+    //条目写入或移出后同步各类型的键数，old与new分别是原有与新的条目的类型，键被移出时计入删除数
+    fn count(&self, old: Option<usize>, new: Option<usize>) {
+        if old == new {
+            return;
+        }
+        if let Some(old) = old {
+            self.types[old].fetch_sub(1, Ordering::Relaxed);
+            if new.is_none() {
+                self.deleted[old].fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        if let Some(new) = new {
+            self.types[new].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    //条目写入或移出后同步估算的内存占用，old与new分别是原有与新的条目大小
+    fn charge(&self, shard: &Shard, old: usize, new: usize) {
+        for used in [&self.used, &shard.used] {
+            if new >= old {
+                used.fetch_add(new - old, Ordering::Relaxed);
+            } else {
+                used.fetch_sub(old - new, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+impl Shard {
+    //先尝试无等待地加读锁，锁被占用时记录一次争用后再阻塞等待
+    fn read(&self) -> RwLockReadGuard<'_, Buckets<String, Entry>> {
+        reentry();
+        self.reads.fetch_add(1, Ordering::Relaxed);
+        match self.entries.try_read() {
+            Ok(entries) => entries,
+            Err(_) => {
+                self.contended.fetch_add(1, Ordering::Relaxed);
+                self.entries.read().unwrap()
+            }
+        }
+    }
+
+    //与read相同，加的是写锁
+    fn write(&self) -> RwLockWriteGuard<'_, Buckets<String, Entry>> {
+        reentry();
+        self.writes.fetch_add(1, Ordering::Relaxed);
+        match self.entries.try_write() {
+            Ok(entries) => entries,
+            Err(_) => {
+                self.contended.fetch_add(1, Ordering::Relaxed);
+                self.entries.write().unwrap()
+            }
+        }
+    }
+}
+
+impl Storage for ShardedStorage {
+    fn get(&self, key: &str, f: &mut dyn FnMut(&Entry)) -> bool {
+        match self.shard(key).read().get(key) {
+            Some(entry) => {
+                callback(|| f(entry));
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn set(&self, key: String, entry: Entry) -> Option<Entry> {
+        let shard = self.shard(&key);
+        let size = entry.size;
+        let kind = entry.value.type_index();
+        let old = shard.write().insert(key, entry);
+        self.charge(shard, old.as_ref().map_or(0, |old| old.size), size);
+        self.count(old.as_ref().map(|old| old.value.type_index()), Some(kind));
+        old
+    }
+
+    fn del(&self, key: &str) -> Option<(String, Entry)> {
+        let shard = self.shard(key);
+        let removed = shard.write().remove_entry(key)?;
+        self.charge(shard, removed.1.size, 0);
+        self.count(Some(removed.1.value.type_index()), None);
+        Some(removed)
+    }
+
+    fn del_if(&self, key: &str, f: &dyn Fn(&Entry) -> bool) -> Option<(String, Entry)> {
+        let shard = self.shard(key);
+        let mut entries = shard.write();
+        let entry = entries.get(key)?;
+        if !callback(|| f(entry)) {
+            return None;
+        }
+        let removed = entries.remove_entry(key)?;
+        self.charge(shard, removed.1.size, 0);
+        self.count(Some(removed.1.value.type_index()), None);
+        Some(removed)
+    }
+
+    //已有的条目先取出交给f，f返回后再写回，整个过程都持有该键所在分片的写锁
+    fn entry(&self, key: String, f: &mut dyn FnMut(&str, &mut Option<Entry>)) {
+        let shard = self.shard(&key);
+        shard.write().update(key, |key, slot| {
+            let old = slot
+                .as_ref()
+                .map(|entry| (entry.size, entry.value.type_index()));
+            callback(|| f(key, slot));
+            let size = slot.as_ref().map_or(0, |entry| entry.size);
+            self.charge(shard, old.map_or(0, |(size, _)| size), size);
+            self.count(
+                old.map(|(_, kind)| kind),
+                slot.as_ref().map(|entry| entry.value.type_index()),
+            );
+        })
+    }
+
+    //键落在不同的分片上时不加锁，直接返回false
+    fn entries(&self, keys: &[&str], f: &mut dyn FnMut(&mut [Option<Entry>])) -> bool {
+        let index = match keys.first() {
+            Some(key) => self.index(key),
+            None => return true,
+        };
+        if keys.iter().any(|key| self.index(key) != index) {
+            return false;
+        }
+        let shard = &self.shards[index];
+        let mut entries = shard.write();
+        let mut slots: Vec<Option<Entry>> = keys.iter().map(|key| entries.remove(*key)).collect();
+        let old: usize = slots.iter().flatten().map(|entry| entry.size).sum();
+        let kinds: Vec<Option<usize>> = slots
+            .iter()
+            .map(|slot| slot.as_ref().map(|entry| entry.value.type_index()))
+            .collect();
+        callback(|| f(&mut slots));
+        let new: usize = slots.iter().flatten().map(|entry| entry.size).sum();
+        for ((key, slot), kind) in keys.iter().zip(slots).zip(kinds) {
+            self.count(kind, slot.as_ref().map(|entry| entry.value.type_index()));
+            if let Some(entry) = slot {
+                entries.insert(key.to_string(), entry);
+            }
+        }
+        self.charge(shard, old, new);
+        true
+    }
+
+    //逐个分片加读锁遍历
+    fn scan(&self, f: &mut dyn FnMut(&str, &Entry) -> bool) {
+        for shard in self.shards.iter() {
+            for (key, entry) in shard.read().iter() {
+                if !callback(|| f(key, entry)) {
+                    return;
+                }
+            }
+        }
+    }
+
+    //游标的低位是分片下标，其余的高位是分片内Buckets的游标。
+    //分片内的游标只用到哈希值的高位，分片数不超过哈希槽数量，两者不会重叠
+    fn scan_from(&self, cursor: u64, count: usize, f: &mut dyn FnMut(&str, &Entry)) -> u64 {
+        let mask = self.shards.len() as u64 - 1;
+        let mut index = (cursor & mask) as usize;
+        let mut cursor = cursor & !mask;
+        let mut visited = 0;
+        loop {
+            cursor = self.shards[index]
+                .read()
+                .scan(cursor, count - visited, |key, entry| {
+                    visited += 1;
+                    callback(|| f(key, entry));
+                });
+            if cursor != 0 {
+                return cursor | index as u64;
+            }
+            index += 1;
+            if index == self.shards.len() {
+                return 0;
+            }
+            if visited >= count {
+                return index as u64;
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.read().len()).sum()
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    fn type_counts(&self) -> [usize; TYPE_NAMES.len()] {
+        std::array::from_fn(|index| self.types[index].load(Ordering::Relaxed))
+    }
+
+    fn deletions(&self) -> [u64; TYPE_NAMES.len()] {
+        std::array::from_fn(|index| self.deleted[index].load(Ordering::Relaxed))
+    }
+
+    fn contended(&self) -> u64 {
+        self.shards
+            .iter()
+            .map(|shard| shard.contended.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    fn shards(&self) -> Vec<ShardStats> {
+        reentry();
+        self.shards
+            .iter()
+            .map(|shard| ShardStats {
+                keys: shard.entries.read().unwrap().len(),
+                memory: shard.used.load(Ordering::Relaxed),
+                reads: shard.reads.load(Ordering::Relaxed),
+                writes: shard.writes.load(Ordering::Relaxed),
+                contended: shard.contended.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::evict::Access;
+    use crate::lib::value::Value;
+    use bytes::Bytes;
+
+    fn entry(value: &'static str) -> Entry {
+        Entry {
+            value: Value::String(Bytes::from_static(value.as_bytes())),
+            version: 0,
+            expires_at: None,
+            access: Access::default(),
+            size: value.len(),
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "存储后端的回调中不能再访问存储")]
+    fn storage_access_inside_a_callback_panics() {
+        let storage = ShardedStorage::new(1);
+        storage.set("a".to_string(), entry("1"));
+        storage.set("b".to_string(), entry("2"));
+        //分片只有一个，遍历时持有的读锁会让这次写入死锁
+        storage.scan(&mut |_, _| {
+            storage.del("b");
+            true
+        });
+    }
+
+    #[test]
+    fn storage_is_usable_after_a_callback_returns() {
+        let storage = ShardedStorage::new(4);
+        storage.set("a".to_string(), entry("1"));
+        let mut keys = vec![];
+        storage.scan(&mut |key, _| {
+            keys.push(key.to_string());
+            true
+        });
+        for key in keys {
+            storage.entry(key, &mut |_, slot| *slot = None);
+        }
+        assert_eq!(storage.len(), 0);
+    }
+}
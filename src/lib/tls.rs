@@ -0,0 +1,70 @@
+use crate::lib;
+use crate::lib::config::Config;
+use rustls::pki_types::CertificateDer;
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use std::fmt::Display;
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+
+///按配置创建TLS监听使用的rustls配置
+///
+/// tls-auth-clients为yes时客户端必须出示由tls-ca-cert-file签发的证书，
+/// 为optional时不出示证书也可以连接，但出示的证书必须有效
+pub(crate) fn server_config(config: &Config) -> lib::Result<Arc<ServerConfig>> {
+    let certs = load_certs(config.tls_cert_file())?;
+    let key_file = config.tls_key_file();
+    let key = rustls_pemfile::private_key(&mut BufReader::new(open(key_file)?))?
+        .ok_or_else(|| invalid(format!("No private key found in '{}'", key_file)))?;
+    let builder = ServerConfig::builder();
+    let builder = match config.tls_auth_clients() {
+        "no" => builder.with_no_client_auth(),
+        auth => {
+            let ca_file = config.tls_ca_cert_file();
+            if ca_file.is_empty() {
+                return Err(invalid(
+                    "tls-ca-cert-file is required to authenticate clients",
+                ));
+            }
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(ca_file)? {
+                roots.add(cert).map_err(invalid)?;
+            }
+            let verifier = WebPkiClientVerifier::builder(Arc::new(roots));
+            let verifier = if auth == "optional" {
+                verifier.allow_unauthenticated()
+            } else {
+                verifier
+            };
+            builder.with_client_cert_verifier(verifier.build().map_err(invalid)?)
+        }
+    };
+    let config = builder.with_single_cert(certs, key).map_err(invalid)?;
+    Ok(Arc::new(config))
+}
+
+//读取PEM文件中的全部证书
+fn load_certs(path: &str) -> lib::Result<Vec<CertificateDer<'static>>> {
+    let certs =
+        rustls_pemfile::certs(&mut BufReader::new(open(path)?)).collect::<Result<Vec<_>, _>>()?;
+    if certs.is_empty() {
+        return Err(invalid(format!("No certificate found in '{}'", path)));
+    }
+    Ok(certs)
+}
+
+//打开证书或私钥文件，出错时带上文件名
+fn open(path: &str) -> lib::Result<File> {
+    if path.is_empty() {
+        return Err(invalid(
+            "tls-cert-file and tls-key-file are required for TLS",
+        ));
+    }
+    File::open(path).map_err(|e| invalid(format!("Failed to open '{}': {}", path, e)))
+}
+
+//证书、私钥或TLS相关的配置不可用
+fn invalid(err: impl Display) -> lib::Error {
+    lib::Error::Config(err.to_string())
+}
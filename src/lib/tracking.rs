@@ -0,0 +1,178 @@
+use crate::lib::frame::Frame;
+use bytes::Bytes;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+///RESP2的连接在订阅模式下通过这个频道接收失效消息
+pub(crate) const CHANNEL: &str = "__redis__:invalidate";
+
+///发给连接的一条失效消息
+#[derive(Debug, Clone)]
+pub(crate) enum Invalidation {
+    ///键被修改、删除或过期
+    Key(Bytes),
+    ///整个数据库被清空或交换，客户端需要丢弃所有缓存
+    Flush,
+}
+
+impl Invalidation {
+    ///RESP3中推送的失效消息
+    pub(crate) fn push(self) -> Frame {
+        Frame::Push(vec![
+            Frame::Bulk(Bytes::from_static(b"invalidate")),
+            self.keys(),
+        ])
+    }
+
+    ///RESP2中以__redis__:invalidate频道上的消息发送的失效消息
+    pub(crate) fn message(self) -> Frame {
+        Frame::Push(vec![
+            Frame::Bulk(Bytes::from_static(b"message")),
+            Frame::Bulk(Bytes::from_static(CHANNEL.as_bytes())),
+            self.keys(),
+        ])
+    }
+
+    //失效的键，清空时为空值
+    fn keys(self) -> Frame {
+        match self {
+            Invalidation::Key(key) => Frame::Array(vec![Frame::Bulk(key)]),
+            Invalidation::Flush => Frame::Null,
+        }
+    }
+}
+
+///CLIENT TRACKING的选项
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Options {
+    ///接收失效消息的连接，None时发给开启追踪的连接自己
+    pub(crate) redirect: Option<u64>,
+    ///广播模式，不记录读取过的键，任何匹配前缀的键被修改时都会收到消息
+    pub(crate) bcast: bool,
+    ///广播模式关注的键前缀，为空时关注所有键
+    pub(crate) prefixes: Vec<String>,
+}
+
+impl Options {
+    //键被修改时是否需要通知，默认模式下只通知读取过的键
+    fn watches(&self, key: &str) -> bool {
+        self.bcast
+            && (self.prefixes.is_empty()
+                || self.prefixes.iter().any(|prefix| key.starts_with(prefix)))
+    }
+}
+
+///客户端缓存的追踪表
+///
+/// 默认模式下记录每个连接读取过的键，键被修改时通知一次后就不再追踪，直到再次读取；
+/// 广播模式下按前缀通知，不记录键。键不区分所在的逻辑数据库
+#[derive(Debug, Default)]
+pub(crate) struct Tracking {
+    table: Mutex<Table>,
+    //开启了追踪的连接数，为0时写入无需检查追踪表
+    active: AtomicUsize,
+}
+
+#[derive(Debug, Default)]
+struct Table {
+    //开启了追踪的连接与选项
+    clients: HashMap<u64, Options>,
+    //键与读取过它的连接，关闭追踪的连接在键被修改时才会被清理
+    keys: HashMap<String, HashSet<u64>>,
+}
+
+impl Tracking {
+    ///为连接开启追踪，已经开启时修改原有的选项
+    ///
+    /// 与redis一致，已经开启时不能切换广播模式，需要先关闭追踪，此时返回false且不做修改。
+    /// 广播模式下再次开启时新的前缀加入原有的前缀，转发的连接换为新的选项
+    pub(crate) fn enable(&self, id: u64, mut options: Options) -> bool {
+        let mut table = self.table.lock().unwrap();
+        if let Some(current) = table.clients.get(&id) {
+            if current.bcast != options.bcast {
+                return false;
+            }
+            //没有前缀代表关注所有键，与任何前缀合并之后仍然关注所有键
+            if current.prefixes.is_empty() || options.prefixes.is_empty() {
+                options.prefixes.clear();
+            } else {
+                let added = std::mem::take(&mut options.prefixes);
+                options.prefixes = current.prefixes.clone();
+                for prefix in added {
+                    if !options.prefixes.contains(&prefix) {
+                        options.prefixes.push(prefix);
+                    }
+                }
+            }
+        }
+        table.clients.insert(id, options);
+        self.active.store(table.clients.len(), Ordering::Release);
+        true
+    }
+
+    ///关闭连接的追踪，连接关闭时同样需要调用
+    pub(crate) fn disable(&self, id: u64) {
+        let mut table = self.table.lock().unwrap();
+        table.clients.remove(&id);
+        self.active.store(table.clients.len(), Ordering::Release);
+    }
+
+    ///是否有连接开启了追踪
+    pub(crate) fn is_active(&self) -> bool {
+        self.clients() > 0
+    }
+
+    ///开启了追踪的连接数
+    pub(crate) fn clients(&self) -> usize {
+        self.active.load(Ordering::Acquire)
+    }
+
+    ///连接的失效消息转发给哪个连接，没有开启追踪时为None，不转发时为Some(None)
+    pub(crate) fn redirect(&self, id: u64) -> Option<Option<u64>> {
+        let table = self.table.lock().unwrap();
+        table.clients.get(&id).map(|options| options.redirect)
+    }
+
+    ///记录连接读取过的键，只对默认模式的连接生效
+    pub(crate) fn track(&self, id: u64, keys: Vec<String>) {
+        let mut table = self.table.lock().unwrap();
+        if table.clients.get(&id).is_none_or(|options| options.bcast) {
+            return;
+        }
+        for key in keys {
+            table.keys.entry(key).or_default().insert(id);
+        }
+    }
+
+    ///键被修改，返回需要收到失效消息的连接
+    pub(crate) fn invalidate(&self, key: &str) -> Vec<u64> {
+        let mut table = self.table.lock().unwrap();
+        let readers = table.keys.remove(key).unwrap_or_default();
+        let mut targets: Vec<u64> = table
+            .clients
+            .iter()
+            .filter(|(id, options)| {
+                (!options.bcast && readers.contains(*id)) || options.watches(key)
+            })
+            .map(|(id, options)| options.redirect.unwrap_or(*id))
+            .collect();
+        targets.sort_unstable();
+        targets.dedup();
+        targets
+    }
+
+    ///数据库被清空，返回所有需要收到失效消息的连接
+    pub(crate) fn flush(&self) -> Vec<u64> {
+        let mut table = self.table.lock().unwrap();
+        table.keys.clear();
+        let mut targets: Vec<u64> = table
+            .clients
+            .iter()
+            .map(|(id, options)| options.redirect.unwrap_or(*id))
+            .collect();
+        targets.sort_unstable();
+        targets.dedup();
+        targets
+    }
+}
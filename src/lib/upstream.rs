@@ -0,0 +1,113 @@
+use crate::lib;
+use crate::lib::frame::{Frame, FrameError};
+use crate::lib::propagate;
+use bytes::{Buf, Bytes, BytesMut};
+use std::io::{self, Cursor};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+///作为客户端连接另一个服务器，复制时连接主节点与MIGRATE连接目标节点使用
+///
+/// 复制偏移量按命令流的字节数计算，全量同步的快照也不是完整的帧，因此直接在套接字上收发，不经过Connection
+#[derive(Debug)]
+pub(crate) struct Upstream {
+    socket: TcpStream,
+    buffer: BytesMut,
+    //连接与每次读取的超时
+    timeout: Duration,
+}
+
+impl Upstream {
+    ///连接host:port，超过timeout没有连上时返回错误
+    pub(crate) async fn connect(host: &str, port: u16, timeout: Duration) -> lib::Result<Upstream> {
+        let socket = tokio::time::timeout(timeout, TcpStream::connect((host, port)))
+            .await
+            .map_err(|_| "timeout connecting to peer")??;
+        Ok(Upstream {
+            socket,
+            buffer: BytesMut::with_capacity(4 * 1024),
+            timeout,
+        })
+    }
+
+    ///发送一条命令，不等待回复
+    pub(crate) async fn send<A: AsRef<[u8]>>(&mut self, args: &[A]) -> io::Result<()> {
+        let args: Vec<Bytes> = args
+            .iter()
+            .map(|arg| Bytes::copy_from_slice(arg.as_ref()))
+            .collect();
+        let mut data = vec![];
+        propagate::encode(&mut data, &args);
+        self.socket.write_all(&data).await
+    }
+
+    ///发送一条命令并等待回复
+    pub(crate) async fn call<A: AsRef<[u8]>>(&mut self, args: &[A]) -> lib::Result<Frame> {
+        self.send(args).await?;
+        self.reply().await
+    }
+
+    ///读取一条回复，对端关闭连接时返回错误
+    pub(crate) async fn reply(&mut self) -> lib::Result<Frame> {
+        match self.read_frame().await? {
+            Some((frame, _)) => Ok(frame),
+            None => Err("connection closed by peer".into()),
+        }
+    }
+
+    ///读取一个帧，同时返回其在数据流中占用的字节数，对端关闭连接时返回None
+    ///
+    /// 数据先读入缓冲区，在select!中被取消时不会丢失
+    pub(crate) async fn read_frame(&mut self) -> lib::Result<Option<(Frame, usize)>> {
+        loop {
+            let mut cursor = Cursor::new(&self.buffer[..]);
+            match Frame::check(&mut cursor) {
+                Ok(()) => {
+                    let len = cursor.position() as usize;
+                    cursor.set_position(0);
+                    let frame = Frame::parse(&mut cursor)?;
+                    self.buffer.advance(len);
+                    return Ok(Some((frame, len)));
+                }
+                Err(FrameError::Incomplete) => {}
+                Err(e) => return Err(e.into()),
+            }
+            if !self.fill().await? {
+                return Ok(None);
+            }
+        }
+    }
+
+    ///读取全量同步的快照，格式为“$长度\r\n”之后跟着快照的内容，末尾没有\r\n
+    pub(crate) async fn read_payload(&mut self) -> lib::Result<Vec<u8>> {
+        let end = loop {
+            if let Some(end) = self.buffer.windows(2).position(|window| window == b"\r\n") {
+                break end;
+            }
+            if !self.fill().await? {
+                return Err("connection closed by peer".into());
+            }
+        };
+        let len: usize = std::str::from_utf8(&self.buffer[..end])
+            .ok()
+            .and_then(|header| header.strip_prefix('$'))
+            .and_then(|len| len.parse().ok())
+            .ok_or("invalid snapshot header")?;
+        self.buffer.advance(end + 2);
+        while self.buffer.len() < len {
+            if !self.fill().await? {
+                return Err("connection closed by peer".into());
+            }
+        }
+        Ok(self.buffer.split_to(len).to_vec())
+    }
+
+    //从套接字读取更多的数据，对端关闭连接时返回false
+    async fn fill(&mut self) -> lib::Result<bool> {
+        let read = tokio::time::timeout(self.timeout, self.socket.read_buf(&mut self.buffer))
+            .await
+            .map_err(|_| "timeout reading from peer")??;
+        Ok(read > 0)
+    }
+}
@@ -0,0 +1,287 @@
+use crate::lib::frame::Frame;
+use crate::lib::value::hash::Hash;
+use crate::lib::value::list::List;
+use crate::lib::value::set::Set;
+use crate::lib::value::stream::Stream;
+use crate::lib::value::zset::ZSet;
+use bytes::Bytes;
+use std::fmt::{Display, Formatter};
+
+pub(crate) mod dump;
+pub(crate) mod hash;
+pub(crate) mod hll;
+pub(crate) mod limits;
+pub(crate) mod list;
+mod listpack;
+pub(crate) mod set;
+pub(crate) mod stream;
+pub(crate) mod zset;
+
+///操作与键的类型不符时回复的错误
+pub(crate) const WRONGTYPE: &str =
+    "WRONGTYPE Operation against a key holding the wrong kind of value";
+
+///所有类型的名称，下标与Value::type_index一致
+pub(crate) const TYPE_NAMES: [&str; 6] = ["string", "list", "hash", "set", "zset", "stream"];
+
+///数据库中存储的值
+///
+/// 每个命令只能操作特定类型的值，类型不符时回复WRONGTYPE错误，而不是把值当作其他类型解读
+#[derive(Debug, Clone)]
+pub(crate) enum Value {
+    ///字符串，位图与HyperLogLog同样以字符串存储
+    String(Bytes),
+    ///以整数存储的字符串
+    ///
+    /// 由INCR系列命令写入，加减时不必反复解析与格式化，以字符串读取时才转换为十进制的写法，类型仍是string
+    Int(i64),
+    ///列表
+    List(List),
+    ///哈希
+    Hash(Hash),
+    ///集合
+    Set(Set),
+    ///有序集合
+    ZSet(ZSet),
+    ///流
+    Stream(Stream),
+}
+
+///值的类型与命令要求的不符
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct WrongType;
+
+impl Value {
+    ///类型名称，与TYPE命令的回复一致
+    pub(crate) fn type_name(&self) -> &'static str {
+        TYPE_NAMES[self.type_index()]
+    }
+
+    ///类型在TYPE_NAMES中的下标，用于按类型计数
+    pub(crate) fn type_index(&self) -> usize {
+        match self {
+            Value::String(_) | Value::Int(_) => 0,
+            Value::List(_) => 1,
+            Value::Hash(_) => 2,
+            Value::Set(_) => 3,
+            Value::ZSet(_) => 4,
+            Value::Stream(_) => 5,
+        }
+    }
+
+    ///集合类型的值是否为空
+    ///
+    /// 空的集合不会保留在数据库中，而空字符串与空的流都是合法的值
+    pub(crate) fn is_empty(&self) -> bool {
+        match self {
+            Value::String(_) | Value::Int(_) | Value::Stream(_) => false,
+            Value::List(list) => list.is_empty(),
+            Value::Hash(hash) => hash.is_empty(),
+            Value::Set(set) => set.is_empty(),
+            Value::ZSet(zset) => zset.len() == 0,
+        }
+    }
+
+    ///估算值占用的内存字节数
+    ///
+    /// 集合类型按前samples个元素的平均大小推算全部元素，samples为0时统计全部元素
+    pub(crate) fn memory(&self, samples: usize) -> usize {
+        match self {
+            Value::String(data) => data.len(),
+            Value::Int(_) => std::mem::size_of::<i64>(),
+            Value::List(list) => list.memory(samples),
+            Value::Hash(hash) => hash.memory(samples),
+            Value::Set(set) => set.memory(samples),
+            Value::ZSet(zset) => zset.memory(samples),
+            Value::Stream(stream) => stream.memory(samples),
+        }
+    }
+
+    ///内部编码的名称，与OBJECT ENCODING的回复一致
+    ///
+    /// 字符串按redis的规则区分：以整数存储或能表示为整数的是int，不超过44字节的是embstr，其余是raw
+    pub(crate) fn encoding(&self) -> &'static str {
+        match self {
+            Value::Int(_) => "int",
+            Value::String(data) if integer(data).is_some() => "int",
+            Value::String(data) if data.len() <= 44 => "embstr",
+            Value::String(_) => "raw",
+            Value::List(list) => list.encoding(),
+            Value::Hash(hash) => hash.encoding(),
+            Value::Set(set) => set.encoding(),
+            Value::ZSet(zset) => zset.encoding(),
+            Value::Stream(_) => "stream",
+        }
+    }
+
+    ///换用encoding编码，只能从紧凑的编码换成普通的编码，无法换用时返回false
+    ///
+    /// 只供测试使用，不需要写入大量元素就能覆盖依赖编码的代码
+    #[cfg(any(test, feature = "debug-commands"))]
+    pub(crate) fn set_encoding(&mut self, encoding: &str) -> bool {
+        if self.encoding() == encoding {
+            return true;
+        }
+        match (self, encoding) {
+            (Value::List(list), "quicklist") => list.unpack(),
+            (Value::Hash(hash), "hashtable") => hash.unpack(),
+            (Value::Set(set), "hashtable") => set.unpack(),
+            (Value::ZSet(zset), "skiplist") => zset.unpack(),
+            _ => return false,
+        }
+        true
+    }
+
+    ///以字符串读取，以整数存储的值转换为十进制的写法
+    pub(crate) fn as_string(&self) -> Result<Bytes, WrongType> {
+        match self {
+            Value::String(data) => Ok(data.clone()),
+            Value::Int(n) => Ok(Bytes::from(n.to_string())),
+            _ => Err(WrongType),
+        }
+    }
+
+    ///以列表读取
+    pub(crate) fn as_list(&self) -> Result<&List, WrongType> {
+        match self {
+            Value::List(list) => Ok(list),
+            _ => Err(WrongType),
+        }
+    }
+
+    ///以列表修改
+    pub(crate) fn as_list_mut(&mut self) -> Result<&mut List, WrongType> {
+        match self {
+            Value::List(list) => Ok(list),
+            _ => Err(WrongType),
+        }
+    }
+
+    ///以集合读取
+    pub(crate) fn as_set(&self) -> Result<&Set, WrongType> {
+        match self {
+            Value::Set(set) => Ok(set),
+            _ => Err(WrongType),
+        }
+    }
+
+    ///以集合修改
+    pub(crate) fn as_set_mut(&mut self) -> Result<&mut Set, WrongType> {
+        match self {
+            Value::Set(set) => Ok(set),
+            _ => Err(WrongType),
+        }
+    }
+
+    ///以有序集合读取
+    pub(crate) fn as_zset(&self) -> Result<&ZSet, WrongType> {
+        match self {
+            Value::ZSet(zset) => Ok(zset),
+            _ => Err(WrongType),
+        }
+    }
+
+    ///以有序集合修改
+    pub(crate) fn as_zset_mut(&mut self) -> Result<&mut ZSet, WrongType> {
+        match self {
+            Value::ZSet(zset) => Ok(zset),
+            _ => Err(WrongType),
+        }
+    }
+
+    ///以流读取
+    pub(crate) fn as_stream(&self) -> Result<&Stream, WrongType> {
+        match self {
+            Value::Stream(stream) => Ok(stream),
+            _ => Err(WrongType),
+        }
+    }
+
+    ///以流修改
+    pub(crate) fn as_stream_mut(&mut self) -> Result<&mut Stream, WrongType> {
+        match self {
+            Value::Stream(stream) => Ok(stream),
+            _ => Err(WrongType),
+        }
+    }
+
+    ///以哈希读取
+    pub(crate) fn as_hash(&self) -> Result<&Hash, WrongType> {
+        match self {
+            Value::Hash(hash) => Ok(hash),
+            _ => Err(WrongType),
+        }
+    }
+
+    ///以哈希修改
+    pub(crate) fn as_hash_mut(&mut self) -> Result<&mut Hash, WrongType> {
+        match self {
+            Value::Hash(hash) => Ok(hash),
+            _ => Err(WrongType),
+        }
+    }
+}
+
+impl From<Bytes> for Value {
+    fn from(data: Bytes) -> Value {
+        Value::String(data)
+    }
+}
+
+impl Display for WrongType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        WRONGTYPE.fmt(f)
+    }
+}
+
+impl std::error::Error for WrongType {}
+
+impl From<WrongType> for Frame {
+    fn from(_: WrongType) -> Frame {
+        Frame::Error(WRONGTYPE.to_string())
+    }
+}
+
+//按前samples个元素的平均大小推算len个元素的总大小，samples为0时统计全部元素
+fn estimate(len: usize, samples: usize, sizes: impl Iterator<Item = usize>) -> usize {
+    let taken = if samples == 0 { len } else { samples.min(len) };
+    if taken == 0 {
+        return 0;
+    }
+    sizes.take(taken).sum::<usize>() * len / taken
+}
+
+//data表示的64位整数，只接受转换回字符串后完全相同的写法，如“01”“+1”都不是整数
+fn integer(data: &[u8]) -> Option<i64> {
+    let n: i64 = std::str::from_utf8(data).ok()?.parse().ok()?;
+    Some(n).filter(|n| n.to_string().as_bytes() == data)
+}
+
+///两种编码之一的迭代器
+#[derive(Debug, Clone)]
+pub(crate) enum Either<L, R> {
+    Left(L),
+    Right(R),
+}
+
+impl<L: Iterator, R: Iterator<Item = L::Item>> Iterator for Either<L, R> {
+    type Item = L::Item;
+
+    fn next(&mut self) -> Option<L::Item> {
+        match self {
+            Either::Left(iter) => iter.next(),
+            Either::Right(iter) => iter.next(),
+        }
+    }
+}
+
+impl<L: DoubleEndedIterator, R: DoubleEndedIterator<Item = L::Item>> DoubleEndedIterator
+    for Either<L, R>
+{
+    fn next_back(&mut self) -> Option<L::Item> {
+        match self {
+            Either::Left(iter) => iter.next_back(),
+            Either::Right(iter) => iter.next_back(),
+        }
+    }
+}
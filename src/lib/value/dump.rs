@@ -0,0 +1,252 @@
+use crate::lib::crc64;
+use crate::lib::value::hash::Hash;
+use crate::lib::value::list::List;
+use crate::lib::value::set::Set;
+use crate::lib::value::stream::Stream;
+use crate::lib::value::zset::ZSet;
+use crate::lib::value::Value;
+use bytes::Bytes;
+
+///序列化格式的版本号，格式变化时递增，旧版本的数据在RESTORE时被拒绝
+pub(crate) const VERSION: u16 = 1;
+
+///版本号或校验和不符时的错误
+pub(crate) const BAD_CHECKSUM: &str = "ERR DUMP payload version or checksum are wrong";
+///数据无法解析时的错误
+pub(crate) const BAD_FORMAT: &str = "ERR Bad data format";
+
+//各类型值的标记，与RDB中的类型编号一致
+const STRING: u8 = 0;
+const LIST: u8 = 1;
+const SET: u8 = 2;
+const ZSET: u8 = 3;
+const HASH: u8 = 4;
+const STREAM: u8 = 15;
+
+///将值序列化为DUMP的格式
+///
+/// 格式为类型标记与值的内容，之后是2字节的版本号与对之前所有字节计算的8字节CRC64，
+/// 整数都以小端序存储。MIGRATE与持久化可以复用同一格式
+pub(crate) fn serialize(value: &Value) -> Bytes {
+    let mut encoder = Encoder::default();
+    encode_value(&mut encoder, value);
+    let mut data = encoder.buf;
+    data.extend_from_slice(&VERSION.to_le_bytes());
+    let crc = crc64::checksum(&data);
+    data.extend_from_slice(&crc.to_le_bytes());
+    Bytes::from(data)
+}
+
+///写入类型标记与值的内容，不带版本号与校验和
+pub(crate) fn encode_value(encoder: &mut Encoder, value: &Value) {
+    match value {
+        Value::String(data) => {
+            encoder.put_u8(STRING);
+            encoder.put_bytes(data);
+        }
+        Value::Int(n) => {
+            encoder.put_u8(STRING);
+            encoder.put_bytes(n.to_string().as_bytes());
+        }
+        Value::List(list) => {
+            encoder.put_u8(LIST);
+            encoder.put_len(list.len());
+            list.iter().for_each(|item| encoder.put_bytes(&item));
+        }
+        Value::Set(set) => {
+            encoder.put_u8(SET);
+            encoder.put_len(set.len());
+            set.iter().for_each(|member| encoder.put_bytes(&member));
+        }
+        Value::ZSet(zset) => {
+            encoder.put_u8(ZSET);
+            encoder.put_len(zset.len());
+            for (member, score) in zset.iter() {
+                encoder.put_bytes(&member);
+                encoder.put_u64(score.to_bits());
+            }
+        }
+        Value::Hash(hash) => {
+            encoder.put_u8(HASH);
+            encoder.put_len(hash.len());
+            for (field, value) in hash.iter() {
+                encoder.put_bytes(&field);
+                encoder.put_bytes(&value);
+            }
+        }
+        Value::Stream(stream) => {
+            encoder.put_u8(STREAM);
+            stream.serialize(encoder);
+        }
+    }
+}
+
+///解析DUMP格式的数据，先校验版本号与校验和，再解析值的内容
+pub(crate) fn deserialize(data: &[u8]) -> Result<Value, &'static str> {
+    if data.len() < 10 {
+        return Err(BAD_CHECKSUM);
+    }
+    let (body, crc) = data.split_at(data.len() - 8);
+    let version = u16::from_le_bytes([body[body.len() - 2], body[body.len() - 1]]);
+    if version != VERSION || crc64::checksum(body) != u64::from_le_bytes(crc.try_into().unwrap()) {
+        return Err(BAD_CHECKSUM);
+    }
+    let mut decoder = Decoder {
+        data: &body[..body.len() - 2],
+    };
+    let value = decode_value(&mut decoder).ok_or(BAD_FORMAT)?;
+    //内容之后不能有多余的字节
+    if !decoder.data.is_empty() {
+        return Err(BAD_FORMAT);
+    }
+    Ok(value)
+}
+
+///解析类型标记与值的内容
+pub(crate) fn decode_value(decoder: &mut Decoder) -> Option<Value> {
+    let value = match decoder.get_u8()? {
+        STRING => Value::String(decoder.get_bytes()?),
+        LIST => {
+            let len = decoder.get_len()?;
+            let mut list = List::default();
+            for _ in 0..len {
+                list.push_back(decoder.get_bytes()?);
+            }
+            Value::List(list)
+        }
+        SET => {
+            let len = decoder.get_len()?;
+            let mut set = Set::default();
+            for _ in 0..len {
+                set.insert(decoder.get_bytes()?);
+            }
+            Value::Set(set)
+        }
+        ZSET => {
+            let len = decoder.get_len()?;
+            let mut zset = ZSet::default();
+            for _ in 0..len {
+                let member = decoder.get_bytes()?;
+                let score = f64::from_bits(decoder.get_u64()?);
+                if score.is_nan() {
+                    return None;
+                }
+                zset.insert(member, score);
+            }
+            Value::ZSet(zset)
+        }
+        HASH => {
+            let len = decoder.get_len()?;
+            let mut hash = Hash::default();
+            for _ in 0..len {
+                hash.insert(decoder.get_bytes()?, decoder.get_bytes()?);
+            }
+            Value::Hash(hash)
+        }
+        STREAM => Value::Stream(Stream::deserialize(decoder)?),
+        _ => return None,
+    };
+    //空的集合类型不会出现在数据库中
+    Some(value).filter(|value| !value.is_empty())
+}
+
+///序列化时写入数据
+#[derive(Debug, Default)]
+pub(crate) struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    ///已经写入的数据
+    pub(crate) fn into_vec(self) -> Vec<u8> {
+        self.buf
+    }
+
+    ///写入一个字节
+    pub(crate) fn put_u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    ///写入8字节的整数
+    pub(crate) fn put_u64(&mut self, value: u64) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    ///写入长度，每字节存7位，最高位表示后面还有字节
+    pub(crate) fn put_len(&mut self, len: usize) {
+        let mut len = len as u64;
+        while len >= 0x80 {
+            self.buf.push((len as u8) | 0x80);
+            len >>= 7;
+        }
+        self.buf.push(len as u8);
+    }
+
+    ///写入带长度前缀的字节串
+    pub(crate) fn put_bytes(&mut self, data: &[u8]) {
+        self.put_len(data.len());
+        self.buf.extend_from_slice(data);
+    }
+}
+
+///反序列化时读取数据，数据不足时返回None
+#[derive(Debug)]
+pub(crate) struct Decoder<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    ///从data的开头开始读取
+    pub(crate) fn new(data: &'a [u8]) -> Decoder<'a> {
+        Decoder { data }
+    }
+
+    ///数据是否已经读完
+    pub(crate) fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    ///读取一个字节
+    pub(crate) fn get_u8(&mut self) -> Option<u8> {
+        let (first, rest) = self.data.split_first()?;
+        self.data = rest;
+        Some(*first)
+    }
+
+    ///读取8字节的整数
+    pub(crate) fn get_u64(&mut self) -> Option<u64> {
+        let value = u64::from_le_bytes(self.data.get(..8)?.try_into().ok()?);
+        self.data = &self.data[8..];
+        Some(value)
+    }
+
+    ///读取长度
+    ///
+    /// 长度不可能超过剩余的字节数，据此拒绝被篡改的长度，避免按其预分配内存
+    pub(crate) fn get_len(&mut self) -> Option<usize> {
+        let mut len = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = self.get_u8()?;
+            if shift > 63 {
+                return None;
+            }
+            len |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                break;
+            }
+            shift += 7;
+        }
+        usize::try_from(len)
+            .ok()
+            .filter(|len| *len <= self.data.len())
+    }
+
+    ///读取带长度前缀的字节串
+    pub(crate) fn get_bytes(&mut self) -> Option<Bytes> {
+        let len = self.get_len()?;
+        let (data, rest) = self.data.split_at(len);
+        self.data = rest;
+        Some(Bytes::copy_from_slice(data))
+    }
+}
@@ -0,0 +1,182 @@
+use crate::lib::scan::Buckets;
+use crate::lib::value::limits;
+use crate::lib::value::listpack::Listpack;
+use crate::lib::value::Either;
+use bytes::Bytes;
+
+///哈希
+///
+/// 字段少且短时字段与值交替存放在Listpack中，查找需要逐个比较；
+/// 超出限制后换用哈希表，之后不再换回
+#[derive(Debug, Clone)]
+pub(crate) struct Hash(Repr);
+
+//哈希的编码
+#[derive(Debug, Clone)]
+enum Repr {
+    Packed(Listpack),
+    Table(Buckets<Bytes, Bytes>),
+}
+
+impl Default for Hash {
+    fn default() -> Hash {
+        Hash(Repr::Packed(Listpack::default()))
+    }
+}
+
+impl FromIterator<(Bytes, Bytes)> for Hash {
+    fn from_iter<I: IntoIterator<Item = (Bytes, Bytes)>>(iter: I) -> Hash {
+        let mut hash = Hash::default();
+        for (field, value) in iter {
+            hash.insert(field, value);
+        }
+        hash
+    }
+}
+
+impl Hash {
+    ///字段个数
+    pub(crate) fn len(&self) -> usize {
+        match &self.0 {
+            Repr::Packed(packed) => packed.len() / 2,
+            Repr::Table(table) => table.len(),
+        }
+    }
+
+    ///是否没有字段
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    ///当前的编码名称，与OBJECT ENCODING的回复一致
+    pub(crate) fn encoding(&self) -> &'static str {
+        match &self.0 {
+            Repr::Packed(_) => "listpack",
+            Repr::Table(_) => "hashtable",
+        }
+    }
+
+    ///估算占用的内存字节数，按前samples个字段推算，samples为0时统计全部字段
+    pub(crate) fn memory(&self, samples: usize) -> usize {
+        match &self.0 {
+            Repr::Packed(packed) => packed.bytes(),
+            Repr::Table(table) => super::estimate(
+                table.len(),
+                samples,
+                table.iter().map(|(field, value)| {
+                    2 * std::mem::size_of::<Bytes>() + field.len() + value.len()
+                }),
+            ),
+        }
+    }
+
+    ///获取字段的值
+    pub(crate) fn get(&self, field: &[u8]) -> Option<Bytes> {
+        match &self.0 {
+            Repr::Packed(packed) => packed
+                .pairs()
+                .find(|(cur, _)| *cur == field)
+                .map(|(_, value)| Bytes::copy_from_slice(value)),
+            Repr::Table(table) => table.get(field).cloned(),
+        }
+    }
+
+    ///字段是否存在
+    pub(crate) fn contains(&self, field: &[u8]) -> bool {
+        match &self.0 {
+            Repr::Packed(packed) => packed.pairs().any(|(cur, _)| cur == field),
+            Repr::Table(table) => table.contains_key(field),
+        }
+    }
+
+    ///遍历字段与值
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (Bytes, Bytes)> + '_ {
+        match &self.0 {
+            Repr::Packed(packed) => Either::Left(packed.pairs().map(|(field, value)| {
+                (Bytes::copy_from_slice(field), Bytes::copy_from_slice(value))
+            })),
+            Repr::Table(table) => Either::Right(
+                table
+                    .iter()
+                    .map(|(field, value)| (field.clone(), value.clone())),
+            ),
+        }
+    }
+
+    ///从游标开始遍历一批字段与值，返回下一个游标，为0时遍历结束
+    ///
+    /// listpack编码的哈希很小，总是一次返回全部字段
+    pub(crate) fn scan(&self, cursor: u64, count: usize, mut f: impl FnMut(Bytes, Bytes)) -> u64 {
+        match &self.0 {
+            Repr::Packed(_) => {
+                self.iter().for_each(|(field, value)| f(field, value));
+                0
+            }
+            Repr::Table(table) => table.scan(cursor, count, |field, value| {
+                f(field.clone(), value.clone())
+            }),
+        }
+    }
+
+    ///写入字段的值，返回原来的值
+    pub(crate) fn insert(&mut self, field: Bytes, value: Bytes) -> Option<Bytes> {
+        let index = match &self.0 {
+            Repr::Packed(packed) => packed.pairs().position(|(cur, _)| cur == &field[..]),
+            Repr::Table(_) => None,
+        };
+        match self.grow(&field, &value, index.is_none()) {
+            Repr::Packed(packed) => match index {
+                Some(index) => {
+                    let old = packed.remove(2 * index + 1);
+                    packed.insert(2 * index + 1, &value);
+                    Some(old)
+                }
+                None => {
+                    packed.push_back(&field);
+                    packed.push_back(&value);
+                    None
+                }
+            },
+            Repr::Table(table) => table.insert(field, value),
+        }
+    }
+
+    ///删除字段，返回原来的值
+    pub(crate) fn remove(&mut self, field: &[u8]) -> Option<Bytes> {
+        match &mut self.0 {
+            Repr::Packed(packed) => {
+                let index = packed.pairs().position(|(cur, _)| cur == field)?;
+                let value = packed.remove(2 * index + 1);
+                packed.remove(2 * index);
+                Some(value)
+            }
+            Repr::Table(table) => table.remove(field),
+        }
+    }
+
+    ///换用哈希表（hashtable编码），之后不再换回
+    pub(crate) fn unpack(&mut self) {
+        if let Repr::Packed(packed) = &self.0 {
+            let table = packed
+                .pairs()
+                .map(|(field, value)| {
+                    (Bytes::copy_from_slice(field), Bytes::copy_from_slice(value))
+                })
+                .collect();
+            self.0 = Repr::Table(table);
+        }
+    }
+
+    //即将写入field与value，added表示是否新增字段，超出Listpack的限制时先换用哈希表，返回写入后的编码
+    fn grow(&mut self, field: &[u8], value: &[u8], added: bool) -> &mut Repr {
+        if let Repr::Packed(packed) = &self.0 {
+            //字段数达到hash-max-listpack-entries或字段、值长于hash-max-listpack-value时换用
+            let full = added && packed.len() / 2 >= limits::hash_max_entries();
+            let long = limits::hash_max_value();
+            if full || field.len() > long || value.len() > long {
+                self.unpack();
+            }
+        }
+        &mut self.0
+    }
+}
@@ -0,0 +1,285 @@
+use bytes::Bytes;
+
+//寄存器下标占用的哈希位数
+const P: u32 = 14;
+//寄存器个数
+const REGISTERS: usize = 1 << P;
+//用于计算前导零的哈希位数
+const Q: u32 = 64 - P;
+//每个寄存器占用的位数
+const BITS: usize = 6;
+//密集编码的寄存器数组长度
+const DENSE_SIZE: usize = (REGISTERS * BITS).div_ceil(8);
+//头部长度：魔数、编码、3字节保留、8字节基数缓存
+const HEADER_SIZE: usize = 16;
+//稀疏编码超过该长度时改用密集编码，与redis的hll-sparse-max-bytes默认值一致
+const SPARSE_MAX_BYTES: usize = 3000;
+//稀疏编码中VAL操作码能表示的最大值
+const SPARSE_VAL_MAX: u8 = 32;
+//编码类型
+const DENSE: u8 = 0;
+const SPARSE: u8 = 1;
+
+///键中的字符串不是合法的HyperLogLog时回复的错误
+pub(crate) const INVALID: &str = "WRONGTYPE Key is not a valid HyperLogLog string value.";
+
+///HyperLogLog，以字符串的形式存储
+///
+/// 存储格式与redis一致：16字节的头部之后是稀疏或密集编码的16384个6位寄存器，
+/// 寄存器较少被使用时以稀疏编码节省空间。内存中总是展开为完整的寄存器数组，
+/// 编码时顺带计算基数写入头部缓存，只读取单个键的PFCOUNT无需再遍历寄存器
+#[derive(Debug, Clone)]
+pub(crate) struct Hll {
+    registers: Vec<u8>,
+}
+
+impl Hll {
+    ///创建一个所有寄存器都为0的HyperLogLog
+    pub(crate) fn new() -> Hll {
+        Hll {
+            registers: vec![0; REGISTERS],
+        }
+    }
+
+    ///从字符串中解析，不是合法的HyperLogLog时返回None
+    ///
+    /// 密集编码的寄存器能存下0到63，超过Q+1的值不可能由PFADD写入，只会来自损坏或伪造的数据
+    pub(crate) fn decode(data: &[u8]) -> Option<Hll> {
+        if data.len() < HEADER_SIZE || &data[..4] != b"HYLL" {
+            return None;
+        }
+        let body = &data[HEADER_SIZE..];
+        let registers = match data[4] {
+            DENSE if body.len() == DENSE_SIZE => {
+                (0..REGISTERS).map(|index| dense_get(body, index)).collect()
+            }
+            SPARSE => decode_sparse(body)?,
+            _ => return None,
+        };
+        if registers.iter().any(|register| *register as u32 > Q + 1) {
+            return None;
+        }
+        Some(Hll { registers })
+    }
+
+    ///读取头部缓存的基数，缓存失效或不是合法的HyperLogLog时返回None
+    pub(crate) fn cached_count(data: &[u8]) -> Option<u64> {
+        if data.len() < HEADER_SIZE || &data[..4] != b"HYLL" {
+            return None;
+        }
+        let card = u64::from_le_bytes(data[8..HEADER_SIZE].try_into().unwrap());
+        //最高位为1代表缓存失效
+        Some(card).filter(|card| card >> 63 == 0)
+    }
+
+    ///添加元素，返回是否有寄存器被更新
+    pub(crate) fn add(&mut self, element: &[u8]) -> bool {
+        let hash = murmur_hash64a(element, 0xadc8_3b19);
+        let index = (hash & (REGISTERS as u64 - 1)) as usize;
+        //补上第Q位，保证计数不超过Q+1
+        let count = ((hash >> P) | (1 << Q)).trailing_zeros() as u8 + 1;
+        if count > self.registers[index] {
+            self.registers[index] = count;
+            true
+        } else {
+            false
+        }
+    }
+
+    ///合并另一个HyperLogLog，每个寄存器取两者中的较大值
+    pub(crate) fn merge(&mut self, other: &Hll) {
+        for (register, other) in self.registers.iter_mut().zip(&other.registers) {
+            *register = (*register).max(*other);
+        }
+    }
+
+    ///估算基数
+    ///
+    /// 使用与redis相同的改进估算方法（Ertl, 2017），不需要针对小基数与大基数的分段修正
+    pub(crate) fn count(&self) -> u64 {
+        let mut histogram = [0u32; Q as usize + 2];
+        for register in &self.registers {
+            histogram[*register as usize] += 1;
+        }
+        let m = REGISTERS as f64;
+        let q = Q as usize;
+        let mut z = m * tau((m - histogram[q + 1] as f64) / m);
+        for count in histogram[1..=q].iter().rev() {
+            z += *count as f64;
+            z *= 0.5;
+        }
+        z += m * sigma(histogram[0] as f64 / m);
+        const ALPHA_INF: f64 = 0.721_347_520_444_481_7;
+        (ALPHA_INF * m * m / z).round() as u64
+    }
+
+    ///编码为存储用的字符串，稀疏编码足够小时使用稀疏编码
+    pub(crate) fn encode(&self) -> Bytes {
+        let (encoding, body) = match encode_sparse(&self.registers) {
+            Some(body) => (SPARSE, body),
+            None => {
+                let mut body = vec![0; DENSE_SIZE];
+                for (index, value) in self.registers.iter().enumerate() {
+                    dense_set(&mut body, index, *value);
+                }
+                (DENSE, body)
+            }
+        };
+        let mut data = Vec::with_capacity(HEADER_SIZE + body.len());
+        data.extend_from_slice(b"HYLL");
+        data.extend_from_slice(&[encoding, 0, 0, 0]);
+        data.extend_from_slice(&self.count().to_le_bytes());
+        data.extend_from_slice(&body);
+        Bytes::from(data)
+    }
+}
+
+//读取密集编码中的寄存器，寄存器从低位开始依次紧密排列
+fn dense_get(body: &[u8], index: usize) -> u8 {
+    let byte = index * BITS / 8;
+    let shift = index * BITS % 8;
+    let low = body[byte] as u16;
+    let high = body.get(byte + 1).copied().unwrap_or(0) as u16;
+    (((low | (high << 8)) >> shift) & 0x3f) as u8
+}
+
+//写入密集编码中的寄存器
+fn dense_set(body: &mut [u8], index: usize, value: u8) {
+    let byte = index * BITS / 8;
+    let shift = index * BITS % 8;
+    let value = (value as u16) << shift;
+    let mask = 0x3fu16 << shift;
+    body[byte] = (body[byte] & !(mask as u8)) | value as u8;
+    if let Some(next) = body.get_mut(byte + 1) {
+        *next = (*next & !((mask >> 8) as u8)) | (value >> 8) as u8;
+    }
+}
+
+//解析稀疏编码
+//
+// ZERO为00xxxxxx，代表1到64个为0的寄存器；XZERO为01xxxxxx yyyyyyyy，代表1到16384个为0的寄存器；
+// VAL为1vvvvvxx，代表1到4个值为1到32的寄存器
+fn decode_sparse(body: &[u8]) -> Option<Vec<u8>> {
+    let mut registers = Vec::with_capacity(REGISTERS);
+    let mut i = 0;
+    while i < body.len() {
+        let op = body[i];
+        let (value, len) = if op & 0xc0 == 0 {
+            i += 1;
+            (0, (op & 0x3f) as usize + 1)
+        } else if op & 0xc0 == 0x40 {
+            let next = *body.get(i + 1)? as usize;
+            i += 2;
+            (0, ((((op & 0x3f) as usize) << 8) | next) + 1)
+        } else {
+            i += 1;
+            (((op >> 2) & 0x1f) + 1, (op & 0x3) as usize + 1)
+        };
+        if registers.len() + len > REGISTERS {
+            return None;
+        }
+        registers.extend(std::iter::repeat_n(value, len));
+    }
+    Some(registers).filter(|registers| registers.len() == REGISTERS)
+}
+
+//编码为稀疏编码，有寄存器的值超出VAL的范围或编码过长时返回None
+fn encode_sparse(registers: &[u8]) -> Option<Vec<u8>> {
+    let mut body = vec![];
+    let mut i = 0;
+    while i < registers.len() {
+        let value = registers[i];
+        let run = registers[i..].iter().take_while(|r| **r == value).count();
+        i += run;
+        if value == 0 {
+            let mut left = run;
+            while left > 0 {
+                let len = left.min(REGISTERS);
+                if len > 64 {
+                    body.push(0x40 | ((len - 1) >> 8) as u8);
+                    body.push(((len - 1) & 0xff) as u8);
+                } else {
+                    body.push((len - 1) as u8);
+                }
+                left -= len;
+            }
+        } else {
+            if value > SPARSE_VAL_MAX {
+                return None;
+            }
+            let mut left = run;
+            while left > 0 {
+                let len = left.min(4);
+                body.push(0x80 | ((value - 1) << 2) | (len - 1) as u8);
+                left -= len;
+            }
+        }
+        if body.len() > SPARSE_MAX_BYTES {
+            return None;
+        }
+    }
+    Some(body)
+}
+
+//MurmurHash64A，与redis计算HyperLogLog时使用的哈希一致
+fn murmur_hash64a(key: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4_a793_5bd1_e995;
+    const R: u32 = 47;
+    let mut h = seed ^ (key.len() as u64).wrapping_mul(M);
+    let mut chunks = key.chunks_exact(8);
+    for chunk in &mut chunks {
+        let mut k = u64::from_le_bytes(chunk.try_into().unwrap());
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        for (i, byte) in tail.iter().enumerate() {
+            h ^= (*byte as u64) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+    h
+}
+
+//改进估算方法中的sigma函数
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+    let mut y = 1.0;
+    let mut z = x;
+    loop {
+        x *= x;
+        let prev = z;
+        z += x * y;
+        y += y;
+        if prev == z {
+            return z;
+        }
+    }
+}
+
+//改进估算方法中的tau函数
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+    let mut y = 1.0;
+    let mut z = 1.0 - x;
+    loop {
+        x = x.sqrt();
+        let prev = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if prev == z {
+            return z / 3.0;
+        }
+    }
+}
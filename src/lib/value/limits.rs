@@ -0,0 +1,102 @@
+use crate::lib::config::Config;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+//各类型紧凑编码的上限，默认值与redis一致
+static ZSET_MAX_ENTRIES: AtomicUsize = AtomicUsize::new(128);
+static ZSET_MAX_VALUE: AtomicUsize = AtomicUsize::new(64);
+static HASH_MAX_ENTRIES: AtomicUsize = AtomicUsize::new(128);
+static HASH_MAX_VALUE: AtomicUsize = AtomicUsize::new(64);
+static SET_MAX_INTSET_ENTRIES: AtomicUsize = AtomicUsize::new(512);
+
+///按配置更新紧凑编码的上限
+///
+/// 编码在值的内部转换，拿不到数据库的配置，所以上限在进程内共享，与loglevel一样在启动与修改配置时同步。
+/// 新的上限只影响之后的写入，已经换用的编码不会换回
+pub(crate) fn configure(config: &Config) {
+    let limits = [
+        (&ZSET_MAX_ENTRIES, config.zset_max_listpack_entries()),
+        (&ZSET_MAX_VALUE, config.zset_max_listpack_value()),
+        (&HASH_MAX_ENTRIES, config.hash_max_listpack_entries()),
+        (&HASH_MAX_VALUE, config.hash_max_listpack_value()),
+        (&SET_MAX_INTSET_ENTRIES, config.set_max_intset_entries()),
+    ];
+    for (limit, value) in limits {
+        limit.store(value, Ordering::Relaxed);
+    }
+}
+
+///有序集合以listpack存放的最多成员数
+pub(crate) fn zset_max_entries() -> usize {
+    ZSET_MAX_ENTRIES.load(Ordering::Relaxed)
+}
+
+///有序集合以listpack存放时成员的最大长度
+pub(crate) fn zset_max_value() -> usize {
+    ZSET_MAX_VALUE.load(Ordering::Relaxed)
+}
+
+///哈希以listpack存放的最多字段数
+pub(crate) fn hash_max_entries() -> usize {
+    HASH_MAX_ENTRIES.load(Ordering::Relaxed)
+}
+
+///哈希以listpack存放时字段与值的最大长度
+pub(crate) fn hash_max_value() -> usize {
+    HASH_MAX_VALUE.load(Ordering::Relaxed)
+}
+
+///集合以intset存放的最多成员数
+pub(crate) fn set_max_intset_entries() -> usize {
+    SET_MAX_INTSET_ENTRIES.load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lib::cmd::Command;
+    use crate::lib::config::Config;
+    use crate::lib::db::Db;
+    use crate::lib::frame::Frame;
+    use bytes::Bytes;
+
+    fn run(db: &Db, args: &[&str]) -> Frame {
+        let frame = Frame::Array(
+            args.iter()
+                .map(|arg| Frame::Bulk(Bytes::copy_from_slice(arg.as_bytes())))
+                .collect(),
+        );
+        Command::from_frame(frame).unwrap().apply_now(db)
+    }
+
+    //上限在进程内共享，这里只调高，以免影响并行的其他测试，结束时恢复默认值
+    #[test]
+    fn conversions_follow_the_configured_limits() {
+        let db = Db::new(Config::default());
+        run(&db, &["CONFIG", "SET", "zset-max-listpack-entries", "200"]);
+        run(&db, &["CONFIG", "SET", "hash-max-listpack-value", "100"]);
+        run(&db, &["CONFIG", "SET", "set-max-intset-entries", "1000"]);
+        for i in 0..200 {
+            let n = i.to_string();
+            run(&db, &["ZADD", "zset", &n, &n]);
+            run(&db, &["SADD", "set", &n]);
+        }
+        run(&db, &["HSET", "hash", "field", &"x".repeat(100)]);
+        assert!(run(&db, &["OBJECT", "ENCODING", "zset"]) == "listpack");
+        assert!(run(&db, &["OBJECT", "ENCODING", "set"]) == "intset");
+        assert!(run(&db, &["OBJECT", "ENCODING", "hash"]) == "listpack");
+        //超出上限后换用，删除成员也不再换回
+        run(&db, &["ZADD", "zset", "200", "200"]);
+        run(&db, &["ZREM", "zset", "200", "199"]);
+        assert!(run(&db, &["OBJECT", "ENCODING", "zset"]) == "skiplist");
+        run(&db, &["HSET", "hash", "field", &"x".repeat(101)]);
+        assert!(run(&db, &["OBJECT", "ENCODING", "hash"]) == "hashtable");
+        for (name, value) in [
+            ("zset-max-listpack-entries", "128"),
+            ("hash-max-listpack-value", "64"),
+            ("set-max-intset-entries", "512"),
+        ] {
+            run(&db, &["CONFIG", "SET", name, value]);
+        }
+        assert_eq!(super::zset_max_entries(), 128);
+        assert_eq!(super::set_max_intset_entries(), 512);
+    }
+}
@@ -0,0 +1,159 @@
+use crate::lib::value::listpack::Listpack;
+use crate::lib::value::Either;
+use bytes::Bytes;
+use std::collections::VecDeque;
+
+//元素数超过该值时换用VecDeque，与redis的list-max-listpack-size按个数限制时一致
+const MAX_PACKED_ENTRIES: usize = 128;
+//元素超过该长度时同样换用VecDeque
+const MAX_PACKED_VALUE: usize = 64;
+
+///列表
+///
+/// 元素少且短时紧凑地存放在Listpack中，超出限制后换用VecDeque，之后不再换回
+#[derive(Debug, Clone)]
+pub(crate) struct List(Repr);
+
+//列表的编码
+#[derive(Debug, Clone)]
+enum Repr {
+    Packed(Listpack),
+    Deque(VecDeque<Bytes>),
+}
+
+impl Default for List {
+    fn default() -> List {
+        List(Repr::Packed(Listpack::default()))
+    }
+}
+
+impl FromIterator<Bytes> for List {
+    fn from_iter<I: IntoIterator<Item = Bytes>>(iter: I) -> List {
+        let mut list = List::default();
+        for value in iter {
+            list.push_back(value);
+        }
+        list
+    }
+}
+
+impl List {
+    ///元素个数
+    pub(crate) fn len(&self) -> usize {
+        match &self.0 {
+            Repr::Packed(packed) => packed.len(),
+            Repr::Deque(deque) => deque.len(),
+        }
+    }
+
+    ///是否没有元素
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    ///当前的编码名称，与OBJECT ENCODING的回复一致。VecDeque沿用redis的quicklist
+    pub(crate) fn encoding(&self) -> &'static str {
+        match &self.0 {
+            Repr::Packed(_) => "listpack",
+            Repr::Deque(_) => "quicklist",
+        }
+    }
+
+    ///估算占用的内存字节数，按前samples个元素推算，samples为0时统计全部元素
+    pub(crate) fn memory(&self, samples: usize) -> usize {
+        match &self.0 {
+            Repr::Packed(packed) => packed.bytes(),
+            Repr::Deque(deque) => super::estimate(
+                deque.len(),
+                samples,
+                deque
+                    .iter()
+                    .map(|value| std::mem::size_of::<Bytes>() + value.len()),
+            ),
+        }
+    }
+
+    ///从头到尾遍历元素
+    pub(crate) fn iter(&self) -> impl DoubleEndedIterator<Item = Bytes> + '_ {
+        match &self.0 {
+            Repr::Packed(packed) => Either::Left(packed.iter().map(Bytes::copy_from_slice)),
+            Repr::Deque(deque) => Either::Right(deque.iter().cloned()),
+        }
+    }
+
+    ///下标在start与stop之间（都包含）的元素，调用方保证下标在范围内
+    pub(crate) fn range(&self, start: usize, stop: usize) -> Vec<Bytes> {
+        match &self.0 {
+            Repr::Packed(_) => self.iter().skip(start).take(stop + 1 - start).collect(),
+            Repr::Deque(deque) => deque.range(start..=stop).cloned().collect(),
+        }
+    }
+
+    ///第一个等于value的元素的下标
+    pub(crate) fn position(&self, value: &[u8]) -> Option<usize> {
+        match &self.0 {
+            Repr::Packed(packed) => packed.iter().position(|cur| cur == value),
+            Repr::Deque(deque) => deque.iter().position(|cur| cur == value),
+        }
+    }
+
+    ///在开头插入元素
+    pub(crate) fn push_front(&mut self, value: Bytes) {
+        match self.grow(&value) {
+            Repr::Packed(packed) => packed.push_front(&value),
+            Repr::Deque(deque) => deque.push_front(value),
+        }
+    }
+
+    ///在末尾追加元素
+    pub(crate) fn push_back(&mut self, value: Bytes) {
+        match self.grow(&value) {
+            Repr::Packed(packed) => packed.push_back(&value),
+            Repr::Deque(deque) => deque.push_back(value),
+        }
+    }
+
+    ///在第index个元素之前插入，index为元素个数时追加到末尾
+    pub(crate) fn insert(&mut self, index: usize, value: Bytes) {
+        match self.grow(&value) {
+            Repr::Packed(packed) => packed.insert(index, &value),
+            Repr::Deque(deque) => deque.insert(index, value),
+        }
+    }
+
+    ///弹出第一个元素
+    pub(crate) fn pop_front(&mut self) -> Option<Bytes> {
+        match &mut self.0 {
+            Repr::Packed(packed) if packed.len() == 0 => None,
+            Repr::Packed(packed) => Some(packed.remove(0)),
+            Repr::Deque(deque) => deque.pop_front(),
+        }
+    }
+
+    ///弹出最后一个元素
+    pub(crate) fn pop_back(&mut self) -> Option<Bytes> {
+        match &mut self.0 {
+            Repr::Packed(packed) if packed.len() == 0 => None,
+            Repr::Packed(packed) => Some(packed.remove(packed.len() - 1)),
+            Repr::Deque(deque) => deque.pop_back(),
+        }
+    }
+
+    ///换用VecDeque（quicklist编码），之后不再换回
+    pub(crate) fn unpack(&mut self) {
+        if let Repr::Packed(packed) = &self.0 {
+            let deque = packed.iter().map(Bytes::copy_from_slice).collect();
+            self.0 = Repr::Deque(deque);
+        }
+    }
+
+    //即将写入value，超出Listpack的限制时先换用VecDeque，返回写入后的编码
+    fn grow(&mut self, value: &[u8]) -> &mut Repr {
+        if let Repr::Packed(packed) = &self.0 {
+            if packed.len() >= MAX_PACKED_ENTRIES || value.len() > MAX_PACKED_VALUE {
+                self.unpack();
+            }
+        }
+        &mut self.0
+    }
+}
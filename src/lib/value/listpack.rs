@@ -0,0 +1,148 @@
+use bytes::Bytes;
+
+///元素的最大长度
+pub(crate) const MAX_ELEMENT: usize = u8::MAX as usize;
+
+///紧凑存储的字节串序列
+///
+/// 所有元素依次存放在同一块缓冲区中，每个元素前后各有一个字节记录长度，可以从两端遍历。
+/// 相比每个元素单独分配，省去了指针、长度与分配器的开销，但按下标访问与中间的插入删除都需要扫描或移动缓冲区，
+/// 只适合元素少且短的集合。元素不能超过MAX_ELEMENT字节，由使用方在超出之前换用其他编码
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Listpack {
+    //依次存放的元素
+    buf: Vec<u8>,
+    //元素个数
+    len: usize,
+}
+
+impl Listpack {
+    ///元素个数
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    ///占用的字节数
+    pub(crate) fn bytes(&self) -> usize {
+        self.buf.capacity()
+    }
+
+    ///从前往后遍历元素
+    pub(crate) fn iter(&self) -> Iter<'_> {
+        Iter {
+            buf: &self.buf,
+            front: 0,
+            back: self.buf.len(),
+        }
+    }
+
+    ///把相邻的两个元素作为一对遍历，元素个数必须是偶数
+    pub(crate) fn pairs(&self) -> Pairs<'_> {
+        Pairs(self.iter())
+    }
+
+    ///在末尾追加元素
+    pub(crate) fn push_back(&mut self, data: &[u8]) {
+        self.insert(self.len, data);
+    }
+
+    ///在开头插入元素
+    pub(crate) fn push_front(&mut self, data: &[u8]) {
+        self.insert(0, data);
+    }
+
+    ///在第index个元素之前插入，index为元素个数时追加到末尾
+    pub(crate) fn insert(&mut self, index: usize, data: &[u8]) {
+        debug_assert!(data.len() <= MAX_ELEMENT);
+        let at = self.offset(index);
+        let len = data.len() as u8;
+        let element = std::iter::once(len)
+            .chain(data.iter().copied())
+            .chain(std::iter::once(len));
+        self.buf.splice(at..at, element);
+        self.len += 1;
+    }
+
+    ///删除第index个元素并返回
+    pub(crate) fn remove(&mut self, index: usize) -> Bytes {
+        let at = self.offset(index);
+        let len = self.buf[at] as usize;
+        let data = Bytes::copy_from_slice(&self.buf[at + 1..at + 1 + len]);
+        self.buf.drain(at..at + len + 2);
+        self.len -= 1;
+        data
+    }
+
+    //第index个元素在缓冲区中的起始位置，index为元素个数时是缓冲区末尾
+    //
+    // 按离得近的一端扫描
+    fn offset(&self, index: usize) -> usize {
+        if index <= self.len / 2 {
+            let mut at = 0;
+            for _ in 0..index {
+                at += self.buf[at] as usize + 2;
+            }
+            at
+        } else {
+            let mut at = self.buf.len();
+            for _ in index..self.len {
+                at -= self.buf[at - 1] as usize + 2;
+            }
+            at
+        }
+    }
+}
+
+///Listpack的迭代器
+#[derive(Debug, Clone)]
+pub(crate) struct Iter<'a> {
+    buf: &'a [u8],
+    //尚未遍历的部分的起止位置
+    front: usize,
+    back: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        if self.front >= self.back {
+            return None;
+        }
+        let len = self.buf[self.front] as usize;
+        let data = &self.buf[self.front + 1..self.front + 1 + len];
+        self.front += len + 2;
+        Some(data)
+    }
+}
+
+impl<'a> DoubleEndedIterator for Iter<'a> {
+    fn next_back(&mut self) -> Option<&'a [u8]> {
+        if self.front >= self.back {
+            return None;
+        }
+        let len = self.buf[self.back - 1] as usize;
+        let data = &self.buf[self.back - 1 - len..self.back - 1];
+        self.back -= len + 2;
+        Some(data)
+    }
+}
+
+///成对遍历Listpack的迭代器
+#[derive(Debug, Clone)]
+pub(crate) struct Pairs<'a>(Iter<'a>);
+
+impl<'a> Iterator for Pairs<'a> {
+    type Item = (&'a [u8], &'a [u8]);
+
+    fn next(&mut self) -> Option<(&'a [u8], &'a [u8])> {
+        Some((self.0.next()?, self.0.next()?))
+    }
+}
+
+impl<'a> DoubleEndedIterator for Pairs<'a> {
+    fn next_back(&mut self) -> Option<(&'a [u8], &'a [u8])> {
+        let second = self.0.next_back()?;
+        Some((self.0.next_back()?, second))
+    }
+}
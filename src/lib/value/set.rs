@@ -0,0 +1,144 @@
+use crate::lib::scan::Buckets;
+use crate::lib::value::{integer, limits, Either};
+use bytes::Bytes;
+
+///集合
+///
+/// 成员都是整数且不多时按数值排序存放在数组中，查找用二分；
+/// 写入非整数的成员或超出限制后换用哈希表，之后不再换回
+#[derive(Debug, Clone)]
+pub(crate) struct Set(Repr);
+
+//集合的编码
+#[derive(Debug, Clone)]
+enum Repr {
+    Ints(Vec<i64>),
+    Table(Buckets<Bytes, ()>),
+}
+
+impl Default for Set {
+    fn default() -> Set {
+        Set(Repr::Ints(Vec::new()))
+    }
+}
+
+impl FromIterator<Bytes> for Set {
+    fn from_iter<I: IntoIterator<Item = Bytes>>(iter: I) -> Set {
+        let mut set = Set::default();
+        for member in iter {
+            set.insert(member);
+        }
+        set
+    }
+}
+
+impl Set {
+    ///成员个数
+    pub(crate) fn len(&self) -> usize {
+        match &self.0 {
+            Repr::Ints(ints) => ints.len(),
+            Repr::Table(table) => table.len(),
+        }
+    }
+
+    ///是否没有成员
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    ///当前的编码名称，与OBJECT ENCODING的回复一致
+    pub(crate) fn encoding(&self) -> &'static str {
+        match &self.0 {
+            Repr::Ints(_) => "intset",
+            Repr::Table(_) => "hashtable",
+        }
+    }
+
+    ///估算占用的内存字节数，按前samples个成员推算，samples为0时统计全部成员
+    pub(crate) fn memory(&self, samples: usize) -> usize {
+        match &self.0 {
+            Repr::Ints(ints) => ints.capacity() * std::mem::size_of::<i64>(),
+            Repr::Table(table) => super::estimate(
+                table.len(),
+                samples,
+                table
+                    .keys()
+                    .map(|member| std::mem::size_of::<Bytes>() + member.len()),
+            ),
+        }
+    }
+
+    ///成员是否存在
+    pub(crate) fn contains(&self, member: &[u8]) -> bool {
+        match &self.0 {
+            Repr::Ints(ints) => integer(member).is_some_and(|n| ints.binary_search(&n).is_ok()),
+            Repr::Table(table) => table.contains_key(member),
+        }
+    }
+
+    ///遍历成员，整数编码时按数值从小到大
+    pub(crate) fn iter(&self) -> impl Iterator<Item = Bytes> + '_ {
+        match &self.0 {
+            Repr::Ints(ints) => Either::Left(ints.iter().map(|n| Bytes::from(n.to_string()))),
+            Repr::Table(table) => Either::Right(table.keys().cloned()),
+        }
+    }
+
+    ///从游标开始遍历一批成员，返回下一个游标，为0时遍历结束
+    ///
+    /// intset编码的集合很小，总是一次返回全部成员
+    pub(crate) fn scan(&self, cursor: u64, count: usize, mut f: impl FnMut(Bytes)) -> u64 {
+        match &self.0 {
+            Repr::Ints(_) => {
+                self.iter().for_each(&mut f);
+                0
+            }
+            Repr::Table(table) => table.scan(cursor, count, |member, _| f(member.clone())),
+        }
+    }
+
+    ///加入成员，返回是否是新成员
+    pub(crate) fn insert(&mut self, member: Bytes) -> bool {
+        if let Repr::Ints(ints) = &mut self.0 {
+            let n = integer(&member);
+            match n.map(|n| (n, ints.binary_search(&n))) {
+                Some((_, Ok(_))) => return false,
+                //成员数达到set-max-intset-entries时换用哈希表
+                Some((n, Err(at))) if ints.len() < limits::set_max_intset_entries() => {
+                    ints.insert(at, n);
+                    return true;
+                }
+                _ => self.unpack(),
+            }
+        }
+        match &mut self.0 {
+            Repr::Table(table) => table.insert(member, ()).is_none(),
+            Repr::Ints(_) => unreachable!(),
+        }
+    }
+
+    ///换用哈希表（hashtable编码），之后不再换回
+    pub(crate) fn unpack(&mut self) {
+        if let Repr::Ints(ints) = &self.0 {
+            let table = ints
+                .iter()
+                .map(|n| (Bytes::from(n.to_string()), ()))
+                .collect();
+            self.0 = Repr::Table(table);
+        }
+    }
+
+    ///删除成员，返回成员是否存在
+    pub(crate) fn remove(&mut self, member: &[u8]) -> bool {
+        match &mut self.0 {
+            Repr::Ints(ints) => match integer(member).map(|n| ints.binary_search(&n)) {
+                Some(Ok(at)) => {
+                    ints.remove(at);
+                    true
+                }
+                _ => false,
+            },
+            Repr::Table(table) => table.remove(member).is_some(),
+        }
+    }
+}
@@ -0,0 +1,567 @@
+use crate::lib::value::dump::{Decoder, Encoder};
+use bytes::Bytes;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::{Display, Formatter};
+
+///流中条目的id
+///
+/// 由毫秒时间戳与同一毫秒内的序号组成，按先时间戳后序号的顺序比较
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) struct StreamId {
+    pub(crate) ms: u64,
+    pub(crate) seq: u64,
+}
+
+impl StreamId {
+    ///最小的id，流中不允许出现
+    pub(crate) const MIN: StreamId = StreamId { ms: 0, seq: 0 };
+    ///最大的id
+    pub(crate) const MAX: StreamId = StreamId {
+        ms: u64::MAX,
+        seq: u64::MAX,
+    };
+
+    ///解析“ms-seq”形式的id，只有时间戳时序号取default_seq
+    pub(crate) fn parse(text: &str, default_seq: u64) -> Option<StreamId> {
+        let (ms, seq) = match text.split_once('-') {
+            Some((ms, seq)) => (ms.parse().ok()?, seq.parse().ok()?),
+            None => (text.parse().ok()?, default_seq),
+        };
+        Some(StreamId { ms, seq })
+    }
+
+    ///紧跟在当前id之后的id，已经是最大的id时返回None
+    pub(crate) fn next(self) -> Option<StreamId> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(StreamId { ms: self.ms, seq }),
+            None => Some(StreamId {
+                ms: self.ms.checked_add(1)?,
+                seq: 0,
+            }),
+        }
+    }
+
+    ///紧挨在当前id之前的id，已经是最小的id时返回None
+    pub(crate) fn prev(self) -> Option<StreamId> {
+        match self.seq.checked_sub(1) {
+            Some(seq) => Some(StreamId { ms: self.ms, seq }),
+            None => Some(StreamId {
+                ms: self.ms.checked_sub(1)?,
+                seq: u64::MAX,
+            }),
+        }
+    }
+}
+
+impl Display for StreamId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+///流中一个条目的字段与值
+pub(crate) type Fields = Vec<(Bytes, Bytes)>;
+
+///流
+///
+/// 条目按id排序存储，只能在末尾追加。last_id记录曾经写入过的最大id，
+/// 即使对应的条目已被裁剪，新的条目也必须大于它
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Stream {
+    //按id排序的条目
+    entries: BTreeMap<StreamId, Fields>,
+    //曾经写入过的最大id
+    last_id: StreamId,
+    //消费者组，按组名索引
+    groups: HashMap<Bytes, Group>,
+}
+
+///消费者组
+///
+/// 组内的消费者共同消费一个流：每个条目只投递给其中一个消费者，
+/// 投递后进入待确认列表，直到被XACK确认或被其他消费者认领
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Group {
+    ///最近一次投递的条目id，之后的条目才是新条目
+    pub(crate) last_delivered: StreamId,
+    //全部待确认条目，按id排序
+    pending: BTreeMap<StreamId, Pending>,
+    //消费者，按名称索引
+    consumers: HashMap<Bytes, Consumer>,
+}
+
+///一个待确认条目
+#[derive(Debug, Clone)]
+pub(crate) struct Pending {
+    ///持有该条目的消费者
+    pub(crate) consumer: Bytes,
+    ///最近一次投递的时间，unix时间戳，毫秒
+    pub(crate) delivered_at: u64,
+    ///投递次数
+    pub(crate) delivery_count: u64,
+}
+
+///消费者组中的一个消费者
+#[derive(Debug, Clone, Default)]
+struct Consumer {
+    //最近一次活动的时间，unix时间戳，毫秒
+    seen_at: u64,
+    //该消费者持有的待确认条目
+    pending: BTreeSet<StreamId>,
+}
+
+impl Group {
+    ///创建从指定id之后开始投递的组
+    pub(crate) fn new(last_delivered: StreamId) -> Group {
+        Group {
+            last_delivered,
+            ..Group::default()
+        }
+    }
+
+    ///全部待确认条目
+    pub(crate) fn pending(&self) -> &BTreeMap<StreamId, Pending> {
+        &self.pending
+    }
+
+    ///每个消费者的名称与持有的待确认条目数
+    pub(crate) fn consumers(&self) -> impl Iterator<Item = (&Bytes, usize)> + '_ {
+        self.consumers
+            .iter()
+            .map(|(name, consumer)| (name, consumer.pending.len()))
+    }
+
+    ///消费者持有的id大于after的待确认条目
+    pub(crate) fn consumer_pending(
+        &self,
+        consumer: &[u8],
+        after: StreamId,
+    ) -> impl Iterator<Item = StreamId> + '_ {
+        let ids = self.consumers.get(consumer).map(|c| &c.pending);
+        ids.into_iter()
+            .flat_map(move |ids| ids.iter().copied().filter(move |id| *id > after))
+    }
+
+    ///创建消费者，已存在时只更新活动时间，返回是否为新建
+    pub(crate) fn touch(&mut self, consumer: &Bytes, now: u64) -> bool {
+        let created = !self.consumers.contains_key(consumer);
+        self.consumers.entry(consumer.clone()).or_default().seen_at = now;
+        created
+    }
+
+    ///删除消费者及其持有的待确认条目，返回删除的条目数，消费者不存在时返回None
+    pub(crate) fn remove_consumer(&mut self, consumer: &[u8]) -> Option<usize> {
+        let removed = self.consumers.remove(consumer)?;
+        for id in &removed.pending {
+            self.pending.remove(id);
+        }
+        Some(removed.pending.len())
+    }
+
+    ///确认条目，返回条目是否处于待确认状态
+    pub(crate) fn ack(&mut self, id: StreamId) -> bool {
+        match self.pending.remove(&id) {
+            Some(pending) => {
+                if let Some(consumer) = self.consumers.get_mut(&pending.consumer) {
+                    consumer.pending.remove(&id);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    ///将条目交给消费者持有
+    ///
+    /// 条目原本由其他消费者持有时转移给新的消费者。increment为true时投递次数加一
+    pub(crate) fn assign(&mut self, id: StreamId, consumer: &Bytes, now: u64, increment: bool) {
+        let count = match self.pending.get(&id) {
+            Some(old) => {
+                if old.consumer != *consumer {
+                    if let Some(owner) = self.consumers.get_mut(&old.consumer) {
+                        owner.pending.remove(&id);
+                    }
+                }
+                old.delivery_count
+            }
+            None => 0,
+        };
+        let pending = Pending {
+            consumer: consumer.clone(),
+            delivered_at: now,
+            delivery_count: if increment { count + 1 } else { count },
+        };
+        self.pending.insert(id, pending);
+        let owner = self.consumers.entry(consumer.clone()).or_default();
+        owner.seen_at = now;
+        owner.pending.insert(id);
+    }
+
+    ///修改待确认条目的投递时间与次数
+    pub(crate) fn set_delivery(&mut self, id: StreamId, delivered_at: u64, count: Option<u64>) {
+        if let Some(pending) = self.pending.get_mut(&id) {
+            pending.delivered_at = delivered_at;
+            if let Some(count) = count {
+                pending.delivery_count = count;
+            }
+        }
+    }
+}
+
+impl Stream {
+    ///条目个数
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    ///估算占用的内存字节数，按前samples个条目推算，samples为0时统计全部条目
+    ///
+    /// 消费者组只计入待确认条目的数量
+    pub(crate) fn memory(&self, samples: usize) -> usize {
+        let id = std::mem::size_of::<StreamId>();
+        let field = 2 * std::mem::size_of::<Bytes>();
+        let entries = super::estimate(
+            self.entries.len(),
+            samples,
+            self.entries.values().map(|fields| {
+                id + fields
+                    .iter()
+                    .map(|(name, value)| field + name.len() + value.len())
+                    .sum::<usize>()
+            }),
+        );
+        let pending: usize = self.groups.values().map(|group| group.pending.len()).sum();
+        entries + pending * (id + std::mem::size_of::<Pending>())
+    }
+
+    ///曾经写入过的最大id
+    pub(crate) fn last_id(&self) -> StreamId {
+        self.last_id
+    }
+
+    ///为新条目生成id
+    ///
+    /// ms为None时使用当前时间，时钟回拨时沿用上一个条目的时间戳；时间戳相同时序号加一。
+    /// 生成的id不大于last_id时返回None
+    pub(crate) fn next_id(&self, ms: Option<u64>, now: u64) -> Option<StreamId> {
+        let ms = ms.unwrap_or_else(|| now.max(self.last_id.ms));
+        let id = match ms.cmp(&self.last_id.ms) {
+            std::cmp::Ordering::Less => return None,
+            std::cmp::Ordering::Equal => self.last_id.next()?,
+            std::cmp::Ordering::Greater => StreamId { ms, seq: 0 },
+        };
+        //0-0不是合法的id
+        Some(id).filter(|id| *id > self.last_id && *id != StreamId::MIN)
+    }
+
+    ///追加条目，调用方需保证id大于last_id
+    pub(crate) fn append(&mut self, id: StreamId, fields: Fields) {
+        debug_assert!(id > self.last_id);
+        self.entries.insert(id, fields);
+        self.last_id = id;
+    }
+
+    ///删除最旧的条目直到只剩max_len个，返回删除的条目数
+    pub(crate) fn trim(&mut self, max_len: usize) -> usize {
+        let mut removed = 0;
+        while self.entries.len() > max_len {
+            self.entries.pop_first();
+            removed += 1;
+        }
+        removed
+    }
+
+    ///按id从小到大遍历闭区间内的条目
+    pub(crate) fn range(
+        &self,
+        start: StreamId,
+        end: StreamId,
+    ) -> impl DoubleEndedIterator<Item = (&StreamId, &Fields)> + '_ {
+        //起点大于终点时BTreeMap::range会panic，换成一个必然为空的区间
+        if start > end {
+            self.entries.range(StreamId::MIN..StreamId::MIN)
+        } else {
+            self.entries.range(start..=end)
+        }
+    }
+
+    ///按id获取条目
+    pub(crate) fn get(&self, id: &StreamId) -> Option<&Fields> {
+        self.entries.get(id)
+    }
+
+    ///按名称获取消费者组
+    pub(crate) fn group(&self, name: &[u8]) -> Option<&Group> {
+        self.groups.get(name)
+    }
+
+    ///按名称获取可修改的消费者组
+    pub(crate) fn group_mut(&mut self, name: &[u8]) -> Option<&mut Group> {
+        self.groups.get_mut(name)
+    }
+
+    ///创建消费者组，组名已存在时返回false
+    pub(crate) fn create_group(&mut self, name: Bytes, last_delivered: StreamId) -> bool {
+        if self.groups.contains_key(&name) {
+            return false;
+        }
+        self.groups.insert(name, Group::new(last_delivered));
+        true
+    }
+
+    ///删除消费者组，返回组是否存在
+    pub(crate) fn destroy_group(&mut self, name: &[u8]) -> bool {
+        self.groups.remove(name).is_some()
+    }
+
+    ///向消费者投递组内尚未投递过的条目，最多count个
+    ///
+    /// 投递后推进组的last_delivered，noack为false时条目进入待确认列表。组不存在时返回None
+    pub(crate) fn deliver(
+        &mut self,
+        group: &[u8],
+        consumer: &Bytes,
+        count: usize,
+        noack: bool,
+        now: u64,
+    ) -> Option<Vec<(StreamId, Fields)>> {
+        let group = self.groups.get_mut(group)?;
+        group.touch(consumer, now);
+        let start = match group.last_delivered.next() {
+            Some(start) => start,
+            None => return Some(vec![]),
+        };
+        let entries: Vec<_> = self
+            .entries
+            .range(start..)
+            .take(count)
+            .map(|(id, fields)| (*id, fields.clone()))
+            .collect();
+        for (id, _) in &entries {
+            group.last_delivered = *id;
+            if !noack {
+                group.assign(*id, consumer, now, true);
+            }
+        }
+        Some(entries)
+    }
+
+    ///按DUMP的格式写入条目、last_id与消费者组
+    ///
+    /// 消费者持有的待确认条目由组的待确认列表重建，不单独写入
+    pub(crate) fn serialize(&self, encoder: &mut Encoder) {
+        encoder.put_len(self.entries.len());
+        for (id, fields) in &self.entries {
+            put_id(encoder, *id);
+            encoder.put_len(fields.len());
+            for (field, value) in fields {
+                encoder.put_bytes(field);
+                encoder.put_bytes(value);
+            }
+        }
+        put_id(encoder, self.last_id);
+        encoder.put_len(self.groups.len());
+        for (name, group) in &self.groups {
+            encoder.put_bytes(name);
+            put_id(encoder, group.last_delivered);
+            encoder.put_len(group.pending.len());
+            for (id, pending) in &group.pending {
+                put_id(encoder, *id);
+                encoder.put_bytes(&pending.consumer);
+                encoder.put_u64(pending.delivered_at);
+                encoder.put_u64(pending.delivery_count);
+            }
+            encoder.put_len(group.consumers.len());
+            for (name, consumer) in &group.consumers {
+                encoder.put_bytes(name);
+                encoder.put_u64(consumer.seen_at);
+            }
+        }
+    }
+
+    ///读取serialize写入的流，数据不完整或条目id不递增时返回None
+    pub(crate) fn deserialize(decoder: &mut Decoder) -> Option<Stream> {
+        let mut stream = Stream::default();
+        for _ in 0..decoder.get_len()? {
+            let id = get_id(decoder)?;
+            let mut fields = vec![];
+            for _ in 0..decoder.get_len()? {
+                fields.push((decoder.get_bytes()?, decoder.get_bytes()?));
+            }
+            if id <= stream.last_id && !stream.entries.is_empty() {
+                return None;
+            }
+            stream.entries.insert(id, fields);
+            stream.last_id = id;
+        }
+        let last_id = get_id(decoder)?;
+        if last_id < stream.last_id {
+            return None;
+        }
+        stream.last_id = last_id;
+        for _ in 0..decoder.get_len()? {
+            let name = decoder.get_bytes()?;
+            let mut group = Group::new(get_id(decoder)?);
+            for _ in 0..decoder.get_len()? {
+                let id = get_id(decoder)?;
+                let pending = Pending {
+                    consumer: decoder.get_bytes()?,
+                    delivered_at: decoder.get_u64()?,
+                    delivery_count: decoder.get_u64()?,
+                };
+                let owner = group.consumers.entry(pending.consumer.clone()).or_default();
+                owner.pending.insert(id);
+                group.pending.insert(id, pending);
+            }
+            for _ in 0..decoder.get_len()? {
+                let name = decoder.get_bytes()?;
+                group.consumers.entry(name).or_default().seen_at = decoder.get_u64()?;
+            }
+            stream.groups.insert(name, group);
+        }
+        Some(stream)
+    }
+}
+
+//写入条目id
+fn put_id(encoder: &mut Encoder, id: StreamId) {
+    encoder.put_u64(id.ms);
+    encoder.put_u64(id.seq);
+}
+
+//读取条目id
+fn get_id(decoder: &mut Decoder) -> Option<StreamId> {
+    Some(StreamId {
+        ms: decoder.get_u64()?,
+        seq: decoder.get_u64()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(ms: u64, seq: u64) -> StreamId {
+        StreamId { ms, seq }
+    }
+
+    fn fields(value: &'static str) -> Fields {
+        vec![(
+            Bytes::from_static(b"field"),
+            Bytes::from_static(value.as_bytes()),
+        )]
+    }
+
+    #[test]
+    fn ids_parse_and_order() {
+        assert_eq!(StreamId::parse("5-3", 0), Some(id(5, 3)));
+        assert_eq!(StreamId::parse("5", u64::MAX), Some(id(5, u64::MAX)));
+        assert_eq!(StreamId::parse("5-x", 0), None);
+        assert_eq!(StreamId::parse("-1", 0), None);
+        assert!(id(1, 9) < id(2, 0));
+        assert_eq!(id(1, u64::MAX).next(), Some(id(2, 0)));
+        assert_eq!(id(2, 0).prev(), Some(id(1, u64::MAX)));
+        assert_eq!(StreamId::MAX.next(), None);
+        assert_eq!(StreamId::MIN.prev(), None);
+        assert_eq!(id(7, 1).to_string(), "7-1");
+    }
+
+    #[test]
+    fn generated_ids_increase() {
+        let mut stream = Stream::default();
+        //空流中不能使用0-0
+        assert_eq!(stream.next_id(Some(0), 100), Some(id(0, 1)));
+        let first = stream.next_id(None, 100).unwrap();
+        assert_eq!(first, id(100, 0));
+        stream.append(first, fields("a"));
+        //同一毫秒内序号加一
+        assert_eq!(stream.next_id(None, 100), Some(id(100, 1)));
+        //时钟回拨时沿用上一个条目的时间戳
+        assert_eq!(stream.next_id(None, 50), Some(id(100, 1)));
+        //显式指定更小的时间戳时拒绝
+        assert_eq!(stream.next_id(Some(99), 200), None);
+        stream.append(id(u64::MAX, u64::MAX), fields("b"));
+        assert_eq!(stream.next_id(None, 300), None);
+    }
+
+    #[test]
+    fn range_and_trim_keep_order() {
+        let mut stream = Stream::default();
+        for (seq, value) in ["a", "b", "c", "d"].into_iter().enumerate() {
+            stream.append(id(1, seq as u64), fields(value));
+        }
+        let ids: Vec<_> = stream
+            .range(id(1, 1), id(1, 2))
+            .map(|(id, _)| *id)
+            .collect();
+        assert_eq!(ids, [id(1, 1), id(1, 2)]);
+        let reversed: Vec<_> = stream
+            .range(StreamId::MIN, StreamId::MAX)
+            .rev()
+            .map(|(id, _)| *id)
+            .collect();
+        assert_eq!(reversed, [id(1, 3), id(1, 2), id(1, 1), id(1, 0)]);
+        //起点大于终点时为空
+        assert_eq!(stream.range(id(2, 0), id(1, 0)).count(), 0);
+        assert_eq!(stream.trim(2), 2);
+        assert_eq!(stream.len(), 2);
+        assert!(stream.get(&id(1, 0)).is_none());
+        //被裁剪的条目不影响新id必须大于last_id
+        assert_eq!(stream.last_id(), id(1, 3));
+        assert_eq!(stream.next_id(Some(1), 0), Some(id(1, 4)));
+    }
+
+    #[test]
+    fn groups_deliver_each_entry_once_and_track_pending() {
+        let mut stream = Stream::default();
+        for seq in 1..=3 {
+            stream.append(id(1, seq), fields("x"));
+        }
+        assert!(stream.create_group(Bytes::from_static(b"g"), StreamId::MIN));
+        assert!(!stream.create_group(Bytes::from_static(b"g"), StreamId::MIN));
+        let alice = Bytes::from_static(b"alice");
+        let bob = Bytes::from_static(b"bob");
+        let first = stream.deliver(b"g", &alice, 2, false, 10).unwrap();
+        assert_eq!(
+            first.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            [id(1, 1), id(1, 2)]
+        );
+        let second = stream.deliver(b"g", &bob, 10, false, 10).unwrap();
+        assert_eq!(
+            second.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            [id(1, 3)]
+        );
+        assert!(stream
+            .deliver(b"g", &bob, 10, false, 10)
+            .unwrap()
+            .is_empty());
+        assert!(stream.deliver(b"missing", &bob, 10, false, 10).is_none());
+        let group = stream.group_mut(b"g").unwrap();
+        assert_eq!(group.pending().len(), 3);
+        assert!(group.ack(id(1, 1)));
+        assert!(!group.ack(id(1, 1)));
+        assert_eq!(group.pending().len(), 2);
+        assert!(stream.destroy_group(b"g"));
+        assert!(stream.group(b"g").is_none());
+    }
+
+    #[test]
+    fn serialized_stream_round_trips() {
+        let mut stream = Stream::default();
+        stream.append(id(1, 1), fields("a"));
+        stream.append(id(2, 0), fields("b"));
+        stream.trim(1);
+        stream.create_group(Bytes::from_static(b"g"), StreamId::MIN);
+        stream.deliver(b"g", &Bytes::from_static(b"c"), 1, false, 10);
+        let mut encoder = Encoder::default();
+        stream.serialize(&mut encoder);
+        let data = encoder.into_vec();
+        let copy = Stream::deserialize(&mut Decoder::new(&data)).unwrap();
+        assert_eq!(copy.len(), 1);
+        assert_eq!(copy.get(&id(2, 0)), Some(&fields("b")));
+        assert_eq!(copy.last_id(), id(2, 0));
+        let group = copy.group(b"g").unwrap();
+        assert_eq!(group.last_delivered, id(2, 0));
+        assert_eq!(group.pending()[&id(2, 0)].consumer, "c");
+    }
+}
@@ -0,0 +1,363 @@
+use crate::lib::scan::Buckets;
+use crate::lib::value::limits;
+use crate::lib::value::listpack::Listpack;
+use crate::lib::value::Either;
+use bytes::Bytes;
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::ops::Bound;
+
+///有序集合
+///
+/// 成员少且短时成员与分数交替存放在Listpack中，按(分数, 成员)排序，查找需要逐个比较；
+/// 超出限制后换用两个索引：成员到分数的哈希表用于按成员查找，按(分数, 成员)排序的BTreeSet用于按分数或排名遍历，
+/// 两者始终同步修改，之后不再换回。分数相同的成员按字节序排列，与redis一致
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ZSet(Repr);
+
+//有序集合的编码
+#[derive(Debug, Clone)]
+enum Repr {
+    //分数以8字节的小端序存放
+    Packed(Listpack),
+    Indexed {
+        //成员与分数
+        scores: Buckets<Bytes, f64>,
+        //按分数排序的成员
+        ordered: BTreeSet<(Score, Bytes)>,
+    },
+}
+
+impl Default for Repr {
+    fn default() -> Repr {
+        Repr::Packed(Listpack::default())
+    }
+}
+
+///可以排序的分数
+///
+/// 分数在写入前已经排除了NaN，这里按total_cmp比较，-0.0在写入时统一换成0.0
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Score(pub(crate) f64);
+
+impl PartialEq for Score {
+    fn eq(&self, other: &Score) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Score) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Score) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+///分数区间的一端
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ScoreBound {
+    pub(crate) score: f64,
+    ///是否不包含该分数本身
+    pub(crate) exclusive: bool,
+}
+
+impl ScoreBound {
+    ///解析“1.5”“(1.5”“-inf”“+inf”这样的分数区间端点
+    pub(crate) fn parse(arg: &str) -> Option<ScoreBound> {
+        let (exclusive, text) = match arg.strip_prefix('(') {
+            Some(text) => (true, text),
+            None => (false, arg),
+        };
+        let score = parse_score(text)?;
+        Some(ScoreBound { score, exclusive })
+    }
+
+    //作为下界时score是否在区间内
+    fn below(&self, score: f64) -> bool {
+        if self.exclusive {
+            score > self.score
+        } else {
+            score >= self.score
+        }
+    }
+
+    //作为上界时score是否在区间内
+    fn above(&self, score: f64) -> bool {
+        if self.exclusive {
+            score < self.score
+        } else {
+            score <= self.score
+        }
+    }
+}
+
+impl ZSet {
+    ///成员个数
+    pub(crate) fn len(&self) -> usize {
+        match &self.0 {
+            Repr::Packed(packed) => packed.len() / 2,
+            Repr::Indexed { scores, .. } => scores.len(),
+        }
+    }
+
+    ///当前的编码名称，与OBJECT ENCODING的回复一致。索引沿用redis的skiplist
+    pub(crate) fn encoding(&self) -> &'static str {
+        match &self.0 {
+            Repr::Packed(_) => "listpack",
+            Repr::Indexed { .. } => "skiplist",
+        }
+    }
+
+    ///估算占用的内存字节数，按前samples个成员推算，samples为0时统计全部成员
+    ///
+    /// 哈希表与BTreeSet中各存一份成员，两份共享同一块字节
+    pub(crate) fn memory(&self, samples: usize) -> usize {
+        match &self.0 {
+            Repr::Packed(packed) => packed.bytes(),
+            Repr::Indexed { scores, .. } => {
+                let item = 2 * (std::mem::size_of::<Bytes>() + std::mem::size_of::<f64>());
+                super::estimate(
+                    scores.len(),
+                    samples,
+                    scores.keys().map(|member| item + member.len()),
+                )
+            }
+        }
+    }
+
+    ///获取成员的分数
+    pub(crate) fn score(&self, member: &[u8]) -> Option<f64> {
+        match &self.0 {
+            Repr::Packed(packed) => packed
+                .pairs()
+                .find(|(cur, _)| *cur == member)
+                .map(|(_, score)| decode(score)),
+            Repr::Indexed { scores, .. } => scores.get(member).copied(),
+        }
+    }
+
+    ///写入成员的分数，返回原来的分数
+    pub(crate) fn insert(&mut self, member: Bytes, score: f64) -> Option<f64> {
+        //-0.0与0.0在redis中是同一个分数
+        let score = if score == 0.0 { 0.0 } else { score };
+        let old = self.remove(&member);
+        match self.grow(&member) {
+            Repr::Packed(packed) => {
+                let key = (Score(score), &member[..]);
+                let index = packed
+                    .pairs()
+                    .position(|(cur, cur_score)| (Score(decode(cur_score)), cur) > key)
+                    .unwrap_or(packed.len() / 2);
+                packed.insert(2 * index, &member);
+                packed.insert(2 * index + 1, &score.to_le_bytes());
+            }
+            Repr::Indexed { scores, ordered } => {
+                scores.insert(member.clone(), score);
+                ordered.insert((Score(score), member));
+            }
+        }
+        old
+    }
+
+    ///删除成员，返回其分数
+    pub(crate) fn remove(&mut self, member: &[u8]) -> Option<f64> {
+        match &mut self.0 {
+            Repr::Packed(packed) => {
+                let index = packed.pairs().position(|(cur, _)| cur == member)?;
+                let score = packed.remove(2 * index + 1);
+                packed.remove(2 * index);
+                Some(decode(&score))
+            }
+            Repr::Indexed { scores, ordered } => {
+                let (member, score) = scores.remove_entry(member)?;
+                ordered.remove(&(Score(score), member));
+                Some(score)
+            }
+        }
+    }
+
+    ///成员按分数从小到大的排名，从0开始
+    pub(crate) fn rank(&self, member: &[u8]) -> Option<usize> {
+        match &self.0 {
+            Repr::Packed(packed) => packed.pairs().position(|(cur, _)| cur == member),
+            Repr::Indexed { scores, ordered } => {
+                let (member, score) = scores.get_key_value(member)?;
+                let key = (Score(*score), member.clone());
+                Some(ordered.range(..key).count())
+            }
+        }
+    }
+
+    ///按分数从小到大遍历成员
+    pub(crate) fn iter(&self) -> impl DoubleEndedIterator<Item = (Bytes, f64)> + '_ {
+        match &self.0 {
+            Repr::Packed(packed) => Either::Left(
+                packed
+                    .pairs()
+                    .map(|(member, score)| (Bytes::copy_from_slice(member), decode(score))),
+            ),
+            Repr::Indexed { ordered, .. } => Either::Right(
+                ordered
+                    .iter()
+                    .map(|(score, member)| (member.clone(), score.0)),
+            ),
+        }
+    }
+
+    ///从游标开始遍历一批成员与分数，返回下一个游标，为0时遍历结束
+    ///
+    /// listpack编码的有序集合很小，总是一次返回全部成员；索引编码按成员的哈希表遍历，与分数的顺序无关
+    pub(crate) fn scan(&self, cursor: u64, count: usize, mut f: impl FnMut(Bytes, f64)) -> u64 {
+        match &self.0 {
+            Repr::Packed(_) => {
+                self.iter().for_each(|(member, score)| f(member, score));
+                0
+            }
+            Repr::Indexed { scores, .. } => {
+                scores.scan(cursor, count, |member, score| f(member.clone(), *score))
+            }
+        }
+    }
+
+    ///按分数从小到大遍历分数在区间内的成员
+    pub(crate) fn range_by_score(
+        &self,
+        min: ScoreBound,
+        max: ScoreBound,
+    ) -> impl DoubleEndedIterator<Item = (Bytes, f64)> + '_ {
+        let range = match &self.0 {
+            Repr::Packed(_) => Either::Left(self.iter()),
+            Repr::Indexed { ordered, .. } => Either::Right(
+                range(ordered, min, max).map(|(score, member)| (member.clone(), score.0)),
+            ),
+        };
+        range.filter(move |(_, score)| min.below(*score) && max.above(*score))
+    }
+
+    ///弹出分数最小的成员
+    pub(crate) fn pop_min(&mut self) -> Option<(Bytes, f64)> {
+        match &mut self.0 {
+            Repr::Packed(packed) if packed.len() == 0 => None,
+            Repr::Packed(packed) => {
+                let member = packed.remove(0);
+                Some((member, decode(&packed.remove(0))))
+            }
+            Repr::Indexed { scores, ordered } => {
+                let (score, member) = ordered.pop_first()?;
+                scores.remove(&member);
+                Some((member, score.0))
+            }
+        }
+    }
+
+    ///弹出分数最大的成员
+    pub(crate) fn pop_max(&mut self) -> Option<(Bytes, f64)> {
+        match &mut self.0 {
+            Repr::Packed(packed) if packed.len() == 0 => None,
+            Repr::Packed(packed) => {
+                let score = decode(&packed.remove(packed.len() - 1));
+                Some((packed.remove(packed.len() - 1), score))
+            }
+            Repr::Indexed { scores, ordered } => {
+                let (score, member) = ordered.pop_last()?;
+                scores.remove(&member);
+                Some((member, score.0))
+            }
+        }
+    }
+
+    ///换用索引（skiplist编码），之后不再换回
+    pub(crate) fn unpack(&mut self) {
+        if let Repr::Packed(packed) = &self.0 {
+            let mut scores = Buckets::default();
+            let mut ordered = BTreeSet::new();
+            for (member, score) in packed.pairs() {
+                let member = Bytes::copy_from_slice(member);
+                let score = decode(score);
+                scores.insert(member.clone(), score);
+                ordered.insert((Score(score), member));
+            }
+            self.0 = Repr::Indexed { scores, ordered };
+        }
+    }
+
+    //即将写入新成员member，超出Listpack的限制时先换用索引，返回写入后的编码
+    fn grow(&mut self, member: &[u8]) -> &mut Repr {
+        if let Repr::Packed(packed) = &self.0 {
+            //成员数达到zset-max-listpack-entries或成员长于zset-max-listpack-value时换用
+            let full = packed.len() / 2 >= limits::zset_max_entries();
+            if full || member.len() > limits::zset_max_value() {
+                self.unpack();
+            }
+        }
+        &mut self.0
+    }
+}
+
+//按分数截取ordered中大致在区间内的部分，开区间的端点由调用方再排除
+//
+// 空成员是同一分数下最小的成员，以此构造只按分数截取的区间
+fn range(
+    ordered: &BTreeSet<(Score, Bytes)>,
+    min: ScoreBound,
+    max: ScoreBound,
+) -> std::collections::btree_set::Range<'_, (Score, Bytes)> {
+    if min.score > max.score {
+        let empty = (Score(f64::NEG_INFINITY), Bytes::new());
+        ordered.range((Bound::Unbounded, Bound::Excluded(empty)))
+    } else {
+        let lower = Bound::Included((Score(min.score), Bytes::new()));
+        let upper = if max.score == f64::INFINITY {
+            Bound::Unbounded
+        } else {
+            Bound::Excluded((Score(next_up(max.score)), Bytes::new()))
+        };
+        ordered.range((lower, upper))
+    }
+}
+
+//Listpack中存放的分数
+fn decode(score: &[u8]) -> f64 {
+    f64::from_le_bytes(score.try_into().unwrap_or_default())
+}
+
+//比score大的最小浮点数
+fn next_up(score: f64) -> f64 {
+    if score.is_nan() || score == f64::INFINITY {
+        return score;
+    }
+    if score == 0.0 {
+        return f64::from_bits(1);
+    }
+    let bits = score.to_bits();
+    if score > 0.0 {
+        f64::from_bits(bits + 1)
+    } else {
+        f64::from_bits(bits - 1)
+    }
+}
+
+///解析分数，允许inf与-inf，不允许NaN
+pub(crate) fn parse_score(text: &str) -> Option<f64> {
+    let score = match text.to_ascii_lowercase().as_str() {
+        "inf" | "+inf" => f64::INFINITY,
+        "-inf" => f64::NEG_INFINITY,
+        text => text.parse().ok()?,
+    };
+    Some(score).filter(|score: &f64| !score.is_nan())
+}
+
+///将分数格式化为回复中的字符串
+pub(crate) fn format_score(score: f64) -> Bytes {
+    if score.is_infinite() {
+        return Bytes::from_static(if score > 0.0 { b"inf" } else { b"-inf" });
+    }
+    Bytes::from(score.to_string())
+}
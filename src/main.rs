@@ -1,8 +1,98 @@
 extern crate core;
-use redis_rust_server_2::lib::run;
+use clap::Parser;
+use redis_rust_server_2::lib::{run, Allocator, Result, Server, ServerBuilder};
+use std::path::PathBuf;
 
-#[tokio::main]
-async fn main() {
-    run().await;
+//统计内存占用，INFO与内存上限依赖它
+#[global_allocator]
+static ALLOCATOR: Allocator = Allocator;
+
+///兼容redis协议的内存数据库服务器
+///
+/// 命令行参数覆盖配置文件中的同名配置
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Args {
+    ///redis.conf格式的配置文件
+    config: Option<PathBuf>,
+    ///监听的端口
+    #[arg(long)]
+    port: Option<u16>,
+    ///Prometheus指标的HTTP端口，不设置时不提供指标
+    #[arg(long)]
+    metrics_port: Option<u16>,
+    ///HTTP网关的端口，不设置时不提供网关
+    #[arg(long)]
+    http_port: Option<u16>,
+    ///监听的地址，多个地址以空格分隔
+    #[arg(long)]
+    bind: Option<String>,
+    ///额外监听的unix套接字路径
+    #[arg(long)]
+    unixsocket: Option<String>,
+    ///持久化文件所在的目录
+    #[arg(long)]
+    dir: Option<String>,
+    ///内存上限，支持kb、mb、gb等单位，0代表不限制
+    #[arg(long)]
+    maxmemory: Option<String>,
+    ///是否开启AOF，yes或no
+    #[arg(long)]
+    appendonly: Option<String>,
+    ///客户端需要提供的密码
+    #[arg(long)]
+    requirepass: Option<String>,
+    ///日志文件，为空时输出到标准输出
+    #[arg(long)]
+    logfile: Option<String>,
+    ///工作线程数，0代表与CPU核数相同
+    #[arg(long)]
+    worker_threads: Option<String>,
+}
+
+impl Args {
+    //先读取配置文件，再用命令行参数覆盖
+    fn builder(self) -> Result<ServerBuilder> {
+        let mut builder = Server::builder();
+        if let Some(path) = &self.config {
+            builder = builder.config_file(path)?;
+        }
+        if let Some(port) = self.port {
+            builder = builder.port(port);
+        }
+        if let Some(port) = self.metrics_port {
+            builder = builder.metrics_port(port);
+        }
+        if let Some(port) = self.http_port {
+            builder = builder.http_port(port);
+        }
+        let overrides = [
+            ("bind", self.bind),
+            ("unixsocket", self.unixsocket),
+            ("dir", self.dir),
+            ("maxmemory", self.maxmemory),
+            ("appendonly", self.appendonly),
+            ("requirepass", self.requirepass),
+            ("logfile", self.logfile),
+            ("worker-threads", self.worker_threads),
+        ];
+        for (name, value) in overrides {
+            if let Some(value) = value {
+                builder = builder.set(name, &value)?;
+            }
+        }
+        Ok(builder)
+    }
 }
 
+//运行时的类型与线程数来自配置，所以在解析配置之后再创建运行时
+fn main() {
+    let result = Args::parse().builder().and_then(|builder| {
+        let runtime = builder.runtime()?;
+        runtime.block_on(run(builder))
+    });
+    if let Err(e) = result {
+        eprintln!("服务器启动失败：{}", e);
+        std::process::exit(1);
+    }
+}